@@ -1,22 +1,74 @@
 use axum::{
     Json,
     body::Body,
-    extract::State,
-    http::{Request, StatusCode},
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, Method, Request, StatusCode, Uri},
     middleware::Next,
-    response::{IntoResponse, Response},
+    response::{IntoResponse, Redirect, Response},
 };
 use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use hmac::{Hmac, Mac};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
 use crate::config::AuthConfig;
+use crate::services::oidc::{self, OidcClient};
 
-/// Session token to expiry time mapping
-pub type SessionStore = Arc<RwLock<HashMap<String, Instant>>>;
+/// Header the frontend echoes the CSRF token in on state-changing requests
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Endpoints that accept a signed URL in place of a session, so media
+/// elements and external players can fetch files without cookies
+const SIGNED_URL_ROUTES: &[&str] = &["/api/files/download", "/api/files/thumbnail"];
+
+#[derive(Debug, Deserialize)]
+struct SignedQuery {
+    path: String,
+    expires: i64,
+    sig: String,
+}
+
+/// Who `auth_middleware` let a request through as, added to the request's
+/// extensions for the layers and handlers behind it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller {
+    /// Per-client id: `user:<name>` for a proxy user, the session id (as
+    /// listed in expirables), `link:<signature>` for a signed URL, or
+    /// `anonymous` with auth disabled
+    pub id: String,
+    /// Whether the caller may use the `/api/admin` endpoints
+    pub admin: bool,
+}
+
+impl Caller {
+    const ANONYMOUS: &str = "anonymous";
+
+    /// Id of the caller a request was let through as. Only requests that
+    /// did not pass `auth_middleware` lack one, and count as anonymous.
+    pub fn id_of(extensions: &axum::http::Extensions) -> String {
+        extensions
+            .get::<Caller>()
+            .map(|caller| caller.id.clone())
+            .unwrap_or_else(|| Self::ANONYMOUS.to_string())
+    }
+}
+
+/// A logged-in browser
+#[derive(Debug, Clone, Copy)]
+pub struct Session {
+    pub expires: Instant,
+    /// Logged in with the password, or as one of `admin_users` through SSO
+    pub admin: bool,
+}
+
+/// Session token to session mapping
+pub type SessionStore = Arc<RwLock<HashMap<String, Session>>>;
 
 /// Create a new session store
 pub fn new_session_store() -> SessionStore {
@@ -28,13 +80,34 @@ pub fn new_session_store() -> SessionStore {
 pub struct AuthState {
     pub config: AuthConfig,
     pub sessions: SessionStore,
+    /// Present when single sign-on is configured
+    pub oidc: Option<Arc<OidcClient>>,
+    /// Key CSRF tokens are derived with; sessions do not outlive the
+    /// process, so neither do the tokens
+    csrf_key: [u8; 32],
+    /// Key signed URLs are derived with, which likewise stop working on
+    /// restart
+    url_key: [u8; 32],
 }
 
 impl AuthState {
     pub fn new(config: AuthConfig) -> Self {
+        let oidc = config
+            .oidc
+            .clone()
+            .map(|oidc| Arc::new(OidcClient::new(oidc)));
+        let random_key = || {
+            let mut key = [0u8; 32];
+            key[..16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+            key[16..].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+            key
+        };
         Self {
             config,
             sessions: new_session_store(),
+            oidc,
+            csrf_key: random_key(),
+            url_key: random_key(),
         }
     }
 
@@ -53,27 +126,36 @@ impl AuthState {
     }
 
     /// Create a new session and return the token
-    pub async fn create_session(&self) -> String {
+    pub async fn create_session(&self, admin: bool) -> String {
         let token = Self::generate_token();
-        let expiry = Instant::now() + Duration::from_secs(self.config.session_timeout_secs);
+        let expires = Instant::now() + Duration::from_secs(self.config.session_timeout_secs);
 
         let mut sessions = self.sessions.write().await;
-        sessions.insert(token.clone(), expiry);
+        sessions.insert(token.clone(), Session { expires, admin });
 
         // Clean up expired sessions while we have the lock
-        sessions.retain(|_, exp| *exp > Instant::now());
+        sessions.retain(|_, session| session.expires > Instant::now());
 
         token
     }
 
     /// Validate a session token
     pub async fn validate_session(&self, token: &str) -> bool {
+        self.session(token).await.is_some()
+    }
+
+    /// The unexpired session for a token
+    async fn session(&self, token: &str) -> Option<Session> {
         let sessions = self.sessions.read().await;
-        if let Some(expiry) = sessions.get(token) {
-            *expiry > Instant::now()
-        } else {
-            false
-        }
+        sessions
+            .get(token)
+            .filter(|session| session.expires > Instant::now())
+            .copied()
+    }
+
+    /// Whether a user named by SSO or a proxy is listed in `admin_users`
+    pub fn is_admin_user(&self, user: &str) -> bool {
+        self.config.admin_users.iter().any(|admin| admin == user)
     }
 
     /// Invalidate a session
@@ -81,6 +163,160 @@ impl AuthState {
         let mut sessions = self.sessions.write().await;
         sessions.remove(token);
     }
+
+    /// Public identifier for a session, safe to list without exposing the token
+    pub fn session_id(token: &str) -> String {
+        hex::encode(&Sha256::digest(token.as_bytes())[..8])
+    }
+
+    /// Unexpired sessions as (session id, expiry)
+    pub async fn list_sessions(&self) -> Vec<(String, Instant)> {
+        let now = Instant::now();
+        let sessions = self.sessions.read().await;
+        sessions
+            .iter()
+            .filter(|(_, session)| session.expires > now)
+            .map(|(token, session)| (Self::session_id(token), session.expires))
+            .collect()
+    }
+
+    /// Invalidate the session with the given id, returning whether it existed
+    pub async fn invalidate_session_by_id(&self, id: &str) -> bool {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|token, _| Self::session_id(token) != id);
+        sessions.len() != before
+    }
+
+    /// User named by the reverse proxy's header, if proxy authentication is
+    /// configured and the request came directly from a trusted proxy
+    pub fn proxy_user(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> Option<String> {
+        let header = self.config.proxy_header.as_deref()?;
+        let peer = peer?.ip().to_canonical();
+        if !self
+            .config
+            .trusted_proxies
+            .iter()
+            .any(|net| net.contains(&peer))
+        {
+            return None;
+        }
+        headers
+            .get(header)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|user| !user.is_empty())
+            .map(str::to_string)
+    }
+
+    /// User a trusted proxy authenticated for `request`
+    pub fn request_proxy_user(&self, request: &Request<Body>) -> Option<String> {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0);
+        self.proxy_user(request.headers(), peer)
+    }
+
+    /// Caller for a request authenticated by a proxy user or, failing
+    /// that, a session
+    async fn caller(&self, proxy_user: Option<&str>, session: Option<&str>) -> Option<Caller> {
+        match (proxy_user, session) {
+            (Some(user), _) => Some(Caller {
+                id: format!("user:{user}"),
+                admin: self.is_admin_user(user),
+            }),
+            (None, Some(token)) => Some(Caller {
+                id: Self::session_id(token),
+                admin: self.session(token).await?.admin,
+            }),
+            (None, None) => None,
+        }
+    }
+
+    /// Token of the valid session the cookie jar carries, if any
+    pub async fn session_from_cookies(&self, jar: &CookieJar) -> Option<String> {
+        let token = jar.get(&self.config.cookie_name)?.value().to_string();
+        self.validate_session(&token).await.then_some(token)
+    }
+
+    /// CSRF token for a request authenticated by a proxy user or a session.
+    /// It is derived rather than stored, and a forged cross-site request
+    /// cannot read it, so echoing it shows the request came from the
+    /// frontend.
+    pub fn csrf_token(&self, proxy_user: Option<&str>, session: Option<&str>) -> Option<String> {
+        let identity = match (proxy_user, session) {
+            (Some(user), _) => format!("user:{user}"),
+            (None, Some(token)) => format!("session:{token}"),
+            (None, None) => return None,
+        };
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.csrf_key).expect("HMAC accepts keys of any size");
+        mac.update(identity.as_bytes());
+        Some(hex::encode(&mac.finalize().into_bytes()[..16]))
+    }
+
+    /// HMAC over a path and expiry time for a signed URL
+    fn url_mac(&self, path: &str, expires_at: i64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.url_key).expect("HMAC accepts keys of any size");
+        mac.update(format!("{path}\n{expires_at}").as_bytes());
+        mac
+    }
+
+    /// Query string letting `path` be fetched from the download and
+    /// thumbnail endpoints without a session until `expires_at` (Unix
+    /// seconds)
+    pub fn signed_query(&self, path: &str, expires_at: i64) -> String {
+        let signature = hex::encode(&self.url_mac(path, expires_at).finalize().into_bytes()[..16]);
+        format!(
+            "path={}&expires={expires_at}&sig={signature}",
+            utf8_percent_encode(path, NON_ALPHANUMERIC)
+        )
+    }
+
+    /// The signature `uri` carries, if it is unexpired and valid for its `path`
+    fn signed_uri_signature(&self, uri: &Uri) -> Option<String> {
+        let Query(signed) = Query::<SignedQuery>::try_from_uri(uri).ok()?;
+        let signature = hex::decode(&signed.sig).ok()?;
+        (signed.expires > chrono::Utc::now().timestamp()
+            && signature.len() == 16
+            && self
+                .url_mac(&signed.path, signed.expires)
+                .verify_truncated_left(&signature)
+                .is_ok())
+        .then_some(signed.sig)
+    }
+
+    /// Cookie carrying a new session's token
+    fn session_cookie(&self, token: String) -> Cookie<'static> {
+        let mut cookie = Cookie::new(self.config.cookie_name.clone(), token);
+        cookie.set_path("/");
+        cookie.set_http_only(true);
+        cookie.set_same_site(SameSite::Lax);
+        // Set max age in seconds
+        cookie.set_max_age(time::Duration::seconds(
+            self.config.session_timeout_secs as i64,
+        ));
+        cookie
+    }
+
+    /// Cookie remembering the `state` of an SSO login in the browser that
+    /// started it; an empty value expires it
+    fn oidc_state_cookie(&self, state: String) -> Cookie<'static> {
+        let expired = state.is_empty();
+        let mut cookie = Cookie::new(format!("{}_oidc_state", self.config.cookie_name), state);
+        cookie.set_path("/api/auth/oidc");
+        cookie.set_http_only(true);
+        // Lax, so the cookie comes along on the provider's redirect back
+        cookie.set_same_site(SameSite::Lax);
+        cookie.set_max_age(if expired {
+            time::Duration::ZERO
+        } else {
+            time::Duration::seconds(oidc::LOGIN_TIMEOUT.as_secs() as i64)
+        });
+        cookie
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -93,12 +329,31 @@ pub struct LoginResponse {
     pub success: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Send back in `X-CSRF-Token` on state-changing requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AuthStatusResponse {
     pub authenticated: bool,
     pub auth_required: bool,
+    /// Whether the password form can be used
+    pub password_login: bool,
+    /// Whether single sign-on is available at `/api/auth/oidc/login`
+    pub sso: bool,
+    /// Send back in `X-CSRF-Token` on state-changing requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set by the provider when the user cancelled or was refused
+    pub error: Option<String>,
+    pub error_description: Option<String>,
 }
 
 /// Login endpoint
@@ -113,31 +368,24 @@ pub async fn login(
             Json(LoginResponse {
                 success: true,
                 error: None,
+                csrf_token: None,
             }),
         )
             .into_response();
     }
 
     if auth.verify_password(&req.password) {
-        let token = auth.create_session().await;
-
-        // Create a session cookie
-        let mut cookie = Cookie::new(auth.config.cookie_name.clone(), token);
-        cookie.set_path("/");
-        cookie.set_http_only(true);
-        cookie.set_same_site(SameSite::Lax);
-        // Set max age in seconds
-        cookie.set_max_age(time::Duration::seconds(
-            auth.config.session_timeout_secs as i64,
-        ));
-
-        let jar = jar.add(cookie);
+        // The password is the owner's, so it grants admin
+        let token = auth.create_session(true).await;
+        let csrf_token = auth.csrf_token(None, Some(&token));
+        let jar = jar.add(auth.session_cookie(token));
 
         (
             jar,
             Json(LoginResponse {
                 success: true,
                 error: None,
+                csrf_token,
             }),
         )
             .into_response()
@@ -147,6 +395,7 @@ pub async fn login(
             Json(LoginResponse {
                 success: false,
                 error: Some("Invalid password".to_string()),
+                csrf_token: None,
             }),
         )
             .into_response();
@@ -155,6 +404,74 @@ pub async fn login(
     }
 }
 
+/// Send the browser to the identity provider to log in
+pub async fn oidc_login(State(auth): State<Arc<AuthState>>, jar: CookieJar) -> Response {
+    let Some(oidc) = &auth.oidc else {
+        return (StatusCode::NOT_FOUND, "Single sign-on is not configured").into_response();
+    };
+
+    match oidc.authorization_url().await {
+        Ok(login) => (
+            jar.add(auth.oidc_state_cookie(login.state)),
+            Redirect::to(&login.url),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("SSO login failed to start: {}", e);
+            login_error_redirect(&e.to_string())
+        }
+    }
+}
+
+/// Where the identity provider sends the browser back after login
+pub async fn oidc_callback(
+    State(auth): State<Arc<AuthState>>,
+    jar: CookieJar,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Response {
+    let Some(oidc) = &auth.oidc else {
+        return (StatusCode::NOT_FOUND, "Single sign-on is not configured").into_response();
+    };
+
+    // The state is only good for this one callback, whatever its outcome
+    let state_cookie = auth.oidc_state_cookie(String::new());
+    let browser_state = jar
+        .get(state_cookie.name())
+        .map(|cookie| cookie.value().to_string());
+    let jar = jar.remove(state_cookie);
+
+    if let Some(error) = query.error {
+        let message = query.error_description.unwrap_or(error);
+        return (jar, login_error_redirect(&message)).into_response();
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        let message = "The identity provider sent an incomplete response";
+        return (jar, login_error_redirect(message)).into_response();
+    };
+
+    match oidc
+        .finish_login(&code, &state, browser_state.as_deref())
+        .await
+    {
+        Ok(user) => {
+            tracing::info!("SSO login for {}", user);
+            let token = auth.create_session(auth.is_admin_user(&user)).await;
+            (jar.add(auth.session_cookie(token)), Redirect::to("/")).into_response()
+        }
+        Err(e) => {
+            tracing::warn!("SSO login refused: {}", e);
+            (jar, login_error_redirect(&e.to_string())).into_response()
+        }
+    }
+}
+
+/// Back to the login page, which shows `login_error`
+fn login_error_redirect(message: &str) -> Response {
+    let message =
+        percent_encoding::utf8_percent_encode(message, percent_encoding::NON_ALPHANUMERIC);
+    Redirect::to(&format!("/?login_error={message}")).into_response()
+}
+
 /// Logout endpoint
 pub async fn logout(State(auth): State<Arc<AuthState>>, jar: CookieJar) -> impl IntoResponse {
     // Get token from cookie and invalidate session
@@ -176,6 +493,7 @@ pub async fn logout(State(auth): State<Arc<AuthState>>, jar: CookieJar) -> impl
         Json(LoginResponse {
             success: true,
             error: None,
+            csrf_token: None,
         }),
     )
 }
@@ -184,23 +502,30 @@ pub async fn logout(State(auth): State<Arc<AuthState>>, jar: CookieJar) -> impl
 pub async fn auth_status(
     State(auth): State<Arc<AuthState>>,
     jar: CookieJar,
+    request: Request<Body>,
 ) -> Json<AuthStatusResponse> {
+    let password_login = auth.config.password.is_some();
+    let sso = auth.oidc.is_some();
     if !auth.config.enabled {
         return Json(AuthStatusResponse {
             authenticated: true,
             auth_required: false,
+            password_login,
+            sso,
+            csrf_token: None,
         });
     }
 
-    let authenticated = if let Some(cookie) = jar.get(&auth.config.cookie_name) {
-        auth.validate_session(cookie.value()).await
-    } else {
-        false
-    };
+    let proxy_user = auth.request_proxy_user(&request);
+    let session = auth.session_from_cookies(&jar).await;
+    let csrf_token = auth.csrf_token(proxy_user.as_deref(), session.as_deref());
 
     Json(AuthStatusResponse {
-        authenticated,
+        authenticated: csrf_token.is_some(),
         auth_required: true,
+        password_login,
+        sso,
+        csrf_token,
     })
 }
 
@@ -208,23 +533,74 @@ pub async fn auth_status(
 pub async fn auth_middleware(
     State(auth): State<Arc<AuthState>>,
     jar: CookieJar,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
     // If auth is not enabled, allow all requests
     if !auth.config.enabled {
+        request.extensions_mut().insert(Caller {
+            id: Caller::ANONYMOUS.to_string(),
+            admin: true,
+        });
         return next.run(request).await;
     }
 
-    // Check for valid session cookie
-    if let Some(cookie) = jar.get(&auth.config.cookie_name) {
-        if auth.validate_session(cookie.value()).await {
-            return next.run(request).await;
-        }
+    if matches!(*request.method(), Method::GET | Method::HEAD)
+        && SIGNED_URL_ROUTES.contains(&request.uri().path())
+        && let Some(signature) = auth.signed_uri_signature(request.uri())
+    {
+        request.extensions_mut().insert(Caller {
+            id: format!("link:{signature}"),
+            admin: false,
+        });
+        return next.run(request).await;
+    }
+
+    // Check for a trusted proxy header or a valid session cookie
+    let proxy_user = auth.request_proxy_user(&request);
+    let session = match proxy_user {
+        Some(_) => None,
+        None => auth.session_from_cookies(&jar).await,
+    };
+    let (Some(caller), Some(csrf_token)) = (
+        auth.caller(proxy_user.as_deref(), session.as_deref()).await,
+        auth.csrf_token(proxy_user.as_deref(), session.as_deref()),
+    ) else {
+        // No valid session - return 401
+        return (StatusCode::UNAUTHORIZED, "Authentication required").into_response();
+    };
+
+    // Browsers send cookies (and proxies add their header) on forged
+    // cross-site requests too; only the frontend knows the CSRF token
+    if is_state_changing(request.method())
+        && request
+            .headers()
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok())
+            != Some(csrf_token.as_str())
+    {
+        return (StatusCode::FORBIDDEN, "Missing or invalid CSRF token").into_response();
+    }
+
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+/// Refuse callers other than admins on the routes it guards, behind
+/// `auth_middleware`
+pub async fn admin_middleware(request: Request<Body>, next: Next) -> Response {
+    if !request
+        .extensions()
+        .get::<Caller>()
+        .is_some_and(|caller| caller.admin)
+    {
+        return (StatusCode::FORBIDDEN, "Administrator access required").into_response();
     }
+    next.run(request).await
+}
 
-    // No valid session - return 401
-    (StatusCode::UNAUTHORIZED, "Authentication required").into_response()
+fn is_state_changing(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
 }
 
 #[cfg(test)]
@@ -250,12 +626,19 @@ mod tests {
             },
             session_timeout_secs: 60,
             cookie_name: "fm_session".to_string(),
+            oidc: None,
+            proxy_header: None,
+            trusted_proxies: Vec::new(),
+            admin_users: Vec::new(),
         }
     }
 
     fn app_with_auth(state: Arc<AuthState>) -> Router {
         Router::new()
-            .route("/protected", get(|| async { StatusCode::OK }))
+            .route(
+                "/protected",
+                get(|| async { StatusCode::OK }).post(|| async { StatusCode::OK }),
+            )
             .with_state(state.clone())
             .layer(middleware::from_fn_with_state(state, auth_middleware))
     }
@@ -264,7 +647,7 @@ mod tests {
     async fn middleware_allows_valid_session() {
         let state = Arc::new(AuthState::new(auth_config(true)));
         let cookie_name = state.config.cookie_name.clone();
-        let token = state.create_session().await;
+        let token = state.create_session(true).await;
 
         let app = app_with_auth(state.clone());
         let request = Request::builder()
@@ -278,6 +661,38 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn middleware_requires_csrf_token_for_writes() {
+        let state = Arc::new(AuthState::new(auth_config(true)));
+        let cookie_name = state.config.cookie_name.clone();
+        let token = state.create_session(true).await;
+        let csrf_token = state.csrf_token(None, Some(&token)).unwrap();
+        let app = app_with_auth(state.clone());
+
+        let post = |csrf: Option<&str>| {
+            let mut builder = Request::builder()
+                .method("POST")
+                .uri("/protected")
+                .header("cookie", format!("{}={}", cookie_name, token));
+            if let Some(csrf) = csrf {
+                builder = builder.header(CSRF_HEADER, csrf);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(post(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // A token from another session does not carry over
+        let other = state.create_session(true).await;
+        let other_csrf = state.csrf_token(None, Some(&other)).unwrap();
+        let response = app.clone().oneshot(post(Some(&other_csrf))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app.oneshot(post(Some(&csrf_token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
     #[tokio::test]
     async fn middleware_rejects_missing_session() {
         let state = Arc::new(AuthState::new(auth_config(true)));
@@ -293,6 +708,132 @@ mod tests {
         assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
     }
 
+    #[tokio::test]
+    async fn middleware_trusts_proxy_header_only_from_trusted_proxy() {
+        let mut config = auth_config(true);
+        config.proxy_header = Some("Remote-User".to_string());
+        config.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+        let app = app_with_auth(Arc::new(AuthState::new(config)));
+
+        let request = |peer: &str, user: Option<&str>| {
+            let mut builder = Request::builder().method("GET").uri("/protected");
+            if let Some(user) = user {
+                builder = builder.header("Remote-User", user);
+            }
+            let mut request = builder.body(Body::empty()).unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+            request
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("10.1.2.3:5000", Some("alex")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Anyone else could send the header themselves
+        let response = app
+            .clone()
+            .oneshot(request("192.168.1.5:5000", Some("alex")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.oneshot(request("10.1.2.3:5000", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn middleware_tells_callers_apart() {
+        let mut config = auth_config(true);
+        config.proxy_header = Some("Remote-User".to_string());
+        config.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+        let state = Arc::new(AuthState::new(config));
+        let token = state.create_session(true).await;
+        let app = Router::new()
+            .route(
+                "/protected",
+                get(|extensions: axum::http::Extensions| async move { Caller::id_of(&extensions) }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ));
+        let caller = |user: Option<&str>, cookie: Option<String>| {
+            let mut builder = Request::builder().uri("/protected");
+            if let Some(user) = user {
+                builder = builder.header("Remote-User", user);
+            }
+            if let Some(cookie) = cookie {
+                builder = builder.header("cookie", cookie);
+            }
+            let mut request = builder.body(Body::empty()).unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo("10.1.2.3:5000".parse::<SocketAddr>().unwrap()));
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        assert_eq!(caller(Some("alex"), None).await, "user:alex");
+        assert_eq!(caller(Some("sam"), None).await, "user:sam");
+        assert_eq!(
+            caller(None, Some(format!("fm_session={token}"))).await,
+            AuthState::session_id(&token)
+        );
+    }
+
+    #[tokio::test]
+    async fn admin_routes_need_an_admin() {
+        let mut config = auth_config(true);
+        config.proxy_header = Some("Remote-User".to_string());
+        config.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+        config.admin_users = vec!["alex".to_string()];
+        let state = Arc::new(AuthState::new(config));
+        let password_session = state.create_session(true).await;
+        let sso_session = state.create_session(false).await;
+        let app = Router::new()
+            .route("/api/admin/db", get(|| async { StatusCode::OK }))
+            .route_layer(middleware::from_fn(admin_middleware))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ));
+        let status = |user: Option<&str>, session: Option<&str>| {
+            let mut builder = Request::builder().uri("/api/admin/db");
+            if let Some(user) = user {
+                builder = builder.header("Remote-User", user);
+            }
+            if let Some(session) = session {
+                builder = builder.header("cookie", format!("fm_session={session}"));
+            }
+            let mut request = builder.body(Body::empty()).unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo("10.1.2.3:5000".parse::<SocketAddr>().unwrap()));
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(status(None, Some(&password_session)).await, StatusCode::OK);
+        assert_eq!(status(Some("alex"), None).await, StatusCode::OK);
+        assert_eq!(status(Some("sam"), None).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(None, Some(&sso_session)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(status(None, None).await, StatusCode::UNAUTHORIZED);
+    }
+
     #[tokio::test]
     async fn middleware_bypasses_when_disabled() {
         let state = Arc::new(AuthState::new(auth_config(false)));
@@ -307,4 +848,49 @@ mod tests {
         let response = app.oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn sso_callback_requires_the_browser_that_started_the_login() {
+        let mut config = auth_config(true);
+        config.oidc = Some(crate::config::OidcConfig {
+            issuer: "http://127.0.0.1:9".to_string(),
+            client_id: "filex".to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost/api/auth/oidc/callback".to_string(),
+            groups_claim: "groups".to_string(),
+            allowed_groups: Vec::new(),
+        });
+        let app = Router::new()
+            .route("/api/auth/oidc/callback", get(oidc_callback))
+            .with_state(Arc::new(AuthState::new(config)));
+
+        let request = Request::builder()
+            .uri("/api/auth/oidc/callback?code=code&state=theirs")
+            .header("cookie", "fm_session_oidc_state=mine")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()["location"].to_str().unwrap();
+        assert!(location.starts_with("/?login_error="), "{location}");
+        let cookies: Vec<_> = response
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect();
+        // No session, and the state cookie is cleared
+        assert!(
+            cookies
+                .iter()
+                .all(|cookie| !cookie.starts_with("fm_session="))
+        );
+        assert!(
+            cookies
+                .iter()
+                .any(|cookie| cookie.starts_with("fm_session_oidc_state=;")
+                    && cookie.contains("Max-Age=0"))
+        );
+    }
 }
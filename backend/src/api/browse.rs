@@ -3,20 +3,53 @@ use axum::{
     extract::{Query, State},
     http::StatusCode,
 };
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqlitePool;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::api::{SortField, SortOrder};
+use crate::api::{ApiError, ErrorResponse, SortField, SortOrder, notes};
 use crate::db;
 use crate::models::{FileEntry, TreeNode};
-use crate::services::{FilesystemService, SearchService};
+use crate::services::filesystem::{is_hidden_name, is_hidden_path};
+use crate::services::metadata::MetadataError;
+use crate::services::{
+    DedupStore, DownloadThrottle, FileTemplates, FilesystemService, FsError, HookService,
+    LiveUpdates, LockManager, Readahead, SearchService, StorageBackend, StorageGuard,
+    UploadTracker, VirusScanner, WebhookService, preview,
+};
 
 pub struct AppState {
     pub fs: FilesystemService,
+    /// Where creating, renaming, moving, copying and deleting happen; the
+    /// root directory, or an in-memory tree in tests
+    pub backend: Arc<dyn StorageBackend>,
     pub pool: SqlitePool,
     pub search: Arc<SearchService>,
+    /// Database volume
+    pub storage: StorageGuard,
+    /// Root volume, checked before uploads and copies
+    pub root_storage: StorageGuard,
+    /// Default for the `show_hidden` query parameter
+    pub show_hidden: bool,
+    pub webhooks: Arc<WebhookService>,
+    pub hooks: Arc<HookService>,
+    pub uploads: UploadTracker,
+    pub downloads: DownloadThrottle,
+    /// Prefetching for media read in consecutive ranges
+    pub readahead: Readahead,
+    pub locks: LockManager,
+    /// Changes pushed to clients watching directories
+    pub live: LiveUpdates,
+    /// Templates offered for new files
+    pub templates: FileTemplates,
+    /// Whether `/api/files/link` may create links
+    pub allow_links: bool,
+    /// Scans uploads before they are moved into place
+    pub scanner: VirusScanner,
+    /// Stores uploads once per distinct content, when configured
+    pub dedup: Arc<DedupStore>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,6 +59,99 @@ pub struct ListQuery {
     pub limit: Option<usize>,
     pub sort_by: Option<SortField>,
     pub sort_order: Option<SortOrder>,
+    pub show_hidden: Option<bool>,
+    /// Also list entries excluded by `.fxignore`
+    pub include_ignored: Option<bool>,
+    /// Where to read the listing from; `index` falls back to `fs` when the
+    /// index cannot answer or is out of date for this directory
+    pub source: Option<ListSource>,
+    /// Only files running at least this many seconds
+    pub min_duration: Option<f64>,
+    /// Only files at least this large: `720p`, `1080p`, `4k`, `8k`, or
+    /// `WIDTHxHEIGHT`
+    pub min_resolution: Option<String>,
+    /// Only files of this kind
+    pub mime_class: Option<FileCategory>,
+}
+
+/// The media filters of a listing. Files are checked against their indexed
+/// metadata, so files not indexed yet never match; folders are left out.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct MediaFilter {
+    min_duration: Option<f64>,
+    /// Width and height of the smallest size, either way round
+    min_resolution: Option<(u32, u32)>,
+    class: Option<FileCategory>,
+}
+
+impl MediaFilter {
+    fn from_query(query: &ListQuery) -> Result<Self, ApiError> {
+        let min_resolution = match query.min_resolution.as_deref() {
+            Some(value) => Some(parse_resolution(value).ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "Invalid min_resolution: {value} (expected e.g. 1080p, 4k or 1920x1080)"
+                    ),
+                )
+            })?),
+            None => None,
+        };
+        Ok(Self {
+            min_duration: query.min_duration,
+            min_resolution,
+            class: query.mime_class,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn matches(&self, entry: &FileEntry) -> bool {
+        if entry.is_dir {
+            return false;
+        }
+        let long_enough = self
+            .min_duration
+            .is_none_or(|min| entry.duration.is_some_and(|d| d >= min));
+        // A 1920x800 scope film still counts as 1080p, and portrait video
+        // as its landscape equivalent
+        let large_enough = self.min_resolution.is_none_or(|(min_w, min_h)| {
+            let (Some(w), Some(h)) = (entry.width, entry.height) else {
+                return false;
+            };
+            w.max(h) >= min_w || w.min(h) >= min_h
+        });
+        let right_kind = self
+            .class
+            .is_none_or(|class| FileCategory::of(entry.mime_type.as_deref()) == class);
+        long_enough && large_enough && right_kind
+    }
+}
+
+/// `720p`, `1080p`, `4k`, `8k`, or `WIDTHxHEIGHT` as a width and height
+fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let value = value.trim().to_ascii_lowercase();
+    let height = match value.as_str() {
+        "4k" | "uhd" => 2160,
+        "8k" => 4320,
+        _ => match value.split_once('x') {
+            Some((w, h)) => {
+                let size = (w.parse().ok()?, h.parse().ok()?);
+                return (size.0 > 0 && size.1 > 0).then_some(size);
+            }
+            None => value.strip_suffix('p')?.parse().ok()?,
+        },
+    };
+    (height > 0).then_some((height * 16 / 9, height))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListSource {
+    Fs,
+    Index,
 }
 
 #[derive(Debug, Serialize)]
@@ -37,44 +163,96 @@ pub struct ListResponse {
     pub sort_by: SortField,
     pub sort_order: SortOrder,
     pub total: usize,
+    /// The directory is under a `.fxnopreview` marker
+    pub previews_disabled: bool,
+    /// Where the listing came from
+    pub source: ListSource,
 }
 
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
+impl From<&MetadataError> for ErrorResponse {
+    fn from(e: &MetadataError) -> Self {
+        let response = Self::new(e.to_string());
+        match e {
+            MetadataError::FfprobeNotFound => {
+                response.with_hint("Install FFmpeg so that ffprobe is on the server's PATH.")
+            }
+            MetadataError::PopplerNotFound => response.with_hint(
+                "Install poppler-utils so that pdfinfo and pdftoppm are on the server's PATH.",
+            ),
+            MetadataError::Timeout => response.with_hint(
+                "The file took too long to process; it may be very large or on slow storage.",
+            ),
+            _ => response,
+        }
+    }
 }
 
 /// List directory contents
 pub async fn list_directory(
     State(state): State<Arc<AppState>>,
     Query(query): Query<ListQuery>,
-) -> Result<Json<ListResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ListResponse>, ApiError> {
+    let filter = MediaFilter::from_query(&query)?;
     let path = query.path.unwrap_or_else(|| "/".to_string());
     let offset = query.offset.unwrap_or(0);
     let limit = query.limit.unwrap_or(1000).max(1);
     let sort_by = query.sort_by.unwrap_or(SortField::Name);
     let sort_order = query.sort_order.unwrap_or(SortOrder::Asc);
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
 
     // Get file list from filesystem
-    let entries = state.fs.list_directory(&path).map_err(|e| {
-        let (status, msg) = match &e {
-            crate::services::filesystem::FsError::NotFound(_) => {
-                (StatusCode::NOT_FOUND, e.to_string())
-            }
-            crate::services::filesystem::FsError::PermissionDenied(_) => {
-                (StatusCode::FORBIDDEN, e.to_string())
-            }
-            crate::services::filesystem::FsError::PathEscape => {
-                (StatusCode::FORBIDDEN, "Access denied".to_string())
-            }
-            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-        };
-        (status, Json(ErrorResponse { error: msg }))
-    })?;
+    let respect_ignore = !query.include_ignored.unwrap_or(false);
 
-    let total = entries.len();
+    // The index holds what the indexer walked: ignored entries are never in
+    // it, and dotfiles only when shown by default. Filtered listings are
+    // read from disk and filtered after enrichment below.
+    let index_can_answer =
+        respect_ignore && (!show_hidden || state.show_hidden) && filter.is_empty();
+    if query.source == Some(ListSource::Index)
+        && index_can_answer
+        && let Some((mut entries, total)) = list_from_index(
+            &state,
+            &path,
+            show_hidden,
+            sort_by,
+            sort_order,
+            offset,
+            limit,
+        )
+        .await
+    {
+        notes::flag_notes(&state, &mut entries).await;
+        let previews_disabled = state
+            .fs
+            .resolve_path(&path)
+            .is_ok_and(|dir| preview::previews_disabled(state.fs.root(), &dir));
+        return Ok(Json(ListResponse {
+            path,
+            entries,
+            offset,
+            limit,
+            sort_by,
+            sort_order,
+            total,
+            previews_disabled,
+            source: ListSource::Index,
+        }));
+    }
+
+    let entries = state
+        .fs
+        .list_directory(&path, respect_ignore)
+        .map_err(|e| ApiError::from(&e))?;
 
     let mut entries = entries;
+    if !show_hidden {
+        entries.retain(|entry| !is_hidden_name(&entry.name));
+    }
+
+    let previews_disabled = state
+        .fs
+        .resolve_path(&path)
+        .is_ok_and(|dir| preview::previews_disabled(state.fs.root(), &dir));
 
     // Enrich with indexed media metadata
     let paths: Vec<String> = entries.iter().map(|e| e.path.clone()).collect();
@@ -87,15 +265,35 @@ pub async fn list_directory(
                 entry.width = indexed.width.map(|w| w as u32);
                 entry.height = indexed.height.map(|h| h as u32);
                 entry.duration = indexed.duration;
+                entry.artist = indexed.artist.clone();
+                entry.album = indexed.album.clone();
+                entry.title = indexed.title.clone();
+                entry.track_number = indexed.track_number.map(|n| n as u32);
+                entry.page_count = indexed.page_count.map(|n| n as u32);
+                entry.author = indexed.author.clone();
+                entry.original_name = indexed.original_name.clone();
+            }
+        }
+    }
+    if let Ok(mut links) = db::links_for_paths(&state.pool, &paths).await {
+        for entry in &mut entries {
+            if let Some((kind, target)) = links.remove(&entry.path) {
+                entry.link_kind = Some(kind);
+                entry.link_target = Some(target);
             }
         }
     }
 
+    if !filter.is_empty() {
+        entries.retain(|entry| filter.matches(entry));
+    }
+    let total = entries.len();
+
     sort_entries(&mut entries, sort_by, sort_order);
 
     // Apply pagination after sorting so slice boundaries are stable
-    let paged_entries: Vec<_> = entries.into_iter().skip(offset).take(limit).collect();
-    let entries = paged_entries;
+    let mut entries: Vec<_> = entries.into_iter().skip(offset).take(limit).collect();
+    notes::flag_notes(&state, &mut entries).await;
 
     Ok(Json(ListResponse {
         path,
@@ -105,9 +303,60 @@ pub async fn list_directory(
         sort_by,
         sort_order,
         total,
+        previews_disabled,
+        source: ListSource::Fs,
     }))
 }
 
+/// One page of `path` from the index, if the index is current for it: the
+/// directory's modification time, which changes whenever an entry is added,
+/// removed, or renamed in it, must match what the indexer recorded
+async fn list_from_index(
+    state: &AppState,
+    path: &str,
+    show_hidden: bool,
+    sort_by: SortField,
+    sort_order: SortOrder,
+    offset: usize,
+    limit: usize,
+) -> Option<(Vec<FileEntry>, usize)> {
+    let dir = state.fs.resolve_path(path).ok()?;
+    let fs_modified = std::fs::metadata(&dir)
+        .ok()
+        .filter(|m| m.is_dir())?
+        .modified()
+        .ok()
+        .map(|t| DateTime::<Utc>::from(t).to_rfc3339());
+    let indexed_path = if path.trim_end_matches('/').is_empty() {
+        "/".to_string()
+    } else {
+        format!("/{}", path.trim_matches('/'))
+    };
+    let (_, indexed_modified, _, _) = db::get_file_by_path(&state.pool, &indexed_path)
+        .await
+        .ok()
+        .flatten()?;
+    if fs_modified.is_none() || indexed_modified != fs_modified {
+        return None;
+    }
+
+    let (rows, total) = db::list_indexed_children(
+        &state.pool,
+        &indexed_path,
+        show_hidden,
+        sort_by.into(),
+        sort_order.into(),
+        limit as i64,
+        offset as i64,
+    )
+    .await
+    .ok()?;
+    Some((
+        rows.into_iter().map(FileEntry::from).collect(),
+        total as usize,
+    ))
+}
+
 fn sort_entries(entries: &mut [FileEntry], sort_by: SortField, sort_order: SortOrder) {
     use std::cmp::Ordering;
 
@@ -172,41 +421,312 @@ fn sort_entries(entries: &mut [FileEntry], sort_by: SortField, sort_order: SortO
     });
 }
 
-/// Get directory tree for sidebar
+/// Deepest tree one request may expand
+const MAX_TREE_DEPTH: usize = 4;
+
+#[derive(Debug, Deserialize)]
+pub struct TreeQuery {
+    pub path: Option<String>,
+    pub show_hidden: Option<bool>,
+    /// Also list directories excluded by `.fxignore`
+    pub include_ignored: Option<bool>,
+    /// Levels to expand, 1 (the default) to `MAX_TREE_DEPTH`
+    pub depth: Option<usize>,
+}
+
+/// Paths of `nodes` and all their expanded descendants
+fn tree_paths(nodes: &[TreeNode], paths: &mut Vec<String>) {
+    for node in nodes {
+        paths.push(node.path.clone());
+        if let Some(children) = &node.children {
+            tree_paths(children, paths);
+        }
+    }
+}
+
+fn fill_counts(nodes: &mut [TreeNode], counts: &HashMap<String, (i64, i64)>) {
+    for node in nodes {
+        if let Some(&(dirs, files)) = counts.get(&node.path) {
+            node.child_dir_count = Some(dirs);
+            node.file_count = Some(files);
+        }
+        if let Some(children) = &mut node.children {
+            fill_counts(children, counts);
+        }
+    }
+}
+
+/// Get directory tree for sidebar, `depth` levels deep, with the number of
+/// subdirectories and files in each node where the index knows them
 pub async fn get_tree(
     State(state): State<Arc<AppState>>,
-    Query(query): Query<ListQuery>,
-) -> Result<Json<Vec<TreeNode>>, (StatusCode, Json<ErrorResponse>)> {
+    Query(query): Query<TreeQuery>,
+) -> Result<Json<Vec<TreeNode>>, ApiError> {
     let path = query.path.unwrap_or_else(|| "/".to_string());
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
+    let depth = query.depth.unwrap_or(1).clamp(1, MAX_TREE_DEPTH);
 
-    let nodes = state.fs.get_tree_node(&path).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
+    let respect_ignore = !query.include_ignored.unwrap_or(false);
+
+    let mut nodes = state
+        .fs
+        .get_tree(&path, show_hidden, respect_ignore, depth)
+        .map_err(|e| ApiError::from(&e))?;
+
+    let mut paths = Vec::new();
+    tree_paths(&nodes, &mut paths);
+    match db::get_child_counts(&state.pool, &paths, show_hidden).await {
+        Ok(counts) => fill_counts(&mut nodes, &counts),
+        Err(e) => tracing::warn!("Failed to count tree children: {}", e),
+    }
+
+    Ok(Json(nodes))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileCategory {
+    Images,
+    Video,
+    Audio,
+    Documents,
+    Other,
+}
+
+impl FileCategory {
+    const ALL: [FileCategory; 5] = [
+        FileCategory::Images,
+        FileCategory::Video,
+        FileCategory::Audio,
+        FileCategory::Documents,
+        FileCategory::Other,
+    ];
+
+    /// The category of a MIME type, as `db::get_type_stats` groups them
+    fn of(mime_type: Option<&str>) -> Self {
+        let Some(mime) = mime_type else {
+            return FileCategory::Other;
+        };
+        if mime.starts_with("image/") {
+            FileCategory::Images
+        } else if mime.starts_with("video/") {
+            FileCategory::Video
+        } else if mime.starts_with("audio/") {
+            FileCategory::Audio
+        } else if mime.starts_with("text/")
+            || matches!(
+                mime,
+                "application/pdf"
+                    | "application/msword"
+                    | "application/rtf"
+                    | "application/epub+zip"
+            )
+            || mime.starts_with("application/vnd.openxmlformats-officedocument.")
+            || mime.starts_with("application/vnd.oasis.opendocument.")
+            || mime.starts_with("application/vnd.ms-")
+        {
+            FileCategory::Documents
+        } else {
+            FileCategory::Other
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            FileCategory::Images => "images",
+            FileCategory::Video => "video",
+            FileCategory::Audio => "audio",
+            FileCategory::Documents => "documents",
+            FileCategory::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoryStats {
+    pub category: FileCategory,
+    pub count: i64,
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectoryStatsResponse {
+    pub path: String,
+    pub total_files: i64,
+    pub total_size: i64,
+    /// Every category in a fixed order, including empty ones
+    pub categories: Vec<CategoryStats>,
+}
+
+/// Storage breakdown by file type for everything indexed under a directory
+pub async fn directory_stats(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<DirectoryStatsResponse>, ApiError> {
+    let path = query.path.unwrap_or_else(|| "/".to_string());
+    let resolved = state.fs.resolve_path(&path)?;
+    if !resolved.is_dir() {
+        return Err(ApiError::from(&FsError::NotADirectory(path)));
+    }
+
+    let path = state.fs.relative_path(&resolved);
+    let rows = db::get_type_stats(&state.pool, &path)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let categories: Vec<CategoryStats> = FileCategory::ALL
+        .into_iter()
+        .map(|category| {
+            let (count, size) = rows
+                .iter()
+                .find(|(name, _, _)| name == category.as_str())
+                .map(|(_, count, size)| (*count, *size))
+                .unwrap_or((0, 0));
+            CategoryStats {
+                category,
+                count,
+                size,
+            }
+        })
+        .collect();
+
+    Ok(Json(DirectoryStatsResponse {
+        path,
+        total_files: categories.iter().map(|c| c.count).sum(),
+        total_size: categories.iter().map(|c| c.size).sum(),
+        categories,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecentAdditionsQuery {
+    pub path_prefix: Option<String>,
+    /// A span back from now (`12h`, `7d`, `2w`) or a date/RFC 3339 timestamp
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecentDay {
+    /// UTC calendar day, `YYYY-MM-DD`
+    pub date: String,
+    pub count: usize,
+    pub total_size: u64,
+    pub entries: Vec<FileEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecentAdditionsResponse {
+    pub path_prefix: String,
+    pub since: DateTime<Utc>,
+    pub days: Vec<RecentDay>,
+    pub total: usize,
+    /// More files were added than `limit`; the oldest were left out
+    pub truncated: bool,
+}
+
+/// Parse the `since` parameter relative to `now`
+fn parse_since(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Some(unit) = value.chars().last().filter(char::is_ascii_alphabetic)
+        && let Ok(amount) = value[..value.len() - 1].parse::<i64>()
+    {
+        let span = match unit {
+            'h' => Duration::try_hours(amount)?,
+            'd' => Duration::try_days(amount)?,
+            'w' => Duration::try_weeks(amount)?,
+            _ => return None,
+        };
+        return now.checked_sub_signed(span);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Files first indexed since a point in time, grouped by day, newest first
+pub async fn recent_additions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RecentAdditionsQuery>,
+) -> Result<Json<RecentAdditionsResponse>, ApiError> {
+    let since_param = query.since.as_deref().unwrap_or("7d");
+    let since = parse_since(since_param, Utc::now()).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid since value: {since_param}"),
         )
     })?;
+    let limit = query.limit.unwrap_or(500).clamp(1, 5000);
 
-    Ok(Json(nodes))
+    let path = query.path_prefix.unwrap_or_else(|| "/".to_string());
+    let resolved = state.fs.resolve_path(&path)?;
+    let path_prefix = state.fs.relative_path(&resolved);
+
+    // One extra row tells whether the result was cut off
+    let mut rows = db::get_recent_additions(
+        &state.pool,
+        &path_prefix,
+        &since.format("%Y-%m-%d %H:%M:%S").to_string(),
+        limit as i64 + 1,
+    )
+    .await
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !state.show_hidden {
+        rows.retain(|row| !is_hidden_path(&row.file.path));
+    }
+    let truncated = rows.len() > limit;
+    rows.truncate(limit);
+
+    let total = rows.len();
+    let mut days: Vec<RecentDay> = Vec::new();
+    for row in rows {
+        let date = row
+            .first_indexed_at
+            .get(..10)
+            .unwrap_or_default()
+            .to_string();
+        let entry = FileEntry::from(row.file);
+        match days.last_mut() {
+            Some(day) if day.date == date => {
+                day.count += 1;
+                day.total_size += entry.size.unwrap_or(0);
+                day.entries.push(entry);
+            }
+            _ => days.push(RecentDay {
+                date,
+                count: 1,
+                total_size: entry.size.unwrap_or(0),
+                entries: vec![entry],
+            }),
+        }
+    }
+
+    Ok(Json(RecentAdditionsResponse {
+        path_prefix,
+        since,
+        days,
+        total,
+        truncated,
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::services::FilesystemService;
+    use crate::api::ErrorCode;
     use chrono::Utc;
     use sqlx::sqlite::SqlitePoolOptions;
     use std::fs;
     use tempfile::tempdir;
 
-    fn now_sqlite_timestamp() -> String {
-        Utc::now()
-            .naive_utc()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string()
-    }
-
     async fn test_state() -> (Arc<AppState>, tempfile::TempDir, std::path::PathBuf) {
         let tmp = tempdir().expect("tempdir created");
         let root = tmp.path().join("root");
@@ -222,9 +742,8 @@ mod tests {
         let search = Arc::new(crate::services::SearchService::new());
 
         let state = Arc::new(AppState {
-            fs: FilesystemService::new(root.clone()),
-            pool,
             search,
+            ..AppState::for_tests(root.clone(), pool)
         });
 
         (state, tmp, root)
@@ -238,19 +757,12 @@ mod tests {
 
         // Seed index row
         let indexed = crate::models::IndexedFileRow {
-            id: 0,
-            path: "/video.mp4".to_string(),
-            name: "video.mp4".to_string(),
-            is_dir: false,
             size: Some(4),
-            created_at: None,
-            modified_at: None,
             mime_type: Some("video/mp4".to_string()),
             width: Some(1920),
             height: Some(1080),
             duration: Some(12.5),
-            metadata_status: "complete".to_string(),
-            indexed_at: now_sqlite_timestamp(),
+            ..crate::models::IndexedFileRow::for_tests("/video.mp4", false)
         };
         crate::db::upsert_file(&state.pool, &indexed).await.unwrap();
 
@@ -262,6 +774,12 @@ mod tests {
                 limit: None,
                 sort_by: None,
                 sort_order: None,
+                show_hidden: None,
+                include_ignored: None,
+                source: None,
+                min_duration: None,
+                min_resolution: None,
+                mime_class: None,
             }),
         )
         .await
@@ -273,6 +791,7 @@ mod tests {
         assert_eq!(resp.0.sort_by, SortField::Name);
         assert_eq!(resp.0.sort_order, SortOrder::Asc);
         assert_eq!(resp.0.total, 1);
+        assert!(!resp.0.previews_disabled);
         assert_eq!(entries.len(), 1);
         let entry = &entries[0];
         assert_eq!(entry.path, "/video.mp4");
@@ -282,23 +801,252 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn list_directory_maps_not_found_to_404() {
-        let (state, _tmp, _) = test_state().await;
+    async fn list_directory_filters_by_media_metadata() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir(root.join("folder")).unwrap();
+        for (name, size, duration) in [
+            ("uhd.mkv", (3840, 1600), Some(7200.0)),
+            ("hd.mp4", (1920, 1080), Some(600.0)),
+            ("photo.jpg", (6000, 4000), None),
+        ] {
+            fs::write(root.join(name), b"data").unwrap();
+            let indexed = crate::models::IndexedFileRow {
+                size: Some(4),
+                width: Some(size.0),
+                height: Some(size.1),
+                duration,
+                ..crate::models::IndexedFileRow::for_tests(&format!("/{name}"), false)
+            };
+            crate::db::upsert_file(&state.pool, &indexed).await.unwrap();
+        }
+        fs::write(root.join("unindexed.mp4"), b"data").unwrap();
 
-        let err = list_directory(
-            State(state),
+        let query = |min_duration, min_resolution: Option<&str>, mime_class| ListQuery {
+            path: Some("/".to_string()),
+            offset: None,
+            limit: None,
+            sort_by: None,
+            sort_order: None,
+            show_hidden: None,
+            include_ignored: None,
+            source: None,
+            min_duration,
+            min_resolution: min_resolution.map(str::to_string),
+            mime_class,
+        };
+        let names = |resp: ListResponse| -> Vec<String> {
+            assert_eq!(resp.total, resp.entries.len());
+            resp.entries.into_iter().map(|e| e.name).collect()
+        };
+
+        let resp = list_directory(
+            State(state.clone()),
+            Query(query(None, Some("4k"), Some(FileCategory::Video))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(names(resp.0), ["uhd.mkv"]);
+
+        let resp = list_directory(State(state.clone()), Query(query(Some(60.0), None, None)))
+            .await
+            .unwrap();
+        assert_eq!(names(resp.0), ["hd.mp4", "uhd.mkv"]);
+
+        let resp = list_directory(
+            State(state.clone()),
+            Query(query(None, Some("1920x1080"), Some(FileCategory::Images))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(names(resp.0), ["photo.jpg"]);
+
+        let err = list_directory(State(state), Query(query(None, Some("huge"), None)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn list_directory_maps_errors_like_other_handlers() {
+        let (state, _tmp, root) = test_state().await;
+        fs::write(root.join("notes.txt"), b"n").unwrap();
+
+        for (path, status, code) in [
+            ("/missing", StatusCode::NOT_FOUND, ErrorCode::NotFound),
+            (
+                "/notes.txt",
+                StatusCode::BAD_REQUEST,
+                ErrorCode::NotADirectory,
+            ),
+        ] {
+            let err = list(&state, path).await.unwrap_err();
+            assert_eq!(err.status, status, "{path}");
+            assert_eq!(err.body.code, Some(code), "{path}");
+            assert!(err.body.hint.is_none());
+        }
+    }
+
+    async fn list(state: &Arc<AppState>, path: &str) -> Result<Json<ListResponse>, ApiError> {
+        list_directory(
+            State(state.clone()),
             Query(ListQuery {
-                path: Some("/missing".to_string()),
+                path: Some(path.to_string()),
                 offset: None,
                 limit: None,
                 sort_by: None,
                 sort_order: None,
+                show_hidden: None,
+                include_ignored: None,
+                source: None,
+                min_duration: None,
+                min_resolution: None,
+                mime_class: None,
+            }),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn directory_stats_groups_by_type_under_prefix() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("photos")).unwrap();
+        fs::create_dir_all(root.join("photos_old")).unwrap();
+
+        for (path, mime, size) in [
+            ("/photos", None, None),
+            ("/photos/a.jpg", Some("image/jpeg"), Some(100)),
+            ("/photos/b.png", Some("image/png"), Some(50)),
+            ("/photos/notes.pdf", Some("application/pdf"), Some(10)),
+            ("/photos/clip.mp4", Some("video/mp4"), Some(1000)),
+            ("/photos/data.bin", None, Some(5)),
+            ("/photos_old/c.jpg", Some("image/jpeg"), Some(7)),
+        ] {
+            let row = crate::models::IndexedFileRow {
+                size,
+                mime_type: mime.map(str::to_string),
+                ..crate::models::IndexedFileRow::for_tests(path, mime.is_none() && size.is_none())
+            };
+            db::upsert_file(&state.pool, &row).await.unwrap();
+        }
+
+        let Json(resp) = directory_stats(
+            State(state.clone()),
+            Query(StatsQuery {
+                path: Some("/photos/".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resp.path, "/photos");
+        assert_eq!(resp.total_files, 5);
+        assert_eq!(resp.total_size, 1165);
+        let by_category: Vec<_> = resp
+            .categories
+            .iter()
+            .map(|c| (c.category, c.count, c.size))
+            .collect();
+        assert_eq!(
+            by_category,
+            vec![
+                (FileCategory::Images, 2, 150),
+                (FileCategory::Video, 1, 1000),
+                (FileCategory::Audio, 0, 0),
+                (FileCategory::Documents, 1, 10),
+                (FileCategory::Other, 1, 5),
+            ]
+        );
+
+        let Json(root_stats) = directory_stats(State(state), Query(StatsQuery { path: None }))
+            .await
+            .unwrap();
+        assert_eq!(root_stats.total_files, 6);
+    }
+
+    #[tokio::test]
+    async fn recent_additions_groups_new_files_by_day() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("inbox")).unwrap();
+
+        for path in ["/inbox/new.jpg", "/inbox/old.jpg", "/elsewhere.txt"] {
+            let row = crate::models::IndexedFileRow {
+                size: Some(10),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
+            };
+            db::upsert_file(&state.pool, &row).await.unwrap();
+        }
+        sqlx::query(
+            "UPDATE indexed_files SET first_indexed_at = '2020-01-01 00:00:00' WHERE path = ?",
+        )
+        .bind("/inbox/old.jpg")
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+        let Json(resp) = recent_additions(
+            State(state.clone()),
+            Query(RecentAdditionsQuery {
+                path_prefix: Some("/inbox".to_string()),
+                since: Some("7d".to_string()),
+                limit: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resp.total, 1);
+        assert!(!resp.truncated);
+        assert_eq!(resp.days.len(), 1);
+        assert_eq!(resp.days[0].entries[0].path, "/inbox/new.jpg");
+        assert_eq!(resp.days[0].total_size, 10);
+
+        let err = recent_additions(
+            State(state),
+            Query(RecentAdditionsQuery {
+                path_prefix: None,
+                since: Some("soon".to_string()),
+                limit: None,
             }),
         )
         .await
         .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn parse_since_accepts_spans_and_dates() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        assert_eq!(
+            parse_since("2d", now),
+            Some(Utc.with_ymd_and_hms(2024, 3, 8, 12, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_since("2024-03-01", now),
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap())
+        );
+        assert!(parse_since("3x", now).is_none());
+    }
 
-        assert_eq!(err.0, StatusCode::NOT_FOUND);
+    #[test]
+    fn error_response_hints_common_failures() {
+        let denied = ErrorResponse::from(&FsError::PermissionDenied("/photos".to_string()));
+        assert!(denied.hint.unwrap().contains("PUID"));
+
+        let refused =
+            ErrorResponse::from(&FsError::PermissionDenied("Cannot delete root".to_string()));
+        assert!(refused.hint.is_none());
+
+        let escape = ErrorResponse::from(&FsError::PathEscape);
+        assert!(escape.hint.unwrap().contains("symlink"));
+
+        let ffprobe = ErrorResponse::from(&MetadataError::FfprobeNotFound);
+        assert!(ffprobe.hint.unwrap().contains("FFmpeg"));
+
+        let json = serde_json::to_value(ErrorResponse::new("Nope")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "code": "internal", "message": "Nope", "error": "Nope" })
+        );
     }
 
     #[tokio::test]
@@ -318,6 +1066,12 @@ mod tests {
                 limit: Some(10),
                 sort_by: Some(SortField::Name),
                 sort_order: Some(SortOrder::Asc),
+                show_hidden: None,
+                include_ignored: None,
+                source: None,
+                min_duration: None,
+                min_resolution: None,
+                mime_class: None,
             }),
         )
         .await
@@ -329,6 +1083,173 @@ mod tests {
         assert_eq!(resp.0.entries.len(), 10);
     }
 
+    #[tokio::test]
+    async fn list_directory_hides_dotfiles_unless_requested() {
+        let (state, _tmp, root) = test_state().await;
+        fs::write(root.join(".env"), b"secret").unwrap();
+        fs::write(root.join("readme.txt"), b"hi").unwrap();
+
+        let query = |show_hidden| ListQuery {
+            path: Some("/".to_string()),
+            offset: None,
+            limit: None,
+            sort_by: None,
+            sort_order: None,
+            show_hidden,
+            include_ignored: None,
+            source: None,
+            min_duration: None,
+            min_resolution: None,
+            mime_class: None,
+        };
+
+        let resp = list_directory(State(state.clone()), Query(query(None)))
+            .await
+            .unwrap();
+        assert_eq!(resp.0.total, 1);
+        assert_eq!(resp.0.entries[0].name, "readme.txt");
+
+        let resp = list_directory(State(state), Query(query(Some(true))))
+            .await
+            .unwrap();
+        assert_eq!(resp.0.total, 2);
+    }
+
+    #[tokio::test]
+    async fn list_directory_reads_index_while_it_is_current() {
+        let (state, _tmp, root) = test_state().await;
+        fs::write(root.join("b.txt"), b"b").unwrap();
+
+        let row =
+            |path: &str, is_dir: bool, modified_at: Option<String>| crate::models::IndexedFileRow {
+                modified_at,
+                ..crate::models::IndexedFileRow::for_tests(path, is_dir)
+            };
+        let root_modified =
+            DateTime::<Utc>::from(fs::metadata(&root).unwrap().modified().unwrap()).to_rfc3339();
+        crate::db::upsert_file(&state.pool, &row("/", true, Some(root_modified)))
+            .await
+            .unwrap();
+        // Only in the index, so the listing shows where it came from
+        for path in ["/a.txt", "/b.txt", "/.hidden"] {
+            crate::db::upsert_file(&state.pool, &row(path, false, None))
+                .await
+                .unwrap();
+        }
+
+        let query = || ListQuery {
+            path: Some("/".to_string()),
+            offset: None,
+            limit: None,
+            sort_by: None,
+            sort_order: None,
+            show_hidden: None,
+            include_ignored: None,
+            source: Some(ListSource::Index),
+            min_duration: None,
+            min_resolution: None,
+            mime_class: None,
+        };
+
+        let resp = list_directory(State(state.clone()), Query(query()))
+            .await
+            .unwrap();
+        assert_eq!(resp.0.source, ListSource::Index);
+        assert_eq!(resp.0.total, 2);
+        let names: Vec<_> = resp.0.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "b.txt"]);
+
+        // A new entry changes the directory's mtime, so the index is stale
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(root.join("c.txt"), b"c").unwrap();
+        let resp = list_directory(State(state), Query(query())).await.unwrap();
+        assert_eq!(resp.0.source, ListSource::Fs);
+        assert_eq!(resp.0.total, 2);
+    }
+
+    #[tokio::test]
+    async fn tree_expands_levels_with_indexed_counts() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("photos/2024/may")).unwrap();
+        fs::create_dir_all(root.join("docs")).unwrap();
+
+        for (path, is_dir) in [
+            ("/photos", true),
+            ("/photos/2024", true),
+            ("/photos/a.jpg", false),
+            ("/photos/b.jpg", false),
+            ("/photos/.thumbs.db", false),
+            ("/photos/2024/may", true),
+        ] {
+            let row = crate::models::IndexedFileRow::for_tests(path, is_dir);
+            crate::db::upsert_file(&state.pool, &row).await.unwrap();
+        }
+
+        let tree = |depth: Option<usize>| {
+            get_tree(
+                State(state.clone()),
+                Query(TreeQuery {
+                    path: None,
+                    show_hidden: None,
+                    include_ignored: None,
+                    depth,
+                }),
+            )
+        };
+
+        let nodes = tree(Some(2)).await.unwrap().0;
+        let names: Vec<_> = nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, ["docs", "photos"]);
+        // Not indexed, so no counts
+        assert_eq!(nodes[0].file_count, None);
+        assert!(nodes[0].children.is_none());
+        let photos = &nodes[1];
+        assert_eq!(photos.child_dir_count, Some(1));
+        assert_eq!(photos.file_count, Some(2));
+        let year = &photos.children.as_ref().unwrap()[0];
+        assert_eq!(year.path, "/photos/2024");
+        assert_eq!(year.child_dir_count, Some(1));
+        assert_eq!(year.file_count, Some(0));
+        assert!(year.children.is_none());
+
+        assert!(nodes.iter().all(|n| n.readable));
+        let nodes = tree(None).await.unwrap().0;
+        assert!(nodes[1].children.is_none());
+        assert_eq!(nodes[1].file_count, Some(2));
+        let nodes = tree(Some(100)).await.unwrap().0;
+        assert!(nodes[1].children.as_ref().unwrap()[0].children.is_some());
+    }
+
+    #[tokio::test]
+    async fn tree_errors_map_to_status_codes() {
+        let (state, _tmp, root) = test_state().await;
+        fs::write(root.join("notes.txt"), b"").unwrap();
+
+        let tree = |path: &str| {
+            get_tree(
+                State(state.clone()),
+                Query(TreeQuery {
+                    path: Some(path.to_string()),
+                    show_hidden: None,
+                    include_ignored: None,
+                    depth: None,
+                }),
+            )
+        };
+        assert_eq!(
+            tree("/missing").await.unwrap_err().status,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            tree("/notes.txt").await.unwrap_err().status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            tree("/../..").await.unwrap_err().status,
+            StatusCode::FORBIDDEN
+        );
+    }
+
     #[tokio::test]
     async fn list_directory_sorts_by_size_descending() {
         let (state, _tmp, root) = test_state().await;
@@ -352,6 +1273,12 @@ mod tests {
                 limit: Some(10),
                 sort_by: Some(SortField::Size),
                 sort_order: Some(SortOrder::Desc),
+                show_hidden: None,
+                include_ignored: None,
+                source: None,
+                min_duration: None,
+                min_resolution: None,
+                mime_class: None,
             }),
         )
         .await
@@ -0,0 +1,305 @@
+//! The error body every endpoint returns, so clients can branch on a stable
+//! `code` rather than on status codes or message text.
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde::ser::{SerializeMap, Serializer};
+
+use crate::services::FsError;
+
+/// Kind of failure, stable across releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    InvalidName,
+    NotADirectory,
+    NotAFile,
+    NotFound,
+    /// The target already exists
+    Exists,
+    /// The request conflicts with the current state
+    Conflict,
+    /// The entry changed since the client listed it
+    Modified,
+    Forbidden,
+    /// The path resolves outside the served root
+    PathEscape,
+    Unauthorized,
+    /// Another client holds a lock on the path
+    Locked,
+    /// Not enough space left on the volume
+    Quota,
+    /// The volume is mounted read-only
+    Readonly,
+    /// The virus scanner found something in an upload
+    Infected,
+    TooLarge,
+    RangeNotSatisfiable,
+    UnsupportedMediaType,
+    RateLimited,
+    Timeout,
+    Unavailable,
+    NotImplemented,
+    Internal,
+}
+
+impl ErrorCode {
+    /// The code for a status, for errors that do not name a more specific one
+    pub fn for_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::PRECONDITION_FAILED => ErrorCode::Modified,
+            StatusCode::LOCKED => ErrorCode::Locked,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::TooLarge,
+            StatusCode::RANGE_NOT_SATISFIABLE => ErrorCode::RangeNotSatisfiable,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+            StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => ErrorCode::Timeout,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Unavailable,
+            StatusCode::NOT_IMPLEMENTED => ErrorCode::NotImplemented,
+            StatusCode::INSUFFICIENT_STORAGE => ErrorCode::Quota,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ErrorResponse {
+    /// Set from the status when the error is sent, unless already specific
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    /// The path the error is about
+    pub path: Option<String>,
+    /// Structured detail for clients, e.g. the conflicting entry
+    pub details: Option<serde_json::Value>,
+    /// What the operator can do about it, for failures with a known remedy
+    pub hint: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            code: None,
+            message: message.into(),
+            path: None,
+            details: None,
+            hint: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+impl Serialize for ErrorResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", &self.code.unwrap_or(ErrorCode::Internal))?;
+        map.serialize_entry("message", &self.message)?;
+        // Clients before `code` and `message` read the message from here
+        map.serialize_entry("error", &self.message)?;
+        if let Some(path) = &self.path {
+            map.serialize_entry("path", path)?;
+        }
+        if let Some(details) = &self.details {
+            map.serialize_entry("details", details)?;
+        }
+        if let Some(hint) = &self.hint {
+            map.serialize_entry("hint", hint)?;
+        }
+        map.end()
+    }
+}
+
+const PERMISSION_HINT: &str = "The server process cannot access this path. When running in Docker, \
+     set PUID/PGID to the owner of the mounted directory (run `id` on the host).";
+
+/// Status and code for a filesystem error
+pub(crate) fn classify_fs_error(e: &FsError) -> (StatusCode, ErrorCode) {
+    use std::io::ErrorKind;
+    match e {
+        FsError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::NotFound),
+        FsError::PermissionDenied(_) => (StatusCode::FORBIDDEN, ErrorCode::Forbidden),
+        FsError::PathEscape => (StatusCode::FORBIDDEN, ErrorCode::PathEscape),
+        FsError::InvalidName(_) => (StatusCode::BAD_REQUEST, ErrorCode::InvalidName),
+        FsError::NotADirectory(_) => (StatusCode::BAD_REQUEST, ErrorCode::NotADirectory),
+        FsError::NotAFile(_) => (StatusCode::BAD_REQUEST, ErrorCode::NotAFile),
+        FsError::Modified(_) => (StatusCode::PRECONDITION_FAILED, ErrorCode::Modified),
+        FsError::Io(io) => match io.kind() {
+            ErrorKind::NotFound => (StatusCode::NOT_FOUND, ErrorCode::NotFound),
+            ErrorKind::AlreadyExists => (StatusCode::CONFLICT, ErrorCode::Exists),
+            ErrorKind::PermissionDenied => (StatusCode::FORBIDDEN, ErrorCode::Forbidden),
+            ErrorKind::ReadOnlyFilesystem => (StatusCode::FORBIDDEN, ErrorCode::Readonly),
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => {
+                (StatusCode::INSUFFICIENT_STORAGE, ErrorCode::Quota)
+            }
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal),
+        },
+    }
+}
+
+impl From<&FsError> for ErrorResponse {
+    fn from(e: &FsError) -> Self {
+        let (_, code) = classify_fs_error(e);
+        let response = Self::new(e.to_string()).with_code(code);
+        match e {
+            FsError::NotFound(path)
+            | FsError::NotADirectory(path)
+            | FsError::NotAFile(path)
+            | FsError::Modified(path) => response.with_path(path.clone()),
+            // Refusals such as "Cannot delete root" reuse this variant with a
+            // message rather than a path; those are not permission problems.
+            FsError::PermissionDenied(path) if path.starts_with('/') => {
+                response.with_path(path.clone()).with_hint(PERMISSION_HINT)
+            }
+            FsError::Io(io) if io.kind() == std::io::ErrorKind::PermissionDenied => {
+                response.with_hint(PERMISSION_HINT)
+            }
+            FsError::PathEscape => response.with_hint(
+                "The path resolves outside the served root, usually through a symlink. \
+                 Check where symlinks in this path point.",
+            ),
+            _ => response,
+        }
+    }
+}
+
+/// An error response: a status and the body explaining it
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub body: ErrorResponse,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ErrorResponse::new(message),
+        }
+    }
+
+    pub fn with_body(status: StatusCode, body: ErrorResponse) -> Self {
+        Self { status, body }
+    }
+
+    /// 500 with the error's message
+    pub fn internal(e: impl std::fmt::Display) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.body.code = Some(code);
+        self
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.body.path = Some(path.into());
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.body.details = Some(details);
+        self
+    }
+
+    /// The message clients see
+    pub fn message(&self) -> &str {
+        &self.body.message
+    }
+}
+
+impl From<(StatusCode, Json<ErrorResponse>)> for ApiError {
+    fn from((status, Json(body)): (StatusCode, Json<ErrorResponse>)) -> Self {
+        Self { status, body }
+    }
+}
+
+impl From<&FsError> for ApiError {
+    fn from(e: &FsError) -> Self {
+        let (status, _) = classify_fs_error(e);
+        Self {
+            status,
+            body: ErrorResponse::from(e),
+        }
+    }
+}
+
+impl From<FsError> for ApiError {
+    fn from(e: FsError) -> Self {
+        Self::from(&e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(mut self) -> Response {
+        self.body
+            .code
+            .get_or_insert(ErrorCode::for_status(self.status));
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    async fn body_of(error: ApiError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn errors_carry_a_code_message_and_path() {
+        let (status, body) = body_of(FsError::NotFound("/a.txt".to_string()).into()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["message"], "Path not found: /a.txt");
+        assert_eq!(body["error"], body["message"]);
+        assert_eq!(body["path"], "/a.txt");
+
+        let exists = FsError::Io(std::io::ErrorKind::AlreadyExists.into());
+        let (status, body) = body_of(exists.into()).await;
+        assert_eq!(
+            (status, &body["code"]),
+            (StatusCode::CONFLICT, &"exists".into())
+        );
+
+        let full = FsError::Io(std::io::ErrorKind::StorageFull.into());
+        assert_eq!(body_of(full.into()).await.1["code"], "quota");
+
+        // Without a specific code, the status decides
+        let (_, body) = body_of(ApiError::new(StatusCode::LOCKED, "Locked")).await;
+        assert_eq!(body["code"], "locked");
+        assert!(body.get("path").is_none());
+    }
+}
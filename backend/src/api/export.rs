@@ -0,0 +1,406 @@
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::api::system::format_bytes;
+use crate::api::{ApiError, AppState, ErrorResponse};
+use crate::services::FsError;
+use crate::services::ReportService;
+use crate::services::report::ReportNode;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Html,
+    Pdf,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub path: Option<String>,
+    pub format: Option<ReportFormat>,
+    pub depth: Option<usize>,
+    #[serde(default)]
+    pub thumbnails: bool,
+}
+
+/// Export a printable inventory of a directory as HTML or PDF
+pub async fn report(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Response, ApiError> {
+    let path = query.path.unwrap_or_else(|| "/".to_string());
+    let format = query.format.unwrap_or(ReportFormat::Html);
+    let depth = query.depth.unwrap_or(3).min(32);
+
+    let state_clone = state.clone();
+    let tree =
+        tokio::task::spawn_blocking(move || ReportService::build(&state_clone.fs, &path, depth))
+            .await
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map_err(|e| {
+                let status = match &e {
+                    FsError::NotFound(_) => StatusCode::NOT_FOUND,
+                    FsError::PermissionDenied(_) | FsError::PathEscape => StatusCode::FORBIDDEN,
+                    FsError::NotADirectory(_) => StatusCode::BAD_REQUEST,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                ApiError::with_body(status, ErrorResponse::from(&e))
+            })?;
+
+    let generated_at = Utc::now().format("%Y-%m-%d %H:%M UTC").to_string();
+
+    Ok(match format {
+        ReportFormat::Html => (
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            render_html(&tree, &generated_at, query.thumbnails),
+        )
+            .into_response(),
+        ReportFormat::Pdf => (
+            [
+                (header::CONTENT_TYPE, "application/pdf"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "inline; filename=\"filex-report.pdf\"",
+                ),
+            ],
+            render_pdf(&report_lines(&tree, &generated_at)),
+        )
+            .into_response(),
+    })
+}
+
+fn summary(node: &ReportNode) -> String {
+    format!(
+        "{} files, {} folders, {}",
+        node.file_count,
+        node.dir_count,
+        format_bytes(node.size as i64)
+    )
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_html(tree: &ReportNode, generated_at: &str, thumbnails: bool) -> String {
+    let mut html = String::new();
+    let title = escape_html(&tree.path);
+
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Report: {title}</title>\
+         <style>\
+         body{{font:13px/1.4 system-ui,sans-serif;margin:2em;color:#111}}\
+         table{{border-collapse:collapse;width:100%}}\
+         th,td{{padding:2px 8px;border-bottom:1px solid #ddd;text-align:left;vertical-align:middle}}\
+         td.num,th.num{{text-align:right;white-space:nowrap}}\
+         tr.dir td{{font-weight:600}}\
+         img{{max-height:48px;max-width:64px}}\
+         @media print{{body{{margin:0}}tr{{break-inside:avoid}}}}\
+         </style></head><body>\
+         <h1>{title}</h1><p>{summary}<br>Generated {generated_at}</p>\
+         <table><thead><tr>{thumb_header}<th>Name</th><th class=\"num\">Size</th>\
+         <th class=\"num\">Files</th></tr></thead><tbody>\n",
+        summary = escape_html(&summary(tree)),
+        thumb_header = if thumbnails { "<th></th>" } else { "" },
+    );
+
+    for child in &tree.children {
+        render_html_rows(&mut html, child, 0, thumbnails);
+    }
+
+    html.push_str("</tbody></table></body></html>\n");
+    html
+}
+
+fn render_html_rows(html: &mut String, node: &ReportNode, level: usize, thumbnails: bool) {
+    let indent = level as f32 * 1.25;
+    let class = if node.is_dir { " class=\"dir\"" } else { "" };
+    let _ = write!(html, "<tr{class}>");
+
+    if thumbnails {
+        html.push_str("<td>");
+        let encoded = utf8_percent_encode(&node.path, NON_ALPHANUMERIC);
+        match node.mime_type.as_deref() {
+            _ if node.no_preview => {}
+            Some(mime) if mime.starts_with("image/") => {
+                let _ = write!(
+                    html,
+                    "<img src=\"/api/files/download?path={encoded}\" alt=\"\">"
+                );
+            }
+            Some("application/pdf") => {
+                let _ = write!(
+                    html,
+                    "<img src=\"/api/files/thumbnail?path={encoded}\" alt=\"\">"
+                );
+            }
+            _ => {}
+        }
+        html.push_str("</td>");
+    }
+
+    let name = if node.is_dir {
+        format!("{}/", node.name)
+    } else {
+        node.name.clone()
+    };
+    let files = if node.is_dir {
+        node.file_count.to_string()
+    } else {
+        String::new()
+    };
+    let _ = writeln!(
+        html,
+        "<td style=\"padding-left:{indent}em\">{}</td><td class=\"num\">{}</td>\
+         <td class=\"num\">{files}</td></tr>",
+        escape_html(&name),
+        format_bytes(node.size as i64),
+    );
+
+    for child in &node.children {
+        render_html_rows(html, child, level + 1, thumbnails);
+    }
+}
+
+/// Plain-text lines for the PDF rendering (indented tree with sizes).
+fn report_lines(tree: &ReportNode, generated_at: &str) -> Vec<String> {
+    let mut lines = vec![
+        format!("Report: {}", tree.path),
+        summary(tree),
+        format!("Generated {generated_at}"),
+        String::new(),
+    ];
+    for child in &tree.children {
+        push_report_lines(&mut lines, child, 0);
+    }
+    lines
+}
+
+fn push_report_lines(lines: &mut Vec<String>, node: &ReportNode, level: usize) {
+    const WIDTH: usize = 100;
+    const SIZE_WIDTH: usize = 10;
+
+    let mut name = format!("{}{}", "  ".repeat(level), node.name);
+    if node.is_dir {
+        name.push('/');
+    }
+    let size = format_bytes(node.size as i64);
+    let name_width = WIDTH - SIZE_WIDTH - 2;
+    if name.chars().count() > name_width {
+        name = name.chars().take(name_width - 3).collect::<String>() + "...";
+    }
+    lines.push(format!("{name:<name_width$}  {size:>SIZE_WIDTH$}"));
+
+    for child in &node.children {
+        push_report_lines(lines, child, level + 1);
+    }
+}
+
+/// Render monospaced text lines into a minimal multi-page PDF (US Letter,
+/// built-in Courier, WinAnsi encoding; characters outside Latin-1 become `?`).
+fn render_pdf(lines: &[String]) -> Vec<u8> {
+    const PAGE_WIDTH: u32 = 612;
+    const PAGE_HEIGHT: u32 = 792;
+    const MARGIN: u32 = 40;
+    const FONT_SIZE: u32 = 8;
+    const LEADING: u32 = 10;
+    let lines_per_page = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize;
+
+    let pages: Vec<&[String]> = if lines.is_empty() {
+        vec![&[]]
+    } else {
+        lines.chunks(lines_per_page).collect()
+    };
+
+    // Object layout: 1 catalog, 2 page tree, 3 font, then (page, content) pairs
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", 4 + i * 2))
+        .collect();
+    objects.push(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+    );
+    objects.push(
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_vec(),
+    );
+
+    for (i, page) in pages.iter().enumerate() {
+        let mut content = format!(
+            "BT /F1 {FONT_SIZE} Tf {LEADING} TL {MARGIN} {} Td\n",
+            PAGE_HEIGHT - MARGIN
+        )
+        .into_bytes();
+        for line in page.iter() {
+            content.push(b'(');
+            for c in line.chars() {
+                match c {
+                    '(' | ')' | '\\' => content.extend_from_slice(&[b'\\', c as u8]),
+                    c if (c as u32) < 0x20 => content.push(b' '),
+                    c if (c as u32) <= 0xFF => content.push(c as u32 as u8),
+                    _ => content.push(b'?'),
+                }
+            }
+            content.extend_from_slice(b") Tj T*\n");
+        }
+        content.extend_from_slice(b"ET");
+
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                5 + i * 2
+            )
+            .into_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend_from_slice(&content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = pdf.len();
+    pdf.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+    );
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn test_state() -> (Arc<AppState>, tempfile::TempDir, std::path::PathBuf) {
+        let tmp = tempdir().expect("tempdir created");
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(root.clone(), pool));
+
+        (state, tmp, root)
+    }
+
+    #[tokio::test]
+    async fn html_report_lists_entries_and_escapes_names() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("photos")).unwrap();
+        fs::write(root.join("photos/<b>&cat.jpg"), b"1234").unwrap();
+
+        let response = report(
+            State(state),
+            Query(ReportQuery {
+                path: None,
+                format: None,
+                depth: None,
+                thumbnails: true,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        assert!(html.contains("photos/"));
+        assert!(html.contains("&lt;b&gt;&amp;cat.jpg"));
+        assert!(!html.contains("<b>&cat"));
+        assert!(html.contains("/api/files/download?path=%2Fphotos%2F%3Cb%3E%26cat"));
+    }
+
+    #[tokio::test]
+    async fn pdf_report_is_well_formed() {
+        let (state, _tmp, root) = test_state().await;
+        fs::write(root.join("inventory (1).txt"), b"data").unwrap();
+
+        let response = report(
+            State(state.clone()),
+            Query(ReportQuery {
+                path: Some("/".to_string()),
+                format: Some(ReportFormat::Pdf),
+                depth: Some(1),
+                thumbnails: false,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/pdf");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.starts_with(b"%PDF-1.4"));
+        assert!(body.ends_with(b"%%EOF\n"));
+        let text = String::from_utf8_lossy(&body);
+        assert!(text.contains("inventory \\(1\\).txt"));
+
+        let err = report(
+            State(state),
+            Query(ReportQuery {
+                path: Some("/inventory (1).txt".to_string()),
+                format: Some(ReportFormat::Pdf),
+                depth: None,
+                thumbnails: false,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+}
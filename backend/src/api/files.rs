@@ -1,3 +1,4 @@
+use axum::body::Bytes;
 use axum::{
     Json,
     body::Body,
@@ -6,11 +7,15 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use axum_extra::response::file_stream::FileStream;
+use chrono::{DateTime, Utc};
 use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs::File;
 use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
 use tokio_util::io::ReaderStream;
 
 // Encode filenames for Content-Disposition to avoid header injection.
@@ -25,26 +30,99 @@ const FILENAME_ENCODE_SET: &AsciiSet = &CONTROLS
     .add(b'\r')
     .add(b'\t');
 
-use crate::api::{AppState, ErrorResponse};
+use crate::api::locks::{lock_token, locked};
+use crate::api::system::format_bytes;
+use crate::api::{ApiError, AppState, ErrorCode, ErrorResponse};
 use crate::db;
+use crate::models::FileEntry;
+use crate::services::antivirus::ScanVerdict;
+use crate::services::filesystem::{
+    ExpectedState, FsError, LinkKind, SyncCompare, SyncStats, canonical_name, mime_type_of,
+};
+use crate::services::metadata::MetadataError;
+use crate::services::naming::NamePolicy;
+use crate::services::templates::TemplateError;
+use crate::services::text::{self, ContentInfo};
+use crate::services::uploads::{MAX_UPLOAD_ID_LEN, UploadHandle, UploadProgress, valid_upload_id};
+use crate::services::webhooks::{EventSource, FileEvent, FileEventKind};
+use crate::services::{ArchiveService, FilesystemService, ManifestService, MetadataService};
+use crate::services::{preview, readahead};
+
+pub(crate) fn status_for_fs_error(e: &crate::services::filesystem::FsError) -> StatusCode {
+    crate::api::error::classify_fs_error(e).0
+}
+
+/// Refuse to write `bytes` more when that would eat into the root volume's
+/// reserve
+fn ensure_room(state: &AppState, bytes: u64) -> Result<(), ApiError> {
+    if state.root_storage.has_room_for(bytes) {
+        return Ok(());
+    }
+    let status = state.root_storage.status();
+    Err(ApiError::new(
+        StatusCode::INSUFFICIENT_STORAGE,
+        format!(
+            "Not enough free space: {} needed, {} available, {} kept in reserve",
+            format_bytes(bytes as i64),
+            format_bytes(status.available_bytes.unwrap_or(0) as i64),
+            format_bytes(status.min_free_bytes as i64),
+        ),
+    ))
+}
+
+/// Tell webhooks, hooks, and live subscribers about a change made through a
+/// write endpoint
+fn report_change(
+    state: &AppState,
+    event: FileEventKind,
+    path: &str,
+    from: Option<&str>,
+    is_dir: bool,
+) {
+    state.webhooks.api_event(event, path, from, is_dir);
+    state.hooks.run(event, path, from);
+    state.live.publish(FileEvent::new(
+        event,
+        path,
+        from,
+        Some(is_dir),
+        EventSource::Api,
+    ));
+}
 
-fn status_for_fs_error(e: &crate::services::filesystem::FsError) -> StatusCode {
-    match e {
-        crate::services::filesystem::FsError::NotFound(_) => StatusCode::NOT_FOUND,
-        crate::services::filesystem::FsError::PermissionDenied(_) => StatusCode::FORBIDDEN,
-        _ => StatusCode::INTERNAL_SERVER_ERROR,
+/// Bring the search index up to date below directories that were renamed,
+/// moved, or deleted; only the directories themselves are updated in place
+async fn reload_search_below(state: &AppState, dirs: &[&str]) {
+    if let Err(e) = state.search.reload_subtrees(&state.pool, dirs).await {
+        tracing::warn!("Failed to reload search index below {:?}: {}", dirs, e);
     }
 }
 
+/// Apply the `.fxnames` policy governing `dir`, if any, to a new entry name
+fn normalize_name(fs: &FilesystemService, dir: &std::path::Path, name: &str) -> String {
+    NamePolicy::for_directory(fs.root(), dir)
+        .map(|policy| policy.apply(name))
+        .unwrap_or_else(|| name.to_string())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateDirRequest {
     pub path: String,
+    /// Create missing parent directories too
+    #[serde(default)]
+    pub recursive: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RenameRequest {
     pub path: String,
     pub new_name: String,
+    /// Refuse unless the entry still has this modification time, as listed
+    #[serde(default)]
+    pub expected_mtime: Option<DateTime<Utc>>,
+    /// Refuse unless the entry still has this size, as listed
+    #[serde(default)]
+    pub expected_size: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -53,6 +131,12 @@ pub struct MoveRequest {
     pub to: String,
     #[serde(default)]
     pub overwrite: bool,
+    /// Refuse unless the entry still has this modification time, as listed
+    #[serde(default)]
+    pub expected_mtime: Option<DateTime<Utc>>,
+    /// Refuse unless the entry still has this size, as listed
+    #[serde(default)]
+    pub expected_size: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -61,11 +145,45 @@ pub struct CopyRequest {
     pub to: String,
     #[serde(default)]
     pub overwrite: bool,
+    #[serde(default)]
+    pub mode: CopyMode,
+    /// In `sync` mode, compare file contents instead of size and
+    /// modification time
+    #[serde(default)]
+    pub checksum: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyMode {
+    /// Copy everything, or skip an existing target unless `overwrite`
+    #[default]
+    Copy,
+    /// Copy only files missing or different at the destination
+    Sync,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SizeRequest {
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct SizeResponse {
+    /// Files in the selection, counting those inside directories
+    pub files: u64,
+    pub bytes: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DeleteRequest {
     pub path: String,
+    /// Refuse unless the entry still has this modification time, as listed
+    #[serde(default)]
+    pub expected_mtime: Option<DateTime<Utc>>,
+    /// Refuse unless the entry still has this size, as listed
+    #[serde(default)]
+    pub expected_size: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -73,6 +191,40 @@ pub struct DownloadQuery {
     pub path: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct StatResponse {
+    #[serde(flatten)]
+    pub entry: FileEntry,
+    /// Text or binary, and the likely encoding of text; files only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<ContentInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadBatchRequest {
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailQuery {
+    pub path: String,
+    pub size: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ManifestRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestResponse {
+    pub path: String,
+    pub file_count: u64,
+    pub total_size: u64,
+    pub generated_at: String,
+    pub written: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct SuccessResponse {
     pub success: bool,
@@ -82,27 +234,237 @@ pub struct SuccessResponse {
     pub message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub performed: Option<bool>,
+    /// What a copy in `sync` mode did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync: Option<SyncStats>,
 }
 
-/// Create a new directory
+/// Create a new directory, and with `recursive` any missing parents
 pub async fn create_directory(
     State(state): State<Arc<AppState>>,
     Json(req): Json<CreateDirRequest>,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    state.fs.create_directory(&req.path).map_err(|e| {
-        (
-            status_for_fs_error(&e),
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let created = if req.recursive {
+        state.backend.create_directory_all(&req.path)
+    } else {
+        state
+            .backend
+            .create_directory(&req.path)
+            .and_then(|()| state.backend.get_entry(&req.path))
+            .map(|entry| vec![entry.path])
+    }
+    .map_err(|e| ApiError::from(&e))?;
+
+    index_new_entries(&state, &created).await;
+    for path in &created {
+        report_change(&state, FileEventKind::Created, path, None, true);
+    }
 
     Ok(Json(SuccessResponse {
         success: true,
         path: Some(req.path),
-        message: Some("Directory created".to_string()),
+        message: Some(
+            if created.is_empty() {
+                "Directory already exists"
+            } else {
+                "Directory created"
+            }
+            .to_string(),
+        ),
+        performed: req.recursive.then_some(!created.is_empty()),
+        sync: None,
+    }))
+}
+
+/// Add files and directories just created through the API to the database
+/// and search index, so they can be found before the next index run. Files
+/// are left for the indexer to read their metadata.
+async fn index_new_entries(state: &AppState, paths: &[String]) {
+    for path in paths {
+        let Ok(entry) = state.backend.get_entry(path) else {
+            continue;
+        };
+        let is_file = !entry.is_dir;
+        let row = crate::models::IndexedFileRow {
+            id: 0,
+            path: path.clone(),
+            name: path.rsplit('/').next().unwrap_or_default().to_string(),
+            is_dir: entry.is_dir,
+            size: entry.size.map(|size| size as i64),
+            created_at: entry.created.map(|t| t.to_rfc3339()),
+            modified_at: entry.modified.map(|t| t.to_rfc3339()),
+            mime_type: entry.mime_type,
+            width: None,
+            height: None,
+            duration: None,
+            metadata_status: if is_file { "pending" } else { "complete" }.to_string(),
+            indexed_at: String::new(),
+            artist: None,
+            album: None,
+            title: None,
+            track_number: None,
+            page_count: None,
+            author: None,
+            original_name: None,
+        };
+        if let Err(e) = db::upsert_file(&state.pool, &row).await {
+            tracing::warn!("Failed to index {}: {}", path, e);
+            continue;
+        }
+        if let Err(e) = state.search.refresh_entry(&state.pool, path).await {
+            tracing::warn!("Failed to add {} to the search index: {}", path, e);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateFileRequest {
+    pub path: String,
+    /// Template to copy; the file starts empty without one
+    pub template: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplatesResponse {
+    pub templates: Vec<String>,
+}
+
+fn template_error(e: TemplateError) -> ApiError {
+    let status = match e {
+        TemplateError::NotFound(_) => StatusCode::NOT_FOUND,
+        TemplateError::InvalidName(_) => StatusCode::BAD_REQUEST,
+        TemplateError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        TemplateError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+/// Names of the templates new files can start from
+pub async fn list_templates(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TemplatesResponse>, ApiError> {
+    let templates = state.templates.list().map_err(template_error)?;
+    Ok(Json(TemplatesResponse { templates }))
+}
+
+/// Create a new file, empty or as a copy of a template. Fails with `409`
+/// if something by that name already exists.
+pub async fn create_file(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateFileRequest>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let fs_error = |e: FsError| ApiError::from(&e);
+    let (dir, name) = req.path.rsplit_once('/').unwrap_or(("", &req.path));
+    let contents = match &req.template {
+        Some(template) => state.templates.read(template).map_err(template_error)?,
+        None => Vec::new(),
+    };
+
+    let parent_dir = state.fs.resolve_path(dir).map_err(fs_error)?;
+    if !parent_dir.is_dir() {
+        return Err(fs_error(FsError::NotADirectory(dir.to_string())));
+    }
+    let clean_name =
+        FilesystemService::sanitize_file_name(&normalize_name(&state.fs, &parent_dir, name))
+            .map_err(fs_error)?;
+    ensure_room(&state, contents.len() as u64)?;
+
+    let (mut file, _) = create_upload_file(&parent_dir, &clean_name, ConflictPolicy::Fail)
+        .await
+        .map_err(|e| {
+            let status = if e.kind() == std::io::ErrorKind::AlreadyExists {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            ApiError::new(status, e.to_string())
+        })?;
+    let written = async {
+        file.write_all(&contents).await?;
+        file.flush().await
+    };
+    if let Err(e) = written.await {
+        let _ = tokio::fs::remove_file(parent_dir.join(&clean_name)).await;
+        return Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        ));
+    }
+
+    let path = state.fs.relative_path(&parent_dir.join(&clean_name));
+    index_new_entries(&state, std::slice::from_ref(&path)).await;
+    report_change(&state, FileEventKind::Created, &path, None, false);
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: Some(path),
+        message: Some("File created".to_string()),
+        performed: None,
+        sync: None,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkRequest {
+    /// Existing file or directory to link to
+    pub target: String,
+    /// Where to create the link
+    pub path: String,
+    pub kind: LinkKind,
+}
+
+/// Create a hard link or symlink to something inside the root, when
+/// `FM_ALLOW_LINKS` is set
+pub async fn create_link(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<LinkRequest>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    if !state.allow_links {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Creating links is disabled; set FM_ALLOW_LINKS=true to enable it",
+        ));
+    }
+    let fs_error = |e: FsError| ApiError::from(&e);
+    let target = state.fs.resolve_path(&req.target).map_err(fs_error)?;
+    let (dir, name) = req.path.rsplit_once('/').unwrap_or(("", &req.path));
+    let parent_dir = state.fs.resolve_path(dir).map_err(fs_error)?;
+    if !parent_dir.is_dir() {
+        return Err(fs_error(FsError::NotADirectory(dir.to_string())));
+    }
+    let clean_name =
+        FilesystemService::sanitize_file_name(&normalize_name(&state.fs, &parent_dir, name))
+            .map_err(fs_error)?;
+
+    let link = state
+        .fs
+        .create_link(&target, &parent_dir, &clean_name, req.kind)
+        .map_err(|e| match e {
+            FsError::Io(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                ApiError::new(StatusCode::CONFLICT, format!("{clean_name} already exists"))
+            }
+            e => fs_error(e),
+        })?;
+
+    // The link's own path; resolving it would follow a symlink
+    let path = format!(
+        "{}/{}",
+        state.fs.relative_path(&parent_dir).trim_end_matches('/'),
+        canonical_name(&clean_name)
+    );
+    let target_path = state.fs.relative_path(&target);
+    index_new_entries(&state, std::slice::from_ref(&path)).await;
+    if let Err(e) = db::set_link(&state.pool, &path, req.kind.as_str(), &target_path).await {
+        tracing::warn!("Failed to record link {} -> {}: {}", path, target_path, e);
+    }
+    report_change(&state, FileEventKind::Created, &path, None, link.is_dir());
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: Some(path),
+        message: Some(format!("Linked to {target_path}")),
         performed: None,
+        sync: None,
     }))
 }
 
@@ -110,48 +472,64 @@ pub async fn create_directory(
 pub async fn rename(
     State(state): State<Arc<AppState>>,
     Json(req): Json<RenameRequest>,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<SuccessResponse>, ApiError> {
     if req.new_name == "."
         || req.new_name == ".."
         || req.new_name.contains('/')
         || req.new_name.contains('\\')
     {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid new name".to_string(),
-            }),
-        ));
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Invalid new name"));
     }
 
-    let new_path = state.fs.rename(&req.path, &req.new_name).map_err(|e| {
-        (
-            status_for_fs_error(&e),
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+    let expected = ExpectedState {
+        mtime: req.expected_mtime,
+        size: req.expected_size,
+    };
+    state.backend.check_expected(&req.path, &expected)?;
+
+    let new_name = match state.fs.resolve_path(&req.path) {
+        Ok(source) => source
+            .parent()
+            .map(|dir| normalize_name(&state.fs, dir, &req.new_name))
+            .unwrap_or_else(|| req.new_name.clone()),
+        Err(_) => req.new_name.clone(),
+    };
+
+    let new_path = state
+        .backend
+        .rename(&req.path, &new_name)
+        .map_err(|e| ApiError::from(&e))?;
 
-    db::rename_path(&state.pool, &req.path, &new_path, &req.new_name)
+    let db_error = |e: sqlx::Error| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    db::rename_path(&state.pool, &req.path, &new_path, &new_name)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+        .map_err(db_error)?;
+
+    let original_name = (new_name != req.new_name).then_some(req.new_name.as_str());
+    db::set_original_name(&state.pool, &new_path, &new_name, original_name)
+        .await
+        .map_err(db_error)?;
 
     // Update search index
     state.search.rename_entry(&req.path, &new_path).await;
+    let is_dir = state.backend.is_dir(&new_path);
+    if is_dir {
+        reload_search_below(&state, &[&req.path, &new_path]).await;
+    }
+    report_change(
+        &state,
+        FileEventKind::Renamed,
+        &new_path,
+        Some(&req.path),
+        is_dir,
+    );
 
     Ok(Json(SuccessResponse {
         success: true,
         path: Some(new_path),
         message: Some("Renamed successfully".to_string()),
         performed: None,
+        sync: None,
     }))
 }
 
@@ -159,38 +537,26 @@ pub async fn rename(
 pub async fn move_entry(
     State(state): State<Arc<AppState>>,
     Json(req): Json<MoveRequest>,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let expected = ExpectedState {
+        mtime: req.expected_mtime,
+        size: req.expected_size,
+    };
+    state.backend.check_expected(&req.from, &expected)?;
+
     let result = state
-        .fs
+        .backend
         .move_entry(&req.from, &req.to, req.overwrite)
-        .map_err(|e| {
-            (
-                status_for_fs_error(&e),
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+        .map_err(|e| ApiError::from(&e))?;
 
     if result.performed {
-        let new_name = std::path::Path::new(&result.path)
-            .file_name()
-            .map(|n| n.to_string_lossy().to_string())
-            .unwrap_or_else(|| req.to.clone());
-
-        db::rename_path(&state.pool, &req.from, &result.path, &new_name)
+        record_move(&state, &req.from, &result.path, false)
             .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: e.to_string(),
-                    }),
-                )
-            })?;
-
-        // Update search index
-        state.search.rename_entry(&req.from, &result.path).await;
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if req.overwrite {
+            // A replaced file may have been the last link to a stored upload
+            state.dedup.prune_later();
+        }
     }
 
     Ok(Json(SuccessResponse {
@@ -205,25 +571,323 @@ pub async fn move_entry(
             .to_string(),
         ),
         performed: Some(result.performed),
+        sync: None,
+    }))
+}
+
+/// Carry the index, search and subscribers over to an entry moved from
+/// `from` to `to`. `replaced` says something at `to` was overwritten, whose
+/// rows go first.
+async fn record_move(
+    state: &AppState,
+    from: &str,
+    to: &str,
+    replaced: bool,
+) -> Result<(), sqlx::Error> {
+    if replaced {
+        db::delete_by_paths(&state.pool, &[to]).await?;
+        state.search.remove_entry(to).await;
+    }
+    let new_name = to.rsplit('/').next().unwrap_or(to);
+    db::rename_path(&state.pool, from, to, new_name).await?;
+
+    // Update search index
+    state.search.rename_entry(from, to).await;
+    let is_dir = state.backend.is_dir(to);
+    if is_dir || replaced {
+        reload_search_below(state, &[from, to]).await;
+    }
+    report_change(state, FileEventKind::Renamed, to, Some(from), is_dir);
+    Ok(())
+}
+
+/// What to do with a batch-move item whose name is taken at the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictResolution {
+    Overwrite,
+    /// Move it as `name (1).ext`, ...
+    Rename,
+    Skip,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveBatchRequest {
+    pub paths: Vec<String>,
+    /// Directory to move everything into
+    pub to: String,
+    /// Answers to the conflicts an earlier call reported, by source path
+    #[serde(default)]
+    pub resolutions: HashMap<String, ConflictResolution>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoveConflict {
+    pub path: String,
+    /// The path that is already taken
+    pub destination: String,
+    pub is_dir: bool,
+    /// Whether what is in the way is a directory
+    pub existing_is_dir: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MoveOutcome {
+    Moved,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoveBatchItem {
+    pub from: String,
+    pub outcome: MoveOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoveBatchResponse {
+    /// False when conflicts without a resolution stopped the batch before
+    /// anything was moved
+    pub performed: bool,
+    pub conflicts: Vec<MoveConflict>,
+    pub results: Vec<MoveBatchItem>,
+}
+
+/// One entry of a batch move, checked before anything moves
+struct PlannedMove {
+    source: PathBuf,
+    from: String,
+    name: String,
+    is_dir: bool,
+    conflict: Option<MoveConflict>,
+}
+
+/// Move several entries into one directory. Every entry is checked first;
+/// if any name is taken at the destination (or by another entry in the
+/// batch) and the request has no resolution for it, nothing moves and the
+/// conflicts are returned so the client can ask and call again with
+/// `resolutions`.
+pub async fn move_batch(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<MoveBatchRequest>,
+) -> Result<Json<MoveBatchResponse>, ApiError> {
+    let fs_error = |e: FsError| ApiError::from(&e);
+    if req.paths.is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Nothing to move"));
+    }
+    let dest_dir = state.fs.resolve_path(&req.to).map_err(fs_error)?;
+    if !dest_dir.is_dir() {
+        return Err(fs_error(FsError::NotADirectory(req.to.clone())));
+    }
+    let dest = state.fs.relative_path(&dest_dir);
+    let dest_path = |name: &str| format!("{}/{name}", dest.trim_end_matches('/'));
+
+    let mut plan: Vec<PlannedMove> = Vec::new();
+    for path in &req.paths {
+        let source = state.fs.resolve_path(path).map_err(fs_error)?;
+        if source == state.fs.root() {
+            return Err(fs_error(FsError::PermissionDenied(
+                "Cannot move root".to_string(),
+            )));
+        }
+        if source.is_dir() && dest_dir.starts_with(&source) {
+            return Err(fs_error(FsError::PermissionDenied(format!(
+                "Cannot move {path} into itself"
+            ))));
+        }
+        let from = state.fs.relative_path(&source);
+        if plan.iter().any(|planned| planned.from == from) {
+            continue;
+        }
+        let name = source
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let destination = dest_path(&name);
+        let existing = dest_dir.join(&name);
+        let taken_by_batch = plan.iter().find(|planned| planned.name == name);
+        let conflict = if let Some(earlier) = taken_by_batch {
+            Some(earlier.is_dir)
+        } else if existing.exists() && existing != source {
+            Some(existing.is_dir())
+        } else {
+            None
+        };
+        plan.push(PlannedMove {
+            conflict: conflict.map(|existing_is_dir| MoveConflict {
+                path: from.clone(),
+                destination,
+                is_dir: source.is_dir(),
+                existing_is_dir,
+            }),
+            is_dir: source.is_dir(),
+            source,
+            from,
+            name,
+        });
+    }
+
+    let unresolved: Vec<MoveConflict> = plan
+        .iter_mut()
+        .filter(|planned| !req.resolutions.contains_key(&planned.from))
+        .filter_map(|planned| planned.conflict.take())
+        .collect();
+    if !unresolved.is_empty() {
+        return Ok(Json(MoveBatchResponse {
+            performed: false,
+            conflicts: unresolved,
+            results: Vec::new(),
+        }));
+    }
+
+    let mut results = Vec::with_capacity(plan.len());
+    for planned in plan {
+        let resolution = planned
+            .conflict
+            .as_ref()
+            .and_then(|_| req.resolutions.get(&planned.from).copied());
+        let item = |outcome, to: Option<String>, error: Option<String>| MoveBatchItem {
+            from: planned.from.clone(),
+            outcome,
+            to,
+            error,
+        };
+        if dest_dir.join(&planned.name) == planned.source {
+            // Already in the destination
+            results.push(item(MoveOutcome::Skipped, Some(planned.from.clone()), None));
+            continue;
+        }
+        let (target, overwrite) = match resolution {
+            Some(ConflictResolution::Skip) => {
+                results.push(item(MoveOutcome::Skipped, None, None));
+                continue;
+            }
+            Some(ConflictResolution::Rename) => (
+                dest_path(&FilesystemService::unused_name(&dest_dir, &planned.name)),
+                false,
+            ),
+            Some(ConflictResolution::Overwrite) => (dest.clone(), true),
+            None => (dest.clone(), false),
+        };
+        let replaced = overwrite && dest_dir.join(&planned.name).exists();
+        let moved = match state.backend.move_entry(&planned.from, &target, overwrite) {
+            Ok(result) if result.performed => {
+                if replaced {
+                    state.dedup.prune_later();
+                }
+                record_move(&state, &planned.from, &result.path, replaced)
+                    .await
+                    .map(|()| result.path)
+                    .map_err(|e| e.to_string())
+            }
+            Ok(result) => Err(format!("{} already exists", result.path)),
+            Err(e) => Err(e.to_string()),
+        };
+        results.push(match moved {
+            Ok(to) => item(MoveOutcome::Moved, Some(to), None),
+            Err(e) => item(MoveOutcome::Failed, None, Some(e)),
+        });
+    }
+
+    Ok(Json(MoveBatchResponse {
+        performed: true,
+        conflicts: Vec::new(),
+        results,
     }))
 }
 
+/// Number of files and bytes in a selection, for confirming a copy or
+/// move before starting it. Directories are totalled from the index, or by
+/// walking them when not indexed; entries inside another selected directory
+/// count once.
+pub async fn selection_size(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<SizeRequest>,
+) -> Result<Json<SizeResponse>, ApiError> {
+    let mut paths = Vec::with_capacity(req.paths.len());
+    for path in &req.paths {
+        let resolved = state.fs.resolve_path(path)?;
+        paths.push((state.fs.relative_path(&resolved), resolved.is_dir()));
+    }
+    paths.sort();
+    paths.dedup();
+    let below = |path: &str, dir: &str| {
+        dir == "/"
+            || path
+                .strip_prefix(dir)
+                .is_some_and(|rest| rest.starts_with('/'))
+    };
+    let selected: Vec<(String, bool)> = paths
+        .iter()
+        .filter(|(path, _)| {
+            !paths
+                .iter()
+                .any(|(dir, is_dir)| *is_dir && dir != path && below(path, dir))
+        })
+        .cloned()
+        .collect();
+
+    let mut total = SizeResponse { files: 0, bytes: 0 };
+    for (path, is_dir) in selected {
+        let indexed = if is_dir {
+            db::get_tree_totals(&state.pool, &path)
+                .await
+                .map_err(ApiError::internal)?
+        } else {
+            None
+        };
+        let (files, bytes) = match indexed {
+            Some((files, bytes)) => (files as u64, bytes as u64),
+            None => {
+                let state = state.clone();
+                tokio::task::spawn_blocking(move || state.fs.entry_totals(&path))
+                    .await
+                    .map_err(ApiError::internal)??
+            }
+        };
+        total.files += files;
+        total.bytes += bytes;
+    }
+    Ok(Json(total))
+}
+
 /// Copy a file or directory
 pub async fn copy_entry(
     State(state): State<Arc<AppState>>,
     Json(req): Json<CopyRequest>,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<SuccessResponse>, ApiError> {
+    if req.mode == CopyMode::Sync {
+        return sync_entry(&state, &req);
+    }
+
+    let size = state
+        .backend
+        .entry_size(&req.from)
+        .map_err(|e| ApiError::from(&e))?;
+    ensure_room(&state, size)?;
+
     let result = state
-        .fs
+        .backend
         .copy_entry(&req.from, &req.to, req.overwrite)
-        .map_err(|e| {
-            (
-                status_for_fs_error(&e),
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+        .map_err(|e| ApiError::from(&e))?;
+    if result.performed && req.overwrite {
+        // A replaced file may have been the last link to a stored upload
+        state.dedup.prune_later();
+    }
+    if result.performed {
+        report_change(
+            &state,
+            FileEventKind::Created,
+            &result.path,
+            None,
+            state.backend.is_dir(&result.path),
+        );
+    }
 
     Ok(Json(SuccessResponse {
         success: true,
@@ -237,6 +901,43 @@ pub async fn copy_entry(
             .to_string(),
         ),
         performed: Some(result.performed),
+        sync: None,
+    }))
+}
+
+/// Copy only what is missing or different at the destination. No space
+/// check is made up front, as most files are usually skipped.
+fn sync_entry(state: &AppState, req: &CopyRequest) -> Result<Json<SuccessResponse>, ApiError> {
+    let compare = if req.checksum {
+        SyncCompare::Contents
+    } else {
+        SyncCompare::SizeAndMtime
+    };
+    let (path, stats) = state.backend.sync_entry(&req.from, &req.to, compare)?;
+    let performed = stats.created + stats.updated > 0;
+    if stats.updated > 0 {
+        // Updated files are recreated rather than written through links
+        state.dedup.prune_later();
+    }
+    if performed {
+        report_change(
+            state,
+            FileEventKind::Modified,
+            &path,
+            None,
+            state.backend.is_dir(&path),
+        );
+    }
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: Some(path),
+        message: Some(format!(
+            "Synced: {} created, {} updated, {} unchanged",
+            stats.created, stats.updated, stats.skipped
+        )),
+        performed: Some(performed),
+        sync: Some(stats),
     }))
 }
 
@@ -244,74 +945,128 @@ pub async fn copy_entry(
 pub async fn delete(
     State(state): State<Arc<AppState>>,
     Json(req): Json<DeleteRequest>,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    state.fs.delete(&req.path).map_err(|e| {
-        (
-            status_for_fs_error(&e),
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let expected = ExpectedState {
+        mtime: req.expected_mtime,
+        size: req.expected_size,
+    };
+    state.backend.check_expected(&req.path, &expected)?;
+
+    let is_dir = state.backend.is_dir(&req.path);
+    state
+        .backend
+        .delete(&req.path)
+        .map_err(|e| ApiError::from(&e))?;
+    // Stored uploads no longer linked from anywhere
+    state.dedup.prune_later();
 
     let delete_paths = [req.path.as_str()];
     db::delete_by_paths(&state.pool, &delete_paths)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Update search index
     state.search.remove_entry(&req.path).await;
+    if is_dir {
+        reload_search_below(&state, &[&req.path]).await;
+    }
+    report_change(&state, FileEventKind::Deleted, &req.path, None, is_dir);
 
     Ok(Json(SuccessResponse {
         success: true,
         path: Some(req.path),
         message: Some("Deleted successfully".to_string()),
         performed: None,
+        sync: None,
     }))
 }
 
-/// Download a file
-pub async fn download(
+/// Generate MANIFEST.txt and manifest.json (sizes + SHA-256) inside a directory
+pub async fn create_manifest(
     State(state): State<Arc<AppState>>,
-    Query(query): Query<DownloadQuery>,
-    headers: HeaderMap,
-) -> Result<Response<Body>, (StatusCode, Json<ErrorResponse>)> {
-    let resolved = state.fs.resolve_path(&query.path).map_err(|e| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
+    Json(req): Json<ManifestRequest>,
+) -> Result<Json<ManifestResponse>, ApiError> {
+    let dir = state
+        .fs
+        .resolve_path(&req.path)
+        .map_err(|e| ApiError::from(&e))?;
+
+    if !dir.is_dir() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Manifest target must be a directory",
+        ));
+    }
+
+    // Hashing a large tree is slow blocking IO; keep it off the async workers.
+    let (manifest, written) = tokio::task::spawn_blocking(move || {
+        let manifest = ManifestService::generate(&dir)?;
+        let written = ManifestService::write(&dir, &manifest)?;
+        Ok::<_, std::io::Error>((manifest, written))
+    })
+    .await
+    .map_err(std::io::Error::other)
+    .and_then(|r| r)
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let base = req.path.trim_end_matches('/');
+    Ok(Json(ManifestResponse {
+        written: written
+            .into_iter()
+            .map(|name| format!("{base}/{name}"))
+            .collect(),
+        path: req.path,
+        file_count: manifest.file_count,
+        total_size: manifest.total_size,
+        generated_at: manifest.generated_at.to_rfc3339(),
+    }))
+}
+
+/// One file or directory, with whether a file holds text and in which
+/// encoding, so editors can refuse binaries and decode legacy text
+pub async fn stat(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Json<StatResponse>, ApiError> {
+    let fs_error = |e: FsError| ApiError::from(&e);
+    let resolved = state.fs.resolve_path(&query.path).map_err(fs_error)?;
+    let entry = state.fs.get_entry(&query.path).map_err(fs_error)?;
+    let content = if entry.is_dir {
+        None
+    } else {
+        Some(
+            tokio::task::spawn_blocking(move || text::detect_file(&resolved))
+                .await
+                .map_err(std::io::Error::other)
+                .and_then(|r| r)
+                .map_err(|e| fs_error(e.into()))?,
         )
-    })?;
+    };
+    Ok(Json(StatResponse { entry, content }))
+}
+
+/// Download a file
+pub async fn download(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, ApiError> {
+    let resolved = state
+        .fs
+        .resolve_path(&query.path)
+        .map_err(|e| ApiError::with_body(StatusCode::NOT_FOUND, ErrorResponse::from(&e)))?;
 
     if resolved.is_dir() {
-        return Err((
+        return Err(ApiError::new(
             StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Cannot download a directory".to_string(),
-            }),
+            "Cannot download a directory",
         ));
     }
 
     let file_size = tokio::fs::metadata(&resolved)
         .await
         .map(|metadata| metadata.len())
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     let filename = resolved
         .file_name()
@@ -319,43 +1074,41 @@ pub async fn download(
         .unwrap_or("download");
     let encoded_filename = utf8_percent_encode(filename, FILENAME_ENCODE_SET).to_string();
 
-    let mime = mime_guess::from_path(&resolved)
-        .first_or_octet_stream()
-        .to_string();
+    let mut mime =
+        mime_type_of(&resolved).unwrap_or_else(|| "application/octet-stream".to_string());
+    // Browsers assume a charset for text; tell them the one the file has
+    if mime.starts_with("text/")
+        && let Ok(ContentInfo {
+            encoding: Some(encoding),
+            ..
+        }) = text::detect_file(&resolved)
+    {
+        mime = format!("{mime}; charset={}", encoding.charset());
+    }
 
-    let mut response = if let Some(range_header) = headers.get(header::RANGE) {
+    let response = if let Some(range_header) = headers.get(header::RANGE) {
         let range_header = range_header.to_str().map_err(|_| {
-            (
-                StatusCode::RANGE_NOT_SATISFIABLE,
-                Json(ErrorResponse {
-                    error: "Invalid Range header".to_string(),
-                }),
-            )
+            ApiError::new(StatusCode::RANGE_NOT_SATISFIABLE, "Invalid Range header")
         })?;
         let (start, end) = parse_range_header(range_header, file_size)?;
+        if let Some((offset, len)) = state.readahead.on_range(&resolved, start, end, file_size) {
+            let path = resolved.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = readahead::prefetch(&path, offset, len) {
+                    tracing::debug!("Readahead of {} failed: {}", path.display(), e);
+                }
+            });
+        }
         FileStream::<ReaderStream<File>>::try_range_response(&resolved, start, end)
             .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: e.to_string(),
-                    }),
-                )
-            })?
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
     } else {
         FileStream::from_path(&resolved)
             .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: e.to_string(),
-                    }),
-                )
-            })?
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
             .into_response()
     };
+    let mut response = response.map(|body| state.downloads.limit_body(body));
     let headers = response.headers_mut();
     headers.insert(
         header::CONTENT_TYPE,
@@ -366,48 +1119,199 @@ pub async fn download(
     headers.insert(
         header::CONTENT_DISPOSITION,
         HeaderValue::from_str(&format!("attachment; filename*=UTF-8''{encoded_filename}"))
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: e.to_string(),
-                    }),
-                )
-            })?,
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+    );
+
+    Ok(response)
+}
+
+/// Forwards bytes written by the blocking ZIP writer to the response body
+struct ChannelWriter(mpsc::Sender<std::io::Result<Bytes>>);
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client disconnected")
+            })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Deepest directory containing every path in `paths`
+fn common_parent(paths: &[PathBuf]) -> PathBuf {
+    let mut base = paths[0].parent().unwrap_or(&paths[0]).to_path_buf();
+    for path in &paths[1..] {
+        while !path.starts_with(&base) {
+            if !base.pop() {
+                break;
+            }
+        }
+    }
+    base
+}
+
+/// Stream a ZIP of the selected files and folders. Entries keep their paths
+/// relative to the closest common parent of the selection.
+pub async fn download_batch(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<DownloadBatchRequest>,
+) -> Result<Response<Body>, ApiError> {
+    if req.paths.is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "No paths selected"));
+    }
+
+    let mut resolved = Vec::with_capacity(req.paths.len());
+    for path in &req.paths {
+        resolved.push(
+            state
+                .fs
+                .resolve_path(path)
+                .map_err(|e| ApiError::from(&e))?,
+        );
+    }
+
+    // Sorted, a folder comes before anything inside it, so nested selections
+    // can be dropped in one pass
+    resolved.sort();
+    resolved.dedup();
+    let mut selected: Vec<PathBuf> = Vec::new();
+    for path in resolved {
+        if !selected.iter().any(|parent| path.starts_with(parent)) {
+            selected.push(path);
+        }
+    }
+
+    let base = common_parent(&selected);
+    let entries: Vec<(PathBuf, String)> = selected
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .strip_prefix(&base)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (path, name)
+        })
+        .collect();
+
+    let filename = match entries.as_slice() {
+        [(path, _)] => format!(
+            "{}.zip",
+            path.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| "download".to_string())
+        ),
+        _ => "download.zip".to_string(),
+    };
+    let encoded_filename = utf8_percent_encode(&filename, FILENAME_ENCODE_SET).to_string();
+
+    let (tx, mut rx) = mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        let writer = std::io::BufWriter::with_capacity(64 * 1024, ChannelWriter(tx.clone()));
+        let result = ArchiveService::write_zip(&entries, writer)
+            .and_then(|mut writer| std::io::Write::flush(&mut writer));
+        if let Err(e) = result
+            && e.kind() != std::io::ErrorKind::BrokenPipe
+        {
+            tracing::warn!("Batch download failed: {}", e);
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    let body = state
+        .downloads
+        .limit_body(Body::from_stream(futures_util::stream::poll_fn(
+            move |cx| rx.poll_recv(cx),
+        )));
+
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/zip"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!("attachment; filename*=UTF-8''{encoded_filename}"))
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
     );
 
     Ok(response)
 }
 
-fn parse_range_header(
-    range_header: &str,
-    file_size: u64,
-) -> Result<(u64, u64), (StatusCode, Json<ErrorResponse>)> {
+/// Render a PNG thumbnail of a document's first page
+pub async fn thumbnail(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<Response<Body>, ApiError> {
+    let resolved = state
+        .fs
+        .resolve_path(&query.path)
+        .map_err(|e| ApiError::from(&e))?;
+
+    if resolved.is_dir() || !MetadataService::is_pdf_file(&resolved) {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Thumbnails are only available for PDF files",
+        ));
+    }
+
+    if preview::previews_disabled(state.fs.root(), &resolved) {
+        return Err(ApiError::with_body(
+            StatusCode::FORBIDDEN,
+            ErrorResponse::new("Previews are disabled for this directory").with_hint(format!(
+                "Remove the {} file to enable previews.",
+                preview::NO_PREVIEW_MARKER
+            )),
+        ));
+    }
+
+    let size = query.size.unwrap_or(256).clamp(32, 1024);
+    let png = MetadataService::render_pdf_thumbnail(&resolved, size)
+        .await
+        .map_err(|e| {
+            let status = match e {
+                MetadataError::PopplerNotFound => StatusCode::SERVICE_UNAVAILABLE,
+                MetadataError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            ApiError::with_body(status, ErrorResponse::from(&e))
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "private, max-age=3600"),
+        ],
+        png,
+    )
+        .into_response())
+}
+
+fn parse_range_header(range_header: &str, file_size: u64) -> Result<(u64, u64), ApiError> {
     if file_size == 0 {
-        return Err((
+        return Err(ApiError::new(
             StatusCode::RANGE_NOT_SATISFIABLE,
-            Json(ErrorResponse {
-                error: "Range not satisfiable".to_string(),
-            }),
+            "Range not satisfiable",
         ));
     }
 
     let range_header = range_header.trim();
     let Some(ranges) = range_header.strip_prefix("bytes=") else {
-        return Err((
+        return Err(ApiError::new(
             StatusCode::RANGE_NOT_SATISFIABLE,
-            Json(ErrorResponse {
-                error: "Invalid Range header".to_string(),
-            }),
+            "Invalid Range header",
         ));
     };
 
     if ranges.contains(',') {
-        return Err((
+        return Err(ApiError::new(
             StatusCode::RANGE_NOT_SATISFIABLE,
-            Json(ErrorResponse {
-                error: "Multiple ranges are not supported".to_string(),
-            }),
+            "Multiple ranges are not supported",
         ));
     }
 
@@ -417,56 +1321,33 @@ fn parse_range_header(
 
     let (start, end) = if start_part.is_empty() {
         let suffix_len = end_part.parse::<u64>().map_err(|_| {
-            (
-                StatusCode::RANGE_NOT_SATISFIABLE,
-                Json(ErrorResponse {
-                    error: "Invalid Range header".to_string(),
-                }),
-            )
+            ApiError::new(StatusCode::RANGE_NOT_SATISFIABLE, "Invalid Range header")
         })?;
         if suffix_len == 0 {
-            return Err((
+            return Err(ApiError::new(
                 StatusCode::RANGE_NOT_SATISFIABLE,
-                Json(ErrorResponse {
-                    error: "Range not satisfiable".to_string(),
-                }),
+                "Range not satisfiable",
             ));
         }
         let end = file_size - 1;
-        let start = if suffix_len >= file_size {
-            0
-        } else {
-            file_size - suffix_len
-        };
+        let start = file_size.saturating_sub(suffix_len);
         (start, end)
     } else {
         let start = start_part.parse::<u64>().map_err(|_| {
-            (
-                StatusCode::RANGE_NOT_SATISFIABLE,
-                Json(ErrorResponse {
-                    error: "Invalid Range header".to_string(),
-                }),
-            )
+            ApiError::new(StatusCode::RANGE_NOT_SATISFIABLE, "Invalid Range header")
         })?;
         let end = if end_part.is_empty() {
             file_size - 1
         } else {
             end_part.parse::<u64>().map_err(|_| {
-                (
-                    StatusCode::RANGE_NOT_SATISFIABLE,
-                    Json(ErrorResponse {
-                        error: "Invalid Range header".to_string(),
-                    }),
-                )
+                ApiError::new(StatusCode::RANGE_NOT_SATISFIABLE, "Invalid Range header")
             })?
         };
 
         if start >= file_size || start > end {
-            return Err((
+            return Err(ApiError::new(
                 StatusCode::RANGE_NOT_SATISFIABLE,
-                Json(ErrorResponse {
-                    error: "Range not satisfiable".to_string(),
-                }),
+                "Range not satisfiable",
             ));
         }
 
@@ -476,98 +1357,440 @@ fn parse_range_header(
     Ok((start, end))
 }
 
+/// What to do when an uploaded file's name is already taken
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Overwrite,
+    /// Store as `name (1).ext`, `name (2).ext`, ...
+    Rename,
+    #[default]
+    Fail,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadQuery {
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+}
+
+/// Create the file for an upload named `name` in `dir` according to
+/// `policy`, returning it with the name actually used. Exclusive creation
+/// keeps `fail` and `rename` correct against concurrent uploads.
+async fn create_upload_file(
+    dir: &std::path::Path,
+    name: &str,
+    policy: ConflictPolicy,
+) -> std::io::Result<(File, String)> {
+    if policy == ConflictPolicy::Overwrite {
+        if dir.join(name).is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("A folder named {name} already exists"),
+            ));
+        }
+        return Ok((File::create(dir.join(name)).await?, name.to_string()));
+    }
+
+    loop {
+        let candidate = if policy == ConflictPolicy::Rename {
+            FilesystemService::unused_name(dir, name)
+        } else {
+            name.to_string()
+        };
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(&candidate))
+            .await
+        {
+            Ok(file) => return Ok((file, candidate)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if policy == ConflictPolicy::Fail {
+                    return Err(std::io::Error::new(
+                        e.kind(),
+                        format!("{name} already exists"),
+                    ));
+                }
+                // Taken between the check and the create; pick again
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Scan an upload written to `staging` for `dest`, moving it to the
+/// quarantine if it is infected. Uploads that cannot be scanned are refused
+/// rather than let through. Every verdict goes to the scan history.
+async fn scan_upload(
+    state: &AppState,
+    staging: &std::path::Path,
+    dest: &std::path::Path,
+) -> Result<(), ApiError> {
+    if !state.scanner.is_enabled() {
+        return Ok(());
+    }
+    let relative = state.fs.relative_path(dest);
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut scan = db::UploadScanRow {
+        id: 0, // Set by DB
+        path: relative.clone(),
+        status: String::new(),
+        signature: None,
+        quarantined_as: None,
+        error: None,
+        scanned_at: Utc::now().to_rfc3339(),
+    };
+
+    let result = match state.scanner.scan_file(staging).await {
+        Ok(verdict @ (ScanVerdict::Clean | ScanVerdict::Skipped)) => {
+            scan.status = if verdict == ScanVerdict::Clean {
+                "clean"
+            } else {
+                "skipped"
+            }
+            .to_string();
+            Ok(())
+        }
+        Ok(ScanVerdict::Infected(signature)) => {
+            tracing::warn!("Upload {} is infected with {}", relative, signature);
+            scan.status = "infected".to_string();
+            match state.scanner.quarantine(staging, &name).await {
+                Ok(moved) => scan.quarantined_as = Some(moved.to_string_lossy().into_owned()),
+                Err(e) => tracing::warn!("Failed to quarantine {}: {}", relative, e),
+            }
+            let err = ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("{name} contains a virus ({signature}) and was not saved"),
+            )
+            .with_code(ErrorCode::Infected)
+            .with_path(&relative);
+            scan.signature = Some(signature);
+            Err(err)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to scan upload {}: {}", relative, e);
+            scan.status = "error".to_string();
+            scan.error = Some(e.to_string());
+            Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("{name} could not be scanned for viruses: {e}"),
+            )
+            .with_path(&relative))
+        }
+    };
+    if let Err(e) = db::record_upload_scan(&state.pool, &scan).await {
+        tracing::warn!("Failed to record scan of {}: {}", relative, e);
+    }
+    result
+}
+
+/// Move a staged upload to `dest`, through the dedup store when there is
+/// one. A file it replaced may have been the last link to a stored object.
+async fn place_upload(
+    state: &AppState,
+    staging: &std::path::Path,
+    dest: &std::path::Path,
+) -> Result<(), ApiError> {
+    if !state.dedup.is_enabled() {
+        return tokio::fs::rename(staging, dest)
+            .await
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+    let replaced = tokio::fs::metadata(dest).await.is_ok_and(|m| m.len() > 0);
+    let dedup = state.dedup.clone();
+    let (staging, dest) = (staging.to_path_buf(), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || dedup.store(&staging, &dest))
+        .await
+        .map_err(std::io::Error::other)
+        .and_then(|r| r)
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if replaced {
+        state.dedup.prune_later();
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadScansQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadScansResponse {
+    /// Whether `FM_CLAMD_SOCKET` is set
+    pub enabled: bool,
+    pub scans: Vec<db::UploadScanRow>,
+}
+
+/// Recent virus scans of uploads, newest first
+pub async fn upload_scans(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UploadScansQuery>,
+) -> Result<Json<UploadScansResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let scans = db::list_upload_scans(&state.pool, limit)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(UploadScansResponse {
+        enabled: state.scanner.is_enabled(),
+        scans,
+    }))
+}
+
+/// Multipart text part carrying the relative path of the file part after it,
+/// for clients that cannot put slashes in the filename
+const UPLOAD_PATHS_FIELD: &str = "paths[]";
+
+/// Split an uploaded relative path (`photos/2024/a.jpg`) into its directory
+/// components and file name. Either separator is accepted; `.` and empty
+/// components are dropped. Returns `None` for `..` or a missing file name.
+fn split_upload_path(relative: &str) -> Option<(Vec<&str>, &str)> {
+    let mut parts: Vec<&str> = relative
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    if parts.contains(&"..") {
+        return None;
+    }
+    let file_name = parts.pop()?;
+    Some((parts, file_name))
+}
+
+/// Header carrying a client-chosen ID to track an upload's progress by
+pub const UPLOAD_ID_HEADER: &str = "x-upload-id";
+
 async fn upload_impl(
     state: Arc<AppState>,
     target_path: String,
+    query: UploadQuery,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let upload_id = match headers.get(UPLOAD_ID_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(id) if valid_upload_id(id) => Some(id.to_string()),
+            _ => {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "Invalid upload ID: use up to {MAX_UPLOAD_ID_LEN} letters, digits, '-' or '_'"
+                    ),
+                ));
+            }
+        },
+        None => None,
+    };
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+
+    let progress = state.uploads.start(upload_id, content_length);
+    // Without a Content-Length, each file is still refused once the reserve
+    // has been reached
+    let result = match ensure_room(&state, content_length.unwrap_or(0)) {
+        Ok(()) => {
+            receive_upload(
+                &state,
+                target_path,
+                query,
+                multipart,
+                &progress,
+                lock_token(&headers),
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    progress.finish(result.as_ref().err().map(|e| e.message().to_string()));
+    result
+}
+
+async fn receive_upload(
+    state: &AppState,
+    target_path: String,
+    query: UploadQuery,
     mut multipart: Multipart,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let target_dir = state.fs.resolve_path(&target_path).map_err(|e| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+    progress: &UploadHandle,
+    lock_token: Option<&str>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let target_dir = state
+        .fs
+        .resolve_path(&target_path)
+        .map_err(|e| ApiError::new(StatusCode::NOT_FOUND, e.to_string()))?;
 
     if !target_dir.is_dir() {
-        return Err((
+        return Err(ApiError::new(
             StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Target must be a directory".to_string(),
-            }),
+            "Target must be a directory",
         ));
     }
 
     let mut uploaded = Vec::new();
+    // Relative path from a preceding `paths[]` part, used for the next file
+    let mut pending_path: Option<String> = None;
 
-    while let Some(mut field) = multipart.next_field().await.map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })? {
-        let file_name = field.file_name().map(|s| s.to_string()).ok_or_else(|| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse {
-                    error: "Missing filename".to_string(),
-                }),
-            )
-        })?;
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        if field.name() == Some(UPLOAD_PATHS_FIELD) && field.file_name().is_none() {
+            let path = field
+                .text()
+                .await
+                .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+            pending_path = Some(path);
+            continue;
+        }
 
-        let dest_path = target_dir.join(&file_name);
+        let file_name = field
+            .file_name()
+            .map(|s| s.to_string())
+            .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "Missing filename"))?;
+        let relative_name = pending_path.take().unwrap_or(file_name);
+        let (dirs, file_name) = split_upload_path(&relative_name)
+            .ok_or_else(|| ApiError::new(StatusCode::FORBIDDEN, "Invalid filename"))?;
+
+        let invalid_name = |e: FsError| {
+            let status = match e {
+                FsError::PathEscape => StatusCode::FORBIDDEN,
+                FsError::NotADirectory(_) => StatusCode::CONFLICT,
+                _ => status_for_fs_error(&e),
+            };
+            ApiError::with_body(status, ErrorResponse::from(&e))
+        };
 
-        // Security: ensure we're still under root
-        if !dest_path.starts_with(&target_dir) {
-            return Err((
-                StatusCode::FORBIDDEN,
-                Json(ErrorResponse {
-                    error: "Invalid filename".to_string(),
-                }),
-            ));
+        // Recreate the folder structure, normalizing each level as if it
+        // had been created through the UI
+        let mut parent_dir = target_dir.clone();
+        for dir in dirs {
+            let dir_name =
+                FilesystemService::sanitize_file_name(&normalize_name(&state.fs, &parent_dir, dir))
+                    .map_err(invalid_name)?;
+            parent_dir = state
+                .fs
+                .ensure_subdirectory(&parent_dir, &dir_name)
+                .map_err(invalid_name)?;
         }
 
-        let file = File::create(&dest_path).await.map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
-
-        let mut writer = BufWriter::new(file);
-        while let Some(chunk) = field.chunk().await.map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })? {
-            writer.write_all(&chunk).await.map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: e.to_string(),
-                    }),
-                )
-            })?;
+        let clean_name = FilesystemService::sanitize_file_name(&normalize_name(
+            &state.fs,
+            &parent_dir,
+            file_name,
+        ))
+        .map_err(invalid_name)?;
+        ensure_room(state, 0)?;
+        let replaces_file =
+            query.conflict == ConflictPolicy::Overwrite && parent_dir.join(&clean_name).is_file();
+        if replaces_file {
+            let target = state.fs.relative_path(&parent_dir.join(&clean_name));
+            state
+                .locks
+                .check(&target, lock_token)
+                .map_err(|held| locked(&held))?;
+        }
+        let create_failed = |e: std::io::Error| {
+            let status = if e.kind() == std::io::ErrorKind::AlreadyExists {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            ApiError::new(status, e.to_string())
+        };
+        // With a scanner or the dedup store, the upload is written to a
+        // hidden file beside its target and only moved into place once
+        // scanned and stored, so a file it overwrites stays until then.
+        // Other policies still reserve the name.
+        let (file, stored_name, staging) = if state.scanner.is_enabled() || state.dedup.is_enabled()
+        {
+            let stored_name = if query.conflict == ConflictPolicy::Overwrite {
+                if parent_dir.join(&clean_name).is_dir() {
+                    return Err(ApiError::new(
+                        StatusCode::CONFLICT,
+                        format!("A folder named {clean_name} already exists"),
+                    ));
+                }
+                clean_name.clone()
+            } else {
+                create_upload_file(&parent_dir, &clean_name, query.conflict)
+                    .await
+                    .map_err(create_failed)?
+                    .1
+            };
+            let staging =
+                parent_dir.join(format!(".filex-upload-{}", uuid::Uuid::new_v4().simple()));
+            let file = File::create(&staging).await.map_err(create_failed)?;
+            (file, stored_name, Some(staging))
+        } else {
+            let (file, stored_name) = create_upload_file(&parent_dir, &clean_name, query.conflict)
+                .await
+                .map_err(create_failed)?;
+            (file, stored_name, None)
+        };
+        let dest_path = parent_dir.join(&stored_name);
+        let reserved = query.conflict != ConflictPolicy::Overwrite;
+        progress.file_started(&stored_name);
+
+        let written = async {
+            let mut writer = BufWriter::new(file);
+            while let Some(chunk) = field
+                .chunk()
+                .await
+                .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?
+            {
+                writer
+                    .write_all(&chunk)
+                    .await
+                    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                progress.add_bytes(chunk.len() as u64);
+            }
+            writer
+                .flush()
+                .await
+                .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+        .await;
+        if let Some(staging) = &staging {
+            let placed = async {
+                written?;
+                scan_upload(state, staging, &dest_path).await?;
+                place_upload(state, staging, &dest_path).await
+            }
+            .await;
+            if let Err(e) = placed {
+                // Whatever is left of the upload, and the name reserved for it
+                let _ = tokio::fs::remove_file(staging).await;
+                if reserved {
+                    let _ = tokio::fs::remove_file(&dest_path).await;
+                }
+                return Err(e);
+            }
+        } else {
+            written?;
         }
+        progress.file_finished();
 
-        writer.flush().await.map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+        // Collision renames are not recorded; only what the name policy and
+        // sanitization changed
+        let original_name = (clean_name != file_name).then_some(file_name);
+        let relative = state.fs.relative_path(&dest_path);
+        db::set_original_name(&state.pool, &relative, &stored_name, original_name)
+            .await
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-        uploaded.push(file_name);
+        let event = if replaces_file {
+            FileEventKind::Modified
+        } else {
+            FileEventKind::Created
+        };
+        report_change(state, event, &relative, None, false);
+
+        uploaded.push(stored_name);
     }
 
     Ok(Json(SuccessResponse {
@@ -575,6 +1798,7 @@ async fn upload_impl(
         path: Some(target_path),
         message: Some(format!("Uploaded {} file(s)", uploaded.len())),
         performed: None,
+        sync: None,
     }))
 }
 
@@ -582,40 +1806,48 @@ async fn upload_impl(
 pub async fn upload(
     State(state): State<Arc<AppState>>,
     Path(target_path): Path<String>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
     multipart: Multipart,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    upload_impl(state, target_path, multipart).await
+) -> Result<Json<SuccessResponse>, ApiError> {
+    upload_impl(state, target_path, query, headers, multipart).await
 }
 
 /// Upload files to root directory
 pub async fn upload_root(
     State(state): State<Arc<AppState>>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
     multipart: Multipart,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    upload_impl(state, "/".to_string(), multipart).await
+) -> Result<Json<SuccessResponse>, ApiError> {
+    upload_impl(state, "/".to_string(), query, headers, multipart).await
+}
+
+/// Server-side progress of an upload sent with `X-Upload-Id`; kept for a
+/// while after it finishes
+pub async fn upload_progress(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<UploadProgress>, ApiError> {
+    state
+        .uploads
+        .get(&id)
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Upload {id} not found")))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::services::FilesystemService;
     use axum::Router;
     use axum::body::Body;
     use axum::extract::State;
     use axum::http::{Request, StatusCode, header};
-    use chrono::Utc;
     use sqlx::sqlite::SqlitePoolOptions;
     use std::fs;
     use tempfile::tempdir;
     use tower::ServiceExt;
 
-    fn now_sqlite_timestamp() -> String {
-        Utc::now()
-            .naive_utc()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string()
-    }
-
     async fn test_state() -> (Arc<AppState>, tempfile::TempDir, std::path::PathBuf) {
         let tmp = tempdir().expect("tempdir created");
         let root = tmp.path().join("root");
@@ -631,60 +1863,308 @@ mod tests {
         let search = Arc::new(crate::services::SearchService::new());
 
         let state = Arc::new(AppState {
-            fs: FilesystemService::new(root.clone()),
-            pool,
             search,
+            ..AppState::for_tests(root.clone(), pool)
         });
 
         (state, tmp, root)
     }
 
     #[tokio::test]
-    async fn rename_updates_filesystem_and_index() {
+    async fn recursive_create_directory_indexes_new_parents() {
         let (state, _tmp, root) = test_state().await;
-        let original = root.join("old.txt");
-        fs::write(&original, b"hello").unwrap();
-
-        let indexed = crate::models::IndexedFileRow {
-            id: 0,
-            path: "/old.txt".to_string(),
-            name: "old.txt".to_string(),
-            is_dir: false,
-            size: Some(5),
-            created_at: None,
-            modified_at: None,
-            mime_type: Some("text/plain".to_string()),
-            width: None,
-            height: None,
-            duration: None,
-            metadata_status: "complete".to_string(),
-            indexed_at: now_sqlite_timestamp(),
+        let create = |path: &str, recursive: bool| {
+            create_directory(
+                State(state.clone()),
+                Json(CreateDirRequest {
+                    path: path.to_string(),
+                    recursive,
+                }),
+            )
         };
-        crate::db::upsert_file(&state.pool, &indexed)
-            .await
-            .expect("seed index");
-
-        let resp = rename(
-            State(state.clone()),
-            Json(RenameRequest {
-                path: "/old.txt".to_string(),
-                new_name: "new.txt".to_string(),
-            }),
-        )
-        .await
-        .expect("rename should succeed");
 
-        assert_eq!(resp.0.path.as_deref(), Some("/new.txt"));
-        assert!(!original.exists());
-        assert!(root.join("new.txt").exists());
+        let err = create("/clients/acme", false).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
 
-        let count_old: i64 =
-            sqlx::query_scalar("SELECT COUNT(*) FROM indexed_files WHERE path = ?")
-                .bind("/old.txt")
-                .fetch_one(&state.pool)
+        let resp = create("/clients/acme", true).await.unwrap();
+        assert_eq!(resp.0.performed, Some(true));
+        assert!(root.join("clients/acme").is_dir());
+        let indexed: Vec<String> =
+            sqlx::query_scalar("SELECT path FROM indexed_files WHERE is_dir ORDER BY path")
+                .fetch_all(&state.pool)
                 .await
                 .unwrap();
-        let count_new: i64 =
+        assert_eq!(indexed, ["/clients", "/clients/acme"]);
+        assert_eq!(state.search.search("acme").await.len(), 1);
+
+        let resp = create("/clients/acme", true).await.unwrap();
+        assert_eq!(resp.0.performed, Some(false));
+        let err = create("/clients/../../acme", true).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn create_file_starts_empty_or_from_a_template() {
+        let (state, tmp, root) = test_state().await;
+        let templates = tmp.path().join("templates");
+        fs::create_dir(&templates).unwrap();
+        fs::write(templates.join("README.md"), b"# Project\n").unwrap();
+        let state = Arc::new(AppState {
+            templates: crate::services::FileTemplates::new(templates),
+            ..Arc::try_unwrap(state).ok().unwrap()
+        });
+        fs::create_dir(root.join("docs")).unwrap();
+        let create = |path: &str, template: Option<&str>| {
+            create_file(
+                State(state.clone()),
+                Json(CreateFileRequest {
+                    path: path.to_string(),
+                    template: template.map(str::to_string),
+                }),
+            )
+        };
+
+        let resp = create("/docs/todo.txt", None).await.unwrap();
+        assert_eq!(resp.0.path.as_deref(), Some("/docs/todo.txt"));
+        assert_eq!(fs::read(root.join("docs/todo.txt")).unwrap(), b"");
+        let resp = create("/docs/README.md", Some("README.md")).await.unwrap();
+        assert_eq!(resp.0.path.as_deref(), Some("/docs/README.md"));
+        assert_eq!(
+            fs::read_to_string(root.join("docs/README.md")).unwrap(),
+            "# Project\n"
+        );
+        assert_eq!(state.search.search("todo").await.len(), 1);
+        let status: String =
+            sqlx::query_scalar("SELECT metadata_status FROM indexed_files WHERE path = ?")
+                .bind("/docs/README.md")
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "pending");
+
+        for (path, template, expected) in [
+            ("/docs/todo.txt", None, StatusCode::CONFLICT),
+            ("/docs", None, StatusCode::CONFLICT),
+            ("/missing/a.txt", None, StatusCode::NOT_FOUND),
+            ("/docs/b.txt", Some("nope.md"), StatusCode::NOT_FOUND),
+            ("/docs/b.txt", Some("../README.md"), StatusCode::BAD_REQUEST),
+        ] {
+            let err = create(path, template).await.unwrap_err();
+            assert_eq!(err.status, expected, "{path}");
+        }
+        assert!(!root.join("docs/b.txt").exists());
+
+        let listed = list_templates(State(state.clone())).await.unwrap();
+        assert_eq!(listed.0.templates, ["README.md"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn links_are_gated_and_indexed_with_their_target() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir(root.join("albums")).unwrap();
+        fs::write(root.join("beach.jpg"), b"jpeg").unwrap();
+        let request = |path: &str, kind: LinkKind| {
+            Json(LinkRequest {
+                target: "/beach.jpg".to_string(),
+                path: path.to_string(),
+                kind,
+            })
+        };
+
+        let err = create_link(
+            State(state.clone()),
+            request("/albums/beach.jpg", LinkKind::Hard),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+
+        let state = Arc::new(AppState {
+            allow_links: true,
+            ..Arc::try_unwrap(state).ok().unwrap()
+        });
+        let resp = create_link(
+            State(state.clone()),
+            request("/albums/beach.jpg", LinkKind::Hard),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.path.as_deref(), Some("/albums/beach.jpg"));
+        let resp = create_link(
+            State(state.clone()),
+            request("/albums/alias.jpg", LinkKind::Symbolic),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.path.as_deref(), Some("/albums/alias.jpg"));
+        assert!(root.join("albums/alias.jpg").is_symlink());
+
+        let err = create_link(
+            State(state.clone()),
+            request("/albums/alias.jpg", LinkKind::Hard),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+
+        let links = db::links_for_paths(
+            &state.pool,
+            &[
+                "/albums/alias.jpg".to_string(),
+                "/albums/beach.jpg".to_string(),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            links["/albums/alias.jpg"],
+            ("symbolic".to_string(), "/beach.jpg".to_string())
+        );
+        assert_eq!(links["/albums/beach.jpg"].0, "hard");
+        assert_eq!(state.search.search("alias").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn move_batch_reports_conflicts_before_moving_anything() {
+        let (state, _tmp, root) = test_state().await;
+        for dir in ["inbox/sub", "other", "archive"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("inbox/a.txt"), b"new").unwrap();
+        fs::write(root.join("inbox/b.txt"), b"b").unwrap();
+        fs::write(root.join("other/b.txt"), b"other b").unwrap();
+        fs::write(root.join("archive/a.txt"), b"old").unwrap();
+        for path in ["/inbox/a.txt", "/archive/a.txt"] {
+            sqlx::query("INSERT INTO indexed_files (path, name) VALUES (?, 'a.txt')")
+                .bind(path)
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+        let move_batch = |resolutions: &[(&str, ConflictResolution)]| {
+            move_batch(
+                State(state.clone()),
+                Json(MoveBatchRequest {
+                    paths: ["/inbox/a.txt", "/inbox/b.txt", "/other/b.txt", "/inbox/sub"]
+                        .map(str::to_string)
+                        .to_vec(),
+                    to: "/archive".to_string(),
+                    resolutions: resolutions
+                        .iter()
+                        .map(|(path, resolution)| (path.to_string(), *resolution))
+                        .collect(),
+                }),
+            )
+        };
+
+        let resp = move_batch(&[]).await.unwrap().0;
+        assert!(!resp.performed);
+        let conflicts: Vec<_> = resp
+            .conflicts
+            .iter()
+            .map(|c| (c.path.as_str(), c.destination.as_str()))
+            .collect();
+        assert_eq!(
+            conflicts,
+            [
+                ("/inbox/a.txt", "/archive/a.txt"),
+                ("/other/b.txt", "/archive/b.txt")
+            ]
+        );
+        assert!(root.join("inbox/b.txt").exists());
+
+        let resp = move_batch(&[
+            ("/inbox/a.txt", ConflictResolution::Overwrite),
+            ("/other/b.txt", ConflictResolution::Rename),
+        ])
+        .await
+        .unwrap()
+        .0;
+        assert!(resp.performed);
+        let moved: Vec<_> = resp
+            .results
+            .iter()
+            .map(|r| (r.outcome, r.to.as_deref()))
+            .collect();
+        assert_eq!(
+            moved,
+            [
+                (MoveOutcome::Moved, Some("/archive/a.txt")),
+                (MoveOutcome::Moved, Some("/archive/b.txt")),
+                (MoveOutcome::Moved, Some("/archive/b (1).txt")),
+                (MoveOutcome::Moved, Some("/archive/sub")),
+            ]
+        );
+        assert_eq!(fs::read(root.join("archive/a.txt")).unwrap(), b"new");
+        assert_eq!(
+            fs::read(root.join("archive/b (1).txt")).unwrap(),
+            b"other b"
+        );
+        let indexed: Vec<String> = sqlx::query_scalar("SELECT path FROM indexed_files")
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(indexed, ["/archive/a.txt"]);
+
+        let err = move_batch_into(&state, "/archive", "/archive/sub").await;
+        assert_eq!(err, StatusCode::FORBIDDEN);
+        let err = move_batch_into(&state, "/archive/a.txt", "/archive/a.txt").await;
+        assert_eq!(err, StatusCode::BAD_REQUEST);
+    }
+
+    async fn move_batch_into(state: &Arc<AppState>, path: &str, to: &str) -> StatusCode {
+        move_batch(
+            State(state.clone()),
+            Json(MoveBatchRequest {
+                paths: vec![path.to_string()],
+                to: to.to_string(),
+                resolutions: HashMap::new(),
+            }),
+        )
+        .await
+        .unwrap_err()
+        .status
+    }
+
+    #[tokio::test]
+    async fn rename_updates_filesystem_and_index() {
+        let (state, _tmp, root) = test_state().await;
+        let original = root.join("old.txt");
+        fs::write(&original, b"hello").unwrap();
+
+        let indexed = crate::models::IndexedFileRow {
+            size: Some(5),
+            mime_type: Some("text/plain".to_string()),
+            ..crate::models::IndexedFileRow::for_tests("/old.txt", false)
+        };
+        crate::db::upsert_file(&state.pool, &indexed)
+            .await
+            .expect("seed index");
+
+        let resp = rename(
+            State(state.clone()),
+            Json(RenameRequest {
+                path: "/old.txt".to_string(),
+                new_name: "new.txt".to_string(),
+                expected_mtime: None,
+                expected_size: None,
+            }),
+        )
+        .await
+        .expect("rename should succeed");
+
+        assert_eq!(resp.0.path.as_deref(), Some("/new.txt"));
+        assert!(!original.exists());
+        assert!(root.join("new.txt").exists());
+
+        let count_old: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM indexed_files WHERE path = ?")
+                .bind("/old.txt")
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+        let count_new: i64 =
             sqlx::query_scalar("SELECT COUNT(*) FROM indexed_files WHERE path = ?")
                 .bind("/new.txt")
                 .fetch_one(&state.pool)
@@ -695,6 +2175,154 @@ mod tests {
         assert_eq!(count_new, 1);
     }
 
+    #[tokio::test]
+    async fn file_operations_go_through_the_storage_backend() {
+        use crate::services::{MemoryBackend, StorageBackend};
+
+        let (state, _tmp, root) = test_state().await;
+        let Ok(state) = Arc::try_unwrap(state) else {
+            panic!("state is shared");
+        };
+        let memory = Arc::new(MemoryBackend::new());
+        let state = Arc::new(AppState {
+            backend: memory.clone(),
+            ..state
+        });
+
+        let created = create_directory(
+            State(state.clone()),
+            Json(CreateDirRequest {
+                path: "/docs/2024".to_string(),
+                recursive: true,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(created.0.performed, Some(true));
+        let indexed: (bool,) = sqlx::query_as("SELECT is_dir FROM indexed_files WHERE path = ?")
+            .bind("/docs/2024")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert!(indexed.0);
+        memory.write_file("/docs/a.txt", b"hello").unwrap();
+
+        let renamed = rename(
+            State(state.clone()),
+            Json(RenameRequest {
+                path: "/docs/a.txt".to_string(),
+                new_name: "b.txt".to_string(),
+                expected_mtime: None,
+                expected_size: Some(5),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(renamed.0.path.as_deref(), Some("/docs/b.txt"));
+
+        let copied = copy_entry(
+            State(state.clone()),
+            Json(CopyRequest {
+                from: "/docs/b.txt".to_string(),
+                to: "/docs/2024".to_string(),
+                overwrite: false,
+                mode: CopyMode::Copy,
+                checksum: false,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(copied.0.path.as_deref(), Some("/docs/2024/b.txt"));
+
+        let synced = copy_entry(
+            State(state.clone()),
+            Json(CopyRequest {
+                from: "/docs".to_string(),
+                to: "/mirror".to_string(),
+                overwrite: false,
+                mode: CopyMode::Sync,
+                checksum: true,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(synced.0.sync.map(|stats| stats.created), Some(2));
+        assert_eq!(memory.read_file("/mirror/2024/b.txt").unwrap(), b"hello");
+        memory.delete("/mirror").unwrap();
+
+        let moved = move_entry(
+            State(state.clone()),
+            Json(MoveRequest {
+                from: "/docs/2024".to_string(),
+                to: "/archive".to_string(),
+                overwrite: false,
+                expected_mtime: None,
+                expected_size: None,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(moved.0.path.as_deref(), Some("/archive"));
+        assert_eq!(memory.read_file("/archive/b.txt").unwrap(), b"hello");
+
+        let deleted = delete(
+            State(state.clone()),
+            Json(DeleteRequest {
+                path: "/docs".to_string(),
+                expected_mtime: None,
+                expected_size: None,
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(deleted.0.success);
+        let names: Vec<String> = memory
+            .list_directory("/", true)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["archive"]);
+
+        // Nothing reached the root directory
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn rename_applies_directory_name_policy() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir(root.join("scans")).unwrap();
+        fs::write(
+            root.join("scans/.fxnames"),
+            "transliterate = de\nreplace_spaces = _\n",
+        )
+        .unwrap();
+        fs::write(root.join("scans/a.pdf"), b"pdf").unwrap();
+
+        let resp = rename(
+            State(state.clone()),
+            Json(RenameRequest {
+                path: "/scans/a.pdf".to_string(),
+                new_name: "Übersicht März.pdf".to_string(),
+                expected_mtime: None,
+                expected_size: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resp.0.path.as_deref(), Some("/scans/Uebersicht_Maerz.pdf"));
+        assert!(root.join("scans/Uebersicht_Maerz.pdf").exists());
+
+        let original: Option<String> =
+            sqlx::query_scalar("SELECT original_name FROM indexed_files WHERE path = ?")
+                .bind("/scans/Uebersicht_Maerz.pdf")
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+        assert_eq!(original.as_deref(), Some("Übersicht März.pdf"));
+    }
+
     #[tokio::test]
     async fn download_rejects_directories_and_sets_headers() {
         let (state, _tmp, root) = test_state().await;
@@ -711,7 +2339,7 @@ mod tests {
         )
         .await
         .unwrap_err();
-        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
 
         // Successful download returns headers
         let response = download(
@@ -726,7 +2354,10 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
         let headers = response.headers();
-        assert_eq!(headers.get(header::CONTENT_TYPE).unwrap(), "text/plain");
+        assert_eq!(
+            headers.get(header::CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
         let disposition = headers.get(header::CONTENT_DISPOSITION).unwrap();
         assert!(
             disposition
@@ -737,6 +2368,56 @@ mod tests {
         assert_eq!(headers.get(header::ACCEPT_RANGES).unwrap(), "bytes");
     }
 
+    #[tokio::test]
+    async fn stat_reports_text_encoding_of_files() {
+        let (state, _tmp, root) = test_state().await;
+        fs::write(root.join("legacy.txt"), b"caf\xE9").unwrap();
+        fs::write(root.join("blob.bin"), b"\x00\x01\x02\x03\xFF").unwrap();
+        fs::create_dir(root.join("docs")).unwrap();
+        let stat_of = |path: &str| {
+            stat(
+                State(state.clone()),
+                Query(DownloadQuery {
+                    path: path.to_string(),
+                }),
+            )
+        };
+
+        let Json(resp) = stat_of("/legacy.txt").await.unwrap();
+        assert_eq!(resp.entry.path, "/legacy.txt");
+        let content = resp.content.unwrap();
+        assert!(content.is_text);
+        assert_eq!(content.encoding, Some(text::TextEncoding::Latin1));
+        assert!(
+            !stat_of("/blob.bin")
+                .await
+                .unwrap()
+                .0
+                .content
+                .unwrap()
+                .is_text
+        );
+        assert!(stat_of("/docs").await.unwrap().0.content.is_none());
+        assert_eq!(
+            stat_of("/missing").await.unwrap_err().status,
+            StatusCode::NOT_FOUND
+        );
+
+        let response = download(
+            State(state.clone()),
+            Query(DownloadQuery {
+                path: "/legacy.txt".to_string(),
+            }),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/plain; charset=iso-8859-1"
+        );
+    }
+
     #[tokio::test]
     async fn download_with_range_returns_partial_response() {
         let (state, _tmp, root) = test_state().await;
@@ -804,6 +2485,111 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[tokio::test]
+    async fn upload_progress_is_tracked_by_upload_id() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("dir")).unwrap();
+
+        let app = Router::new()
+            .route("/upload/{*path}", axum::routing::post(upload))
+            .with_state(state.clone());
+        let boundary = "BOUNDARYPROGRESS";
+        let body = format!(
+            "--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"big.bin\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n\
+             0123456789\r\n\
+             --{boundary}--"
+        );
+        let send = |id: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/upload/dir")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .header(header::CONTENT_LENGTH, body.len())
+                .header(UPLOAD_ID_HEADER, id)
+                .body(Body::from(body.clone()))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(send("up-1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let progress = upload_progress(State(state.clone()), Path("up-1".to_string()))
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(
+            progress.state,
+            crate::services::uploads::UploadState::Complete
+        );
+        assert_eq!(progress.bytes_received, 10);
+        assert_eq!(progress.files_completed, 1);
+        assert_eq!(progress.content_length, Some(body.len() as u64));
+
+        let response = app.oneshot(send("../etc")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let err = upload_progress(State(state), Path("missing".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn uploads_and_copies_respect_the_free_space_reserve() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("a.txt"), b"data").unwrap();
+        let Ok(state) = Arc::try_unwrap(state) else {
+            panic!("state is shared");
+        };
+        let state = Arc::new(AppState {
+            root_storage: crate::services::StorageGuard::new(root.clone(), u64::MAX),
+            ..state
+        });
+
+        let err = copy_entry(
+            State(state.clone()),
+            Json(CopyRequest {
+                from: "/a.txt".to_string(),
+                to: "/dir".to_string(),
+                overwrite: false,
+                mode: CopyMode::Copy,
+                checksum: false,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!root.join("dir/a.txt").exists());
+
+        let app = Router::new()
+            .route("/upload/{*path}", axum::routing::post(upload))
+            .with_state(state);
+        let boundary = "BOUNDARYFULL";
+        let body = format!(
+            "--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"big.bin\"\r\n\r\n\
+             0123456789\r\n\
+             --{boundary}--"
+        );
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload/dir")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!root.join("dir/big.bin").exists());
+    }
+
     #[tokio::test]
     async fn upload_succeeds_and_writes_file() {
         let (state, _tmp, root) = test_state().await;
@@ -839,6 +2625,406 @@ mod tests {
         assert_eq!(fs::read_to_string(uploaded).unwrap(), "hello world");
     }
 
+    #[tokio::test]
+    async fn upload_applies_conflict_policy_and_sanitizes_names() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/a.txt"), b"old").unwrap();
+
+        let app = Router::new()
+            .route("/upload/{*path}", axum::routing::post(upload))
+            .with_state(state.clone());
+        let send = |query: &str, filename: &str| {
+            let boundary = "BOUNDARYCONFLICT";
+            Request::builder()
+                .method("POST")
+                .uri(format!("/upload/dir{query}"))
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(format!(
+                    "--{boundary}\r\n\
+                     Content-Disposition: form-data; name=\"files\"; filename=\"{filename}\"\r\n\r\n\
+                     new\r\n\
+                     --{boundary}--"
+                )))
+                .unwrap()
+        };
+
+        // Existing files are kept unless asked otherwise
+        let response = app.clone().oneshot(send("", "a.txt")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(fs::read_to_string(root.join("dir/a.txt")).unwrap(), "old");
+
+        let response = app
+            .clone()
+            .oneshot(send("?conflict=rename", "a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            fs::read_to_string(root.join("dir/a (1).txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(fs::read_to_string(root.join("dir/a.txt")).unwrap(), "old");
+
+        let response = app
+            .clone()
+            .oneshot(send("?conflict=overwrite", "a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(fs::read_to_string(root.join("dir/a.txt")).unwrap(), "new");
+
+        let response = app.clone().oneshot(send("", "aux:1?.txt")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(root.join("dir/aux_1_.txt").exists());
+
+        let response = app.oneshot(send("", "..")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn infected_uploads_are_quarantined_instead_of_saved() {
+        use crate::services::VirusScanner;
+        use crate::services::antivirus::{ClamdAddress, tests::fake_clamd};
+
+        let (state, tmp, root) = test_state().await;
+        fs::write(root.join("report.txt"), b"old").unwrap();
+        let Ok(state) = Arc::try_unwrap(state) else {
+            panic!("state is shared");
+        };
+        let quarantine = tmp.path().join("quarantine");
+        let state = Arc::new(AppState {
+            scanner: VirusScanner::new(
+                ClamdAddress::Tcp(fake_clamd().await),
+                quarantine.clone(),
+                1024 * 1024,
+            ),
+            ..state
+        });
+
+        let app = Router::new()
+            .route("/upload", axum::routing::post(upload_root))
+            .with_state(state.clone());
+        let send = |query: &str, filename: &str, contents: &str| {
+            let boundary = "BOUNDARYSCAN";
+            Request::builder()
+                .method("POST")
+                .uri(format!("/upload{query}"))
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(format!(
+                    "--{boundary}\r\n\
+                     Content-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\n\r\n\
+                     {contents}\r\n\
+                     --{boundary}--"
+                )))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(send("", "notes.txt", "hello"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "hello");
+
+        // Neither the new name nor an overwritten file is touched
+        let response = app
+            .clone()
+            .oneshot(send("", "eicar.com", "X5O!P%@AP EICAR test"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = app
+            .oneshot(send("?conflict=overwrite", "report.txt", "EICAR"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(!root.join("eicar.com").exists());
+        assert_eq!(fs::read_to_string(root.join("report.txt")).unwrap(), "old");
+        let mut names: Vec<_> = fs::read_dir(&root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["notes.txt", "report.txt"]);
+        assert_eq!(fs::read_dir(&quarantine).unwrap().count(), 2);
+
+        let scans = upload_scans(State(state), Query(UploadScansQuery { limit: None }))
+            .await
+            .unwrap();
+        let statuses: Vec<_> = scans.0.scans.iter().map(|s| s.status.as_str()).collect();
+        assert_eq!(statuses, ["infected", "infected", "clean"]);
+        assert_eq!(
+            scans.0.scans[0].signature.as_deref(),
+            Some("Eicar-Test-Signature")
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn duplicate_uploads_are_stored_once() {
+        use crate::services::DedupStore;
+        use std::os::unix::fs::MetadataExt;
+
+        let (state, tmp, root) = test_state().await;
+        let Ok(state) = Arc::try_unwrap(state) else {
+            panic!("state is shared");
+        };
+        let store = tmp.path().join("store");
+        let state = Arc::new(AppState {
+            dedup: Arc::new(DedupStore::new(store.clone())),
+            ..state
+        });
+
+        let app = Router::new()
+            .route("/upload", axum::routing::post(upload_root))
+            .with_state(state.clone());
+        for filename in ["a.jpg", "b.jpg"] {
+            let boundary = "BOUNDARYDEDUP";
+            let request = Request::builder()
+                .method("POST")
+                .uri("/upload")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(format!(
+                    "--{boundary}\r\n\
+                     Content-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\n\r\n\
+                     same bytes\r\n\
+                     --{boundary}--"
+                )))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let a = fs::metadata(root.join("a.jpg")).unwrap();
+        assert_eq!(a.ino(), fs::metadata(root.join("b.jpg")).unwrap().ino());
+        assert_eq!(a.nlink(), 3);
+
+        // The stored copy goes once nothing links to it
+        for path in ["/a.jpg", "/b.jpg"] {
+            let deleted = delete(
+                State(state.clone()),
+                Json(DeleteRequest {
+                    path: path.to_string(),
+                    expected_mtime: None,
+                    expected_size: None,
+                }),
+            )
+            .await
+            .unwrap();
+            assert!(deleted.0.success);
+        }
+        let objects = || {
+            walkdir::WalkDir::new(&store)
+                .into_iter()
+                .filter(|e| e.as_ref().unwrap().file_type().is_file())
+                .count()
+        };
+        for _ in 0..50 {
+            if objects() == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(objects(), 0);
+    }
+
+    #[tokio::test]
+    async fn upload_recreates_folder_structure_under_target() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("dir")).unwrap();
+
+        let app = Router::new()
+            .route("/upload/{*path}", axum::routing::post(upload))
+            .with_state(state.clone());
+
+        let boundary = "BOUNDARYTREE";
+        let body_stream = Body::from(format!(
+            "--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"files\"; filename=\"album/2024/a.txt\"\r\n\r\n\
+             alpha\r\n\
+             --{boundary}\r\n\
+             Content-Disposition: form-data; name=\"paths[]\"\r\n\r\n\
+             album\\notes\\b.txt\r\n\
+             --{boundary}\r\n\
+             Content-Disposition: form-data; name=\"files\"; filename=\"b.txt\"\r\n\r\n\
+             beta\r\n\
+             --{boundary}--"
+        ));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload/dir")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(body_stream)
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            fs::read_to_string(root.join("dir/album/2024/a.txt")).unwrap(),
+            "alpha"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("dir/album/notes/b.txt")).unwrap(),
+            "beta"
+        );
+
+        let boundary = "BOUNDARYESCAPE";
+        let body_stream = Body::from(format!(
+            "--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"files\"; filename=\"album/../../evil.txt\"\r\n\r\n\
+             nope\r\n\
+             --{boundary}--"
+        ));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload/dir")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(body_stream)
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!root.join("evil.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn thumbnail_rejects_non_pdf_files() {
+        let (state, _tmp, root) = test_state().await;
+        fs::write(root.join("notes.txt"), b"hello").unwrap();
+
+        let err = thumbnail(
+            State(state.clone()),
+            Query(ThumbnailQuery {
+                path: "/notes.txt".to_string(),
+                size: None,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let err = thumbnail(
+            State(state),
+            Query(ThumbnailQuery {
+                path: "/missing.pdf".to_string(),
+                size: None,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn download_batch_zips_selection_relative_to_common_parent() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("a/c")).unwrap();
+        fs::write(root.join("a/b/one.txt"), b"1").unwrap();
+        fs::write(root.join("a/c/two.txt"), b"2").unwrap();
+
+        let response = download_batch(
+            State(state.clone()),
+            Json(DownloadBatchRequest {
+                paths: vec![
+                    "/a/b".to_string(),
+                    "/a/c/two.txt".to_string(),
+                    "/a/b/one.txt".to_string(),
+                ],
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/zip");
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, vec!["b/", "b/one.txt", "c/two.txt"]);
+
+        let err = download_batch(
+            State(state),
+            Json(DownloadBatchRequest {
+                paths: vec!["/missing".to_string()],
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn thumbnail_respects_preview_opt_out() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("private")).unwrap();
+        fs::write(root.join("private/scan.pdf"), b"%PDF-1.4").unwrap();
+        fs::write(root.join("private").join(preview::NO_PREVIEW_MARKER), b"").unwrap();
+
+        let err = thumbnail(
+            State(state),
+            Query(ThumbnailQuery {
+                path: "/private/scan.pdf".to_string(),
+                size: None,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn create_manifest_writes_files_into_directory() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("drop/sub")).unwrap();
+        fs::write(root.join("drop/a.txt"), b"abc").unwrap();
+        fs::write(root.join("drop/sub/b.txt"), b"defg").unwrap();
+
+        let resp = create_manifest(
+            State(state.clone()),
+            Json(ManifestRequest {
+                path: "/drop".to_string(),
+            }),
+        )
+        .await
+        .expect("manifest should succeed");
+
+        assert_eq!(resp.0.file_count, 2);
+        assert_eq!(resp.0.total_size, 7);
+        assert_eq!(
+            resp.0.written,
+            vec!["/drop/MANIFEST.txt", "/drop/manifest.json"]
+        );
+        assert!(root.join("drop/MANIFEST.txt").exists());
+        assert!(root.join("drop/manifest.json").exists());
+
+        let err = create_manifest(
+            State(state),
+            Json(ManifestRequest {
+                path: "/drop/a.txt".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn delete_removes_file_and_index_row() {
         let (state, _tmp, root) = test_state().await;
@@ -846,19 +3032,9 @@ mod tests {
         fs::write(&file_path, b"bye").unwrap();
 
         let indexed = crate::models::IndexedFileRow {
-            id: 0,
-            path: "/remove.txt".to_string(),
-            name: "remove.txt".to_string(),
-            is_dir: false,
             size: Some(3),
-            created_at: None,
-            modified_at: None,
             mime_type: Some("text/plain".to_string()),
-            width: None,
-            height: None,
-            duration: None,
-            metadata_status: "complete".to_string(),
-            indexed_at: now_sqlite_timestamp(),
+            ..crate::models::IndexedFileRow::for_tests("/remove.txt", false)
         };
         crate::db::upsert_file(&state.pool, &indexed)
             .await
@@ -868,6 +3044,8 @@ mod tests {
             State(state.clone()),
             Json(DeleteRequest {
                 path: "/remove.txt".to_string(),
+                expected_mtime: None,
+                expected_size: None,
             }),
         )
         .await
@@ -883,6 +3061,88 @@ mod tests {
         assert_eq!(remaining, 0);
     }
 
+    #[tokio::test]
+    async fn selection_size_totals_files_from_the_index_or_disk() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir_all(root.join("indexed/sub")).unwrap();
+        fs::write(root.join("indexed/sub/a.bin"), b"abc").unwrap();
+        fs::create_dir_all(root.join("loose")).unwrap();
+        fs::write(root.join("loose/b.bin"), b"12345").unwrap();
+        fs::write(root.join("loose/c.bin"), b"1").unwrap();
+        fs::write(root.join("top.bin"), b"1234567").unwrap();
+
+        // The index is trusted for directories it holds, even when stale
+        for (path, is_dir, size) in [
+            ("/indexed", true, None),
+            ("/indexed/sub", true, None),
+            ("/indexed/sub/a.bin", false, Some(100)),
+        ] {
+            let row = crate::models::IndexedFileRow {
+                size,
+                ..crate::models::IndexedFileRow::for_tests(path, is_dir)
+            };
+            crate::db::upsert_file(&state.pool, &row).await.unwrap();
+        }
+
+        let size = |paths: &[&str]| {
+            selection_size(
+                State(state.clone()),
+                Json(SizeRequest {
+                    paths: paths.iter().map(|p| p.to_string()).collect(),
+                }),
+            )
+        };
+        let resp = size(&["/indexed", "/loose", "/top.bin", "/loose/b.bin"])
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.0,
+            SizeResponse {
+                files: 4,
+                bytes: 100 + 6 + 7
+            }
+        );
+
+        let err = size(&["/missing"]).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn delete_refuses_a_file_changed_since_it_was_listed() {
+        let (state, _tmp, root) = test_state().await;
+        let file_path = root.join("report.txt");
+        fs::write(&file_path, b"v1").unwrap();
+        let listed = state.fs.get_entry("/report.txt").unwrap();
+
+        fs::write(&file_path, b"v2 with more").unwrap();
+        let err = delete(
+            State(state.clone()),
+            Json(DeleteRequest {
+                path: "/report.txt".to_string(),
+                expected_mtime: listed.modified,
+                expected_size: listed.size,
+            }),
+        )
+        .await
+        .expect_err("stale delete refused");
+        assert_eq!(err.status, StatusCode::PRECONDITION_FAILED);
+        assert_eq!(err.body.code, Some(crate::api::ErrorCode::Modified));
+        assert!(file_path.exists());
+
+        let current = state.fs.get_entry("/report.txt").unwrap();
+        let _ = delete(
+            State(state),
+            Json(DeleteRequest {
+                path: "/report.txt".to_string(),
+                expected_mtime: current.modified,
+                expected_size: current.size,
+            }),
+        )
+        .await
+        .expect("current delete ok");
+        assert!(!file_path.exists());
+    }
+
     #[tokio::test]
     async fn move_endpoint_moves_and_updates_index() {
         let (state, _tmp, root) = test_state().await;
@@ -893,19 +3153,9 @@ mod tests {
         fs::write(&original, b"move me").unwrap();
 
         let indexed = crate::models::IndexedFileRow {
-            id: 0,
-            path: "/from/file.txt".to_string(),
-            name: "file.txt".to_string(),
-            is_dir: false,
             size: Some(7),
-            created_at: None,
-            modified_at: None,
             mime_type: Some("text/plain".to_string()),
-            width: None,
-            height: None,
-            duration: None,
-            metadata_status: "complete".to_string(),
-            indexed_at: now_sqlite_timestamp(),
+            ..crate::models::IndexedFileRow::for_tests("/from/file.txt", false)
         };
         crate::db::upsert_file(&state.pool, &indexed)
             .await
@@ -917,6 +3167,8 @@ mod tests {
                 from: "/from/file.txt".to_string(),
                 to: "/to".to_string(),
                 overwrite: false,
+                expected_mtime: None,
+                expected_size: None,
             }),
         )
         .await
@@ -952,19 +3204,9 @@ mod tests {
         fs::write(&original, b"copy me").unwrap();
 
         let indexed = crate::models::IndexedFileRow {
-            id: 0,
-            path: "/from/file.txt".to_string(),
-            name: "file.txt".to_string(),
-            is_dir: false,
             size: Some(7),
-            created_at: None,
-            modified_at: None,
             mime_type: Some("text/plain".to_string()),
-            width: None,
-            height: None,
-            duration: None,
-            metadata_status: "complete".to_string(),
-            indexed_at: now_sqlite_timestamp(),
+            ..crate::models::IndexedFileRow::for_tests("/from/file.txt", false)
         };
         crate::db::upsert_file(&state.pool, &indexed)
             .await
@@ -976,6 +3218,8 @@ mod tests {
                 from: "/from/file.txt".to_string(),
                 to: "/to".to_string(),
                 overwrite: false,
+                mode: CopyMode::Copy,
+                checksum: false,
             }),
         )
         .await
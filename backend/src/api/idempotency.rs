@@ -0,0 +1,330 @@
+//! `Idempotency-Key` support for uploads, copies, moves and deletes. A client
+//! that retries a request with the same key (a flaky mobile connection, a
+//! proxy retrying a timed-out request) gets the first response replayed
+//! instead of the work being done twice.
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::State,
+    http::{HeaderValue, Method, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::api::auth::Caller;
+use crate::api::{ApiError, ErrorCode};
+use crate::db::{self, IdempotentResponseRow};
+
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// Set on a response replayed for a retried request
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// How long a key is remembered
+const KEY_TTL_SECS: i64 = 24 * 60 * 60;
+
+const MAX_KEY_LEN: usize = 255;
+
+/// Largest JSON request body read to tell requests apart, and largest
+/// response kept for replay
+const MAX_BUFFERED_BYTES: usize = 1024 * 1024;
+
+pub struct IdempotencyState {
+    pub pool: SqlitePool,
+    /// Keys whose first request is still running
+    in_flight: Mutex<HashSet<String>>,
+}
+
+impl IdempotencyState {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            in_flight: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+/// Releases a key when its first request finishes, however it finishes
+struct InFlight<'a> {
+    state: &'a IdempotencyState,
+    key: String,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.state
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.key);
+    }
+}
+
+/// Whether requests to `path` honour the header
+fn is_covered(method: &Method, path: &str) -> bool {
+    let mutating = matches!(*method, Method::POST | Method::PUT | Method::DELETE);
+    let covered = path.starts_with("/api/files/upload")
+        || matches!(
+            path,
+            "/api/files/copy" | "/api/files/move" | "/api/files/move-batch" | "/api/files/delete"
+        );
+    mutating && covered
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+fn is_json(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+fn replay(stored: IdempotentResponseRow) -> Response {
+    let status = u16::try_from(stored.status)
+        .ok()
+        .and_then(|s| StatusCode::from_u16(s).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.body).into_response();
+    let headers = response.headers_mut();
+    if let Some(content_type) = stored
+        .content_type
+        .and_then(|v| HeaderValue::from_str(&v).ok())
+    {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+/// Replay the stored response for a repeated `Idempotency-Key`, or run the
+/// request and store its response. Keys are per client and kept for a day.
+/// A key reused for a different request is refused with 422, and a retry
+/// while the first request is still running with 409. Server errors are
+/// not stored, so the client can retry them.
+pub async fn idempotency_middleware(
+    State(state): State<Arc<IdempotencyState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(key) = request
+        .headers()
+        .get(IDEMPOTENCY_HEADER)
+        .map(|v| v.to_str().unwrap_or_default().to_string())
+    else {
+        return next.run(request).await;
+    };
+    if !is_covered(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+    if !valid_key(&key) {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("{IDEMPOTENCY_HEADER} must be 1 to {MAX_KEY_LEN} visible ASCII characters"),
+        )
+        .into_response();
+    }
+    let scoped_key = format!("{}:{}", Caller::id_of(request.extensions()), key);
+
+    // Identify the request by method, URI, and for JSON requests the body;
+    // upload bodies are streamed and not read twice
+    let mut fingerprint = Sha256::new();
+    fingerprint.update(request.method().as_str());
+    fingerprint.update(request.uri().to_string());
+    let request = if is_json(&request) {
+        let (parts, body) = request.into_parts();
+        let Ok(bytes) = to_bytes(body, MAX_BUFFERED_BYTES).await else {
+            return ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large")
+                .into_response();
+        };
+        fingerprint.update(&bytes);
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        request
+    };
+    let fingerprint = hex::encode(fingerprint.finalize());
+
+    match db::get_idempotent_response(&state.pool, &scoped_key, KEY_TTL_SECS).await {
+        Ok(Some(stored)) if stored.fingerprint != fingerprint => {
+            return ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("{IDEMPOTENCY_HEADER} was already used for a different request"),
+            )
+            .with_code(ErrorCode::Conflict)
+            .into_response();
+        }
+        Ok(Some(stored)) => return replay(stored),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Idempotency key lookup failed: {}", e),
+    }
+
+    if !state
+        .in_flight
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(scoped_key.clone())
+    {
+        return ApiError::new(
+            StatusCode::CONFLICT,
+            format!("A request with this {IDEMPOTENCY_HEADER} is still in progress"),
+        )
+        .into_response();
+    }
+    let _in_flight = InFlight {
+        state: &state,
+        key: scoped_key.clone(),
+    };
+
+    let response = next.run(request).await;
+    if response.status().is_server_error() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_BUFFERED_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!(
+                "Response too large to keep for {}: {}",
+                IDEMPOTENCY_HEADER,
+                e
+            );
+            return ApiError::internal("Response could not be read").into_response();
+        }
+    };
+    let stored = IdempotentResponseRow {
+        key: scoped_key,
+        fingerprint,
+        status: parts.status.as_u16() as i64,
+        content_type: parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        body: bytes.to_vec(),
+    };
+    if let Err(e) = db::save_idempotent_response(&state.pool, &stored, KEY_TTL_SECS).await {
+        tracing::warn!("Could not store idempotent response: {}", e);
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, middleware, routing::post};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    async fn app() -> (Router, Arc<AtomicUsize>) {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+        let state = Arc::new(IdempotencyState::new(pool));
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new()
+            .route(
+                "/api/files/copy",
+                post(move || {
+                    let calls = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    async move { axum::Json(serde_json::json!({ "calls": calls })) }
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state,
+                idempotency_middleware,
+            ));
+        (app, calls)
+    }
+
+    fn copy(key: Option<&str>, body: &str) -> Request<Body> {
+        copy_as("user:alex", key, body)
+    }
+
+    fn copy_as(caller: &str, key: Option<&str>, body: &str) -> Request<Body> {
+        let mut request = Request::post("/api/files/copy")
+            .header(header::CONTENT_TYPE, "application/json")
+            .extension(Caller {
+                id: caller.to_string(),
+                admin: false,
+            })
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        if let Some(key) = key {
+            request
+                .headers_mut()
+                .insert(IDEMPOTENCY_HEADER, HeaderValue::from_str(key).unwrap());
+        }
+        request
+    }
+
+    #[tokio::test]
+    async fn retried_requests_replay_the_first_response() {
+        let (app, calls) = app().await;
+        let body = r#"{"from":"/a","to":"/b"}"#;
+
+        let first = app.clone().oneshot(copy(Some("k1"), body)).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert!(first.headers().get(REPLAYED_HEADER).is_none());
+
+        let retry = app.clone().oneshot(copy(Some("k1"), body)).await.unwrap();
+        assert_eq!(retry.headers()[REPLAYED_HEADER], "true");
+        let replayed = to_bytes(retry.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&replayed[..], br#"{"calls":1}"#);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The same key for another request is refused
+        let other = app
+            .clone()
+            .oneshot(copy(Some("k1"), r#"{"from":"/c","to":"/b"}"#))
+            .await
+            .unwrap();
+        assert_eq!(other.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Without a key, or with a new one, the work is done again
+        app.clone().oneshot(copy(None, body)).await.unwrap();
+        app.clone().oneshot(copy(Some("k2"), body)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let invalid = app.oneshot(copy(Some("has space"), body)).await.unwrap();
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn keys_are_kept_apart_per_caller() {
+        let (app, calls) = app().await;
+        let body = r#"{"from":"/a","to":"/b"}"#;
+
+        app.clone().oneshot(copy(Some("k1"), body)).await.unwrap();
+        // Another proxy user picking the same key gets their own work done
+        let theirs = app
+            .clone()
+            .oneshot(copy_as("user:sam", Some("k1"), body))
+            .await
+            .unwrap();
+        assert!(theirs.headers().get(REPLAYED_HEADER).is_none());
+        let other = app
+            .oneshot(copy_as(
+                "user:kim",
+                Some("k1"),
+                r#"{"from":"/c","to":"/b"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,260 @@
+//! Live directory updates over server-sent events. A client lists the
+//! directories it is showing and gets a `change` event, with the entry as
+//! it now is, whenever something in one of them is created, modified,
+//! deleted, or renamed, instead of polling for fresh listings.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, Stream};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::api::notes::{existing_path, flag_notes};
+use crate::api::{ApiError, AppState};
+use crate::models::FileEntry;
+use crate::services::filesystem::is_hidden_path;
+use crate::services::webhooks::{FileEvent, FileEventKind};
+
+/// Most directories one connection may watch
+const MAX_WATCHED_DIRS: usize = 32;
+
+#[derive(Debug, Serialize)]
+pub struct ChangeEvent {
+    pub event: FileEventKind,
+    pub path: String,
+    /// Previous path of a rename or move
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// The entry as it is now; absent once it is gone
+    pub entry: Option<FileEntry>,
+}
+
+fn bad_request(message: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, message.into())
+}
+
+/// Directory holding a root-relative path
+fn parent_dir(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+struct Subscription {
+    state: Arc<AppState>,
+    dirs: HashSet<String>,
+    show_hidden: bool,
+    receiver: tokio::sync::broadcast::Receiver<FileEvent>,
+}
+
+impl Subscription {
+    fn watches(&self, event: &FileEvent) -> bool {
+        let visible = |path: &str| self.show_hidden || !is_hidden_path(path);
+        let touches = |path: &str| visible(path) && self.dirs.contains(parent_dir(path));
+        touches(&event.path) || event.from.as_deref().is_some_and(touches)
+    }
+
+    async fn change(&self, event: FileEvent) -> ChangeEvent {
+        let entry = match event.event {
+            FileEventKind::Deleted => None,
+            _ => match self.state.fs.get_entry(&event.path) {
+                Ok(entry) => {
+                    let mut entries = [entry];
+                    flag_notes(&self.state, &mut entries).await;
+                    let [entry] = entries;
+                    Some(entry)
+                }
+                Err(_) => None,
+            },
+        };
+        ChangeEvent {
+            event: event.event,
+            path: event.path,
+            from: event.from,
+            entry,
+        }
+    }
+
+    /// The next event for this client, or `None` once the server shuts down
+    async fn next(&mut self) -> Option<Event> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if self.watches(&event) => {
+                    let change = self.change(event).await;
+                    match Event::default().event("change").json_data(&change) {
+                        Ok(event) => return Some(event),
+                        Err(_) => continue,
+                    }
+                }
+                Ok(_) => continue,
+                // Changes were missed; the client should reload its listings
+                Err(RecvError::Lagged(_)) => {
+                    return Some(Event::default().event("resync").data(""));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Stream changes in the directories named by repeated `path` parameters
+pub async fn directory_events(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let mut dirs = HashSet::new();
+    let mut show_hidden = state.show_hidden;
+    for (key, value) in params {
+        match key.as_str() {
+            "path" => {
+                let dir = existing_path(&state, &value)?;
+                if !state.fs.resolve_path(&dir).is_ok_and(|p| p.is_dir()) {
+                    return Err(bad_request(format!("{dir} is not a directory")));
+                }
+                dirs.insert(dir);
+            }
+            "show_hidden" => show_hidden = value == "true" || value == "1",
+            _ => {}
+        }
+    }
+    if dirs.is_empty() {
+        return Err(bad_request("Name at least one directory to watch"));
+    }
+    if dirs.len() > MAX_WATCHED_DIRS {
+        return Err(bad_request(format!(
+            "At most {MAX_WATCHED_DIRS} directories can be watched at once"
+        )));
+    }
+
+    let subscription = Subscription {
+        receiver: state.live.subscribe(),
+        state,
+        dirs,
+        show_hidden,
+    };
+    let events = stream::unfold(subscription, |mut subscription| async move {
+        let event = subscription.next().await?;
+        Some((Ok(event), subscription))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::webhooks::EventSource;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn test_state() -> (Arc<AppState>, tempfile::TempDir) {
+        let tmp = tempdir().expect("tempdir created");
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("photos")).unwrap();
+        fs::write(root.join("photos/beach.jpg"), b"jpeg").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(root, pool));
+
+        (state, tmp)
+    }
+
+    fn event(kind: FileEventKind, path: &str, from: Option<&str>) -> FileEvent {
+        FileEvent::new(kind, path, from, None, EventSource::Api)
+    }
+
+    #[test]
+    fn parent_dirs() {
+        assert_eq!(parent_dir("/a.txt"), "/");
+        assert_eq!(parent_dir("/photos/beach.jpg"), "/photos");
+    }
+
+    #[tokio::test]
+    async fn subscribers_get_changes_in_watched_directories_only() {
+        let (state, _tmp) = test_state().await;
+        let mut subscription = Subscription {
+            receiver: state.live.subscribe(),
+            state: state.clone(),
+            dirs: HashSet::from(["/photos".to_string()]),
+            show_hidden: false,
+        };
+
+        assert!(subscription.watches(&event(FileEventKind::Created, "/photos/beach.jpg", None)));
+        assert!(!subscription.watches(&event(FileEventKind::Created, "/beach.jpg", None)));
+        assert!(!subscription.watches(&event(FileEventKind::Created, "/photos/.cache", None)));
+        assert!(!subscription.watches(&event(
+            FileEventKind::Created,
+            "/photos/2024/beach.jpg",
+            None
+        )));
+        // Moving something out of a watched directory is a change to it
+        assert!(subscription.watches(&event(
+            FileEventKind::Renamed,
+            "/beach.jpg",
+            Some("/photos/beach.jpg")
+        )));
+
+        let change = subscription
+            .change(event(FileEventKind::Modified, "/photos/beach.jpg", None))
+            .await;
+        let entry = change.entry.expect("entry is looked up");
+        assert_eq!(entry.name, "beach.jpg");
+        assert_eq!(entry.size, Some(4));
+        let change = subscription
+            .change(event(FileEventKind::Deleted, "/photos/beach.jpg", None))
+            .await;
+        assert!(change.entry.is_none());
+
+        state
+            .live
+            .publish(event(FileEventKind::Created, "/elsewhere.txt", None));
+        state
+            .live
+            .publish(event(FileEventKind::Modified, "/photos/beach.jpg", None));
+        assert!(subscription.next().await.is_some());
+        assert!(subscription.receiver.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_existing_directories_can_be_watched() {
+        let (state, _tmp) = test_state().await;
+        let watch = |paths: &[&str]| {
+            directory_events(
+                State(state.clone()),
+                Query(
+                    paths
+                        .iter()
+                        .map(|p| ("path".to_string(), p.to_string()))
+                        .collect(),
+                ),
+            )
+        };
+
+        assert!(watch(&["/photos", "/"]).await.is_ok());
+        assert_eq!(
+            watch(&[]).await.err().unwrap().status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            watch(&["/photos/beach.jpg"]).await.err().unwrap().status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            watch(&["/missing"]).await.err().unwrap().status,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Advisory file locks (see `services::locks`). Overwriting a locked file
+//! through an upload requires the lock's token in `X-Lock-Token`.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::files::SuccessResponse;
+use crate::api::notes::existing_path;
+use crate::api::{ApiError, AppState};
+use crate::services::locks::{DEFAULT_LOCK_SECS, FileLock};
+
+/// Header carrying the token of a lock the client holds
+pub const LOCK_TOKEN_HEADER: &str = "x-lock-token";
+
+/// Longest owner description accepted, in characters
+const MAX_OWNER_CHARS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct LockQuery {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LockStatusResponse {
+    pub path: String,
+    pub lock: Option<FileLock>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LockRequest {
+    pub path: String,
+    /// Name shown to others who run into the lock
+    pub owner: Option<String>,
+    /// Lifetime in seconds; defaults to five minutes
+    pub ttl_secs: Option<u64>,
+    /// Token of a lock already held, to refresh it
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LockResponse {
+    #[serde(flatten)]
+    pub lock: FileLock,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnlockRequest {
+    pub path: String,
+    pub token: String,
+}
+
+/// `423 Locked`, naming who holds the lock and until when
+pub(crate) fn locked(lock: &FileLock) -> ApiError {
+    let holder = lock.owner.as_deref().unwrap_or("someone else");
+    ApiError::new(
+        StatusCode::LOCKED,
+        format!(
+            "{} is locked by {holder} until {}",
+            lock.path,
+            lock.expires_at.to_rfc3339()
+        ),
+    )
+}
+
+/// Token of the lock the client says it holds, from `X-Lock-Token`
+pub(crate) fn lock_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(LOCK_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// The current lock on a file, if any
+pub async fn lock_status(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LockQuery>,
+) -> Result<Json<LockStatusResponse>, ApiError> {
+    let path = existing_path(&state, &query.path)?;
+    let lock = state.locks.get(&path);
+    Ok(Json(LockStatusResponse { path, lock }))
+}
+
+/// Take out or refresh a lock on a file
+pub async fn lock(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<LockRequest>,
+) -> Result<Json<LockResponse>, ApiError> {
+    let path = existing_path(&state, &req.path)?;
+    let owner = req
+        .owner
+        .map(|owner| {
+            owner
+                .trim()
+                .chars()
+                .take(MAX_OWNER_CHARS)
+                .collect::<String>()
+        })
+        .filter(|owner| !owner.is_empty());
+    let lock = state
+        .locks
+        .lock(
+            &path,
+            owner,
+            req.ttl_secs.unwrap_or(DEFAULT_LOCK_SECS),
+            req.token.as_deref(),
+        )
+        .map_err(|held| locked(&held))?;
+    let token = lock.token.clone();
+    Ok(Json(LockResponse { lock, token }))
+}
+
+/// Release a lock
+pub async fn unlock(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<UnlockRequest>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let path = existing_path(&state, &req.path)?;
+    let released = state
+        .locks
+        .unlock(&path, &req.token)
+        .map_err(|held| locked(&held))?;
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: Some(path),
+        message: Some(if released { "Unlocked" } else { "Not locked" }.to_string()),
+        performed: Some(released),
+        sync: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::Request};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    async fn test_state() -> (Arc<AppState>, tempfile::TempDir) {
+        let tmp = tempdir().expect("tempdir created");
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("app.conf"), b"port = 80").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(root, pool));
+
+        (state, tmp)
+    }
+
+    #[tokio::test]
+    async fn locked_files_cannot_be_overwritten_without_the_token() {
+        let (state, _tmp) = test_state().await;
+        let held = lock(
+            State(state.clone()),
+            Json(LockRequest {
+                path: "app.conf".to_string(),
+                owner: Some("  ana ".to_string()),
+                ttl_secs: None,
+                token: None,
+            }),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(held.lock.path, "/app.conf");
+        assert_eq!(held.lock.owner.as_deref(), Some("ana"));
+
+        let status = lock_status(
+            State(state.clone()),
+            Query(LockQuery {
+                path: "/app.conf".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        let json = serde_json::to_value(&status.0).unwrap();
+        assert_eq!(json["lock"]["owner"], "ana");
+        assert!(json["lock"].get("token").is_none());
+
+        let app = Router::new()
+            .route(
+                "/upload",
+                axum::routing::post(crate::api::files::upload_root),
+            )
+            .with_state(state.clone());
+        let boundary = "BOUNDARYLOCK";
+        let upload = |token: Option<&str>| {
+            let body = format!(
+                "--{boundary}\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"app.conf\"\r\n\r\n\
+                 port = 8080\r\n\
+                 --{boundary}--"
+            );
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/upload?conflict=overwrite")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                );
+            if let Some(token) = token {
+                request = request.header(LOCK_TOKEN_HEADER, token);
+            }
+            request.body(Body::from(body)).unwrap()
+        };
+
+        let response = app.clone().oneshot(upload(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::LOCKED);
+        let response = app.clone().oneshot(upload(Some("guess"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::LOCKED);
+        let response = app.oneshot(upload(Some(&held.token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            fs::read_to_string(state.fs.root().join("app.conf")).unwrap(),
+            "port = 8080"
+        );
+
+        let err = unlock(
+            State(state.clone()),
+            Json(UnlockRequest {
+                path: "/app.conf".to_string(),
+                token: "guess".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::LOCKED);
+        let resp = unlock(
+            State(state.clone()),
+            Json(UnlockRequest {
+                path: "/app.conf".to_string(),
+                token: held.token,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.performed, Some(true));
+        assert!(state.locks.get("/app.conf").is_none());
+    }
+}
@@ -0,0 +1,364 @@
+//! Links for players that cannot send the session cookie: signed URLs for
+//! `<video>` and `<img>` elements behind proxies that strip cookies, and M3U
+//! playlists of a folder's media whose entries are signed download URLs, so
+//! a folder can be handed straight to VLC.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::{ApiError, AppState, AuthState};
+use crate::db;
+use crate::models::FileEntry;
+use crate::services::FsError;
+use crate::services::filesystem::is_hidden_name;
+
+/// How long the links in a playlist keep working, long enough to play
+/// through a season of episodes
+pub const PLAYLIST_LINK_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Lifetime of a signed URL when none is asked for, and the longest allowed
+pub const SIGNED_URL_TTL: Duration = Duration::from_secs(15 * 60);
+pub const MAX_SIGNED_URL_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct MediaState {
+    pub app: Arc<AppState>,
+    pub auth: Arc<AuthState>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaylistQuery {
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignQuery {
+    pub path: String,
+    /// Seconds the URL stays valid
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedUrlResponse {
+    pub url: String,
+    pub thumbnail_url: String,
+    /// Unix time the URLs stop working
+    pub expires_at: i64,
+}
+
+fn fs_error(e: FsError) -> ApiError {
+    ApiError::from(&e)
+}
+
+/// Unix time `ttl` from now
+fn expiry(ttl: Duration) -> i64 {
+    chrono::Utc::now().timestamp() + ttl.as_secs() as i64
+}
+
+fn is_media(entry: &FileEntry) -> bool {
+    !entry.is_dir
+        && entry
+            .mime_type
+            .as_deref()
+            .is_some_and(|mime| mime.starts_with("audio/") || mime.starts_with("video/"))
+}
+
+/// `scheme://host` the client reached the server at, so playlist entries
+/// work once the playlist is saved and opened elsewhere
+fn request_origin(headers: &HeaderMap) -> String {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let Some(host) = header("x-forwarded-host").or_else(|| header(header::HOST.as_str())) else {
+        return String::new();
+    };
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    format!("{scheme}://{host}")
+}
+
+/// Time-limited URLs for downloading a file and its thumbnail without a
+/// session
+pub async fn sign(
+    State(state): State<Arc<MediaState>>,
+    Query(query): Query<SignQuery>,
+) -> Result<Json<SignedUrlResponse>, ApiError> {
+    let resolved = state.app.fs.resolve_path(&query.path).map_err(fs_error)?;
+    if resolved.is_dir() {
+        return Err(fs_error(FsError::NotAFile(query.path)));
+    }
+    let path = state.app.fs.relative_path(&resolved);
+    let ttl = query
+        .expires_in
+        .map_or(SIGNED_URL_TTL, Duration::from_secs)
+        .min(MAX_SIGNED_URL_TTL);
+
+    let expires_at = expiry(ttl);
+    let signed = state.auth.signed_query(&path, expires_at);
+    Ok(Json(SignedUrlResponse {
+        url: format!("/api/files/download?{signed}"),
+        thumbnail_url: format!("/api/files/thumbnail?{signed}"),
+        expires_at,
+    }))
+}
+
+/// M3U8 playlist of the audio and video files directly inside a folder, in
+/// name order. Each entry is a download link signed for
+/// `PLAYLIST_LINK_TTL`.
+pub async fn playlist(
+    State(state): State<Arc<MediaState>>,
+    Query(query): Query<PlaylistQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let mut entries = state
+        .app
+        .fs
+        .list_directory(&query.path, true)
+        .map_err(fs_error)?;
+    entries.retain(|e| is_media(e) && (state.app.show_hidden || !is_hidden_name(&e.name)));
+
+    let paths: Vec<String> = entries.iter().map(|e| e.path.clone()).collect();
+    let indexed: HashMap<String, _> = db::get_metadata_for_paths(&state.app.pool, &paths)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|row| (row.path.clone(), row))
+        .collect();
+
+    let origin = request_origin(&headers);
+    let expires_at = expiry(PLAYLIST_LINK_TTL);
+    let mut body = String::from("#EXTM3U\n");
+    for entry in &entries {
+        let row = indexed.get(&entry.path);
+        let seconds = row
+            .and_then(|r| r.duration)
+            .map_or(-1, |d| d.round() as i64);
+        let label = match row.map(|r| (r.artist.as_deref(), r.title.as_deref())) {
+            Some((Some(artist), Some(title))) => format!("{artist} - {title}"),
+            Some((None, Some(title))) => title.to_string(),
+            _ => entry.name.clone(),
+        };
+        let label = label.replace(['\r', '\n'], " ");
+        let query = state.auth.signed_query(&entry.path, expires_at);
+        body.push_str(&format!(
+            "#EXTINF:{seconds},{label}\n{origin}/api/files/download?{query}\n"
+        ));
+    }
+
+    let folder = query
+        .path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("filex");
+    let disposition = format!(
+        "attachment; filename*=UTF-8''{}.m3u8",
+        utf8_percent_encode(folder, NON_ALPHANUMERIC)
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("audio/x-mpegurl; charset=utf-8"),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition)
+                    .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthConfig;
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::get,
+    };
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    async fn test_state() -> (Arc<MediaState>, tempfile::TempDir, std::path::PathBuf) {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+
+        let auth = Arc::new(AuthState::new(AuthConfig {
+            enabled: true,
+            password: Some("secret".to_string()),
+            session_timeout_secs: 60,
+            cookie_name: "fm_session".to_string(),
+            oidc: None,
+            proxy_header: None,
+            trusted_proxies: Vec::new(),
+            admin_users: Vec::new(),
+        }));
+        let state = Arc::new(MediaState {
+            app: Arc::new(AppState::for_tests(root.clone(), pool)),
+            auth,
+        });
+        (state, tmp, root)
+    }
+
+    /// Status of a cookieless GET through the auth middleware
+    async fn status_without_session(state: &MediaState, uri: &str) -> StatusCode {
+        Router::new()
+            .route("/api/files/download", get(|| async { StatusCode::OK }))
+            .route("/api/files/thumbnail", get(|| async { StatusCode::OK }))
+            .route("/api/files/other", get(|| async { StatusCode::OK }))
+            .layer(middleware::from_fn_with_state(
+                state.auth.clone(),
+                crate::api::auth::auth_middleware,
+            ))
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn signed_urls_work_until_they_expire() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir(root.join("photos")).unwrap();
+        fs::write(root.join("photos/cat.jpg"), b"jpeg").unwrap();
+        let sign_for = |path: &str, expires_in: Option<u64>| {
+            sign(
+                State(state.clone()),
+                Query(SignQuery {
+                    path: path.to_string(),
+                    expires_in,
+                }),
+            )
+        };
+
+        let Json(signed) = sign_for("/photos/cat.jpg", None).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        assert!((signed.expires_at - now - 15 * 60).abs() <= 1);
+        assert_eq!(
+            status_without_session(&state, &signed.url).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status_without_session(&state, &format!("{}&size=256", signed.thumbnail_url)).await,
+            StatusCode::OK
+        );
+        let tampered = signed.url.replace("cat", "dog");
+        assert_eq!(
+            status_without_session(&state, &tampered).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // Lifetimes are capped
+        let Json(long) = sign_for("/photos/cat.jpg", Some(365 * 24 * 3600))
+            .await
+            .unwrap();
+        assert!(long.expires_at <= now + MAX_SIGNED_URL_TTL.as_secs() as i64 + 1);
+
+        let expired = format!(
+            "/api/files/download?{}",
+            state.auth.signed_query("/photos/cat.jpg", now - 1)
+        );
+        assert_eq!(
+            status_without_session(&state, &expired).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert_eq!(
+            sign_for("/photos", None).await.unwrap_err().status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            sign_for("/missing.jpg", None).await.unwrap_err().status,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn playlist_links_play_without_a_session() {
+        let (state, _tmp, root) = test_state().await;
+        fs::create_dir(root.join("Album")).unwrap();
+        for name in ["02 Second.mp3", "01 First.flac", "cover.jpg", ".hidden.mp3"] {
+            fs::write(root.join("Album").join(name), b"").unwrap();
+        }
+        db::upsert_file(
+            &state.app.pool,
+            &crate::models::IndexedFileRow {
+                size: Some(0),
+                mime_type: Some("audio/flac".to_string()),
+                duration: Some(184.4),
+                indexed_at: String::new(),
+                artist: Some("Band".to_string()),
+                title: Some("First".to_string()),
+                ..crate::models::IndexedFileRow::for_tests("/Album/01 First.flac", false)
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("nas.local:3000"));
+        let response = playlist(
+            State(state.clone()),
+            Query(PlaylistQuery {
+                path: "/Album".to_string(),
+            }),
+            headers,
+        )
+        .await
+        .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 5, "{body}");
+        assert_eq!(lines[0], "#EXTM3U");
+        assert_eq!(lines[1], "#EXTINF:184,Band - First");
+        assert!(lines[2].starts_with(
+            "http://nas.local:3000/api/files/download?path=%2FAlbum%2F01%20First%2Eflac&expires="
+        ));
+        assert_eq!(lines[3], "#EXTINF:-1,02 Second.mp3");
+
+        let link = lines[2].trim_start_matches("http://nas.local:3000");
+        assert_eq!(status_without_session(&state, link).await, StatusCode::OK);
+        let other_file = link.replace("01%20First%2Eflac", "02%20Second%2Emp3");
+        assert_eq!(
+            status_without_session(&state, &other_file).await,
+            StatusCode::UNAUTHORIZED
+        );
+        let other_route = link.replace("/download", "/other");
+        assert_eq!(
+            status_without_session(&state, &other_route).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
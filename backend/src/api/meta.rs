@@ -0,0 +1,273 @@
+//! Star ratings and other custom fields people attach to files ("status:
+//! final", "client: acme"). Fields are plain text keyed by path, follow the
+//! file through renames and can be used to filter and sort search results.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::api::notes::existing_path;
+use crate::api::{ApiError, AppState};
+use crate::db::{self, MetaFilter};
+
+/// Longest field name accepted, in characters
+const MAX_KEY_CHARS: usize = 64;
+/// Longest field value accepted, in characters
+const MAX_VALUE_CHARS: usize = 1000;
+/// Field holding the star rating, which must be a whole number of stars
+const RATING_KEY: &str = "rating";
+const MAX_RATING: u8 = 5;
+
+#[derive(Debug, Deserialize)]
+pub struct MetaQuery {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetaResponse {
+    pub path: String,
+    pub meta: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMetaRequest {
+    pub path: String,
+    /// Fields to set; `null` removes a field
+    pub set: HashMap<String, Option<String>>,
+}
+
+fn bad_request(message: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, message.into())
+}
+
+/// Field names are short lowercase identifiers so they read well in filters
+pub(crate) fn field_key(key: &str) -> Result<&str, ApiError> {
+    let key = key.trim();
+    let valid = !key.is_empty()
+        && key.chars().count() <= MAX_KEY_CHARS
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if valid {
+        Ok(key)
+    } else {
+        Err(bad_request(format!(
+            "Invalid field name '{key}': use up to {MAX_KEY_CHARS} lowercase letters, digits, '_' or '-'"
+        )))
+    }
+}
+
+fn field_value<'a>(key: &str, value: &'a str) -> Result<&'a str, ApiError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(bad_request(format!(
+            "Field '{key}' cannot be empty; send null to remove it"
+        )));
+    }
+    if value.chars().count() > MAX_VALUE_CHARS {
+        return Err(bad_request(format!(
+            "Field '{key}' is longer than {MAX_VALUE_CHARS} characters"
+        )));
+    }
+    if key == RATING_KEY && !matches!(value.parse::<u8>(), Ok(1..=MAX_RATING)) {
+        return Err(bad_request(format!(
+            "Rating must be a whole number from 1 to {MAX_RATING}"
+        )));
+    }
+    Ok(value)
+}
+
+/// Parse search conditions like `rating>=4,status=final,reviewed`: a bare
+/// name requires the field to be set, `=` matches the value exactly and `>=`
+/// or `<=` compare numerically
+pub(crate) fn parse_filters(raw: &str) -> Result<Vec<MetaFilter>, ApiError> {
+    let mut filters = Vec::new();
+    for condition in raw.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let Some(at) = condition.find(['<', '>', '=']) else {
+            filters.push(MetaFilter::Exists(field_key(condition)?.to_string()));
+            continue;
+        };
+        let key = field_key(&condition[..at])?.to_string();
+        let rest = &condition[at..];
+        let number = |value: &str| {
+            value.trim().parse::<f64>().map_err(|_| {
+                bad_request(format!(
+                    "Expected a number in field condition '{condition}'"
+                ))
+            })
+        };
+        let filter = if let Some(value) = rest.strip_prefix(">=") {
+            MetaFilter::AtLeast(key, number(value)?)
+        } else if let Some(value) = rest.strip_prefix("<=") {
+            MetaFilter::AtMost(key, number(value)?)
+        } else if let Some(value) = rest.strip_prefix('=') {
+            MetaFilter::Equals(key, value.trim().to_string())
+        } else {
+            return Err(bad_request(format!(
+                "Invalid field condition '{condition}'"
+            )));
+        };
+        filters.push(filter);
+    }
+    Ok(filters)
+}
+
+/// Custom fields of a file or directory
+pub async fn get_meta(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<MetaQuery>,
+) -> Result<Json<MetaResponse>, ApiError> {
+    let path = existing_path(&state, &query.path)?;
+    let meta = db::get_meta(&state.pool, &path)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(MetaResponse { path, meta }))
+}
+
+/// Set or remove custom fields of a file or directory
+pub async fn update_meta(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<UpdateMetaRequest>,
+) -> Result<Json<MetaResponse>, ApiError> {
+    let path = existing_path(&state, &req.path)?;
+    let mut changes = Vec::with_capacity(req.set.len());
+    for (key, value) in &req.set {
+        let key = field_key(key)?;
+        let value = value.as_deref().map(|v| field_value(key, v)).transpose()?;
+        changes.push((key.to_string(), value.map(str::to_string)));
+    }
+
+    db::update_meta(&state.pool, &path, &changes)
+        .await
+        .map_err(ApiError::internal)?;
+    let meta = db::get_meta(&state.pool, &path)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(MetaResponse { path, meta }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn test_state() -> (Arc<AppState>, tempfile::TempDir) {
+        let tmp = tempdir().expect("tempdir created");
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("cut.mov"), b"data").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(root, pool));
+
+        (state, tmp)
+    }
+
+    fn update(path: &str, set: &[(&str, Option<&str>)]) -> Json<UpdateMetaRequest> {
+        Json(UpdateMetaRequest {
+            path: path.to_string(),
+            set: set
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.map(str::to_string)))
+                .collect(),
+        })
+    }
+
+    #[tokio::test]
+    async fn fields_are_set_removed_and_follow_renames() {
+        let (state, _tmp) = test_state().await;
+
+        let resp = update_meta(
+            State(state.clone()),
+            update(
+                "cut.mov",
+                &[("rating", Some("4")), ("status", Some(" final "))],
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.path, "/cut.mov");
+        assert_eq!(resp.0.meta["rating"], "4");
+        assert_eq!(resp.0.meta["status"], "final");
+
+        let resp = update_meta(
+            State(state.clone()),
+            update("/cut.mov", &[("status", None)]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.meta.len(), 1);
+
+        db::rename_path(&state.pool, "/cut.mov", "/edit.mov", "edit.mov")
+            .await
+            .unwrap();
+        fs::rename(
+            state.fs.root().join("cut.mov"),
+            state.fs.root().join("edit.mov"),
+        )
+        .unwrap();
+        let resp = get_meta(
+            State(state.clone()),
+            Query(MetaQuery {
+                path: "/edit.mov".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.meta.get("rating").map(String::as_str), Some("4"));
+    }
+
+    #[tokio::test]
+    async fn invalid_fields_are_rejected() {
+        let (state, _tmp) = test_state().await;
+        for set in [
+            [("rating", Some("6"))],
+            [("rating", Some("4.5"))],
+            [("Status", Some("final"))],
+            [("status", Some("  "))],
+        ] {
+            let err = update_meta(State(state.clone()), update("/cut.mov", &set))
+                .await
+                .unwrap_err();
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        }
+        assert!(
+            db::get_meta(&state.pool, "/cut.mov")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn parses_filter_conditions() {
+        assert_eq!(
+            parse_filters("rating>=4, status=final ,reviewed,").unwrap(),
+            vec![
+                MetaFilter::AtLeast("rating".to_string(), 4.0),
+                MetaFilter::Equals("status".to_string(), "final".to_string()),
+                MetaFilter::Exists("reviewed".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_filters("rating<=2").unwrap(),
+            vec![MetaFilter::AtMost("rating".to_string(), 2.0)]
+        );
+        assert!(parse_filters("rating>=high").is_err());
+        assert!(parse_filters("rating>4").is_err());
+        assert!(parse_filters("Bad Key").is_err());
+    }
+}
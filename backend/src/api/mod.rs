@@ -1,10 +1,62 @@
 pub mod auth;
 pub mod browse;
+pub mod error;
+pub mod export;
 pub mod files;
+pub mod idempotency;
+pub mod live;
+pub mod locks;
+pub mod media;
+pub mod meta;
+pub mod notes;
+pub mod remotes;
 pub mod search;
+pub mod similar;
 pub mod sort;
+pub mod sync_jobs;
 pub mod system;
+pub mod timeouts;
+pub mod transfers;
+pub mod usage;
+pub mod views;
 
 pub use auth::AuthState;
-pub use browse::{AppState, ErrorResponse};
+pub use browse::AppState;
+pub use error::{ApiError, ErrorCode, ErrorResponse};
 pub use sort::{SortField, SortOrder};
+
+#[cfg(test)]
+impl AppState {
+    /// State over the directory `root` and an initialized `pool`, with every
+    /// optional service off. Tests override what they need with struct
+    /// update syntax.
+    pub fn for_tests(root: std::path::PathBuf, pool: sqlx::SqlitePool) -> Self {
+        use crate::services::{
+            DedupStore, DownloadThrottle, FileTemplates, FilesystemService, HookService,
+            LiveUpdates, LockManager, Readahead, SearchService, StorageGuard, UploadTracker,
+            VirusScanner, WebhookService,
+        };
+        use std::sync::Arc;
+
+        Self {
+            fs: FilesystemService::new(root.clone()),
+            backend: Arc::new(FilesystemService::new(root)),
+            pool,
+            search: Arc::new(SearchService::new()),
+            storage: StorageGuard::new(std::env::temp_dir(), 0),
+            root_storage: StorageGuard::new(std::env::temp_dir(), 0),
+            show_hidden: false,
+            webhooks: Arc::new(WebhookService::disabled()),
+            hooks: Arc::new(HookService::disabled()),
+            uploads: UploadTracker::new(),
+            downloads: DownloadThrottle::unlimited(),
+            readahead: Readahead::disabled(),
+            locks: LockManager::new(),
+            live: LiveUpdates::new(),
+            templates: FileTemplates::disabled(),
+            allow_links: false,
+            scanner: VirusScanner::disabled(),
+            dedup: Arc::new(DedupStore::disabled()),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Notes people sharing a root leave on files ("final version, don't
+//! touch"). Notes are searchable with the file's path and follow it through
+//! renames and moves.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::api::files::SuccessResponse;
+use crate::api::{ApiError, AppState, ErrorResponse};
+use crate::db::{self, FileNoteRow};
+use crate::models::FileEntry;
+use crate::services::filesystem::FsError;
+
+/// Longest note accepted, in characters
+const MAX_NOTE_CHARS: usize = 4000;
+
+#[derive(Debug, Deserialize)]
+pub struct NotesQuery {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotesResponse {
+    pub path: String,
+    pub notes: Vec<FileNoteRow>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNoteRequest {
+    pub path: String,
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNoteRequest {
+    pub body: String,
+}
+
+fn note_not_found(id: i64) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, format!("Note {id} not found"))
+}
+
+/// Index path of an existing file or directory
+pub(crate) fn existing_path(state: &AppState, path: &str) -> Result<String, ApiError> {
+    let resolved = state.fs.resolve_path(path).map_err(|e| {
+        let status = match &e {
+            FsError::NotFound(_) => StatusCode::NOT_FOUND,
+            FsError::PermissionDenied(_) | FsError::PathEscape => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::with_body(status, ErrorResponse::from(&e))
+    })?;
+    Ok(state.fs.relative_path(&resolved))
+}
+
+/// Note text without surrounding whitespace, if it is acceptable
+fn note_body(body: &str) -> Result<&str, ApiError> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Note cannot be empty",
+        ));
+    }
+    if body.chars().count() > MAX_NOTE_CHARS {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Note is longer than {MAX_NOTE_CHARS} characters"),
+        ));
+    }
+    Ok(body)
+}
+
+/// Make the path findable by its notes' current text
+async fn refresh_search(state: &AppState, path: &str) {
+    if let Err(e) = state.search.refresh_entry(&state.pool, path).await {
+        warn!("Failed to update search text for {}: {}", path, e);
+    }
+}
+
+/// Mark the entries someone left notes on
+pub async fn flag_notes(state: &AppState, entries: &mut [FileEntry]) {
+    let paths: Vec<String> = entries.iter().map(|e| e.path.clone()).collect();
+    match db::paths_with_notes(&state.pool, &paths).await {
+        Ok(noted) => {
+            for entry in entries {
+                entry.has_notes = noted.contains(&entry.path);
+            }
+        }
+        Err(e) => warn!("Failed to look up notes: {}", e),
+    }
+}
+
+/// Notes on a file or directory, oldest first
+pub async fn list_notes(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<NotesQuery>,
+) -> Result<Json<NotesResponse>, ApiError> {
+    let path = existing_path(&state, &query.path)?;
+    let notes = db::list_notes(&state.pool, &path)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(NotesResponse { path, notes }))
+}
+
+/// Leave a note on a file or directory
+pub async fn create_note(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateNoteRequest>,
+) -> Result<Json<FileNoteRow>, ApiError> {
+    let path = existing_path(&state, &req.path)?;
+    let body = note_body(&req.body)?;
+    let note = db::create_note(&state.pool, &path, body)
+        .await
+        .map_err(ApiError::internal)?;
+    refresh_search(&state, &path).await;
+    Ok(Json(note))
+}
+
+/// Replace the text of a note
+pub async fn update_note(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Json(req): Json<UpdateNoteRequest>,
+) -> Result<Json<FileNoteRow>, ApiError> {
+    let body = note_body(&req.body)?;
+    let note = db::update_note(&state.pool, id, body)
+        .await
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| note_not_found(id))?;
+    refresh_search(&state, &note.path).await;
+    Ok(Json(note))
+}
+
+/// Remove a note
+pub async fn delete_note(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let note = db::delete_note(&state.pool, id)
+        .await
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| note_not_found(id))?;
+    refresh_search(&state, &note.path).await;
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: Some(note.path),
+        message: Some("Note deleted".to_string()),
+        performed: None,
+        sync: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn test_state() -> (Arc<AppState>, tempfile::TempDir) {
+        let tmp = tempdir().expect("tempdir created");
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("cut.mov"), b"data").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(root, pool));
+
+        (state, tmp)
+    }
+
+    #[tokio::test]
+    async fn notes_are_searchable_and_follow_renames() {
+        let (state, _tmp) = test_state().await;
+        sqlx::query("INSERT INTO indexed_files (path, name) VALUES ('/cut.mov', 'cut.mov')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        state.search.rebuild_from_db(&state.pool).await.unwrap();
+
+        let note = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                path: "cut.mov".to_string(),
+                body: "  final version, don't touch ".to_string(),
+            }),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(note.path, "/cut.mov");
+        assert_eq!(note.body, "final version, don't touch");
+        assert_eq!(state.search.search("final version").await.len(), 1);
+
+        let resp = update_note(
+            State(state.clone()),
+            Path(note.id),
+            Json(UpdateNoteRequest {
+                body: "needs color grading".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.body, "needs color grading");
+        assert!(state.search.search("final").await.is_empty());
+        assert_eq!(state.search.search("grading").await.len(), 1);
+
+        db::rename_path(&state.pool, "/cut.mov", "/edit.mov", "edit.mov")
+            .await
+            .unwrap();
+        fs::rename(
+            state.fs.root().join("cut.mov"),
+            state.fs.root().join("edit.mov"),
+        )
+        .unwrap();
+        let resp = list_notes(
+            State(state.clone()),
+            Query(NotesQuery {
+                path: "/edit.mov".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.notes.len(), 1);
+        assert_eq!(resp.0.notes[0].id, note.id);
+
+        let resp = delete_note(State(state.clone()), Path(note.id))
+            .await
+            .unwrap();
+        assert_eq!(resp.0.path.as_deref(), Some("/edit.mov"));
+        let err = delete_note(State(state), Path(note.id)).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn notes_must_have_text_and_a_target() {
+        let (state, _tmp) = test_state().await;
+        let create = |path: &str, body: &str| {
+            create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    path: path.to_string(),
+                    body: body.to_string(),
+                }),
+            )
+        };
+
+        assert_eq!(
+            create("/cut.mov", "   ").await.unwrap_err().status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            create("/cut.mov", &"x".repeat(MAX_NOTE_CHARS + 1))
+                .await
+                .unwrap_err()
+                .status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            create("/missing.mov", "hi").await.unwrap_err().status,
+            StatusCode::NOT_FOUND
+        );
+
+        let _ = create("/cut.mov", "hi").await.unwrap();
+        let mut entries = state.fs.list_directory("/", true).unwrap();
+        flag_notes(&state, &mut entries).await;
+        assert!(entries[0].has_notes);
+    }
+}
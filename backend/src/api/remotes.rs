@@ -0,0 +1,155 @@
+//! Copying directories to rclone remotes and following their progress.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::{ApiError, ErrorCode};
+use crate::services::RemoteSyncService;
+use crate::services::remotes::RemoteSync;
+
+#[derive(Debug, Serialize)]
+pub struct RemotesResponse {
+    /// Whether `FM_RCLONE_PATH` is set
+    pub enabled: bool,
+    pub remotes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoteSyncRequest {
+    /// Directory below the root to copy
+    pub path: String,
+    /// Path on the remote; its root by default
+    #[serde(default)]
+    pub remote_path: String,
+    /// Remove files on the remote that the directory does not have
+    #[serde(default)]
+    pub delete_extraneous: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemoteSyncsResponse {
+    pub syncs: Vec<RemoteSync>,
+}
+
+fn not_configured() -> ApiError {
+    ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "rclone is not configured; set FM_RCLONE_PATH",
+    )
+}
+
+/// Remotes set up in rclone's config
+pub async fn list_remotes(
+    State(service): State<Arc<RemoteSyncService>>,
+) -> Result<Json<RemotesResponse>, ApiError> {
+    if !service.is_enabled() {
+        return Ok(Json(RemotesResponse {
+            enabled: false,
+            remotes: Vec::new(),
+        }));
+    }
+    let remotes = service.list_remotes().await.map_err(ApiError::internal)?;
+    Ok(Json(RemotesResponse {
+        enabled: true,
+        remotes,
+    }))
+}
+
+/// Start copying a directory to the remote `name`, with `rclone sync` when
+/// `delete_extraneous` is set and `rclone copy` otherwise. Returns 202 with
+/// the sync to follow, or 409 if the same destination is already syncing.
+pub async fn start_sync(
+    State(service): State<Arc<RemoteSyncService>>,
+    Path(name): Path<String>,
+    Json(req): Json<RemoteSyncRequest>,
+) -> Result<(StatusCode, Json<RemoteSync>), ApiError> {
+    if !service.is_enabled() {
+        return Err(not_configured());
+    }
+    let resolved = service.fs().resolve_path(&req.path)?;
+    let path = service.fs().relative_path(&resolved);
+    if !resolved.is_dir() {
+        return Err(
+            ApiError::new(StatusCode::BAD_REQUEST, format!("Not a directory: {path}"))
+                .with_code(ErrorCode::NotADirectory)
+                .with_path(&path),
+        );
+    }
+    // Only configured remotes, so the name cannot smuggle in a local path
+    // or a flag
+    let remotes = service.list_remotes().await.map_err(ApiError::internal)?;
+    if !remotes.contains(&name) {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No rclone remote named {name}"),
+        ));
+    }
+
+    let remote_path = req.remote_path.trim_start_matches('/');
+    match service
+        .start_sync(&path, &name, remote_path, req.delete_extraneous)
+        .map_err(ApiError::internal)?
+    {
+        Some(sync) => Ok((StatusCode::ACCEPTED, Json(sync))),
+        None => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("{name}:{remote_path} is already being synced"),
+        )),
+    }
+}
+
+/// Syncs in progress and recently finished, newest first
+pub async fn list_syncs(
+    State(service): State<Arc<RemoteSyncService>>,
+) -> Json<RemoteSyncsResponse> {
+    Json(RemoteSyncsResponse {
+        syncs: service.syncs(),
+    })
+}
+
+/// One sync with its latest progress
+pub async fn get_sync(
+    State(service): State<Arc<RemoteSyncService>>,
+    Path(id): Path<u64>,
+) -> Result<Json<RemoteSync>, ApiError> {
+    service
+        .get_sync(id)
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Sync {id} not found")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::FilesystemService;
+
+    #[tokio::test]
+    async fn remotes_report_when_rclone_is_not_configured() {
+        let tmp = tempfile::tempdir().unwrap();
+        let service = Arc::new(RemoteSyncService::new(
+            None,
+            FilesystemService::new(tmp.path().to_path_buf()),
+        ));
+
+        let listed = list_remotes(State(service.clone())).await.unwrap();
+        assert!(!listed.0.enabled);
+
+        let err = start_sync(
+            State(service),
+            Path("gdrive".to_string()),
+            Json(RemoteSyncRequest {
+                path: "/".to_string(),
+                remote_path: String::new(),
+                delete_extraneous: false,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
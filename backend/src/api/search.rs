@@ -2,24 +2,51 @@ use axum::{
     Json,
     extract::{Query, State},
     http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
 };
-use serde::Deserialize;
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 use std::sync::Arc;
+use tokio::sync::mpsc;
 
-use crate::api::{AppState, ErrorResponse, SortField, SortOrder};
+use crate::api::{ApiError, AppState, SortField, SortOrder, meta, notes};
 use crate::db::{self, SearchSortField, SortOrder as DbSortOrder};
 use crate::models::FileEntry;
+use crate::services::filesystem::is_hidden_path;
+use crate::services::query::AdvancedQuery;
+use crate::services::search_index::query_terms;
 
 #[derive(Debug, Deserialize)]
 pub struct SearchQuery {
+    #[serde(default)]
     pub q: String,
     pub offset: Option<usize>,
     pub limit: Option<usize>,
     pub sort_by: Option<SortField>,
     pub sort_order: Option<SortOrder>,
+    pub show_hidden: Option<bool>,
+    /// Custom field conditions, e.g. `rating>=4,status=final`
+    pub meta: Option<String>,
+    /// Custom field to sort by instead of `sort_by`
+    pub sort_meta: Option<String>,
+    /// How `q` is read; `advanced` understands `ext:`, `size>`, `modified<`,
+    /// quoted phrases and `-` exclusions
+    pub syntax: Option<SearchSyntax>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSyntax {
+    /// Every whitespace-separated word must match
+    #[default]
+    Simple,
+    Advanced,
+}
+
+#[derive(Debug, Serialize)]
 pub struct SearchResponse {
     pub query: String,
     pub entries: Vec<FileEntry>,
@@ -30,18 +57,131 @@ pub struct SearchResponse {
     pub total: i64,
 }
 
-/// Search files by path
+/// Results sent by a streaming search unless the client asks for fewer
+const DEFAULT_STREAM_LIMIT: usize = 50;
+const MAX_STREAM_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct StreamSearchQuery {
+    #[serde(default)]
+    pub q: String,
+    /// Most entries to send in all
+    pub limit: Option<usize>,
+    pub show_hidden: Option<bool>,
+}
+
+/// One step of a streaming search: entries matched since the last step
+/// (until `limit` have been sent) and how far the scan has got
+#[derive(Debug, Serialize)]
+pub struct SearchProgress {
+    pub entries: Vec<FileEntry>,
+    /// Matches so far, including ones past `limit`
+    pub matched: usize,
+    pub scanned: usize,
+    pub index_size: usize,
+}
+
+fn bad_request(message: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, message.into())
+}
+
+/// IDs of the rows, leaving out hidden paths unless `show_hidden`
+fn visible_ids(rows: Vec<(i64, String)>, show_hidden: bool) -> HashSet<i64> {
+    rows.into_iter()
+        .filter(|(_, path)| show_hidden || !is_hidden_path(path))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Keep the matches that are `allowed`, or take `allowed` as the matches
+/// when nothing has been matched yet
+fn narrow(matching: &mut Option<Vec<i64>>, allowed: HashSet<i64>) {
+    match matching {
+        Some(ids) => ids.retain(|id| allowed.contains(id)),
+        None => *matching = Some(allowed.into_iter().collect()),
+    }
+}
+
+/// Order two custom field values numerically when both are numbers
+fn compare_values(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// One page of `ids` ordered by the custom field `key`; entries without the
+/// field come last in either direction
+async fn page_by_meta(
+    state: &AppState,
+    mut ids: Vec<i64>,
+    key: &str,
+    descending: bool,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<FileEntry>, ApiError> {
+    let values = db::meta_values_for_ids(&state.pool, &ids, key)
+        .await
+        .map_err(ApiError::internal)?;
+    ids.sort_by(|a, b| match (values.get(a), values.get(b)) {
+        (Some(x), Some(y)) => {
+            let ord = compare_values(x, y);
+            if descending { ord.reverse() } else { ord }.then(a.cmp(b))
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    });
+
+    let page: Vec<i64> = ids.into_iter().skip(offset).take(limit).collect();
+    if page.is_empty() {
+        return Ok(vec![]);
+    }
+    let (rows, _) = db::get_files_by_ids(
+        &state.pool,
+        &page,
+        page.len() as i64,
+        0,
+        SearchSortField::Name,
+        DbSortOrder::Asc,
+    )
+    .await
+    .map_err(ApiError::internal)?;
+    let position: HashMap<i64, usize> = page.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let mut rows = rows;
+    rows.sort_by_key(|row| position.get(&row.id).copied().unwrap_or(usize::MAX));
+    Ok(rows.into_iter().map(FileEntry::from).collect())
+}
+
+/// Search files by path, notes and custom fields
 pub async fn search_files(
     State(state): State<Arc<AppState>>,
     Query(query): Query<SearchQuery>,
-) -> Result<Json<SearchResponse>, (StatusCode, Json<ErrorResponse>)> {
-    if query.q.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Search query cannot be empty".to_string(),
-            }),
-        ));
+) -> Result<Json<SearchResponse>, ApiError> {
+    let filters = match query.meta.as_deref() {
+        Some(raw) => meta::parse_filters(raw)?,
+        None => vec![],
+    };
+    let sort_meta = query
+        .sort_meta
+        .as_deref()
+        .filter(|key| !key.trim().is_empty())
+        .map(meta::field_key)
+        .transpose()?;
+    let (terms, excluded, file_filters) = match query.syntax.unwrap_or_default() {
+        SearchSyntax::Simple => (query_terms(&query.q), vec![], vec![]),
+        SearchSyntax::Advanced => {
+            let parsed = AdvancedQuery::parse(&query.q).map_err(|e| bad_request(e.to_string()))?;
+            (parsed.terms, parsed.excluded, parsed.filters)
+        }
+    };
+    let has_text = !terms.is_empty();
+    if !has_text && filters.is_empty() && file_filters.is_empty() {
+        return Err(bad_request(if excluded.is_empty() {
+            "Search query cannot be empty"
+        } else {
+            "Search needs a term or filter besides exclusions"
+        }));
     }
 
     let limit = query.limit.unwrap_or(1000).max(1);
@@ -49,24 +189,46 @@ pub async fn search_files(
     let sort_by = query.sort_by.unwrap_or(SortField::Name);
     let sort_order = query.sort_order.unwrap_or(SortOrder::Asc);
 
-    let db_sort_field = match sort_by {
-        SortField::Name => SearchSortField::Name,
-        SortField::Path => SearchSortField::Path,
-        SortField::Size => SearchSortField::Size,
-        SortField::Modified => SearchSortField::Modified,
-        SortField::Created => SearchSortField::Created,
-        SortField::Type => SearchSortField::Type,
-        SortField::Resolutions => SearchSortField::Resolutions,
-        SortField::Duration => SearchSortField::Duration,
-    };
-
-    let db_sort_order = match sort_order {
-        SortOrder::Asc => DbSortOrder::Asc,
-        SortOrder::Desc => DbSortOrder::Desc,
+    let db_sort_field = SearchSortField::from(sort_by);
+    let db_sort_order = DbSortOrder::from(sort_order);
+
+    // Use in-memory search to get matching IDs, narrowed by custom fields
+    // and file filters, less any excluded terms
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
+    let mut matching_ids = if has_text {
+        Some(
+            state
+                .search
+                .find_terms(&state.pool, &terms, show_hidden)
+                .await
+                .map_err(ApiError::internal)?,
+        )
+    } else {
+        None
     };
-
-    // Use in-memory search to get matching IDs
-    let matching_ids = state.search.search(&query.q).await;
+    if !filters.is_empty() {
+        let rows = db::ids_matching_meta(&state.pool, &filters)
+            .await
+            .map_err(ApiError::internal)?;
+        narrow(&mut matching_ids, visible_ids(rows, show_hidden));
+    }
+    if !file_filters.is_empty() {
+        let rows = db::ids_matching_file_filters(&state.pool, &file_filters)
+            .await
+            .map_err(ApiError::internal)?;
+        narrow(&mut matching_ids, visible_ids(rows, show_hidden));
+    }
+    let mut matching_ids = matching_ids.unwrap_or_default();
+    for term in &excluded {
+        let excluded_ids: HashSet<i64> = state
+            .search
+            .find_terms(&state.pool, std::slice::from_ref(term), true)
+            .await
+            .map_err(ApiError::internal)?
+            .into_iter()
+            .collect();
+        matching_ids.retain(|id| !excluded_ids.contains(id));
+    }
 
     if matching_ids.is_empty() {
         return Ok(Json(SearchResponse {
@@ -81,25 +243,29 @@ pub async fn search_files(
     }
 
     // Fetch full records from SQLite by ID
-    let (results, total) = db::get_files_by_ids(
-        &state.pool,
-        &matching_ids,
-        limit as i64,
-        offset as i64,
-        db_sort_field,
-        db_sort_order,
-    )
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
-
-    let entries: Vec<FileEntry> = results.into_iter().map(FileEntry::from).collect();
+    let (mut entries, total) = match sort_meta {
+        Some(key) => {
+            let total = matching_ids.len() as i64;
+            let descending = sort_order == SortOrder::Desc;
+            let entries =
+                page_by_meta(&state, matching_ids, key, descending, limit, offset).await?;
+            (entries, total)
+        }
+        None => {
+            let (results, total) = db::get_files_by_ids(
+                &state.pool,
+                &matching_ids,
+                limit as i64,
+                offset as i64,
+                db_sort_field,
+                db_sort_order,
+            )
+            .await
+            .map_err(ApiError::internal)?;
+            (results.into_iter().map(FileEntry::from).collect(), total)
+        }
+    };
+    notes::flag_notes(&state, &mut entries).await;
 
     Ok(Json(SearchResponse {
         query: query.q,
@@ -112,22 +278,113 @@ pub async fn search_files(
     }))
 }
 
+struct StreamingSearch {
+    state: Arc<AppState>,
+    batches: mpsc::Receiver<(Vec<i64>, usize)>,
+    remaining: usize,
+    matched: usize,
+    scanned: usize,
+    index_size: usize,
+    done: bool,
+}
+
+impl StreamingSearch {
+    /// Entries for the first `remaining` of `ids`, ordered by name
+    async fn entries(&mut self, ids: &[i64]) -> Vec<FileEntry> {
+        let ids = &ids[..ids.len().min(self.remaining)];
+        if ids.is_empty() {
+            return vec![];
+        }
+        let rows = match db::get_files_by_ids(
+            &self.state.pool,
+            ids,
+            ids.len() as i64,
+            0,
+            SearchSortField::Name,
+            DbSortOrder::Asc,
+        )
+        .await
+        {
+            Ok((rows, _)) => rows,
+            Err(e) => {
+                tracing::warn!("Failed to load streamed search results: {}", e);
+                vec![]
+            }
+        };
+        self.remaining -= rows.len();
+        let mut entries: Vec<FileEntry> = rows.into_iter().map(FileEntry::from).collect();
+        notes::flag_notes(&self.state, &mut entries).await;
+        entries
+    }
+
+    /// `results` for each slice of the index scanned, then `done`
+    async fn next(&mut self) -> Option<Event> {
+        if self.done {
+            return None;
+        }
+        let (name, entries) = match self.batches.recv().await {
+            Some((ids, scanned)) => {
+                self.matched += ids.len();
+                self.scanned = scanned;
+                ("results", self.entries(&ids).await)
+            }
+            None => {
+                self.done = true;
+                ("done", vec![])
+            }
+        };
+        let progress = SearchProgress {
+            entries,
+            matched: self.matched,
+            scanned: self.scanned,
+            index_size: self.index_size,
+        };
+        Event::default().event(name).json_data(&progress).ok()
+    }
+}
+
+/// Search as the user types: matches are streamed as server-sent events
+/// while the index is scanned, so the first hits show up before a scan of
+/// a very large index finishes. Closing the connection stops the scan.
+pub async fn search_stream(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StreamSearchQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Search query cannot be empty",
+        ));
+    }
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
+    let search = StreamingSearch {
+        batches: state
+            .search
+            .search_streaming(&state.pool, query.q, show_hidden),
+        index_size: state.search.index_size().await,
+        state,
+        remaining: query
+            .limit
+            .unwrap_or(DEFAULT_STREAM_LIMIT)
+            .clamp(1, MAX_STREAM_LIMIT),
+        matched: 0,
+        scanned: 0,
+        done: false,
+    };
+    let events = stream::unfold(search, |mut search| async move {
+        let event = search.next().await?;
+        Some((Ok(event), search))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::services::FilesystemService;
-    use chrono::Utc;
     use sqlx::sqlite::SqlitePoolOptions;
     use std::fs;
     use tempfile::tempdir;
 
-    fn now_sqlite_timestamp() -> String {
-        Utc::now()
-            .naive_utc()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string()
-    }
-
     async fn test_state() -> (Arc<AppState>, tempfile::TempDir) {
         let tmp = tempdir().expect("tempdir created");
         let root = tmp.path().join("root");
@@ -143,9 +400,8 @@ mod tests {
         let search = Arc::new(crate::services::SearchService::new());
 
         let state = Arc::new(AppState {
-            fs: FilesystemService::new(root),
-            pool,
             search,
+            ..AppState::for_tests(root, pool)
         });
 
         (state, tmp)
@@ -167,6 +423,56 @@ mod tests {
         state.search.add_entry(id, &indexed.path).await;
     }
 
+    #[tokio::test]
+    async fn streaming_search_sends_matches_then_done() {
+        let (state, _tmp) = test_state().await;
+        for path in ["/docs/report1.txt", "/docs/report2.txt", "/docs/notes.txt"] {
+            let indexed = crate::models::IndexedFileRow {
+                size: Some(5),
+                mime_type: Some("text/plain".to_string()),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
+            };
+            seed_file(&state, &indexed).await;
+        }
+
+        let mut search = StreamingSearch {
+            batches: state
+                .search
+                .search_streaming(&state.pool, "report".to_string(), false),
+            state: state.clone(),
+            remaining: 1,
+            matched: 0,
+            scanned: 0,
+            index_size: 3,
+            done: false,
+        };
+        let (ids, scanned) = search.batches.recv().await.unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(scanned, 3);
+        let entries = search.entries(&ids).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "report1.txt");
+        // The limit is used up
+        assert!(search.entries(&ids).await.is_empty());
+
+        assert!(search.next().await.is_some());
+        assert!(search.done);
+        assert!(search.next().await.is_none());
+
+        let err = search_stream(
+            State(state),
+            Query(StreamSearchQuery {
+                q: " ".to_string(),
+                limit: None,
+                show_hidden: None,
+            }),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn search_rejects_empty_query() {
         let (state, _tmp) = test_state().await;
@@ -179,12 +485,16 @@ mod tests {
                 limit: None,
                 sort_by: None,
                 sort_order: None,
+                show_hidden: None,
+                meta: None,
+                sort_meta: None,
+                syntax: None,
             }),
         )
         .await
         .unwrap_err();
 
-        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
@@ -198,19 +508,9 @@ mod tests {
             "/docs/reports/2024-summary.txt",
         ] {
             let indexed = crate::models::IndexedFileRow {
-                id: 0,
-                path: path.to_string(),
-                name: path.split('/').last().unwrap().to_string(),
-                is_dir: false,
                 size: Some(5),
-                created_at: None,
-                modified_at: None,
                 mime_type: Some("text/plain".to_string()),
-                width: None,
-                height: None,
-                duration: None,
-                metadata_status: "complete".to_string(),
-                indexed_at: now_sqlite_timestamp(),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
             };
             seed_file(&state, &indexed).await;
         }
@@ -224,6 +524,10 @@ mod tests {
                 limit: None,
                 sort_by: None,
                 sort_order: None,
+                show_hidden: None,
+                meta: None,
+                sort_meta: None,
+                syntax: None,
             }),
         )
         .await
@@ -255,19 +559,9 @@ mod tests {
             "/people/123johndoe.txt",
         ] {
             let indexed = crate::models::IndexedFileRow {
-                id: 0,
-                path: path.to_string(),
-                name: path.split('/').last().unwrap().to_string(),
-                is_dir: false,
                 size: Some(1),
-                created_at: None,
-                modified_at: None,
                 mime_type: Some("text/plain".to_string()),
-                width: None,
-                height: None,
-                duration: None,
-                metadata_status: "complete".to_string(),
-                indexed_at: now_sqlite_timestamp(),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
             };
             seed_file(&state, &indexed).await;
         }
@@ -280,6 +574,10 @@ mod tests {
                 limit: None,
                 sort_by: None,
                 sort_order: None,
+                show_hidden: None,
+                meta: None,
+                sort_meta: None,
+                syntax: None,
             }),
         )
         .await
@@ -301,19 +599,9 @@ mod tests {
 
         for path in ["/docs/h&m.txt", "/docs/hm.txt", "/docs/h-m.txt"] {
             let indexed = crate::models::IndexedFileRow {
-                id: 0,
-                path: path.to_string(),
-                name: path.split('/').last().unwrap().to_string(),
-                is_dir: false,
                 size: Some(1),
-                created_at: None,
-                modified_at: None,
                 mime_type: Some("text/plain".to_string()),
-                width: None,
-                height: None,
-                duration: None,
-                metadata_status: "complete".to_string(),
-                indexed_at: now_sqlite_timestamp(),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
             };
             seed_file(&state, &indexed).await;
         }
@@ -326,6 +614,10 @@ mod tests {
                 limit: None,
                 sort_by: None,
                 sort_order: None,
+                show_hidden: None,
+                meta: None,
+                sort_meta: None,
+                syntax: None,
             }),
         )
         .await
@@ -343,19 +635,9 @@ mod tests {
         for i in 0..35 {
             let path = format!("/notes/note-{i}.txt");
             let indexed = crate::models::IndexedFileRow {
-                id: 0,
-                path: path.clone(),
-                name: path.split('/').last().unwrap().to_string(),
-                is_dir: false,
                 size: Some(1),
-                created_at: None,
-                modified_at: None,
                 mime_type: Some("text/plain".to_string()),
-                width: None,
-                height: None,
-                duration: None,
-                metadata_status: "complete".to_string(),
-                indexed_at: now_sqlite_timestamp(),
+                ..crate::models::IndexedFileRow::for_tests(&path, false)
             };
             seed_file(&state, &indexed).await;
         }
@@ -368,6 +650,10 @@ mod tests {
                 limit: Some(10),
                 sort_by: None,
                 sort_order: None,
+                show_hidden: None,
+                meta: None,
+                sort_meta: None,
+                syntax: None,
             }),
         )
         .await
@@ -379,6 +665,47 @@ mod tests {
         assert_eq!(resp.0.limit, 10);
     }
 
+    #[tokio::test]
+    async fn search_matches_audio_tags_after_rebuild() {
+        let (state, _tmp) = test_state().await;
+
+        let indexed = crate::models::IndexedFileRow {
+            size: Some(1),
+            mime_type: Some("audio/flac".to_string()),
+            duration: Some(180.0),
+            artist: Some("Massive Attack".to_string()),
+            album: Some("Mezzanine".to_string()),
+            title: Some("Teardrop".to_string()),
+            track_number: Some(3),
+            ..crate::models::IndexedFileRow::for_tests("/music/track01.flac", false)
+        };
+        crate::db::upsert_file(&state.pool, &indexed).await.unwrap();
+        state.search.rebuild_from_db(&state.pool).await.unwrap();
+
+        let resp = search_files(
+            State(state.clone()),
+            Query(SearchQuery {
+                q: "mezzanine teardrop".to_string(),
+                offset: None,
+                limit: None,
+                sort_by: None,
+                sort_order: None,
+                show_hidden: None,
+                meta: None,
+                sort_meta: None,
+                syntax: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resp.0.total, 1);
+        let entry = &resp.0.entries[0];
+        assert_eq!(entry.path, "/music/track01.flac");
+        assert_eq!(entry.artist.as_deref(), Some("Massive Attack"));
+        assert_eq!(entry.track_number, Some(3));
+    }
+
     #[tokio::test]
     async fn search_sorts_by_duration_desc() {
         let (state, _tmp) = test_state().await;
@@ -391,19 +718,12 @@ mod tests {
 
         for (path, duration) in files {
             let indexed = crate::models::IndexedFileRow {
-                id: 0,
-                path: path.to_string(),
-                name: path.split('/').last().unwrap().to_string(),
-                is_dir: false,
                 size: Some(1),
-                created_at: None,
-                modified_at: None,
                 mime_type: Some("video/mp4".to_string()),
                 width: Some(1920),
                 height: Some(1080),
                 duration: Some(duration),
-                metadata_status: "complete".to_string(),
-                indexed_at: now_sqlite_timestamp(),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
             };
             seed_file(&state, &indexed).await;
         }
@@ -416,6 +736,10 @@ mod tests {
                 limit: Some(10),
                 sort_by: Some(SortField::Duration),
                 sort_order: Some(SortOrder::Desc),
+                show_hidden: None,
+                meta: None,
+                sort_meta: None,
+                syntax: None,
             }),
         )
         .await
@@ -426,4 +750,145 @@ mod tests {
         assert_eq!(resp.0.sort_by, SortField::Duration);
         assert_eq!(resp.0.sort_order, SortOrder::Desc);
     }
+
+    #[tokio::test]
+    async fn search_filters_and_sorts_by_custom_fields() {
+        let (state, _tmp) = test_state().await;
+
+        for (path, rating) in [
+            ("/shots/a.jpg", Some("3")),
+            ("/shots/b.jpg", Some("5")),
+            ("/shots/c.jpg", Some("4")),
+            ("/shots/d.jpg", None),
+            ("/.trash/e.jpg", Some("5")),
+        ] {
+            let indexed = crate::models::IndexedFileRow {
+                size: Some(5),
+                mime_type: Some("image/jpeg".to_string()),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
+            };
+            seed_file(&state, &indexed).await;
+            if let Some(rating) = rating {
+                crate::db::update_meta(
+                    &state.pool,
+                    path,
+                    &[("rating".to_string(), Some(rating.to_string()))],
+                )
+                .await
+                .unwrap();
+            }
+        }
+
+        let search = |q: &str, meta: Option<&str>, sort_meta: Option<&str>| {
+            search_files(
+                State(state.clone()),
+                Query(SearchQuery {
+                    q: q.to_string(),
+                    offset: None,
+                    limit: None,
+                    sort_by: None,
+                    sort_order: Some(SortOrder::Desc),
+                    show_hidden: None,
+                    meta: meta.map(str::to_string),
+                    sort_meta: sort_meta.map(str::to_string),
+                    syntax: None,
+                }),
+            )
+        };
+        let names = |resp: &SearchResponse| -> Vec<String> {
+            resp.entries.iter().map(|e| e.name.clone()).collect()
+        };
+
+        // Field conditions alone are a valid search; hidden paths stay hidden
+        let resp = search("", Some("rating>=4"), Some("rating")).await.unwrap();
+        assert_eq!(names(&resp.0), ["b.jpg", "c.jpg"]);
+        assert_eq!(resp.0.total, 2);
+
+        // Entries without the field sort last
+        let resp = search("shots", None, Some("rating")).await.unwrap();
+        assert_eq!(names(&resp.0), ["b.jpg", "c.jpg", "a.jpg", "d.jpg"]);
+
+        let resp = search("a.jpg", Some("rating=3"), None).await.unwrap();
+        assert_eq!(names(&resp.0), ["a.jpg"]);
+
+        let err = search("shots", Some("rating>=lots"), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn advanced_syntax_combines_terms_filters_and_exclusions() {
+        let (state, _tmp) = test_state().await;
+
+        for (path, size, modified) in [
+            ("/clips/beach day.mp4", 2 << 30, "2022-06-01T10:00:00+00:00"),
+            (
+                "/clips/beach night.mp4",
+                2 << 30,
+                "2023-06-01T10:00:00+00:00",
+            ),
+            ("/clips/beach day.mov", 3 << 30, "2022-07-01T10:00:00+00:00"),
+            (
+                "/clips/beach day draft.mp4",
+                512 << 20,
+                "2022-06-02T10:00:00+00:00",
+            ),
+            (
+                "/clips/day at the beach.mp4",
+                2 << 30,
+                "2022-06-03T10:00:00+00:00",
+            ),
+        ] {
+            let indexed = crate::models::IndexedFileRow {
+                size: Some(size),
+                modified_at: Some(modified.to_string()),
+                ..crate::models::IndexedFileRow::for_tests(path, false)
+            };
+            seed_file(&state, &indexed).await;
+        }
+
+        let search = |q: &str, syntax: SearchSyntax| {
+            search_files(
+                State(state.clone()),
+                Query(SearchQuery {
+                    q: q.to_string(),
+                    offset: None,
+                    limit: None,
+                    sort_by: None,
+                    sort_order: None,
+                    show_hidden: None,
+                    meta: None,
+                    sort_meta: None,
+                    syntax: Some(syntax),
+                }),
+            )
+        };
+        let names = |resp: &SearchResponse| -> Vec<String> {
+            resp.entries.iter().map(|e| e.name.clone()).collect()
+        };
+
+        let resp = search(
+            r#"ext:mp4 size>1gb modified<2023-01-01 "beach day" -draft"#,
+            SearchSyntax::Advanced,
+        )
+        .await
+        .unwrap();
+        assert_eq!(names(&resp.0), ["beach day.mp4"]);
+
+        // Filters alone are a valid search
+        let resp = search("ext:mov", SearchSyntax::Advanced).await.unwrap();
+        assert_eq!(names(&resp.0), ["beach day.mov"]);
+
+        // The simple syntax takes the same query word for word
+        let resp = search("\"beach day\" -draft", SearchSyntax::Simple)
+            .await
+            .unwrap();
+        assert!(resp.0.entries.is_empty());
+
+        for q in ["-draft", "size>huge"] {
+            let err = search(q, SearchSyntax::Advanced).await.unwrap_err();
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        }
+    }
 }
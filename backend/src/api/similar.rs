@@ -0,0 +1,237 @@
+//! "Find similar": other copies and versions of a file, from the index.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::api::{ApiError, AppState, ErrorCode};
+use crate::db;
+use crate::models::FileEntry;
+use crate::services::ManifestService;
+use crate::services::similar::{self, DURATION_TOLERANCE_SECS, SimilarReason};
+
+/// Name tokens searched for; later ones are usually release tags
+const MAX_NAME_TOKENS: usize = 8;
+
+/// Index rows considered before scoring
+const MAX_CANDIDATES: i64 = 1000;
+
+/// Files of the same size hashed to compare contents
+const MAX_HASHED: usize = 16;
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarQuery {
+    pub path: String,
+    pub limit: Option<usize>,
+    pub show_hidden: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarEntry {
+    #[serde(flatten)]
+    pub entry: FileEntry,
+    /// Higher is more alike; a matching checksum alone scores 1
+    pub score: f64,
+    pub reasons: Vec<SimilarReason>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarResponse {
+    pub path: String,
+    /// Most alike first
+    pub entries: Vec<SimilarEntry>,
+}
+
+/// Indexed files with a similar name, the same running time or dimensions,
+/// or the same contents as the file at `path`. Contents are compared only
+/// between files of the same size.
+pub async fn similar_files(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SimilarQuery>,
+) -> Result<Json<SimilarResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
+    let resolved = state.fs.resolve_path(&query.path)?;
+    let path = state.fs.relative_path(&resolved);
+
+    let source = db::get_indexed_file(&state.pool, &path)
+        .await
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| {
+            ApiError::new(StatusCode::NOT_FOUND, format!("Not indexed yet: {path}"))
+                .with_path(&path)
+        })?;
+    if source.is_dir {
+        return Err(
+            ApiError::new(StatusCode::BAD_REQUEST, format!("Not a file: {path}"))
+                .with_code(ErrorCode::NotAFile)
+                .with_path(&path),
+        );
+    }
+
+    let mut tokens = similar::name_tokens(&source.name);
+    tokens.truncate(MAX_NAME_TOKENS);
+    let candidates = db::similar_candidates(
+        &state.pool,
+        &source,
+        &tokens,
+        DURATION_TOLERANCE_SECS,
+        show_hidden,
+        MAX_CANDIDATES,
+    )
+    .await
+    .map_err(ApiError::internal)?;
+
+    let same_size: Vec<String> = candidates
+        .iter()
+        .filter(|c| c.size == source.size)
+        .take(MAX_HASHED)
+        .map(|c| c.path.clone())
+        .collect();
+    let same_content = if same_size.is_empty() {
+        HashSet::new()
+    } else {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            let hash = |path: &str| {
+                let resolved = state.fs.resolve_path(path).ok()?;
+                ManifestService::hash_file(&resolved).ok()
+            };
+            let Some(source_hash) = hash(&path) else {
+                return HashSet::new();
+            };
+            same_size
+                .into_iter()
+                .filter(|p| hash(p).as_ref() == Some(&source_hash))
+                .collect::<HashSet<_>>()
+        })
+        .await
+        .map_err(ApiError::internal)?
+    };
+
+    let mut entries: Vec<SimilarEntry> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let found = similar::compare(
+                &source,
+                &tokens,
+                &candidate,
+                same_content.contains(&candidate.path),
+            )?;
+            Some(SimilarEntry {
+                entry: FileEntry::from(candidate),
+                score: found.score,
+                reasons: found.reasons,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.entry.path.cmp(&b.entry.path))
+    });
+    entries.truncate(limit);
+
+    Ok(Json(SimilarResponse {
+        path: source.path,
+        entries,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn test_state() -> (Arc<AppState>, tempfile::TempDir) {
+        let tmp = tempdir().expect("tempdir created");
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(root, pool));
+
+        (state, tmp)
+    }
+
+    /// Write `contents` to `path` and index it with a running time
+    async fn seed(state: &AppState, path: &str, contents: &str, duration: f64) {
+        fs::write(state.fs.root().join(&path[1..]), contents).unwrap();
+        let row = crate::models::IndexedFileRow {
+            size: Some(contents.len() as i64),
+            mime_type: Some("video/mp4".to_string()),
+            duration: Some(duration),
+            indexed_at: "2024-01-01 00:00:00".to_string(),
+            ..crate::models::IndexedFileRow::for_tests(path, false)
+        };
+        crate::db::upsert_file(&state.pool, &row).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn finds_copies_and_other_cuts() {
+        let (state, _tmp) = test_state().await;
+        seed(&state, "/Movie.2010.1080p.mkv", "same bytes", 7200.0).await;
+        seed(
+            &state,
+            "/Movie 2010 Extended_100%.mkv",
+            "longer cut",
+            8100.0,
+        )
+        .await;
+        seed(&state, "/download.mp4", "same bytes", 7200.0).await;
+        seed(&state, "/lookalike.mp4", "other data", 60.0).await;
+        seed(&state, "/holiday.mp4", "short", 30.0).await;
+
+        let resp = similar_files(
+            State(state.clone()),
+            Query(SimilarQuery {
+                path: "/Movie.2010.1080p.mkv".to_string(),
+                limit: None,
+                show_hidden: None,
+            }),
+        )
+        .await
+        .unwrap();
+        let found: Vec<_> = resp
+            .0
+            .entries
+            .iter()
+            .map(|e| (e.entry.path.as_str(), e.reasons.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "/download.mp4",
+                    vec![SimilarReason::Duration, SimilarReason::Checksum]
+                ),
+                ("/Movie 2010 Extended_100%.mkv", vec![SimilarReason::Name]),
+            ]
+        );
+
+        let err = similar_files(
+            State(state),
+            Query(SimilarQuery {
+                path: "/".to_string(),
+                limit: None,
+                show_hidden: None,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::db::{SearchSortField, SortOrder as DbSortOrder};
+
 #[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum SortField {
@@ -19,3 +21,27 @@ pub enum SortOrder {
     Asc,
     Desc,
 }
+
+impl From<SortField> for SearchSortField {
+    fn from(field: SortField) -> Self {
+        match field {
+            SortField::Name => Self::Name,
+            SortField::Path => Self::Path,
+            SortField::Size => Self::Size,
+            SortField::Modified => Self::Modified,
+            SortField::Created => Self::Created,
+            SortField::Type => Self::Type,
+            SortField::Resolutions => Self::Resolutions,
+            SortField::Duration => Self::Duration,
+        }
+    }
+}
+
+impl From<SortOrder> for DbSortOrder {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Asc => Self::Asc,
+            SortOrder::Desc => Self::Desc,
+        }
+    }
+}
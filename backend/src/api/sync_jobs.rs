@@ -0,0 +1,341 @@
+//! Scheduled one-way sync jobs between directories: define them, see how
+//! their last runs went, and run one now.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::files::SuccessResponse;
+use crate::api::{ApiError, ErrorCode};
+use crate::db::{self, SyncJobRow, SyncRunRow};
+use crate::services::SyncJobService;
+use crate::services::sync_jobs::{MAX_INTERVAL_SECS, format_time};
+
+/// Shortest interval between runs of a job
+const MIN_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSyncJobRequest {
+    pub source: String,
+    pub destination: String,
+    pub interval_secs: i64,
+    #[serde(default)]
+    pub delete_extraneous: bool,
+    #[serde(default)]
+    pub checksum: bool,
+    /// Wait a full interval before the first run instead of running soon
+    #[serde(default)]
+    pub delay_first_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncJobEntry {
+    #[serde(flatten)]
+    pub job: SyncJobRow,
+    pub running: bool,
+    pub last_run: Option<SyncRunRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncJobsResponse {
+    pub jobs: Vec<SyncJobEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRunsQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncRunsResponse {
+    pub runs: Vec<SyncRunRow>,
+}
+
+fn job_not_found(id: i64) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, format!("Sync job {id} not found"))
+}
+
+async fn entry(service: &SyncJobService, job: SyncJobRow) -> Result<SyncJobEntry, ApiError> {
+    let last_run = db::list_sync_runs(service.pool(), job.id, 1)
+        .await
+        .map_err(ApiError::internal)?
+        .pop();
+    Ok(SyncJobEntry {
+        running: service.is_running(job.id),
+        job,
+        last_run,
+    })
+}
+
+/// Destination as an absolute path without `.` or `..` components or a
+/// trailing slash; it need not exist yet
+fn destination_path(destination: &str) -> Result<String, ApiError> {
+    let mut parts = Vec::new();
+    for part in destination
+        .split('/')
+        .filter(|p| !p.is_empty() && *p != ".")
+    {
+        if part == ".." {
+            return Err(
+                ApiError::new(StatusCode::BAD_REQUEST, "Destination cannot contain '..'")
+                    .with_code(ErrorCode::InvalidName),
+            );
+        }
+        parts.push(part);
+    }
+    Ok(format!("/{}", parts.join("/")))
+}
+
+fn is_within(path: &str, dir: &str) -> bool {
+    path == dir || dir == "/" || path.starts_with(&format!("{dir}/"))
+}
+
+/// All jobs with their most recent run
+pub async fn list_sync_jobs(
+    State(service): State<Arc<SyncJobService>>,
+) -> Result<Json<SyncJobsResponse>, ApiError> {
+    let mut jobs = Vec::new();
+    for job in db::list_sync_jobs(service.pool())
+        .await
+        .map_err(ApiError::internal)?
+    {
+        jobs.push(entry(&service, job).await?);
+    }
+    Ok(Json(SyncJobsResponse { jobs }))
+}
+
+/// Add a job mirroring the directory `source` into `destination` every
+/// `interval_secs`
+pub async fn create_sync_job(
+    State(service): State<Arc<SyncJobService>>,
+    Json(req): Json<CreateSyncJobRequest>,
+) -> Result<Json<SyncJobEntry>, ApiError> {
+    if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&req.interval_secs) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Interval must be between {MIN_INTERVAL_SECS} and {MAX_INTERVAL_SECS} seconds"),
+        ));
+    }
+    let resolved = service.fs().resolve_path(&req.source)?;
+    let source = service.fs().relative_path(&resolved);
+    if !resolved.is_dir() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Not a directory: {source}"),
+        )
+        .with_code(ErrorCode::NotADirectory)
+        .with_path(&source));
+    }
+    let destination = destination_path(&req.destination)?;
+    if is_within(&source, &destination) || is_within(&destination, &source) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Source and destination cannot be inside one another",
+        ));
+    }
+
+    let now = Utc::now();
+    let first_run = if req.delay_first_run {
+        now + Duration::seconds(req.interval_secs)
+    } else {
+        now
+    };
+    let mut job = SyncJobRow {
+        id: 0, // Set by DB
+        source,
+        destination,
+        interval_secs: req.interval_secs,
+        delete_extraneous: req.delete_extraneous,
+        checksum: req.checksum,
+        enabled: true,
+        created_at: format_time(now),
+        next_run_at: format_time(first_run),
+    };
+    job.id = db::create_sync_job(service.pool(), &job)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(entry(&service, job).await?))
+}
+
+/// One job with its most recent run
+pub async fn get_sync_job(
+    State(service): State<Arc<SyncJobService>>,
+    Path(id): Path<i64>,
+) -> Result<Json<SyncJobEntry>, ApiError> {
+    let job = db::get_sync_job(service.pool(), id)
+        .await
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| job_not_found(id))?;
+    Ok(Json(entry(&service, job).await?))
+}
+
+/// Remove a job and its history. Files already mirrored stay.
+pub async fn delete_sync_job(
+    State(service): State<Arc<SyncJobService>>,
+    Path(id): Path<i64>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    if !db::delete_sync_job(service.pool(), id)
+        .await
+        .map_err(ApiError::internal)?
+    {
+        return Err(job_not_found(id));
+    }
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: None,
+        message: Some(format!("Sync job {id} deleted")),
+        performed: None,
+        sync: None,
+    }))
+}
+
+/// A job's runs, newest first
+pub async fn sync_job_runs(
+    State(service): State<Arc<SyncJobService>>,
+    Path(id): Path<i64>,
+    Query(query): Query<SyncRunsQuery>,
+) -> Result<Json<SyncRunsResponse>, ApiError> {
+    db::get_sync_job(service.pool(), id)
+        .await
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| job_not_found(id))?;
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let runs = db::list_sync_runs(service.pool(), id, limit)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(SyncRunsResponse { runs }))
+}
+
+/// Start a run now, in the background; its result shows up as the job's
+/// last run. 409 if the job is already running.
+pub async fn run_sync_job(
+    State(service): State<Arc<SyncJobService>>,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<SyncJobEntry>), ApiError> {
+    let job = db::get_sync_job(service.pool(), id)
+        .await
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| job_not_found(id))?;
+    if service.is_running(id) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("Sync job {id} is already running"),
+        ));
+    }
+
+    let runner = service.clone();
+    let queued = job.clone();
+    tokio::spawn(async move {
+        runner.run_job(&queued).await;
+    });
+    Ok((StatusCode::ACCEPTED, Json(entry(&service, job).await?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::FilesystemService;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn test_service() -> (Arc<SyncJobService>, tempfile::TempDir) {
+        let tmp = tempdir().expect("tempdir created");
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+        let service = SyncJobService::new(pool, FilesystemService::new(tmp.path().to_path_buf()));
+        (Arc::new(service), tmp)
+    }
+
+    fn request(source: &str, destination: &str) -> CreateSyncJobRequest {
+        CreateSyncJobRequest {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            interval_secs: 3600,
+            delete_extraneous: false,
+            checksum: false,
+            delay_first_run: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn jobs_are_validated_and_report_their_last_run() {
+        let (service, tmp) = test_service().await;
+        fs::create_dir(tmp.path().join("photos")).unwrap();
+        fs::write(tmp.path().join("photos/a.jpg"), "a").unwrap();
+        fs::write(tmp.path().join("notes.txt"), "n").unwrap();
+
+        for (source, destination) in [
+            ("/notes.txt", "/backup"),
+            ("/photos", "/photos/backup"),
+            ("/photos", "/"),
+            ("/photos", "/backup/../photos"),
+        ] {
+            let err = create_sync_job(State(service.clone()), Json(request(source, destination)))
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.status,
+                StatusCode::BAD_REQUEST,
+                "{source} -> {destination}"
+            );
+        }
+        for interval_secs in [5, MAX_INTERVAL_SECS + 1, 9_000_000_000_000_000] {
+            let err = create_sync_job(
+                State(service.clone()),
+                Json(CreateSyncJobRequest {
+                    interval_secs,
+                    ..request("/photos", "/backup")
+                }),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(err.status, StatusCode::BAD_REQUEST, "{interval_secs}");
+        }
+
+        let created = create_sync_job(
+            State(service.clone()),
+            Json(request("/photos/", "/backup/photos/")),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(created.job.destination, "/backup/photos");
+        assert!(created.last_run.is_none());
+
+        let run = service.run_job(&created.job).await.unwrap();
+        assert_eq!(run.created, 1);
+        assert!(tmp.path().join("backup/photos/a.jpg").exists());
+
+        let listed = list_sync_jobs(State(service.clone())).await.unwrap().0;
+        assert_eq!(listed.jobs.len(), 1);
+        assert_eq!(listed.jobs[0].last_run.as_ref().unwrap().id, run.id);
+
+        let runs = sync_job_runs(
+            State(service.clone()),
+            Path(created.job.id),
+            Query(SyncRunsQuery { limit: None }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(runs.0.runs.len(), 1);
+
+        let deleted = delete_sync_job(State(service.clone()), Path(created.job.id))
+            .await
+            .unwrap();
+        assert!(deleted.0.success);
+        let err = get_sync_job(State(service), Path(created.job.id))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,22 +1,105 @@
-use axum::{Json, extract::State, http::StatusCode};
-use serde::Serialize;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Instant;
-use tracing::{error, info};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use tracing::{error, info, warn};
 
-use crate::api::AppState;
+use crate::api::files::{SuccessResponse, status_for_fs_error};
+use crate::api::{ApiError, AppState, AuthState};
+use crate::config::Config;
 use crate::db;
-use crate::services::{IndexerService, MetadataService};
+use crate::services::integrity::IntegrityReport;
+use crate::services::permissions::{
+    self, PermissionChange, PermissionError, PermissionResult, parse_mode,
+};
+use crate::services::search::SearchIndexStatus;
+use crate::services::storage::StorageStatus;
+use crate::services::support::redacted_config;
+use crate::services::{
+    BackupError, IndexerService, IntegrityService, LogBuffer, MetadataService, SupportBundle,
+};
 use crate::version;
 
+/// State for the support bundle endpoint, which reads from most services
+pub struct SupportState {
+    pub app: Arc<AppState>,
+    pub indexer: Arc<IndexerService>,
+    pub config: Config,
+    pub logs: LogBuffer,
+    pub integrity: Arc<IntegrityService>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct HealthResponse {
     pub status: &'static str,
     pub version: &'static str,
     pub git_commit: &'static str,
     pub built_at: &'static str,
+    /// Clients older than this should ask the user to update
+    pub min_client_version: &'static str,
     pub ffprobe_available: bool,
+    pub pdf_tools_available: bool,
     pub database_status: DatabaseStatus,
+    pub database_storage: StorageStatus,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LivenessResponse {
+    pub status: &'static str,
+}
+
+/// Whether one dependency needed to serve requests is usable
+#[derive(Debug, Serialize)]
+pub struct DependencyStatus {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DependencyStatus {
+    fn from_result(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Self {
+                ok: true,
+                error: None,
+            },
+            Err(error) => Self {
+                ok: false,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessChecks {
+    /// The root is mounted and its listing can be read
+    pub root: DependencyStatus,
+    /// The database accepts writes
+    pub database: DependencyStatus,
+    /// The search index has been loaded from the database
+    pub search_index: DependencyStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub checks: ReadinessChecks,
 }
 
 #[derive(Debug, Serialize)]
@@ -37,14 +120,30 @@ pub struct DatabaseStatus {
 #[derive(Debug, Serialize)]
 pub struct IndexStatusResponse {
     pub is_running: bool,
+    /// Set while the root does not match the index; missing files are not
+    /// purged until it is resolved or accepted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_mismatch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_index: Option<SearchIndexStatus>,
 }
 
-/// Health check endpoint with database status
-pub async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthResponse>) {
-    let version_info = version::current();
+#[derive(Debug, Serialize)]
+struct Diagnostics {
+    database_status: DatabaseStatus,
+    database_storage: StorageStatus,
+    ffprobe_available: bool,
+    pdf_tools_available: bool,
+    root_path_exists: bool,
+    root_path_readable: bool,
+    root_path_read_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root_path_owner: Option<String>,
+    integrity: Option<IntegrityReport>,
+}
 
-    // Check database connectivity
-    let db_status = match sqlx::query("SELECT 1").execute(&state.pool).await {
+async fn database_status(pool: &SqlitePool) -> DatabaseStatus {
+    match sqlx::query("SELECT 1").execute(pool).await {
         Ok(_) => DatabaseStatus {
             connected: true,
             error: None,
@@ -53,9 +152,29 @@ pub async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Hea
             connected: false,
             error: Some(e.to_string()),
         },
-    };
+    }
+}
+
+/// Health check endpoint with database status
+pub async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthResponse>) {
+    let version_info = version::current();
+
+    // Check database connectivity
+    let db_status = database_status(&state.pool).await;
+    let storage = state.storage.status();
 
-    let overall_status = if db_status.connected {
+    let mut warnings = Vec::new();
+    if storage.low {
+        let message = format!(
+            "Database volume is below its free-space reserve ({} of {} bytes free); indexing is paused",
+            storage.available_bytes.unwrap_or(0),
+            storage.min_free_bytes
+        );
+        warn!("{}", message);
+        warnings.push(message);
+    }
+
+    let overall_status = if db_status.connected && !storage.low {
         "ok"
     } else {
         "degraded"
@@ -73,12 +192,92 @@ pub async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Hea
             version: version_info.version,
             git_commit: version_info.git_commit,
             built_at: version_info.built_at,
+            min_client_version: version_info.min_client_version,
             ffprobe_available: MetadataService::is_available(),
+            pdf_tools_available: MetadataService::is_pdf_tools_available(),
             database_status: db_status,
+            database_storage: storage,
+            warnings,
         }),
     )
 }
 
+/// How long a readiness check may take before its dependency counts as down;
+/// a dead network mount otherwise blocks instead of failing
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Liveness probe: the process is up and serving requests
+pub async fn liveness() -> Json<LivenessResponse> {
+    Json(LivenessResponse { status: "ok" })
+}
+
+/// Readiness probe: the root, database, and search index are all usable.
+/// Responds `503` with the failing dependencies otherwise.
+pub async fn readiness(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let (root, database) = tokio::join!(check_root(state.fs.root()), check_database(&state));
+    let search_index = if state.search.is_loaded() {
+        Ok(())
+    } else {
+        Err("Search index has not been loaded yet".to_string())
+    };
+
+    let checks = ReadinessChecks {
+        root: DependencyStatus::from_result(root),
+        database: DependencyStatus::from_result(database),
+        search_index: DependencyStatus::from_result(search_index),
+    };
+    let ready = checks.root.ok && checks.database.ok && checks.search_index.ok;
+    if !ready {
+        warn!("Readiness check failed: {:?}", checks);
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(ReadinessResponse { ready, checks }))
+}
+
+async fn check_root(root: &Path) -> Result<(), String> {
+    let root = root.to_path_buf();
+    // Reads on a hung mount never return, so give up waiting on the thread
+    let read = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(&root)?;
+        if let Some(entry) = entries.next() {
+            entry?;
+        }
+        Ok(())
+    });
+    match tokio::time::timeout(READINESS_TIMEOUT, read).await {
+        Ok(Ok(Ok(()))) => Ok(()),
+        Ok(Ok(Err(e))) => Err(format!("Root is not readable: {e}")),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!(
+            "Root did not respond within {}s",
+            READINESS_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+async fn check_database(state: &AppState) -> Result<(), String> {
+    if state.storage.is_low() {
+        return Err("Database volume is below its free-space reserve".to_string());
+    }
+    let now = Utc::now().to_rfc3339();
+    let write = db::set_index_meta(&state.pool, "ready_checked_at", &now);
+    match tokio::time::timeout(READINESS_TIMEOUT, write).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(format!("Database is not writable: {e}")),
+        Err(_) => Err(format!(
+            "Database did not accept a write within {}s",
+            READINESS_TIMEOUT.as_secs()
+        )),
+    }
+}
+
 /// Statistics endpoint
 pub async fn statistics(
     State(state): State<Arc<AppState>>,
@@ -109,7 +308,460 @@ pub async fn statistics(
     }
 }
 
-fn format_bytes(bytes: i64) -> String {
+/// Kinds of state that expire on their own. Sessions are the only kind today;
+/// shares, trash, and upload sessions belong here as they are added.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpirableKind {
+    Session,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Expirable {
+    pub kind: ExpirableKind,
+    pub id: String,
+    pub expires_at: String,
+    pub expires_in_secs: u64,
+    /// The requester's own session; expiring it logs them out
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpirablesResponse {
+    pub items: Vec<Expirable>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpireRequest {
+    pub kind: ExpirableKind,
+    pub id: String,
+}
+
+/// List everything scheduled to expire, soonest first
+pub async fn list_expirables(
+    State(auth): State<Arc<AuthState>>,
+    jar: CookieJar,
+) -> Json<ExpirablesResponse> {
+    let now = Instant::now();
+    let current_id = jar
+        .get(&auth.config.cookie_name)
+        .map(|cookie| AuthState::session_id(cookie.value()));
+
+    let mut items: Vec<Expirable> = auth
+        .list_sessions()
+        .await
+        .into_iter()
+        .map(|(id, expiry)| {
+            let remaining = expiry.saturating_duration_since(now);
+            let expires_at = Utc::now()
+                + chrono::Duration::from_std(remaining).unwrap_or(chrono::Duration::zero());
+            Expirable {
+                kind: ExpirableKind::Session,
+                current: current_id.as_deref() == Some(id.as_str()),
+                id,
+                expires_at: expires_at.to_rfc3339(),
+                expires_in_secs: remaining.as_secs(),
+            }
+        })
+        .collect();
+    items.sort_by_key(|item| item.expires_in_secs);
+
+    Json(ExpirablesResponse { items })
+}
+
+/// Expire one item immediately
+pub async fn expire_now(
+    State(auth): State<Arc<AuthState>>,
+    Json(req): Json<ExpireRequest>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    let expired = match req.kind {
+        ExpirableKind::Session => auth.invalidate_session_by_id(&req.id).await,
+    };
+
+    if !expired {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No such item: {}", req.id),
+        ));
+    }
+
+    info!("Expired {:?} {} on request", req.kind, req.id);
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: None,
+        message: None,
+        performed: Some(true),
+        sync: None,
+    }))
+}
+
+/// Download a `.tar.gz` with redacted config, recent logs, diagnostics,
+/// index stats, and version info for attaching to bug reports
+pub async fn support_bundle(State(state): State<Arc<SupportState>>) -> Result<Response, ApiError> {
+    let internal_error =
+        |e: std::io::Error| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    let root = state.config.root_path.clone();
+    let diagnostics = Diagnostics {
+        database_status: database_status(&state.app.pool).await,
+        database_storage: state.app.storage.status(),
+        ffprobe_available: MetadataService::is_available(),
+        pdf_tools_available: MetadataService::is_pdf_tools_available(),
+        root_path_exists: root.exists(),
+        root_path_readable: std::fs::read_dir(&root).is_ok(),
+        root_path_read_only: std::fs::metadata(&root)
+            .map(|m| m.permissions().readonly())
+            .unwrap_or(false),
+        root_path_owner: root_owner(&root),
+        integrity: state.integrity.last_report().await,
+    };
+
+    let (total_files, total_size) = db::get_indexed_totals(&state.app.pool)
+        .await
+        .unwrap_or_default();
+    let index = serde_json::json!({
+        "is_running": state.indexer.is_running().await,
+        "last_indexed_at": db::get_last_indexed_at(&state.app.pool).await.ok().flatten(),
+        "total_files_count": total_files,
+        "total_size_bytes": total_size,
+        "last_run": state.indexer.last_stats().await,
+    });
+
+    let mut bundle = SupportBundle::new();
+    bundle
+        .add_json("version.json", &version::current())
+        .map_err(internal_error)?;
+    bundle
+        .add_json("config.json", &redacted_config(&state.config))
+        .map_err(internal_error)?;
+    bundle
+        .add_json("diagnostics.json", &diagnostics)
+        .map_err(internal_error)?;
+    bundle
+        .add_json("index.json", &index)
+        .map_err(internal_error)?;
+    let mut logs = state.logs.lines().join("\n");
+    logs.push('\n');
+    bundle.add_text("logs.txt", logs);
+
+    let name = format!("filex-support-{}", Utc::now().format("%Y%m%d-%H%M%S"));
+    let archive = bundle.into_tar_gz(&name).map_err(internal_error)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}.tar.gz\""),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// Result of the most recent database integrity check, if one has run
+pub async fn integrity_status(
+    State(state): State<Arc<SupportState>>,
+) -> Json<Option<IntegrityReport>> {
+    Json(state.integrity.last_report().await)
+}
+
+/// Run a database integrity check now instead of waiting for the schedule
+pub async fn run_integrity_check(State(state): State<Arc<SupportState>>) -> Json<IntegrityReport> {
+    Json(state.integrity.run_check().await)
+}
+
+fn backup_error(e: BackupError) -> ApiError {
+    let status = match e {
+        BackupError::LowStorage => StatusCode::INSUFFICIENT_STORAGE,
+        BackupError::Invalid(_) => StatusCode::BAD_REQUEST,
+        BackupError::Io(_) | BackupError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+/// Download a consistent snapshot of the database (index, tags, shares,
+/// audit log, ...) taken while the server keeps running
+pub async fn backup(State(state): State<Arc<SupportState>>) -> Result<Response, ApiError> {
+    let file = state.integrity.snapshot().await.map_err(backup_error)?;
+    let size = file
+        .metadata()
+        .await
+        .map_err(|e| backup_error(e.into()))?
+        .len();
+    let name = format!("filex-backup-{}.db", Utc::now().format("%Y%m%d-%H%M%S"));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (header::CONTENT_LENGTH, size.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}\""),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/// Upload a snapshot from `backup` as the request body. It replaces the
+/// database on the next start; the current one is kept next to it.
+pub async fn restore(
+    State(state): State<Arc<SupportState>>,
+    body: Body,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    if state.app.storage.is_low() {
+        return Err(backup_error(BackupError::LowStorage));
+    }
+
+    let upload = state.integrity.upload_path();
+    let written = async {
+        let mut file = tokio::fs::File::create(&upload).await?;
+        let mut stream = body.into_data_stream();
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk.map_err(std::io::Error::other)?)
+                .await?;
+        }
+        file.flush().await
+    }
+    .await;
+    if let Err(e) = written {
+        let _ = tokio::fs::remove_file(&upload).await;
+        return Err(backup_error(e.into()));
+    }
+
+    state
+        .integrity
+        .schedule_restore(&upload)
+        .await
+        .map_err(backup_error)?;
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: None,
+        message: Some("Restore scheduled; restart filex to apply it".into()),
+        performed: Some(true),
+        sync: None,
+    }))
+}
+
+/// A database maintenance step for `/api/admin/db`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbOperation {
+    Vacuum,
+    Analyze,
+    IntegrityCheck,
+    Checkpoint,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DbMaintenanceRequest {
+    /// Steps to run in order; all of them when omitted
+    pub operations: Option<Vec<DbOperation>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DbOperationResult {
+    pub operation: DbOperation,
+    pub ok: bool,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DbMaintenanceResponse {
+    pub results: Vec<DbOperationResult>,
+}
+
+/// Run database maintenance (VACUUM, ANALYZE, integrity check, WAL
+/// checkpoint) and report each step. Steps run in order; a failing step does
+/// not stop the rest.
+pub async fn db_maintenance(
+    State(state): State<Arc<SupportState>>,
+    Json(req): Json<DbMaintenanceRequest>,
+) -> Json<DbMaintenanceResponse> {
+    let operations = req.operations.unwrap_or_else(|| {
+        vec![
+            DbOperation::Checkpoint,
+            DbOperation::IntegrityCheck,
+            DbOperation::Vacuum,
+            DbOperation::Analyze,
+        ]
+    });
+
+    let pool = &state.app.pool;
+    let mut results = Vec::with_capacity(operations.len());
+    for operation in operations {
+        let started_at = Instant::now();
+        let outcome: Result<Option<serde_json::Value>, String> = match operation {
+            DbOperation::Vacuum => {
+                if state.app.storage.is_low() {
+                    Err("Database volume is below its free-space reserve".to_string())
+                } else if state.indexer.is_running().await {
+                    Err("The indexer is running; try again when it is done".to_string())
+                } else {
+                    let size = || {
+                        std::fs::metadata(&state.config.database_path)
+                            .map(|m| m.len())
+                            .ok()
+                    };
+                    let size_before = size();
+                    db::vacuum(pool)
+                        .await
+                        .map(|()| {
+                            Some(serde_json::json!({
+                                "size_before": size_before,
+                                "size_after": size(),
+                            }))
+                        })
+                        .map_err(|e| e.to_string())
+                }
+            }
+            DbOperation::Analyze => db::analyze(pool)
+                .await
+                .map(|()| None)
+                .map_err(|e| e.to_string()),
+            DbOperation::IntegrityCheck => match db::integrity_check(pool).await {
+                Ok(messages) if messages.is_empty() => Ok(None),
+                Ok(messages) => Err(messages.join("; ")),
+                Err(e) => Err(e.to_string()),
+            },
+            DbOperation::Checkpoint => db::wal_checkpoint(pool)
+                .await
+                .map(|result| serde_json::to_value(result).ok())
+                .map_err(|e| e.to_string()),
+        };
+
+        let duration_ms = started_at.elapsed().as_millis() as u64;
+        results.push(match outcome {
+            Ok(details) => DbOperationResult {
+                operation,
+                ok: true,
+                duration_ms,
+                error: None,
+                details,
+            },
+            Err(error) => {
+                warn!("Database {:?} failed: {}", operation, error);
+                DbOperationResult {
+                    operation,
+                    ok: false,
+                    duration_ms,
+                    error: Some(error),
+                    details: None,
+                }
+            }
+        });
+    }
+
+    Json(DbMaintenanceResponse { results })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PermissionsRequest {
+    pub path: String,
+    /// Octal mode bits such as `644`, for files and (unless `dir_mode` is
+    /// given) directories
+    pub mode: Option<String>,
+    pub dir_mode: Option<String>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PermissionsResponse {
+    pub changed: usize,
+    pub failed: usize,
+    pub results: Vec<PermissionResult>,
+}
+
+fn permission_error(e: PermissionError) -> ApiError {
+    let status = match &e {
+        PermissionError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        PermissionError::InvalidMode(_)
+        | PermissionError::NothingToChange
+        | PermissionError::TooManyEntries(_) => StatusCode::BAD_REQUEST,
+        PermissionError::Fs(e) => status_for_fs_error(e),
+    };
+    ApiError::new(status, e.to_string())
+}
+
+/// Change mode bits and ownership of an entry, and with `recursive` of
+/// everything below it. Off unless `FM_ALLOW_PERMISSION_CHANGES` is set;
+/// Unix only. Each path is reported; a failing one does not stop the rest.
+pub async fn change_permissions(
+    State(state): State<Arc<SupportState>>,
+    Json(req): Json<PermissionsRequest>,
+) -> Result<Json<PermissionsResponse>, ApiError> {
+    if !state.config.allow_permission_changes {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Changing permissions is disabled",
+        ));
+    }
+    let parse = |mode: Option<String>| mode.as_deref().map(parse_mode).transpose();
+    let change = PermissionChange {
+        mode: parse(req.mode).map_err(permission_error)?,
+        dir_mode: parse(req.dir_mode).map_err(permission_error)?,
+        uid: req.uid,
+        gid: req.gid,
+    };
+
+    let app = state.app.clone();
+    let path = req.path.clone();
+    let results = tokio::task::spawn_blocking(move || {
+        permissions::change_permissions(&app.fs, &app.dedup, &path, change, req.recursive)
+    })
+    .await
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(permission_error)?;
+    // Stored uploads whose only other link was just given its own copy
+    state.app.dedup.prune_later();
+
+    let failed = results.iter().filter(|r| !r.ok).count();
+    for result in results.iter().filter(|r| !r.ok) {
+        warn!(
+            "Changing permissions of {} failed: {}",
+            result.path,
+            result.error.as_deref().unwrap_or_default()
+        );
+    }
+    info!(
+        "Changed permissions of {} entries under {}",
+        results.len() - failed,
+        req.path
+    );
+    Ok(Json(PermissionsResponse {
+        changed: results.len() - failed,
+        failed,
+        results,
+    }))
+}
+
+/// `uid:gid` owning the root, to compare against PUID/PGID
+#[cfg(unix)]
+fn root_owner(root: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(root)
+        .ok()
+        .map(|m| format!("{}:{}", m.uid(), m.gid()))
+}
+
+#[cfg(not(unix))]
+fn root_owner(_root: &Path) -> Option<String> {
+    None
+}
+
+pub(crate) fn format_bytes(bytes: i64) -> String {
     let units = ["B", "KB", "MB", "GB", "TB", "PB"];
     let mut value = (bytes.max(0)) as f64;
     let mut unit_index = 0;
@@ -130,18 +782,44 @@ fn format_bytes(bytes: i64) -> String {
 pub async fn index_status(State(indexer): State<Arc<IndexerService>>) -> Json<IndexStatusResponse> {
     Json(IndexStatusResponse {
         is_running: indexer.is_running().await,
+        root_mismatch: indexer.root_mismatch().await,
+        search_index: indexer.search_index_status().await,
     })
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct TriggerIndexQuery {
+    /// Remove missing entries even if more than `FM_PURGE_MAX_PERCENT` are gone
+    #[serde(default)]
+    pub confirm_purge: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndexRunsQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexRunsResponse {
+    pub runs: Vec<db::IndexRunRow>,
+}
+
 /// Trigger manual index
 pub async fn trigger_index(
     State(indexer): State<Arc<IndexerService>>,
+    Query(query): Query<TriggerIndexQuery>,
 ) -> Result<Json<IndexStatusResponse>, StatusCode> {
+    if query.confirm_purge {
+        warn!(
+            "Index triggered with confirm_purge; missing entries will be removed regardless of FM_PURGE_MAX_PERCENT"
+        );
+    }
+
     // Spawn indexing in background
     let indexer_clone = indexer.clone();
     tokio::spawn(async move {
         let started_at = Instant::now();
-        match indexer_clone.run_full_index().await {
+        match indexer_clone.run_index(query.confirm_purge).await {
             Ok(stats) => {
                 let elapsed = started_at.elapsed().as_secs_f64();
                 info!(
@@ -160,7 +838,90 @@ pub async fn trigger_index(
         }
     });
 
-    Ok(Json(IndexStatusResponse { is_running: true }))
+    Ok(Json(IndexStatusResponse {
+        is_running: true,
+        root_mismatch: indexer.root_mismatch().await,
+        search_index: indexer.search_index_status().await,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchRebuildResponse {
+    pub entries: usize,
+    pub duration_ms: u64,
+}
+
+/// Rebuild the in-memory search index from the database, e.g. after it
+/// drifted from the index, without restarting
+pub async fn rebuild_search(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<SearchRebuildResponse>, ApiError> {
+    let started_at = Instant::now();
+    let entries = state
+        .search
+        .rebuild_from_db(&state.pool)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(SearchRebuildResponse {
+        entries,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HookRunsQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HookRunsResponse {
+    pub runs: Vec<db::HookRunRow>,
+}
+
+/// Recent runs of server-side hooks with their output, newest first
+pub async fn hook_runs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HookRunsQuery>,
+) -> Result<Json<HookRunsResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let runs = db::list_hook_runs(&state.pool, limit)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(HookRunsResponse { runs }))
+}
+
+/// Recent index runs, newest first, including incomplete and failed ones
+pub async fn index_runs(
+    State(indexer): State<Arc<IndexerService>>,
+    Query(query): Query<IndexRunsQuery>,
+) -> Result<Json<IndexRunsResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 200);
+    let runs = db::list_index_runs(indexer.pool(), limit)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(IndexRunsResponse { runs }))
+}
+
+/// Accept the current root as the one the index belongs to, after it was
+/// intentionally replaced or moved
+pub async fn accept_root(
+    State(indexer): State<Arc<IndexerService>>,
+) -> Result<Json<SuccessResponse>, ApiError> {
+    indexer
+        .accept_root()
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        path: None,
+        message: Some("Root accepted; missing files will be removed on the next index run".into()),
+        performed: Some(true),
+        sync: None,
+    }))
 }
 
 #[cfg(test)]
@@ -169,9 +930,7 @@ mod tests {
     use crate::api::AppState;
     use crate::config::{AuthConfig, Config};
     use crate::db;
-    use crate::services::{FilesystemService, SearchService};
     use sqlx::sqlite::SqlitePoolOptions;
-    use std::time::Duration;
     use tempfile::tempdir;
     use tokio::time::{sleep, timeout};
 
@@ -181,14 +940,51 @@ mod tests {
             host: "127.0.0.1".to_string(),
             port: 0,
             database_path: root.join("filex.db"),
+            db_min_free_mb: 0,
+            root_min_free_mb: 0,
+            db_check_interval_secs: 0,
             enable_indexer: false,
             index_interval_secs: 0,
+            purge_max_percent: 25,
+            show_hidden: false,
+            case_insensitive_paths: false,
+            search_index_max_mb: 0,
+            search_transliterate: false,
+            search_fold_kana: false,
+            ffprobe_concurrency: 2,
+            ffprobe_timeout_secs: 15,
+            webhook_urls: Vec::new(),
+            webhook_secret: None,
+            hooks_file: None,
+            notify_file: None,
+            templates_dir: None,
+            rclone_path: None,
+            clamd_socket: None,
+            quarantine_dir: None,
+            clamd_max_mb: 100,
+            dedup_store: None,
+            allow_links: false,
+            allow_permission_changes: false,
+            download_rate_limit_kib: 0,
+            download_rate_limit_total_kib: 0,
+            readahead_mb: 0,
+            max_downloads: 0,
+            max_downloads_per_session: 0,
+            max_uploads: 0,
+            max_uploads_per_session: 0,
+            request_timeout_secs: 0,
+            transfer_timeout_secs: 0,
+            body_timeout_secs: 0,
             static_path: root.to_path_buf(),
             auth: AuthConfig {
                 enabled: false,
                 password: None,
                 session_timeout_secs: 0,
                 cookie_name: "test".to_string(),
+                oidc: None,
+                proxy_header: None,
+                trusted_proxies: Vec::new(),
+                admin_users: Vec::new(),
             },
         }
     }
@@ -203,34 +999,341 @@ mod tests {
             .unwrap();
         db::init_db(&pool).await.unwrap();
 
-        let state = Arc::new(AppState {
-            fs: FilesystemService::new(tmp.path().to_path_buf()),
-            pool: pool.clone(),
-            search: Arc::new(SearchService::new()),
-        });
+        let state = Arc::new(AppState::for_tests(tmp.path().to_path_buf(), pool.clone()));
 
         let (status, Json(resp)) = health(State(state)).await;
         assert_eq!(status, StatusCode::OK);
         assert_eq!(resp.status, "ok");
         assert!(resp.database_status.connected);
+        assert_eq!(resp.min_client_version, version::MIN_CLIENT_VERSION);
     }
 
     #[tokio::test]
-    async fn statistics_reports_last_indexed_at() {
+    async fn readiness_reports_each_dependency() {
+        let tmp = tempdir().unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+
+        let root = tmp.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        let state = Arc::new(AppState::for_tests(root.clone(), pool.clone()));
+
+        // Not ready until the search index is loaded
+        let (status, Json(resp)) = readiness(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(resp.checks.root.ok);
+        assert!(resp.checks.database.ok);
+        assert!(!resp.checks.search_index.ok);
+
+        state.search.rebuild_from_db(&pool).await.unwrap();
+        let (status, Json(resp)) = readiness(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(resp.ready);
+
+        // A missing root (e.g. an unmounted volume) fails readiness
+        std::fs::remove_dir(&root).unwrap();
+        let (status, Json(resp)) = readiness(State(state)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!resp.checks.root.ok);
+        assert!(resp.checks.root.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn rebuild_search_reloads_index_from_database() {
+        let tmp = tempdir().unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+        sqlx::query("INSERT INTO indexed_files (path, name) VALUES ('/drift.txt', 'drift.txt')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let state = Arc::new(AppState::for_tests(tmp.path().to_path_buf(), pool));
+        assert!(state.search.search("drift").await.is_empty());
+
+        let resp = rebuild_search(State(state.clone())).await.unwrap();
+        assert_eq!(resp.0.entries, 1);
+        assert_eq!(state.search.search("drift").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn db_maintenance_reports_each_operation() {
+        let tmp = tempdir().unwrap();
+        let config = test_config(tmp.path());
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!(
+                "sqlite:{}?mode=rwc",
+                config.database_path.display()
+            ))
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+
+        let app = Arc::new(AppState::for_tests(tmp.path().to_path_buf(), pool.clone()));
+        let state = Arc::new(SupportState {
+            app,
+            indexer: Arc::new(IndexerService::new(pool.clone(), &config, None)),
+            integrity: Arc::new(IntegrityService::new(pool, &config)),
+            config,
+            logs: LogBuffer::new(10),
+        });
+
+        let Json(resp) = db_maintenance(
+            State(state.clone()),
+            Json(DbMaintenanceRequest { operations: None }),
+        )
+        .await;
+        let operations: Vec<_> = resp.results.iter().map(|r| r.operation).collect();
+        assert_eq!(
+            operations,
+            [
+                DbOperation::Checkpoint,
+                DbOperation::IntegrityCheck,
+                DbOperation::Vacuum,
+                DbOperation::Analyze,
+            ]
+        );
+        assert!(resp.results.iter().all(|r| r.ok), "{:?}", resp.results);
+        let vacuum = &resp.results[2];
+        assert!(vacuum.details.as_ref().unwrap()["size_after"].is_u64());
+
+        let Json(resp) = db_maintenance(
+            State(state),
+            Json(DbMaintenanceRequest {
+                operations: Some(vec![DbOperation::Analyze]),
+            }),
+        )
+        .await;
+        assert_eq!(resp.results.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn permission_changes_are_gated_and_reported_per_path() {
+        use std::os::unix::fs::PermissionsExt;
+
         let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(root.join("share")).unwrap();
+        std::fs::write(root.join("share/a.txt"), b"a").unwrap();
+        let mut config = test_config(tmp.path());
         let pool = SqlitePoolOptions::new()
             .max_connections(1)
             .connect("sqlite::memory:")
             .await
             .unwrap();
         db::init_db(&pool).await.unwrap();
+        let app = Arc::new(AppState::for_tests(root.clone(), pool.clone()));
+        let support = |config: Config| {
+            Arc::new(SupportState {
+                app: app.clone(),
+                indexer: Arc::new(IndexerService::new(pool.clone(), &config, None)),
+                integrity: Arc::new(IntegrityService::new(pool.clone(), &config)),
+                config,
+                logs: LogBuffer::new(10),
+            })
+        };
+        let request = |mode: &str| PermissionsRequest {
+            path: "/share".into(),
+            mode: Some(mode.into()),
+            dir_mode: None,
+            uid: None,
+            gid: None,
+            recursive: true,
+        };
+
+        let err = change_permissions(State(support(config.clone())), Json(request("600")))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+
+        config.allow_permission_changes = true;
+        let state = support(config);
+        let err = change_permissions(State(state.clone()), Json(request("999")))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+        let Json(resp) = change_permissions(State(state), Json(request("700")))
+            .await
+            .unwrap();
+        assert_eq!((resp.changed, resp.failed), (2, 0));
+        let mode = std::fs::metadata(root.join("share/a.txt"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[tokio::test]
+    async fn support_bundle_contains_diagnostics_and_redacts_password() {
+        let tmp = tempdir().unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+
+        let mut config = test_config(tmp.path());
+        config.auth.password = Some("hunter2".to_string());
+        let app = Arc::new(AppState::for_tests(tmp.path().to_path_buf(), pool.clone()));
+        let logs = LogBuffer::new(10);
+        {
+            use std::io::Write;
+            use tracing_subscriber::fmt::MakeWriter;
+            writeln!(logs.make_writer(), "INFO started").unwrap();
+        }
+        let state = Arc::new(SupportState {
+            app,
+            indexer: Arc::new(IndexerService::new(pool.clone(), &config, None)),
+            integrity: Arc::new(IntegrityService::new(pool, &config)),
+            config,
+            logs,
+        });
+
+        let response = support_bundle(State(state)).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&body[..]));
+        let mut files = std::collections::HashMap::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry
+                .path()
+                .unwrap()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut text).unwrap();
+            files.insert(name, text);
+        }
+
+        assert!(files["diagnostics.json"].contains("\"root_path_readable\": true"));
+        assert!(files["version.json"].contains(version::VERSION));
+        assert!(!files["config.json"].contains("hunter2"));
+        assert_eq!(files["logs.txt"], "INFO started\n");
+        assert!(files.contains_key("index.json"));
+    }
+
+    #[tokio::test]
+    async fn backup_downloads_a_snapshot_that_restore_accepts() {
+        let tmp = tempdir().unwrap();
+        let config = test_config(tmp.path());
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!(
+                "sqlite:{}?mode=rwc",
+                config.database_path.display()
+            ))
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
 
-        let state = Arc::new(AppState {
-            fs: FilesystemService::new(tmp.path().to_path_buf()),
-            pool: pool.clone(),
-            search: Arc::new(SearchService::new()),
+        let app = Arc::new(AppState::for_tests(tmp.path().to_path_buf(), pool.clone()));
+        let state = Arc::new(SupportState {
+            app,
+            indexer: Arc::new(IndexerService::new(pool.clone(), &config, None)),
+            integrity: Arc::new(IntegrityService::new(pool, &config)),
+            config,
+            logs: LogBuffer::new(10),
         });
 
+        let response = backup(State(state.clone())).await.unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/vnd.sqlite3"
+        );
+        let snapshot = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(snapshot.starts_with(b"SQLite format 3\0"));
+
+        let err = restore(State(state.clone()), Body::from("junk"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+        let resp = restore(State(state.clone()), Body::from(snapshot))
+            .await
+            .unwrap();
+        assert_eq!(resp.0.performed, Some(true));
+        assert!(crate::services::integrity::backup_path(&state.config.database_path).exists());
+    }
+
+    #[tokio::test]
+    async fn expirables_lists_and_expires_sessions() {
+        let mut auth_config = test_config(Path::new("/")).auth;
+        auth_config.enabled = true;
+        auth_config.session_timeout_secs = 60;
+        let auth = Arc::new(AuthState::new(auth_config));
+        let mine = auth.create_session(true).await;
+        let other = auth.create_session(true).await;
+
+        let jar = CookieJar::new().add(axum_extra::extract::cookie::Cookie::new(
+            "test",
+            mine.clone(),
+        ));
+        let Json(resp) = list_expirables(State(auth.clone()), jar).await;
+        assert_eq!(resp.items.len(), 2);
+        assert!(resp.items.iter().all(|i| i.kind == ExpirableKind::Session));
+        assert!(resp.items.iter().all(|i| i.id != mine && i.id != other));
+        let current: Vec<_> = resp.items.iter().filter(|i| i.current).collect();
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].id, AuthState::session_id(&mine));
+
+        let other_id = AuthState::session_id(&other);
+        let Json(resp) = expire_now(
+            State(auth.clone()),
+            Json(ExpireRequest {
+                kind: ExpirableKind::Session,
+                id: other_id.clone(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.performed, Some(true));
+        assert!(!auth.validate_session(&other).await);
+        assert!(auth.validate_session(&mine).await);
+
+        let err = expire_now(
+            State(auth),
+            Json(ExpireRequest {
+                kind: ExpirableKind::Session,
+                id: other_id,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn statistics_reports_last_indexed_at() {
+        let tmp = tempdir().unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(tmp.path().to_path_buf(), pool.clone()));
+
         let (status, Json(resp)) = statistics(State(state)).await;
         assert_eq!(status, StatusCode::OK);
         assert!(resp.last_indexed_at.is_none());
@@ -272,7 +1375,9 @@ mod tests {
 
         let indexer = Arc::new(IndexerService::new(pool.clone(), &test_config(&root), None));
 
-        let Json(resp) = trigger_index(State(indexer.clone())).await.unwrap();
+        let Json(resp) = trigger_index(State(indexer.clone()), Query(TriggerIndexQuery::default()))
+            .await
+            .unwrap();
         assert!(resp.is_running);
 
         // Wait until the indexed row appears.
@@ -0,0 +1,89 @@
+//! Request and body timeouts, so stalled clients or dead storage cannot pin
+//! handler tasks forever.
+
+use axum::http::StatusCode;
+use std::time::Duration;
+use tower::layer::util::{Identity, Stack};
+use tower::util::{Either, option_layer};
+use tower_http::timeout::{RequestBodyTimeoutLayer, ResponseBodyTimeoutLayer, TimeoutLayer};
+
+/// Fail requests whose handler has not responded within `timeout` with
+/// `408 Request Timeout`. A zero timeout disables the limit.
+pub fn request_timeout(timeout: Duration) -> Either<TimeoutLayer, Identity> {
+    option_layer(
+        (!timeout.is_zero())
+            .then(|| TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, timeout)),
+    )
+}
+
+/// Abort request and response bodies that go `timeout` without a chunk.
+/// Long transfers are fine as long as data keeps moving. A zero timeout
+/// disables the limit.
+pub fn body_idle_timeout(
+    timeout: Duration,
+) -> Stack<RequestBodyTimeoutLayer, ResponseBodyTimeoutLayer> {
+    // The body types differ with and without the layers, so "disabled" is a
+    // timeout that never fires rather than a missing layer
+    let timeout = if timeout.is_zero() {
+        Duration::MAX
+    } else {
+        timeout
+    };
+    Stack::new(
+        RequestBodyTimeoutLayer::new(timeout),
+        ResponseBodyTimeoutLayer::new(timeout),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::Request, routing::post};
+    use futures_util::stream;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn slow_handlers_and_stalled_bodies_time_out() {
+        let app = Router::new()
+            .route(
+                "/slow",
+                post(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "done"
+                }),
+            )
+            .route("/echo", post(|body: String| async move { body }))
+            .layer(request_timeout(Duration::from_millis(500)))
+            .layer(body_idle_timeout(Duration::from_millis(20)));
+
+        let slow = Request::post("/slow").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(slow).await.unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+
+        // A body that never finishes arriving
+        let stalled = Body::from_stream(stream::pending::<Result<Vec<u8>, std::io::Error>>());
+        let response = app
+            .clone()
+            .oneshot(Request::post("/echo").body(stalled).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .oneshot(Request::post("/echo").body(Body::from("hi")).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Zero disables
+        assert!(matches!(request_timeout(Duration::ZERO), Either::Right(_)));
+        let unlimited = Router::new()
+            .route("/echo", post(|body: String| async move { body }))
+            .layer(body_idle_timeout(Duration::ZERO));
+        let response = unlimited
+            .oneshot(Request::post("/echo").body(Body::from("hi")).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,135 @@
+//! Concurrency limits for downloads and uploads (see `services::transfers`).
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{HeaderValue, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use std::sync::Arc;
+
+use crate::api::ApiError;
+use crate::api::auth::Caller;
+use crate::api::usage::operation_name;
+use crate::services::transfers::{TransferKind, TransferManager};
+
+/// Seconds a client is asked to wait before retrying a refused transfer
+const RETRY_AFTER_SECS: u64 = 5;
+
+pub struct TransferState {
+    pub manager: TransferManager,
+}
+
+/// Which limit a route counts against, if any
+fn transfer_kind(operation: &str) -> Option<TransferKind> {
+    if operation.starts_with("files/download") {
+        Some(TransferKind::Download)
+    } else if operation.starts_with("files/upload") {
+        Some(TransferKind::Upload)
+    } else {
+        None
+    }
+}
+
+/// Refuse downloads and uploads beyond the configured concurrency with
+/// `429 Too Many Requests`. A transfer holds its slot until the response
+/// body has been sent or dropped.
+pub async fn transfer_limit_middleware(
+    State(state): State<Arc<TransferState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(kind) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| transfer_kind(&operation_name(path.as_str())))
+    else {
+        return next.run(request).await;
+    };
+
+    let client = Caller::id_of(request.extensions());
+    let Some(permit) = state.manager.try_start(&client, kind) else {
+        let what = match kind {
+            TransferKind::Download => "downloads",
+            TransferKind::Upload => "uploads",
+        };
+        let mut response = ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Too many {what} in progress; try again shortly"),
+        )
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+        return response;
+    };
+
+    let response = next.run(request).await;
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().map(move |chunk| {
+            let _slot = &permit;
+            chunk
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transfers::TransferLimits;
+    use axum::{Router, middleware, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn refuses_transfers_beyond_the_limit_until_one_finishes() {
+        let manager = TransferManager::new(
+            TransferLimits {
+                per_session: 0,
+                total: 1,
+            },
+            TransferLimits::default(),
+        );
+        let state = Arc::new(TransferState {
+            manager: manager.clone(),
+        });
+        let app = Router::new()
+            .route("/api/files/download", get(|| async { "data" }))
+            .route("/api/browse", get(|| async { "listing" }))
+            .route_layer(middleware::from_fn_with_state(
+                state,
+                transfer_limit_middleware,
+            ));
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        // The first download's body has not been read yet, so it holds its slot
+        let first = app
+            .clone()
+            .oneshot(get("/api/files/download"))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(manager.active(TransferKind::Download), 1);
+
+        let refused = app
+            .clone()
+            .oneshot(get("/api/files/download"))
+            .await
+            .unwrap();
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(refused.headers()[header::RETRY_AFTER], "5");
+
+        // Other routes are not limited
+        let other = app.clone().oneshot(get("/api/browse")).await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(first.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"data");
+        assert_eq!(manager.active(TransferKind::Download), 0);
+        let again = app.oneshot(get("/api/files/download")).await.unwrap();
+        assert_eq!(again.status(), StatusCode::OK);
+    }
+}
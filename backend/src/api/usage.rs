@@ -0,0 +1,160 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{Extensions, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::api::auth::Caller;
+use crate::services::UsageTracker;
+
+pub struct UsageState {
+    pub tracker: UsageTracker,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientUsageEntry {
+    pub client: String,
+    pub current: bool,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+    pub operations: BTreeMap<String, u64>,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageResponse {
+    pub since: DateTime<Utc>,
+    pub clients: Vec<ClientUsageEntry>,
+}
+
+/// Count the request as an operation and meter body bytes in both
+/// directions as they are streamed. Runs inside the auth layer, so
+/// rejected requests are not counted.
+pub async fn usage_middleware(
+    State(state): State<Arc<UsageState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let client = Caller::id_of(request.extensions());
+    let operation = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| operation_name(path.as_str()))
+        .unwrap_or_else(|| "other".to_string());
+    state.tracker.record_operation(&client, &operation);
+
+    let tracker = state.tracker.clone();
+    let upload_client = client.clone();
+    let request = request.map(|body| {
+        Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                tracker.add_uploaded(&upload_client, chunk.len() as u64);
+            }
+        }))
+    });
+
+    let response = next.run(request).await;
+
+    let tracker = state.tracker.clone();
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                tracker.add_downloaded(&client, chunk.len() as u64);
+            }
+        }))
+    })
+}
+
+/// `/api/files/upload/{*path}` becomes `files/upload`
+pub(crate) fn operation_name(route: &str) -> String {
+    route
+        .trim_start_matches("/api/")
+        .split('/')
+        .filter(|segment| !segment.is_empty() && !segment.starts_with('{'))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Bytes and operation counts per client since startup
+pub async fn usage(
+    State(state): State<Arc<UsageState>>,
+    extensions: Extensions,
+) -> Json<UsageResponse> {
+    let current = Caller::id_of(&extensions);
+    let mut clients: Vec<_> = state
+        .tracker
+        .snapshot()
+        .into_iter()
+        .map(|(client, usage)| ClientUsageEntry {
+            current: client == current,
+            client,
+            bytes_uploaded: usage.bytes_uploaded,
+            bytes_downloaded: usage.bytes_downloaded,
+            operations: usage.operations,
+            last_seen: usage.last_seen,
+        })
+        .collect();
+    clients.sort_by(|a, b| {
+        (b.bytes_uploaded + b.bytes_downloaded).cmp(&(a.bytes_uploaded + a.bytes_downloaded))
+    });
+
+    Json(UsageResponse {
+        since: state.tracker.since(),
+        clients,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::StatusCode, middleware, routing::post};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn middleware_meters_bytes_and_operations_per_caller() {
+        let state = Arc::new(UsageState {
+            tracker: UsageTracker::new(),
+        });
+        let app = Router::new()
+            .route(
+                "/api/files/upload/{*path}",
+                post(|body: String| async move { format!("got {}", body.len()) }),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                usage_middleware,
+            ));
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/files/upload/a/b.txt")
+            .extension(Caller {
+                id: "user:alex".to_string(),
+                admin: false,
+            })
+            .body(Body::from("hello world"))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"got 11");
+
+        let snapshot = state.tracker.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        let (client, usage) = &snapshot[0];
+        assert_eq!(client, "user:alex");
+        assert_eq!(usage.bytes_uploaded, 11);
+        assert_eq!(usage.bytes_downloaded, 6);
+        assert_eq!(usage.operations.get("files/upload"), Some(&1));
+    }
+}
@@ -0,0 +1,638 @@
+//! Library-wide views over the index: every image, every video, or all
+//! media newest first, regardless of which folder it is in; a timeline of
+//! media by when it was taken; and a map of where it was taken.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::{ApiError, AppState, ErrorResponse, SortField, SortOrder};
+use crate::db::{self, BoundingBox, MediaClass};
+use crate::models::FileEntry;
+use crate::services::filesystem::FsError;
+
+#[derive(Debug, Deserialize)]
+pub struct ViewQuery {
+    /// Only files under this directory; the whole library by default
+    pub path: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    pub sort_by: Option<SortField>,
+    pub sort_order: Option<SortOrder>,
+    pub show_hidden: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ViewResponse {
+    pub path: String,
+    pub entries: Vec<FileEntry>,
+    pub offset: usize,
+    pub limit: usize,
+    pub sort_by: SortField,
+    pub sort_order: SortOrder,
+    pub total: i64,
+}
+
+/// Every indexed image, newest first by default
+pub async fn images(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ViewQuery>,
+) -> Result<Json<ViewResponse>, ApiError> {
+    list_view(&state, MediaClass::Images, query).await
+}
+
+/// Every indexed video, newest first by default
+pub async fn videos(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ViewQuery>,
+) -> Result<Json<ViewResponse>, ApiError> {
+    list_view(&state, MediaClass::Videos, query).await
+}
+
+/// Images and videos together, newest first by default
+pub async fn recent(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ViewQuery>,
+) -> Result<Json<ViewResponse>, ApiError> {
+    list_view(&state, MediaClass::Media, query).await
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimelineGroup {
+    Year,
+    Month,
+    Day,
+}
+
+impl TimelineGroup {
+    /// Length of the period keys: `2024`, `2024-05`, `2024-05-01`
+    fn key_len(self) -> usize {
+        match self {
+            Self::Year => 4,
+            Self::Month => 7,
+            Self::Day => 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimelineQuery {
+    /// Only files under this directory; the whole library by default
+    pub path: Option<String>,
+    /// Period to count files by; months by default
+    pub group: Option<TimelineGroup>,
+    /// List only files taken in this period, e.g. `2024-05`
+    pub bucket: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    pub show_hidden: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimelineBucket {
+    pub key: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimelineEntry {
+    #[serde(flatten)]
+    pub entry: FileEntry,
+    /// When the file was taken (camera local time) or, without a capture
+    /// date, last modified
+    pub taken_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimelineResponse {
+    pub path: String,
+    pub group: TimelineGroup,
+    /// Every period with media in it, newest first
+    pub buckets: Vec<TimelineBucket>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    /// One page of the files in `bucket`, or in all periods, newest first
+    pub entries: Vec<TimelineEntry>,
+    pub offset: usize,
+    pub limit: usize,
+    pub total: i64,
+}
+
+/// Images and videos grouped by the date they were taken
+pub async fn timeline(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Json<TimelineResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(200).clamp(1, 5000);
+    let offset = query.offset.unwrap_or(0);
+    let group = query.group.unwrap_or(TimelineGroup::Month);
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
+    let bucket = query.bucket.filter(|b| !b.is_empty());
+    if let Some(bucket) = &bucket
+        && !is_period_key(bucket)
+    {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid bucket: {bucket}"),
+        ));
+    }
+
+    let path = resolve_dir(&state, query.path)?;
+    let internal = |e: sqlx::Error| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    let buckets = db::get_timeline_buckets(
+        &state.pool,
+        MediaClass::Media,
+        &path,
+        show_hidden,
+        group.key_len() as i64,
+    )
+    .await
+    .map_err(internal)?
+    .into_iter()
+    .map(|(key, count)| TimelineBucket { key, count })
+    .collect();
+
+    let (rows, total) = db::list_timeline(
+        &state.pool,
+        MediaClass::Media,
+        &path,
+        show_hidden,
+        bucket.as_deref().unwrap_or(""),
+        limit as i64,
+        offset as i64,
+    )
+    .await
+    .map_err(internal)?;
+
+    Ok(Json(TimelineResponse {
+        path,
+        group,
+        buckets,
+        bucket,
+        entries: rows
+            .into_iter()
+            .map(|row| TimelineEntry {
+                entry: FileEntry::from(row.file),
+                taken_at: row.taken_at,
+            })
+            .collect(),
+        offset,
+        limit,
+        total,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MapQuery {
+    /// `west,south,east,north` in degrees; the whole world by default
+    pub bbox: Option<String>,
+    /// Only files under this directory; the whole library by default
+    pub path: Option<String>,
+    /// Merge files close together into one point; on by default
+    pub cluster: Option<bool>,
+    /// Cells per side the box is cut into when clustering
+    pub grid: Option<u32>,
+    pub limit: Option<usize>,
+    pub show_hidden: Option<bool>,
+}
+
+/// GeoJSON `FeatureCollection` of geotagged media
+#[derive(Debug, Serialize)]
+pub struct MapResponse {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub features: Vec<MapFeature>,
+    /// More points were in the box than `limit`
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MapFeature {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub geometry: MapPoint,
+    pub properties: MapProperties,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MapPoint {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// `[longitude, latitude]`, as GeoJSON orders them
+    pub coordinates: [f64; 2],
+}
+
+#[derive(Debug, Serialize)]
+pub struct MapProperties {
+    /// Several files merged into one point
+    pub cluster: bool,
+    pub count: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<String>,
+}
+
+/// Geotagged images and videos inside a bounding box, as GeoJSON points
+pub async fn map(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<MapQuery>,
+) -> Result<Json<MapResponse>, ApiError> {
+    let bbox = match query.bbox.as_deref() {
+        Some(bbox) => parse_bbox(bbox).ok_or_else(|| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid bbox: {bbox} (expected west,south,east,north with west < east)"),
+            )
+        })?,
+        None => BoundingBox {
+            west: -180.0,
+            south: -90.0,
+            east: 180.0,
+            north: 90.0,
+        },
+    };
+    let grid = query
+        .cluster
+        .unwrap_or(true)
+        .then(|| query.grid.unwrap_or(64).clamp(1, 512));
+    let limit = query.limit.unwrap_or(5000).clamp(1, 20000);
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
+    let path = resolve_dir(&state, query.path)?;
+
+    // One extra row tells whether the result was cut off
+    let mut rows = db::get_map_points(
+        &state.pool,
+        MediaClass::Media,
+        &path,
+        show_hidden,
+        bbox,
+        grid,
+        limit as i64 + 1,
+    )
+    .await
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let truncated = rows.len() > limit;
+    rows.truncate(limit);
+
+    let features = rows
+        .into_iter()
+        .map(|row| {
+            let single = row.count == 1;
+            MapFeature {
+                kind: "Feature",
+                geometry: MapPoint {
+                    kind: "Point",
+                    coordinates: [row.longitude, row.latitude],
+                },
+                properties: MapProperties {
+                    cluster: !single,
+                    count: row.count,
+                    path: single.then_some(row.path),
+                    name: single.then_some(row.name),
+                    mime_type: row.mime_type.filter(|_| single),
+                    taken_at: row.taken_at.filter(|_| single),
+                },
+            }
+        })
+        .collect();
+
+    Ok(Json(MapResponse {
+        kind: "FeatureCollection",
+        features,
+        truncated,
+    }))
+}
+
+/// `west,south,east,north`, inside the world and not crossing the
+/// antimeridian
+fn parse_bbox(value: &str) -> Option<BoundingBox> {
+    let parts: Vec<f64> = value
+        .split(',')
+        .map(|part| part.trim().parse().ok().filter(|n: &f64| n.is_finite()))
+        .collect::<Option<_>>()?;
+    let [west, south, east, north] = parts[..] else {
+        return None;
+    };
+    let valid = (-180.0..=180.0).contains(&west)
+        && (-180.0..=180.0).contains(&east)
+        && (-90.0..=90.0).contains(&south)
+        && (-90.0..=90.0).contains(&north)
+        && west < east
+        && south < north;
+    valid.then_some(BoundingBox {
+        west,
+        south,
+        east,
+        north,
+    })
+}
+
+/// `2024`, `2024-05`, or `2024-05-01`
+fn is_period_key(key: &str) -> bool {
+    matches!(key.len(), 4 | 7 | 10)
+        && key.char_indices().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// Index path of a directory given by the client, the root by default
+fn resolve_dir(state: &AppState, path: Option<String>) -> Result<String, ApiError> {
+    let path = path.unwrap_or_else(|| "/".to_string());
+    let resolved = state.fs.resolve_path(&path).map_err(|e| {
+        let status = match &e {
+            FsError::NotFound(_) => StatusCode::NOT_FOUND,
+            FsError::PermissionDenied(_) | FsError::PathEscape => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::with_body(status, ErrorResponse::from(&e))
+    })?;
+    Ok(state.fs.relative_path(&resolved))
+}
+
+async fn list_view(
+    state: &AppState,
+    class: MediaClass,
+    query: ViewQuery,
+) -> Result<Json<ViewResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(200).clamp(1, 5000);
+    let offset = query.offset.unwrap_or(0);
+    let sort_by = query.sort_by.unwrap_or(SortField::Modified);
+    let sort_order = query.sort_order.unwrap_or(SortOrder::Desc);
+    let show_hidden = query.show_hidden.unwrap_or(state.show_hidden);
+
+    let path = resolve_dir(state, query.path)?;
+
+    let (rows, total) = db::list_media(
+        &state.pool,
+        class,
+        &path,
+        show_hidden,
+        sort_by.into(),
+        sort_order.into(),
+        limit as i64,
+        offset as i64,
+    )
+    .await
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(ViewResponse {
+        path,
+        entries: rows.into_iter().map(FileEntry::from).collect(),
+        offset,
+        limit,
+        sort_by,
+        sort_order,
+        total,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn test_state() -> (Arc<AppState>, tempfile::TempDir) {
+        let tmp = tempdir().expect("tempdir created");
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let state = Arc::new(AppState::for_tests(root, pool));
+
+        (state, tmp)
+    }
+
+    async fn seed(state: &AppState, path: &str, mime_type: &str, modified_at: &str) {
+        let row = crate::models::IndexedFileRow {
+            size: Some(1),
+            modified_at: Some(modified_at.to_string()),
+            mime_type: Some(mime_type.to_string()),
+            indexed_at: "2024-01-01 00:00:00".to_string(),
+            ..crate::models::IndexedFileRow::for_tests(path, false)
+        };
+        crate::db::upsert_file(&state.pool, &row).await.unwrap();
+    }
+
+    fn query() -> ViewQuery {
+        ViewQuery {
+            path: None,
+            offset: None,
+            limit: None,
+            sort_by: None,
+            sort_order: None,
+            show_hidden: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn views_slice_the_library_by_type() {
+        let (state, _tmp) = test_state().await;
+        for (path, mime_type, modified_at) in [
+            ("/a/old.jpg", "image/jpeg", "2023-01-01T00:00:00+00:00"),
+            ("/b/c/new.png", "image/png", "2024-06-01T00:00:00+00:00"),
+            ("/clip.mp4", "video/mp4", "2024-01-01T00:00:00+00:00"),
+            ("/song.mp3", "audio/mpeg", "2024-07-01T00:00:00+00:00"),
+            (
+                "/.cache/thumb.jpg",
+                "image/jpeg",
+                "2024-08-01T00:00:00+00:00",
+            ),
+        ] {
+            seed(&state, path, mime_type, modified_at).await;
+        }
+
+        let resp = images(State(state.clone()), Query(query())).await.unwrap();
+        let paths: Vec<_> = resp.0.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/b/c/new.png", "/a/old.jpg"]);
+        assert_eq!(resp.0.total, 2);
+
+        let resp = videos(State(state.clone()), Query(query())).await.unwrap();
+        assert_eq!(resp.0.total, 1);
+        assert_eq!(resp.0.entries[0].path, "/clip.mp4");
+
+        let resp = recent(
+            State(state.clone()),
+            Query(ViewQuery {
+                limit: Some(2),
+                offset: Some(1),
+                ..query()
+            }),
+        )
+        .await
+        .unwrap();
+        let paths: Vec<_> = resp.0.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/clip.mp4", "/a/old.jpg"]);
+        assert_eq!(resp.0.total, 3);
+
+        let resp = images(
+            State(state),
+            Query(ViewQuery {
+                show_hidden: Some(true),
+                sort_by: Some(SortField::Name),
+                sort_order: Some(SortOrder::Asc),
+                ..query()
+            }),
+        )
+        .await
+        .unwrap();
+        let names: Vec<_> = resp.0.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["new.png", "old.jpg", "thumb.jpg"]);
+    }
+
+    #[tokio::test]
+    async fn timeline_buckets_media_by_capture_date() {
+        let (state, _tmp) = test_state().await;
+        for (path, mime_type, modified_at) in [
+            ("/a.jpg", "image/jpeg", "2024-06-03T08:00:00+00:00"),
+            ("/b.jpg", "image/jpeg", "2024-06-20T08:00:00+00:00"),
+            ("/c.mp4", "video/mp4", "2024-07-01T08:00:00+00:00"),
+            ("/d.txt", "text/plain", "2024-07-01T08:00:00+00:00"),
+        ] {
+            seed(&state, path, mime_type, modified_at).await;
+        }
+        // Taken years before it was copied here
+        crate::db::update_media_metadata(
+            &state.pool,
+            "/b.jpg",
+            None,
+            None,
+            None,
+            &Default::default(),
+            chrono::NaiveDate::from_ymd_opt(2019, 12, 31)
+                .unwrap()
+                .and_hms_opt(23, 0, 0),
+            None,
+            "complete",
+        )
+        .await
+        .unwrap();
+
+        let query = |group, bucket: Option<&str>| TimelineQuery {
+            path: None,
+            group,
+            bucket: bucket.map(str::to_string),
+            offset: None,
+            limit: None,
+            show_hidden: None,
+        };
+
+        let resp = timeline(State(state.clone()), Query(query(None, None)))
+            .await
+            .unwrap();
+        let buckets: Vec<_> = resp
+            .0
+            .buckets
+            .iter()
+            .map(|b| (b.key.as_str(), b.count))
+            .collect();
+        assert_eq!(buckets, [("2024-07", 1), ("2024-06", 1), ("2019-12", 1)]);
+        let paths: Vec<_> = resp
+            .0
+            .entries
+            .iter()
+            .map(|e| e.entry.path.as_str())
+            .collect();
+        assert_eq!(paths, ["/c.mp4", "/a.jpg", "/b.jpg"]);
+        assert_eq!(resp.0.entries[2].taken_at, "2019-12-31T23:00:00");
+
+        let resp = timeline(
+            State(state.clone()),
+            Query(query(Some(TimelineGroup::Year), Some("2024"))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.0.buckets.len(), 2);
+        assert_eq!(resp.0.total, 2);
+
+        let err = timeline(State(state), Query(query(None, Some("2024-6"))))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn map_returns_geotagged_media_as_geojson() {
+        let (state, _tmp) = test_state().await;
+        for (path, location) in [
+            ("/amsterdam/1.jpg", (52.370, 4.895)),
+            ("/amsterdam/2.jpg", (52.371, 4.896)),
+            ("/sydney.jpg", (-33.868, 151.209)),
+            ("/unknown.jpg", (0.0, 0.0)),
+        ] {
+            seed(&state, path, "image/jpeg", "2024-01-01T00:00:00+00:00").await;
+            crate::db::update_media_metadata(
+                &state.pool,
+                path,
+                None,
+                None,
+                None,
+                &Default::default(),
+                None,
+                crate::services::exif::valid_location(location.0, location.1),
+                "complete",
+            )
+            .await
+            .unwrap();
+        }
+
+        let query = |bbox: Option<&str>, cluster| MapQuery {
+            bbox: bbox.map(str::to_string),
+            path: None,
+            cluster,
+            grid: None,
+            limit: None,
+            show_hidden: None,
+        };
+
+        let resp = map(State(state.clone()), Query(query(None, None)))
+            .await
+            .unwrap();
+        assert_eq!(resp.0.features.len(), 2);
+        let cluster = &resp.0.features[0].properties;
+        assert!(cluster.cluster);
+        assert_eq!(cluster.count, 2);
+        assert_eq!(cluster.path, None);
+        let single = &resp.0.features[1];
+        assert_eq!(single.properties.path.as_deref(), Some("/sydney.jpg"));
+        assert_eq!(single.geometry.coordinates, [151.209, -33.868]);
+
+        let resp = map(
+            State(state.clone()),
+            Query(query(Some("4,52,5,53"), Some(false))),
+        )
+        .await
+        .unwrap();
+        let paths: Vec<_> = resp
+            .0
+            .features
+            .iter()
+            .map(|f| f.properties.path.as_deref().unwrap())
+            .collect();
+        assert_eq!(paths, ["/amsterdam/1.jpg", "/amsterdam/2.jpg"]);
+
+        let err = map(State(state), Query(query(Some("170,0,-170,10"), None)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+}
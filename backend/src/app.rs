@@ -0,0 +1,591 @@
+//! Wiring of the services and routes behind the HTTP API, for the server
+//! binary and for applications that embed Filex.
+
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
+};
+use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::cors::{Any, CorsLayer};
+use tower_http::services::{ServeDir, ServeFile};
+use tower_http::trace::TraceLayer;
+
+use crate::{
+    api::{self, AppState, AuthState},
+    config::Config,
+    db,
+    services::{
+        DedupStore, DownloadThrottle, FileTemplates, FilesystemService, HookService,
+        IndexerService, IntegrityService, LiveUpdates, LockManager, LogBuffer, NotificationService,
+        Readahead, RemoteSyncService, SearchService, StorageBackend, StorageGuard, SyncJobService,
+        TransferManager, UploadTracker, UsageTracker, VirusScanner, WebhookService, integrity,
+        search_index::Normalization,
+    },
+};
+
+/// Open the database, start the background indexer and integrity checks
+/// `config` asks for, and return the router serving the API and the
+/// frontend. Must be called inside a Tokio runtime. Serve it with
+/// `into_make_service_with_connect_info::<SocketAddr>()`, which proxy
+/// authentication needs to see who sent a request.
+pub async fn build_router(config: &Config) -> anyhow::Result<Router> {
+    build_router_with_logs(config, LogBuffer::new(2000)).await
+}
+
+/// Like `build_router`, with the buffer of recent log lines that support
+/// bundles include; the caller installs it as a `tracing` writer
+pub async fn build_router_with_logs(config: &Config, logs: LogBuffer) -> anyhow::Result<Router> {
+    // Ensure database directory exists
+    if let Some(parent) = config.database_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    // Swap in the last known-good backup if the previous integrity check failed
+    match integrity::restore_if_scheduled(&config.database_path) {
+        Ok(true) => tracing::warn!("Database restored from backup after a failed integrity check"),
+        Ok(false) => {}
+        Err(e) => tracing::error!("Scheduled database restore failed: {}", e),
+    }
+
+    // Initialize database
+    let db_url = format!("sqlite:{}?mode=rwc", config.database_path.display());
+    let pool = SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect(&db_url)
+        .await?;
+
+    db::init_db(&pool).await?;
+    let merged = db::set_case_insensitive_paths(&pool, config.case_insensitive_paths).await?;
+    if merged > 0 {
+        tracing::info!("Merged {} index rows that differed only in case", merged);
+    }
+    tracing::info!("Database initialized");
+
+    // Initialize services
+    let fs = FilesystemService::new(config.root_path.clone());
+
+    // Initialize search service and populate index from database
+    let search_service = Arc::new(
+        SearchService::with_case_insensitive_paths(config.case_insensitive_paths)
+            .with_memory_limit(config.search_index_max_mb as usize * 1024 * 1024)
+            .with_normalization(Normalization {
+                transliterate: config.search_transliterate,
+                fold_kana: config.search_fold_kana,
+            }),
+    );
+    if let Err(e) = search_service.rebuild_from_db(&pool).await {
+        tracing::warn!("Initial search index build failed: {}", e);
+    }
+
+    let webhooks = Arc::new(WebhookService::from_config(config));
+    if webhooks.is_enabled() {
+        tracing::info!("Webhooks: {} URL(s)", config.webhook_urls.len());
+    }
+
+    let hooks = Arc::new(HookService::from_config(config, pool.clone())?);
+    if !hooks.is_empty() {
+        tracing::info!("Hooks: {} configured", hooks.len());
+    }
+
+    let live = LiveUpdates::new();
+
+    let notifications = Arc::new(NotificationService::from_config(config, &live)?);
+    if notifications.is_enabled() {
+        tracing::info!("Notifications enabled");
+    }
+
+    let backend: Arc<dyn StorageBackend> =
+        Arc::new(FilesystemService::new(config.root_path.clone()));
+    let indexer = Arc::new(
+        IndexerService::new(pool.clone(), config, Some(search_service.clone()))
+            .with_backend(backend.clone())
+            .with_webhooks(webhooks.clone())
+            .with_live_updates(live.clone())
+            .with_notifications(notifications),
+    );
+
+    // Catch a remounted or wrong root before the first index run purges
+    // everything it cannot find
+    if config.enable_indexer {
+        indexer.verify_root().await;
+    }
+
+    // Initialize auth state
+    let auth_state = Arc::new(AuthState::new(config.auth.clone()));
+
+    // Start background indexer if enabled
+    if config.enable_indexer {
+        let indexer_clone = indexer.clone();
+        let interval = config.index_interval_secs;
+        tokio::spawn(async move {
+            indexer_clone.start_background_loop(interval).await;
+        });
+    }
+
+    let integrity = Arc::new(IntegrityService::new(pool.clone(), config));
+    if config.db_check_interval_secs > 0 {
+        let integrity_clone = integrity.clone();
+        let interval = config.db_check_interval_secs;
+        tokio::spawn(async move {
+            integrity_clone.start_background_loop(interval).await;
+        });
+    }
+
+    let sync_jobs = Arc::new(SyncJobService::new(
+        pool.clone(),
+        FilesystemService::new(config.root_path.clone()),
+    ));
+    {
+        let sync_jobs_clone = sync_jobs.clone();
+        tokio::spawn(async move {
+            sync_jobs_clone.start_background_loop().await;
+        });
+    }
+
+    let remotes = Arc::new(RemoteSyncService::from_config(config));
+    if remotes.is_enabled() {
+        tracing::info!("rclone remotes enabled");
+    }
+
+    // Shared state
+    let storage = StorageGuard::from_config(config);
+    if storage.is_low() {
+        tracing::error!(
+            "Database volume is below the FM_DB_MIN_FREE_MB reserve; indexing will not write until space is freed"
+        );
+    }
+    let app_state = Arc::new(AppState {
+        fs,
+        backend,
+        pool,
+        search: search_service,
+        storage,
+        root_storage: StorageGuard::for_root(config),
+        show_hidden: config.show_hidden,
+        webhooks,
+        hooks,
+        uploads: UploadTracker::new(),
+        downloads: DownloadThrottle::from_config(config),
+        readahead: Readahead::from_config(config),
+        locks: LockManager::new(),
+        live,
+        templates: FileTemplates::from_config(config),
+        allow_links: config.allow_links,
+        scanner: VirusScanner::from_config(config),
+        dedup: Arc::new(DedupStore::from_config(config)),
+    });
+    if app_state.dedup.is_enabled() {
+        tracing::info!("Deduplicated upload store enabled");
+        // Objects whose files were deleted while the server was down
+        app_state.dedup.prune_later();
+    }
+
+    let support_state = Arc::new(api::system::SupportState {
+        app: app_state.clone(),
+        indexer: indexer.clone(),
+        config: config.clone(),
+        logs,
+        integrity,
+    });
+
+    let usage_state = Arc::new(api::usage::UsageState {
+        tracker: UsageTracker::new(),
+    });
+
+    let transfer_state = Arc::new(api::transfers::TransferState {
+        manager: TransferManager::from_config(config),
+    });
+
+    let idempotency_state = Arc::new(api::idempotency::IdempotencyState::new(
+        app_state.pool.clone(),
+    ));
+
+    // Transfers and admin backups move whole files in one request, so they
+    // get a longer limit than everything else
+    let request_timeout =
+        api::timeouts::request_timeout(Duration::from_secs(config.request_timeout_secs));
+    let transfer_timeout =
+        api::timeouts::request_timeout(Duration::from_secs(config.transfer_timeout_secs));
+
+    // CORS configuration
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any);
+
+    // Protected routes that require authentication
+    let protected_routes = Router::new()
+        .route("/api/browse", get(api::browse::list_directory))
+        .route("/api/browse/stats", get(api::browse::directory_stats))
+        .route("/api/recent-additions", get(api::browse::recent_additions))
+        .route("/api/tree", get(api::browse::get_tree))
+        .route("/api/search", get(api::search::search_files))
+        .route("/api/search/stream", get(api::search::search_stream))
+        .route("/api/views/images", get(api::views::images))
+        .route("/api/views/videos", get(api::views::videos))
+        .route("/api/views/recent", get(api::views::recent))
+        .route("/api/views/timeline", get(api::views::timeline))
+        .route("/api/views/map", get(api::views::map))
+        .route("/api/statistics", get(api::system::statistics))
+        .route("/api/files/stat", get(api::files::stat))
+        .route("/api/files/similar", get(api::similar::similar_files))
+        .route("/api/files/mkdir", post(api::files::create_directory))
+        .route("/api/files/create", post(api::files::create_file))
+        .route("/api/files/templates", get(api::files::list_templates))
+        .route("/api/files/link", post(api::files::create_link))
+        .route("/api/files/rename", post(api::files::rename))
+        .route("/api/files/copy", post(api::files::copy_entry))
+        .route("/api/files/move", post(api::files::move_entry))
+        .route("/api/files/move-batch", post(api::files::move_batch))
+        .route("/api/files/size", post(api::files::selection_size))
+        .route("/api/files/delete", delete(api::files::delete))
+        .route("/api/files/manifest", post(api::files::create_manifest))
+        .route("/api/files/thumbnail", get(api::files::thumbnail))
+        .route(
+            "/api/files/notes",
+            get(api::notes::list_notes).post(api::notes::create_note),
+        )
+        .route(
+            "/api/files/notes/{id}",
+            put(api::notes::update_note).delete(api::notes::delete_note),
+        )
+        .route(
+            "/api/files/meta",
+            get(api::meta::get_meta).post(api::meta::update_meta),
+        )
+        .route(
+            "/api/files/lock",
+            get(api::locks::lock_status).post(api::locks::lock),
+        )
+        .route("/api/files/unlock", post(api::locks::unlock))
+        .route("/api/events", get(api::live::directory_events))
+        .route("/api/export/report", get(api::export::report))
+        .route("/api/hooks/runs", get(api::system::hook_runs))
+        .route(
+            "/api/index/rebuild-search",
+            post(api::system::rebuild_search),
+        )
+        .route(
+            "/api/uploads/{id}/progress",
+            get(api::files::upload_progress),
+        )
+        .route("/api/uploads/scans", get(api::files::upload_scans))
+        .with_state(app_state.clone())
+        .route_layer(request_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            idempotency_state.clone(),
+            api::idempotency::idempotency_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            usage_state.clone(),
+            api::usage::usage_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Downloads and uploads, which get the longer transfer timeout
+    let protected_transfer_routes = Router::new()
+        .route("/api/files/download", get(api::files::download))
+        .route(
+            "/api/files/download-batch",
+            post(api::files::download_batch),
+        )
+        .route("/api/files/upload", post(api::files::upload_root))
+        .route("/api/files/upload/", post(api::files::upload_root))
+        .route("/api/files/upload/{*path}", post(api::files::upload))
+        .with_state(app_state.clone())
+        .route_layer(transfer_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            idempotency_state,
+            api::idempotency::idempotency_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            transfer_state,
+            api::transfers::transfer_limit_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            usage_state.clone(),
+            api::usage::usage_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Protected routes that require indexer state
+    let protected_index_routes = Router::new()
+        .route("/api/index/status", get(api::system::index_status))
+        .route("/api/index/trigger", post(api::system::trigger_index))
+        .route("/api/index/runs", get(api::system::index_runs))
+        .route("/api/index/accept-root", post(api::system::accept_root))
+        .with_state(indexer.clone())
+        .route_layer(request_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    let protected_sync_routes = Router::new()
+        .route(
+            "/api/sync-jobs",
+            get(api::sync_jobs::list_sync_jobs).post(api::sync_jobs::create_sync_job),
+        )
+        .route(
+            "/api/sync-jobs/{id}",
+            get(api::sync_jobs::get_sync_job).delete(api::sync_jobs::delete_sync_job),
+        )
+        .route(
+            "/api/sync-jobs/{id}/runs",
+            get(api::sync_jobs::sync_job_runs),
+        )
+        .route(
+            "/api/sync-jobs/{id}/run",
+            post(api::sync_jobs::run_sync_job),
+        )
+        .with_state(sync_jobs)
+        .route_layer(request_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    let protected_remote_routes = Router::new()
+        .route("/api/remotes", get(api::remotes::list_remotes))
+        .route("/api/remotes/syncs", get(api::remotes::list_syncs))
+        .route("/api/remotes/syncs/{id}", get(api::remotes::get_sync))
+        .route("/api/remotes/{name}/sync", post(api::remotes::start_sync))
+        .with_state(remotes)
+        .route_layer(request_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Protected routes that read across services for diagnostics
+    let protected_support_routes = Router::new()
+        .route(
+            "/api/system/support-bundle",
+            post(api::system::support_bundle),
+        )
+        .route("/api/system/integrity", get(api::system::integrity_status))
+        .route(
+            "/api/system/integrity/check",
+            post(api::system::run_integrity_check),
+        )
+        .with_state(support_state.clone())
+        .route_layer(transfer_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Protected routes only admins may use
+    let protected_admin_routes = Router::new()
+        .route("/api/admin/backup", get(api::system::backup))
+        .route("/api/admin/restore", post(api::system::restore))
+        .route("/api/admin/db", post(api::system::db_maintenance))
+        .route(
+            "/api/admin/permissions",
+            post(api::system::change_permissions),
+        )
+        .with_state(support_state)
+        .route_layer(transfer_timeout)
+        .route_layer(middleware::from_fn(api::auth::admin_middleware))
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Protected routes that hand out signed links
+    let protected_media_routes = Router::new()
+        .route("/api/files/sign", get(api::media::sign))
+        .route("/api/files/playlist", get(api::media::playlist))
+        .with_state(Arc::new(api::media::MediaState {
+            app: app_state.clone(),
+            auth: auth_state.clone(),
+        }))
+        .route_layer(request_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Protected usage statistics
+    let protected_usage_routes = Router::new()
+        .route("/api/stats/usage", get(api::usage::usage))
+        .with_state(usage_state)
+        .route_layer(request_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Protected routes over auth state
+    let protected_auth_routes = Router::new()
+        .route("/api/system/expirables", get(api::system::list_expirables))
+        .route(
+            "/api/system/expirables/expire",
+            post(api::system::expire_now),
+        )
+        .with_state(auth_state.clone())
+        .route_layer(request_timeout.clone())
+        .route_layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            api::auth::auth_middleware,
+        ));
+
+    // Auth routes (not protected)
+    let auth_routes = Router::new()
+        .route("/api/auth/login", post(api::auth::login))
+        .route("/api/auth/logout", post(api::auth::logout))
+        .route("/api/auth/status", get(api::auth::auth_status))
+        .route("/api/auth/oidc/login", get(api::auth::oidc_login))
+        .route("/api/auth/oidc/callback", get(api::auth::oidc_callback))
+        .with_state(auth_state.clone())
+        .route_layer(request_timeout.clone());
+
+    // Static file serving for frontend
+    let static_path = config.static_path.clone();
+    let index_file = static_path.join("index.html");
+
+    let serve_dir = ServeDir::new(&static_path).not_found_service(ServeFile::new(&index_file));
+
+    // Health route with app state for database checks (not protected)
+    let health_route = Router::new()
+        .route("/api/health", get(api::system::health))
+        .route("/api/health/live", get(api::system::liveness))
+        .route("/api/health/ready", get(api::system::readiness))
+        .with_state(app_state.clone())
+        .route_layer(request_timeout);
+
+    // Build router
+    let router = Router::new()
+        .merge(health_route)
+        .merge(auth_routes)
+        .merge(protected_routes)
+        .merge(protected_transfer_routes)
+        .merge(protected_index_routes)
+        .merge(protected_support_routes)
+        .merge(protected_admin_routes)
+        .merge(protected_sync_routes)
+        .merge(protected_remote_routes)
+        .merge(protected_auth_routes)
+        .merge(protected_usage_routes)
+        .merge(protected_media_routes)
+        .fallback_service(serve_dir)
+        .layer(DefaultBodyLimit::disable())
+        .layer(api::timeouts::body_idle_timeout(Duration::from_secs(
+            config.body_timeout_secs,
+        )))
+        .layer(cors)
+        .layer(TraceLayer::new_for_http());
+
+    Ok(router)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthConfig;
+    use axum::body::{Body, to_bytes};
+    use axum::http::{Request, StatusCode};
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    fn test_config(root: &std::path::Path) -> Config {
+        Config {
+            root_path: root.to_path_buf(),
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            database_path: root.join("filex.db"),
+            db_min_free_mb: 0,
+            root_min_free_mb: 0,
+            db_check_interval_secs: 0,
+            enable_indexer: false,
+            index_interval_secs: 0,
+            purge_max_percent: 25,
+            show_hidden: false,
+            case_insensitive_paths: false,
+            search_index_max_mb: 0,
+            search_transliterate: false,
+            search_fold_kana: false,
+            ffprobe_concurrency: 2,
+            ffprobe_timeout_secs: 15,
+            webhook_urls: Vec::new(),
+            webhook_secret: None,
+            hooks_file: None,
+            notify_file: None,
+            templates_dir: None,
+            rclone_path: None,
+            clamd_socket: None,
+            quarantine_dir: None,
+            clamd_max_mb: 100,
+            dedup_store: None,
+            allow_links: false,
+            allow_permission_changes: false,
+            download_rate_limit_kib: 0,
+            download_rate_limit_total_kib: 0,
+            readahead_mb: 0,
+            max_downloads: 0,
+            max_downloads_per_session: 0,
+            max_uploads: 0,
+            max_uploads_per_session: 0,
+            request_timeout_secs: 0,
+            transfer_timeout_secs: 0,
+            body_timeout_secs: 0,
+            static_path: root.to_path_buf(),
+            auth: AuthConfig {
+                enabled: false,
+                password: None,
+                session_timeout_secs: 0,
+                cookie_name: "test".to_string(),
+                oidc: None,
+                proxy_header: None,
+                trusted_proxies: Vec::new(),
+                admin_users: Vec::new(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn built_router_serves_the_api() {
+        let root = tempdir().unwrap();
+        let data = tempdir().unwrap();
+        std::fs::write(root.path().join("notes.txt"), "hello").unwrap();
+        let config = Config {
+            database_path: data.path().join("filex.db"),
+            ..test_config(root.path())
+        };
+        let router = build_router(&config).await.unwrap();
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get("/api/health/live")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router
+            .oneshot(
+                Request::get("/api/browse?path=/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let listing: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(listing["entries"][0]["name"], "notes.txt");
+    }
+}
@@ -0,0 +1,387 @@
+//! Command-line access to a Filex library for scripts and headless boxes.
+//!
+//! By default it works on the database and root named by the same `FM_*`
+//! settings as the server. With `--server` it talks to a running server over
+//! the HTTP API instead, so it does not race the server's own indexer.
+
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+use clap::{Parser, Subcommand};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use sqlx::sqlite::SqlitePoolOptions;
+
+use filex_backend::config::Config;
+use filex_backend::db::{self, SearchSortField, SortOrder};
+use filex_backend::models::FileEntry;
+use filex_backend::services::search_index::Normalization;
+use filex_backend::services::{FilesystemService, IndexerService, ManifestService, SearchService};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Parser)]
+#[command(name = "filex-cli", version = filex_backend::version::VERSION, about)]
+struct Cli {
+    /// Talk to a running server at this URL instead of the local database
+    #[arg(long, env = "FILEX_SERVER", global = true)]
+    server: Option<String>,
+
+    /// Password for the server, when it has one
+    #[arg(long, env = "FM_AUTH_PASSWORD", global = true, hide_env_values = true)]
+    password: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run an index pass over the root
+    Index {
+        /// Remove missing entries even past FM_PURGE_MAX_PERCENT
+        #[arg(long)]
+        confirm_purge: bool,
+    },
+    /// Search the index by name and path
+    Search {
+        query: String,
+        #[arg(long, default_value_t = 100)]
+        limit: i64,
+    },
+    /// List a directory
+    Ls {
+        #[arg(default_value = "/")]
+        path: String,
+        /// Include hidden entries
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Print the SHA-256 of a file
+    Hash { path: String },
+    /// Compact the database
+    Vacuum,
+}
+
+fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let cli = Cli::parse();
+
+    match cli.server {
+        Some(server) => {
+            let remote = Remote::connect(&server, cli.password.as_deref())?;
+            remote.run(cli.command)
+        }
+        None => tokio::runtime::Runtime::new()?.block_on(run_local(cli.command)),
+    }
+}
+
+async fn run_local(command: Command) -> anyhow::Result<()> {
+    let config = Config::from_env();
+    let fs = FilesystemService::new(config.root_path.clone());
+
+    match command {
+        Command::Index { confirm_purge } => {
+            let pool = open_database(&config).await?;
+            let stats = IndexerService::new(pool, &config, None)
+                .run_index(confirm_purge)
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+        }
+        Command::Search { query, limit } => {
+            let pool = open_database(&config).await?;
+            let search = SearchService::with_case_insensitive_paths(config.case_insensitive_paths)
+                .with_normalization(Normalization {
+                    transliterate: config.search_transliterate,
+                    fold_kana: config.search_fold_kana,
+                });
+            search.rebuild_from_db(&pool).await?;
+            let ids = search.find(&pool, &query, false).await?;
+            let (rows, _) =
+                db::get_files_by_ids(&pool, &ids, limit, 0, SearchSortField::Path, SortOrder::Asc)
+                    .await?;
+            for row in rows {
+                print_entry(&row.path, row.is_dir, row.size.map(|s| s as u64));
+            }
+        }
+        Command::Ls { path, all } => {
+            for entry in fs.list_directory(&path, true)? {
+                if all || !entry.name.starts_with('.') {
+                    print_entry(&entry.path, entry.is_dir, entry.size);
+                }
+            }
+        }
+        Command::Hash { path } => {
+            let full_path = fs.resolve_path(&path)?;
+            println!("{}  {}", ManifestService::hash_file(&full_path)?, path);
+        }
+        Command::Vacuum => {
+            let pool = open_database(&config).await?;
+            let before = file_size(&config.database_path);
+            db::vacuum(&pool).await?;
+            println!("{} -> {} bytes", before, file_size(&config.database_path));
+        }
+    }
+    Ok(())
+}
+
+async fn open_database(config: &Config) -> anyhow::Result<SqlitePool> {
+    if !config.database_path.exists() {
+        bail!(
+            "no database at {}; start the server once or set FM_DATABASE_PATH",
+            config.database_path.display()
+        );
+    }
+    let db_url = format!("sqlite:{}?mode=rw", config.database_path.display());
+    let pool = SqlitePoolOptions::new()
+        .max_connections(2)
+        .connect(&db_url)
+        .await?;
+    db::init_db(&pool).await?;
+    Ok(pool)
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn print_entry(path: &str, is_dir: bool, size: Option<u64>) {
+    println!("{}", format_entry(path, is_dir, size));
+}
+
+/// One listing line: the size (or `-` for a directory) right-aligned, then
+/// the path, with a trailing `/` on directories
+fn format_entry(path: &str, is_dir: bool, size: Option<u64>) -> String {
+    if is_dir {
+        format!("{:>14}  {}/", "-", path.trim_end_matches('/'))
+    } else {
+        format!("{:>14}  {}", size.unwrap_or(0), path)
+    }
+}
+
+/// A logged-in session with a running server
+struct Remote {
+    agent: ureq::Agent,
+    base: String,
+    cookie: Option<String>,
+    csrf_token: Option<String>,
+}
+
+impl Remote {
+    fn connect(server: &str, password: Option<&str>) -> anyhow::Result<Self> {
+        let mut remote = Self {
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+            base: server.trim_end_matches('/').to_string(),
+            cookie: None,
+            csrf_token: None,
+        };
+        let Some(password) = password else {
+            return Ok(remote);
+        };
+
+        let response = remote
+            .agent
+            .post(&remote.url("/api/auth/login"))
+            .set("Content-Type", "application/json")
+            .send_string(&json!({ "password": password }).to_string())
+            .map_err(http_error)?;
+        remote.cookie = response
+            .header("Set-Cookie")
+            .and_then(|cookie| cookie.split(';').next())
+            .map(str::to_string);
+        let body: Value = serde_json::from_str(&response.into_string()?)?;
+        if body["success"] != json!(true) {
+            bail!(
+                "login failed: {}",
+                body["error"].as_str().unwrap_or("unknown error")
+            );
+        }
+        remote.csrf_token = body["csrf_token"].as_str().map(str::to_string);
+        Ok(remote)
+    }
+
+    fn run(&self, command: Command) -> anyhow::Result<()> {
+        match command {
+            Command::Index { confirm_purge } => {
+                let path = format!("/api/index/trigger?confirm_purge={confirm_purge}");
+                let body = self.post(&path, json!({}))?;
+                println!("{}", serde_json::to_string_pretty(&body)?);
+            }
+            Command::Search { query, limit } => {
+                let limit = limit.to_string();
+                let body = self.get("/api/search", &[("q", &query), ("limit", &limit)])?;
+                print_entries(body, true)?;
+            }
+            Command::Ls { path, all } => {
+                let mut query = vec![("path", path.as_str()), ("limit", "100000")];
+                if all {
+                    // The server leaves dotfiles out unless asked for them
+                    query.push(("show_hidden", "true"));
+                }
+                let body = self.get("/api/browse", &query)?;
+                print_entries(body, all)?;
+            }
+            Command::Hash { path } => {
+                let response = self
+                    .request("GET", "/api/files/download")
+                    .query("path", &path)
+                    .call()
+                    .map_err(http_error)?;
+                let mut reader = response.into_reader();
+                let mut hasher = Sha256::new();
+                let mut buf = vec![0u8; 64 * 1024];
+                loop {
+                    let read = reader.read(&mut buf)?;
+                    if read == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..read]);
+                }
+                println!("{}  {}", hex::encode(hasher.finalize()), path);
+            }
+            Command::Vacuum => {
+                let body = self.post("/api/admin/db", json!({ "operations": ["vacuum"] }))?;
+                println!("{}", serde_json::to_string_pretty(&body)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let mut request = self.agent.request(method, &self.url(path));
+        if let Some(cookie) = &self.cookie {
+            request = request.set("Cookie", cookie);
+        }
+        if let Some(token) = &self.csrf_token {
+            request = request.set("X-CSRF-Token", token);
+        }
+        request
+    }
+
+    fn get(&self, path: &str, query: &[(&str, &str)]) -> anyhow::Result<Value> {
+        let response = self
+            .request("GET", path)
+            .query_pairs(query.iter().copied())
+            .call()
+            .map_err(http_error)?;
+        Ok(serde_json::from_str(&response.into_string()?)?)
+    }
+
+    fn post(&self, path: &str, body: Value) -> anyhow::Result<Value> {
+        let response = self
+            .request("POST", path)
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())
+            .map_err(http_error)?;
+        let text = response.into_string()?;
+        if text.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&text).context("server returned invalid JSON")
+    }
+}
+
+fn print_entries(body: Value, include_hidden: bool) -> anyhow::Result<()> {
+    for line in entry_lines(body, include_hidden)? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Listing lines for the `entries` of a browse or search response
+fn entry_lines(body: Value, include_hidden: bool) -> anyhow::Result<Vec<String>> {
+    let entries: Vec<FileEntry> = serde_json::from_value(body["entries"].clone())?;
+    Ok(entries
+        .iter()
+        .filter(|entry| include_hidden || !entry.name.starts_with('.'))
+        .map(|entry| format_entry(&entry.path, entry.is_dir, entry.size))
+        .collect())
+}
+
+fn http_error(error: ureq::Error) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(status, response) => anyhow::anyhow!(
+            "server returned {}: {}",
+            status,
+            response.into_string().unwrap_or_default()
+        ),
+        e => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("filex-cli").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn commands_parse_with_their_defaults() {
+        assert!(matches!(parse(&["ls"]).command, Command::Ls { path, all: false } if path == "/"));
+
+        let cli = parse(&["--server", "http://nas:3000", "ls", "-a", "/photos"]);
+        assert_eq!(cli.server.as_deref(), Some("http://nas:3000"));
+        assert!(matches!(cli.command, Command::Ls { path, all: true } if path == "/photos"));
+
+        // Global options also go after the subcommand
+        let cli = parse(&["search", "beach", "--limit", "5", "--password", "secret"]);
+        assert_eq!(cli.password.as_deref(), Some("secret"));
+        assert!(matches!(cli.command, Command::Search { query, limit: 5 } if query == "beach"));
+
+        assert!(matches!(
+            parse(&["index", "--confirm-purge"]).command,
+            Command::Index {
+                confirm_purge: true
+            }
+        ));
+        assert!(
+            matches!(parse(&["hash", "/a.jpg"]).command, Command::Hash { path } if path == "/a.jpg")
+        );
+        assert!(matches!(parse(&["vacuum"]).command, Command::Vacuum));
+
+        for bad in [
+            &[][..],
+            &["hash"],
+            &["search", "x", "--limit", "many"],
+            &["rm", "/"],
+        ] {
+            assert!(
+                Cli::try_parse_from(std::iter::once("filex-cli").chain(bad.iter().copied()))
+                    .is_err(),
+                "{bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn entries_are_listed_with_sizes_and_directory_slashes() {
+        let body = json!({
+            "entries": [
+                { "name": "photos", "path": "/photos", "is_dir": true },
+                { "name": "notes.txt", "path": "/notes.txt", "is_dir": false, "size": 1234 },
+                { "name": ".hidden", "path": "/.hidden", "is_dir": false, "size": 1 },
+            ]
+        });
+        assert_eq!(
+            entry_lines(body.clone(), false).unwrap(),
+            ["             -  /photos/", "          1234  /notes.txt",]
+        );
+        assert_eq!(entry_lines(body, true).unwrap().len(), 3);
+
+        assert_eq!(format_entry("/", true, None), "             -  /");
+        assert_eq!(
+            format_entry("/empty", false, None),
+            "             0  /empty"
+        );
+        assert!(entry_lines(json!({ "error": "nope" }), true).is_err());
+    }
+}
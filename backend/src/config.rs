@@ -1,3 +1,5 @@
+use ipnet::IpNet;
+use std::net::IpAddr;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
@@ -14,12 +16,120 @@ pub struct Config {
     /// SQLite database path
     pub database_path: PathBuf,
 
+    /// Minimum free space (MiB) to keep on the database volume; 0 disables
+    pub db_min_free_mb: u64,
+
+    /// Free space (MiB) uploads and copies must leave on the root volume
+    pub root_min_free_mb: u64,
+
+    /// Database integrity check interval in seconds; 0 disables
+    pub db_check_interval_secs: u64,
+
     /// Whether to start background indexer
     pub enable_indexer: bool,
 
     /// Indexer scan interval in seconds
     pub index_interval_secs: u64,
 
+    /// Largest share (percent) of indexed entries one run may remove as
+    /// missing without confirmation; 100 disables the check
+    pub purge_max_percent: u64,
+
+    /// Include dotfiles in browse, tree, search, and the index by default
+    pub show_hidden: bool,
+
+    /// Treat paths differing only in (ASCII) case as the same file, for
+    /// roots on case-insensitive filesystems
+    pub case_insensitive_paths: bool,
+
+    /// Memory (MiB) the in-memory search index may use before searches fall
+    /// back to the database; 0 is unlimited
+    pub search_index_max_mb: u64,
+
+    /// Transliterate indexed text and queries to ASCII, so "Łódź" matches
+    /// "lodz"
+    pub search_transliterate: bool,
+
+    /// Match katakana and hiragana interchangeably in search
+    pub search_fold_kana: bool,
+
+    /// Maximum number of concurrent metadata extractions (ffprobe/pdfinfo)
+    pub ffprobe_concurrency: usize,
+
+    /// Per-file ffprobe timeout in seconds
+    pub ffprobe_timeout_secs: u64,
+
+    /// URLs that receive file events as JSON POSTs
+    pub webhook_urls: Vec<String>,
+
+    /// Secret for the HMAC-SHA256 signature of webhook payloads
+    pub webhook_secret: Option<String>,
+
+    /// File defining commands to run when files change (see `services::hooks`)
+    pub hooks_file: Option<PathBuf>,
+
+    /// File defining notification channels and watched folders (see
+    /// `services::notifications`)
+    pub notify_file: Option<PathBuf>,
+
+    /// Directory of templates for new files (see `services::templates`)
+    pub templates_dir: Option<PathBuf>,
+
+    /// rclone binary for copying directories to cloud remotes (see
+    /// `services::remotes`); unset disables
+    pub rclone_path: Option<PathBuf>,
+
+    /// clamd socket uploads are scanned with: a Unix socket path or
+    /// `host:port` (see `services::antivirus`); unset disables
+    pub clamd_socket: Option<String>,
+
+    /// Where infected uploads are moved; `quarantine` next to the database
+    /// by default
+    pub quarantine_dir: Option<PathBuf>,
+
+    /// Uploads larger than this many MiB are not scanned
+    pub clamd_max_mb: u64,
+
+    /// Directory uploads are stored in by content and hard-linked from
+    /// (see `services::dedup`); unset stores them as plain files
+    pub dedup_store: Option<PathBuf>,
+
+    /// Allow creating hard links and symlinks through the API
+    pub allow_links: bool,
+
+    /// Allow admins to change mode bits and ownership (see
+    /// `services::permissions`)
+    pub allow_permission_changes: bool,
+
+    /// Bandwidth limit for each download in KiB/s; 0 is unlimited
+    pub download_rate_limit_kib: u64,
+
+    /// Bandwidth limit shared by all downloads in KiB/s; 0 is unlimited
+    pub download_rate_limit_total_kib: u64,
+
+    /// MiB read ahead of media streamed in consecutive ranges; 0 is off
+    pub readahead_mb: u64,
+
+    /// Most downloads in progress at once, overall and per session; 0 is
+    /// unlimited
+    pub max_downloads: usize,
+    pub max_downloads_per_session: usize,
+
+    /// Most uploads in progress at once, overall and per session; 0 is
+    /// unlimited
+    pub max_uploads: usize,
+    pub max_uploads_per_session: usize,
+
+    /// Seconds a handler may take to respond; 0 disables
+    pub request_timeout_secs: u64,
+
+    /// Seconds uploads, downloads, backups and restores may take to respond;
+    /// 0 disables
+    pub transfer_timeout_secs: u64,
+
+    /// Seconds a request or response body may go without data; 0 disables
+    pub body_timeout_secs: u64,
+
     /// Static files directory (frontend build)
     pub static_path: PathBuf,
 
@@ -40,6 +150,77 @@ pub struct AuthConfig {
 
     /// Cookie name for session token
     pub cookie_name: String,
+
+    /// Single sign-on through an OpenID Connect provider, if configured
+    pub oidc: Option<OidcConfig>,
+
+    /// Header naming the user (e.g. `Remote-User`) set by an authenticating
+    /// reverse proxy; only honored on requests from `trusted_proxies`
+    pub proxy_header: Option<String>,
+
+    /// Addresses or networks of the reverse proxies allowed to set `proxy_header`
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// Users named by SSO or `proxy_header` who may use the `/api/admin`
+    /// endpoints, like a password login can
+    pub admin_users: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL; discovery is read from `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+
+    pub client_id: String,
+
+    /// Client secret; `None` for public clients, which rely on PKCE alone
+    pub client_secret: Option<String>,
+
+    /// Callback URL registered with the provider, ending in `/api/auth/oidc/callback`
+    pub redirect_url: String,
+
+    /// ID token claim holding the user's groups
+    pub groups_claim: String,
+
+    /// Groups allowed to sign in; empty allows every user the provider accepts
+    pub allowed_groups: Vec<String>,
+}
+
+impl OidcConfig {
+    fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let issuer = var("FM_OIDC_ISSUER")?;
+        let (Some(client_id), Some(redirect_url)) =
+            (var("FM_OIDC_CLIENT_ID"), var("FM_OIDC_REDIRECT_URL"))
+        else {
+            tracing::warn!(
+                "FM_OIDC_ISSUER is set but FM_OIDC_CLIENT_ID or FM_OIDC_REDIRECT_URL is not. SSO disabled."
+            );
+            return None;
+        };
+        if !crate::services::oidc::is_secure_url(&issuer) {
+            tracing::warn!(
+                "FM_OIDC_ISSUER must be an https:// URL (plain http only for localhost). SSO disabled."
+            );
+            return None;
+        }
+
+        Some(Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: var("FM_OIDC_CLIENT_SECRET"),
+            redirect_url,
+            groups_claim: var("FM_OIDC_GROUPS_CLAIM").unwrap_or_else(|| "groups".to_string()),
+            allowed_groups: var("FM_OIDC_ALLOWED_GROUPS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|group| group.trim().to_string())
+                        .filter(|group| !group.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
 }
 
 impl Config {
@@ -49,11 +230,13 @@ impl Config {
             .unwrap_or(false);
 
         let auth_password = std::env::var("FM_AUTH_PASSWORD").ok();
+        let oidc = OidcConfig::from_env();
+        let (proxy_header, trusted_proxies) = proxy_auth_from_env();
 
-        // Warn if auth is enabled but no password is set
-        if auth_enabled && auth_password.is_none() {
+        // Warn if auth is enabled but there is no way to log in
+        if auth_enabled && auth_password.is_none() && oidc.is_none() && proxy_header.is_none() {
             tracing::warn!(
-                "FM_AUTH_ENABLED is true but FM_AUTH_PASSWORD is not set. Authentication disabled."
+                "FM_AUTH_ENABLED is true but none of FM_AUTH_PASSWORD, FM_OIDC_ISSUER, or FM_AUTH_PROXY_HEADER is set. Authentication disabled."
             );
         }
 
@@ -73,6 +256,21 @@ impl Config {
                 .map(PathBuf::from)
                 .unwrap_or_else(|_| PathBuf::from("/app/data/filex.db")),
 
+            db_min_free_mb: std::env::var("FM_DB_MIN_FREE_MB")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(512),
+
+            root_min_free_mb: std::env::var("FM_ROOT_MIN_FREE_MB")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            db_check_interval_secs: std::env::var("FM_DB_CHECK_INTERVAL")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(86400), // nightly
+
             enable_indexer: std::env::var("FM_ENABLE_INDEXER")
                 .map(|v| v == "true" || v == "1")
                 .unwrap_or(true),
@@ -82,12 +280,162 @@ impl Config {
                 .and_then(|p| p.parse().ok())
                 .unwrap_or(300), // 5 minutes
 
+            purge_max_percent: std::env::var("FM_PURGE_MAX_PERCENT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .map(|n: u64| n.min(100))
+                .unwrap_or(25),
+
+            show_hidden: std::env::var("FM_SHOW_HIDDEN")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+
+            case_insensitive_paths: std::env::var("FM_CASE_INSENSITIVE_PATHS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+
+            search_index_max_mb: std::env::var("FM_SEARCH_INDEX_MAX_MB")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            search_transliterate: std::env::var("FM_SEARCH_TRANSLITERATE")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+
+            search_fold_kana: std::env::var("FM_SEARCH_FOLD_KANA")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+
+            ffprobe_concurrency: std::env::var("FM_FFPROBE_CONCURRENCY")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .filter(|&n: &usize| n > 0)
+                .unwrap_or(4),
+
+            ffprobe_timeout_secs: std::env::var("FM_FFPROBE_TIMEOUT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .filter(|&n: &u64| n > 0)
+                .unwrap_or(15),
+
+            webhook_urls: std::env::var("FM_WEBHOOK_URLS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|url| url.trim().to_string())
+                        .filter(|url| !url.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+
+            webhook_secret: std::env::var("FM_WEBHOOK_SECRET")
+                .ok()
+                .filter(|s| !s.is_empty()),
+
+            hooks_file: std::env::var("FM_HOOKS_FILE")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+
+            notify_file: std::env::var("FM_NOTIFY_FILE")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+
+            templates_dir: std::env::var("FM_TEMPLATES_DIR")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+
+            rclone_path: std::env::var("FM_RCLONE_PATH")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+
+            clamd_socket: std::env::var("FM_CLAMD_SOCKET")
+                .ok()
+                .filter(|p| !p.is_empty()),
+
+            quarantine_dir: std::env::var("FM_QUARANTINE_DIR")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+
+            clamd_max_mb: std::env::var("FM_CLAMD_MAX_MB")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(100),
+
+            dedup_store: std::env::var("FM_DEDUP_STORE")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+
+            allow_links: std::env::var("FM_ALLOW_LINKS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+
+            allow_permission_changes: std::env::var("FM_ALLOW_PERMISSION_CHANGES")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+
+            download_rate_limit_kib: std::env::var("FM_DOWNLOAD_RATE_LIMIT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            download_rate_limit_total_kib: std::env::var("FM_DOWNLOAD_RATE_LIMIT_TOTAL")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            readahead_mb: std::env::var("FM_READAHEAD_MB")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            max_downloads: std::env::var("FM_MAX_DOWNLOADS")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            max_downloads_per_session: std::env::var("FM_MAX_DOWNLOADS_PER_SESSION")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            max_uploads: std::env::var("FM_MAX_UPLOADS")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            max_uploads_per_session: std::env::var("FM_MAX_UPLOADS_PER_SESSION")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0),
+
+            request_timeout_secs: std::env::var("FM_REQUEST_TIMEOUT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(60),
+
+            transfer_timeout_secs: std::env::var("FM_TRANSFER_TIMEOUT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(21600), // 6 hours
+
+            body_timeout_secs: std::env::var("FM_BODY_TIMEOUT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(120),
+
             static_path: std::env::var("FM_STATIC_PATH")
                 .map(PathBuf::from)
                 .unwrap_or_else(|_| PathBuf::from("./static")),
 
             auth: AuthConfig {
-                enabled: auth_enabled && auth_password.is_some(),
+                enabled: auth_enabled
+                    && (auth_password.is_some() || oidc.is_some() || proxy_header.is_some()),
                 password: auth_password,
                 session_timeout_secs: std::env::var("FM_SESSION_TIMEOUT")
                     .ok()
@@ -95,6 +443,17 @@ impl Config {
                     .unwrap_or(86400), // 24 hours
                 cookie_name: std::env::var("FM_SESSION_COOKIE")
                     .unwrap_or_else(|_| "fm_session".to_string()),
+                oidc,
+                proxy_header,
+                trusted_proxies,
+                admin_users: std::env::var("FM_ADMIN_USERS")
+                    .map(|v| {
+                        v.split(',')
+                            .map(|user| user.trim().to_string())
+                            .filter(|user| !user.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
             },
         }
     }
@@ -103,3 +462,40 @@ impl Config {
         format!("{}:{}", self.host, self.port)
     }
 }
+
+/// `FM_AUTH_PROXY_HEADER` and `FM_AUTH_TRUSTED_PROXIES`. The header is
+/// ignored unless at least one valid proxy address is given, since trusting
+/// it from anyone would let any client pick a user.
+fn proxy_auth_from_env() -> (Option<String>, Vec<IpNet>) {
+    let Some(header) = std::env::var("FM_AUTH_PROXY_HEADER")
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+    else {
+        return (None, Vec::new());
+    };
+
+    let mut proxies = Vec::new();
+    for entry in std::env::var("FM_AUTH_TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        match entry
+            .parse::<IpNet>()
+            .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+        {
+            Ok(net) => proxies.push(net),
+            Err(_) => tracing::warn!("Ignoring invalid FM_AUTH_TRUSTED_PROXIES entry {:?}", entry),
+        }
+    }
+
+    if proxies.is_empty() {
+        tracing::warn!(
+            "FM_AUTH_PROXY_HEADER is set but FM_AUTH_TRUSTED_PROXIES lists no valid proxy. Proxy authentication disabled."
+        );
+        return (None, Vec::new());
+    }
+    (Some(header), proxies)
+}
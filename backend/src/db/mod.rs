@@ -2,8 +2,19 @@ pub mod queries;
 pub mod schema;
 
 pub use queries::{
-    SearchSortField, SortOrder, delete_by_paths, get_file_by_path, get_files_by_ids,
-    get_indexed_totals, get_last_indexed_at, get_metadata_for_paths, list_indexed_paths,
-    rename_path, update_media_metadata, upsert_file, vacuum,
+    BoundingBox, CheckpointResult, FileFilter, FileNoteRow, HookRunRow, IdempotentResponseRow,
+    IndexRunRow, MapPointRow, MediaClass, MetaFilter, SearchSortField, SortOrder, SyncJobRow,
+    SyncRunRow, TimelineRow, UploadScanRow, analyze, create_note, create_sync_job, delete_by_paths,
+    delete_note, delete_sync_job, due_sync_jobs, get_child_counts, get_file_by_path,
+    get_files_by_ids, get_idempotent_response, get_index_meta, get_indexed_file,
+    get_indexed_totals, get_last_indexed_at, get_map_points, get_meta, get_metadata_for_paths,
+    get_note, get_recent_additions, get_sync_job, get_timeline_buckets, get_tree_totals,
+    get_type_stats, ids_matching_file_filters, ids_matching_meta, integrity_check, links_for_paths,
+    list_hook_runs, list_index_runs, list_indexed_children, list_indexed_paths, list_media,
+    list_notes, list_sync_jobs, list_sync_runs, list_timeline, list_upload_scans,
+    meta_values_for_ids, paths_with_notes, record_hook_run, record_index_run, record_sync_run,
+    record_upload_scan, rename_path, save_idempotent_response, set_index_meta, set_link,
+    set_original_name, set_sync_job_next_run, similar_candidates, update_document_metadata,
+    update_media_metadata, update_meta, update_note, upsert_file, vacuum, wal_checkpoint,
 };
-pub use schema::init_db;
+pub use schema::{init_db, set_case_insensitive_paths};
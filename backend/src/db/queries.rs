@@ -1,5 +1,14 @@
-use crate::models::IndexedFileRow;
+use crate::models::{AudioTags, DocumentMetadata, IndexedFileRow};
+use crate::services::filesystem::canonical_name;
+use crate::services::search::like_pattern;
+use chrono::NaiveDateTime;
+use serde::Serialize;
 use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// How `captured_at` is stored: local time of the camera, no offset, so it
+/// sorts and buckets alongside the RFC 3339 `modified_at`
+const CAPTURED_AT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
 
 #[derive(Clone, Copy)]
 pub enum SortOrder {
@@ -19,6 +28,9 @@ pub enum SearchSortField {
     Duration,
 }
 
+/// Tables of data people attach to paths, which follow renames and deletes
+const PATH_KEYED_TABLES: [&str; 2] = ["file_notes", "file_meta"];
+
 /// Rename a path in the index and cascade the update to children if the target
 /// represents a directory. Returns the total number of affected rows.
 pub async fn rename_path(
@@ -27,6 +39,9 @@ pub async fn rename_path(
     new_path: &str,
     new_name: &str,
 ) -> Result<u64, sqlx::Error> {
+    let old_path = &*canonical_name(old_path);
+    let new_path = &*canonical_name(new_path);
+    let new_name = &*canonical_name(new_name);
     let mut tx = pool.begin().await?;
     let mut affected = 0;
 
@@ -51,11 +66,343 @@ pub async fn rename_path(
     .await?;
     affected += res_children.rows_affected();
 
+    // Notes and custom fields follow their files; they are not counted as
+    // index rows
+    for table in PATH_KEYED_TABLES {
+        sqlx::query(&format!("UPDATE {table} SET path = ? WHERE path = ?"))
+            .bind(new_path)
+            .bind(old_path)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!(
+            "UPDATE {table} SET path = ? || substr(path, length(?)+1) WHERE path LIKE ?"
+        ))
+        .bind(new_path)
+        .bind(old_path)
+        .bind(format!("{}/%", old_path.trim_end_matches('/')))
+        .execute(&mut *tx)
+        .await?;
+    }
+
     tx.commit().await?;
 
     Ok(affected)
 }
 
+impl SearchSortField {
+    /// SQL expression over `indexed_files` to order by
+    fn order_expr(self) -> &'static str {
+        match self {
+            Self::Name => "LOWER(name)",
+            Self::Path => "LOWER(path)",
+            Self::Size => "COALESCE(size, 0)",
+            Self::Modified => "COALESCE(modified_at, '')",
+            Self::Created => "COALESCE(created_at, '')",
+            Self::Type => "COALESCE(mime_type, '')",
+            Self::Resolutions => "COALESCE(width, 0) * COALESCE(height, 0)",
+            Self::Duration => "COALESCE(duration, 0)",
+        }
+    }
+}
+
+impl SortOrder {
+    fn as_sql(self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+/// One page of the indexed entries directly inside `dir`, directories first,
+/// plus the number of such entries
+pub async fn list_indexed_children(
+    pool: &SqlitePool,
+    dir: &str,
+    show_hidden: bool,
+    sort_field: SearchSortField,
+    sort_order: SortOrder,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<IndexedFileRow>, i64), sqlx::Error> {
+    let parent = format!("{}/", dir.trim_end_matches('/'));
+    let filter = "parent = ? AND path != '/' AND (? OR name NOT LIKE '.%')";
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM indexed_files WHERE {filter}"
+    ))
+    .bind(&parent)
+    .bind(show_hidden)
+    .fetch_one(pool)
+    .await?;
+
+    let sql = format!(
+        r#"
+        SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name
+        FROM indexed_files
+        WHERE {filter}
+        ORDER BY is_dir DESC, {} {}, name ASC
+        LIMIT ? OFFSET ?
+        "#,
+        sort_field.order_expr(),
+        sort_order.as_sql()
+    );
+    let rows = sqlx::query_as(&sql)
+        .bind(&parent)
+        .bind(show_hidden)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+    Ok((rows, total))
+}
+
+/// Kinds of file the library-wide views list
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaClass {
+    Images,
+    Videos,
+    /// Images and videos together
+    Media,
+}
+
+impl MediaClass {
+    /// WHERE clause for files of this class under a directory; binds the
+    /// directory prefix twice, then whether hidden paths are included
+    fn filter(self) -> String {
+        let class = match self {
+            Self::Images => "mime_type LIKE 'image/%'",
+            Self::Videos => "mime_type LIKE 'video/%'",
+            Self::Media => "(mime_type LIKE 'image/%' OR mime_type LIKE 'video/%')",
+        };
+        format!(
+            "is_dir = 0 AND {class} AND substr(path, 1, length(?)) = ? \
+             AND (? OR (path NOT LIKE '/.%' AND path NOT LIKE '%/.%'))"
+        )
+    }
+}
+
+/// One page of the indexed files of `class` anywhere under `dir`, plus the
+/// number of such files
+#[allow(clippy::too_many_arguments)]
+pub async fn list_media(
+    pool: &SqlitePool,
+    class: MediaClass,
+    dir: &str,
+    show_hidden: bool,
+    sort_field: SearchSortField,
+    sort_order: SortOrder,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<IndexedFileRow>, i64), sqlx::Error> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    let filter = class.filter();
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM indexed_files WHERE {filter}"
+    ))
+    .bind(&prefix)
+    .bind(&prefix)
+    .bind(show_hidden)
+    .fetch_one(pool)
+    .await?;
+
+    let sql = format!(
+        r#"
+        SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name
+        FROM indexed_files
+        WHERE {filter}
+        ORDER BY {} {}, path ASC
+        LIMIT ? OFFSET ?
+        "#,
+        sort_field.order_expr(),
+        sort_order.as_sql()
+    );
+    let rows = sqlx::query_as(&sql)
+        .bind(&prefix)
+        .bind(&prefix)
+        .bind(show_hidden)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+    Ok((rows, total))
+}
+
+/// When a file was taken, falling back to when it was last modified
+const TAKEN_AT: &str = "COALESCE(captured_at, modified_at)";
+
+/// An indexed file with the time it was taken
+#[derive(Debug, sqlx::FromRow)]
+pub struct TimelineRow {
+    #[sqlx(flatten)]
+    pub file: IndexedFileRow,
+    pub taken_at: String,
+}
+
+/// Number of files of `class` under `dir` per period, newest first. A
+/// period is the first `key_len` characters of the time taken: 4 for
+/// years (`2024`), 7 for months (`2024-05`), 10 for days (`2024-05-01`).
+pub async fn get_timeline_buckets(
+    pool: &SqlitePool,
+    class: MediaClass,
+    dir: &str,
+    show_hidden: bool,
+    key_len: i64,
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    let sql = format!(
+        r#"
+        SELECT substr({TAKEN_AT}, 1, ?) AS bucket, COUNT(*)
+        FROM indexed_files
+        WHERE {} AND {TAKEN_AT} IS NOT NULL
+        GROUP BY bucket
+        ORDER BY bucket DESC
+        "#,
+        class.filter()
+    );
+    sqlx::query_as(&sql)
+        .bind(key_len)
+        .bind(&prefix)
+        .bind(&prefix)
+        .bind(show_hidden)
+        .fetch_all(pool)
+        .await
+}
+
+/// One page of the files of `class` under `dir` taken in the period
+/// `bucket` (any prefix of the time taken, all time if empty), newest
+/// first, plus the number of such files
+pub async fn list_timeline(
+    pool: &SqlitePool,
+    class: MediaClass,
+    dir: &str,
+    show_hidden: bool,
+    bucket: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<TimelineRow>, i64), sqlx::Error> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    let filter = format!(
+        "{} AND {TAKEN_AT} IS NOT NULL AND substr({TAKEN_AT}, 1, length(?)) = ?",
+        class.filter()
+    );
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM indexed_files WHERE {filter}"
+    ))
+    .bind(&prefix)
+    .bind(&prefix)
+    .bind(show_hidden)
+    .bind(bucket)
+    .bind(bucket)
+    .fetch_one(pool)
+    .await?;
+
+    let sql = format!(
+        r#"
+        SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name, {TAKEN_AT} AS taken_at
+        FROM indexed_files
+        WHERE {filter}
+        ORDER BY taken_at DESC, path ASC
+        LIMIT ? OFFSET ?
+        "#
+    );
+    let rows = sqlx::query_as(&sql)
+        .bind(&prefix)
+        .bind(&prefix)
+        .bind(show_hidden)
+        .bind(bucket)
+        .bind(bucket)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+    Ok((rows, total))
+}
+
+/// Area on the map, in degrees; never crosses the antimeridian
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+/// A point on the photo map: one geotagged file, or several close together.
+/// The file columns describe the file only when `count` is 1.
+#[derive(Debug, sqlx::FromRow)]
+pub struct MapPointRow {
+    pub count: i64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub path: String,
+    pub name: String,
+    pub mime_type: Option<String>,
+    pub taken_at: Option<String>,
+}
+
+/// Geotagged files of `class` under `dir` inside `bbox`, most crowded points
+/// first. With a `grid`, the box is cut into `grid` × `grid` cells and the
+/// files in each cell merge into one point at their average position.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_map_points(
+    pool: &SqlitePool,
+    class: MediaClass,
+    dir: &str,
+    show_hidden: bool,
+    bbox: BoundingBox,
+    grid: Option<u32>,
+    limit: i64,
+) -> Result<Vec<MapPointRow>, sqlx::Error> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    let group_by = match grid {
+        Some(grid) => {
+            let cell = |low: f64, high: f64, column: &str| {
+                let size = (high - low) / f64::from(grid);
+                format!(
+                    "MIN(CAST(({column} - {low}) / {size} AS INTEGER), {})",
+                    grid - 1
+                )
+            };
+            format!(
+                "{}, {}",
+                cell(bbox.south, bbox.north, "latitude"),
+                cell(bbox.west, bbox.east, "longitude")
+            )
+        }
+        None => "id".to_string(),
+    };
+    let sql = format!(
+        r#"
+        SELECT COUNT(*) AS count, AVG(latitude) AS latitude, AVG(longitude) AS longitude,
+            MIN(path) AS path, MIN(name) AS name, MIN(mime_type) AS mime_type,
+            MIN({TAKEN_AT}) AS taken_at
+        FROM indexed_files
+        WHERE {} AND latitude BETWEEN ? AND ? AND longitude BETWEEN ? AND ?
+        GROUP BY {group_by}
+        ORDER BY count DESC, path ASC
+        LIMIT ?
+        "#,
+        class.filter()
+    );
+    sqlx::query_as(&sql)
+        .bind(&prefix)
+        .bind(&prefix)
+        .bind(show_hidden)
+        .bind(bbox.south)
+        .bind(bbox.north)
+        .bind(bbox.west)
+        .bind(bbox.east)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
 /// Fetch indexed files by their IDs with sorting and pagination.
 ///
 /// This is used by the in-memory search to fetch full records after ID matching.
@@ -74,21 +421,8 @@ pub async fn get_files_by_ids(
 
     let total = ids.len() as i64;
 
-    let order_expr = match sort_field {
-        SearchSortField::Name => "LOWER(name)",
-        SearchSortField::Path => "LOWER(path)",
-        SearchSortField::Size => "COALESCE(size, 0)",
-        SearchSortField::Modified => "COALESCE(modified_at, '')",
-        SearchSortField::Created => "COALESCE(created_at, '')",
-        SearchSortField::Type => "COALESCE(mime_type, '')",
-        SearchSortField::Resolutions => "COALESCE(width, 0) * COALESCE(height, 0)",
-        SearchSortField::Duration => "COALESCE(duration, 0)",
-    };
-
-    let order_dir = match sort_order {
-        SortOrder::Asc => "ASC",
-        SortOrder::Desc => "DESC",
-    };
+    let order_expr = sort_field.order_expr();
+    let order_dir = sort_order.as_sql();
 
     // SQLite defaults to 999 bound parameters. We need to handle the case where
     // we have more IDs than the limit. We'll chunk the IDs and sort in memory
@@ -102,7 +436,7 @@ pub async fn get_files_by_ids(
         let placeholders = vec!["?"; ids.len()].join(", ");
         let sql = format!(
             r#"
-            SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at
+            SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name
             FROM indexed_files
             WHERE id IN ({placeholders})
             ORDER BY is_dir DESC, {order_expr} {order_dir}, name ASC
@@ -126,7 +460,7 @@ pub async fn get_files_by_ids(
             let placeholders = vec!["?"; chunk.len()].join(", ");
             let sql = format!(
                 r#"
-                SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at
+                SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name
                 FROM indexed_files
                 WHERE id IN ({placeholders})
                 "#
@@ -216,7 +550,7 @@ pub async fn get_metadata_for_paths(
 
         let mut query_builder = sqlx::query_as::<_, IndexedFileRow>(&query);
         for path in chunk {
-            query_builder = query_builder.bind(path);
+            query_builder = query_builder.bind(canonical_name(path).into_owned());
         }
 
         rows.extend(query_builder.fetch_all(pool).await?);
@@ -225,20 +559,130 @@ pub async fn get_metadata_for_paths(
     Ok(rows)
 }
 
-/// Get size, last-modified value, and metadata status for a path, returning
-/// `None` when the path is not indexed.
+/// Record that an indexed path is a link made to `target`
+pub async fn set_link(
+    pool: &SqlitePool,
+    path: &str,
+    kind: &str,
+    target: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE indexed_files SET link_kind = ?, link_target = ? WHERE path = ?")
+        .bind(kind)
+        .bind(canonical_name(target))
+        .bind(canonical_name(path))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Kind and target of the links among `paths`, keyed by path
+pub async fn links_for_paths(
+    pool: &SqlitePool,
+    paths: &[String],
+) -> Result<HashMap<String, (String, String)>, sqlx::Error> {
+    // Chunked like `get_metadata_for_paths`, under SQLite's default limit of
+    // 999 bound parameters
+    const CHUNK_SIZE: usize = 949;
+    let mut links = HashMap::new();
+    for chunk in paths.chunks(CHUNK_SIZE) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let query = format!(
+            "SELECT path, link_kind, link_target FROM indexed_files \
+             WHERE link_kind IS NOT NULL AND path IN ({placeholders})"
+        );
+        let mut query_builder = sqlx::query_as::<_, (String, String, String)>(&query);
+        for path in chunk {
+            query_builder = query_builder.bind(path);
+        }
+        links.extend(
+            query_builder
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|(path, kind, target)| (path, (kind, target))),
+        );
+    }
+    Ok(links)
+}
+
+/// Get size, last-modified value, metadata status, and MIME type for a
+/// path, returning `None` when the path is not indexed.
 pub async fn get_file_by_path(
     pool: &SqlitePool,
     path: &str,
-) -> Result<Option<(Option<i64>, Option<String>, String)>, sqlx::Error> {
-    let row: Option<(Option<i64>, Option<String>, String)> = sqlx::query_as(
-        "SELECT size, modified_at, metadata_status FROM indexed_files WHERE path = ?",
+) -> Result<Option<(Option<i64>, Option<String>, String, Option<String>)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT size, modified_at, metadata_status, mime_type FROM indexed_files WHERE path = ?",
     )
-    .bind(path)
+    .bind(canonical_name(path))
     .fetch_optional(pool)
-    .await?;
+    .await
+}
+
+/// The indexed row for a file or directory, if indexed
+pub async fn get_indexed_file(
+    pool: &SqlitePool,
+    path: &str,
+) -> Result<Option<IndexedFileRow>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name
+        FROM indexed_files
+        WHERE path = ?
+        "#,
+    )
+    .bind(canonical_name(path))
+    .fetch_optional(pool)
+    .await
+}
+
+/// Indexed files other than `source` that could be another copy or version
+/// of it: the same size, a running time within `duration_tolerance`
+/// seconds, or a name containing one of `name_tokens`. Scoring them is up
+/// to the caller.
+pub async fn similar_candidates(
+    pool: &SqlitePool,
+    source: &IndexedFileRow,
+    name_tokens: &[String],
+    duration_tolerance: f64,
+    show_hidden: bool,
+    limit: i64,
+) -> Result<Vec<IndexedFileRow>, sqlx::Error> {
+    let mut conditions = Vec::new();
+    if source.size.is_some_and(|size| size > 0) {
+        conditions.push("size = ?");
+    }
+    if source.duration.is_some_and(|d| d > 0.0) {
+        conditions.push("abs(duration - ?) <= ?");
+    }
+    conditions.extend(name_tokens.iter().map(|_| "name LIKE ? ESCAPE '\\'"));
+    if conditions.is_empty() {
+        return Ok(vec![]);
+    }
 
-    Ok(row)
+    let sql = format!(
+        r#"
+        SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name
+        FROM indexed_files
+        WHERE is_dir = 0 AND path != ?
+          AND (? OR (path NOT LIKE '/.%' AND path NOT LIKE '%/.%'))
+          AND ({})
+        ORDER BY path
+        LIMIT ?
+        "#,
+        conditions.join(" OR ")
+    );
+    let mut query = sqlx::query_as(&sql).bind(&source.path).bind(show_hidden);
+    if let Some(size) = source.size.filter(|size| *size > 0) {
+        query = query.bind(size);
+    }
+    if let Some(duration) = source.duration.filter(|d| *d > 0.0) {
+        query = query.bind(duration).bind(duration_tolerance);
+    }
+    for token in name_tokens {
+        query = query.bind(like_pattern(token));
+    }
+    query.bind(limit).fetch_all(pool).await
 }
 
 /// Return all indexed paths from the database.
@@ -253,9 +697,10 @@ pub async fn list_indexed_paths(pool: &SqlitePool) -> Result<Vec<String>, sqlx::
 pub async fn upsert_file(pool: &SqlitePool, file: &IndexedFileRow) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-        INSERT INTO indexed_files (path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+        INSERT INTO indexed_files (path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, artist, album, title, track_number, page_count, author, indexed_at, first_indexed_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
         ON CONFLICT(path) DO UPDATE SET
+            path = excluded.path,
             name = excluded.name,
             is_dir = excluded.is_dir,
             size = excluded.size,
@@ -266,11 +711,18 @@ pub async fn upsert_file(pool: &SqlitePool, file: &IndexedFileRow) -> Result<(),
             height = excluded.height,
             duration = excluded.duration,
             metadata_status = excluded.metadata_status,
-            indexed_at = CURRENT_TIMESTAMP
+            artist = excluded.artist,
+            album = excluded.album,
+            title = excluded.title,
+            track_number = excluded.track_number,
+            page_count = excluded.page_count,
+            author = excluded.author,
+            indexed_at = CURRENT_TIMESTAMP,
+            first_indexed_at = COALESCE(indexed_files.first_indexed_at, CURRENT_TIMESTAMP)
         "#,
     )
-    .bind(&file.path)
-    .bind(&file.name)
+    .bind(canonical_name(&file.path))
+    .bind(canonical_name(&file.name))
     .bind(file.is_dir)
     .bind(file.size)
     .bind(&file.created_at)
@@ -280,32 +732,110 @@ pub async fn upsert_file(pool: &SqlitePool, file: &IndexedFileRow) -> Result<(),
     .bind(file.height)
     .bind(file.duration)
     .bind(&file.metadata_status)
+    .bind(&file.artist)
+    .bind(&file.album)
+    .bind(&file.title)
+    .bind(file.track_number)
+    .bind(file.page_count)
+    .bind(&file.author)
     .execute(pool)
     .await?;
 
     Ok(())
 }
 
-/// Update the media metadata fields for an existing path and bump its
-/// `indexed_at` timestamp.
+/// Record the name an entry had before a naming policy changed it, or clear
+/// it with `None`. Recording inserts a placeholder row for paths the indexer
+/// has not seen yet; the next index run fills in the rest and keeps this column.
+pub async fn set_original_name(
+    pool: &SqlitePool,
+    path: &str,
+    name: &str,
+    original_name: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let Some(original_name) = original_name else {
+        sqlx::query("UPDATE indexed_files SET original_name = NULL WHERE path = ?")
+            .bind(path)
+            .execute(pool)
+            .await?;
+        return Ok(());
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO indexed_files (path, name, is_dir, metadata_status, original_name)
+        VALUES (?, ?, 0, 'pending', ?)
+        ON CONFLICT(path) DO UPDATE SET original_name = excluded.original_name
+        "#,
+    )
+    .bind(path)
+    .bind(name)
+    .bind(original_name)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Update the media metadata fields (including audio tags, capture time,
+/// and location) for an existing path and bump its `indexed_at` timestamp.
+#[allow(clippy::too_many_arguments)]
 pub async fn update_media_metadata(
     pool: &SqlitePool,
     path: &str,
     width: Option<i32>,
     height: Option<i32>,
     duration: Option<f64>,
+    tags: &AudioTags,
+    captured_at: Option<NaiveDateTime>,
+    location: Option<(f64, f64)>,
     metadata_status: &str,
 ) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
         UPDATE indexed_files
-        SET width = ?, height = ?, duration = ?, metadata_status = ?, indexed_at = CURRENT_TIMESTAMP
+        SET width = ?, height = ?, duration = ?, artist = ?, album = ?, title = ?, track_number = ?,
+            captured_at = ?, latitude = ?, longitude = ?, metadata_status = ?,
+            indexed_at = CURRENT_TIMESTAMP
         WHERE path = ?
         "#,
     )
     .bind(width)
     .bind(height)
     .bind(duration)
+    .bind(&tags.artist)
+    .bind(&tags.album)
+    .bind(&tags.title)
+    .bind(tags.track_number.map(|n| n as i32))
+    .bind(captured_at.map(|t| t.format(CAPTURED_AT_FORMAT).to_string()))
+    .bind(location.map(|(latitude, _)| latitude))
+    .bind(location.map(|(_, longitude)| longitude))
+    .bind(metadata_status)
+    .bind(path)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Update the document metadata fields for an existing path and bump its
+/// `indexed_at` timestamp.
+pub async fn update_document_metadata(
+    pool: &SqlitePool,
+    path: &str,
+    document: &DocumentMetadata,
+    metadata_status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE indexed_files
+        SET page_count = ?, title = ?, author = ?, metadata_status = ?, indexed_at = CURRENT_TIMESTAMP
+        WHERE path = ?
+        "#,
+    )
+    .bind(document.page_count.map(|n| n as i32))
+    .bind(&document.title)
+    .bind(&document.author)
     .bind(metadata_status)
     .bind(path)
     .execute(pool)
@@ -315,6 +845,76 @@ pub async fn update_media_metadata(
 }
 
 /// Delete rows for the supplied paths (and their descendants), returning the number of deleted records.
+/// Count and total size of indexed files under `dir`, grouped by broad type.
+/// Returns `(category, count, size)` for categories that have any files;
+/// category names are `images`, `video`, `audio`, `documents`, and `other`.
+pub async fn get_type_stats(
+    pool: &SqlitePool,
+    dir: &str,
+) -> Result<Vec<(String, i64, i64)>, sqlx::Error> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    sqlx::query_as(
+        r#"
+        SELECT
+            CASE
+                WHEN mime_type LIKE 'image/%' THEN 'images'
+                WHEN mime_type LIKE 'video/%' THEN 'video'
+                WHEN mime_type LIKE 'audio/%' THEN 'audio'
+                WHEN mime_type LIKE 'text/%'
+                    OR mime_type IN ('application/pdf', 'application/msword', 'application/rtf', 'application/epub+zip')
+                    OR mime_type LIKE 'application/vnd.openxmlformats-officedocument.%'
+                    OR mime_type LIKE 'application/vnd.oasis.opendocument.%'
+                    OR mime_type LIKE 'application/vnd.ms-%'
+                    THEN 'documents'
+                ELSE 'other'
+            END AS category,
+            COUNT(*),
+            COALESCE(SUM(size), 0)
+        FROM indexed_files
+        WHERE is_dir = 0 AND substr(path, 1, length(?)) = ?
+        GROUP BY category
+        "#,
+    )
+    .bind(&prefix)
+    .bind(&prefix)
+    .fetch_all(pool)
+    .await
+}
+
+/// An indexed file with the time it was first seen
+#[derive(Debug, sqlx::FromRow)]
+pub struct RecentFileRow {
+    #[sqlx(flatten)]
+    pub file: IndexedFileRow,
+    pub first_indexed_at: String,
+}
+
+/// Files under `dir` first indexed at or after `since` (SQLite
+/// `CURRENT_TIMESTAMP` format), newest first
+pub async fn get_recent_additions(
+    pool: &SqlitePool,
+    dir: &str,
+    since: &str,
+    limit: i64,
+) -> Result<Vec<RecentFileRow>, sqlx::Error> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    sqlx::query_as(
+        r#"
+        SELECT id, path, name, is_dir, size, created_at, modified_at, mime_type, width, height, duration, metadata_status, indexed_at, artist, album, title, track_number, page_count, author, original_name, first_indexed_at
+        FROM indexed_files
+        WHERE is_dir = 0 AND first_indexed_at >= ? AND substr(path, 1, length(?)) = ?
+        ORDER BY first_indexed_at DESC, path ASC
+        LIMIT ?
+        "#,
+    )
+    .bind(since)
+    .bind(&prefix)
+    .bind(&prefix)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
 pub async fn delete_by_paths<T: AsRef<str>>(
     pool: &SqlitePool,
     paths: &[T],
@@ -327,14 +927,23 @@ pub async fn delete_by_paths<T: AsRef<str>>(
     let mut removed = 0;
 
     for path in paths {
-        let path = path.as_ref();
+        let path = &*canonical_name(path.as_ref());
         let pattern = format!("{}/%", path.trim_end_matches('/'));
         let result = sqlx::query("DELETE FROM indexed_files WHERE path = ? OR path LIKE ?")
             .bind(path)
-            .bind(pattern)
+            .bind(&pattern)
             .execute(&mut *tx)
             .await?;
         removed += result.rows_affected();
+        for table in PATH_KEYED_TABLES {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE path = ? OR path LIKE ?"
+            ))
+            .bind(path)
+            .bind(&pattern)
+            .execute(&mut *tx)
+            .await?;
+        }
     }
 
     tx.commit().await?;
@@ -348,6 +957,49 @@ pub async fn vacuum(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     Ok(())
 }
 
+/// Refresh the statistics the query planner uses to pick indexes.
+pub async fn analyze(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("ANALYZE").execute(pool).await?;
+    Ok(())
+}
+
+/// Problems found by `PRAGMA integrity_check`; empty when the database is
+/// intact.
+pub async fn integrity_check(pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(pool)
+        .await?;
+    if rows.len() == 1 && rows[0] == "ok" {
+        Ok(Vec::new())
+    } else {
+        Ok(rows)
+    }
+}
+
+/// Outcome of a WAL checkpoint, as reported by `PRAGMA wal_checkpoint`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CheckpointResult {
+    /// Whether readers or writers kept the checkpoint from completing
+    pub busy: bool,
+    /// Frames in the WAL file
+    pub log_frames: i64,
+    /// Frames copied back into the database
+    pub checkpointed_frames: i64,
+}
+
+/// Copy the WAL into the database and truncate it.
+pub async fn wal_checkpoint(pool: &SqlitePool) -> Result<CheckpointResult, sqlx::Error> {
+    let (busy, log_frames, checkpointed_frames): (i64, i64, i64) =
+        sqlx::query_as("PRAGMA wal_checkpoint(TRUNCATE)")
+            .fetch_one(pool)
+            .await?;
+    Ok(CheckpointResult {
+        busy: busy != 0,
+        log_frames,
+        checkpointed_frames,
+    })
+}
+
 /// Get the most recent `indexed_at` timestamp from the database.
 pub async fn get_last_indexed_at(pool: &SqlitePool) -> Result<Option<String>, sqlx::Error> {
     sqlx::query_scalar("SELECT MAX(indexed_at) FROM indexed_files")
@@ -364,27 +1016,755 @@ pub async fn get_indexed_totals(pool: &SqlitePool) -> Result<(i64, i64), sqlx::E
     .await
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::Utc;
-    use sqlx::sqlite::SqlitePoolOptions;
+/// Number of indexed files anywhere under `dir` and their total size, or
+/// `None` when `dir` itself is not indexed
+pub async fn get_tree_totals(
+    pool: &SqlitePool,
+    dir: &str,
+) -> Result<Option<(i64, i64)>, sqlx::Error> {
+    if get_file_by_path(pool, dir).await?.is_none() {
+        return Ok(None);
+    }
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM indexed_files \
+         WHERE is_dir = 0 AND substr(path, 1, length(?)) = ?",
+    )
+    .bind(&prefix)
+    .bind(&prefix)
+    .fetch_one(pool)
+    .await
+    .map(Some)
+}
 
-    fn now_sqlite_timestamp() -> String {
-        Utc::now()
-            .naive_utc()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string()
+/// Indexed subdirectories and files directly inside each of `dirs`, by
+/// path; directories missing from the index are left out
+pub async fn get_child_counts(
+    pool: &SqlitePool,
+    dirs: &[String],
+    show_hidden: bool,
+) -> Result<HashMap<String, (i64, i64)>, sqlx::Error> {
+    // Chunked like `get_files_by_ids`, under SQLite's default limit of 999
+    // bound parameters
+    const CHUNK_SIZE: usize = 949;
+    let mut counts = HashMap::new();
+    for chunk in dirs.chunks(CHUNK_SIZE) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let sql = format!(
+            "SELECT d.path, \
+                    COUNT(c.id) FILTER (WHERE c.is_dir), \
+                    COUNT(c.id) FILTER (WHERE NOT c.is_dir) \
+             FROM indexed_files d \
+             LEFT JOIN indexed_files c ON c.parent = d.path || '/' \
+                 AND (? OR c.name NOT LIKE '.%') \
+             WHERE d.is_dir AND d.path IN ({placeholders}) \
+             GROUP BY d.path"
+        );
+        let mut query = sqlx::query_as::<_, (String, i64, i64)>(&sql).bind(show_hidden);
+        for dir in chunk {
+            query = query.bind(dir);
+        }
+        counts.extend(
+            query
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|(path, dirs, files)| (path, (dirs, files))),
+        );
     }
+    Ok(counts)
+}
 
-    #[tokio::test]
-    async fn get_metadata_for_paths_batches_under_sqlite_variable_limit() {
-        let pool = SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect("sqlite::memory:")
-            .await
-            .unwrap();
-        crate::db::init_db(&pool).await.unwrap();
+/// Runs kept in `index_runs`; older ones are pruned as new ones are added
+const INDEX_RUN_HISTORY: i64 = 200;
+
+/// One index run as recorded in the run history
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct IndexRunRow {
+    pub id: i64,
+    pub started_at: String,
+    pub finished_at: String,
+    /// `complete`, `incomplete` (finished but skipped part of the work), or
+    /// `failed`
+    pub status: String,
+    pub files_scanned: i64,
+    pub files_indexed: i64,
+    pub files_removed: i64,
+    pub errors: i64,
+    pub note: Option<String>,
+}
+
+pub async fn record_index_run(pool: &SqlitePool, run: &IndexRunRow) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO index_runs \
+         (started_at, finished_at, status, files_scanned, files_indexed, files_removed, errors, note) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&run.started_at)
+    .bind(&run.finished_at)
+    .bind(&run.status)
+    .bind(run.files_scanned)
+    .bind(run.files_indexed)
+    .bind(run.files_removed)
+    .bind(run.errors)
+    .bind(&run.note)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM index_runs WHERE id NOT IN \
+         (SELECT id FROM index_runs ORDER BY id DESC LIMIT ?)",
+    )
+    .bind(INDEX_RUN_HISTORY)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Most recent runs first
+pub async fn list_index_runs(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<IndexRunRow>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM index_runs ORDER BY id DESC LIMIT ?")
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Runs kept in `hook_runs`; older ones are pruned as new ones are added
+const HOOK_RUN_HISTORY: i64 = 500;
+
+/// One execution of a server-side hook
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct HookRunRow {
+    pub id: i64,
+    pub hook: String,
+    pub event: String,
+    pub path: String,
+    /// Command line after placeholders were filled in
+    pub command: String,
+    /// `ok`, `failed` (non-zero exit), `timeout`, or `error` (did not start)
+    pub status: String,
+    pub exit_code: Option<i64>,
+    /// Combined stdout and stderr, truncated
+    pub output: String,
+    pub started_at: String,
+    pub duration_ms: i64,
+}
+
+pub async fn record_hook_run(pool: &SqlitePool, run: &HookRunRow) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO hook_runs \
+         (hook, event, path, command, status, exit_code, output, started_at, duration_ms) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&run.hook)
+    .bind(&run.event)
+    .bind(&run.path)
+    .bind(&run.command)
+    .bind(&run.status)
+    .bind(run.exit_code)
+    .bind(&run.output)
+    .bind(&run.started_at)
+    .bind(run.duration_ms)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM hook_runs WHERE id NOT IN \
+         (SELECT id FROM hook_runs ORDER BY id DESC LIMIT ?)",
+    )
+    .bind(HOOK_RUN_HISTORY)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Most recent runs first
+pub async fn list_hook_runs(pool: &SqlitePool, limit: i64) -> Result<Vec<HookRunRow>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM hook_runs ORDER BY id DESC LIMIT ?")
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Runs kept in `sync_runs` per job; older ones are pruned as new ones are
+/// added
+const SYNC_RUN_HISTORY: i64 = 100;
+
+/// A directory mirrored into another on a schedule
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SyncJobRow {
+    pub id: i64,
+    pub source: String,
+    pub destination: String,
+    pub interval_secs: i64,
+    /// Remove destination entries that are not in the source
+    pub delete_extraneous: bool,
+    /// Compare file contents rather than size and modification time
+    pub checksum: bool,
+    pub enabled: bool,
+    pub created_at: String,
+    pub next_run_at: String,
+}
+
+/// One run of a sync job
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SyncRunRow {
+    pub id: i64,
+    pub job_id: i64,
+    pub started_at: String,
+    pub finished_at: String,
+    /// `success` or `failed`
+    pub status: String,
+    pub created: i64,
+    pub updated: i64,
+    pub skipped: i64,
+    pub deleted: i64,
+    pub error: Option<String>,
+}
+
+/// Add a job; `id` is ignored. Returns the new job's id.
+pub async fn create_sync_job(pool: &SqlitePool, job: &SyncJobRow) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO sync_jobs \
+         (source, destination, interval_secs, delete_extraneous, checksum, enabled, created_at, next_run_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&job.source)
+    .bind(&job.destination)
+    .bind(job.interval_secs)
+    .bind(job.delete_extraneous)
+    .bind(job.checksum)
+    .bind(job.enabled)
+    .bind(&job.created_at)
+    .bind(&job.next_run_at)
+    .execute(pool)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_sync_job(pool: &SqlitePool, id: i64) -> Result<Option<SyncJobRow>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM sync_jobs WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn list_sync_jobs(pool: &SqlitePool) -> Result<Vec<SyncJobRow>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM sync_jobs ORDER BY id")
+        .fetch_all(pool)
+        .await
+}
+
+/// Remove a job and its run history. Returns whether it existed.
+pub async fn delete_sync_job(pool: &SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM sync_runs WHERE job_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    let result = sqlx::query("DELETE FROM sync_jobs WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Enabled jobs whose next run is at or before `now`, an RFC 3339 time in
+/// the same format as `next_run_at`
+pub async fn due_sync_jobs(pool: &SqlitePool, now: &str) -> Result<Vec<SyncJobRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT * FROM sync_jobs WHERE enabled AND next_run_at <= ? ORDER BY next_run_at",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn set_sync_job_next_run(
+    pool: &SqlitePool,
+    id: i64,
+    next_run_at: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE sync_jobs SET next_run_at = ? WHERE id = ?")
+        .bind(next_run_at)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Add a run to the job's history; `id` is ignored. Returns the new run's id.
+pub async fn record_sync_run(pool: &SqlitePool, run: &SyncRunRow) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO sync_runs \
+         (job_id, started_at, finished_at, status, created, updated, skipped, deleted, error) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(run.job_id)
+    .bind(&run.started_at)
+    .bind(&run.finished_at)
+    .bind(&run.status)
+    .bind(run.created)
+    .bind(run.updated)
+    .bind(run.skipped)
+    .bind(run.deleted)
+    .bind(&run.error)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM sync_runs WHERE job_id = ? AND id NOT IN \
+         (SELECT id FROM sync_runs WHERE job_id = ? ORDER BY id DESC LIMIT ?)",
+    )
+    .bind(run.job_id)
+    .bind(run.job_id)
+    .bind(SYNC_RUN_HISTORY)
+    .execute(pool)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+/// A job's runs, most recent first
+pub async fn list_sync_runs(
+    pool: &SqlitePool,
+    job_id: i64,
+    limit: i64,
+) -> Result<Vec<SyncRunRow>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM sync_runs WHERE job_id = ? ORDER BY id DESC LIMIT ?")
+        .bind(job_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Scans kept in `upload_scans`; older ones are pruned as new ones are added
+const UPLOAD_SCAN_HISTORY: i64 = 1000;
+
+/// The virus scan of one uploaded file
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct UploadScanRow {
+    pub id: i64,
+    /// Where the upload was headed
+    pub path: String,
+    /// `clean`, `infected`, `skipped` (too large to scan), or `error` (not
+    /// scanned, upload refused)
+    pub status: String,
+    pub signature: Option<String>,
+    /// Path of the infected file in the quarantine directory
+    pub quarantined_as: Option<String>,
+    pub error: Option<String>,
+    pub scanned_at: String,
+}
+
+pub async fn record_upload_scan(
+    pool: &SqlitePool,
+    scan: &UploadScanRow,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO upload_scans (path, status, signature, quarantined_as, error, scanned_at) \
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&scan.path)
+    .bind(&scan.status)
+    .bind(&scan.signature)
+    .bind(&scan.quarantined_as)
+    .bind(&scan.error)
+    .bind(&scan.scanned_at)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM upload_scans WHERE id NOT IN \
+         (SELECT id FROM upload_scans ORDER BY id DESC LIMIT ?)",
+    )
+    .bind(UPLOAD_SCAN_HISTORY)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Most recent scans first
+pub async fn list_upload_scans(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<UploadScanRow>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM upload_scans ORDER BY id DESC LIMIT ?")
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// A response kept for replay under an idempotency key
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct IdempotentResponseRow {
+    pub key: String,
+    /// What the original request was, so a key reused for another request
+    /// is refused rather than answered with the wrong response
+    pub fingerprint: String,
+    pub status: i64,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// The response stored under `key` within the last `ttl_secs`
+pub async fn get_idempotent_response(
+    pool: &SqlitePool,
+    key: &str,
+    ttl_secs: i64,
+) -> Result<Option<IdempotentResponseRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT key, fingerprint, status, content_type, body FROM idempotency_keys \
+         WHERE key = ? AND created_at >= datetime('now', ?)",
+    )
+    .bind(key)
+    .bind(format!("-{ttl_secs} seconds"))
+    .fetch_optional(pool)
+    .await
+}
+
+/// Store a response for replay, dropping responses older than `ttl_secs`
+pub async fn save_idempotent_response(
+    pool: &SqlitePool,
+    response: &IdempotentResponseRow,
+    ttl_secs: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE created_at < datetime('now', ?)")
+        .bind(format!("-{ttl_secs} seconds"))
+        .execute(pool)
+        .await?;
+    sqlx::query(
+        "INSERT OR REPLACE INTO idempotency_keys (key, fingerprint, status, content_type, body) \
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&response.key)
+    .bind(&response.fingerprint)
+    .bind(response.status)
+    .bind(&response.content_type)
+    .bind(&response.body)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Read a value stored alongside the index (e.g. the root fingerprint)
+pub async fn get_index_meta(pool: &SqlitePool, key: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT value FROM index_meta WHERE key = ?")
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn set_index_meta(pool: &SqlitePool, key: &str, value: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO index_meta (key, value) VALUES (?, ?) \
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+    )
+    .bind(key)
+    .bind(value)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A note someone left on a file or directory
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct FileNoteRow {
+    pub id: i64,
+    pub path: String,
+    pub body: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Notes on `path`, oldest first
+pub async fn list_notes(pool: &SqlitePool, path: &str) -> Result<Vec<FileNoteRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, path, body, created_at, updated_at FROM file_notes WHERE path = ? ORDER BY id",
+    )
+    .bind(path)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_note(pool: &SqlitePool, id: i64) -> Result<Option<FileNoteRow>, sqlx::Error> {
+    sqlx::query_as("SELECT id, path, body, created_at, updated_at FROM file_notes WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn create_note(
+    pool: &SqlitePool,
+    path: &str,
+    body: &str,
+) -> Result<FileNoteRow, sqlx::Error> {
+    sqlx::query_as(
+        "INSERT INTO file_notes (path, body) VALUES (?, ?) \
+         RETURNING id, path, body, created_at, updated_at",
+    )
+    .bind(path)
+    .bind(body)
+    .fetch_one(pool)
+    .await
+}
+
+/// Replace a note's text, returning the note unless it does not exist
+pub async fn update_note(
+    pool: &SqlitePool,
+    id: i64,
+    body: &str,
+) -> Result<Option<FileNoteRow>, sqlx::Error> {
+    sqlx::query_as(
+        "UPDATE file_notes SET body = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? \
+         RETURNING id, path, body, created_at, updated_at",
+    )
+    .bind(body)
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Remove a note, returning it unless it did not exist
+pub async fn delete_note(pool: &SqlitePool, id: i64) -> Result<Option<FileNoteRow>, sqlx::Error> {
+    sqlx::query_as(
+        "DELETE FROM file_notes WHERE id = ? RETURNING id, path, body, created_at, updated_at",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Which of `paths` have at least one note
+pub async fn paths_with_notes(
+    pool: &SqlitePool,
+    paths: &[String],
+) -> Result<HashSet<String>, sqlx::Error> {
+    // Chunked like `get_metadata_for_paths`, under SQLite's default limit of
+    // 999 bound parameters
+    const CHUNK_SIZE: usize = 949;
+    let mut noted = HashSet::new();
+    for chunk in paths.chunks(CHUNK_SIZE) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let query = format!("SELECT DISTINCT path FROM file_notes WHERE path IN ({placeholders})");
+        let mut query_builder = sqlx::query_scalar::<_, String>(&query);
+        for path in chunk {
+            query_builder = query_builder.bind(path);
+        }
+        noted.extend(query_builder.fetch_all(pool).await?);
+    }
+    Ok(noted)
+}
+
+/// Custom fields of `path`, by key
+pub async fn get_meta(
+    pool: &SqlitePool,
+    path: &str,
+) -> Result<BTreeMap<String, String>, sqlx::Error> {
+    let rows: Vec<(String, String)> =
+        sqlx::query_as("SELECT key, value FROM file_meta WHERE path = ?")
+            .bind(path)
+            .fetch_all(pool)
+            .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Set (`Some`) or remove (`None`) custom fields of `path` in one go
+pub async fn update_meta(
+    pool: &SqlitePool,
+    path: &str,
+    changes: &[(String, Option<String>)],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (key, value) in changes {
+        match value {
+            Some(value) => {
+                sqlx::query(
+                    "INSERT INTO file_meta (path, key, value) VALUES (?, ?, ?) \
+                     ON CONFLICT(path, key) DO UPDATE SET value = excluded.value, \
+                     updated_at = CURRENT_TIMESTAMP",
+                )
+                .bind(path)
+                .bind(key)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM file_meta WHERE path = ? AND key = ?")
+                    .bind(path)
+                    .bind(key)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+    tx.commit().await
+}
+
+/// A condition on a custom field
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaFilter {
+    /// The field is set
+    Exists(String),
+    Equals(String, String),
+    /// The field holds a number at least this large
+    AtLeast(String, f64),
+    /// The field holds a number at most this large
+    AtMost(String, f64),
+}
+
+/// IDs and paths of indexed entries meeting every filter
+pub async fn ids_matching_meta(
+    pool: &SqlitePool,
+    filters: &[MetaFilter],
+) -> Result<Vec<(i64, String)>, sqlx::Error> {
+    let field = "SELECT 1 FROM file_meta m WHERE m.path = f.path AND m.key = ?";
+    let conditions: Vec<String> = filters
+        .iter()
+        .map(|filter| match filter {
+            MetaFilter::Exists(_) => format!("EXISTS ({field})"),
+            MetaFilter::Equals(..) => format!("EXISTS ({field} AND m.value = ?)"),
+            MetaFilter::AtLeast(..) => format!(
+                "EXISTS ({field} AND m.value GLOB '*[0-9]*' AND CAST(m.value AS REAL) >= ?)"
+            ),
+            MetaFilter::AtMost(..) => format!(
+                "EXISTS ({field} AND m.value GLOB '*[0-9]*' AND CAST(m.value AS REAL) <= ?)"
+            ),
+        })
+        .collect();
+    let sql = format!(
+        "SELECT f.id, f.path FROM indexed_files f WHERE {}",
+        if conditions.is_empty() {
+            "1".to_string()
+        } else {
+            conditions.join(" AND ")
+        }
+    );
+
+    let mut query = sqlx::query_as(&sql);
+    for filter in filters {
+        query = match filter {
+            MetaFilter::Exists(key) => query.bind(key),
+            MetaFilter::Equals(key, value) => query.bind(key).bind(value),
+            MetaFilter::AtLeast(key, n) | MetaFilter::AtMost(key, n) => query.bind(key).bind(n),
+        };
+    }
+    query.fetch_all(pool).await
+}
+
+/// A condition on an indexed entry's own columns
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileFilter {
+    /// The name ends in one of these extensions (lowercase, without the dot)
+    Extension(Vec<String>),
+    /// At least this many bytes
+    MinSize(i64),
+    /// At most this many bytes
+    MaxSize(i64),
+    /// Modified at or after this RFC 3339 timestamp
+    ModifiedFrom(String),
+    /// Modified before this RFC 3339 timestamp
+    ModifiedBefore(String),
+    IsDir(bool),
+}
+
+/// IDs and paths of indexed entries meeting every filter
+pub async fn ids_matching_file_filters(
+    pool: &SqlitePool,
+    filters: &[FileFilter],
+) -> Result<Vec<(i64, String)>, sqlx::Error> {
+    let conditions: Vec<String> = filters
+        .iter()
+        .map(|filter| match filter {
+            FileFilter::Extension(extensions) => format!(
+                "({})",
+                vec!["lower(f.name) LIKE ?"; extensions.len().max(1)].join(" OR ")
+            ),
+            FileFilter::MinSize(_) => "f.size >= ?".to_string(),
+            FileFilter::MaxSize(_) => "f.size <= ?".to_string(),
+            FileFilter::ModifiedFrom(_) => "f.modified_at >= ?".to_string(),
+            FileFilter::ModifiedBefore(_) => "f.modified_at < ?".to_string(),
+            FileFilter::IsDir(_) => "f.is_dir = ?".to_string(),
+        })
+        .collect();
+    let sql = format!(
+        "SELECT f.id, f.path FROM indexed_files f WHERE {}",
+        if conditions.is_empty() {
+            "1".to_string()
+        } else {
+            conditions.join(" AND ")
+        }
+    );
+
+    let mut query = sqlx::query_as(&sql);
+    for filter in filters {
+        query = match filter {
+            FileFilter::Extension(extensions) if extensions.is_empty() => query.bind(""),
+            FileFilter::Extension(extensions) => {
+                for ext in extensions {
+                    query = query.bind(format!("%.{ext}"));
+                }
+                query
+            }
+            FileFilter::MinSize(n) | FileFilter::MaxSize(n) => query.bind(n),
+            FileFilter::ModifiedFrom(at) | FileFilter::ModifiedBefore(at) => query.bind(at),
+            FileFilter::IsDir(is_dir) => query.bind(is_dir),
+        };
+    }
+    query.fetch_all(pool).await
+}
+
+/// Values of the custom field `key` for the indexed entries `ids`, by ID
+pub async fn meta_values_for_ids(
+    pool: &SqlitePool,
+    ids: &[i64],
+    key: &str,
+) -> Result<HashMap<i64, String>, sqlx::Error> {
+    // Chunked like `get_files_by_ids`, under SQLite's default limit of 999
+    // bound parameters
+    const CHUNK_SIZE: usize = 949;
+    let mut values = HashMap::new();
+    for chunk in ids.chunks(CHUNK_SIZE) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let sql = format!(
+            "SELECT f.id, m.value FROM indexed_files f \
+             JOIN file_meta m ON m.path = f.path AND m.key = ? \
+             WHERE f.id IN ({placeholders})"
+        );
+        let mut query = sqlx::query_as::<_, (i64, String)>(&sql).bind(key);
+        for id in chunk {
+            query = query.bind(id);
+        }
+        values.extend(query.fetch_all(pool).await?);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn now_sqlite_timestamp() -> String {
+        Utc::now()
+            .naive_utc()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn get_metadata_for_paths_batches_under_sqlite_variable_limit() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
 
         let total = 1_100; // > SQLite's 999 variable limit
         for i in 0..total {
@@ -403,6 +1783,13 @@ mod tests {
                 duration: None,
                 metadata_status: "complete".to_string(),
                 indexed_at: now_sqlite_timestamp(),
+                artist: None,
+                album: None,
+                title: None,
+                track_number: None,
+                page_count: None,
+                author: None,
+                original_name: None,
             };
             upsert_file(&pool, &row).await.unwrap();
         }
@@ -463,6 +1850,128 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn nfd_paths_are_stored_and_matched_as_nfc() {
+        use unicode_normalization::UnicodeNormalization;
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        // A row indexed before v14 from an NFD name
+        let nfd_path: String = "/café.txt".nfd().collect();
+        sqlx::query("INSERT INTO indexed_files (path, name) VALUES (?, ?)")
+            .bind(&nfd_path)
+            .bind(&nfd_path[1..])
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO file_notes (path, body) VALUES (?, 'menu')")
+            .bind(&nfd_path)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("PRAGMA user_version = 13")
+            .execute(&pool)
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let stored: Vec<String> = sqlx::query_scalar("SELECT path FROM indexed_files")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, vec!["/café.txt".to_string()]);
+        assert_eq!(list_notes(&pool, "/café.txt").await.unwrap().len(), 1);
+
+        // Either spelling finds the row
+        let rows = get_metadata_for_paths(&pool, std::slice::from_ref(&nfd_path))
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        let renamed = rename_path(&pool, &nfd_path, "/menu.txt", "menu.txt")
+            .await
+            .unwrap();
+        assert_eq!(renamed, 1);
+        assert_eq!(list_notes(&pool, "/menu.txt").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn case_insensitive_paths_merge_duplicates_and_match_any_case() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        for (path, indexed_at) in [
+            ("/Docs/a.txt", "2024-01-01 00:00:00"),
+            ("/docs/a.txt", "2024-06-01 00:00:00"),
+            ("/docs/b.txt", "2024-01-01 00:00:00"),
+        ] {
+            sqlx::query("INSERT INTO indexed_files (path, name, indexed_at) VALUES (?, 'x', ?)")
+                .bind(path)
+                .bind(indexed_at)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let merged = crate::db::set_case_insensitive_paths(&pool, true)
+            .await
+            .unwrap();
+        assert_eq!(merged, 1);
+        assert!(
+            get_file_by_path(&pool, "/DOCS/A.TXT")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(
+            list_indexed_paths(&pool).await.unwrap(),
+            vec!["/docs/a.txt".to_string(), "/docs/b.txt".to_string()]
+        );
+        // Indexes survive the rebuild
+        let indexes: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'idx_files_path'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(indexes, 1);
+
+        // Re-indexing under a new spelling updates the row instead of adding one
+        let mut row = get_metadata_for_paths(&pool, &["/docs/b.txt".to_string()])
+            .await
+            .unwrap()
+            .remove(0);
+        row.path = "/Docs/B.txt".to_string();
+        upsert_file(&pool, &row).await.unwrap();
+        assert_eq!(
+            list_indexed_paths(&pool).await.unwrap(),
+            vec!["/docs/a.txt".to_string(), "/Docs/B.txt".to_string()]
+        );
+
+        // Switching back keeps everything and compares exactly again
+        assert_eq!(
+            crate::db::set_case_insensitive_paths(&pool, false)
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(
+            get_file_by_path(&pool, "/DOCS/A.TXT")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(list_indexed_paths(&pool).await.unwrap().len(), 2);
+    }
+
     #[tokio::test]
     async fn get_files_by_ids_chunks_and_sorts() {
         let pool = SqlitePoolOptions::new()
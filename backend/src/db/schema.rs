@@ -1,6 +1,8 @@
 use sqlx::{Error, sqlite::SqlitePool};
 
-const DB_VERSION: i64 = 1;
+use crate::services::filesystem::canonical_name;
+
+const DB_VERSION: i64 = 18;
 
 pub async fn init_db(pool: &SqlitePool) -> Result<(), Error> {
     // Enable WAL mode for better concurrent read/write performance
@@ -22,7 +24,15 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), Error> {
             height INTEGER,
             duration REAL,
             metadata_status TEXT NOT NULL DEFAULT 'complete',
-            indexed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            indexed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            artist TEXT,
+            album TEXT,
+            title TEXT,
+            track_number INTEGER,
+            page_count INTEGER,
+            author TEXT,
+            original_name TEXT,
+            first_indexed_at TEXT
         );
         
         CREATE INDEX IF NOT EXISTS idx_files_path ON indexed_files(path);
@@ -57,6 +67,65 @@ async fn migrate_db(pool: &SqlitePool) -> Result<(), Error> {
         migrate_to_v1(pool).await?;
     }
 
+    if version < 2 {
+        migrate_to_v2(pool).await?;
+    }
+
+    if version < 3 {
+        migrate_to_v3(pool).await?;
+    }
+
+    if version < 4 {
+        migrate_to_v4(pool).await?;
+    }
+
+    if version < 5 {
+        migrate_to_v5(pool).await?;
+    }
+
+    if version < 6 {
+        migrate_to_v6(pool).await?;
+    }
+
+    if version < 7 {
+        migrate_to_v7(pool).await?;
+    }
+
+    if version < 8 {
+        migrate_to_v8(pool).await?;
+    }
+
+    if version < 9 {
+        migrate_to_v9(pool).await?;
+    }
+    if version < 10 {
+        migrate_to_v10(pool).await?;
+    }
+    if version < 11 {
+        migrate_to_v11(pool).await?;
+    }
+    if version < 12 {
+        migrate_to_v12(pool).await?;
+    }
+    if version < 13 {
+        migrate_to_v13(pool).await?;
+    }
+    if version < 14 {
+        migrate_to_v14(pool).await?;
+    }
+    if version < 15 {
+        migrate_to_v15(pool).await?;
+    }
+    if version < 16 {
+        migrate_to_v16(pool).await?;
+    }
+    if version < 17 {
+        migrate_to_v17(pool).await?;
+    }
+    if version < 18 {
+        migrate_to_v18(pool).await?;
+    }
+
     if version < DB_VERSION {
         set_user_version(pool, DB_VERSION).await?;
     }
@@ -80,6 +149,476 @@ async fn migrate_to_v1(pool: &SqlitePool) -> Result<(), Error> {
     Ok(())
 }
 
+async fn migrate_to_v2(pool: &SqlitePool) -> Result<(), Error> {
+    // Audio tag columns; existing rows get filled on the next metadata pass.
+    for (column, ty) in [
+        ("artist", "TEXT"),
+        ("album", "TEXT"),
+        ("title", "TEXT"),
+        ("track_number", "INTEGER"),
+    ] {
+        if !column_exists(pool, "indexed_files", column).await? {
+            let sql = format!("ALTER TABLE indexed_files ADD COLUMN {column} {ty}");
+            sqlx::query(&sql).execute(pool).await?;
+        }
+    }
+
+    // Re-queue audio files so tags are extracted for rows indexed before v2.
+    sqlx::query(
+        "UPDATE indexed_files SET metadata_status = 'pending' WHERE mime_type LIKE 'audio/%'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v3(pool: &SqlitePool) -> Result<(), Error> {
+    // Document metadata columns; `title` is shared with audio tags.
+    for (column, ty) in [("page_count", "INTEGER"), ("author", "TEXT")] {
+        if !column_exists(pool, "indexed_files", column).await? {
+            let sql = format!("ALTER TABLE indexed_files ADD COLUMN {column} {ty}");
+            sqlx::query(&sql).execute(pool).await?;
+        }
+    }
+
+    sqlx::query(
+        "UPDATE indexed_files SET metadata_status = 'pending' WHERE mime_type = 'application/pdf'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v4(pool: &SqlitePool) -> Result<(), Error> {
+    // Pre-normalization names for entries renamed by a `.fxnames` policy.
+    if !column_exists(pool, "indexed_files", "original_name").await? {
+        sqlx::query("ALTER TABLE indexed_files ADD COLUMN original_name TEXT")
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn migrate_to_v5(pool: &SqlitePool) -> Result<(), Error> {
+    // When a path was first seen, unlike `indexed_at` which moves on every
+    // change. Existing rows fall back to their last index time.
+    if !column_exists(pool, "indexed_files", "first_indexed_at").await? {
+        sqlx::query("ALTER TABLE indexed_files ADD COLUMN first_indexed_at TEXT")
+            .execute(pool)
+            .await?;
+    }
+    sqlx::query(
+        "UPDATE indexed_files SET first_indexed_at = indexed_at WHERE first_indexed_at IS NULL",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_files_first_indexed_at ON indexed_files(first_indexed_at)",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v6(pool: &SqlitePool) -> Result<(), Error> {
+    // Small key/value store for facts about the index as a whole, such as
+    // the fingerprint of the root it was built from.
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS index_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v7(pool: &SqlitePool) -> Result<(), Error> {
+    // One row per index run, so runs that skipped the missing-file purge or
+    // failed can be told apart from complete ones afterwards.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS index_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            started_at TEXT NOT NULL,
+            finished_at TEXT NOT NULL,
+            status TEXT NOT NULL,
+            files_scanned INTEGER NOT NULL DEFAULT 0,
+            files_indexed INTEGER NOT NULL DEFAULT 0,
+            files_removed INTEGER NOT NULL DEFAULT 0,
+            errors INTEGER NOT NULL DEFAULT 0,
+            note TEXT
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v8(pool: &SqlitePool) -> Result<(), Error> {
+    // Audit trail of server-side hook commands and what they printed.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS hook_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            hook TEXT NOT NULL,
+            event TEXT NOT NULL,
+            path TEXT NOT NULL,
+            command TEXT NOT NULL,
+            status TEXT NOT NULL,
+            exit_code INTEGER,
+            output TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            duration_ms INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v9(pool: &SqlitePool) -> Result<(), Error> {
+    // Directory of each entry, with its trailing slash ("/a/b/" for "/a/b/c",
+    // "/" for "/c"), so a directory can be listed from the index without
+    // scanning everything below it. rtrim strips the trailing characters
+    // that are not "/", i.e. the last path component.
+    if !column_exists(pool, "indexed_files", "parent").await? {
+        sqlx::query(
+            "ALTER TABLE indexed_files ADD COLUMN parent TEXT \
+             GENERATED ALWAYS AS (rtrim(path, replace(path, '/', ''))) VIRTUAL",
+        )
+        .execute(pool)
+        .await?;
+    }
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_files_parent ON indexed_files(parent)")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v10(pool: &SqlitePool) -> Result<(), Error> {
+    // When a photo or video was taken, from EXIF or container tags
+    if !column_exists(pool, "indexed_files", "captured_at").await? {
+        sqlx::query("ALTER TABLE indexed_files ADD COLUMN captured_at TEXT")
+            .execute(pool)
+            .await?;
+    }
+
+    // Re-queue media so capture dates are read for rows indexed before v10.
+    sqlx::query(
+        "UPDATE indexed_files SET metadata_status = 'pending' \
+         WHERE mime_type LIKE 'image/%' OR mime_type LIKE 'video/%'",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_files_taken \
+         ON indexed_files(COALESCE(captured_at, modified_at))",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v11(pool: &SqlitePool) -> Result<(), Error> {
+    // Where a photo or video was taken, in degrees, from EXIF GPS or
+    // container tags
+    for column in ["latitude", "longitude"] {
+        if !column_exists(pool, "indexed_files", column).await? {
+            let sql = format!("ALTER TABLE indexed_files ADD COLUMN {column} REAL");
+            sqlx::query(&sql).execute(pool).await?;
+        }
+    }
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_files_location ON indexed_files(latitude, longitude) \
+         WHERE latitude IS NOT NULL",
+    )
+    .execute(pool)
+    .await?;
+
+    // Re-queue media so locations are read for rows indexed before v11.
+    sqlx::query(
+        "UPDATE indexed_files SET metadata_status = 'pending' \
+         WHERE mime_type LIKE 'image/%' OR mime_type LIKE 'video/%'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v12(pool: &SqlitePool) -> Result<(), Error> {
+    // Notes people leave on files, keyed by path like `indexed_files` so they
+    // follow renames and can be left before the indexer has seen the file
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_file_notes_path ON file_notes(path);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v13(pool: &SqlitePool) -> Result<(), Error> {
+    // Custom key/value fields per path (ratings, status, ...), keyed by path
+    // like `file_notes`
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_meta (
+            path TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (path, key)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_file_meta_key ON file_meta(key, value);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v14(pool: &SqlitePool) -> Result<(), Error> {
+    // Paths are stored in NFC; rows indexed from NFD names (macOS over SMB)
+    // are rewritten. Only non-ASCII rows can differ. Where both spellings
+    // were indexed, the rewritten row replaces the other.
+    let mut tx = pool.begin().await?;
+
+    let rows: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT id, path, name FROM indexed_files \
+         WHERE path GLOB '*[^ -~]*' OR name GLOB '*[^ -~]*'",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for (id, path, name) in rows {
+        let (canonical_path, canonical) = (canonical_name(&path), canonical_name(&name));
+        if canonical_path != path || canonical != name {
+            sqlx::query("UPDATE OR REPLACE indexed_files SET path = ?, name = ? WHERE id = ?")
+                .bind(canonical_path)
+                .bind(canonical)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    for table in ["file_notes", "file_meta"] {
+        let paths: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT path FROM {table} WHERE path GLOB '*[^ -~]*'"
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+        for path in paths {
+            let canonical = canonical_name(&path);
+            if canonical != path {
+                sqlx::query(&format!(
+                    "UPDATE OR REPLACE {table} SET path = ? WHERE path = ?"
+                ))
+                .bind(canonical)
+                .bind(&path)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+    }
+
+    tx.commit().await
+}
+
+async fn migrate_to_v15(pool: &SqlitePool) -> Result<(), Error> {
+    // Links made through the API: `hard` or `symbolic`, and the path the
+    // link was made to
+    for column in ["link_kind", "link_target"] {
+        if !column_exists(pool, "indexed_files", column).await? {
+            let sql = format!("ALTER TABLE indexed_files ADD COLUMN {column} TEXT");
+            sqlx::query(&sql).execute(pool).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn migrate_to_v16(pool: &SqlitePool) -> Result<(), Error> {
+    // Responses to requests sent with an `Idempotency-Key`, replayed when a
+    // client retries; `key` is scoped to the client that sent it
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            key TEXT PRIMARY KEY,
+            fingerprint TEXT NOT NULL,
+            status INTEGER NOT NULL,
+            content_type TEXT,
+            body BLOB NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_idempotency_created ON idempotency_keys(created_at);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v17(pool: &SqlitePool) -> Result<(), Error> {
+    // One-way mirrors of one directory into another, run on a schedule, and
+    // the outcome of each run
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sync_jobs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source TEXT NOT NULL,
+            destination TEXT NOT NULL,
+            interval_secs INTEGER NOT NULL,
+            delete_extraneous BOOLEAN NOT NULL DEFAULT FALSE,
+            checksum BOOLEAN NOT NULL DEFAULT FALSE,
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at TEXT NOT NULL,
+            next_run_at TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS sync_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL,
+            started_at TEXT NOT NULL,
+            finished_at TEXT NOT NULL,
+            status TEXT NOT NULL,
+            created INTEGER NOT NULL DEFAULT 0,
+            updated INTEGER NOT NULL DEFAULT 0,
+            skipped INTEGER NOT NULL DEFAULT 0,
+            deleted INTEGER NOT NULL DEFAULT 0,
+            error TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_sync_runs_job ON sync_runs(job_id, id);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate_to_v18(pool: &SqlitePool) -> Result<(), Error> {
+    // Virus scan verdicts for uploads, including where infected files were
+    // quarantined
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS upload_scans (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL,
+            status TEXT NOT NULL,
+            signature TEXT,
+            quarantined_as TEXT,
+            error TEXT,
+            scanned_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// How `indexed_files.path` is declared, comparing exactly or ignoring case
+const PATH_COLUMN: &str = "path TEXT NOT NULL UNIQUE";
+const PATH_COLUMN_NOCASE: &str = "path TEXT NOT NULL UNIQUE COLLATE NOCASE";
+
+/// Make `indexed_files.path` compare ignoring ASCII case (SQLite's NOCASE) or
+/// exactly, so every lookup, rename and upsert by path follows suit. The
+/// table is rebuilt when the setting changes; when switching to NOCASE, rows
+/// differing only in case are merged, keeping the most recently indexed.
+/// Returns how many rows were merged away.
+pub async fn set_case_insensitive_paths(pool: &SqlitePool, enabled: bool) -> Result<u64, Error> {
+    let table_sql: String = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'indexed_files'",
+    )
+    .fetch_one(pool)
+    .await?;
+    if table_sql.contains(PATH_COLUMN_NOCASE) == enabled {
+        return Ok(0);
+    }
+    let (from, to) = if enabled {
+        (PATH_COLUMN, PATH_COLUMN_NOCASE)
+    } else {
+        (PATH_COLUMN_NOCASE, PATH_COLUMN)
+    };
+    let rebuild_sql =
+        table_sql
+            .replacen(from, to, 1)
+            .replacen("indexed_files", "indexed_files_rebuild", 1);
+    let index_sqls: Vec<String> = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master \
+         WHERE type = 'index' AND tbl_name = 'indexed_files' AND sql IS NOT NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // Generated columns (`parent`) are left out of `table_info` and cannot be
+    // inserted into
+    let columns: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM pragma_table_info('indexed_files')")
+            .fetch_all(pool)
+            .await?;
+    let columns = columns.join(", ");
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(&rebuild_sql).execute(&mut *tx).await?;
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM indexed_files")
+        .fetch_one(&mut *tx)
+        .await?;
+    let kept = sqlx::query(&format!(
+        "INSERT INTO indexed_files_rebuild ({columns}) \
+         SELECT {columns} FROM indexed_files WHERE id IN ( \
+             SELECT id FROM ( \
+                 SELECT id, ROW_NUMBER() OVER ( \
+                     PARTITION BY path COLLATE NOCASE ORDER BY indexed_at DESC, id DESC \
+                 ) AS rank FROM indexed_files \
+             ) WHERE rank = 1 OR ? = 0 \
+         )"
+    ))
+    .bind(enabled)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query("DROP TABLE indexed_files")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("ALTER TABLE indexed_files_rebuild RENAME TO indexed_files")
+        .execute(&mut *tx)
+        .await?;
+    for sql in index_sqls {
+        sqlx::query(&sql).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+
+    Ok(total as u64 - kept)
+}
+
 /// Check if a column exists on a given table
 async fn column_exists(pool: &SqlitePool, table: &str, column: &str) -> Result<bool, Error> {
     let exists: Option<(i64,)> =
@@ -1,6 +1,13 @@
 pub mod api;
+pub mod app;
 pub mod config;
 pub mod db;
 pub mod models;
 pub mod services;
 pub mod version;
+
+pub use app::{build_router, build_router_with_logs};
+pub use config::Config;
+pub use services::{
+    FilesystemService, IndexerService, MemoryBackend, SearchService, StorageBackend,
+};
@@ -23,6 +23,34 @@ pub struct FileEntry {
     pub duration: Option<f64>, // seconds
     #[serde(skip_serializing_if = "Option::is_none")]
     pub indexed_at: Option<DateTime<Utc>>,
+
+    // Audio tags (from index, if available)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_number: Option<u32>,
+
+    // Document metadata (from index, if available)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Name as uploaded or requested, when a naming policy changed it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_name: Option<String>,
+    /// Someone left a note on the entry
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_notes: bool,
+    /// `hard` or `symbolic`, for links made through the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_kind: Option<String>,
+    /// Path the link was made to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
 }
 
 /// Directory tree node for sidebar
@@ -31,6 +59,17 @@ pub struct TreeNode {
     pub name: String,
     pub path: String,
     pub has_children: bool,
+    /// False for directories the server cannot list, shown without children
+    pub readable: bool,
+    /// Subdirectories, when the tree was requested more than one level deep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<TreeNode>>,
+    /// Subdirectories and files directly inside, from the index; absent
+    /// when the directory has not been indexed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub child_dir_count: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<i64>,
 }
 
 /// Raw indexed file row from the database
@@ -50,6 +89,46 @@ pub struct IndexedFileRow {
     #[serde(skip_serializing)]
     pub metadata_status: String,
     pub indexed_at: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track_number: Option<i32>,
+    pub page_count: Option<i32>,
+    pub author: Option<String>,
+    pub original_name: Option<String>,
+}
+
+#[cfg(test)]
+impl IndexedFileRow {
+    /// Indexed row for `path` with its metadata complete and nothing else
+    /// known. Tests fill in what they need with struct update syntax.
+    pub fn for_tests(path: &str, is_dir: bool) -> Self {
+        Self {
+            id: 0,
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap_or_default().to_string(),
+            is_dir,
+            size: None,
+            created_at: None,
+            modified_at: None,
+            mime_type: None,
+            width: None,
+            height: None,
+            duration: None,
+            metadata_status: "complete".to_string(),
+            indexed_at: Utc::now()
+                .naive_utc()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+            artist: None,
+            album: None,
+            title: None,
+            track_number: None,
+            page_count: None,
+            author: None,
+            original_name: None,
+        }
+    }
 }
 
 impl From<IndexedFileRow> for FileEntry {
@@ -77,6 +156,16 @@ impl From<IndexedFileRow> for FileEntry {
             indexed_at: NaiveDateTime::parse_from_str(&row.indexed_at, "%Y-%m-%d %H:%M:%S")
                 .ok()
                 .map(|dt| Utc.from_utc_datetime(&dt)),
+            artist: row.artist,
+            album: row.album,
+            title: row.title,
+            track_number: row.track_number.map(|n| n as u32),
+            page_count: row.page_count.map(|n| n as u32),
+            author: row.author,
+            original_name: row.original_name,
+            has_notes: false,
+            link_kind: None,
+            link_target: None,
         }
     }
 }
@@ -89,4 +178,35 @@ pub struct MediaMetadata {
     pub duration: Option<f64>,
     pub codec: Option<String>,
     pub format: Option<String>,
+    pub tags: AudioTags,
+    /// When the recording was made, from container tags
+    pub captured_at: Option<NaiveDateTime>,
+    /// Where it was made, as `(latitude, longitude)`, from container tags
+    pub location: Option<(f64, f64)>,
+}
+
+/// Music tags (ID3, Vorbis comments, MP4 atoms) as reported by ffprobe
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track_number: Option<u32>,
+}
+
+impl AudioTags {
+    pub fn is_empty(&self) -> bool {
+        self.artist.is_none()
+            && self.album.is_none()
+            && self.title.is_none()
+            && self.track_number.is_none()
+    }
+}
+
+/// Document metadata extracted from pdfinfo
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentMetadata {
+    pub page_count: Option<u32>,
+    pub title: Option<String>,
+    pub author: Option<String>,
 }
@@ -0,0 +1,268 @@
+//! Virus scanning of uploads with ClamAV. Files are streamed to clamd with
+//! its `INSTREAM` command before they are moved into place; infected ones
+//! are moved to a quarantine directory outside the root instead.
+
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::config::Config;
+
+/// Bytes sent to clamd per `INSTREAM` chunk
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Where clamd listens
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClamdAddress {
+    /// A Unix socket, such as `/run/clamav/clamd.ctl`
+    Unix(PathBuf),
+    /// `host:port`
+    Tcp(String),
+}
+
+impl ClamdAddress {
+    /// A path for a Unix socket, `host:port` otherwise
+    pub fn parse(value: &str) -> Self {
+        match value.strip_prefix("tcp://") {
+            Some(address) => Self::Tcp(address.to_string()),
+            None if value.starts_with('/') => Self::Unix(PathBuf::from(value)),
+            None => Self::Tcp(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanVerdict {
+    Clean,
+    /// With the signature clamd matched
+    Infected(String),
+    /// Larger than the scan limit, so not sent to clamd
+    Skipped,
+}
+
+/// Read clamd's reply to `INSTREAM`: `stream: OK`, `stream: <name> FOUND`,
+/// or an error such as `INSTREAM size limit exceeded. ERROR`
+pub fn parse_reply(reply: &str) -> std::io::Result<ScanVerdict> {
+    let reply = reply.trim_end_matches(['\0', '\n']).trim();
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(ScanVerdict::Infected(signature.trim().to_string()))
+    } else {
+        Err(std::io::Error::other(format!("clamd: {result}")))
+    }
+}
+
+pub struct VirusScanner {
+    /// `None` turns scanning off
+    address: Option<ClamdAddress>,
+    quarantine_dir: PathBuf,
+    /// Larger files are not scanned
+    max_bytes: u64,
+}
+
+impl VirusScanner {
+    pub fn disabled() -> Self {
+        Self {
+            address: None,
+            quarantine_dir: PathBuf::new(),
+            max_bytes: 0,
+        }
+    }
+
+    pub fn new(address: ClamdAddress, quarantine_dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            address: Some(address),
+            quarantine_dir,
+            max_bytes,
+        }
+    }
+
+    /// Scan with `FM_CLAMD_SOCKET`, if set. Quarantined files go to
+    /// `FM_QUARANTINE_DIR`, or `quarantine` next to the database.
+    pub fn from_config(config: &Config) -> Self {
+        let Some(socket) = &config.clamd_socket else {
+            return Self::disabled();
+        };
+        let quarantine_dir = config.quarantine_dir.clone().unwrap_or_else(|| {
+            config
+                .database_path
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join("quarantine")
+        });
+        Self::new(
+            ClamdAddress::parse(socket),
+            quarantine_dir,
+            config.clamd_max_mb * 1024 * 1024,
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.address.is_some()
+    }
+
+    /// Scan a file. Errors mean it could not be scanned, not that it is
+    /// infected.
+    pub async fn scan_file(&self, path: &Path) -> std::io::Result<ScanVerdict> {
+        let Some(address) = &self.address else {
+            return Ok(ScanVerdict::Clean);
+        };
+        let mut file = tokio::fs::File::open(path).await?;
+        if file.metadata().await?.len() > self.max_bytes {
+            return Ok(ScanVerdict::Skipped);
+        }
+        match address {
+            ClamdAddress::Tcp(address) => {
+                let stream = tokio::net::TcpStream::connect(address).await?;
+                instream(stream, &mut file).await
+            }
+            #[cfg(unix)]
+            ClamdAddress::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path).await?;
+                instream(stream, &mut file).await
+            }
+            #[cfg(not(unix))]
+            ClamdAddress::Unix(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix sockets are not supported on this platform",
+            )),
+        }
+    }
+
+    /// Move an infected file out of the root, keeping its name after a
+    /// timestamp. Returns where it went.
+    pub async fn quarantine(&self, path: &Path, name: &str) -> std::io::Result<PathBuf> {
+        tokio::fs::create_dir_all(&self.quarantine_dir).await?;
+        let target = self.quarantine_dir.join(format!(
+            "{}-{}-{}",
+            chrono::Utc::now().format("%Y%m%d-%H%M%S"),
+            uuid::Uuid::new_v4().simple(),
+            name
+        ));
+        // The quarantine may be on another volume than the root
+        if tokio::fs::rename(path, &target).await.is_err() {
+            tokio::fs::copy(path, &target).await?;
+            tokio::fs::remove_file(path).await?;
+        }
+        Ok(target)
+    }
+}
+
+/// Send a file with `zINSTREAM` and read the verdict
+async fn instream<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    file: &mut tokio::fs::File,
+) -> std::io::Result<ScanVerdict> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    let mut buf = vec![0u8; CHUNK_BYTES];
+    loop {
+        let n = file.read(&mut buf).await?;
+        stream.write_all(&(n as u32).to_be_bytes()).await?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n]).await?;
+    }
+    stream.flush().await?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    parse_reply(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// A clamd stand-in that reports any stream containing `EICAR` as
+    /// infected. Returns its address.
+    pub(crate) async fn fake_clamd() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut command = [0u8; 10];
+                    socket.read_exact(&mut command).await.unwrap();
+                    assert_eq!(&command, b"zINSTREAM\0");
+                    let mut data = Vec::new();
+                    loop {
+                        let len = socket.read_u32().await.unwrap() as usize;
+                        if len == 0 {
+                            break;
+                        }
+                        let mut chunk = vec![0u8; len];
+                        socket.read_exact(&mut chunk).await.unwrap();
+                        data.extend(chunk);
+                    }
+                    let reply: &[u8] = if data.windows(5).any(|w| w == b"EICAR") {
+                        b"stream: Eicar-Test-Signature FOUND\0"
+                    } else {
+                        b"stream: OK\0"
+                    };
+                    socket.write_all(reply).await.unwrap();
+                });
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn reads_clamd_replies_and_addresses() {
+        assert_eq!(parse_reply("stream: OK\0").unwrap(), ScanVerdict::Clean);
+        assert_eq!(
+            parse_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap(),
+            ScanVerdict::Infected("Win.Test.EICAR_HDB-1".to_string())
+        );
+        assert!(parse_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+
+        assert_eq!(
+            ClamdAddress::parse("/run/clamav/clamd.ctl"),
+            ClamdAddress::Unix(PathBuf::from("/run/clamav/clamd.ctl"))
+        );
+        assert_eq!(
+            ClamdAddress::parse("tcp://clamav:3310"),
+            ClamdAddress::Tcp("clamav:3310".to_string())
+        );
+        assert_eq!(
+            ClamdAddress::parse("localhost:3310"),
+            ClamdAddress::Tcp("localhost:3310".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn scans_files_and_quarantines_them() {
+        let tmp = tempfile::tempdir().unwrap();
+        let scanner = VirusScanner::new(
+            ClamdAddress::Tcp(fake_clamd().await),
+            tmp.path().join("quarantine"),
+            1024,
+        );
+
+        let clean = tmp.path().join("clean.txt");
+        std::fs::write(&clean, "hello").unwrap();
+        assert_eq!(scanner.scan_file(&clean).await.unwrap(), ScanVerdict::Clean);
+
+        let infected = tmp.path().join("eicar.com");
+        std::fs::write(&infected, "X5O!P%@AP EICAR test").unwrap();
+        assert_eq!(
+            scanner.scan_file(&infected).await.unwrap(),
+            ScanVerdict::Infected("Eicar-Test-Signature".to_string())
+        );
+        let moved = scanner.quarantine(&infected, "eicar.com").await.unwrap();
+        assert!(!infected.exists());
+        assert!(moved.starts_with(tmp.path().join("quarantine")));
+
+        let large = tmp.path().join("large.bin");
+        std::fs::write(&large, vec![0u8; 2048]).unwrap();
+        assert_eq!(
+            scanner.scan_file(&large).await.unwrap(),
+            ScanVerdict::Skipped
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! ZIP archives of a selection of files and folders, written to any
+//! `Write` so they can be streamed without a temporary file.
+
+use chrono::{DateTime, Utc};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Already-compressed formats are stored as-is; deflating them costs CPU
+/// for no gain
+fn compression_for(path: &Path) -> CompressionMethod {
+    let compressed = mime_guess::from_path(path).first().is_some_and(|mime| {
+        matches!(mime.type_().as_str(), "image" | "video" | "audio")
+            || matches!(
+                mime.subtype().as_str(),
+                "zip" | "gzip" | "x-7z-compressed" | "x-rar-compressed" | "x-bzip2" | "x-xz"
+            )
+    });
+    if compressed {
+        CompressionMethod::Stored
+    } else {
+        CompressionMethod::Deflated
+    }
+}
+
+fn options_for(path: &Path, metadata: &fs::Metadata) -> SimpleFileOptions {
+    let mut options = SimpleFileOptions::default()
+        .compression_method(compression_for(path))
+        .large_file(metadata.len() >= u32::MAX as u64);
+    if let Some(modified) = metadata
+        .modified()
+        .ok()
+        .and_then(|t| zip::DateTime::try_from(DateTime::<Utc>::from(t).naive_utc()).ok())
+    {
+        options = options.last_modified_time(modified);
+    }
+    options
+}
+
+pub struct ArchiveService;
+
+impl ArchiveService {
+    /// Write `entries` as a ZIP. Each entry is an absolute path and the name
+    /// it gets inside the archive; directories are added recursively.
+    /// Symlinks are skipped so the archive cannot reach outside the root.
+    pub fn write_zip<W: Write>(entries: &[(PathBuf, String)], writer: W) -> io::Result<W> {
+        let mut zip = ZipWriter::new_stream(writer);
+
+        for (path, name) in entries {
+            for item in WalkDir::new(path).follow_links(false).sort_by_file_name() {
+                let item = item.map_err(io::Error::other)?;
+                let relative = item.path().strip_prefix(path).unwrap_or(Path::new(""));
+                let mut archive_name = name.clone();
+                for component in relative.components() {
+                    archive_name.push('/');
+                    archive_name.push_str(&component.as_os_str().to_string_lossy());
+                }
+
+                let file_type = item.file_type();
+                if file_type.is_dir() {
+                    zip.add_directory(archive_name, SimpleFileOptions::default())
+                        .map_err(io::Error::other)?;
+                } else if file_type.is_file() {
+                    let metadata = item.metadata().map_err(io::Error::other)?;
+                    zip.start_file(archive_name, options_for(item.path(), &metadata))
+                        .map_err(io::Error::other)?;
+                    io::copy(&mut File::open(item.path())?, &mut zip)?;
+                }
+            }
+        }
+
+        Ok(zip.finish().map_err(io::Error::other)?.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use tempfile::tempdir;
+
+    #[test]
+    fn archives_files_and_folders_under_given_names() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("docs/sub")).unwrap();
+        fs::write(root.join("docs/a.txt"), b"alpha").unwrap();
+        fs::write(root.join("docs/sub/b.txt"), b"beta").unwrap();
+        fs::write(root.join("photo.jpg"), b"jpeg").unwrap();
+
+        let entries = vec![
+            (root.join("docs"), "docs".to_string()),
+            (root.join("photo.jpg"), "photo.jpg".to_string()),
+        ];
+        let bytes = ArchiveService::write_zip(&entries, Vec::new()).unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "docs/",
+                "docs/a.txt",
+                "docs/sub/",
+                "docs/sub/b.txt",
+                "photo.jpg"
+            ]
+        );
+
+        let mut text = String::new();
+        archive
+            .by_name("docs/sub/b.txt")
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "beta");
+        assert_eq!(
+            archive.by_name("photo.jpg").unwrap().compression(),
+            CompressionMethod::Stored
+        );
+    }
+}
@@ -0,0 +1,859 @@
+//! Storage behind the file operations, so they can run against something
+//! other than a local directory: an in-memory tree for fast tests, and later
+//! remote stores. Paths are root-relative, as in the API. The write and
+//! sync endpoints in `api::files` go through `AppState::backend`, and the
+//! indexer walks the tree through it; downloads, uploads, thumbnails and
+//! media metadata still need local paths.
+
+use chrono::{DateTime, Utc};
+use ignore::WalkBuilder;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use super::filesystem::{
+    ExpectedState, FilesystemService, FsError, IGNORE_FILE, OperationResult, SyncCompare,
+    SyncStats, canonical_name, is_hidden_path,
+};
+use crate::models::FileEntry;
+
+/// One entry found by [`StorageBackend::walk`]
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    /// Root-relative path in canonical Unicode form, `/` for the root
+    pub path: String,
+    pub name: String,
+    pub is_dir: bool,
+    /// False for directories and for links, which are never followed
+    pub is_file: bool,
+    pub size: Option<u64>,
+    pub created: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+    /// Where the entry is on local disk, for tools that need a file to open
+    pub local_path: Option<PathBuf>,
+}
+
+/// The entries of a walk, with errors for what could not be read
+pub type Walk<'a> = Box<dyn Iterator<Item = Result<WalkEntry, FsError>> + Send + 'a>;
+
+/// File operations on a tree of files addressed by root-relative paths.
+/// Implementations must refuse paths that leave the root.
+pub trait StorageBackend: Send + Sync {
+    /// The entry for one file or directory, as listed in its directory
+    fn get_entry(&self, path: &str) -> Result<FileEntry, FsError>;
+
+    /// Directory contents, directories first and then by name; with
+    /// `respect_ignore`, leaving out entries the backend hides from browsing
+    fn list_directory(&self, path: &str, respect_ignore: bool) -> Result<Vec<FileEntry>, FsError>;
+
+    /// Create a directory whose parent exists
+    fn create_directory(&self, path: &str) -> Result<(), FsError>;
+
+    /// Create a directory and any missing parents, returning the paths
+    /// created, outermost first
+    fn create_directory_all(&self, path: &str) -> Result<Vec<String>, FsError>;
+
+    /// Give an entry a new name in the same directory, returning its new path
+    fn rename(&self, path: &str, new_name: &str) -> Result<String, FsError>;
+
+    /// Move an entry into the directory `to`, or to the path `to` if that
+    /// is not an existing directory
+    fn move_entry(&self, from: &str, to: &str, overwrite: bool)
+    -> Result<OperationResult, FsError>;
+
+    /// Copy an entry, with `to` read as for `move_entry`
+    fn copy_entry(&self, from: &str, to: &str, overwrite: bool)
+    -> Result<OperationResult, FsError>;
+
+    /// Delete a file or a directory with everything below it
+    fn delete(&self, path: &str) -> Result<(), FsError>;
+
+    /// Size of a file, or the total of the files below a directory
+    fn entry_size(&self, path: &str) -> Result<u64, FsError>;
+
+    /// Whole contents of a file
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, FsError>;
+
+    /// Create or replace a file in an existing directory
+    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), FsError>;
+
+    /// Copy `from` to `to_dir` like `rsync -r`, with `to_dir` read as for
+    /// `move_entry`, leaving out files already current at the destination.
+    /// Nothing at the destination is deleted. Returns the destination path.
+    fn sync_entry(
+        &self,
+        from: &str,
+        to_dir: &str,
+        compare: SyncCompare,
+    ) -> Result<(String, SyncStats), FsError>;
+
+    /// The root and everything below it, each directory before its
+    /// contents, leaving out hidden entries unless `show_hidden` and
+    /// anything the backend keeps out of the index
+    fn walk(&self, show_hidden: bool) -> Walk<'_>;
+
+    /// Whether anything exists at `path`
+    fn exists(&self, path: &str) -> Result<bool, FsError> {
+        match self.get_entry(path) {
+            Ok(_) => Ok(true),
+            Err(FsError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `path` names an existing directory
+    fn is_dir(&self, path: &str) -> bool {
+        self.get_entry(path).is_ok_and(|entry| entry.is_dir)
+    }
+
+    /// Fail with `FsError::Modified` unless the entry at `path` still has
+    /// the expected modification time and size
+    fn check_expected(&self, path: &str, expected: &ExpectedState) -> Result<(), FsError> {
+        if expected.mtime.is_none() && expected.size.is_none() {
+            return Ok(());
+        }
+        let entry = self.get_entry(path)?;
+        let mtime_changed = expected
+            .mtime
+            .is_some_and(|mtime| entry.modified.map(|m| m.timestamp()) != Some(mtime.timestamp()));
+        let size_changed = expected.size.is_some_and(|size| entry.size != Some(size));
+        if mtime_changed || size_changed {
+            return Err(FsError::Modified(path.to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl StorageBackend for FilesystemService {
+    fn get_entry(&self, path: &str) -> Result<FileEntry, FsError> {
+        FilesystemService::get_entry(self, path)
+    }
+
+    fn list_directory(&self, path: &str, respect_ignore: bool) -> Result<Vec<FileEntry>, FsError> {
+        FilesystemService::list_directory(self, path, respect_ignore)
+    }
+
+    fn create_directory(&self, path: &str) -> Result<(), FsError> {
+        FilesystemService::create_directory(self, path)
+    }
+
+    fn create_directory_all(&self, path: &str) -> Result<Vec<String>, FsError> {
+        FilesystemService::create_directory_all(self, path)
+    }
+
+    fn rename(&self, path: &str, new_name: &str) -> Result<String, FsError> {
+        FilesystemService::rename(self, path, new_name)
+    }
+
+    fn move_entry(
+        &self,
+        from: &str,
+        to: &str,
+        overwrite: bool,
+    ) -> Result<OperationResult, FsError> {
+        FilesystemService::move_entry(self, from, to, overwrite)
+    }
+
+    fn copy_entry(
+        &self,
+        from: &str,
+        to: &str,
+        overwrite: bool,
+    ) -> Result<OperationResult, FsError> {
+        FilesystemService::copy_entry(self, from, to, overwrite)
+    }
+
+    fn delete(&self, path: &str) -> Result<(), FsError> {
+        FilesystemService::delete(self, path)
+    }
+
+    fn entry_size(&self, path: &str) -> Result<u64, FsError> {
+        FilesystemService::entry_size(self, path)
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, FsError> {
+        let resolved = self.resolve_path(path)?;
+        if resolved.is_dir() {
+            return Err(FsError::NotAFile(path.to_string()));
+        }
+        Ok(fs::read(resolved)?)
+    }
+
+    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), FsError> {
+        let (parent, name) = split_path(&normalize(path)?)?;
+        let target = self.resolve_path(&parent)?.join(&name);
+        if target.is_dir() {
+            return Err(FsError::NotAFile(path.to_string()));
+        }
+        Ok(fs::write(target, contents)?)
+    }
+
+    fn sync_entry(
+        &self,
+        from: &str,
+        to_dir: &str,
+        compare: SyncCompare,
+    ) -> Result<(String, SyncStats), FsError> {
+        FilesystemService::sync_entry(self, from, to_dir, compare)
+    }
+
+    /// Honours `.fxignore` files, and lists links without following them
+    fn walk(&self, show_hidden: bool) -> Walk<'_> {
+        let root = self.root().to_path_buf();
+        let walk = WalkBuilder::new(&root)
+            .follow_links(false)
+            .hidden(!show_hidden)
+            .add_custom_ignore_filename(IGNORE_FILE)
+            .build()
+            .map(move |entry| {
+                let entry = entry.map_err(|e| FsError::Io(std::io::Error::other(e)))?;
+                let metadata = entry.metadata().map_err(|e| {
+                    FsError::Io(std::io::Error::other(format!("{:?}: {e}", entry.path())))
+                })?;
+                let path = entry
+                    .path()
+                    .strip_prefix(&root)
+                    .map(|p| format!("/{}", canonical_name(&p.display().to_string())))
+                    .unwrap_or_else(|_| "/".to_string());
+                Ok(WalkEntry {
+                    path,
+                    name: entry
+                        .path()
+                        .file_name()
+                        .map(|n| canonical_name(&n.to_string_lossy()).into_owned())
+                        .unwrap_or_default(),
+                    is_dir: metadata.is_dir(),
+                    is_file: metadata.is_file(),
+                    size: metadata.is_file().then_some(metadata.len()),
+                    created: metadata.created().ok().map(DateTime::<Utc>::from),
+                    modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+                    local_path: Some(entry.into_path()),
+                })
+            });
+        Box::new(walk)
+    }
+
+    /// Resolving the path only, as reading the entry sniffs every file
+    fn exists(&self, path: &str) -> Result<bool, FsError> {
+        match self.resolve_path(path) {
+            Ok(_) => Ok(true),
+            Err(FsError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Without reading the entry, which sniffs the type of a file
+    fn is_dir(&self, path: &str) -> bool {
+        self.resolve_path(path).is_ok_and(|p| p.is_dir())
+    }
+}
+
+enum Node {
+    Dir {
+        modified: DateTime<Utc>,
+    },
+    File {
+        data: Vec<u8>,
+        modified: DateTime<Utc>,
+    },
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        matches!(self, Node::Dir { .. })
+    }
+}
+
+/// A tree held in memory, keyed by normalized path. Nothing is hidden from
+/// listings, since there are no `.fxignore` files to honour.
+pub struct MemoryBackend {
+    nodes: RwLock<BTreeMap<String, Node>>,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBackend {
+    /// An empty tree holding only the root directory
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            "/".to_string(),
+            Node::Dir {
+                modified: Utc::now(),
+            },
+        );
+        Self {
+            nodes: RwLock::new(nodes),
+        }
+    }
+
+    fn entry(path: &str, node: &Node) -> FileEntry {
+        let name = path.rsplit('/').next().unwrap_or_default().to_string();
+        let (is_dir, size, modified, mime_type) = match node {
+            Node::Dir { modified } => (true, None, *modified, None),
+            Node::File { data, modified } => (
+                false,
+                Some(data.len() as u64),
+                *modified,
+                mime_guess::from_path(&name)
+                    .first()
+                    .map(|mime| mime.to_string())
+                    .or_else(|| infer::get(data).map(|kind| kind.mime_type().to_string())),
+            ),
+        };
+        FileEntry {
+            id: None,
+            name,
+            path: path.to_string(),
+            is_dir,
+            size,
+            created: Some(modified),
+            modified: Some(modified),
+            mime_type,
+            width: None,
+            height: None,
+            duration: None,
+            indexed_at: None,
+            artist: None,
+            album: None,
+            title: None,
+            track_number: None,
+            page_count: None,
+            author: None,
+            original_name: None,
+            has_notes: false,
+            link_kind: None,
+            link_target: None,
+        }
+    }
+
+    /// Paths of `path` and everything below it
+    fn subtree(nodes: &BTreeMap<String, Node>, path: &str) -> Vec<String> {
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        nodes
+            .keys()
+            .filter(|key| key.as_str() == path || key.starts_with(&prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Where `move_entry` and `copy_entry` put `from`
+    fn destination(
+        nodes: &BTreeMap<String, Node>,
+        from: &str,
+        to: &str,
+    ) -> Result<String, FsError> {
+        let to = normalize(to)?;
+        if nodes.get(&to).is_some_and(Node::is_dir) {
+            let (_, name) = split_path(from)?;
+            return Ok(join(&to, &name));
+        }
+        let (parent, _) = split_path(&to)?;
+        match nodes.get(&parent) {
+            Some(node) if node.is_dir() => Ok(to),
+            Some(_) => Err(FsError::NotADirectory(parent)),
+            None => Err(FsError::NotFound(parent)),
+        }
+    }
+
+    /// Move (or copy) `from` with everything below it to `dest`
+    fn transfer(nodes: &mut BTreeMap<String, Node>, from: &str, dest: &str, keep_source: bool) {
+        for path in Self::subtree(nodes, from) {
+            let moved = format!("{}{}", dest, &path[from.len()..]);
+            let node = if keep_source {
+                match &nodes[&path] {
+                    Node::Dir { .. } => Node::Dir {
+                        modified: Utc::now(),
+                    },
+                    Node::File { data, .. } => Node::File {
+                        data: data.clone(),
+                        modified: Utc::now(),
+                    },
+                }
+            } else {
+                nodes.remove(&path).expect("path listed from the tree")
+            };
+            nodes.insert(moved, node);
+        }
+    }
+
+    fn move_or_copy(
+        &self,
+        from: &str,
+        to: &str,
+        overwrite: bool,
+        keep_source: bool,
+    ) -> Result<OperationResult, FsError> {
+        let from = normalize(from)?;
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        let source_is_dir = nodes
+            .get(&from)
+            .map(Node::is_dir)
+            .ok_or_else(|| FsError::NotFound(from.clone()))?;
+        if from == "/" {
+            return Err(FsError::PermissionDenied("Cannot move root".to_string()));
+        }
+        let dest = Self::destination(&nodes, &from, to)?;
+        if source_is_dir && (dest == from || dest.starts_with(&format!("{from}/"))) {
+            let verb = if keep_source { "copy" } else { "move" };
+            return Err(FsError::PermissionDenied(format!(
+                "Cannot {verb} a directory into itself"
+            )));
+        }
+
+        if nodes.contains_key(&dest) {
+            if !overwrite || dest == from {
+                return Ok(OperationResult {
+                    path: dest,
+                    performed: false,
+                });
+            }
+            for path in Self::subtree(&nodes, &dest) {
+                nodes.remove(&path);
+            }
+        }
+        Self::transfer(&mut nodes, &from, &dest, keep_source);
+        Ok(OperationResult {
+            path: dest,
+            performed: true,
+        })
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get_entry(&self, path: &str) -> Result<FileEntry, FsError> {
+        let path = normalize(path)?;
+        let nodes = self.nodes.read().unwrap_or_else(|e| e.into_inner());
+        let node = nodes
+            .get(&path)
+            .ok_or_else(|| FsError::NotFound(path.clone()))?;
+        Ok(Self::entry(&path, node))
+    }
+
+    fn list_directory(&self, path: &str, _respect_ignore: bool) -> Result<Vec<FileEntry>, FsError> {
+        let path = normalize(path)?;
+        let nodes = self.nodes.read().unwrap_or_else(|e| e.into_inner());
+        match nodes.get(&path) {
+            Some(node) if node.is_dir() => {}
+            Some(_) => return Err(FsError::NotADirectory(path)),
+            None => return Err(FsError::NotFound(path)),
+        }
+
+        let mut entries: Vec<FileEntry> = nodes
+            .iter()
+            .filter(|(key, _)| {
+                key.as_str() != "/" && split_path(key).is_ok_and(|(parent, _)| parent == path)
+            })
+            .map(|(key, node)| Self::entry(key, node))
+            .collect();
+        entries.sort_by(|a, b| match (a.is_dir, b.is_dir) {
+            (true, false) => std::cmp::Ordering::Less,
+            (false, true) => std::cmp::Ordering::Greater,
+            _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        });
+        Ok(entries)
+    }
+
+    fn create_directory(&self, path: &str) -> Result<(), FsError> {
+        let path = normalize(path)?;
+        let (parent, _) = split_path(&path)?;
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        match nodes.get(&parent) {
+            Some(node) if node.is_dir() => {}
+            Some(_) => return Err(FsError::NotADirectory(parent)),
+            None => return Err(FsError::NotFound(parent)),
+        }
+        if nodes.contains_key(&path) {
+            return Err(FsError::Io(std::io::ErrorKind::AlreadyExists.into()));
+        }
+        nodes.insert(
+            path,
+            Node::Dir {
+                modified: Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    fn create_directory_all(&self, path: &str) -> Result<Vec<String>, FsError> {
+        let path = normalize(path)?;
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        let mut created = Vec::new();
+        let mut current = String::from("/");
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            current = join(&current, component);
+            match nodes.get(&current) {
+                Some(node) if node.is_dir() => {}
+                Some(_) => return Err(FsError::NotADirectory(current)),
+                None => {
+                    nodes.insert(
+                        current.clone(),
+                        Node::Dir {
+                            modified: Utc::now(),
+                        },
+                    );
+                    created.push(current.clone());
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    fn rename(&self, path: &str, new_name: &str) -> Result<String, FsError> {
+        let path = normalize(path)?;
+        if path == "/" {
+            return Err(FsError::PermissionDenied("Cannot rename root".to_string()));
+        }
+        if new_name.is_empty() || new_name.contains('/') || new_name == "." || new_name == ".." {
+            return Err(FsError::InvalidName(new_name.to_string()));
+        }
+        let (parent, _) = split_path(&path)?;
+        let new_path = join(&parent, new_name);
+
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        if !nodes.contains_key(&path) {
+            return Err(FsError::NotFound(path));
+        }
+        if new_path != path {
+            for existing in Self::subtree(&nodes, &new_path) {
+                nodes.remove(&existing);
+            }
+            Self::transfer(&mut nodes, &path, &new_path, false);
+        }
+        Ok(new_path)
+    }
+
+    fn move_entry(
+        &self,
+        from: &str,
+        to: &str,
+        overwrite: bool,
+    ) -> Result<OperationResult, FsError> {
+        self.move_or_copy(from, to, overwrite, false)
+    }
+
+    fn copy_entry(
+        &self,
+        from: &str,
+        to: &str,
+        overwrite: bool,
+    ) -> Result<OperationResult, FsError> {
+        self.move_or_copy(from, to, overwrite, true)
+    }
+
+    fn delete(&self, path: &str) -> Result<(), FsError> {
+        let path = normalize(path)?;
+        if path == "/" {
+            return Err(FsError::PermissionDenied("Cannot delete root".to_string()));
+        }
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        if !nodes.contains_key(&path) {
+            return Err(FsError::NotFound(path));
+        }
+        for existing in Self::subtree(&nodes, &path) {
+            nodes.remove(&existing);
+        }
+        Ok(())
+    }
+
+    fn entry_size(&self, path: &str) -> Result<u64, FsError> {
+        let path = normalize(path)?;
+        let nodes = self.nodes.read().unwrap_or_else(|e| e.into_inner());
+        if !nodes.contains_key(&path) {
+            return Err(FsError::NotFound(path));
+        }
+        Ok(Self::subtree(&nodes, &path)
+            .iter()
+            .map(|key| match &nodes[key] {
+                Node::File { data, .. } => data.len() as u64,
+                Node::Dir { .. } => 0,
+            })
+            .sum())
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, FsError> {
+        let path = normalize(path)?;
+        let nodes = self.nodes.read().unwrap_or_else(|e| e.into_inner());
+        match nodes.get(&path) {
+            Some(Node::File { data, .. }) => Ok(data.clone()),
+            Some(Node::Dir { .. }) => Err(FsError::NotAFile(path)),
+            None => Err(FsError::NotFound(path)),
+        }
+    }
+
+    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), FsError> {
+        let path = normalize(path)?;
+        let (parent, _) = split_path(&path)?;
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        match nodes.get(&parent) {
+            Some(node) if node.is_dir() => {}
+            Some(_) => return Err(FsError::NotADirectory(parent)),
+            None => return Err(FsError::NotFound(parent)),
+        }
+        if nodes.get(&path).is_some_and(Node::is_dir) {
+            return Err(FsError::NotAFile(path));
+        }
+        nodes.insert(
+            path,
+            Node::File {
+                data: contents.to_vec(),
+                modified: Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Copied files keep the source's modification time, as on disk
+    fn sync_entry(
+        &self,
+        from: &str,
+        to_dir: &str,
+        compare: SyncCompare,
+    ) -> Result<(String, SyncStats), FsError> {
+        let from = normalize(from)?;
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        let source_is_dir = nodes
+            .get(&from)
+            .map(Node::is_dir)
+            .ok_or_else(|| FsError::NotFound(from.clone()))?;
+        let dest = Self::destination(&nodes, &from, to_dir)?;
+        if source_is_dir && (dest == from || dest.starts_with(&format!("{from}/"))) {
+            return Err(FsError::PermissionDenied(
+                "Cannot sync a directory into itself".to_string(),
+            ));
+        }
+
+        // Keys sort each directory before its contents
+        let mut stats = SyncStats::default();
+        for path in Self::subtree(&nodes, &from) {
+            let target = format!("{}{}", dest, &path[from.len()..]);
+            match (&nodes[&path], nodes.get(&target)) {
+                (Node::Dir { .. }, Some(Node::Dir { .. })) => {}
+                (Node::Dir { .. }, Some(Node::File { .. })) => {
+                    return Err(FsError::NotADirectory(target));
+                }
+                (Node::Dir { .. }, None) => {
+                    nodes.insert(
+                        target,
+                        Node::Dir {
+                            modified: Utc::now(),
+                        },
+                    );
+                }
+                (Node::File { .. }, Some(Node::Dir { .. })) => {
+                    return Err(FsError::NotAFile(target));
+                }
+                (Node::File { data, modified }, existing) => {
+                    let current = match (existing, compare) {
+                        (
+                            Some(Node::File {
+                                data: dest_data,
+                                modified: dest_modified,
+                            }),
+                            SyncCompare::SizeAndMtime,
+                        ) => {
+                            data.len() == dest_data.len()
+                                && modified.timestamp() == dest_modified.timestamp()
+                        }
+                        (
+                            Some(Node::File {
+                                data: dest_data, ..
+                            }),
+                            SyncCompare::Contents,
+                        ) => data == dest_data,
+                        _ => false,
+                    };
+                    if current {
+                        stats.skipped += 1;
+                        continue;
+                    }
+                    if existing.is_some() {
+                        stats.updated += 1;
+                    } else {
+                        stats.created += 1;
+                    }
+                    let copy = Node::File {
+                        data: data.clone(),
+                        modified: *modified,
+                    };
+                    nodes.insert(target, copy);
+                }
+            }
+        }
+        Ok((dest, stats))
+    }
+
+    /// Hidden names are those starting with a dot, as on disk
+    fn walk(&self, show_hidden: bool) -> Walk<'_> {
+        let nodes = self.nodes.read().unwrap_or_else(|e| e.into_inner());
+        let entries: Vec<_> = nodes
+            .iter()
+            .filter(|(path, _)| show_hidden || !is_hidden_path(path))
+            .map(|(path, node)| {
+                let entry = Self::entry(path, node);
+                Ok(WalkEntry {
+                    path: entry.path,
+                    name: entry.name,
+                    is_dir: entry.is_dir,
+                    is_file: !entry.is_dir,
+                    size: entry.size,
+                    created: entry.created,
+                    modified: entry.modified,
+                    local_path: None,
+                })
+            })
+            .collect();
+        Box::new(entries.into_iter())
+    }
+}
+
+/// `/`-separated form of a root-relative path with `.` and `..` resolved,
+/// refusing paths that climb above the root
+fn normalize(path: &str) -> Result<String, FsError> {
+    let mut parts: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                parts.pop().ok_or(FsError::PathEscape)?;
+            }
+            name => parts.push(name),
+        }
+    }
+    Ok(format!("/{}", parts.join("/")))
+}
+
+/// Parent directory and name of a normalized path other than the root
+fn split_path(path: &str) -> Result<(String, String), FsError> {
+    match path.rsplit_once('/') {
+        Some((_, "")) | None => Err(FsError::InvalidName(path.to_string())),
+        Some(("", name)) => Ok(("/".to_string(), name.to_string())),
+        Some((parent, name)) => Ok((parent.to_string(), name.to_string())),
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// The behaviour every backend shares
+    fn exercise(storage: &dyn StorageBackend) {
+        storage.create_directory("/docs").unwrap();
+        storage.write_file("/docs/a.txt", b"hello").unwrap();
+        assert_eq!(storage.read_file("/docs/a.txt").unwrap(), b"hello");
+        assert_eq!(
+            storage.create_directory_all("/docs/2024/june").unwrap(),
+            ["/docs/2024", "/docs/2024/june"]
+        );
+
+        let names = |path: &str| -> Vec<String> {
+            storage
+                .list_directory(path, true)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect()
+        };
+        assert_eq!(names("/docs"), ["2024", "a.txt"]);
+
+        let entry = storage.get_entry("/docs/a.txt").unwrap();
+        assert_eq!((entry.size, entry.is_dir), (Some(5), false));
+        assert_eq!(entry.mime_type.as_deref(), Some("text/plain"));
+
+        assert_eq!(
+            storage.rename("/docs/a.txt", "b.txt").unwrap(),
+            "/docs/b.txt"
+        );
+        let copied = storage
+            .copy_entry("/docs/b.txt", "/docs/2024", false)
+            .unwrap();
+        assert_eq!(
+            (copied.path.as_str(), copied.performed),
+            ("/docs/2024/b.txt", true)
+        );
+        let skipped = storage
+            .copy_entry("/docs/b.txt", "/docs/2024", false)
+            .unwrap();
+        assert!(!skipped.performed);
+        assert_eq!(storage.entry_size("/docs").unwrap(), 10);
+
+        storage.create_directory("/mirror").unwrap();
+        let (synced, stats) = storage
+            .sync_entry("/docs", "/mirror", SyncCompare::Contents)
+            .unwrap();
+        assert_eq!(synced, "/mirror/docs");
+        assert_eq!((stats.created, stats.skipped), (2, 0));
+        storage.write_file("/mirror/docs/b.txt", b"stale").unwrap();
+        let (_, stats) = storage
+            .sync_entry("/docs", "/mirror", SyncCompare::Contents)
+            .unwrap();
+        assert_eq!((stats.updated, stats.skipped), (1, 1));
+        assert_eq!(storage.read_file("/mirror/docs/b.txt").unwrap(), b"hello");
+        assert!(matches!(
+            storage.sync_entry("/docs", "/docs/2024", SyncCompare::SizeAndMtime),
+            Err(FsError::PermissionDenied(_))
+        ));
+        storage.delete("/mirror").unwrap();
+
+        storage.write_file("/docs/.draft", b"").unwrap();
+        let walked = |show_hidden: bool| -> Vec<(String, bool)> {
+            let mut entries: Vec<_> = storage
+                .walk(show_hidden)
+                .map(|entry| entry.unwrap())
+                .map(|entry| (entry.path, entry.is_file))
+                .collect();
+            entries.sort();
+            entries
+        };
+        let visible = [
+            ("/".to_string(), false),
+            ("/docs".to_string(), false),
+            ("/docs/2024".to_string(), false),
+            ("/docs/2024/b.txt".to_string(), true),
+            ("/docs/2024/june".to_string(), false),
+            ("/docs/b.txt".to_string(), true),
+        ];
+        assert_eq!(walked(false), visible);
+        assert!(walked(true).contains(&("/docs/.draft".to_string(), true)));
+        assert!(storage.exists("/docs/.draft").unwrap());
+        storage.delete("/docs/.draft").unwrap();
+        assert!(!storage.exists("/docs/.draft").unwrap());
+
+        let moved = storage.move_entry("/docs/2024", "/archive", false).unwrap();
+        assert_eq!(moved.path, "/archive");
+        assert_eq!(names("/archive"), ["june", "b.txt"]);
+        assert!(matches!(
+            storage.move_entry("/archive", "/archive/june", false),
+            Err(FsError::PermissionDenied(_))
+        ));
+
+        storage.delete("/archive").unwrap();
+        assert!(matches!(
+            storage.get_entry("/archive/b.txt"),
+            Err(FsError::NotFound(_))
+        ));
+        assert!(storage.delete("/").is_err());
+        assert!(storage.read_file("/../outside").is_err());
+    }
+
+    #[test]
+    fn filesystem_backend_behaves_as_a_storage_backend() {
+        let root = tempdir().unwrap();
+        exercise(&FilesystemService::new(root.path().to_path_buf()));
+    }
+
+    #[test]
+    fn memory_backend_behaves_as_a_storage_backend() {
+        exercise(&MemoryBackend::new());
+    }
+}
@@ -0,0 +1,307 @@
+//! Content-addressable storage for uploads. Each distinct file content is
+//! kept once in the store, named by its SHA-256, and every path it was
+//! uploaded to is a hard link to that copy. The link count doubles as the
+//! reference count: an object whose only link is the store's own is no
+//! longer used anywhere and is pruned.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::services::ManifestService;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PruneStats {
+    pub removed: u64,
+    pub bytes: u64,
+}
+
+pub struct DedupStore {
+    /// `None` stores uploads as plain files
+    dir: Option<PathBuf>,
+    /// Held while linking and pruning, so an object is not pruned between
+    /// being found and being linked to
+    lock: Mutex<()>,
+    /// Whether a prune is waiting to run
+    prune_queued: AtomicBool,
+}
+
+impl DedupStore {
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            lock: Mutex::new(()),
+            prune_queued: AtomicBool::new(false),
+        }
+    }
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir: Some(dir),
+            ..Self::disabled()
+        }
+    }
+
+    /// Store uploads in `FM_DEDUP_STORE`, if set. Hard link counts are only
+    /// read on Unix, and a store inside the root would show up in listings,
+    /// so either turns it off with a warning.
+    pub fn from_config(config: &Config) -> Self {
+        let Some(dir) = &config.dedup_store else {
+            return Self::disabled();
+        };
+        if cfg!(not(unix)) {
+            warn!("FM_DEDUP_STORE is only supported on Unix; storing uploads as plain files");
+            return Self::disabled();
+        }
+        let root = config
+            .root_path
+            .canonicalize()
+            .unwrap_or(config.root_path.clone());
+        let store = dir.canonicalize().unwrap_or(dir.clone());
+        if store.starts_with(&root) {
+            warn!(
+                "FM_DEDUP_STORE {:?} is inside the root; storing uploads as plain files",
+                dir
+            );
+            return Self::disabled();
+        }
+        Self::new(dir.clone())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    fn object_path(dir: &Path, hash: &str) -> PathBuf {
+        dir.join(&hash[..2]).join(hash)
+    }
+
+    /// Put the file at `staged` in place at `dest`, replacing any file
+    /// there, as a link to the stored copy of its contents. `staged` is
+    /// consumed. Returns whether the contents were already stored.
+    ///
+    /// The store must be on the same volume as `dest`; where it is not,
+    /// or the link fails otherwise, `staged` is moved to `dest` as a plain
+    /// file instead.
+    pub fn store(&self, staged: &Path, dest: &Path) -> io::Result<bool> {
+        let Some(dir) = &self.dir else {
+            fs::rename(staged, dest)?;
+            return Ok(false);
+        };
+        let hash = ManifestService::hash_file(staged)?;
+        let object = Self::object_path(dir, &hash);
+        fs::create_dir_all(object.parent().unwrap_or(dir))?;
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let existed = object.is_file();
+        if !existed && let Err(e) = fs::rename(staged, &object) {
+            warn!("Failed to move {:?} into the dedup store: {}", staged, e);
+            fs::rename(staged, dest)?;
+            return Ok(false);
+        }
+
+        // Linked beside `dest` first, so a file it replaces is swapped out
+        // in one rename
+        let link = dest.with_file_name(format!(".filex-link-{}", uuid::Uuid::new_v4().simple()));
+        match fs::hard_link(&object, &link) {
+            Ok(()) => {
+                let renamed = fs::rename(&link, dest);
+                // Renaming onto another link to the same file does nothing,
+                // as when the same contents are uploaded over themselves
+                let _ = fs::remove_file(&link);
+                renamed?;
+                if existed {
+                    fs::remove_file(staged)?;
+                }
+                Ok(existed)
+            }
+            Err(e) => {
+                warn!("Failed to link {:?} to the dedup store: {}", dest, e);
+                if existed {
+                    fs::rename(staged, dest)?;
+                } else {
+                    // Take the only copy back out of the store
+                    fs::rename(&object, dest)?;
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    /// Give the file at `path` an inode of its own if it shares one with
+    /// other uploads, before a change to its mode or owner that would
+    /// otherwise reach them and the stored copy too. Returns whether it was
+    /// copied; the stored object may be left unused, so prune afterwards.
+    pub fn unshare(&self, path: &Path) -> io::Result<bool> {
+        if !self.is_enabled() {
+            return Ok(false);
+        }
+        let meta = fs::symlink_metadata(path)?;
+        if !meta.is_file() || link_count(&meta) <= 1 {
+            return Ok(false);
+        }
+        let copy = path.with_file_name(format!(".filex-link-{}", uuid::Uuid::new_v4().simple()));
+        let copied = fs::copy(path, &copy).and_then(|_| {
+            fs::File::options()
+                .write(true)
+                .open(&copy)?
+                .set_modified(meta.modified()?)?;
+            fs::rename(&copy, path)
+        });
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&copy);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Remove stored objects that no path links to anymore
+    pub fn prune(&self) -> io::Result<PruneStats> {
+        let mut stats = PruneStats::default();
+        let Some(dir) = &self.dir else {
+            return Ok(stats);
+        };
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let prefixes = match fs::read_dir(dir) {
+            Ok(prefixes) => prefixes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(stats),
+            Err(e) => return Err(e),
+        };
+        for prefix in prefixes {
+            let prefix = prefix?;
+            if !prefix.file_type()?.is_dir() {
+                continue;
+            }
+            for object in fs::read_dir(prefix.path())? {
+                let object = object?;
+                let meta = object.metadata()?;
+                if meta.is_file() && link_count(&meta) == 1 {
+                    fs::remove_file(object.path())?;
+                    stats.removed += 1;
+                    stats.bytes += meta.len();
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Prune in the background, after deletes that may have dropped the
+    /// last link to objects. Calls while a prune is already waiting share
+    /// it.
+    pub fn prune_later(self: &Arc<Self>) {
+        if !self.is_enabled() || self.prune_queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || {
+            // Cleared before scanning, so a delete during the scan queues
+            // another one
+            let guard = store.lock.lock().unwrap_or_else(|e| e.into_inner());
+            store.prune_queued.store(false, Ordering::Release);
+            drop(guard);
+            match store.prune() {
+                Ok(stats) if stats.removed > 0 => info!(
+                    "Pruned {} unused objects ({} bytes) from the dedup store",
+                    stats.removed, stats.bytes
+                ),
+                Ok(_) => {}
+                Err(e) => warn!("Failed to prune the dedup store: {}", e),
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+fn link_count(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink()
+}
+
+/// Never prune where link counts cannot be read
+#[cfg(not(unix))]
+fn link_count(_meta: &fs::Metadata) -> u64 {
+    u64::MAX
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn duplicate_uploads_share_one_copy_until_unused() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let store = DedupStore::new(tmp.path().join("store"));
+
+        let stage = |contents: &str| {
+            let staged = root.join(format!(".staged-{}", uuid::Uuid::new_v4()));
+            fs::write(&staged, contents).unwrap();
+            staged
+        };
+        assert!(!store.store(&stage("same"), &root.join("a.jpg")).unwrap());
+        assert!(store.store(&stage("same"), &root.join("b.jpg")).unwrap());
+        assert!(!store.store(&stage("other"), &root.join("c.jpg")).unwrap());
+
+        let a = fs::metadata(root.join("a.jpg")).unwrap();
+        let b = fs::metadata(root.join("b.jpg")).unwrap();
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(a.nlink(), 3);
+        assert_eq!(fs::read_to_string(root.join("b.jpg")).unwrap(), "same");
+        let mut names: Vec<_> = fs::read_dir(&root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.jpg", "b.jpg", "c.jpg"]);
+
+        // Overwriting swaps the link rather than writing through it
+        assert!(store.store(&stage("other"), &root.join("a.jpg")).unwrap());
+        assert_eq!(fs::read_to_string(root.join("a.jpg")).unwrap(), "other");
+        assert_eq!(fs::read_to_string(root.join("b.jpg")).unwrap(), "same");
+
+        // Objects stay while anything links to them
+        assert_eq!(store.prune().unwrap(), PruneStats::default());
+        fs::remove_file(root.join("b.jpg")).unwrap();
+        assert_eq!(
+            store.prune().unwrap(),
+            PruneStats {
+                removed: 1,
+                bytes: 4
+            }
+        );
+        assert_eq!(fs::read_to_string(root.join("c.jpg")).unwrap(), "other");
+    }
+
+    #[test]
+    fn unsharing_gives_a_path_its_own_copy() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let store = DedupStore::new(tmp.path().join("store"));
+        for name in ["a.jpg", "b.jpg"] {
+            let staged = root.join(".staged");
+            fs::write(&staged, "same").unwrap();
+            store.store(&staged, &root.join(name)).unwrap();
+        }
+        let before = fs::metadata(root.join("a.jpg")).unwrap();
+
+        assert!(store.unshare(&root.join("a.jpg")).unwrap());
+        let a = fs::metadata(root.join("a.jpg")).unwrap();
+        let b = fs::metadata(root.join("b.jpg")).unwrap();
+        assert_ne!(a.ino(), b.ino());
+        assert_eq!((a.nlink(), b.nlink()), (1, 2));
+        assert_eq!(a.mtime(), before.mtime());
+        assert_eq!(fs::read_to_string(root.join("a.jpg")).unwrap(), "same");
+
+        // Nothing to do for a file that is already alone
+        assert!(!store.unshare(&root.join("a.jpg")).unwrap());
+        assert!(!DedupStore::disabled().unshare(&root.join("b.jpg")).unwrap());
+    }
+}
@@ -0,0 +1,311 @@
+//! Just enough EXIF to tell when and where a photo was taken: JPEG files
+//! and the TIFF-based formats most raw files use (DNG, CR2, NEF, ARW, ...).
+
+use chrono::NaiveDateTime;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// EXIF sits near the start of the file; raw files rarely put it further
+const READ_LIMIT: u64 = 256 * 1024;
+
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Exif {
+    /// When the photo was taken, in the camera's local time
+    pub taken_at: Option<NaiveDateTime>,
+    /// Where it was taken, as `(latitude, longitude)` in degrees
+    pub location: Option<(f64, f64)>,
+}
+
+/// What the EXIF data of the photo at `path` says; empty if it has none
+pub async fn read(path: &Path) -> Exif {
+    let mut data = Vec::new();
+    if let Ok(file) = tokio::fs::File::open(path).await
+        && file.take(READ_LIMIT).read_to_end(&mut data).await.is_ok()
+    {
+        parse(&data)
+    } else {
+        Exif::default()
+    }
+}
+
+fn parse(data: &[u8]) -> Exif {
+    let tiff = if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some(data)
+    } else {
+        jpeg_exif(data)
+    };
+    let Some(tiff) = tiff.and_then(Tiff::new) else {
+        return Exif::default();
+    };
+    let Some(ifd0) = tiff.u32(4).map(|offset| offset as usize) else {
+        return Exif::default();
+    };
+
+    let sub_ifd = |tag| {
+        tiff.entry(ifd0, tag)
+            .and_then(|entry| tiff.u32(entry + 8))
+            .map(|offset| offset as usize)
+    };
+    let taken_at = sub_ifd(TAG_EXIF_IFD)
+        .and_then(|ifd| {
+            [TAG_DATE_TIME_ORIGINAL, TAG_DATE_TIME_DIGITIZED]
+                .iter()
+                .find_map(|tag| tiff.date_time(ifd, *tag))
+        })
+        .or_else(|| tiff.date_time(ifd0, TAG_DATE_TIME));
+    let location = sub_ifd(TAG_GPS_IFD).and_then(|ifd| {
+        let latitude = tiff.coordinate(ifd, TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, b'S')?;
+        let longitude = tiff.coordinate(ifd, TAG_GPS_LONGITUDE, TAG_GPS_LONGITUDE_REF, b'W')?;
+        valid_location(latitude, longitude)
+    });
+
+    Exif { taken_at, location }
+}
+
+/// `(latitude, longitude)` if it is on Earth and not the `0, 0` that
+/// devices without a fix write
+pub fn valid_location(latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+    let on_earth = (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude);
+    (on_earth && (latitude, longitude) != (0.0, 0.0)).then_some((latitude, longitude))
+}
+
+/// TIFF data of a JPEG's `Exif` APP1 segment
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        // Start of scan: image data follows, no more metadata
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xE1
+            && let Some(tiff) = segment.strip_prefix(b"Exif\0\0")
+        {
+            return Some(tiff);
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        Some(Self {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// Offset of the 12-byte entry for `tag` in the IFD at `ifd`
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(ifd)? as usize;
+        (0..count)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|entry| self.u16(*entry) == Some(tag))
+    }
+
+    /// An ASCII `YYYY:MM:DD HH:MM:SS` value; the 20 bytes never fit inline
+    fn date_time(&self, ifd: usize, tag: u16) -> Option<NaiveDateTime> {
+        let entry = self.entry(ifd, tag)?;
+        let offset = self.u32(entry + 8)? as usize;
+        let value = self.data.get(offset..offset + 19)?;
+        NaiveDateTime::parse_from_str(std::str::from_utf8(value).ok()?, "%Y:%m:%d %H:%M:%S").ok()
+    }
+
+    /// Degrees from three RATIONALs (degrees, minutes, seconds), negated
+    /// when the one-letter reference tag is `negative` (`S` or `W`)
+    fn coordinate(&self, ifd: usize, tag: u16, ref_tag: u16, negative: u8) -> Option<f64> {
+        let offset = self.u32(self.entry(ifd, tag)? + 8)? as usize;
+        let mut degrees = 0.0;
+        for (i, unit) in [1.0, 60.0, 3600.0].iter().enumerate() {
+            let numerator = self.u32(offset + i * 8)?;
+            let denominator = self.u32(offset + i * 8 + 4)?;
+            if denominator == 0 {
+                return None;
+            }
+            degrees += f64::from(numerator) / f64::from(denominator) / unit;
+        }
+        // The reference letter fits inside the entry itself
+        let reference = *self.data.get(self.entry(ifd, ref_tag)? + 8)?;
+        Some(if reference == negative {
+            -degrees
+        } else {
+            degrees
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Big-endian TIFF with IFD0 (DateTime, Exif IFD pointer) and an Exif
+    /// IFD holding `original`, if given
+    fn tiff(original: Option<&str>) -> Vec<u8> {
+        let mut data = b"MM\0*".to_vec();
+        data.extend(8u32.to_be_bytes());
+        // IFD0 at 8: two entries, then the next-IFD offset
+        let exif_ifd = 8 + 2 + 2 * 12 + 4;
+        let modified_value = exif_ifd + 2 + 12 + 4;
+        let original_value = modified_value + 20;
+        data.extend(2u16.to_be_bytes());
+        data.extend(TAG_DATE_TIME.to_be_bytes());
+        data.extend(2u16.to_be_bytes());
+        data.extend(20u32.to_be_bytes());
+        data.extend((modified_value as u32).to_be_bytes());
+        data.extend(TAG_EXIF_IFD.to_be_bytes());
+        data.extend(4u16.to_be_bytes());
+        data.extend(1u32.to_be_bytes());
+        data.extend((exif_ifd as u32).to_be_bytes());
+        data.extend(0u32.to_be_bytes());
+        // Exif IFD
+        data.extend(u16::from(original.is_some()).to_be_bytes());
+        if original.is_some() {
+            data.extend(TAG_DATE_TIME_ORIGINAL.to_be_bytes());
+            data.extend(2u16.to_be_bytes());
+            data.extend(20u32.to_be_bytes());
+            data.extend((original_value as u32).to_be_bytes());
+        } else {
+            data.extend([0; 12]);
+        }
+        data.extend(0u32.to_be_bytes());
+        data.extend(b"2024:03:02 10:00:00\0");
+        data.extend(original.unwrap_or("").as_bytes());
+        data.push(0);
+        data
+    }
+
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        // An APP0 segment first, as most cameras write
+        data.extend([0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]);
+        data.extend([0xFF, 0xE1]);
+        data.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend([0xFF, 0xDA]);
+        data
+    }
+
+    /// Little-endian TIFF with only a GPS IFD
+    fn gps_tiff(latitude_ref: u8, longitude_ref: u8) -> Vec<u8> {
+        let entry = |data: &mut Vec<u8>, tag: u16, ty: u16, count: u32, value: [u8; 4]| {
+            data.extend(tag.to_le_bytes());
+            data.extend(ty.to_le_bytes());
+            data.extend(count.to_le_bytes());
+            data.extend(value);
+        };
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        entry(&mut data, TAG_GPS_IFD, 4, 1, 26u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        // GPS IFD at 26, its values at 80 and 104
+        data.extend(4u16.to_le_bytes());
+        entry(
+            &mut data,
+            TAG_GPS_LATITUDE_REF,
+            2,
+            2,
+            [latitude_ref, 0, 0, 0],
+        );
+        entry(&mut data, TAG_GPS_LATITUDE, 5, 3, 80u32.to_le_bytes());
+        entry(
+            &mut data,
+            TAG_GPS_LONGITUDE_REF,
+            2,
+            2,
+            [longitude_ref, 0, 0, 0],
+        );
+        entry(&mut data, TAG_GPS_LONGITUDE, 5, 3, 104u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        for (numerator, denominator) in [(52, 1), (22, 1), (1200, 100), (4, 1), (53, 1), (42, 1)] {
+            data.extend(u32::to_le_bytes(numerator));
+            data.extend(u32::to_le_bytes(denominator));
+        }
+        data
+    }
+
+    #[test]
+    fn reads_date_time_original_from_jpeg_and_tiff() {
+        let taken = NaiveDateTime::parse_from_str("2023-12-24 18:30:05", "%Y-%m-%d %H:%M:%S").ok();
+        let raw = tiff(Some("2023:12:24 18:30:05"));
+        assert_eq!(parse(&raw).taken_at, taken);
+        assert_eq!(parse(&jpeg(&raw)).taken_at, taken);
+        assert_eq!(parse(&raw).location, None);
+    }
+
+    #[test]
+    fn falls_back_to_ifd0_date_time() {
+        assert_eq!(
+            parse(&jpeg(&tiff(None))).taken_at.map(|t| t.to_string()),
+            Some("2024-03-02 10:00:00".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_gps_location() {
+        let (latitude, longitude) = parse(&gps_tiff(b'N', b'W')).location.unwrap();
+        assert!((latitude - 52.37).abs() < 1e-9);
+        assert!((longitude + 4.895).abs() < 1e-9);
+
+        let (latitude, longitude) = parse(&jpeg(&gps_tiff(b'S', b'E'))).location.unwrap();
+        assert!(latitude < 0.0 && longitude > 0.0);
+
+        assert_eq!(valid_location(0.0, 0.0), None);
+        assert_eq!(valid_location(91.0, 0.0), None);
+    }
+
+    #[test]
+    fn ignores_files_without_exif() {
+        assert_eq!(parse(b"\x89PNG\r\n\x1a\n"), Exif::default());
+        assert_eq!(parse(&[0xFF, 0xD8, 0xFF, 0xDA]), Exif::default());
+        assert_eq!(parse(&[0xFF, 0xD8, 0xFF, 0xE1, 0xFF]), Exif::default());
+    }
+}
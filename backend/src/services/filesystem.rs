@@ -1,7 +1,15 @@
 use chrono::{DateTime, Utc};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
 use thiserror::Error;
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+use walkdir::WalkDir;
 
 use crate::models::{FileEntry, TreeNode};
 
@@ -21,10 +29,196 @@ pub enum FsError {
     #[error("Not a directory: {0}")]
     NotADirectory(String),
 
+    #[error("Not a file: {0}")]
+    NotAFile(String),
+
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+
+    #[error("Modified since it was listed: {0}")]
+    Modified(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+/// Whether two files of the same length hold the same bytes
+fn same_contents(a: &Path, b: &Path) -> Result<bool, FsError> {
+    let mut a = fs::File::open(a)?;
+    let mut b = fs::File::open(b)?;
+    let mut buf_a = vec![0u8; 64 * 1024];
+    let mut buf_b = vec![0u8; 64 * 1024];
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Create `dest` to write new contents into. A file with other hard links,
+/// such as an upload kept in the dedup store, is removed first so that only
+/// this name gets the new contents, as a sync updates files in place. So is
+/// a symlink, which would otherwise be written through to its target.
+fn create_unshared(dest: &Path) -> std::io::Result<fs::File> {
+    let meta = fs::symlink_metadata(dest).ok();
+    if meta.as_ref().is_some_and(|m| m.is_symlink()) {
+        fs::remove_file(dest)?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if meta.is_some_and(|m| m.is_file() && m.nlink() > 1) {
+            fs::remove_file(dest)?;
+        }
+    }
+    fs::File::create(dest)
+}
+
+/// `FsError` for an I/O error on `relative_path`, keeping not-found and
+/// permission errors apart
+fn io_error(e: std::io::Error, relative_path: &str) -> FsError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => FsError::NotFound(relative_path.to_string()),
+        std::io::ErrorKind::PermissionDenied => {
+            FsError::PermissionDenied(relative_path.to_string())
+        }
+        _ => FsError::Io(e),
+    }
+}
+
+/// Dotfiles and dot-directories, the same entries the indexer treats as hidden
+pub fn is_hidden_name(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+/// Whether any component of a root-relative path is hidden
+pub fn is_hidden_path(relative_path: &str) -> bool {
+    relative_path.split('/').any(is_hidden_name)
+}
+
+/// Canonical Unicode form (NFC) of a path or name. macOS clients create
+/// NFD names over SMB, so "café" can be spelled two ways on disk; every path
+/// is stored in and compared against the database in this form.
+pub fn canonical_name(name: &str) -> Cow<'_, str> {
+    if is_nfc(name) {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(name.nfc().collect())
+    }
+}
+
+/// Bytes read from the start of a file to recognise its type by content
+const SNIFF_BYTES: usize = 512;
+
+/// MIME type of a file from its extension, or for files without a known
+/// extension from the magic bytes at its start
+pub fn mime_type_of(path: &Path) -> Option<String> {
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return Some(mime.to_string());
+    }
+    let mut header = Vec::with_capacity(SNIFF_BYTES);
+    fs::File::open(path)
+        .and_then(|file| file.take(SNIFF_BYTES as u64).read_to_end(&mut header))
+        .ok()?;
+    infer::get(&header).map(|kind| kind.mime_type().to_string())
+}
+
+/// `relative_path` under `root` as spelled on disk. Components that do not
+/// exist as given are matched against their directory's entries by
+/// canonical form; without a match the path is returned as given.
+pub fn path_on_disk(root: &Path, relative_path: &str) -> PathBuf {
+    let clean = relative_path.trim_start_matches('/');
+    let direct = root.join(clean);
+    if clean.is_ascii() || direct.symlink_metadata().is_ok() {
+        return direct;
+    }
+
+    let mut current = root.to_path_buf();
+    for component in clean.split('/') {
+        let next = current.join(component);
+        if component.is_ascii() || next.symlink_metadata().is_ok() {
+            current = next;
+            continue;
+        }
+        let wanted = canonical_name(component);
+        let found = fs::read_dir(&current).ok().and_then(|entries| {
+            entries.filter_map(|e| e.ok()).find(|e| {
+                e.file_name()
+                    .to_str()
+                    .is_some_and(|name| canonical_name(name) == wanted)
+            })
+        });
+        match found {
+            Some(entry) => current = entry.path(),
+            None => return direct,
+        }
+    }
+    current
+}
+
+/// `target` as reached from `dir`, both absolute, e.g. `../photos/a.jpg`
+fn relative_to(dir: &Path, target: &Path) -> PathBuf {
+    let dir: Vec<_> = dir.components().collect();
+    let target: Vec<_> = target.components().collect();
+    let common = dir.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = dir[common..].iter().map(|_| "..").collect();
+    relative.extend(&target[common..]);
+    relative
+}
+
+/// How `FilesystemService::create_link` links to its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    /// Another name for the same file; only files can be hard linked
+    Hard,
+    /// A symlink, holding the target's path relative to the link
+    #[serde(alias = "symlink")]
+    Symbolic,
+}
+
+impl LinkKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hard => "hard",
+            Self::Symbolic => "symbolic",
+        }
+    }
+}
+
+/// Device names Windows reserves regardless of extension (`nul.txt` too)
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest name most filesystems accept, in bytes
+const MAX_NAME_BYTES: usize = 255;
+
+/// Per-directory ignore file, gitignore syntax, shared with the indexer
+pub const IGNORE_FILE: &str = ".fxignore";
+
+/// Entries directly inside `dir` that survive the indexer's ignore rules
+/// (`.fxignore` here or in any parent, `.gitignore` inside git repositories).
+/// Hidden entries are kept; callers filter those separately.
+fn unignored_children(dir: &Path) -> HashSet<PathBuf> {
+    WalkBuilder::new(dir)
+        .max_depth(Some(1))
+        .follow_links(false)
+        .hidden(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.depth() == 1)
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
 /// Provides file-management operations that are confined to a single root
 /// directory to prevent directory traversal or accidental access elsewhere on
 /// disk.
@@ -39,6 +233,40 @@ pub struct OperationResult {
     pub performed: bool,
 }
 
+/// How a sync decides that a file at the destination is already current
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SyncCompare {
+    /// Same size and modification time, to the second
+    #[default]
+    SizeAndMtime,
+    /// Same contents, read in full; for destinations whose times cannot
+    /// be trusted
+    Contents,
+}
+
+/// What a sync did, in files
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SyncStats {
+    /// Missing at the destination and copied
+    pub created: u64,
+    /// Different at the destination and replaced
+    pub updated: u64,
+    /// Already current at the destination
+    pub skipped: u64,
+    /// Files and directories at the destination with no source, removed
+    pub deleted: u64,
+}
+
+/// What a client last saw of an entry. A request carrying it only goes
+/// ahead if the entry still matches, so an action taken from a stale
+/// listing cannot hit a file that has changed since.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExpectedState {
+    /// Compared to the second, as some filesystems keep no finer times
+    pub mtime: Option<DateTime<Utc>>,
+    pub size: Option<u64>,
+}
+
 impl FilesystemService {
     /// Create a new service rooted at `root`, canonicalizing the path up front
     /// so later resolution checks compare against a normalized base.
@@ -48,25 +276,23 @@ impl FilesystemService {
         Self { root }
     }
 
+    /// Canonical root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
     /// Resolve and validate a path, ensuring it doesn't escape root
     pub fn resolve_path(&self, relative_path: &str) -> Result<PathBuf, FsError> {
         let path = if relative_path.is_empty() || relative_path == "/" {
             self.root.clone()
         } else {
-            let clean_path = relative_path.trim_start_matches('/');
-            self.root.join(clean_path)
+            path_on_disk(&self.root, relative_path)
         };
 
         // Canonicalize and check it's under root
-        let canonical = path.canonicalize().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                FsError::NotFound(relative_path.to_string())
-            } else if e.kind() == std::io::ErrorKind::PermissionDenied {
-                FsError::PermissionDenied(relative_path.to_string())
-            } else {
-                FsError::Io(e)
-            }
-        })?;
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| io_error(e, relative_path))?;
 
         let root_canonical = self.root.canonicalize()?;
 
@@ -77,7 +303,7 @@ impl FilesystemService {
         Ok(canonical)
     }
 
-    /// Get relative path from root
+    /// Get relative path from root, in canonical Unicode form
     pub fn relative_path(&self, absolute: &Path) -> String {
         let absolute = absolute
             .canonicalize()
@@ -85,18 +311,69 @@ impl FilesystemService {
 
         absolute
             .strip_prefix(&self.root)
-            .map(|p| format!("/{}", p.display()))
+            .map(|p| format!("/{}", canonical_name(&p.display().to_string())))
             .unwrap_or_else(|_| "/".to_string())
     }
 
-    /// List directory contents
-    pub fn list_directory(&self, relative_path: &str) -> Result<Vec<FileEntry>, FsError> {
+    /// The entry for one file or directory, as listed in its directory
+    pub fn get_entry(&self, relative_path: &str) -> Result<FileEntry, FsError> {
+        let path = self.resolve_path(relative_path)?;
+        let metadata = fs::metadata(&path)?;
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_owned();
+        Ok(self.entry_for(&path, &name, &metadata))
+    }
+
+    fn entry_for(&self, file_path: &Path, name: &OsStr, metadata: &fs::Metadata) -> FileEntry {
+        let mime_type = if metadata.is_file() {
+            mime_type_of(file_path)
+        } else {
+            None
+        };
+
+        FileEntry {
+            id: None,
+            name: name.to_string_lossy().to_string(),
+            path: self.relative_path(file_path),
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_file() {
+                Some(metadata.len())
+            } else {
+                None
+            },
+            created: metadata.created().ok().map(DateTime::<Utc>::from),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            mime_type,
+            width: None,
+            height: None,
+            duration: None,
+            indexed_at: None,
+            artist: None,
+            album: None,
+            title: None,
+            track_number: None,
+            page_count: None,
+            author: None,
+            original_name: None,
+            has_notes: false,
+            link_kind: None,
+            link_target: None,
+        }
+    }
+
+    /// List directory contents, leaving out `.fxignore`d entries if
+    /// `respect_ignore` is set
+    pub fn list_directory(
+        &self,
+        relative_path: &str,
+        respect_ignore: bool,
+    ) -> Result<Vec<FileEntry>, FsError> {
         let path = self.resolve_path(relative_path)?;
 
         if !path.is_dir() {
             return Err(FsError::NotADirectory(relative_path.to_string()));
         }
 
+        let kept = respect_ignore.then(|| unignored_children(&path));
         let mut entries = Vec::new();
 
         for entry in fs::read_dir(&path)? {
@@ -110,34 +387,10 @@ impl FilesystemService {
             };
 
             let file_path = entry.path();
-            let relative = self.relative_path(&file_path);
-
-            let mime_type = if metadata.is_file() {
-                mime_guess::from_path(&file_path)
-                    .first()
-                    .map(|m| m.to_string())
-            } else {
-                None
-            };
-
-            entries.push(FileEntry {
-                id: None,
-                name: entry.file_name().to_string_lossy().to_string(),
-                path: relative,
-                is_dir: metadata.is_dir(),
-                size: if metadata.is_file() {
-                    Some(metadata.len())
-                } else {
-                    None
-                },
-                created: metadata.created().ok().map(|t| DateTime::<Utc>::from(t)),
-                modified: metadata.modified().ok().map(|t| DateTime::<Utc>::from(t)),
-                mime_type,
-                width: None,
-                height: None,
-                duration: None,
-                indexed_at: None,
-            });
+            if kept.as_ref().is_some_and(|kept| !kept.contains(&file_path)) {
+                continue;
+            }
+            entries.push(self.entry_for(&file_path, &entry.file_name(), &metadata));
         }
 
         // Sort: directories first, then by name
@@ -151,38 +404,61 @@ impl FilesystemService {
     }
 
     /// Get directory tree for sidebar (single level, lazy loaded).
-    pub fn get_tree_node(&self, relative_path: &str) -> Result<Vec<TreeNode>, FsError> {
+    pub fn get_tree_node(
+        &self,
+        relative_path: &str,
+        show_hidden: bool,
+        respect_ignore: bool,
+    ) -> Result<Vec<TreeNode>, FsError> {
         let path = self.resolve_path(relative_path)?;
 
         if !path.is_dir() {
             return Err(FsError::NotADirectory(relative_path.to_string()));
         }
 
+        let kept = respect_ignore.then(|| unignored_children(&path));
+
         let mut nodes = Vec::new();
 
-        for entry in fs::read_dir(&path)? {
+        let entries = fs::read_dir(&path).map_err(|e| io_error(e, relative_path))?;
+        for entry in entries {
             let entry = match entry {
                 Ok(e) => e,
                 Err(_) => continue,
             };
-            let metadata = match entry.metadata() {
-                Ok(m) => m,
-                Err(_) => continue,
+            // Without metadata, the entry type from the directory listing
+            // still tells directories apart
+            let is_dir = match entry.metadata() {
+                Ok(m) => m.is_dir(),
+                Err(_) => entry.file_type().is_ok_and(|t| t.is_dir()),
             };
 
-            if !metadata.is_dir() {
+            if !is_dir {
+                continue;
+            }
+            if !show_hidden && is_hidden_name(&entry.file_name().to_string_lossy()) {
                 continue;
             }
 
             let file_path = entry.path();
+            if kept.as_ref().is_some_and(|kept| !kept.contains(&file_path)) {
+                continue;
+            }
             let relative = self.relative_path(&file_path);
 
             // Check if this directory has subdirectories
-            let has_children = fs::read_dir(&file_path)
+            let child_kept = respect_ignore.then(|| unignored_children(&file_path));
+            let children = fs::read_dir(&file_path);
+            let readable = children.is_ok();
+            let has_children = children
                 .map(|entries| {
-                    entries
-                        .filter_map(|e| e.ok())
-                        .any(|e| e.metadata().map(|m| m.is_dir()).unwrap_or(false))
+                    entries.filter_map(|e| e.ok()).any(|e| {
+                        (show_hidden || !is_hidden_name(&e.file_name().to_string_lossy()))
+                            && child_kept
+                                .as_ref()
+                                .is_none_or(|kept| kept.contains(&e.path()))
+                            && e.metadata().map(|m| m.is_dir()).unwrap_or(false)
+                    })
                 })
                 .unwrap_or(false);
 
@@ -190,14 +466,39 @@ impl FilesystemService {
                 name: entry.file_name().to_string_lossy().to_string(),
                 path: relative,
                 has_children,
+                readable,
+                children: None,
+                child_dir_count: None,
+                file_count: None,
             });
         }
 
-        nodes.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+        nodes.sort_by_key(|a| a.name.to_lowercase());
 
         Ok(nodes)
     }
 
+    /// Get the directory tree `depth` levels deep, 1 being a single level
+    /// like `get_tree_node`. Subdirectories that cannot be read are left
+    /// unexpanded.
+    pub fn get_tree(
+        &self,
+        relative_path: &str,
+        show_hidden: bool,
+        respect_ignore: bool,
+        depth: usize,
+    ) -> Result<Vec<TreeNode>, FsError> {
+        let mut nodes = self.get_tree_node(relative_path, show_hidden, respect_ignore)?;
+        if depth > 1 {
+            for node in nodes.iter_mut().filter(|node| node.has_children) {
+                node.children = self
+                    .get_tree(&node.path, show_hidden, respect_ignore, depth - 1)
+                    .ok();
+            }
+        }
+        Ok(nodes)
+    }
+
     /// Create a new directory
     pub fn create_directory(&self, relative_path: &str) -> Result<(), FsError> {
         let parent = Path::new(relative_path).parent().unwrap_or(Path::new("/"));
@@ -219,6 +520,175 @@ impl FilesystemService {
         Ok(())
     }
 
+    /// Create a directory along with any missing parents, returning the
+    /// relative paths of the directories that were created, outermost
+    /// first. Each existing ancestor is resolved before descending into it,
+    /// so a symlink pointing outside the root is refused rather than
+    /// followed.
+    pub fn create_directory_all(&self, relative_path: &str) -> Result<Vec<String>, FsError> {
+        let root_canonical = self.root.canonicalize()?;
+        let mut current = root_canonical.clone();
+        let mut created = Vec::new();
+
+        for component in Path::new(relative_path).components() {
+            let name = match component {
+                Component::Normal(name) => name,
+                Component::RootDir | Component::CurDir => continue,
+                Component::ParentDir | Component::Prefix(_) => return Err(FsError::PathEscape),
+            };
+            let next = path_on_disk(&current, &name.to_string_lossy());
+            match fs::create_dir(&next) {
+                Ok(()) => created.push(self.relative_path(&next)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(io_error(e, &self.relative_path(&current))),
+            }
+
+            let resolved = next
+                .canonicalize()
+                .map_err(|e| io_error(e, relative_path))?;
+            if !resolved.starts_with(&root_canonical) {
+                return Err(FsError::PathEscape);
+            }
+            if !resolved.is_dir() {
+                return Err(FsError::NotADirectory(self.relative_path(&resolved)));
+            }
+            current = resolved;
+        }
+
+        Ok(created)
+    }
+
+    /// Make an untrusted name (e.g. from an upload) safe to create on any
+    /// platform: path separators and characters Windows rejects become `_`,
+    /// control characters are dropped, trailing dots and spaces are trimmed,
+    /// reserved device names get a `_` prefix, and overlong names are cut to
+    /// 255 bytes keeping the extension. Fails if nothing usable is left.
+    pub fn sanitize_file_name(name: &str) -> Result<String, FsError> {
+        let mut clean: String = name
+            .chars()
+            .filter(|c| !c.is_control())
+            .map(|c| match c {
+                '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+                c => c,
+            })
+            .collect();
+        clean = clean.trim_start().trim_end_matches(['.', ' ']).to_string();
+
+        if clean.is_empty() || clean.chars().all(|c| c == '.') {
+            return Err(FsError::InvalidName(name.to_string()));
+        }
+
+        let stem = clean.split('.').next().unwrap_or_default();
+        if RESERVED_NAMES
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+        {
+            clean.insert(0, '_');
+        }
+
+        if clean.len() > MAX_NAME_BYTES {
+            let extension = Path::new(&clean)
+                .extension()
+                .map(|ext| format!(".{}", ext.to_string_lossy()))
+                .filter(|ext| ext.len() < MAX_NAME_BYTES / 2)
+                .unwrap_or_default();
+            let mut cut = MAX_NAME_BYTES - extension.len();
+            while !clean.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            clean = format!("{}{}", &clean[..cut], extension);
+        }
+
+        Ok(clean)
+    }
+
+    /// `name` if nothing in `dir` has it yet, otherwise the first free
+    /// `stem (n).ext`
+    pub fn unused_name(dir: &Path, name: &str) -> String {
+        if !dir.join(name).exists() {
+            return name.to_string();
+        }
+        let (stem, extension) = match name.rfind('.') {
+            Some(dot) if dot > 0 => name.split_at(dot),
+            _ => (name, ""),
+        };
+        (1..)
+            .map(|n| format!("{stem} ({n}){extension}"))
+            .find(|candidate| !dir.join(candidate).exists())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Return the child directory `name` of `parent`, creating it if needed.
+    /// `name` must be a single path component, and the result must still
+    /// resolve under root (an existing symlink cannot be followed out).
+    pub fn ensure_subdirectory(&self, parent: &Path, name: &str) -> Result<PathBuf, FsError> {
+        let mut components = Path::new(name).components();
+        let valid = matches!(
+            (components.next(), components.next()),
+            (Some(std::path::Component::Normal(_)), None)
+        );
+        if !valid || name.contains(['/', '\\']) {
+            return Err(FsError::PathEscape);
+        }
+
+        let dir = parent.join(name);
+        match fs::create_dir(&dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+            _ => {}
+        }
+
+        let canonical = dir.canonicalize()?;
+        if !canonical.starts_with(self.root.canonicalize()?) {
+            return Err(FsError::PathEscape);
+        }
+        if !canonical.is_dir() {
+            return Err(FsError::NotADirectory(self.relative_path(&canonical)));
+        }
+        Ok(canonical)
+    }
+
+    /// Create `name` in `parent` as a link to `target`, both resolved under
+    /// root. Symlinks store a relative path so they keep working when the
+    /// root is mounted elsewhere. Returns the link's absolute path.
+    pub fn create_link(
+        &self,
+        target: &Path,
+        parent: &Path,
+        name: &str,
+        kind: LinkKind,
+    ) -> Result<PathBuf, FsError> {
+        let mut components = Path::new(name).components();
+        let valid = matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        );
+        if !valid || name.contains(['/', '\\']) {
+            return Err(FsError::InvalidName(name.to_string()));
+        }
+
+        let link = parent.join(name);
+        match kind {
+            LinkKind::Hard => {
+                if !target.is_file() {
+                    return Err(FsError::NotAFile(self.relative_path(target)));
+                }
+                fs::hard_link(target, &link)?;
+            }
+            LinkKind::Symbolic => {
+                let relative = relative_to(parent, target);
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&relative, &link)?;
+                #[cfg(windows)]
+                if target.is_dir() {
+                    std::os::windows::fs::symlink_dir(&relative, &link)?;
+                } else {
+                    std::os::windows::fs::symlink_file(&relative, &link)?;
+                }
+            }
+        }
+        Ok(link)
+    }
+
     /// Delete a file or directory
     pub fn delete(&self, relative_path: &str) -> Result<(), FsError> {
         let path = self.resolve_path(relative_path)?;
@@ -322,6 +792,198 @@ impl FilesystemService {
         }
     }
 
+    /// Bytes a copy of `path` takes: its size, or the total of the files
+    /// below it
+    pub fn entry_size(&self, path: &str) -> Result<u64, FsError> {
+        Ok(self.entry_totals(path)?.1)
+    }
+
+    /// Number of files and their total bytes at or below `path`
+    pub fn entry_totals(&self, path: &str) -> Result<(u64, u64), FsError> {
+        let resolved = self.resolve_path(path)?;
+        if !resolved.is_dir() {
+            return Ok((1, fs::metadata(&resolved)?.len()));
+        }
+        Ok(WalkDir::new(&resolved)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| e.metadata().ok())
+            .fold((0, 0), |(files, bytes), m| (files + 1, bytes + m.len())))
+    }
+
+    /// Copy `from` to `to_dir` like `rsync -r`, leaving out files already
+    /// current at the destination. Copied files keep the source's
+    /// modification time so the next sync skips them. Nothing at the
+    /// destination is deleted; a file where the source has a directory, or
+    /// the reverse, is an error. Symlinks are never followed: those in the
+    /// source are left out, and those at the destination are replaced.
+    pub fn sync_entry(
+        &self,
+        from: &str,
+        to_dir: &str,
+        compare: SyncCompare,
+    ) -> Result<(String, SyncStats), FsError> {
+        let source = self.resolve_path(from)?;
+        let file_name = source
+            .file_name()
+            .ok_or_else(|| FsError::NotFound(from.to_string()))?;
+        let dest_path = self.build_destination_path(to_dir, file_name)?;
+
+        if source.is_dir() && dest_path.starts_with(&source) {
+            return Err(FsError::PermissionDenied(
+                "Cannot sync a directory into itself".to_string(),
+            ));
+        }
+
+        let mut stats = SyncStats::default();
+        self.sync_recursive(&source, &dest_path, compare, false, &mut stats)?;
+        Ok((self.relative_path(&dest_path), stats))
+    }
+
+    /// Make the directory `destination` a copy of the directory `source`,
+    /// creating it if needed: what `sync_entry` does to the contents, and
+    /// with `delete_extraneous` removing what the source does not have.
+    /// Neither directory may be inside the other. Links below the
+    /// destination are removed or replaced, never deleted through.
+    pub fn mirror_directory(
+        &self,
+        source: &str,
+        destination: &str,
+        compare: SyncCompare,
+        delete_extraneous: bool,
+    ) -> Result<SyncStats, FsError> {
+        let source_path = self.resolve_path(source)?;
+        if !source_path.is_dir() {
+            return Err(FsError::NotADirectory(source.to_string()));
+        }
+        let nested = |a: &Path, b: &Path| a.starts_with(b) || b.starts_with(a);
+        let refuse =
+            || FsError::PermissionDenied("Cannot mirror a directory into itself".to_string());
+        // Checked before creating the destination, and again once symlinks
+        // in it are resolved
+        if nested(&path_on_disk(&self.root, destination), &source_path) {
+            return Err(refuse());
+        }
+        self.create_directory_all(destination)?;
+        let dest_path = self.resolve_path(destination)?;
+        if nested(&dest_path, &source_path) {
+            return Err(refuse());
+        }
+
+        let mut stats = SyncStats::default();
+        self.sync_recursive(
+            &source_path,
+            &dest_path,
+            compare,
+            delete_extraneous,
+            &mut stats,
+        )?;
+        Ok(stats)
+    }
+
+    fn sync_recursive(
+        &self,
+        source: &Path,
+        dest: &Path,
+        compare: SyncCompare,
+        delete_extraneous: bool,
+        stats: &mut SyncStats,
+    ) -> Result<(), FsError> {
+        let mismatch = |path: &Path| self.relative_path(path);
+        // Links could lead out of the root, or back up the tree
+        let source_meta = fs::symlink_metadata(source)?;
+        if source_meta.is_symlink() {
+            return Ok(());
+        }
+        let dest_meta = match fs::symlink_metadata(dest) {
+            Ok(meta) if meta.is_symlink() => {
+                fs::remove_file(dest)?;
+                None
+            }
+            Ok(meta) => Some(meta),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        if source_meta.is_dir() {
+            match dest_meta {
+                Some(meta) if !meta.is_dir() => {
+                    return Err(FsError::NotADirectory(mismatch(dest)));
+                }
+                Some(_) => {}
+                None => fs::create_dir(dest)?,
+            }
+            let mut names = HashSet::new();
+            for entry in fs::read_dir(source)? {
+                let entry = entry?;
+                if entry.file_type()?.is_symlink() {
+                    continue;
+                }
+                let child_dest = dest.join(entry.file_name());
+                self.sync_recursive(
+                    &entry.path(),
+                    &child_dest,
+                    compare,
+                    delete_extraneous,
+                    stats,
+                )?;
+                names.insert(entry.file_name());
+            }
+            if delete_extraneous {
+                for entry in fs::read_dir(dest)? {
+                    let entry = entry?;
+                    if names.contains(&entry.file_name()) {
+                        continue;
+                    }
+                    if entry.file_type()?.is_dir() {
+                        fs::remove_dir_all(entry.path())?;
+                    } else {
+                        fs::remove_file(entry.path())?;
+                    }
+                    stats.deleted += 1;
+                }
+            }
+            return Ok(());
+        }
+
+        if dest_meta.as_ref().is_some_and(|meta| meta.is_dir()) {
+            return Err(FsError::NotAFile(mismatch(dest)));
+        }
+        let current = match (&dest_meta, compare) {
+            (None, _) => false,
+            (Some(dest_meta), SyncCompare::SizeAndMtime) => {
+                let seconds = |meta: &fs::Metadata| {
+                    meta.modified()
+                        .ok()
+                        .map(|t| DateTime::<Utc>::from(t).timestamp())
+                };
+                source_meta.len() == dest_meta.len()
+                    && seconds(&source_meta).is_some()
+                    && seconds(&source_meta) == seconds(dest_meta)
+            }
+            (Some(dest_meta), SyncCompare::Contents) => {
+                source_meta.len() == dest_meta.len() && same_contents(source, dest)?
+            }
+        };
+        if current {
+            stats.skipped += 1;
+            return Ok(());
+        }
+
+        let mut src_file = fs::File::open(source)?;
+        let mut dest_file = create_unshared(dest)?;
+        std::io::copy(&mut src_file, &mut dest_file)?;
+        if let Ok(modified) = source_meta.modified() {
+            dest_file.set_modified(modified)?;
+        }
+        if dest_meta.is_some() {
+            stats.updated += 1;
+        } else {
+            stats.created += 1;
+        }
+        Ok(())
+    }
+
     /// Copy a file or directory recursively
     pub fn copy_entry(
         &self,
@@ -367,7 +1029,7 @@ impl FilesystemService {
 
     fn copy_recursive(&self, source: &Path, dest: &Path) -> Result<(), FsError> {
         if source.is_dir() {
-            fs::create_dir(&dest)?;
+            fs::create_dir(dest)?;
             for entry in fs::read_dir(source)? {
                 let entry = entry?;
                 let file_type = entry.file_type()?;
@@ -411,15 +1073,7 @@ impl FilesystemService {
         let parent = candidate
             .parent()
             .ok_or_else(|| FsError::NotFound(target.to_string()))?;
-        let parent_canonical = parent.canonicalize().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                FsError::NotFound(target.to_string())
-            } else if e.kind() == std::io::ErrorKind::PermissionDenied {
-                FsError::PermissionDenied(target.to_string())
-            } else {
-                FsError::Io(e)
-            }
-        })?;
+        let parent_canonical = parent.canonicalize().map_err(|e| io_error(e, target))?;
 
         if !parent_canonical.starts_with(&root_canonical) {
             return Err(FsError::PathEscape);
@@ -437,6 +1091,7 @@ impl FilesystemService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::StorageBackend;
     use std::fs;
     use tempfile::tempdir;
 
@@ -464,6 +1119,120 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn nfd_names_resolve_from_and_report_nfc_paths() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        let nfd_dir: String = "Résumés".nfd().collect();
+        let nfd_file: String = "café.txt".nfd().collect();
+        fs::create_dir(root.join(&nfd_dir)).unwrap();
+        fs::write(root.join(&nfd_dir).join(&nfd_file), b"data").unwrap();
+
+        let resolved = service.resolve_path("/Résumés/café.txt")?;
+        assert!(resolved.is_file());
+        assert_eq!(service.relative_path(&resolved), "/Résumés/café.txt");
+        assert!(matches!(
+            service.resolve_path("/Résumés/cafe.txt"),
+            Err(FsError::NotFound(_))
+        ));
+
+        let entries = service.list_directory("/Résumés", false)?;
+        assert_eq!(entries[0].path, "/Résumés/café.txt");
+        Ok(())
+    }
+
+    #[test]
+    fn files_without_a_known_extension_are_typed_by_content() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        let png = [
+            0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D,
+        ];
+        fs::write(root.join("scan"), png)?;
+        fs::write(root.join("notes"), b"plain words")?;
+        fs::write(root.join("photo.jpg"), png)?;
+
+        let mime = |name: &str| service.get_entry(name).map(|e| e.mime_type);
+        assert_eq!(mime("scan")?.as_deref(), Some("image/png"));
+        assert_eq!(mime("notes")?, None);
+        // A known extension wins without reading the file
+        assert_eq!(mime("photo.jpg")?.as_deref(), Some("image/jpeg"));
+        Ok(())
+    }
+
+    #[test]
+    fn tree_hides_dot_directories_unless_requested() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        fs::create_dir_all(root.join("visible/.cache")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+
+        let nodes = service.get_tree_node("/", false, false)?;
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].name, "visible");
+        assert!(!nodes[0].has_children);
+
+        let nodes = service.get_tree_node("/", true, false)?;
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes.iter().any(|n| n.name == "visible" && n.has_children));
+
+        let nodes = service.get_tree("/", true, false, 2)?;
+        let visible = nodes.iter().find(|n| n.name == "visible").unwrap();
+        let children = visible.children.as_ref().expect("second level expanded");
+        assert_eq!(children[0].path, "/visible/.cache");
+        assert!(children[0].children.is_none());
+        assert!(service.get_tree("/", true, false, 1)?[0].children.is_none());
+
+        assert!(is_hidden_path("/a/.git/config"));
+        assert!(!is_hidden_path("/a/b.txt"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_directories_stay_in_the_tree() -> Result<(), FsError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (service, _tmp, root) = service_with_root();
+        let locked = root.join("locked");
+        fs::create_dir_all(locked.join("inner")).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions do not stop root
+        let listable = fs::read_dir(&locked).is_ok();
+        let nodes = service.get_tree_node("/", false, false);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let nodes = nodes?;
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].readable, listable);
+        assert_eq!(nodes[0].has_children, listable);
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_rules_apply_when_requested() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        fs::create_dir_all(root.join("app/target/debug")).unwrap();
+        fs::create_dir_all(root.join("app/src")).unwrap();
+        fs::write(root.join("app/build.log"), b"").unwrap();
+        fs::write(root.join("app/main.rs"), b"").unwrap();
+        fs::write(root.join(IGNORE_FILE), "target/\n*.log\n").unwrap();
+
+        let names = |entries: Vec<FileEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.name).collect()
+        };
+        assert_eq!(
+            names(service.list_directory("/app", true)?),
+            vec!["src", "main.rs"]
+        );
+        assert_eq!(names(service.list_directory("/app", false)?).len(), 4);
+
+        fs::create_dir_all(root.join("only_ignored/target")).unwrap();
+        let nodes = service.get_tree_node("/", false, true)?;
+        let only_ignored = nodes.iter().find(|n| n.name == "only_ignored").unwrap();
+        assert!(!only_ignored.has_children);
+        let app = service.get_tree_node("/app", false, true)?;
+        assert_eq!(app.len(), 1);
+        Ok(())
+    }
+
     #[test]
     fn basic_file_operations_work() -> Result<(), FsError> {
         let (service, _tmp, root) = service_with_root();
@@ -485,6 +1254,167 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn create_directory_all_makes_missing_parents_inside_root() -> Result<(), FsError> {
+        let (service, tmp, root) = service_with_root();
+        fs::create_dir(root.join("projects")).unwrap();
+
+        let created = service.create_directory_all("/projects/2024/q1/raw")?;
+        assert_eq!(
+            created,
+            [
+                "/projects/2024",
+                "/projects/2024/q1",
+                "/projects/2024/q1/raw"
+            ]
+        );
+        assert!(root.join("projects/2024/q1/raw").is_dir());
+        assert!(service.create_directory_all("projects/2024")?.is_empty());
+
+        assert!(matches!(
+            service.create_directory_all("/projects/../../escape"),
+            Err(FsError::PathEscape)
+        ));
+        fs::write(root.join("notes.txt"), b"x").unwrap();
+        assert!(matches!(
+            service.create_directory_all("/notes.txt/sub"),
+            Err(FsError::NotADirectory(_))
+        ));
+
+        #[cfg(unix)]
+        {
+            let outside = tmp.path().join("outside");
+            fs::create_dir(&outside).unwrap();
+            std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+            assert!(matches!(
+                service.create_directory_all("/link/sub"),
+                Err(FsError::PathEscape)
+            ));
+            assert!(!outside.join("sub").exists());
+        }
+        #[cfg(not(unix))]
+        let _ = tmp;
+        Ok(())
+    }
+
+    #[test]
+    fn relative_link_targets() {
+        assert_eq!(
+            relative_to(Path::new("/r/a/b"), Path::new("/r/photos/x.jpg")),
+            Path::new("../../photos/x.jpg")
+        );
+        assert_eq!(
+            relative_to(Path::new("/r"), Path::new("/r/x.jpg")),
+            Path::new("x.jpg")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_share_data_or_point_at_their_target() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        fs::create_dir_all(root.join("photos")).unwrap();
+        fs::create_dir_all(root.join("albums/2024")).unwrap();
+        fs::write(root.join("photos/beach.jpg"), b"jpeg").unwrap();
+        let target = service.resolve_path("/photos/beach.jpg")?;
+        let album = service.resolve_path("/albums/2024")?;
+
+        let hard = service.create_link(&target, &album, "hard.jpg", LinkKind::Hard)?;
+        fs::write(&target, b"edited").unwrap();
+        assert_eq!(fs::read(&hard).unwrap(), b"edited");
+
+        let soft = service.create_link(&target, &album, "soft.jpg", LinkKind::Symbolic)?;
+        assert_eq!(
+            fs::read_link(&soft).unwrap(),
+            Path::new("../../photos/beach.jpg")
+        );
+        assert_eq!(service.resolve_path("/albums/2024/soft.jpg")?, target);
+
+        let photos = service.resolve_path("/photos")?;
+        assert!(matches!(
+            service.create_link(&photos, &album, "dir", LinkKind::Hard),
+            Err(FsError::NotAFile(_))
+        ));
+        assert!(matches!(
+            service.create_link(&target, &album, "..", LinkKind::Symbolic),
+            Err(FsError::InvalidName(_))
+        ));
+        assert!(matches!(
+            service.create_link(&target, &album, "hard.jpg", LinkKind::Hard),
+            Err(FsError::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn sanitize_file_name_neutralizes_unsafe_names() {
+        let sanitize = |name| FilesystemService::sanitize_file_name(name).unwrap();
+        assert_eq!(sanitize("report.pdf"), "report.pdf");
+        assert_eq!(sanitize("a/b\\c.txt"), "a_b_c.txt");
+        assert_eq!(sanitize("what?<now>.txt"), "what__now_.txt");
+        assert_eq!(sanitize("bell\u{7}\ttab.txt"), "belltab.txt");
+        assert_eq!(sanitize("trailing. . "), "trailing");
+        assert_eq!(sanitize("CON"), "_CON");
+        assert_eq!(sanitize("nul.tar.gz"), "_nul.tar.gz");
+        assert_eq!(sanitize("console.log"), "console.log");
+
+        let long = format!("{}.jpeg", "é".repeat(200));
+        let cut = sanitize(&long);
+        assert!(cut.len() <= 255);
+        assert!(cut.ends_with("é.jpeg"));
+
+        for name in ["", "  ", ".", "..", "\u{0}"] {
+            assert!(matches!(
+                FilesystemService::sanitize_file_name(name),
+                Err(FsError::InvalidName(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn unused_name_numbers_before_the_extension() {
+        let (_service, _tmp, root) = service_with_root();
+        assert_eq!(FilesystemService::unused_name(&root, "a.txt"), "a.txt");
+        fs::write(root.join("a.txt"), b"").unwrap();
+        fs::write(root.join("a (1).txt"), b"").unwrap();
+        assert_eq!(FilesystemService::unused_name(&root, "a.txt"), "a (2).txt");
+        fs::write(root.join(".env"), b"").unwrap();
+        assert_eq!(FilesystemService::unused_name(&root, ".env"), ".env (1)");
+    }
+
+    #[test]
+    fn ensure_subdirectory_stays_under_root() -> Result<(), FsError> {
+        let (service, tmp, root) = service_with_root();
+        let root = root.canonicalize().unwrap();
+
+        let created = service.ensure_subdirectory(&root, "photos")?;
+        assert_eq!(created, root.join("photos"));
+        // Existing directories are reused
+        assert_eq!(service.ensure_subdirectory(&root, "photos")?, created);
+
+        for name in ["..", ".", "", "a/b", "a\\b"] {
+            let err = service.ensure_subdirectory(&root, name).unwrap_err();
+            assert!(matches!(err, FsError::PathEscape), "{name:?}");
+        }
+
+        fs::write(root.join("file.txt"), b"x").unwrap();
+        let err = service.ensure_subdirectory(&root, "file.txt").unwrap_err();
+        assert!(matches!(err, FsError::NotADirectory(_)));
+
+        #[cfg(unix)]
+        {
+            let outside = tmp.path().join("outside");
+            fs::create_dir(&outside).unwrap();
+            std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+            let err = service.ensure_subdirectory(&root, "link").unwrap_err();
+            assert!(matches!(err, FsError::PathEscape));
+        }
+        #[cfg(not(unix))]
+        let _ = tmp;
+
+        Ok(())
+    }
+
     #[test]
     fn move_and_copy_respect_overwrite() -> Result<(), FsError> {
         let (service, _tmp, root) = service_with_root();
@@ -519,4 +1449,210 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn stale_expectations_are_refused() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        fs::write(root.join("notes.txt"), b"draft").unwrap();
+        let listed = service.get_entry("/notes.txt")?;
+        let expected = ExpectedState {
+            mtime: listed.modified,
+            size: listed.size,
+        };
+
+        service.check_expected("/notes.txt", &expected)?;
+        service.check_expected("/notes.txt", &ExpectedState::default())?;
+
+        fs::write(root.join("notes.txt"), b"final draft").unwrap();
+        assert!(matches!(
+            service.check_expected("/notes.txt", &expected),
+            Err(FsError::Modified(_))
+        ));
+
+        let an_hour_earlier = ExpectedState {
+            mtime: listed.modified.map(|m| m - chrono::Duration::hours(1)),
+            size: None,
+        };
+        assert!(matches!(
+            service.check_expected("/notes.txt", &an_hour_earlier),
+            Err(FsError::Modified(_))
+        ));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_does_not_write_through_hard_links() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("dst/src")).unwrap();
+        fs::write(root.join("src/a.txt"), b"new contents").unwrap();
+        fs::write(root.join("shared.txt"), b"old").unwrap();
+        fs::hard_link(root.join("shared.txt"), root.join("dst/src/a.txt")).unwrap();
+
+        let (_, stats) = service.sync_entry("/src", "/dst", SyncCompare::SizeAndMtime)?;
+        assert_eq!(stats.updated, 1);
+        assert_eq!(
+            fs::read(root.join("dst/src/a.txt")).unwrap(),
+            b"new contents"
+        );
+        assert_eq!(fs::read(root.join("shared.txt")).unwrap(), b"old");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_does_not_follow_symlinks_out_of_the_root() -> Result<(), FsError> {
+        let (service, tmp, root) = service_with_root();
+        let outside = tmp.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/a.txt"), b"a").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("src/escape")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("src/secret.txt"))
+            .unwrap();
+        // Including a loop, which must not be walked forever
+        std::os::unix::fs::symlink(root.join("src"), root.join("src/again")).unwrap();
+        fs::create_dir(root.join("backup")).unwrap();
+
+        let (_, stats) = service.sync_entry("/src", "/backup", SyncCompare::SizeAndMtime)?;
+        assert_eq!(stats.created, 1);
+        assert!(root.join("backup/src/a.txt").exists());
+        for name in ["escape", "secret.txt", "again"] {
+            assert!(fs::symlink_metadata(root.join("backup/src").join(name)).is_err());
+        }
+
+        // Links at the destination are replaced, not written through
+        fs::write(outside.join("a.txt"), b"outside").unwrap();
+        fs::remove_file(root.join("backup/src/a.txt")).unwrap();
+        std::os::unix::fs::symlink(outside.join("a.txt"), root.join("backup/src/a.txt")).unwrap();
+        fs::create_dir(root.join("src/docs")).unwrap();
+        fs::write(root.join("src/docs/b.txt"), b"b").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("backup/src/docs")).unwrap();
+
+        service.sync_entry("/src", "/backup", SyncCompare::SizeAndMtime)?;
+        assert_eq!(fs::read(outside.join("a.txt")).unwrap(), b"outside");
+        assert!(!outside.join("b.txt").exists());
+        assert!(
+            !fs::symlink_metadata(root.join("backup/src/a.txt"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(fs::read(root.join("backup/src/a.txt")).unwrap(), b"a");
+        assert!(root.join("backup/src/docs").is_dir());
+        assert_eq!(fs::read(root.join("backup/src/docs/b.txt")).unwrap(), b"b");
+        Ok(())
+    }
+
+    #[test]
+    fn sync_copies_only_what_changed() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        fs::create_dir_all(root.join("photos/2024")).unwrap();
+        fs::write(root.join("photos/a.jpg"), b"aaaa").unwrap();
+        fs::write(root.join("photos/2024/b.jpg"), b"bbbb").unwrap();
+        fs::create_dir(root.join("backup")).unwrap();
+
+        let (path, stats) = service.sync_entry("/photos", "/backup", SyncCompare::SizeAndMtime)?;
+        assert_eq!(path, "/backup/photos");
+        assert_eq!(
+            stats,
+            SyncStats {
+                created: 2,
+                ..SyncStats::default()
+            }
+        );
+        assert_eq!(
+            fs::read(root.join("backup/photos/2024/b.jpg")).unwrap(),
+            b"bbbb"
+        );
+
+        fs::write(root.join("photos/a.jpg"), b"changed").unwrap();
+        let (_, stats) = service.sync_entry("/photos", "/backup", SyncCompare::SizeAndMtime)?;
+        assert_eq!((stats.created, stats.updated, stats.skipped), (0, 1, 1));
+        assert_eq!(
+            fs::read(root.join("backup/photos/a.jpg")).unwrap(),
+            b"changed"
+        );
+
+        // Same size and time, different bytes: only a content check sees it
+        let copy = root.join("backup/photos/2024/b.jpg");
+        let modified = fs::metadata(&copy).unwrap().modified().unwrap();
+        fs::write(&copy, b"BBBB").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&copy)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let (_, stats) = service.sync_entry("/photos", "/backup", SyncCompare::SizeAndMtime)?;
+        assert_eq!(stats.skipped, 2);
+        let (_, stats) = service.sync_entry("/photos", "/backup", SyncCompare::Contents)?;
+        assert_eq!((stats.updated, stats.skipped), (1, 1));
+        assert_eq!(fs::read(&copy).unwrap(), b"bbbb");
+
+        fs::remove_dir_all(root.join("backup/photos/2024")).unwrap();
+        fs::write(root.join("backup/photos/2024"), b"in the way").unwrap();
+        assert!(matches!(
+            service.sync_entry("/photos", "/backup", SyncCompare::SizeAndMtime),
+            Err(FsError::NotADirectory(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn mirror_removes_extraneous_entries_when_asked() -> Result<(), FsError> {
+        let (service, _tmp, root) = service_with_root();
+        fs::create_dir_all(root.join("src/docs")).unwrap();
+        fs::write(root.join("src/docs/a.txt"), b"a").unwrap();
+        fs::create_dir_all(root.join("mirror/old")).unwrap();
+        fs::write(root.join("mirror/old/x.txt"), b"x").unwrap();
+        fs::write(root.join("mirror/stray.txt"), b"s").unwrap();
+
+        let stats =
+            service.mirror_directory("/src", "/mirror", SyncCompare::SizeAndMtime, false)?;
+        assert_eq!((stats.created, stats.deleted), (1, 0));
+        assert!(root.join("mirror/stray.txt").exists());
+
+        let stats = service.mirror_directory("/src", "/mirror", SyncCompare::SizeAndMtime, true)?;
+        assert_eq!((stats.skipped, stats.deleted), (1, 2));
+        assert!(!root.join("mirror/old").exists());
+        assert!(root.join("mirror/docs/a.txt").exists());
+
+        // A destination that does not exist yet is created
+        service.mirror_directory("/src", "/backups/src", SyncCompare::SizeAndMtime, true)?;
+        assert!(root.join("backups/src/docs/a.txt").exists());
+
+        assert!(
+            service
+                .mirror_directory("/src", "/src/docs", SyncCompare::SizeAndMtime, true)
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mirror_does_not_delete_through_symlinks() -> Result<(), FsError> {
+        let (service, tmp, root) = service_with_root();
+        let outside = tmp.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("keep.txt"), b"keep").unwrap();
+        fs::create_dir_all(root.join("src/docs")).unwrap();
+        fs::write(root.join("src/docs/a.txt"), b"a").unwrap();
+        fs::create_dir(root.join("mirror")).unwrap();
+        // One link where the source has a folder, one the source lacks
+        std::os::unix::fs::symlink(&outside, root.join("mirror/docs")).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("mirror/stray")).unwrap();
+
+        for _ in 0..2 {
+            service.mirror_directory("/src", "/mirror", SyncCompare::SizeAndMtime, true)?;
+        }
+        assert_eq!(fs::read(outside.join("keep.txt")).unwrap(), b"keep");
+        assert!(!outside.join("a.txt").exists());
+        assert!(fs::symlink_metadata(root.join("mirror/stray")).is_err());
+        assert!(root.join("mirror/docs").is_dir());
+        assert!(root.join("mirror/docs/a.txt").exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,436 @@
+//! Commands run on the server when files change through filex, configured in
+//! the file named by `FM_HOOKS_FILE`:
+//!
+//! ```text
+//! [optimize-photos]
+//! events = created, modified     # created, modified, deleted, renamed
+//! path = /photos/**/*.{jpg,png}  # glob over the root-relative path
+//! command = jpegoptim --strip-all {path}
+//! timeout = 60                   # seconds
+//! ```
+//!
+//! The command is split into arguments like a shell would, but is not run
+//! through one; `{path}`, `{relpath}`, `{from}`, and `{event}` are replaced
+//! inside each argument. Every run is recorded with its output in
+//! `hook_runs`.
+
+use anyhow::{Context, bail};
+use chrono::Utc;
+use globset::{Glob, GlobMatcher};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::db;
+use crate::services::webhooks::FileEventKind;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Hooks allowed to run at the same time; further runs wait their turn
+const MAX_CONCURRENT_RUNS: usize = 4;
+
+/// Output kept per run; the rest is cut off
+const MAX_OUTPUT_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub name: String,
+    pub events: Vec<FileEventKind>,
+    pub path: GlobMatcher,
+    /// Command split into arguments, placeholders not yet replaced
+    pub command: Vec<String>,
+    pub timeout: Duration,
+}
+
+impl Hook {
+    fn matches(&self, event: FileEventKind, path: &str) -> bool {
+        self.events.contains(&event) && self.path.is_match(path)
+    }
+}
+
+/// Parse a hooks file. Unlike `.fxnames`, mistakes are errors: a hook that
+/// silently never runs is worse than refusing to start.
+pub fn parse_hooks(text: &str) -> anyhow::Result<Vec<Hook>> {
+    struct Draft {
+        name: String,
+        events: Option<Vec<FileEventKind>>,
+        path: Option<GlobMatcher>,
+        command: Option<Vec<String>>,
+        timeout: Duration,
+    }
+
+    fn finish(draft: Draft) -> anyhow::Result<Hook> {
+        let Some(command) = draft.command else {
+            bail!("hook [{}] has no command", draft.name);
+        };
+        Ok(Hook {
+            events: draft
+                .events
+                .unwrap_or_else(|| vec![FileEventKind::Created, FileEventKind::Modified]),
+            path: match draft.path {
+                Some(path) => path,
+                None => Glob::new("**")?.compile_matcher(),
+            },
+            command,
+            timeout: draft.timeout,
+            name: draft.name,
+        })
+    }
+
+    let mut hooks = Vec::new();
+    let mut draft: Option<Draft> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.split(" #").next().unwrap_or("").trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if let Some(done) = draft.take() {
+                hooks.push(finish(done)?);
+            }
+            draft = Some(Draft {
+                name: name.trim().to_string(),
+                events: None,
+                path: None,
+                command: None,
+                timeout: DEFAULT_TIMEOUT,
+            });
+            continue;
+        }
+
+        let Some(current) = draft.as_mut() else {
+            bail!("line {line_no}: setting outside of a [hook] section");
+        };
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {line_no}: expected key = value");
+        };
+        let value = value.trim();
+        match key.trim() {
+            "events" => {
+                let events = value
+                    .split(',')
+                    .map(|event| parse_event(event.trim()))
+                    .collect::<Option<Vec<_>>>()
+                    .with_context(|| format!("line {line_no}: unknown event in {value:?}"))?;
+                current.events = Some(events);
+            }
+            "path" => {
+                let glob = Glob::new(value)
+                    .with_context(|| format!("line {line_no}: invalid path glob"))?;
+                current.path = Some(glob.compile_matcher());
+            }
+            "command" => {
+                let command = shlex::split(value)
+                    .filter(|args| !args.is_empty())
+                    .with_context(|| format!("line {line_no}: invalid command"))?;
+                current.command = Some(command);
+            }
+            "timeout" => {
+                let secs: u64 = value
+                    .parse()
+                    .with_context(|| format!("line {line_no}: timeout must be seconds"))?;
+                current.timeout = Duration::from_secs(secs);
+            }
+            other => bail!("line {line_no}: unknown setting {other:?}"),
+        }
+    }
+
+    if let Some(done) = draft {
+        hooks.push(finish(done)?);
+    }
+    Ok(hooks)
+}
+
+pub(crate) fn parse_event(event: &str) -> Option<FileEventKind> {
+    match event {
+        "created" => Some(FileEventKind::Created),
+        "modified" => Some(FileEventKind::Modified),
+        "deleted" => Some(FileEventKind::Deleted),
+        "renamed" => Some(FileEventKind::Renamed),
+        _ => None,
+    }
+}
+
+pub struct HookService {
+    hooks: Arc<[Hook]>,
+    root: PathBuf,
+    /// `None` when no hooks are configured
+    pool: Option<SqlitePool>,
+    permits: Arc<Semaphore>,
+}
+
+impl HookService {
+    /// A service with no hooks
+    pub fn disabled() -> Self {
+        Self {
+            hooks: Arc::from([]),
+            root: PathBuf::new(),
+            pool: None,
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_RUNS)),
+        }
+    }
+
+    /// Load hooks from `FM_HOOKS_FILE`, if set
+    pub fn from_config(config: &Config, pool: SqlitePool) -> anyhow::Result<Self> {
+        let Some(file) = &config.hooks_file else {
+            return Ok(Self::disabled());
+        };
+        let text = std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read hooks file {}", file.display()))?;
+        let hooks =
+            parse_hooks(&text).with_context(|| format!("invalid hooks file {}", file.display()))?;
+
+        Ok(Self {
+            hooks: hooks.into(),
+            root: config.root_path.clone(),
+            pool: Some(pool),
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_RUNS)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Start every hook matching a change made through a write endpoint.
+    /// Runs happen in the background; results only go to the run history.
+    pub fn run(&self, event: FileEventKind, path: &str, from: Option<&str>) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+
+        for hook in self.hooks.iter().filter(|hook| hook.matches(event, path)) {
+            let args = render(&hook.command, &self.root, event, path, from);
+            let hook = hook.clone();
+            let root = self.root.clone();
+            let pool = pool.clone();
+            let permits = self.permits.clone();
+            let path = path.to_string();
+            tokio::spawn(async move {
+                let Ok(_permit) = permits.acquire_owned().await else {
+                    return;
+                };
+                let run = execute(&hook, &args, &root, event, &path).await;
+                if let Err(e) = db::record_hook_run(&pool, &run).await {
+                    warn!("Failed to record run of hook [{}]: {}", hook.name, e);
+                }
+            });
+        }
+    }
+}
+
+/// Replace placeholders in each argument of a command template
+fn render(
+    command: &[String],
+    root: &Path,
+    event: FileEventKind,
+    path: &str,
+    from: Option<&str>,
+) -> Vec<String> {
+    let absolute = |relative: &str| {
+        root.join(relative.trim_start_matches('/'))
+            .display()
+            .to_string()
+    };
+    let path_abs = absolute(path);
+    let from_abs = from.map(absolute).unwrap_or_default();
+
+    let values = [
+        ("{path}", path_abs.as_str()),
+        ("{relpath}", path),
+        ("{from}", from_abs.as_str()),
+        ("{event}", event.as_str()),
+    ];
+
+    // One pass, so a file name containing `{path}` is left alone
+    command
+        .iter()
+        .map(|arg| {
+            let mut rendered = String::with_capacity(arg.len());
+            let mut rest = arg.as_str();
+            while !rest.is_empty() {
+                match values.iter().find(|(key, _)| rest.starts_with(key)) {
+                    Some((key, value)) => {
+                        rendered.push_str(value);
+                        rest = &rest[key.len()..];
+                    }
+                    None => {
+                        let c = rest.chars().next().unwrap_or_default();
+                        rendered.push(c);
+                        rest = &rest[c.len_utf8()..];
+                    }
+                }
+            }
+            rendered
+        })
+        .collect()
+}
+
+async fn execute(
+    hook: &Hook,
+    args: &[String],
+    root: &Path,
+    event: FileEventKind,
+    path: &str,
+) -> db::HookRunRow {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    debug!("Running hook [{}]: {:?}", hook.name, args);
+
+    let output = Command::new(&args[0])
+        .args(&args[1..])
+        .current_dir(root)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let (status, exit_code, output) = match tokio::time::timeout(hook.timeout, output).await {
+        Ok(Ok(output)) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            let status = if output.status.success() {
+                "ok"
+            } else {
+                "failed"
+            };
+            (status, output.status.code(), text)
+        }
+        Ok(Err(e)) => ("error", None, format!("failed to start: {e}")),
+        Err(_) => (
+            "timeout",
+            None,
+            format!("killed after {} seconds", hook.timeout.as_secs()),
+        ),
+    };
+    if status != "ok" {
+        warn!(
+            "Hook [{}] for {} {}: {}",
+            hook.name,
+            path,
+            status,
+            output.trim()
+        );
+    }
+
+    db::HookRunRow {
+        id: 0,
+        hook: hook.name.clone(),
+        event: event.as_str().to_string(),
+        path: path.to_string(),
+        command: shlex::try_join(args.iter().map(String::as_str))
+            .unwrap_or_else(|_| args.join(" ")),
+        status: status.to_string(),
+        exit_code: exit_code.map(i64::from),
+        output: truncate_output(output),
+        started_at: started_at.to_rfc3339(),
+        duration_ms: started.elapsed().as_millis() as i64,
+    }
+}
+
+fn truncate_output(mut output: String) -> String {
+    if output.len() > MAX_OUTPUT_BYTES {
+        let mut end = MAX_OUTPUT_BYTES;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        output.truncate(end);
+        output.push_str("\n[output truncated]");
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const HOOKS: &str = r#"
+# Shrink photos as they arrive
+[optimize-photos]
+events = created, modified
+path = /photos/**/*.{jpg,png}
+command = echo "optimizing" {path}
+
+[log-moves]
+events = renamed
+command = echo {from} -> {relpath} # whole tree
+timeout = 5
+"#;
+
+    #[test]
+    fn parses_hooks_and_matches_events_and_paths() {
+        let hooks = parse_hooks(HOOKS).unwrap();
+        assert_eq!(hooks.len(), 2);
+
+        let photos = &hooks[0];
+        assert_eq!(photos.command, ["echo", "optimizing", "{path}"]);
+        assert!(photos.matches(FileEventKind::Created, "/photos/2024/a.jpg"));
+        assert!(!photos.matches(FileEventKind::Created, "/docs/a.jpg"));
+        assert!(!photos.matches(FileEventKind::Deleted, "/photos/a.jpg"));
+
+        let moves = &hooks[1];
+        assert_eq!(moves.timeout, Duration::from_secs(5));
+        assert!(moves.matches(FileEventKind::Renamed, "/any/where.txt"));
+
+        let args = render(
+            &moves.command,
+            Path::new("/srv/files"),
+            FileEventKind::Renamed,
+            "/b.txt",
+            Some("/a.txt"),
+        );
+        assert_eq!(args, ["echo", "/srv/files/a.txt", "->", "/b.txt"]);
+    }
+
+    #[test]
+    fn rejects_mistakes() {
+        assert!(parse_hooks("command = true").is_err());
+        assert!(parse_hooks("[x]\nevents = uploaded\ncommand = true").is_err());
+        assert!(parse_hooks("[x]\npath = /photos").is_err());
+        assert!(parse_hooks("[x]\ncommand = true\nretries = 3").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn records_output_of_each_run() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let hook =
+            &parse_hooks("[greet]\ncommand = sh -c 'echo hello {relpath}; exit 3'").unwrap()[0];
+        let args = render(
+            &hook.command,
+            tmp.path(),
+            FileEventKind::Created,
+            "/a.txt",
+            None,
+        );
+        let run = execute(hook, &args, tmp.path(), FileEventKind::Created, "/a.txt").await;
+        crate::db::record_hook_run(&pool, &run).await.unwrap();
+
+        let runs = crate::db::list_hook_runs(&pool, 10).await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].hook, "greet");
+        assert_eq!(runs[0].status, "failed");
+        assert_eq!(runs[0].exit_code, Some(3));
+        assert_eq!(runs[0].output, "hello /a.txt\n");
+    }
+}
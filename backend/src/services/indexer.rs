@@ -1,36 +1,144 @@
 use chrono::{DateTime, Utc};
-use ignore::WalkBuilder;
+use serde::Serialize;
 use sqlx::sqlite::SqlitePool;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
+use tokio::task::{JoinError, JoinSet};
 use tracing::{debug, error, info, warn};
 
 use crate::config::Config;
 use crate::db;
-use crate::models::IndexedFileRow;
-use crate::services::metadata::MetadataService;
-use crate::services::search::SearchService;
+use crate::models::{AudioTags, IndexedFileRow};
+use crate::services::backend::StorageBackend;
+use crate::services::exif;
+use crate::services::filesystem::FilesystemService;
+use crate::services::live::LiveUpdates;
+use crate::services::metadata::{MetadataError, MetadataService, PDF_MIME};
+use crate::services::notifications::NotificationService;
+use crate::services::preview::PreviewOptOut;
+use crate::services::root_identity::{self, RootCheck};
+use crate::services::search::{SearchIndexStatus, SearchService};
+use crate::services::storage::StorageGuard;
+use crate::services::webhooks::{EventSource, FileEvent, FileEventKind, WebhookService};
 
 const STATUS_PENDING: &str = "pending";
 const STATUS_COMPLETE: &str = "complete";
 
+const RUN_COMPLETE: &str = "complete";
+const RUN_INCOMPLETE: &str = "incomplete";
+const RUN_FAILED: &str = "failed";
+
+/// Re-check database volume free space every this many scanned entries
+const FREE_SPACE_CHECK_INTERVAL: u64 = 1000;
+
+/// Removing up to this many missing entries is always allowed, so ordinary
+/// deletions in small libraries never trip the percentage limit
+const PURGE_ALWAYS_ALLOWED: usize = 100;
+
+/// Refresh query planner statistics after a run adds, updates, or removes
+/// at least this many entries
+const ANALYZE_AFTER_CHANGES: u64 = 10_000;
+
+/// Whether removing `missing` of `total` indexed entries stays within
+/// `max_percent`
+fn purge_allowed(missing: usize, total: usize, max_percent: u64) -> bool {
+    missing <= PURGE_ALWAYS_ALLOWED
+        || max_percent >= 100
+        || (missing as u64) * 100 <= (total as u64) * max_percent
+}
+
 pub struct IndexerService {
     pool: SqlitePool,
     root: PathBuf,
+    backend: Arc<dyn StorageBackend>,
     is_running: Arc<RwLock<bool>>,
     search_service: Option<Arc<SearchService>>,
+    last_stats: Arc<RwLock<Option<IndexStats>>>,
+    /// Why the root no longer matches the index, while it does not
+    root_mismatch: Arc<RwLock<Option<String>>>,
+    storage: StorageGuard,
+    show_hidden: bool,
+    purge_max_percent: u64,
+    metadata_concurrency: usize,
+    ffprobe_timeout: Duration,
+    webhooks: Option<Arc<WebhookService>>,
+    live: Option<LiveUpdates>,
+    notifications: Option<Arc<NotificationService>>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct IndexStats {
     pub files_scanned: u64,
     pub files_indexed: u64,
     pub files_updated: u64,
     pub files_removed: u64,
     pub files_skipped: u64,
+    /// The missing-file purge was skipped, because the root changed
+    /// identity or too many entries went missing at once
+    pub purge_skipped: bool,
+    /// Why the run did not do all of its work; recorded as `incomplete` in
+    /// the run history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incomplete_reason: Option<String>,
     pub errors: u64,
+    /// Files whose media or document metadata was extracted this run
+    pub metadata_extracted: u64,
+    /// Extractions that failed, including timeouts
+    pub metadata_failed: u64,
+    /// Extractions killed after the configured timeout
+    pub metadata_timed_out: u64,
+    /// Wall-clock time spent in the metadata pass
+    pub metadata_elapsed: Duration,
+}
+
+/// Result of extracting metadata for a single file in the second pass
+enum MetadataOutcome {
+    Extracted,
+    /// Not a media file; marked complete without metadata
+    Skipped,
+    Failed,
+    TimedOut,
+    DbError,
+}
+
+impl MetadataOutcome {
+    fn failed(e: &MetadataError) -> Self {
+        if matches!(e, MetadataError::Timeout) {
+            Self::TimedOut
+        } else {
+            Self::Failed
+        }
+    }
+}
+
+impl IndexStats {
+    /// Entries added, updated, or removed by the run
+    fn rows_changed(&self) -> u64 {
+        self.files_indexed + self.files_updated + self.files_removed
+    }
+
+    fn record_metadata(&mut self, result: Result<MetadataOutcome, JoinError>) {
+        match result {
+            Ok(MetadataOutcome::Extracted) => self.metadata_extracted += 1,
+            Ok(MetadataOutcome::Skipped) => {}
+            Ok(MetadataOutcome::Failed) => {
+                self.metadata_failed += 1;
+                self.errors += 1;
+            }
+            Ok(MetadataOutcome::TimedOut) => {
+                self.metadata_failed += 1;
+                self.metadata_timed_out += 1;
+                self.errors += 1;
+            }
+            Ok(MetadataOutcome::DbError) => self.errors += 1,
+            Err(e) => {
+                debug!("Metadata task failed: {}", e);
+                self.errors += 1;
+            }
+        }
+    }
 }
 
 impl IndexerService {
@@ -42,11 +150,46 @@ impl IndexerService {
         Self {
             pool,
             root: config.root_path.clone(),
+            backend: Arc::new(FilesystemService::new(config.root_path.clone())),
             is_running: Arc::new(RwLock::new(false)),
             search_service,
+            last_stats: Arc::new(RwLock::new(None)),
+            root_mismatch: Arc::new(RwLock::new(None)),
+            storage: StorageGuard::from_config(config),
+            show_hidden: config.show_hidden,
+            purge_max_percent: config.purge_max_percent,
+            metadata_concurrency: config.ffprobe_concurrency.max(1),
+            ffprobe_timeout: Duration::from_secs(config.ffprobe_timeout_secs),
+            webhooks: None,
+            live: None,
+            notifications: None,
         }
     }
 
+    /// Index the tree in `backend` instead of the local root
+    pub fn with_backend(mut self, backend: Arc<dyn StorageBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Report changes found on disk as webhook events
+    pub fn with_webhooks(mut self, webhooks: Arc<WebhookService>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Push changes found on disk to clients watching those directories
+    pub fn with_live_updates(mut self, live: LiveUpdates) -> Self {
+        self.live = Some(live);
+        self
+    }
+
+    /// Alert watch rules when a run fails
+    pub fn with_notifications(mut self, notifications: Arc<NotificationService>) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+
     /// Start the background indexer loop
     pub async fn start_background_loop(self: Arc<Self>, interval_secs: u64) {
         let interval = Duration::from_secs(interval_secs);
@@ -82,6 +225,12 @@ impl IndexerService {
 
     /// Run a full index of all files
     pub async fn run_full_index(&self) -> Result<IndexStats, anyhow::Error> {
+        self.run_index(false).await
+    }
+
+    /// Run a full index. `confirm_purge` lets the run remove missing entries
+    /// even beyond the `FM_PURGE_MAX_PERCENT` limit.
+    pub async fn run_index(&self, confirm_purge: bool) -> Result<IndexStats, anyhow::Error> {
         // Serialize runs to avoid overlapping index passes.
         let mut running = self.is_running.write().await;
         if *running {
@@ -93,11 +242,29 @@ impl IndexerService {
         drop(running);
 
         // Vacuum the database before starting a fresh run to reclaim space and keep pages compact.
-        if let Err(err) = db::vacuum(&self.pool).await {
+        // VACUUM rewrites the whole file, so it is skipped when space is already short.
+        if self.storage.is_low() {
+            warn!("Skipping VACUUM: database volume is below its free-space reserve");
+        } else if let Err(err) = db::vacuum(&self.pool).await {
             warn!("VACUUM before index run failed: {}", err);
         }
 
-        let stats = self.do_index().await;
+        let started_at = Utc::now();
+        let stats = self.do_index(confirm_purge).await;
+        if let Ok(stats) = &stats {
+            *self.last_stats.write().await = Some(stats.clone());
+            // Planner statistics go stale after big changes to the tables
+            if stats.rows_changed() >= ANALYZE_AFTER_CHANGES {
+                info!(
+                    "Running ANALYZE after {} changed entries",
+                    stats.rows_changed()
+                );
+                if let Err(err) = db::analyze(&self.pool).await {
+                    warn!("ANALYZE after index run failed: {}", err);
+                }
+            }
+        }
+        self.record_run(started_at, &stats).await;
 
         // Mark as not running
         let mut running = self.is_running.write().await;
@@ -106,20 +273,31 @@ impl IndexerService {
         stats
     }
 
-    async fn do_index(&self) -> Result<IndexStats, anyhow::Error> {
+    async fn do_index(&self, confirm_purge: bool) -> Result<IndexStats, anyhow::Error> {
         let mut stats = IndexStats::default();
         let mut pending_metadata = Vec::new();
 
         let root = self.root.canonicalize()?;
 
+        self.ensure_free_space()?;
         info!("Starting index of {:?}", root);
 
-        for entry in WalkBuilder::new(&root)
-            .follow_links(false)
-            .hidden(true) // Skip hidden files (starting with .)
-            .add_custom_ignore_filename(".fxignore")
-            .build()
-        {
+        // The first run finds everything new; only later runs report changes
+        let first_run = db::get_last_indexed_at(&self.pool)
+            .await
+            .ok()
+            .flatten()
+            .is_none();
+        let webhooks = self
+            .webhooks
+            .as_deref()
+            .filter(|webhooks| webhooks.is_enabled() && !first_run);
+        let live = self
+            .live
+            .as_ref()
+            .filter(|live| live.has_subscribers() && !first_run);
+
+        for entry in self.backend.walk(self.show_hidden) {
             let entry = match entry {
                 Ok(e) => e,
                 Err(e) => {
@@ -130,65 +308,58 @@ impl IndexerService {
             };
 
             stats.files_scanned += 1;
+            if stats.files_scanned % FREE_SPACE_CHECK_INTERVAL == 0 {
+                self.ensure_free_space()?;
+            }
 
-            let path = entry.path();
-            let metadata = match entry.metadata() {
-                Ok(m) => m,
-                Err(e) => {
-                    debug!("Metadata error for {:?}: {}", path, e);
-                    stats.errors += 1;
-                    continue;
-                }
-            };
-
-            // Build relative path
-            let relative_path = path
-                .strip_prefix(&root)
-                .map(|p| format!("/{}", p.display()))
-                .unwrap_or_else(|_| "/".to_string());
-
-            let name = path
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default();
+            let relative_path = entry.path.clone();
 
             // Compute current filesystem size and mtime for change detection
-            let fs_size = if metadata.is_file() {
-                Some(metadata.len() as i64)
-            } else {
-                None
-            };
-            let fs_modified = metadata
-                .modified()
-                .ok()
-                .map(|t| DateTime::<Utc>::from(t).to_rfc3339());
+            let fs_size = entry.size.map(|size| size as i64);
+            let fs_modified = entry.modified.map(|t| t.to_rfc3339());
 
-            let mime_type = if metadata.is_file() {
-                mime_guess::from_path(path).first().map(|m| m.to_string())
-            } else {
-                None
-            };
-
-            let metadata_status = if metadata.is_file() {
+            let metadata_status = if entry.is_file {
                 STATUS_PENDING
             } else {
                 STATUS_COMPLETE
             };
 
             // Check if file is unchanged (skip expensive FFprobe extraction)
-            if let Ok(Some((db_size, db_modified, db_status))) =
-                db::get_file_by_path(&self.pool, &relative_path).await
+            let existing = db::get_file_by_path(&self.pool, &relative_path).await;
+            if let Ok(Some((db_size, db_modified, db_status, db_mime_type))) = &existing
+                && *db_size == fs_size
+                && *db_modified == fs_modified
             {
-                if db_size == fs_size && db_modified == fs_modified {
-                    stats.files_skipped += 1;
+                stats.files_skipped += 1;
 
-                    // If media metadata is not complete yet, queue for second pass
-                    if metadata.is_file() && db_status != STATUS_COMPLETE {
-                        pending_metadata.push((relative_path, path.to_path_buf(), mime_type));
-                    }
-                    continue;
+                // If media metadata is not complete yet, queue for second pass
+                // with the type found when the file was indexed, sniffing
+                // only rows indexed before types were sniffed
+                if entry.is_file
+                    && db_status != STATUS_COMPLETE
+                    && let Some(local_path) = entry.local_path
+                {
+                    let mime_type = db_mime_type
+                        .clone()
+                        .or_else(|| self.mime_type_of(&relative_path));
+                    pending_metadata.push((relative_path, local_path, mime_type));
                 }
+                continue;
             }
+            // Only changed files are sniffed, since files without a known
+            // extension are opened for it
+            let mime_type = if entry.is_file {
+                self.mime_type_of(&relative_path)
+            } else {
+                None
+            };
+            // A directory's mtime changes with its contents, which are
+            // reported themselves
+            let event = match &existing {
+                Ok(None) => Some(FileEventKind::Created),
+                Ok(Some(_)) if entry.is_file => Some(FileEventKind::Modified),
+                _ => None,
+            };
 
             // Reset metadata for changed files; fill in second pass
             let (width, height, duration) = (None, None, None);
@@ -196,13 +367,10 @@ impl IndexerService {
             let indexed_file = IndexedFileRow {
                 id: 0, // Will be set by DB
                 path: relative_path,
-                name,
-                is_dir: metadata.is_dir(),
+                name: entry.name,
+                is_dir: entry.is_dir,
                 size: fs_size,
-                created_at: metadata
-                    .created()
-                    .ok()
-                    .map(|t| DateTime::<Utc>::from(t).to_rfc3339()),
+                created_at: entry.created.map(|t| t.to_rfc3339()),
                 modified_at: fs_modified,
                 mime_type,
                 width,
@@ -210,19 +378,41 @@ impl IndexerService {
                 duration,
                 metadata_status: metadata_status.to_string(),
                 indexed_at: String::new(), // Set by DB
+                artist: None,
+                album: None,
+                title: None,
+                track_number: None,
+                page_count: None,
+                author: None,
+                original_name: None,
             };
 
             if let Err(e) = db::upsert_file(&self.pool, &indexed_file).await {
-                debug!("DB error for {:?}: {}", path, e);
+                debug!("DB error for {}: {}", indexed_file.path, e);
                 stats.errors += 1;
                 continue;
             }
+            if let (Some(webhooks), Some(event)) = (webhooks, event) {
+                webhooks.indexer_event(event, &indexed_file.path, Some(indexed_file.is_dir));
+            }
+            if let (Some(live), Some(event)) = (live, event) {
+                live.publish(FileEvent::new(
+                    event,
+                    &indexed_file.path,
+                    None,
+                    Some(indexed_file.is_dir),
+                    EventSource::Indexer,
+                ));
+            }
 
-            // Queue media files for second pass metadata extraction
-            if metadata.is_file() && metadata_status == STATUS_PENDING {
+            // Queue media files for second pass metadata extraction, which
+            // needs a local file to open; others stay pending
+            if metadata_status == STATUS_PENDING
+                && let Some(local_path) = entry.local_path
+            {
                 pending_metadata.push((
                     indexed_file.path.clone(),
-                    path.to_path_buf(),
+                    local_path,
                     indexed_file.mime_type.clone(),
                 ));
             }
@@ -230,32 +420,70 @@ impl IndexerService {
             stats.files_indexed += 1;
         }
 
-        let indexed_paths = db::list_indexed_paths(&self.pool).await?;
-        let mut missing_paths = Vec::new();
-        for indexed_path in indexed_paths {
-            let abs_path = if indexed_path == "/" {
-                root.clone()
+        // Everything looks missing when the root is the wrong directory, so
+        // only purge when it is still the one the index was built from
+        // A walk cut short (mount dropped mid-scan) also makes many entries
+        // look missing; large purges wait for confirmation
+        if self.verify_root().await {
+            let (missing_paths, indexed_count) = self.find_missing_paths().await?;
+            if !confirm_purge
+                && !purge_allowed(missing_paths.len(), indexed_count, self.purge_max_percent)
+            {
+                let reason = format!(
+                    "{} of {} indexed entries are missing, more than FM_PURGE_MAX_PERCENT ({}%); \
+                     trigger an index with confirm_purge=true to remove them",
+                    missing_paths.len(),
+                    indexed_count,
+                    self.purge_max_percent
+                );
+                error!("Not removing missing files: {}", reason);
+                stats.purge_skipped = true;
+                stats.incomplete_reason = Some(reason);
             } else {
-                root.join(indexed_path.trim_start_matches('/'))
-            };
-            match std::fs::metadata(&abs_path) {
-                Ok(_) => {}
-                Err(err) => {
-                    if err.kind() == std::io::ErrorKind::NotFound {
-                        missing_paths.push(indexed_path);
-                    } else {
-                        debug!("Metadata check failed for {:?}: {}", abs_path, err);
+                match db::delete_by_paths(&self.pool, &missing_paths).await {
+                    Ok(removed) => {
+                        stats.files_removed = removed;
+                        if let Some(webhooks) = webhooks {
+                            for path in &missing_paths {
+                                webhooks.indexer_event(FileEventKind::Deleted, path, None);
+                            }
+                        }
+                        if let Some(live) = live {
+                            for path in &missing_paths {
+                                live.publish(FileEvent::new(
+                                    FileEventKind::Deleted,
+                                    path,
+                                    None,
+                                    None,
+                                    EventSource::Indexer,
+                                ));
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        debug!("Cleanup error: {}", e);
+                        stats.errors += 1;
                     }
                 }
             }
+        } else {
+            stats.purge_skipped = true;
+            stats.incomplete_reason = self
+                .root_mismatch()
+                .await
+                .map(|reason| format!("root does not match the index: {reason}"));
         }
 
-        match db::delete_by_paths(&self.pool, &missing_paths).await {
-            Ok(removed) => stats.files_removed = removed,
-            Err(e) => {
-                debug!("Cleanup error: {}", e);
-                stats.errors += 1;
-            }
+        // Files under a `.fxnopreview` marker stay pending and are picked up
+        // again if the marker is removed
+        let mut opt_out = PreviewOptOut::new(root.clone());
+        let queued = pending_metadata.len();
+        pending_metadata.retain(|(_, abs_path, _)| !opt_out.is_disabled(abs_path));
+        if pending_metadata.len() < queued {
+            debug!(
+                "Skipping metadata for {} files in preview opt-out directories",
+                queued - pending_metadata.len()
+            );
         }
 
         info!(
@@ -263,67 +491,290 @@ impl IndexerService {
             pending_metadata.len()
         );
 
-        // Second pass: fill media metadata for pending files
+        // Second pass: fill media metadata for pending files. Extractions run
+        // concurrently, bounded by the semaphore; a permit is taken before each
+        // spawn so at most `metadata_concurrency` tasks exist at once.
+        self.ensure_free_space()?;
+        let metadata_started = Instant::now();
+        let semaphore = Arc::new(Semaphore::new(self.metadata_concurrency));
+        let mut tasks = JoinSet::new();
+
         for (relative_path, abs_path, mime_type) in pending_metadata {
-            let is_image = mime_type
-                .as_ref()
-                .map(|m| m.starts_with("image/"))
-                .unwrap_or(false);
-
-            match MetadataService::extract(&abs_path).await {
-                Ok(media_meta) => {
-                    let width = media_meta.width.map(|w| w as i32);
-                    let height = media_meta.height.map(|h| h as i32);
-                    let duration = if is_image { None } else { media_meta.duration };
-
-                    if let Err(e) = db::update_media_metadata(
-                        &self.pool,
-                        &relative_path,
+            let permit = semaphore.clone().acquire_owned().await?;
+            let pool = self.pool.clone();
+            let timeout = self.ffprobe_timeout;
+            tasks.spawn(async move {
+                let outcome =
+                    Self::extract_metadata(&pool, &relative_path, &abs_path, mime_type, timeout)
+                        .await;
+                drop(permit);
+                outcome
+            });
+
+            while let Some(result) = tasks.try_join_next() {
+                stats.record_metadata(result);
+            }
+        }
+        while let Some(result) = tasks.join_next().await {
+            stats.record_metadata(result);
+        }
+        stats.metadata_elapsed = metadata_started.elapsed();
+
+        info!(
+            "Metadata pass complete: {} extracted, {} failed, {} timed out, {:.3} seconds (concurrency {})",
+            stats.metadata_extracted,
+            stats.metadata_failed,
+            stats.metadata_timed_out,
+            stats.metadata_elapsed.as_secs_f64(),
+            self.metadata_concurrency
+        );
+
+        // Rebuild search index after successful indexing
+        if let Some(search) = &self.search_service {
+            info!("Rebuilding search index");
+            if let Err(e) = search.rebuild_from_db(&self.pool).await {
+                warn!("Failed to rebuild search index: {}", e);
+                stats.errors += 1;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Indexed paths that no longer exist in the backend, and how many
+    /// paths are indexed in total
+    async fn find_missing_paths(&self) -> Result<(Vec<String>, usize), anyhow::Error> {
+        let indexed_paths = db::list_indexed_paths(&self.pool).await?;
+        let indexed_count = indexed_paths.len();
+        let mut missing_paths = Vec::new();
+        for indexed_path in indexed_paths {
+            match self.backend.exists(&indexed_path) {
+                Ok(true) => {}
+                Ok(false) => missing_paths.push(indexed_path),
+                Err(err) => debug!("Existence check failed for {}: {}", indexed_path, err),
+            }
+        }
+
+        Ok((missing_paths, indexed_count))
+    }
+
+    /// MIME type of the entry at `path`, sniffed from its contents when the
+    /// name does not tell
+    fn mime_type_of(&self, path: &str) -> Option<String> {
+        self.backend.get_entry(path).ok()?.mime_type
+    }
+
+    /// Add the run to the history; failing to record it is only logged
+    async fn record_run(
+        &self,
+        started_at: DateTime<Utc>,
+        result: &Result<IndexStats, anyhow::Error>,
+    ) {
+        let (status, note, stats) = match result {
+            Ok(stats) => match &stats.incomplete_reason {
+                Some(reason) => (RUN_INCOMPLETE, Some(reason.clone()), stats.clone()),
+                None => (RUN_COMPLETE, None, stats.clone()),
+            },
+            Err(e) => (RUN_FAILED, Some(e.to_string()), IndexStats::default()),
+        };
+        if let (Err(e), Some(notifications)) = (result, &self.notifications) {
+            notifications.index_failed(&e.to_string());
+        }
+        let run = db::IndexRunRow {
+            id: 0, // Set by DB
+            started_at: started_at.to_rfc3339(),
+            finished_at: Utc::now().to_rfc3339(),
+            status: status.to_string(),
+            files_scanned: stats.files_scanned as i64,
+            files_indexed: stats.files_indexed as i64,
+            files_removed: stats.files_removed as i64,
+            errors: stats.errors as i64,
+            note,
+        };
+        if let Err(e) = db::record_index_run(&self.pool, &run).await {
+            warn!("Failed to record index run: {}", e);
+        }
+    }
+
+    /// Check the root against the fingerprint stored with the index and
+    /// remember any mismatch. Returns whether the root may be purged.
+    pub async fn verify_root(&self) -> bool {
+        let mismatch = match root_identity::verify(&self.pool, &self.root).await {
+            Ok(RootCheck::Adopted) => {
+                info!("Recorded root fingerprint for {:?}", self.root);
+                None
+            }
+            Ok(RootCheck::Matches) => None,
+            Ok(RootCheck::Mismatch(reason)) => {
+                error!(
+                    "Root {:?} does not match the index ({}); not removing missing files. Accept the new root with POST /api/index/accept-root if this is intended",
+                    self.root, reason
+                );
+                Some(reason)
+            }
+            Err(e) => {
+                error!("Root fingerprint check failed: {}", e);
+                Some(format!("fingerprint check failed: {e}"))
+            }
+        };
+        let ok = mismatch.is_none();
+        *self.root_mismatch.write().await = mismatch;
+        ok
+    }
+
+    /// Why the root does not match the index, as of the last check
+    pub async fn root_mismatch(&self) -> Option<String> {
+        self.root_mismatch.read().await.clone()
+    }
+
+    /// Treat the current root as the one the index belongs to, allowing the
+    /// next run to purge entries missing from it
+    pub async fn accept_root(&self) -> Result<(), anyhow::Error> {
+        root_identity::adopt(&self.pool, &self.root).await?;
+        *self.root_mismatch.write().await = None;
+        info!("Accepted {:?} as the index root", self.root);
+        Ok(())
+    }
+
+    /// Stop the run before writing if the database volume is below its reserve
+    fn ensure_free_space(&self) -> Result<(), anyhow::Error> {
+        let status = self.storage.status();
+        if status.low {
+            error!(
+                "Database volume {:?} has {} bytes free, below the {} byte reserve (FM_DB_MIN_FREE_MB); indexing stopped",
+                status.path,
+                status.available_bytes.unwrap_or(0),
+                status.min_free_bytes
+            );
+            anyhow::bail!("insufficient free space on database volume");
+        }
+        Ok(())
+    }
+
+    /// Extract and store metadata for one pending file
+    async fn extract_metadata(
+        pool: &SqlitePool,
+        relative_path: &str,
+        abs_path: &Path,
+        mime_type: Option<String>,
+        timeout: Duration,
+    ) -> MetadataOutcome {
+        if mime_type.as_deref() == Some(PDF_MIME) {
+            return Self::extract_document_metadata(pool, relative_path, abs_path).await;
+        }
+
+        let is_image = mime_type
+            .as_ref()
+            .map(|m| m.starts_with("image/"))
+            .unwrap_or(false);
+        let is_audio = mime_type
+            .as_ref()
+            .map(|m| m.starts_with("audio/"))
+            .unwrap_or(false);
+
+        let extracted = MetadataService::extract(abs_path, mime_type.as_deref(), timeout).await;
+        let (update, outcome) = match extracted {
+            Ok(media_meta) => {
+                let width = media_meta.width.map(|w| w as i32);
+                let height = media_meta.height.map(|h| h as i32);
+                let duration = if is_image { None } else { media_meta.duration };
+                let tags = if is_audio {
+                    media_meta.tags
+                } else {
+                    AudioTags::default()
+                };
+                // ffprobe does not read EXIF, which is where photos keep these
+                let exif = if is_image {
+                    exif::read(abs_path).await
+                } else {
+                    exif::Exif::default()
+                };
+                let captured_at = exif.taken_at.or(media_meta.captured_at);
+                let location = exif.location.or(media_meta.location);
+                (
+                    db::update_media_metadata(
+                        pool,
+                        relative_path,
                         width,
                         height,
                         duration,
+                        &tags,
+                        captured_at,
+                        location,
                         STATUS_COMPLETE,
                     )
-                    .await
-                    {
-                        debug!("DB update error for {:?}: {}", abs_path, e);
-                        stats.errors += 1;
-                    }
-                }
-                Err(crate::services::metadata::MetadataError::NotMediaFile) => {
-                    // Mark as complete so we don't retry on non-media files
-                    if let Err(e) = db::update_media_metadata(
-                        &self.pool,
-                        &relative_path,
+                    .await,
+                    MetadataOutcome::Extracted,
+                )
+            }
+            Err(MetadataError::NotMediaFile) => {
+                // Mark as complete so we don't retry on non-media files
+                (
+                    db::update_media_metadata(
+                        pool,
+                        relative_path,
+                        None,
                         None,
                         None,
+                        &AudioTags::default(),
+                        None,
                         None,
                         STATUS_COMPLETE,
                     )
-                    .await
-                    {
-                        debug!("DB update error for {:?}: {}", abs_path, e);
-                        stats.errors += 1;
-                    }
-                }
-                Err(e) => {
-                    debug!("Metadata extraction error for {:?}: {}", abs_path, e);
-                    stats.errors += 1;
-                    // Leave metadata_status as pending so future runs can retry
-                }
+                    .await,
+                    MetadataOutcome::Skipped,
+                )
+            }
+            Err(e) => {
+                debug!("Metadata extraction error for {:?}: {}", abs_path, e);
+                // Leave metadata_status as pending so future runs can retry
+                return MetadataOutcome::failed(&e);
             }
+        };
+
+        if let Err(e) = update {
+            debug!("DB update error for {:?}: {}", abs_path, e);
+            return MetadataOutcome::DbError;
         }
+        outcome
+    }
 
-        // Rebuild search index after successful indexing
-        if let Some(search) = &self.search_service {
-            info!("Rebuilding search index");
-            if let Err(e) = search.rebuild_from_db(&self.pool).await {
-                warn!("Failed to rebuild search index: {}", e);
-                stats.errors += 1;
+    /// Fill page count, title, and author for a pending PDF
+    async fn extract_document_metadata(
+        pool: &SqlitePool,
+        relative_path: &str,
+        abs_path: &Path,
+    ) -> MetadataOutcome {
+        match MetadataService::extract_pdf(abs_path).await {
+            Ok(document) => {
+                if let Err(e) =
+                    db::update_document_metadata(pool, relative_path, &document, STATUS_COMPLETE)
+                        .await
+                {
+                    debug!("DB update error for {:?}: {}", abs_path, e);
+                    return MetadataOutcome::DbError;
+                }
+                MetadataOutcome::Extracted
+            }
+            Err(e) => {
+                debug!("PDF metadata extraction error for {:?}: {}", abs_path, e);
+                // Leave metadata_status as pending so future runs can retry
+                MetadataOutcome::failed(&e)
             }
         }
+    }
 
-        Ok(stats)
+    /// Size and memory use of the search index this indexer refreshes
+    pub async fn search_index_status(&self) -> Option<SearchIndexStatus> {
+        match &self.search_service {
+            Some(search) => Some(search.status().await),
+            None => None,
+        }
+    }
+
+    /// Database the index (and its run history) lives in
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
     }
 
     /// Check if indexer is currently running
@@ -331,6 +782,11 @@ impl IndexerService {
         *self.is_running.read().await
     }
 
+    /// Stats from the most recent completed run, if any since startup
+    pub async fn last_stats(&self) -> Option<IndexStats> {
+        self.last_stats.read().await.clone()
+    }
+
     #[cfg(test)]
     pub async fn set_running_for_test(&self, running: bool) {
         let mut guard = self.is_running.write().await;
@@ -345,20 +801,57 @@ mod tests {
     use sqlx::sqlite::SqlitePoolOptions;
     use tempfile::tempdir;
 
-    fn test_config(root: &PathBuf) -> Config {
+    fn test_config(root: &std::path::Path) -> Config {
         Config {
-            root_path: root.clone(),
+            root_path: root.to_path_buf(),
             host: "127.0.0.1".to_string(),
             port: 0,
             database_path: root.join("filex.db"),
+            db_min_free_mb: 0,
+            root_min_free_mb: 0,
+            db_check_interval_secs: 0,
             enable_indexer: false,
             index_interval_secs: 0,
-            static_path: root.clone(),
+            purge_max_percent: 25,
+            show_hidden: false,
+            case_insensitive_paths: false,
+            search_index_max_mb: 0,
+            search_transliterate: false,
+            search_fold_kana: false,
+            ffprobe_concurrency: 2,
+            ffprobe_timeout_secs: 15,
+            webhook_urls: Vec::new(),
+            webhook_secret: None,
+            hooks_file: None,
+            notify_file: None,
+            templates_dir: None,
+            rclone_path: None,
+            clamd_socket: None,
+            quarantine_dir: None,
+            clamd_max_mb: 100,
+            dedup_store: None,
+            allow_links: false,
+            allow_permission_changes: false,
+            download_rate_limit_kib: 0,
+            download_rate_limit_total_kib: 0,
+            readahead_mb: 0,
+            max_downloads: 0,
+            max_downloads_per_session: 0,
+            max_uploads: 0,
+            max_uploads_per_session: 0,
+            request_timeout_secs: 0,
+            transfer_timeout_secs: 0,
+            body_timeout_secs: 0,
+            static_path: root.to_path_buf(),
             auth: AuthConfig {
                 enabled: false,
                 password: None,
                 session_timeout_secs: 0,
                 cookie_name: "test".to_string(),
+                oidc: None,
+                proxy_header: None,
+                trusted_proxies: Vec::new(),
+                admin_users: Vec::new(),
             },
         }
     }
@@ -393,6 +886,10 @@ mod tests {
         assert!(stats.files_indexed >= 3);
         assert_eq!(stats.files_removed, 1);
         assert_eq!(stats.errors, 0);
+        assert_eq!(
+            indexer.last_stats().await.map(|s| s.files_scanned),
+            Some(stats.files_scanned)
+        );
 
         let rows: Vec<(String, String, String)> =
             sqlx::query_as("SELECT path, name, metadata_status FROM indexed_files WHERE path = ?")
@@ -415,6 +912,192 @@ mod tests {
         assert!(stale.is_none());
     }
 
+    #[tokio::test]
+    async fn run_full_index_walks_the_storage_backend() {
+        use crate::services::MemoryBackend;
+
+        let tmp = tempdir().unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let memory = Arc::new(MemoryBackend::new());
+        memory.create_directory("/docs").unwrap();
+        memory.write_file("/docs/a.txt", b"hello").unwrap();
+        memory.write_file("/docs/.draft", b"").unwrap();
+        let indexer = IndexerService::new(pool.clone(), &test_config(tmp.path()), None)
+            .with_backend(memory.clone());
+
+        let stats = indexer.run_full_index().await.unwrap();
+        assert_eq!((stats.files_scanned, stats.errors), (3, 0));
+        // Nothing on disk for ffprobe to open, so metadata stays pending
+        let row: (i64, String, Option<String>) = sqlx::query_as(
+            "SELECT size, metadata_status, mime_type FROM indexed_files WHERE path = ?",
+        )
+        .bind("/docs/a.txt")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            row,
+            (
+                5,
+                STATUS_PENDING.to_string(),
+                Some("text/plain".to_string())
+            )
+        );
+
+        memory.delete("/docs/a.txt").unwrap();
+        let stats = indexer.run_full_index().await.unwrap();
+        assert_eq!(stats.files_removed, 1);
+    }
+
+    #[tokio::test]
+    async fn run_full_index_keeps_entries_when_root_changed_identity() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("file.txt"), b"hello").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let indexer = IndexerService::new(pool.clone(), &test_config(&root), None);
+        indexer.run_full_index().await.unwrap();
+
+        // Simulate the disk not being mounted: an empty directory in its place
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&root).unwrap();
+
+        let stats = indexer.run_full_index().await.unwrap();
+        assert!(stats.purge_skipped);
+        assert_eq!(stats.files_removed, 0);
+        assert!(indexer.root_mismatch().await.is_some());
+        let kept: Option<(i64,)> =
+            sqlx::query_as("SELECT id FROM indexed_files WHERE path = '/file.txt'")
+                .fetch_optional(&pool)
+                .await
+                .unwrap();
+        assert!(kept.is_some());
+
+        indexer.accept_root().await.unwrap();
+        assert!(indexer.root_mismatch().await.is_none());
+        let stats = indexer.run_full_index().await.unwrap();
+        assert!(!stats.purge_skipped);
+        assert_eq!(stats.files_removed, 1);
+    }
+
+    #[test]
+    fn purge_allowed_applies_percentage_above_floor() {
+        assert!(purge_allowed(
+            PURGE_ALWAYS_ALLOWED,
+            PURGE_ALWAYS_ALLOWED,
+            25
+        ));
+        assert!(purge_allowed(250, 1000, 25));
+        assert!(!purge_allowed(251, 1000, 25));
+        assert!(purge_allowed(1000, 1000, 100));
+        assert!(!purge_allowed(101, 1000, 0));
+    }
+
+    #[tokio::test]
+    async fn run_full_index_holds_mass_purge_until_confirmed() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("kept.txt"), b"hello").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let indexer = IndexerService::new(pool.clone(), &test_config(&root), None);
+        indexer.run_full_index().await.unwrap();
+
+        // More stale rows than the floor, all of them missing from disk
+        for i in 0..=PURGE_ALWAYS_ALLOWED {
+            sqlx::query("INSERT INTO indexed_files (path, name, is_dir) VALUES (?, ?, 0)")
+                .bind(format!("/gone-{i}.txt"))
+                .bind(format!("gone-{i}.txt"))
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let stats = indexer.run_full_index().await.unwrap();
+        assert!(stats.purge_skipped);
+        assert!(stats.incomplete_reason.is_some());
+        assert_eq!(stats.files_removed, 0);
+
+        let runs = crate::db::list_index_runs(&pool, 10).await.unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].status, RUN_INCOMPLETE);
+        assert!(runs[0].note.is_some());
+        assert_eq!(runs[1].status, RUN_COMPLETE);
+
+        let stats = indexer.run_index(true).await.unwrap();
+        assert!(!stats.purge_skipped);
+        assert_eq!(stats.files_removed, PURGE_ALWAYS_ALLOWED as u64 + 1);
+    }
+
+    #[test]
+    fn record_metadata_tallies_outcomes() {
+        let mut stats = IndexStats::default();
+        for outcome in [
+            MetadataOutcome::Extracted,
+            MetadataOutcome::Extracted,
+            MetadataOutcome::Skipped,
+            MetadataOutcome::failed(&MetadataError::Timeout),
+            MetadataOutcome::failed(&MetadataError::FfprobeNotFound),
+            MetadataOutcome::DbError,
+        ] {
+            stats.record_metadata(Ok(outcome));
+        }
+
+        assert_eq!(stats.metadata_extracted, 2);
+        assert_eq!(stats.metadata_failed, 2);
+        assert_eq!(stats.metadata_timed_out, 1);
+        assert_eq!(stats.errors, 3);
+    }
+
+    #[tokio::test]
+    async fn run_full_index_refuses_to_write_below_free_space_reserve() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("file.txt"), b"hello").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let mut config = test_config(&root);
+        config.db_min_free_mb = u64::MAX / (1024 * 1024);
+        let indexer = IndexerService::new(pool.clone(), &config, None);
+
+        assert!(indexer.run_full_index().await.is_err());
+        assert!(!indexer.is_running().await);
+
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM indexed_files")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
     #[tokio::test]
     async fn run_full_index_returns_early_when_already_running() {
         let tmp = tempdir().unwrap();
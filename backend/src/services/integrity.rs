@@ -0,0 +1,407 @@
+//! Periodic SQLite integrity checks. Each passing check refreshes a
+//! known-good backup next to the database; a failing check schedules that
+//! backup to be restored on the next start, before the pool is opened.
+//!
+//! The same machinery serves manual backups: snapshots are written with
+//! `VACUUM INTO` so they are consistent while the server runs, and an
+//! uploaded snapshot is validated and scheduled like a backup restore.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::db;
+use crate::services::storage::StorageGuard;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityReport {
+    pub checked_at: DateTime<Utc>,
+    pub ok: bool,
+    /// Problems reported by `PRAGMA integrity_check`; empty when `ok`
+    pub messages: Vec<String>,
+    /// When the last known-good backup was written
+    pub backup_at: Option<DateTime<Utc>>,
+    /// A restore from backup will happen on the next start
+    pub restore_scheduled: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("Database volume is below its free-space reserve")]
+    LowStorage,
+    #[error("Not a usable filex database: {0}")]
+    Invalid(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Contents of the restore marker when an uploaded snapshot, rather than a
+/// failed check, scheduled the restore
+const REQUESTED_RESTORE: &[u8] = b"requested";
+
+pub struct IntegrityService {
+    pool: SqlitePool,
+    database_path: PathBuf,
+    storage: StorageGuard,
+    last_report: Arc<RwLock<Option<IntegrityReport>>>,
+}
+
+impl IntegrityService {
+    pub fn new(pool: SqlitePool, config: &Config) -> Self {
+        Self {
+            pool,
+            database_path: config.database_path.clone(),
+            storage: StorageGuard::from_config(config),
+            last_report: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub async fn last_report(&self) -> Option<IntegrityReport> {
+        self.last_report.read().await.clone()
+    }
+
+    /// Check once at startup, then every `interval_secs`
+    pub async fn start_background_loop(self: Arc<Self>, interval_secs: u64) {
+        let interval = Duration::from_secs(interval_secs);
+        info!(
+            "Starting database integrity checks with {}s interval",
+            interval_secs
+        );
+
+        loop {
+            self.run_check().await;
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Run `PRAGMA integrity_check`, then back up on success or schedule a
+    /// restore on failure
+    pub async fn run_check(&self) -> IntegrityReport {
+        let messages = db::integrity_check(&self.pool)
+            .await
+            .unwrap_or_else(|e| vec![e.to_string()]);
+        let ok = messages.is_empty();
+        let backup = backup_path(&self.database_path);
+
+        let mut restore_scheduled = restore_marker_path(&self.database_path).exists();
+        if ok {
+            // A scheduled restore reads the backup, so leave it alone until then
+            if !restore_scheduled {
+                self.write_backup(&backup).await;
+            }
+        } else {
+            error!(
+                "Database integrity check FAILED for {:?}: {}",
+                self.database_path,
+                messages.join("; ")
+            );
+            if backup.exists() {
+                match std::fs::write(restore_marker_path(&self.database_path), b"") {
+                    Ok(()) => {
+                        restore_scheduled = true;
+                        error!(
+                            "Restore from {:?} scheduled; restart filex to apply it",
+                            backup
+                        );
+                    }
+                    Err(e) => error!("Failed to schedule database restore: {}", e),
+                }
+            } else {
+                error!("No database backup is available to restore from");
+            }
+        }
+
+        let report = IntegrityReport {
+            checked_at: Utc::now(),
+            ok,
+            messages,
+            backup_at: std::fs::metadata(&backup)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from),
+            restore_scheduled,
+        };
+        *self.last_report.write().await = Some(report.clone());
+        report
+    }
+
+    /// Write a consistent copy with `VACUUM INTO`, replacing the previous
+    /// backup only once the new one is complete
+    async fn write_backup(&self, backup: &Path) {
+        if self.storage.is_low() {
+            warn!("Skipping database backup: database volume is below its free-space reserve");
+            return;
+        }
+
+        let staging = backup.with_extension("bak.tmp");
+        match self.vacuum_into(&staging).await {
+            Ok(()) => match std::fs::rename(&staging, backup) {
+                Ok(()) => info!("Database integrity ok; backup written to {:?}", backup),
+                Err(e) => warn!("Failed to replace database backup: {}", e),
+            },
+            Err(e) => warn!("Database backup failed: {}", e),
+        }
+    }
+
+    async fn vacuum_into(&self, target: &Path) -> Result<(), sqlx::Error> {
+        let _ = std::fs::remove_file(target);
+        let result = sqlx::query("VACUUM INTO ?")
+            .bind(target.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await;
+        if result.is_err() {
+            let _ = std::fs::remove_file(target);
+        }
+        result.map(|_| ())
+    }
+
+    /// Write a consistent snapshot of the live database and open it for
+    /// reading. The snapshot is unlinked once open, so it goes away with the
+    /// handle (on platforms that allow removing open files).
+    pub async fn snapshot(&self) -> Result<tokio::fs::File, BackupError> {
+        if self.storage.is_low() {
+            return Err(BackupError::LowStorage);
+        }
+
+        let target = append_extension(
+            &self.database_path,
+            &format!("snapshot-{}.tmp", uuid::Uuid::new_v4()),
+        );
+        self.vacuum_into(&target).await?;
+        let file = tokio::fs::File::open(&target).await;
+        if let Err(e) = std::fs::remove_file(&target) {
+            warn!("Failed to remove database snapshot {:?}: {}", target, e);
+        }
+        Ok(file?)
+    }
+
+    /// Where an uploaded snapshot is written before `schedule_restore`
+    /// checks it
+    pub fn upload_path(&self) -> PathBuf {
+        append_extension(&self.database_path, "upload.tmp")
+    }
+
+    /// Check that `upload` is an intact filex database, then make it the
+    /// backup restored on the next start. The upload is removed if rejected.
+    pub async fn schedule_restore(&self, upload: &Path) -> Result<(), BackupError> {
+        if let Err(e) = validate_snapshot(upload).await {
+            let _ = std::fs::remove_file(upload);
+            return Err(e);
+        }
+
+        std::fs::rename(upload, backup_path(&self.database_path))?;
+        std::fs::write(restore_marker_path(&self.database_path), REQUESTED_RESTORE)?;
+        warn!("Database restore from an uploaded snapshot scheduled; restart filex to apply it");
+        if let Some(report) = self.last_report.write().await.as_mut() {
+            report.restore_scheduled = true;
+        }
+        Ok(())
+    }
+}
+
+async fn validate_snapshot(path: &Path) -> Result<(), BackupError> {
+    let invalid = |e: sqlx::Error| BackupError::Invalid(e.to_string());
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(&format!("sqlite:{}?mode=ro", path.display()))
+        .await
+        .map_err(invalid)?;
+
+    let result = async {
+        let rows = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+            .fetch_all(&pool)
+            .await
+            .map_err(invalid)?;
+        if rows.len() != 1 || rows[0] != "ok" {
+            return Err(BackupError::Invalid(rows.join("; ")));
+        }
+        sqlx::query("SELECT 1 FROM indexed_files LIMIT 1")
+            .fetch_optional(&pool)
+            .await
+            .map_err(invalid)?;
+        Ok(())
+    }
+    .await;
+    pool.close().await;
+    result
+}
+
+pub fn backup_path(database_path: &Path) -> PathBuf {
+    append_extension(database_path, "bak")
+}
+
+fn restore_marker_path(database_path: &Path) -> PathBuf {
+    append_extension(database_path, "restore")
+}
+
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Apply a restore scheduled by a failed check or an uploaded snapshot. The
+/// previous database is kept as `<db>.corrupt-<timestamp>` (or
+/// `<db>.replaced-<timestamp>` after an upload) for inspection. Must run before the pool is
+/// opened. Returns whether a restore happened.
+pub fn restore_if_scheduled(database_path: &Path) -> std::io::Result<bool> {
+    let marker = restore_marker_path(database_path);
+    if !marker.exists() {
+        return Ok(false);
+    }
+
+    let backup = backup_path(database_path);
+    if !backup.exists() {
+        warn!("Database restore was scheduled but {:?} is missing", backup);
+        std::fs::remove_file(&marker)?;
+        return Ok(false);
+    }
+
+    if database_path.exists() {
+        let stamp = Utc::now().format("%Y%m%d%H%M%S");
+        let kind = if std::fs::read(&marker)? == REQUESTED_RESTORE {
+            "replaced"
+        } else {
+            "corrupt"
+        };
+        let previous = append_extension(database_path, &format!("{kind}-{stamp}"));
+        std::fs::rename(database_path, &previous)?;
+        warn!("Moved previous database to {:?}", previous);
+    }
+    // Stale WAL pages belong to the previous file and must not be replayed
+    for suffix in ["wal", "shm"] {
+        let _ = std::fs::remove_file(append_extension(database_path, suffix));
+    }
+
+    std::fs::copy(&backup, database_path)?;
+    std::fs::remove_file(&marker)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn passing_check_writes_backup_that_restore_can_apply() {
+        let tmp = tempdir().unwrap();
+        let db_path = tmp.path().join("filex.db");
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!("sqlite:{}?mode=rwc", db_path.display()))
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        let mut config = Config::from_env();
+        config.database_path = db_path.clone();
+        config.db_min_free_mb = 0;
+        let service = IntegrityService::new(pool.clone(), &config);
+
+        let report = service.run_check().await;
+        assert!(report.ok);
+        assert!(report.backup_at.is_some());
+        assert!(!report.restore_scheduled);
+        assert!(backup_path(&db_path).exists());
+        pool.close().await;
+
+        // Nothing scheduled: nothing happens
+        assert!(!restore_if_scheduled(&db_path).unwrap());
+
+        std::fs::write(&db_path, b"garbage").unwrap();
+        std::fs::write(restore_marker_path(&db_path), b"").unwrap();
+        assert!(restore_if_scheduled(&db_path).unwrap());
+        assert!(!restore_marker_path(&db_path).exists());
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!("sqlite:{}", db_path.display()))
+            .await
+            .unwrap();
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM indexed_files")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn uploaded_snapshot_is_validated_and_restored_on_start() {
+        let tmp = tempdir().unwrap();
+        let db_path = tmp.path().join("filex.db");
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!("sqlite:{}?mode=rwc", db_path.display()))
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        sqlx::query("INSERT INTO indexed_files (path, name) VALUES ('/a.txt', 'a.txt')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut config = Config::from_env();
+        config.database_path = db_path.clone();
+        config.db_min_free_mb = 0;
+        let service = IntegrityService::new(pool.clone(), &config);
+
+        // A snapshot holds the live data
+        let mut snapshot = service.snapshot().await.unwrap();
+        let mut bytes = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut snapshot, &mut bytes)
+            .await
+            .unwrap();
+        assert!(bytes.starts_with(b"SQLite format 3\0"));
+
+        // Garbage is rejected and cleaned up
+        std::fs::write(service.upload_path(), b"not a database").unwrap();
+        assert!(matches!(
+            service.schedule_restore(&service.upload_path()).await,
+            Err(BackupError::Invalid(_))
+        ));
+        assert!(!service.upload_path().exists());
+
+        std::fs::write(service.upload_path(), &bytes).unwrap();
+        service
+            .schedule_restore(&service.upload_path())
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM indexed_files")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // A passing check must not overwrite the uploaded snapshot
+        assert!(service.run_check().await.restore_scheduled);
+        pool.close().await;
+
+        assert!(restore_if_scheduled(&db_path).unwrap());
+        let replaced = std::fs::read_dir(tmp.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .any(|e| e.file_name().to_string_lossy().contains(".replaced-"));
+        assert!(replaced);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!("sqlite:{}", db_path.display()))
+            .await
+            .unwrap();
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM indexed_files")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
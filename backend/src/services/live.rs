@@ -0,0 +1,43 @@
+//! Fan-out of file events to clients watching directories live. Write
+//! endpoints and index runs publish the same events webhooks get; each
+//! subscriber filters for the directories it is showing.
+
+use tokio::sync::broadcast;
+
+use crate::services::webhooks::FileEvent;
+
+/// Events buffered per subscriber; a subscriber that falls further behind
+/// is told to reload instead
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone)]
+pub struct LiveUpdates {
+    sender: broadcast::Sender<FileEvent>,
+}
+
+impl LiveUpdates {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    /// Send an event to every current subscriber
+    pub fn publish(&self, event: FileEvent) {
+        // Failing only means nobody is listening
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FileEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
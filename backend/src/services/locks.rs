@@ -0,0 +1,161 @@
+//! Advisory locks on files, so two people editing the same file through the
+//! UI get a conflict instead of one silently overwriting the other. Locks
+//! live in memory and expire on their own; whoever holds the token may
+//! refresh, release, or overwrite the file.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Lock lifetime when the client does not ask for one
+pub const DEFAULT_LOCK_SECS: u64 = 300;
+
+/// Longest lock a client may take out before having to refresh it
+pub const MAX_LOCK_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileLock {
+    pub path: String,
+    /// Proof of ownership, only ever returned to the client taking the lock
+    #[serde(skip_serializing)]
+    pub token: String,
+    /// Who holds the lock, as they described themselves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    pub locked_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Locks keyed by root-relative path. A std mutex is used since every
+/// critical section is a map update.
+#[derive(Clone)]
+pub struct LockManager {
+    locks: Arc<Mutex<HashMap<String, FileLock>>>,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self {
+            locks: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Lock `path` for `secs` seconds (capped at `MAX_LOCK_SECS`). Passing
+    /// the token of the current lock refreshes it. Fails with the current
+    /// lock when someone else holds it.
+    pub fn lock(
+        &self,
+        path: &str,
+        owner: Option<String>,
+        secs: u64,
+        token: Option<&str>,
+    ) -> Result<FileLock, FileLock> {
+        let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+        locks.retain(|_, lock| lock.expires_at > now);
+
+        let expires_at = now + Duration::seconds(secs.clamp(1, MAX_LOCK_SECS) as i64);
+        let lock = match locks.get(path) {
+            Some(held) if Some(held.token.as_str()) != token => return Err(held.clone()),
+            Some(held) => FileLock {
+                owner: owner.or_else(|| held.owner.clone()),
+                expires_at,
+                ..held.clone()
+            },
+            None => FileLock {
+                path: path.to_string(),
+                token: uuid::Uuid::new_v4().as_simple().to_string(),
+                owner,
+                locked_at: now,
+                expires_at,
+            },
+        };
+        locks.insert(path.to_string(), lock.clone());
+        Ok(lock)
+    }
+
+    /// Release the lock on `path`. Returns whether there was one; fails with
+    /// the current lock when `token` does not match it.
+    pub fn unlock(&self, path: &str, token: &str) -> Result<bool, FileLock> {
+        // Compared and removed under one guard, so a lock someone takes
+        // once this one expires cannot be released with the old token
+        let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+        match locks.get(path) {
+            Some(lock) if lock.expires_at > Utc::now() && lock.token != token => Err(lock.clone()),
+            _ => Ok(locks.remove(path).is_some()),
+        }
+    }
+
+    /// The unexpired lock on `path`, if any
+    pub fn get(&self, path: &str) -> Option<FileLock> {
+        let locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks
+            .get(path)
+            .filter(|lock| lock.expires_at > Utc::now())
+            .cloned()
+    }
+
+    /// Whether whoever presents `token` may change `path`: it is unlocked,
+    /// or locked with that token. Fails with the lock otherwise.
+    pub fn check(&self, path: &str, token: Option<&str>) -> Result<(), FileLock> {
+        match self.get(path) {
+            Some(lock) if Some(lock.token.as_str()) != token => Err(lock),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for LockManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_exclude_other_holders_until_released() {
+        let locks = LockManager::new();
+        let held = locks
+            .lock("/app.conf", Some("ana".to_string()), 60, None)
+            .unwrap();
+        assert!(locks.check("/app.conf", Some(&held.token)).is_ok());
+        assert!(locks.check("/other.conf", None).is_ok());
+
+        let err = locks.lock("/app.conf", None, 60, None).unwrap_err();
+        assert_eq!(err.owner.as_deref(), Some("ana"));
+        assert!(locks.check("/app.conf", None).is_err());
+        assert!(locks.unlock("/app.conf", "guess").is_err());
+
+        // Refreshing keeps the token and owner
+        let refreshed = locks
+            .lock("/app.conf", None, MAX_LOCK_SECS * 10, Some(&held.token))
+            .unwrap();
+        assert_eq!(refreshed.token, held.token);
+        assert_eq!(refreshed.owner.as_deref(), Some("ana"));
+        assert!(refreshed.expires_at <= Utc::now() + Duration::seconds(MAX_LOCK_SECS as i64));
+
+        assert!(matches!(locks.unlock("/app.conf", &held.token), Ok(true)));
+        assert!(locks.lock("/app.conf", None, 60, None).is_ok());
+    }
+
+    #[test]
+    fn expired_locks_are_ignored() {
+        let locks = LockManager::new();
+        let held = locks.lock("/a", None, 60, None).unwrap();
+        locks
+            .locks
+            .lock()
+            .unwrap()
+            .get_mut("/a")
+            .unwrap()
+            .expires_at = Utc::now();
+
+        assert!(locks.get("/a").is_none());
+        let taken = locks.lock("/a", None, 60, None).unwrap();
+        assert_ne!(taken.token, held.token);
+    }
+}
@@ -0,0 +1,165 @@
+//! Manifest generation for archival drops: a sorted listing of every entry
+//! under a directory with sizes and SHA-256 hashes, written next to the data.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use walkdir::WalkDir;
+
+pub const MANIFEST_TXT: &str = "MANIFEST.txt";
+pub const MANIFEST_JSON: &str = "manifest.json";
+
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub generated_at: DateTime<Utc>,
+    pub file_count: u64,
+    pub total_size: u64,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    /// Path relative to the manifest directory, always `/`-separated
+    pub path: String,
+    pub is_dir: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+pub struct ManifestService;
+
+impl ManifestService {
+    /// Walk `dir` and hash every regular file. Entries are sorted by path and
+    /// previous manifests are skipped, so two runs over unchanged data differ
+    /// only in `generated_at`. Symlinks are not followed.
+    pub fn generate(dir: &Path) -> io::Result<Manifest> {
+        let mut entries = Vec::new();
+        let mut file_count = 0;
+        let mut total_size = 0;
+
+        for entry in WalkDir::new(dir)
+            .min_depth(1)
+            .follow_links(false)
+            .sort_by_file_name()
+        {
+            let entry = entry.map_err(io::Error::other)?;
+            let relative = entry
+                .path()
+                .strip_prefix(dir)
+                .map_err(io::Error::other)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if entry.depth() == 1 && (relative == MANIFEST_TXT || relative == MANIFEST_JSON) {
+                continue;
+            }
+
+            let file_type = entry.file_type();
+            if file_type.is_dir() {
+                entries.push(ManifestEntry {
+                    path: relative,
+                    is_dir: true,
+                    size: None,
+                    sha256: None,
+                });
+            } else if file_type.is_file() {
+                let size = entry.metadata().map_err(io::Error::other)?.len();
+                file_count += 1;
+                total_size += size;
+                entries.push(ManifestEntry {
+                    path: relative,
+                    is_dir: false,
+                    size: Some(size),
+                    sha256: Some(Self::hash_file(entry.path())?),
+                });
+            }
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(Manifest {
+            generated_at: Utc::now(),
+            file_count,
+            total_size,
+            entries,
+        })
+    }
+
+    /// Write `MANIFEST.txt` and `manifest.json` into `dir`, returning the
+    /// names of the files written.
+    pub fn write(dir: &Path, manifest: &Manifest) -> io::Result<Vec<&'static str>> {
+        let mut txt = fs::File::create(dir.join(MANIFEST_TXT))?;
+        writeln!(txt, "# Generated: {}", manifest.generated_at.to_rfc3339())?;
+        writeln!(
+            txt,
+            "# Files: {}, Bytes: {}",
+            manifest.file_count, manifest.total_size
+        )?;
+        for entry in &manifest.entries {
+            match (&entry.sha256, entry.size) {
+                (Some(hash), Some(size)) => writeln!(txt, "{hash}  {size:>14}  {}", entry.path)?,
+                _ => writeln!(txt, "{:64}  {:>14}  {}/", "", "", entry.path)?,
+            }
+        }
+        txt.flush()?;
+
+        let json = serde_json::to_vec_pretty(manifest).map_err(io::Error::other)?;
+        fs::write(dir.join(MANIFEST_JSON), json)?;
+
+        Ok(vec![MANIFEST_TXT, MANIFEST_JSON])
+    }
+
+    /// Hex SHA-256 of a file's contents
+    pub fn hash_file(path: &Path) -> io::Result<String> {
+        let mut file = fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+        }
+        Ok(hex::encode(hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn generate_is_sorted_and_skips_previous_manifests() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir(dir.join("b")).unwrap();
+        fs::write(dir.join("b/inner.txt"), b"inner").unwrap();
+        fs::write(dir.join("a.txt"), b"hello").unwrap();
+
+        let first = ManifestService::generate(dir).unwrap();
+        ManifestService::write(dir, &first).unwrap();
+        let second = ManifestService::generate(dir).unwrap();
+
+        let paths: Vec<_> = second.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "b", "b/inner.txt"]);
+        assert_eq!(second.file_count, 2);
+        assert_eq!(second.total_size, 10);
+        assert_eq!(
+            second.entries[0].sha256.as_deref(),
+            Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+        );
+
+        let txt = fs::read_to_string(dir.join(MANIFEST_TXT)).unwrap();
+        assert!(txt.contains("b/inner.txt"));
+        assert!(dir.join(MANIFEST_JSON).exists());
+    }
+}
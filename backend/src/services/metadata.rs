@@ -1,11 +1,14 @@
+use chrono::{DateTime, Datelike, NaiveDateTime};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::Path;
-use std::process::Stdio;
+use std::process::{Output, Stdio};
 use std::time::Duration;
 use thiserror::Error;
 use tokio::process::Command;
 
-use crate::models::MediaMetadata;
+use crate::models::{AudioTags, DocumentMetadata, MediaMetadata};
+use crate::services::exif;
 
 #[derive(Error, Debug)]
 pub enum MetadataError {
@@ -18,9 +21,15 @@ pub enum MetadataError {
     #[error("Failed to parse ffprobe output: {0}")]
     ParseError(String),
 
+    #[error("pdfinfo/pdftoppm not found - ensure poppler-utils is installed")]
+    PopplerNotFound,
+
     #[error("Not a media file")]
     NotMediaFile,
 
+    #[error("Not a PDF file")]
+    NotPdfFile,
+
     #[error("ffprobe timed out")]
     Timeout,
 }
@@ -38,32 +47,38 @@ struct FfprobeStream {
     width: Option<u32>,
     height: Option<u32>,
     duration: Option<String>,
+    tags: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Deserialize)]
 struct FfprobeFormat {
     format_name: Option<String>,
     duration: Option<String>,
+    tags: Option<HashMap<String, String>>,
 }
 
 pub struct MetadataService;
 
+pub const PDF_MIME: &str = "application/pdf";
+
 impl MetadataService {
-    // ffprobe sometimes hangs on malformed files, so guard the call with a timeout
-    const FFPROBE_TIMEOUT: Duration = Duration::from_secs(15);
+    // poppler can hang on broken or enormous PDFs, so guard the call with a timeout
+    const POPPLER_TIMEOUT: Duration = Duration::from_secs(15);
 
-    /// Extract media metadata using ffprobe
-    pub async fn extract(path: &Path) -> Result<MediaMetadata, MetadataError> {
-        // Check if file might be a media file based on mime type
-        if !Self::is_likely_media_file(path) {
+    /// Extract media metadata using ffprobe, for files whose MIME type (as
+    /// indexed, so also sniffed from content) is media. ffprobe sometimes
+    /// hangs on malformed files, so it is killed after `timeout`.
+    pub async fn extract(
+        path: &Path,
+        mime_type: Option<&str>,
+        timeout: Duration,
+    ) -> Result<MediaMetadata, MetadataError> {
+        if !Self::is_likely_media(mime_type) {
             return Err(MetadataError::NotMediaFile);
         }
 
         let mut command = Command::new("ffprobe");
         command
-            .kill_on_drop(true)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
             .args([
                 "-v",
                 "quiet",
@@ -74,28 +89,8 @@ impl MetadataService {
             ])
             .arg(path);
 
-        let child = command.spawn().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                MetadataError::FfprobeNotFound
-            } else {
-                MetadataError::ExecutionFailed(e.to_string())
-            }
-        })?;
-
-        let mut child_opt = Some(child);
-        let output = tokio::select! {
-            res = async {
-                // Safe to unwrap: this branch is exclusive and consumes the child.
-                child_opt.take().unwrap().wait_with_output().await
-            } => res.map_err(|e| MetadataError::ExecutionFailed(e.to_string()))?,
-            _ = tokio::time::sleep(Self::FFPROBE_TIMEOUT) => {
-                if let Some(mut child) = child_opt.take() {
-                    let _ = child.start_kill();
-                    let _ = child.wait().await;
-                }
-                return Err(MetadataError::Timeout);
-            }
-        };
+        let output =
+            Self::run_with_timeout(command, MetadataError::FfprobeNotFound, timeout).await?;
 
         if !output.status.success() {
             return Err(MetadataError::ExecutionFailed(
@@ -114,6 +109,11 @@ impl MetadataService {
             if let Some(dur) = format.duration {
                 metadata.duration = dur.parse().ok();
             }
+            if let Some(tags) = &format.tags {
+                metadata.tags = Self::audio_tags_from(tags);
+                metadata.captured_at = Self::creation_time_from(tags);
+                metadata.location = Self::location_from(tags);
+            }
         }
 
         // Extract from streams (prefer video stream for resolutions)
@@ -124,11 +124,16 @@ impl MetadataService {
                     metadata.height = stream.height;
                     metadata.codec = stream.codec_name;
 
+                    if metadata.captured_at.is_none() {
+                        metadata.captured_at =
+                            stream.tags.as_ref().and_then(Self::creation_time_from);
+                    }
+
                     // Video stream duration takes precedence
-                    if let Some(dur) = stream.duration {
-                        if let Ok(d) = dur.parse::<f64>() {
-                            metadata.duration = Some(d);
-                        }
+                    if let Some(dur) = stream.duration
+                        && let Ok(d) = dur.parse::<f64>()
+                    {
+                        metadata.duration = Some(d);
                     }
                     break;
                 } else if stream.codec_type.as_deref() == Some("audio")
@@ -142,17 +147,215 @@ impl MetadataService {
                         metadata.codec = stream.codec_name;
                     }
                 }
+
+                // Ogg/Opus carry their Vorbis comments on the stream, not the container
+                if metadata.tags.is_empty()
+                    && let Some(tags) = &stream.tags
+                {
+                    metadata.tags = Self::audio_tags_from(tags);
+                }
             }
         }
 
         Ok(metadata)
     }
 
+    /// Extract page count, title, and author from a PDF using pdfinfo
+    pub async fn extract_pdf(path: &Path) -> Result<DocumentMetadata, MetadataError> {
+        if !Self::is_pdf_file(path) {
+            return Err(MetadataError::NotPdfFile);
+        }
+
+        let mut command = Command::new("pdfinfo");
+        command.args(["-enc", "UTF-8"]).arg(path);
+
+        let output = Self::run_with_timeout(
+            command,
+            MetadataError::PopplerNotFound,
+            Self::POPPLER_TIMEOUT,
+        )
+        .await?;
+
+        if !output.status.success() {
+            return Err(MetadataError::ExecutionFailed(
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+
+        Ok(Self::parse_pdfinfo(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
+    /// Render the first page of a PDF as a PNG whose longest side is `size` pixels
+    pub async fn render_pdf_thumbnail(path: &Path, size: u32) -> Result<Vec<u8>, MetadataError> {
+        if !Self::is_pdf_file(path) {
+            return Err(MetadataError::NotPdfFile);
+        }
+
+        // Without an output root, pdftoppm writes the image to stdout
+        let mut command = Command::new("pdftoppm");
+        command
+            .args(["-png", "-f", "1", "-l", "1", "-singlefile", "-scale-to"])
+            .arg(size.to_string())
+            .arg(path);
+
+        let output = Self::run_with_timeout(
+            command,
+            MetadataError::PopplerNotFound,
+            Self::POPPLER_TIMEOUT,
+        )
+        .await?;
+
+        if !output.status.success() || output.stdout.is_empty() {
+            return Err(MetadataError::ExecutionFailed(
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+
+        Ok(output.stdout)
+    }
+
+    /// Parse `Key: value` lines printed by pdfinfo
+    fn parse_pdfinfo(output: &str) -> DocumentMetadata {
+        let mut document = DocumentMetadata::default();
+
+        for line in output.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            match key.trim() {
+                "Pages" => document.page_count = value.parse().ok(),
+                "Title" => document.title = Some(value.to_string()),
+                "Author" => document.author = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        document
+    }
+
+    /// Spawn an external tool and collect its output, killing it if it runs
+    /// longer than `timeout`. `not_found` is returned when the binary is missing.
+    async fn run_with_timeout(
+        mut command: Command,
+        not_found: MetadataError,
+        timeout: Duration,
+    ) -> Result<Output, MetadataError> {
+        command
+            .kill_on_drop(true)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let child = command.spawn().map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                not_found
+            } else {
+                MetadataError::ExecutionFailed(e.to_string())
+            }
+        })?;
+
+        let mut child_opt = Some(child);
+        let output = tokio::select! {
+            res = async {
+                // Safe to unwrap: this branch is exclusive and consumes the child.
+                child_opt.take().unwrap().wait_with_output().await
+            } => res.map_err(|e| MetadataError::ExecutionFailed(e.to_string()))?,
+            _ = tokio::time::sleep(timeout) => {
+                if let Some(mut child) = child_opt.take() {
+                    let _ = child.start_kill();
+                    let _ = child.wait().await;
+                }
+                return Err(MetadataError::Timeout);
+            }
+        };
+
+        Ok(output)
+    }
+
+    /// Map ffprobe's tag dictionary onto `AudioTags`. Keys differ in case
+    /// between containers (`artist` for ID3, `ARTIST` for FLAC), so lookups
+    /// are case-insensitive.
+    fn audio_tags_from(tags: &HashMap<String, String>) -> AudioTags {
+        let get = |keys: &[&str]| {
+            keys.iter().find_map(|key| {
+                tags.iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v.trim())
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_string())
+            })
+        };
+
+        AudioTags {
+            artist: get(&["artist", "album_artist"]),
+            album: get(&["album"]),
+            title: get(&["title"]),
+            // "3/12" style values are common; keep just the track position
+            track_number: get(&["track", "tracknumber"])
+                .and_then(|v| v.split('/').next().and_then(|n| n.trim().parse().ok())),
+        }
+    }
+
+    /// When a recording was made. Apple devices tag the local time with its
+    /// offset, which is kept as local time like EXIF dates; the standard
+    /// `creation_time` is UTC. Cameras without a clock write 1970 or earlier.
+    fn creation_time_from(tags: &HashMap<String, String>) -> Option<NaiveDateTime> {
+        let get = |key: &str| {
+            tags.iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.trim())
+        };
+
+        get("com.apple.quicktime.creationdate")
+            .and_then(|v| DateTime::parse_from_str(v, "%Y-%m-%dT%H:%M:%S%z").ok())
+            .map(|t| t.naive_local())
+            .or_else(|| {
+                get("creation_time")
+                    .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+                    .map(|t| t.naive_utc())
+            })
+            .filter(|t| t.year() > 1970)
+    }
+
+    /// Where a recording was made, from an ISO 6709 tag such as
+    /// `+52.3700+004.8950/` (phones and action cameras write one)
+    fn location_from(tags: &HashMap<String, String>) -> Option<(f64, f64)> {
+        let value = ["com.apple.quicktime.location.ISO6709", "location"]
+            .iter()
+            .find_map(|key| {
+                tags.iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v.trim().trim_end_matches('/'))
+            })?;
+
+        // Each number starts with its sign; a third one is the altitude
+        let starts: Vec<usize> = value
+            .char_indices()
+            .filter(|(_, c)| *c == '+' || *c == '-')
+            .map(|(i, _)| i)
+            .collect();
+        if starts.first() != Some(&0) || starts.len() < 2 {
+            return None;
+        }
+        let end = starts.get(2).copied().unwrap_or(value.len());
+        let latitude = value[..starts[1]].parse().ok()?;
+        let longitude = value[starts[1]..end].parse().ok()?;
+        exif::valid_location(latitude, longitude)
+    }
+
     /// Check if mime type suggests it might be a media file
-    fn is_likely_media_file(path: &Path) -> bool {
-        mime_guess::from_path(path)
-            .first_raw()
-            .is_some_and(Self::is_media_mime)
+    fn is_likely_media(mime_type: Option<&str>) -> bool {
+        mime_type.is_some_and(Self::is_media_mime)
+    }
+
+    pub fn is_pdf_file(path: &Path) -> bool {
+        mime_guess::from_path(path).first_raw() == Some(PDF_MIME)
     }
 
     fn is_media_mime(mime: &str) -> bool {
@@ -170,6 +373,15 @@ impl MetadataService {
             .map(|o| o.status.success())
             .unwrap_or(false)
     }
+
+    /// Check if poppler's pdfinfo is available
+    pub fn is_pdf_tools_available() -> bool {
+        std::process::Command::new("pdfinfo")
+            .arg("-v")
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    }
 }
 
 #[cfg(test)]
@@ -180,19 +392,15 @@ mod tests {
 
     #[test]
     fn test_media_file_detection() {
-        assert!(MetadataService::is_likely_media_file(Path::new(
-            "video.mp4"
-        )));
-        assert!(MetadataService::is_likely_media_file(Path::new(
-            "image.jpg"
-        )));
-        assert!(MetadataService::is_likely_media_file(Path::new(
-            "audio.mp3"
-        )));
-        assert!(!MetadataService::is_likely_media_file(Path::new(
-            "document.pdf"
-        )));
-        assert!(!MetadataService::is_likely_media_file(Path::new("code.rs")));
+        let guess = |name: &str| mime_guess::from_path(name).first_raw();
+        assert!(MetadataService::is_likely_media(guess("video.mp4")));
+        assert!(MetadataService::is_likely_media(guess("image.jpg")));
+        assert!(MetadataService::is_likely_media(guess("audio.mp3")));
+        assert!(!MetadataService::is_likely_media(guess("document.pdf")));
+        assert!(!MetadataService::is_likely_media(guess("code.rs")));
+        // Types sniffed from content count the same as extensions
+        assert!(MetadataService::is_likely_media(Some("video/mp4")));
+        assert!(!MetadataService::is_likely_media(None));
         assert!(MetadataService::is_media_mime(
             "application/vnd.rn-realmedia"
         ));
@@ -205,13 +413,105 @@ mod tests {
         assert!(!MetadataService::is_media_mime("application/octet-stream"));
     }
 
+    #[test]
+    fn audio_tags_are_read_case_insensitively() {
+        let tags: HashMap<String, String> = [
+            ("ARTIST", "Nina Simone"),
+            ("ALBUM", "Pastel Blues"),
+            ("TITLE", " Sinnerman "),
+            ("TRACKNUMBER", "10/10"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let parsed = MetadataService::audio_tags_from(&tags);
+        assert_eq!(parsed.artist.as_deref(), Some("Nina Simone"));
+        assert_eq!(parsed.album.as_deref(), Some("Pastel Blues"));
+        assert_eq!(parsed.title.as_deref(), Some("Sinnerman"));
+        assert_eq!(parsed.track_number, Some(10));
+
+        let empty = MetadataService::audio_tags_from(&HashMap::new());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn creation_time_prefers_local_apple_tag() {
+        let tags = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let utc = tags(&[("creation_time", "2024-05-01T12:34:56.000000Z")]);
+        assert_eq!(
+            MetadataService::creation_time_from(&utc).map(|t| t.to_string()),
+            Some("2024-05-01 12:34:56".to_string())
+        );
+
+        let apple = tags(&[
+            ("creation_time", "2024-05-01T12:34:56.000000Z"),
+            (
+                "com.apple.quicktime.creationdate",
+                "2024-05-01T14:34:56+0200",
+            ),
+        ]);
+        assert_eq!(
+            MetadataService::creation_time_from(&apple).map(|t| t.to_string()),
+            Some("2024-05-01 14:34:56".to_string())
+        );
+
+        let unset = tags(&[("creation_time", "1970-01-01T00:00:00.000000Z")]);
+        assert_eq!(MetadataService::creation_time_from(&unset), None);
+    }
+
+    #[test]
+    fn location_is_read_from_iso6709_tags() {
+        let tags = |key: &str, value: &str| -> HashMap<String, String> {
+            [(key.to_string(), value.to_string())].into_iter().collect()
+        };
+
+        assert_eq!(
+            MetadataService::location_from(&tags("location", "+52.3700+004.8950/")),
+            Some((52.37, 4.895))
+        );
+        assert_eq!(
+            MetadataService::location_from(&tags(
+                "com.apple.quicktime.location.ISO6709",
+                "-33.8688+151.2093+012.000/"
+            )),
+            Some((-33.8688, 151.2093))
+        );
+        assert_eq!(
+            MetadataService::location_from(&tags("location", "somewhere")),
+            None
+        );
+    }
+
+    #[test]
+    fn pdfinfo_output_is_parsed() {
+        let output = "Title:           Quarterly Report\n\
+                      Author:          Jane Doe\n\
+                      Creator:         Writer\n\
+                      Subject:         \n\
+                      Pages:           12\n\
+                      Page size:       612 x 792 pts (letter)\n";
+
+        let document = MetadataService::parse_pdfinfo(output);
+        assert_eq!(document.page_count, Some(12));
+        assert_eq!(document.title.as_deref(), Some("Quarterly Report"));
+        assert_eq!(document.author.as_deref(), Some("Jane Doe"));
+    }
+
     #[tokio::test]
     async fn extract_returns_not_media_for_non_media_file() {
         let dir = tempdir().unwrap();
         let path = dir.path().join("notes.txt");
         fs::write(&path, b"hello").unwrap();
 
-        let result = MetadataService::extract(&path).await;
+        let result =
+            MetadataService::extract(&path, Some("text/plain"), Duration::from_secs(15)).await;
         assert!(matches!(result, Err(MetadataError::NotMediaFile)));
     }
 }
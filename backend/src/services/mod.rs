@@ -1,10 +1,66 @@
+pub mod antivirus;
+pub mod archive;
+pub mod backend;
+pub mod dedup;
+pub mod exif;
 pub mod filesystem;
+pub mod hooks;
 pub mod indexer;
+pub mod integrity;
+pub mod live;
+pub mod locks;
+pub mod log_buffer;
+pub mod manifest;
 pub mod metadata;
+pub mod naming;
+pub mod notifications;
+pub mod oidc;
+pub mod permissions;
+pub mod preview;
+pub mod query;
+pub mod readahead;
+pub mod remotes;
+pub mod report;
+pub mod root_identity;
 pub mod search;
 pub mod search_index;
+pub mod search_shards;
+pub mod similar;
+pub mod storage;
+pub mod support;
+pub mod sync_jobs;
+pub mod templates;
+pub mod text;
+pub mod throttle;
+pub mod transfers;
+pub mod uploads;
+pub mod usage;
+pub mod webhooks;
 
-pub use filesystem::{FilesystemService, FsError};
+pub use antivirus::VirusScanner;
+pub use archive::ArchiveService;
+pub use backend::{MemoryBackend, StorageBackend};
+pub use dedup::DedupStore;
+pub use filesystem::{ExpectedState, FilesystemService, FsError};
+pub use hooks::HookService;
 pub use indexer::IndexerService;
+pub use integrity::{BackupError, IntegrityService};
+pub use live::LiveUpdates;
+pub use locks::LockManager;
+pub use log_buffer::LogBuffer;
+pub use manifest::ManifestService;
 pub use metadata::MetadataService;
+pub use notifications::NotificationService;
+pub use readahead::Readahead;
+pub use remotes::RemoteSyncService;
+pub use report::ReportService;
 pub use search::SearchService;
+pub use storage::StorageGuard;
+pub use support::SupportBundle;
+pub use sync_jobs::SyncJobService;
+pub use templates::FileTemplates;
+pub use throttle::DownloadThrottle;
+pub use transfers::TransferManager;
+pub use uploads::UploadTracker;
+pub use usage::UsageTracker;
+pub use webhooks::WebhookService;
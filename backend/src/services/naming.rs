@@ -0,0 +1,197 @@
+//! Filename normalization for uploads and renames, configured per directory
+//! with a `.fxnames` file:
+//!
+//! ```text
+//! # Applies to this directory and everything below it
+//! strip_emoji = true
+//! transliterate = de        # true for generic ASCII, or a language code
+//! replace_spaces = _
+//! ```
+//!
+//! The nearest `.fxnames` between the target directory and the root wins.
+
+use std::fs;
+use std::path::Path;
+
+pub const POLICY_FILE: &str = ".fxnames";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NamePolicy {
+    pub strip_emoji: bool,
+    /// `None` keeps Unicode; `Some("")` transliterates without language rules
+    pub transliterate: Option<String>,
+    pub replace_spaces: Option<String>,
+}
+
+impl NamePolicy {
+    /// Parse `key = value` lines; unknown keys and malformed lines are ignored
+    pub fn parse(text: &str) -> Self {
+        let mut policy = Self::default();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "strip_emoji" => policy.strip_emoji = is_true(value),
+                "transliterate" => {
+                    policy.transliterate = match value {
+                        "false" | "0" | "" => None,
+                        "true" | "1" | "ascii" => Some(String::new()),
+                        lang => Some(lang.to_ascii_lowercase()),
+                    }
+                }
+                "replace_spaces" => {
+                    policy.replace_spaces = Some(value.to_string())
+                        .filter(|v| !v.is_empty() && !v.contains(['/', '\\']) && !is_false(v))
+                }
+                _ => {}
+            }
+        }
+        policy
+    }
+
+    /// Find the policy governing `dir`, searching upward no further than `root`
+    pub fn for_directory(root: &Path, dir: &Path) -> Option<Self> {
+        dir.ancestors()
+            .take_while(|ancestor| ancestor.starts_with(root))
+            .find_map(|ancestor| fs::read_to_string(ancestor.join(POLICY_FILE)).ok())
+            .map(|text| Self::parse(&text))
+    }
+
+    /// Apply the policy to a file name. The extension is normalized like the
+    /// rest of the name; if nothing usable is left, the name is unchanged.
+    pub fn apply(&self, name: &str) -> String {
+        let mut result: String = if self.strip_emoji {
+            collapse_spaces(&name.chars().filter(|c| !is_emoji(*c)).collect::<String>())
+        } else {
+            name.to_string()
+        };
+
+        if let Some(lang) = &self.transliterate {
+            result = transliterate(&result, lang);
+        }
+
+        if let Some(replacement) = &self.replace_spaces {
+            result = result
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(replacement);
+        }
+
+        let trimmed = result.trim();
+        let became_hidden = trimmed.starts_with('.') && !name.starts_with('.');
+        if trimmed.is_empty() || trimmed == "." || trimmed == ".." || became_hidden {
+            return name.to_string();
+        }
+        trimmed.to_string()
+    }
+}
+
+fn is_true(value: &str) -> bool {
+    matches!(value, "true" | "1" | "yes")
+}
+
+fn is_false(value: &str) -> bool {
+    matches!(value, "false" | "0" | "no")
+}
+
+fn collapse_spaces(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Pictographs, dingbats, flags, and the joiners/selectors that compose them
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF
+            | 0x2600..=0x27BF
+            | 0x2B00..=0x2BFF
+            | 0x200D
+            | 0x20E3
+            | 0xFE0E..=0xFE0F
+            | 0xE0020..=0xE007F
+    )
+}
+
+/// Language-specific spellings that generic transliteration gets wrong
+fn language_rules(lang: &str) -> &'static [(char, &'static str)] {
+    match lang {
+        "de" => &[
+            ('ä', "ae"),
+            ('ö', "oe"),
+            ('ü', "ue"),
+            ('Ä', "Ae"),
+            ('Ö', "Oe"),
+            ('Ü', "Ue"),
+            ('ß', "ss"),
+        ],
+        "da" | "nb" | "nn" | "no" => &[
+            ('æ', "ae"),
+            ('ø', "oe"),
+            ('å', "aa"),
+            ('Æ', "Ae"),
+            ('Ø', "Oe"),
+            ('Å', "Aa"),
+        ],
+        _ => &[],
+    }
+}
+
+fn transliterate(value: &str, lang: &str) -> String {
+    let rules = language_rules(lang);
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        if let Some((_, replacement)) = rules.iter().find(|(from, _)| *from == c) {
+            result.push_str(replacement);
+        } else if c.is_ascii() {
+            result.push(c);
+        } else {
+            // deunicode pads some expansions with spaces (e.g. CJK syllables)
+            result.push_str(deunicode::deunicode_char(c).unwrap_or("").trim());
+        }
+    }
+    result.retain(|c| c != '/' && c != '\\');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn applies_each_rule() {
+        let policy = NamePolicy::parse(
+            "# comment\nstrip_emoji = true\ntransliterate = de\nreplace_spaces = _\nbogus",
+        );
+        assert_eq!(
+            policy.apply("Grüße 🎉 aus Köln.pdf"),
+            "Gruesse_aus_Koeln.pdf"
+        );
+
+        let generic = NamePolicy::parse("transliterate = true");
+        assert_eq!(generic.apply("Crème brûlée.txt"), "Creme brulee.txt");
+        assert_eq!(generic.apply("Grüße.txt"), "Grusse.txt");
+
+        // Nothing left after stripping keeps the original
+        let emoji_only = NamePolicy::parse("strip_emoji = true");
+        assert_eq!(emoji_only.apply("🎉"), "🎉");
+        assert_eq!(NamePolicy::default().apply("a b.txt"), "a b.txt");
+    }
+
+    #[test]
+    fn nearest_policy_file_wins() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::write(root.join(POLICY_FILE), "replace_spaces = -").unwrap();
+        fs::write(root.join("a/b").join(POLICY_FILE), "replace_spaces = _").unwrap();
+
+        let policy = NamePolicy::for_directory(root, &root.join("a/b/c")).unwrap();
+        assert_eq!(policy.replace_spaces.as_deref(), Some("_"));
+        let policy = NamePolicy::for_directory(root, &root.join("a")).unwrap();
+        assert_eq!(policy.replace_spaces.as_deref(), Some("-"));
+        assert!(NamePolicy::for_directory(&root.join("a"), &root.join("a")).is_none());
+    }
+}
@@ -0,0 +1,451 @@
+//! Single sign-on with an OpenID Connect provider (Authentik, Keycloak, ...)
+//! using the authorization code flow with PKCE.
+//!
+//! The ID token comes straight from the provider's token endpoint over TLS,
+//! so its claims are checked (issuer, audience, expiry, nonce) but its
+//! signature is not (OpenID Connect Core 3.1.3.7). Providers are therefore
+//! only used over HTTPS, except on the loopback interface.
+//!
+//! The `state` of a login is also kept in a cookie in the browser that
+//! started it, so a callback carrying someone else's login is refused.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+use crate::config::OidcConfig;
+
+/// How long a user has to finish logging in at the provider
+pub const LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Unfinished logins kept at once; more are refused until some expire
+const MAX_PENDING_LOGINS: usize = 1000;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("Identity provider unavailable: {0}")]
+    Provider(String),
+
+    #[error("Login expired or was not started here; please try again")]
+    UnknownState,
+
+    #[error("Login was started in another browser; please try again")]
+    StateMismatch,
+
+    #[error("Too many logins in progress; please try again later")]
+    TooManyLogins,
+
+    #[error("Invalid ID token: {0}")]
+    InvalidToken(String),
+
+    #[error("{0} is not in a group allowed to use filex")]
+    NotAllowed(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+/// Where to send the browser to log in, and the `state` to remember in it
+pub struct LoginRedirect {
+    pub url: String,
+    pub state: String,
+}
+
+struct PendingLogin {
+    nonce: String,
+    verifier: String,
+    expires: Instant,
+}
+
+pub struct OidcClient {
+    config: OidcConfig,
+    agent: ureq::Agent,
+    discovery: OnceCell<Discovery>,
+    /// Logins sent to the provider, by `state` parameter
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+            discovery: OnceCell::new(),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start a login: the provider URL to send the browser to
+    pub async fn authorization_url(&self) -> Result<LoginRedirect, OidcError> {
+        let discovery = self.discovery().await?;
+
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = format!("{}{}", random_token(), random_token());
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            pending.retain(|_, login| login.expires > now);
+            if pending.len() >= MAX_PENDING_LOGINS {
+                return Err(OidcError::TooManyLogins);
+            }
+            pending.insert(
+                state.clone(),
+                PendingLogin {
+                    nonce: nonce.clone(),
+                    verifier,
+                    expires: now + LOGIN_TIMEOUT,
+                },
+            );
+        }
+
+        let params = [
+            ("response_type", "code"),
+            ("client_id", self.config.client_id.as_str()),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("scope", "openid profile email"),
+            ("state", state.as_str()),
+            ("nonce", nonce.as_str()),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ];
+        let query = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, utf8_percent_encode(value, NON_ALPHANUMERIC)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let separator = if discovery.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        Ok(LoginRedirect {
+            url: format!("{}{}{}", discovery.authorization_endpoint, separator, query),
+            state,
+        })
+    }
+
+    /// Finish a login from the provider's callback, returning the user name.
+    /// `browser_state` is the state remembered by the browser making the
+    /// callback, which must be the one the login was started with.
+    pub async fn finish_login(
+        &self,
+        code: &str,
+        state: &str,
+        browser_state: Option<&str>,
+    ) -> Result<String, OidcError> {
+        if browser_state != Some(state) {
+            return Err(OidcError::StateMismatch);
+        }
+        let login = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            pending
+                .remove(state)
+                .filter(|login| login.expires > Instant::now())
+                .ok_or(OidcError::UnknownState)?
+        };
+        let discovery = self.discovery().await?;
+
+        let mut form = vec![
+            ("grant_type".to_string(), "authorization_code".to_string()),
+            ("code".to_string(), code.to_string()),
+            ("redirect_uri".to_string(), self.config.redirect_url.clone()),
+            ("client_id".to_string(), self.config.client_id.clone()),
+            ("code_verifier".to_string(), login.verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret".to_string(), secret.clone()));
+        }
+
+        let request = self.agent.post(&discovery.token_endpoint);
+        let response: Value = tokio::task::spawn_blocking(move || {
+            let form: Vec<(&str, &str)> = form
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect();
+            match request.send_form(&form) {
+                Ok(response) => {
+                    let body = response.into_string().map_err(|e| e.to_string())?;
+                    serde_json::from_str::<Value>(&body).map_err(|e| e.to_string())
+                }
+                Err(ureq::Error::Status(status, response)) => Err(format!(
+                    "token endpoint returned {}: {}",
+                    status,
+                    response.into_string().unwrap_or_default()
+                )),
+                Err(e) => Err(e.to_string()),
+            }
+        })
+        .await
+        .map_err(|e| OidcError::Provider(e.to_string()))?
+        .map_err(OidcError::Provider)?;
+
+        let id_token = response
+            .get("id_token")
+            .and_then(Value::as_str)
+            .ok_or_else(|| OidcError::Provider("token response has no id_token".to_string()))?;
+        let claims = validate_id_token(
+            id_token,
+            &discovery.issuer,
+            &self.config.client_id,
+            &login.nonce,
+            chrono::Utc::now().timestamp(),
+        )?;
+
+        let user = user_name(&claims);
+        if !self.config.allowed_groups.is_empty() {
+            let groups = groups(&claims, &self.config.groups_claim);
+            if !self
+                .config
+                .allowed_groups
+                .iter()
+                .any(|allowed| groups.contains(allowed))
+            {
+                return Err(OidcError::NotAllowed(user));
+            }
+        }
+        Ok(user)
+    }
+
+    /// Provider endpoints, fetched on first use and kept once they load
+    async fn discovery(&self) -> Result<&Discovery, OidcError> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+                let request = self.agent.get(&url);
+                let discovery = tokio::task::spawn_blocking(move || {
+                    let body = request
+                        .call()
+                        .map_err(|e| e.to_string())?
+                        .into_string()
+                        .map_err(|e| e.to_string())?;
+                    serde_json::from_str::<Discovery>(&body).map_err(|e| e.to_string())
+                })
+                .await
+                .map_err(|e| OidcError::Provider(e.to_string()))?
+                .map_err(|e| OidcError::Provider(format!("{url}: {e}")))?;
+                for endpoint in [&url, &discovery.token_endpoint] {
+                    if !is_secure_url(endpoint) {
+                        return Err(OidcError::Provider(format!("{endpoint} is not HTTPS")));
+                    }
+                }
+                Ok(discovery)
+            })
+            .await
+    }
+}
+
+/// Whether `url` is HTTPS, or plain HTTP to the loopback interface
+pub fn is_secure_url(url: &str) -> bool {
+    if url.starts_with("https://") {
+        return true;
+    }
+    let Some(rest) = url.strip_prefix("http://") else {
+        return false;
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority.rsplit('@').next().unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+fn random_token() -> String {
+    uuid::Uuid::new_v4().as_simple().to_string()
+}
+
+/// Decode an ID token and check the claims that bind it to this login
+fn validate_id_token(
+    token: &str,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<Value, OidcError> {
+    let invalid = |reason: &str| OidcError::InvalidToken(reason.to_string());
+
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| invalid("not a JWT"))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|_| invalid("payload is not base64url"))?;
+    let claims: Value =
+        serde_json::from_slice(&payload).map_err(|_| invalid("payload is not JSON"))?;
+
+    if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+        return Err(invalid("issued by a different provider"));
+    }
+    let audience_matches = match claims.get("aud") {
+        Some(Value::String(aud)) => aud == client_id,
+        Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(client_id)),
+        _ => false,
+    };
+    if !audience_matches {
+        return Err(invalid("issued to a different client"));
+    }
+    match claims.get("exp").and_then(Value::as_i64) {
+        Some(exp) if exp > now => {}
+        _ => return Err(invalid("expired")),
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err(invalid("nonce does not match this login"));
+    }
+    if claims.get("sub").and_then(Value::as_str).is_none() {
+        return Err(invalid("no subject"));
+    }
+
+    Ok(claims)
+}
+
+/// Most readable identifier the provider sent
+fn user_name(claims: &Value) -> String {
+    ["preferred_username", "email", "sub"]
+        .iter()
+        .find_map(|claim| claims.get(*claim).and_then(Value::as_str))
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Groups from a claim holding a list or a single name
+fn groups(claims: &Value, claim: &str) -> Vec<String> {
+    match claims.get(claim) {
+        Some(Value::Array(groups)) => groups
+            .iter()
+            .filter_map(|group| group.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::String(group)) => vec![group.clone()],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn token(claims: Value) -> String {
+        format!(
+            "{}.{}.sig",
+            URL_SAFE_NO_PAD.encode(br#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": "https://sso.example.com/application/o/filex",
+            "aud": "filex",
+            "exp": 2000,
+            "nonce": "n1",
+            "sub": "42",
+            "preferred_username": "alex",
+            "groups": ["media", "admins"],
+        })
+    }
+
+    fn validate(claims: Value) -> Result<Value, OidcError> {
+        validate_id_token(
+            &token(claims),
+            "https://sso.example.com/application/o/filex",
+            "filex",
+            "n1",
+            1000,
+        )
+    }
+
+    #[test]
+    fn accepts_token_for_this_login() {
+        let claims = validate(claims()).unwrap();
+        assert_eq!(user_name(&claims), "alex");
+        assert_eq!(groups(&claims, "groups"), ["media", "admins"]);
+
+        let mut listed = self::claims();
+        listed["aud"] = json!(["other", "filex"]);
+        assert!(validate(listed).is_ok());
+    }
+
+    #[test]
+    fn rejects_tokens_not_meant_for_this_login() {
+        for (claim, value) in [
+            ("iss", json!("https://evil.example.com")),
+            ("aud", json!("other")),
+            ("exp", json!(999)),
+            ("nonce", json!("replayed")),
+        ] {
+            let mut claims = claims();
+            claims[claim] = value;
+            assert!(
+                matches!(validate(claims), Err(OidcError::InvalidToken(_))),
+                "{claim} was not checked"
+            );
+        }
+        assert!(validate_id_token("garbage", "x", "y", "z", 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn callback_without_matching_login_is_refused() {
+        let client = OidcClient::new(OidcConfig {
+            issuer: "http://127.0.0.1:9".to_string(),
+            client_id: "filex".to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost/api/auth/oidc/callback".to_string(),
+            groups_claim: "groups".to_string(),
+            allowed_groups: Vec::new(),
+        });
+        assert!(matches!(
+            client.finish_login("code", "forged", Some("forged")).await,
+            Err(OidcError::UnknownState)
+        ));
+        // A callback with a login another browser started
+        assert!(matches!(
+            client.finish_login("code", "theirs", Some("mine")).await,
+            Err(OidcError::StateMismatch)
+        ));
+        assert!(matches!(
+            client.finish_login("code", "theirs", None).await,
+            Err(OidcError::StateMismatch)
+        ));
+    }
+
+    #[test]
+    fn providers_must_be_reached_over_https() {
+        for url in [
+            "https://sso.example.com/application/o/filex",
+            "http://localhost:9000/realms/home",
+            "http://127.0.0.1:9",
+            "http://[::1]:8080/",
+        ] {
+            assert!(is_secure_url(url), "{url}");
+        }
+        for url in [
+            "http://sso.example.com",
+            "http://localhost.example.com",
+            "http://192.168.1.10:9000",
+            "http://127.0.0.1@sso.example.com",
+            "ftp://localhost",
+        ] {
+            assert!(!is_secure_url(url), "{url}");
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Changing mode bits and ownership of entries in the root, for servers
+//! running as root (e.g. on a NAS) where files arrive with the wrong
+//! permissions. Unix only.
+
+use serde::Serialize;
+use std::path::Path;
+use thiserror::Error;
+
+use crate::services::DedupStore;
+use crate::services::filesystem::{FilesystemService, FsError, canonical_name};
+
+/// Most entries one change may touch, so a stray `recursive` on a huge tree
+/// is refused before anything changes
+pub const MAX_PERMISSION_ENTRIES: usize = 50_000;
+
+#[derive(Debug, Error)]
+pub enum PermissionError {
+    #[error("Changing permissions is only supported on Unix")]
+    Unsupported,
+
+    #[error("Invalid mode '{0}', expected octal bits from 000 to 777")]
+    InvalidMode(String),
+
+    #[error("Nothing to change; give a mode, uid or gid")]
+    NothingToChange,
+
+    #[error("More than {0} entries would change; pick a smaller folder")]
+    TooManyEntries(usize),
+
+    #[error(transparent)]
+    Fs(#[from] FsError),
+}
+
+/// What to set; unset parts are left alone
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PermissionChange {
+    /// Mode bits for files, and for directories unless `dir_mode` is set
+    pub mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl PermissionChange {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Mode bits from octal text such as `644` or `0o755`
+pub fn parse_mode(text: &str) -> Result<u32, PermissionError> {
+    let digits = text.trim().trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| !digits.is_empty() && *mode <= 0o777)
+        .ok_or_else(|| PermissionError::InvalidMode(text.to_string()))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PermissionResult {
+    pub path: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Apply `change` to an entry, and with `recursive` to everything below it.
+/// Symlinks are never followed: their ownership changes, their mode (which
+/// Unix ignores) does not. Files sharing their contents through `dedup` get
+/// a copy of their own first, so the change does not reach the others. Each
+/// entry is reported; one failure does not stop the rest.
+pub fn change_permissions(
+    fs: &FilesystemService,
+    dedup: &DedupStore,
+    relative_path: &str,
+    change: PermissionChange,
+    recursive: bool,
+) -> Result<Vec<PermissionResult>, PermissionError> {
+    if cfg!(not(unix)) {
+        return Err(PermissionError::Unsupported);
+    }
+    if change.is_empty() {
+        return Err(PermissionError::NothingToChange);
+    }
+    let top = fs.resolve_path(relative_path)?;
+    let entries: Vec<walkdir::DirEntry> = walkdir::WalkDir::new(&top)
+        .follow_links(false)
+        .max_depth(if recursive { usize::MAX } else { 0 })
+        .into_iter()
+        .filter_map(Result::ok)
+        .take(MAX_PERMISSION_ENTRIES + 1)
+        .collect();
+    if entries.len() > MAX_PERMISSION_ENTRIES {
+        return Err(PermissionError::TooManyEntries(MAX_PERMISSION_ENTRIES));
+    }
+
+    let root = fs.root().canonicalize().map_err(FsError::Io)?;
+    Ok(entries
+        .iter()
+        .map(|entry| {
+            let outcome = dedup
+                .unshare(entry.path())
+                .map_err(|e| PermissionError::Fs(FsError::Io(e)))
+                .and_then(|_| apply(entry.path(), entry.file_type(), &change));
+            PermissionResult {
+                path: relative_to_root(&root, entry.path()),
+                ok: outcome.is_ok(),
+                error: outcome.err().map(|e| e.to_string()),
+            }
+        })
+        .collect())
+}
+
+/// Root-relative path of an entry, without resolving a symlink at the end
+fn relative_to_root(root: &Path, path: &Path) -> String {
+    let relative = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string();
+    format!("/{}", canonical_name(&relative))
+}
+
+#[cfg(unix)]
+fn apply(
+    path: &Path,
+    file_type: std::fs::FileType,
+    change: &PermissionChange,
+) -> Result<(), PermissionError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let io = |e: std::io::Error| PermissionError::Fs(FsError::Io(e));
+    if change.uid.is_some() || change.gid.is_some() {
+        std::os::unix::fs::lchown(path, change.uid, change.gid).map_err(io)?;
+    }
+    // After chown, which may clear setuid and setgid bits
+    let mode = if file_type.is_symlink() {
+        None
+    } else if file_type.is_dir() {
+        change.dir_mode.or(change.mode)
+    } else {
+        change.mode
+    };
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(io)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply(
+    _path: &Path,
+    _file_type: std::fs::FileType,
+    _change: &PermissionChange,
+) -> Result<(), PermissionError> {
+    Err(PermissionError::Unsupported)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use tempfile::tempdir;
+
+    #[test]
+    fn modes_parse_as_octal() {
+        assert_eq!(parse_mode("644").unwrap(), 0o644);
+        assert_eq!(parse_mode("0o755").unwrap(), 0o755);
+        assert_eq!(parse_mode("0750").unwrap(), 0o750);
+        for bad in ["", "888", "4755", "rwx"] {
+            assert!(parse_mode(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn changes_apply_to_each_entry_below_the_path() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("share/sub")).unwrap();
+        fs::write(root.join("share/a.txt"), b"a").unwrap();
+        fs::write(root.join("share/sub/b.txt"), b"b").unwrap();
+        std::os::unix::fs::symlink("a.txt", root.join("share/link")).unwrap();
+        let service = FilesystemService::new(root.clone());
+        let mode = |path: &str| fs::metadata(root.join(path)).unwrap().permissions().mode() & 0o777;
+        let owner = fs::metadata(&root).unwrap();
+
+        let results = change_permissions(
+            &service,
+            &DedupStore::disabled(),
+            "/share",
+            PermissionChange {
+                mode: Some(0o640),
+                dir_mode: Some(0o750),
+                uid: Some(owner.uid()),
+                gid: Some(owner.gid()),
+            },
+            true,
+        )
+        .unwrap();
+        let mut paths: Vec<&str> = results.iter().map(|r| r.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "/share",
+                "/share/a.txt",
+                "/share/link",
+                "/share/sub",
+                "/share/sub/b.txt"
+            ]
+        );
+        assert!(results.iter().all(|r| r.ok), "{results:?}");
+        assert_eq!(mode("share"), 0o750);
+        assert_eq!(mode("share/sub/b.txt"), 0o640);
+
+        let results = change_permissions(
+            &service,
+            &DedupStore::disabled(),
+            "/share",
+            PermissionChange {
+                mode: Some(0o755),
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(mode("share"), 0o755);
+        assert_eq!(mode("share/sub"), 0o750);
+
+        assert!(matches!(
+            change_permissions(
+                &service,
+                &DedupStore::disabled(),
+                "/share",
+                PermissionChange::default(),
+                true
+            ),
+            Err(PermissionError::NothingToChange)
+        ));
+    }
+
+    #[test]
+    fn changes_do_not_reach_other_deduplicated_uploads() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("share")).unwrap();
+        let dedup = DedupStore::new(tmp.path().join("store"));
+        for name in ["share/a.jpg", "b.jpg"] {
+            let staged = root.join(".staged");
+            fs::write(&staged, b"same").unwrap();
+            fs::set_permissions(&staged, fs::Permissions::from_mode(0o644)).unwrap();
+            dedup.store(&staged, &root.join(name)).unwrap();
+        }
+        let service = FilesystemService::new(root.clone());
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let results = change_permissions(
+            &service,
+            &dedup,
+            "/share",
+            PermissionChange {
+                mode: Some(0o600),
+                ..Default::default()
+            },
+            true,
+        )
+        .unwrap();
+        assert!(results.iter().all(|r| r.ok), "{results:?}");
+        assert_eq!(mode(&root.join("share/a.jpg")), 0o600);
+        assert_eq!(fs::read(root.join("share/a.jpg")).unwrap(), b"same");
+        assert_eq!(mode(&root.join("b.jpg")), 0o644);
+        let objects: Vec<_> = walkdir::WalkDir::new(tmp.path().join("store"))
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|e| e.file_type().is_file())
+            .collect();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(mode(objects[0].path()), 0o644);
+    }
+}
@@ -0,0 +1,71 @@
+//! Per-directory preview opt-out. An empty `.fxnopreview` file in a directory
+//! stops thumbnails and metadata extraction for everything below it, for
+//! trees that are sensitive or too large to be worth processing.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const NO_PREVIEW_MARKER: &str = ".fxnopreview";
+
+/// Whether `path` or any ancestor up to `root` contains the marker
+pub fn previews_disabled(root: &Path, path: &Path) -> bool {
+    path.ancestors()
+        .take_while(|ancestor| ancestor.starts_with(root))
+        .any(|ancestor| ancestor.join(NO_PREVIEW_MARKER).is_file())
+}
+
+/// Memoized [`previews_disabled`] for checking many files, keyed by parent
+/// directory so each directory is looked up once per pass
+pub struct PreviewOptOut {
+    root: PathBuf,
+    cache: HashMap<PathBuf, bool>,
+}
+
+impl PreviewOptOut {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn is_disabled(&mut self, file: &Path) -> bool {
+        let Some(dir) = file.parent() else {
+            return false;
+        };
+        if let Some(&disabled) = self.cache.get(dir) {
+            return disabled;
+        }
+        let disabled = previews_disabled(&self.root, dir);
+        self.cache.insert(dir.to_path_buf(), disabled);
+        disabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn marker_applies_to_descendants_only() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("raw/day1")).unwrap();
+        fs::create_dir_all(root.join("photos")).unwrap();
+        fs::write(root.join("raw").join(NO_PREVIEW_MARKER), b"").unwrap();
+
+        assert!(previews_disabled(root, &root.join("raw")));
+        assert!(previews_disabled(root, &root.join("raw/day1")));
+        assert!(!previews_disabled(root, &root.join("photos")));
+        assert!(!previews_disabled(root, root));
+        // A marker above the root does not count
+        let day1 = root.join("raw/day1");
+        assert!(!previews_disabled(&day1, &day1));
+
+        let mut opt_out = PreviewOptOut::new(root.to_path_buf());
+        assert!(opt_out.is_disabled(&root.join("raw/day1/clip.mov")));
+        assert!(!opt_out.is_disabled(&root.join("photos/a.jpg")));
+    }
+}
@@ -0,0 +1,326 @@
+//! The advanced search syntax, used when a search asks for
+//! `syntax=advanced`:
+//!
+//! ```text
+//! ext:mp4,mov size>1gb modified<2023-01-01 "exact phrase" -excluded
+//! ```
+//!
+//! Plain words and quoted phrases must all appear in a match, as in a simple
+//! search; a leading `-` rules a word or phrase out. `ext:`, `size`,
+//! `modified` and `is:` become database filters. Any other `key:value` is
+//! searched for as written, so paths like `c:` or times like `10:30` still
+//! work.
+
+use chrono::{Datelike, NaiveDate};
+use thiserror::Error;
+
+use crate::db::FileFilter;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum QueryError {
+    #[error("Invalid size '{0}', expected e.g. 500kb or 1.5gb")]
+    InvalidSize(String),
+
+    #[error("Invalid date '{0}', expected YYYY, YYYY-MM or YYYY-MM-DD")]
+    InvalidDate(String),
+
+    #[error("Invalid extension '{0}'")]
+    InvalidExtension(String),
+
+    #[error("Invalid value '{0}' for is:, expected dir or file")]
+    InvalidKind(String),
+
+    #[error("'{0}' only takes ':', as in {0}:value")]
+    InvalidOperator(String),
+}
+
+/// A parsed advanced query
+#[derive(Debug, Default, PartialEq)]
+pub struct AdvancedQuery {
+    /// Words and phrases every match contains
+    pub terms: Vec<String>,
+    /// Words and phrases no match contains
+    pub excluded: Vec<String>,
+    pub filters: Vec<FileFilter>,
+}
+
+impl AdvancedQuery {
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let mut parsed = Self::default();
+        for token in tokenize(query) {
+            if token.negated {
+                parsed.excluded.push(token.text);
+            } else if token.quoted || !parse_filter(&token.text, &mut parsed.filters)? {
+                parsed.terms.push(token.text);
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+struct Token {
+    text: String,
+    /// Started with `-`
+    negated: bool,
+    /// Held a quoted part, so it is taken literally
+    quoted: bool,
+}
+
+/// Split on whitespace outside double quotes; an unclosed quote runs to
+/// the end of the query
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return tokens;
+        }
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut text = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            if c == '"' {
+                quoted = true;
+                text.extend(chars.by_ref().take_while(|&c| c != '"'));
+            } else if c.is_whitespace() {
+                break;
+            } else {
+                text.push(c);
+            }
+        }
+        if !text.is_empty() {
+            tokens.push(Token {
+                text,
+                negated,
+                quoted,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Split `size>=1gb` into key, operator and value
+fn split_condition(text: &str) -> Option<(&str, Op, &str)> {
+    let at = text.find([':', '<', '>', '='])?;
+    let (key, rest) = text.split_at(at);
+    let (op, value) = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+        ("=", Op::Eq),
+        (":", Op::Eq),
+    ]
+    .into_iter()
+    .find_map(|(prefix, op)| rest.strip_prefix(prefix).map(|value| (op, value)))?;
+    Some((key, op, value))
+}
+
+/// Add the filters a `key:value` token stands for. Returns false when the
+/// token is not a filter and should be searched for as text.
+fn parse_filter(text: &str, filters: &mut Vec<FileFilter>) -> Result<bool, QueryError> {
+    let Some((key, op, value)) = split_condition(text) else {
+        return Ok(false);
+    };
+    let key = key.to_ascii_lowercase();
+    let only_eq = |op: Op| {
+        if op == Op::Eq {
+            Ok(())
+        } else {
+            Err(QueryError::InvalidOperator(key.clone()))
+        }
+    };
+    match key.as_str() {
+        "ext" => {
+            only_eq(op)?;
+            let extensions = value
+                .split(',')
+                .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
+                .map(|ext| {
+                    if !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()) {
+                        Ok(ext)
+                    } else {
+                        Err(QueryError::InvalidExtension(ext))
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            filters.push(FileFilter::Extension(extensions));
+        }
+        "size" => {
+            let bytes = parse_size(value)?;
+            filters.extend(match op {
+                Op::Eq => vec![FileFilter::MinSize(bytes), FileFilter::MaxSize(bytes)],
+                Op::Lt => vec![FileFilter::MaxSize(bytes.saturating_sub(1))],
+                Op::Le => vec![FileFilter::MaxSize(bytes)],
+                Op::Gt => vec![FileFilter::MinSize(bytes.saturating_add(1))],
+                Op::Ge => vec![FileFilter::MinSize(bytes)],
+            });
+        }
+        "modified" => {
+            let (start, end) = parse_date(value)?;
+            let bound = |date: NaiveDate| format!("{date}T00:00:00");
+            filters.extend(match op {
+                Op::Eq => vec![
+                    FileFilter::ModifiedFrom(bound(start)),
+                    FileFilter::ModifiedBefore(bound(end)),
+                ],
+                Op::Lt => vec![FileFilter::ModifiedBefore(bound(start))],
+                Op::Le => vec![FileFilter::ModifiedBefore(bound(end))],
+                Op::Gt => vec![FileFilter::ModifiedFrom(bound(end))],
+                Op::Ge => vec![FileFilter::ModifiedFrom(bound(start))],
+            });
+        }
+        "is" => {
+            only_eq(op)?;
+            let is_dir = match value.to_ascii_lowercase().as_str() {
+                "dir" | "folder" => true,
+                "file" => false,
+                _ => return Err(QueryError::InvalidKind(value.to_string())),
+            };
+            filters.push(FileFilter::IsDir(is_dir));
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Bytes in a size like `700`, `500kb` or `1.5g`, counting in 1024s
+fn parse_size(value: &str) -> Result<i64, QueryError> {
+    let invalid = || QueryError::InvalidSize(value.to_string());
+    let split = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: f64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        "t" | "tb" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(invalid()),
+    };
+    if !number.is_finite() || number < 0.0 {
+        return Err(invalid());
+    }
+    Ok((number * multiplier).round() as i64)
+}
+
+/// First day of the year, month or day `value` names, and the first day
+/// after it
+fn parse_date(value: &str) -> Result<(NaiveDate, NaiveDate), QueryError> {
+    let invalid = || QueryError::InvalidDate(value.to_string());
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts[0].len() != 4 || parts.len() > 3 {
+        return Err(invalid());
+    }
+    let numbers = parts
+        .iter()
+        .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let year = numbers[0] as i32;
+    let start = NaiveDate::from_ymd_opt(
+        year,
+        numbers.get(1).copied().unwrap_or(1),
+        numbers.get(2).copied().unwrap_or(1),
+    )
+    .ok_or_else(invalid)?;
+    let end = match numbers.len() {
+        1 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+        2 if start.month() == 12 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+        2 => NaiveDate::from_ymd_opt(year, start.month() + 1, 1),
+        _ => start.succ_opt(),
+    }
+    .ok_or_else(invalid)?;
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_terms_phrases_exclusions_and_filters() {
+        let query = AdvancedQuery::parse(
+            r#"ext:MP4,.mov size>1gb modified<2023-01-01 "exact phrase" -excluded -"two words" c:\dir"#,
+        )
+        .unwrap();
+        assert_eq!(query.terms, ["exact phrase", r"c:\dir"]);
+        assert_eq!(query.excluded, ["excluded", "two words"]);
+        assert_eq!(
+            query.filters,
+            [
+                FileFilter::Extension(vec!["mp4".to_string(), "mov".to_string()]),
+                FileFilter::MinSize(1024 * 1024 * 1024 + 1),
+                FileFilter::ModifiedBefore("2023-01-01T00:00:00".to_string()),
+            ]
+        );
+
+        // Quoted conditions are plain text
+        let query = AdvancedQuery::parse(r#""size>1gb" - """#).unwrap();
+        assert_eq!(query.terms, ["size>1gb"]);
+        assert!(query.excluded.is_empty() && query.filters.is_empty());
+    }
+
+    #[test]
+    fn dates_cover_the_whole_period_they_name() {
+        let filters = |q: &str| AdvancedQuery::parse(q).unwrap().filters;
+        assert_eq!(
+            filters("modified:2023-12"),
+            [
+                FileFilter::ModifiedFrom("2023-12-01T00:00:00".to_string()),
+                FileFilter::ModifiedBefore("2024-01-01T00:00:00".to_string()),
+            ]
+        );
+        assert_eq!(
+            filters("modified<=2024"),
+            [FileFilter::ModifiedBefore(
+                "2025-01-01T00:00:00".to_string()
+            )]
+        );
+        assert_eq!(
+            filters("modified>2024-02-29"),
+            [FileFilter::ModifiedFrom("2024-03-01T00:00:00".to_string())]
+        );
+        assert_eq!(
+            filters("size<=1.5k is:dir"),
+            [FileFilter::MaxSize(1536), FileFilter::IsDir(true)]
+        );
+        // Sizes too large to count in bytes saturate instead of overflowing
+        assert_eq!(
+            filters("size>99999999999tb"),
+            [FileFilter::MinSize(i64::MAX)]
+        );
+        assert_eq!(filters("size<0"), [FileFilter::MaxSize(-1)]);
+    }
+
+    #[test]
+    fn malformed_filters_are_rejected() {
+        for (query, err) in [
+            ("size>big", QueryError::InvalidSize("big".to_string())),
+            ("size>1pb", QueryError::InvalidSize("1pb".to_string())),
+            (
+                "modified<23-1-1",
+                QueryError::InvalidDate("23-1-1".to_string()),
+            ),
+            (
+                "modified:2023-02-30",
+                QueryError::InvalidDate("2023-02-30".to_string()),
+            ),
+            ("ext:", QueryError::InvalidExtension(String::new())),
+            ("ext>mp4", QueryError::InvalidOperator("ext".to_string())),
+            ("is:link", QueryError::InvalidKind("link".to_string())),
+        ] {
+            assert_eq!(AdvancedQuery::parse(query), Err(err), "{query}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Readahead for media played over Range requests. Players that fetch a file
+//! in consecutive chunks wait on every seek of a spinning disk; once a range
+//! continues the previous one, the next stretch of the file is hinted to the
+//! kernel so it is in the page cache before the player asks for it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// A range starting this close after the previous one's end still counts as
+/// sequential, since players skip over bytes they do not need
+const SEQUENTIAL_GAP: u64 = 1024 * 1024;
+
+/// How long a file's last range is remembered
+const FORGET_AFTER: Duration = Duration::from_secs(60);
+
+/// Most files tracked at once
+const MAX_TRACKED: usize = 1024;
+
+#[derive(Clone, Copy)]
+struct LastRange {
+    end: u64,
+    at: Instant,
+}
+
+#[derive(Clone, Default)]
+pub struct Readahead {
+    /// Bytes to prefetch past a sequential range; 0 disables readahead
+    window: u64,
+    recent: Arc<Mutex<HashMap<PathBuf, LastRange>>>,
+}
+
+impl Readahead {
+    /// No readahead
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            ..Self::default()
+        }
+    }
+
+    /// Readahead of `FM_READAHEAD_MB`, if set
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.readahead_mb * 1024 * 1024)
+    }
+
+    /// Record that bytes `start..=end` of `path` were requested. Returns the
+    /// offset and length to prefetch when the range continues the previous
+    /// one.
+    pub fn on_range(
+        &self,
+        path: &Path,
+        start: u64,
+        end: u64,
+        file_size: u64,
+    ) -> Option<(u64, u64)> {
+        if self.window == 0 {
+            return None;
+        }
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if recent.len() >= MAX_TRACKED {
+            recent.retain(|_, last| now.duration_since(last.at) < FORGET_AFTER);
+        }
+        let previous = recent.insert(path.to_path_buf(), LastRange { end, at: now });
+
+        let sequential = previous.is_some_and(|last| {
+            now.duration_since(last.at) < FORGET_AFTER
+                && start > last.end
+                && start - last.end <= SEQUENTIAL_GAP
+        });
+        let offset = end + 1;
+        (sequential && offset < file_size).then(|| (offset, self.window.min(file_size - offset)))
+    }
+}
+
+/// Ask the kernel to read `len` bytes of `path` from `offset` into the page
+/// cache in the background
+#[cfg(target_os = "linux")]
+pub fn prefetch(path: &Path, offset: u64, len: u64) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    // SAFETY: the descriptor is open for the duration of the call
+    let result = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            libc::POSIX_FADV_WILLNEED,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn prefetch(_path: &Path, _offset: u64, _len: u64) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_ranges_continuing_the_previous_one_prefetch() {
+        let readahead = Readahead::new(4096);
+        let movie = Path::new("/media/movie.mkv");
+        let size = 10_000;
+
+        assert_eq!(readahead.on_range(movie, 0, 999, size), None);
+        assert_eq!(
+            readahead.on_range(movie, 1000, 1999, size),
+            Some((2000, 4096))
+        );
+        // Close to the end, the window stops at the end of the file
+        assert_eq!(
+            readahead.on_range(movie, 2000, 7999, size),
+            Some((8000, 2000))
+        );
+        assert_eq!(readahead.on_range(movie, 8000, 9999, size), None);
+
+        // A seek backwards is not sequential
+        assert_eq!(readahead.on_range(movie, 100, 199, size), None);
+        // Nor is the same range on another file
+        let other = Path::new("/media/other.mkv");
+        assert_eq!(readahead.on_range(other, 200, 299, size), None);
+
+        let disabled = Readahead::disabled();
+        disabled.on_range(movie, 0, 999, size);
+        assert_eq!(disabled.on_range(movie, 1000, 1999, size), None);
+    }
+}
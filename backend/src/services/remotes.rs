@@ -0,0 +1,403 @@
+//! Copying a directory to a cloud remote with rclone. filex does not store
+//! remote credentials itself: it uses the remotes already set up in
+//! rclone's own config (`rclone config`) and only runs `rclone sync` or
+//! `rclone copy`, reading progress from rclone's JSON log.
+
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::services::filesystem::FilesystemService;
+
+/// How long `rclone listremotes` may take
+const LIST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Finished syncs kept for the status endpoints
+const FINISHED_HISTORY: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteSyncStatus {
+    Running,
+    Success,
+    Failed,
+}
+
+/// Transfer totals from rclone's last stats line
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"), default)]
+pub struct RemoteProgress {
+    pub bytes: u64,
+    pub total_bytes: u64,
+    pub transfers: u64,
+    pub total_transfers: u64,
+    pub checks: u64,
+    pub deletes: u64,
+    pub errors: u64,
+    /// Bytes per second
+    pub speed: f64,
+    /// Seconds left, when rclone can tell
+    pub eta: Option<f64>,
+}
+
+/// One run of rclone, in progress or finished
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteSync {
+    pub id: u64,
+    /// Directory below the root being copied
+    pub source: String,
+    pub remote: String,
+    pub remote_path: String,
+    /// `rclone sync`, removing files the source does not have, rather than
+    /// `rclone copy`
+    pub delete_extraneous: bool,
+    pub status: RemoteSyncStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub progress: RemoteProgress,
+    /// Last error rclone logged, or why it could not run
+    pub error: Option<String>,
+}
+
+/// What a line of rclone's `--use-json-log` output says
+#[derive(Debug, PartialEq)]
+pub enum LogEvent {
+    Stats(RemoteProgress),
+    Error(String),
+}
+
+#[derive(Deserialize)]
+struct LogLine {
+    #[serde(default)]
+    level: String,
+    #[serde(default)]
+    msg: String,
+    stats: Option<RemoteProgress>,
+}
+
+/// Read a line of rclone's JSON log; other lines are ignored
+pub fn parse_log_line(line: &str) -> Option<LogEvent> {
+    let line: LogLine = serde_json::from_str(line.trim()).ok()?;
+    if let Some(stats) = line.stats {
+        return Some(LogEvent::Stats(stats));
+    }
+    matches!(line.level.as_str(), "error" | "critical")
+        .then(|| LogEvent::Error(line.msg.trim().to_string()))
+}
+
+/// Remote names from `rclone listremotes`, which prints `name:` per line
+fn parse_remotes(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_suffix(':'))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub struct RemoteSyncService {
+    /// rclone binary; `None` turns the feature off
+    binary: Option<PathBuf>,
+    fs: FilesystemService,
+    syncs: Mutex<VecDeque<RemoteSync>>,
+    next_id: AtomicU64,
+}
+
+impl RemoteSyncService {
+    pub fn new(binary: Option<PathBuf>, fs: FilesystemService) -> Self {
+        Self {
+            binary,
+            fs,
+            syncs: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            config.rclone_path.clone(),
+            FilesystemService::new(config.root_path.clone()),
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.binary.is_some()
+    }
+
+    pub fn fs(&self) -> &FilesystemService {
+        &self.fs
+    }
+
+    fn command(&self) -> anyhow::Result<Command> {
+        let Some(binary) = &self.binary else {
+            bail!("rclone is not configured");
+        };
+        let mut command = Command::new(binary);
+        command.stdin(Stdio::null()).kill_on_drop(true);
+        Ok(command)
+    }
+
+    /// Remotes set up in rclone's config
+    pub async fn list_remotes(&self) -> anyhow::Result<Vec<String>> {
+        let output = self.command()?.arg("listremotes").output();
+        let output = tokio::time::timeout(LIST_TIMEOUT, output)
+            .await
+            .context("rclone listremotes timed out")?
+            .context("failed to run rclone")?;
+        if !output.status.success() {
+            bail!(
+                "rclone listremotes failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(parse_remotes(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Syncs in progress and recently finished, newest first
+    pub fn syncs(&self) -> Vec<RemoteSync> {
+        self.lock().iter().rev().cloned().collect()
+    }
+
+    pub fn get_sync(&self, id: u64) -> Option<RemoteSync> {
+        self.lock().iter().find(|s| s.id == id).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<RemoteSync>> {
+        self.syncs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut RemoteSync)) {
+        if let Some(sync) = self.lock().iter_mut().find(|s| s.id == id) {
+            f(sync);
+        }
+    }
+
+    /// Start copying the directory `source` (below the root) to
+    /// `remote:remote_path` in the background. Returns `None` if the same
+    /// destination is already being synced. The remote must be one
+    /// `list_remotes` returns.
+    pub fn start_sync(
+        self: &Arc<Self>,
+        source: &str,
+        remote: &str,
+        remote_path: &str,
+        delete_extraneous: bool,
+    ) -> anyhow::Result<Option<RemoteSync>> {
+        let local = self.fs.resolve_path(source)?;
+        let mut command = self.command()?;
+        command
+            .arg(if delete_extraneous { "sync" } else { "copy" })
+            .arg(&local)
+            .arg(format!("{remote}:{remote_path}"))
+            .args([
+                "--use-json-log",
+                "--stats",
+                "1s",
+                "--stats-log-level",
+                "NOTICE",
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        let sync = {
+            let mut syncs = self.lock();
+            if syncs.iter().any(|s| {
+                s.status == RemoteSyncStatus::Running
+                    && s.remote == remote
+                    && s.remote_path == remote_path
+            }) {
+                return Ok(None);
+            }
+            let sync = RemoteSync {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                source: self.fs.relative_path(&local),
+                remote: remote.to_string(),
+                remote_path: remote_path.to_string(),
+                delete_extraneous,
+                status: RemoteSyncStatus::Running,
+                started_at: Utc::now(),
+                finished_at: None,
+                progress: RemoteProgress::default(),
+                error: None,
+            };
+            syncs.push_back(sync.clone());
+            // Drop the oldest finished syncs beyond the history limit
+            while syncs.len() > FINISHED_HISTORY {
+                match syncs
+                    .iter()
+                    .position(|s| s.status != RemoteSyncStatus::Running)
+                {
+                    Some(i) => drop(syncs.remove(i)),
+                    None => break,
+                }
+            }
+            sync
+        };
+
+        info!(
+            "Starting rclone {} of {} to {}:{}",
+            if delete_extraneous { "sync" } else { "copy" },
+            sync.source,
+            remote,
+            remote_path
+        );
+        let service = self.clone();
+        let id = sync.id;
+        tokio::spawn(async move {
+            let (status, error) = service.run(id, command).await;
+            if let Some(error) = &error {
+                warn!("rclone sync {} failed: {}", id, error);
+            }
+            service.update(id, |sync| {
+                sync.status = status;
+                sync.finished_at = Some(Utc::now());
+                if error.is_some() {
+                    sync.error = error;
+                }
+            });
+        });
+        Ok(Some(sync))
+    }
+
+    /// Run rclone to completion, following its log
+    async fn run(&self, id: u64, mut command: Command) -> (RemoteSyncStatus, Option<String>) {
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                return (
+                    RemoteSyncStatus::Failed,
+                    Some(format!("failed to start rclone: {e}")),
+                );
+            }
+        };
+        if let Some(stderr) = child.stderr.take() {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                match parse_log_line(&line) {
+                    Some(LogEvent::Stats(progress)) => {
+                        self.update(id, |sync| sync.progress = progress)
+                    }
+                    Some(LogEvent::Error(message)) => {
+                        self.update(id, |sync| sync.error = Some(message))
+                    }
+                    None => {}
+                }
+            }
+        }
+        match child.wait().await {
+            Ok(status) if status.success() => (RemoteSyncStatus::Success, None),
+            Ok(status) => (
+                RemoteSyncStatus::Failed,
+                // The last logged error says more than the exit code
+                self.get_sync(id)
+                    .and_then(|s| s.error)
+                    .or_else(|| Some(format!("rclone exited with {status}"))),
+            ),
+            Err(e) => (RemoteSyncStatus::Failed, Some(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stats_and_errors_from_the_json_log() {
+        let stats = r#"{"time":"2024-05-01T10:00:00Z","level":"notice","msg":"Transferred: 1 MiB / 4 MiB, 25%","stats":{"bytes":1048576,"checks":2,"deletes":0,"elapsedTime":1.0,"errors":0,"eta":3,"fatalError":false,"speed":1048576.5,"totalBytes":4194304,"totalTransfers":4,"transfers":1},"source":"accounting/stats.go:482"}"#;
+        assert_eq!(
+            parse_log_line(stats),
+            Some(LogEvent::Stats(RemoteProgress {
+                bytes: 1048576,
+                total_bytes: 4194304,
+                transfers: 1,
+                total_transfers: 4,
+                checks: 2,
+                deletes: 0,
+                errors: 0,
+                speed: 1048576.5,
+                eta: Some(3.0),
+            }))
+        );
+
+        let error = r#"{"level":"error","msg":"photo.jpg: Failed to copy: permission denied\n","object":"photo.jpg"}"#;
+        assert_eq!(
+            parse_log_line(error),
+            Some(LogEvent::Error(
+                "photo.jpg: Failed to copy: permission denied".to_string()
+            ))
+        );
+
+        assert_eq!(parse_log_line(r#"{"level":"info","msg":"Copied"}"#), None);
+        assert_eq!(parse_log_line("2024/05/01 plain text"), None);
+        assert_eq!(
+            parse_remotes("gdrive:\nb2 backup:\n\n"),
+            ["gdrive", "b2 backup"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn runs_rclone_and_follows_its_progress() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(root.join("photos")).unwrap();
+        // Stands in for rclone: lists one remote, and for a copy logs one
+        // stats line and the arguments it got
+        let script = tmp.path().join("rclone");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\n\
+                 if [ \"$1\" = listremotes ]; then echo 'gdrive:'; exit 0; fi\n\
+                 echo \"$@\" > {}\n\
+                 echo '{{\"level\":\"notice\",\"msg\":\"\",\"stats\":{{\"bytes\":5,\"totalBytes\":5,\"transfers\":1,\"totalTransfers\":1}}}}' >&2\n",
+                tmp.path().join("args").display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let service = Arc::new(RemoteSyncService::new(
+            Some(script),
+            FilesystemService::new(root.clone()),
+        ));
+        assert_eq!(service.list_remotes().await.unwrap(), ["gdrive"]);
+
+        let started = service
+            .start_sync("/photos", "gdrive", "backup/photos", false)
+            .unwrap()
+            .unwrap();
+        let mut finished = None;
+        for _ in 0..100 {
+            let sync = service.get_sync(started.id).unwrap();
+            if sync.status != RemoteSyncStatus::Running {
+                finished = Some(sync);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let finished = finished.expect("sync finished");
+        assert_eq!(finished.status, RemoteSyncStatus::Success);
+        assert_eq!(finished.progress.total_transfers, 1);
+
+        let args = std::fs::read_to_string(tmp.path().join("args")).unwrap();
+        let local = root.canonicalize().unwrap().join("photos");
+        assert!(args.starts_with(&format!(
+            "copy {} gdrive:backup/photos --use-json-log",
+            local.display()
+        )));
+    }
+}
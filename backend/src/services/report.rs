@@ -0,0 +1,165 @@
+//! Directory inventory used by the printable report export.
+
+use std::fs;
+use std::path::Path;
+
+use crate::services::{FilesystemService, FsError, preview};
+
+/// One entry in a directory report. Directory sizes and counts are totals for
+/// the whole subtree, even below the depth at which children stop being listed.
+#[derive(Debug)]
+pub struct ReportNode {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+    pub mime_type: Option<String>,
+    /// Under a `.fxnopreview` marker; thumbnails must not be shown
+    pub no_preview: bool,
+    pub children: Vec<ReportNode>,
+}
+
+pub struct ReportService;
+
+impl ReportService {
+    /// Build a report tree for `relative_path`, listing children down to
+    /// `max_depth` levels below it. Symlinks are not followed and unreadable
+    /// directories are reported as empty.
+    pub fn build(
+        fs: &FilesystemService,
+        relative_path: &str,
+        max_depth: usize,
+    ) -> Result<ReportNode, FsError> {
+        let resolved = fs.resolve_path(relative_path)?;
+        if !resolved.is_dir() {
+            return Err(FsError::NotADirectory(relative_path.to_string()));
+        }
+
+        let path = fs.relative_path(&resolved);
+        let name = if path == "/" {
+            "/".to_string()
+        } else {
+            resolved
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+
+        let no_preview = preview::previews_disabled(fs.root(), &resolved);
+        Ok(Self::build_dir(
+            &resolved, name, path, no_preview, 0, max_depth,
+        ))
+    }
+
+    fn build_dir(
+        dir: &Path,
+        name: String,
+        path: String,
+        no_preview: bool,
+        depth: usize,
+        max_depth: usize,
+    ) -> ReportNode {
+        let no_preview = no_preview || dir.join(preview::NO_PREVIEW_MARKER).is_file();
+        let mut node = ReportNode {
+            name,
+            path,
+            is_dir: true,
+            size: 0,
+            file_count: 0,
+            dir_count: 0,
+            mime_type: None,
+            no_preview,
+            children: Vec::new(),
+        };
+
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            return node;
+        };
+
+        let mut children = Vec::new();
+        for entry in read_dir.flatten() {
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            let child_name = entry.file_name().to_string_lossy().to_string();
+            let child_path = format!("{}/{}", node.path.trim_end_matches('/'), child_name);
+
+            if metadata.is_dir() {
+                let child = Self::build_dir(
+                    &entry.path(),
+                    child_name,
+                    child_path,
+                    no_preview,
+                    depth + 1,
+                    max_depth,
+                );
+                node.size += child.size;
+                node.file_count += child.file_count;
+                node.dir_count += child.dir_count + 1;
+                children.push(child);
+            } else if metadata.is_file() {
+                node.size += metadata.len();
+                node.file_count += 1;
+                children.push(ReportNode {
+                    mime_type: mime_guess::from_path(&child_name)
+                        .first()
+                        .map(|m| m.to_string()),
+                    name: child_name,
+                    path: child_path,
+                    is_dir: false,
+                    size: metadata.len(),
+                    file_count: 0,
+                    dir_count: 0,
+                    no_preview,
+                    children: Vec::new(),
+                });
+            }
+        }
+
+        if depth < max_depth {
+            // Same ordering as browsing: directories first, then by name
+            children.sort_by(|a, b| {
+                b.is_dir
+                    .cmp(&a.is_dir)
+                    .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            });
+            node.children = children;
+        }
+
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn build_aggregates_below_max_depth() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/one.txt"), b"12345").unwrap();
+        fs::write(root.join("a/b/two.txt"), b"123").unwrap();
+        fs::write(root.join("top.txt"), b"1").unwrap();
+        let service = FilesystemService::new(root);
+
+        let report = ReportService::build(&service, "/", 1).unwrap();
+        assert_eq!(report.size, 9);
+        assert_eq!(report.file_count, 3);
+        assert_eq!(report.dir_count, 2);
+
+        let names: Vec<_> = report.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "top.txt"]);
+
+        // Children of /a are beyond max_depth but still counted
+        let a = &report.children[0];
+        assert_eq!(a.path, "/a");
+        assert!(a.children.is_empty());
+        assert_eq!(a.size, 8);
+        assert_eq!(a.file_count, 2);
+    }
+}
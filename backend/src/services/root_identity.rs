@@ -0,0 +1,203 @@
+//! Fingerprint of the directory the index was built from. If the configured
+//! root later resolves to a different disk (a mount that failed to come up,
+//! a wrong bind mount), every indexed path looks missing; the indexer uses
+//! this check to refuse the missing-file purge instead of emptying the index.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::db;
+
+/// Marker file holding a random id, written to the root on first index
+pub const ROOT_MARKER: &str = ".fxroot";
+
+const FINGERPRINT_KEY: &str = "root_fingerprint";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootFingerprint {
+    pub canonical_path: String,
+    /// Device id of the root directory (`st_dev`); `None` off Unix
+    pub device_id: Option<u64>,
+    /// Contents of the marker file, `None` if it is missing or unreadable
+    pub marker: Option<String>,
+}
+
+/// Outcome of comparing the current root with the one the index was built from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootCheck {
+    /// No fingerprint was stored yet; the current root was adopted
+    Adopted,
+    Matches,
+    /// The root looks like a different directory; the reason is user-facing
+    Mismatch(String),
+}
+
+impl RootFingerprint {
+    /// Fingerprint `root` as it is now, without writing anything
+    pub fn read(root: &Path) -> io::Result<Self> {
+        let canonical = root.canonicalize()?;
+        let metadata = fs::metadata(&canonical)?;
+        #[cfg(unix)]
+        let device_id = Some(std::os::unix::fs::MetadataExt::dev(&metadata));
+        #[cfg(not(unix))]
+        let device_id = {
+            let _ = &metadata;
+            None
+        };
+        let marker = fs::read_to_string(canonical.join(ROOT_MARKER))
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty());
+
+        Ok(Self {
+            canonical_path: canonical.display().to_string(),
+            device_id,
+            marker,
+        })
+    }
+
+    /// Fingerprint `root`, writing a marker first if there is none. A
+    /// read-only root simply goes without one.
+    pub fn establish(root: &Path) -> io::Result<Self> {
+        let mut fingerprint = Self::read(root)?;
+        if fingerprint.marker.is_none() {
+            let id = uuid::Uuid::new_v4().as_simple().to_string();
+            if fs::write(root.join(ROOT_MARKER), &id).is_ok() {
+                fingerprint.marker = Some(id);
+            }
+        }
+        Ok(fingerprint)
+    }
+
+    /// Why `self` (now) is not the root recorded as `stored`, if it is not.
+    /// A matching marker wins over path and device changes, so moving the
+    /// disk or changing the mount point keeps the index.
+    pub fn mismatch(&self, stored: &Self) -> Option<String> {
+        match (&stored.marker, &self.marker) {
+            (Some(expected), Some(found)) if expected == found => None,
+            (Some(_), Some(_)) => Some(format!(
+                "{} in {} belongs to a different root",
+                ROOT_MARKER, self.canonical_path
+            )),
+            (Some(_), None) => Some(format!(
+                "{} is missing from {}; the disk may not be mounted",
+                ROOT_MARKER, self.canonical_path
+            )),
+            (None, _) if stored.canonical_path != self.canonical_path => Some(format!(
+                "root moved from {} to {}",
+                stored.canonical_path, self.canonical_path
+            )),
+            (None, _) if stored.device_id != self.device_id => Some(format!(
+                "{} is on a different device than when it was indexed",
+                self.canonical_path
+            )),
+            (None, _) => None,
+        }
+    }
+}
+
+/// Compare `root` with the fingerprint stored in the index, storing it on
+/// first use and refreshing it (new path, device, or marker) on a match
+pub async fn verify(pool: &SqlitePool, root: &Path) -> anyhow::Result<RootCheck> {
+    let stored = db::get_index_meta(pool, FINGERPRINT_KEY)
+        .await?
+        .and_then(|json| serde_json::from_str::<RootFingerprint>(&json).ok());
+
+    let Some(stored) = stored else {
+        adopt(pool, root).await?;
+        return Ok(RootCheck::Adopted);
+    };
+
+    let current = RootFingerprint::read(root)?;
+    if let Some(reason) = current.mismatch(&stored) {
+        return Ok(RootCheck::Mismatch(reason));
+    }
+
+    let current = RootFingerprint::establish(root)?;
+    if current != stored {
+        db::set_index_meta(pool, FINGERPRINT_KEY, &serde_json::to_string(&current)?).await?;
+    }
+    Ok(RootCheck::Matches)
+}
+
+/// Record `root` as the directory the index belongs to, replacing any
+/// previous fingerprint
+pub async fn adopt(pool: &SqlitePool, root: &Path) -> anyhow::Result<RootFingerprint> {
+    let fingerprint = RootFingerprint::establish(root)?;
+    db::set_index_meta(pool, FINGERPRINT_KEY, &serde_json::to_string(&fingerprint)?).await?;
+    Ok(fingerprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn detects_a_root_that_lost_its_marker() {
+        let tmp = tempdir().unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+
+        assert_eq!(verify(&pool, tmp.path()).await.unwrap(), RootCheck::Adopted);
+        assert!(tmp.path().join(ROOT_MARKER).exists());
+        assert_eq!(verify(&pool, tmp.path()).await.unwrap(), RootCheck::Matches);
+
+        // An empty mount point in place of the disk has no marker
+        fs::remove_file(tmp.path().join(ROOT_MARKER)).unwrap();
+        assert!(matches!(
+            verify(&pool, tmp.path()).await.unwrap(),
+            RootCheck::Mismatch(_)
+        ));
+
+        // Another disk that was indexed elsewhere has a different marker
+        fs::write(tmp.path().join(ROOT_MARKER), "other").unwrap();
+        assert!(matches!(
+            verify(&pool, tmp.path()).await.unwrap(),
+            RootCheck::Mismatch(_)
+        ));
+
+        adopt(&pool, tmp.path()).await.unwrap();
+        assert_eq!(verify(&pool, tmp.path()).await.unwrap(), RootCheck::Matches);
+    }
+
+    #[test]
+    fn matching_marker_survives_a_new_mount_point() {
+        let stored = RootFingerprint {
+            canonical_path: "/mnt/old".to_string(),
+            device_id: Some(1),
+            marker: Some("abc".to_string()),
+        };
+        let moved = RootFingerprint {
+            canonical_path: "/mnt/new".to_string(),
+            device_id: Some(2),
+            marker: Some("abc".to_string()),
+        };
+        assert_eq!(moved.mismatch(&stored), None);
+
+        // Without markers (read-only root) path and device must both agree
+        let stored = RootFingerprint {
+            marker: None,
+            ..stored
+        };
+        let unmarked = RootFingerprint {
+            marker: None,
+            ..moved
+        };
+        assert!(unmarked.mismatch(&stored).is_some());
+        let same_path = RootFingerprint {
+            canonical_path: stored.canonical_path.clone(),
+            ..unmarked
+        };
+        assert!(same_path.mismatch(&stored).is_some());
+        assert_eq!(stored.mismatch(&stored), None);
+    }
+}
@@ -1,47 +1,239 @@
 //! Search service providing thread-safe access to the in-memory search index.
 
+use serde::Serialize;
 use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeSet;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::sync::{RwLock, mpsc};
 use tracing::{info, warn};
 
-use super::search_index::SearchIndex;
+use super::filesystem::is_hidden_path;
+use super::search_index::{Normalization, SearchIndex, TermExpr, TermQuery, query_terms};
+use super::search_shards::ShardedSearchIndex;
+
+/// Columns loaded from `indexed_files` when rebuilding the index.
+#[derive(sqlx::FromRow)]
+struct SearchRow {
+    id: i64,
+    path: String,
+    artist: Option<String>,
+    album: Option<String>,
+    title: Option<String>,
+    author: Option<String>,
+    /// Text of the notes on the file
+    notes: Option<String>,
+}
+
+/// Selects `SearchRow`s; append a WHERE clause to narrow it down
+const SEARCH_ROW_QUERY: &str = "SELECT id, path, artist, album, title, author, \
+     (SELECT group_concat(body, ' ') FROM file_notes WHERE file_notes.path = indexed_files.path) AS notes \
+     FROM indexed_files";
+
+impl SearchRow {
+    /// Tag, document, and note fields appended to the path as extra
+    /// searchable text.
+    fn extra_text(&self) -> Option<String> {
+        let parts: Vec<&str> = [
+            &self.artist,
+            &self.album,
+            &self.title,
+            &self.author,
+            &self.notes,
+        ]
+        .into_iter()
+        .filter_map(|v| v.as_deref())
+        .collect();
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" "))
+        }
+    }
+}
 
 /// Thread-safe search service wrapping the in-memory search index.
 pub struct SearchService {
-    index: Arc<RwLock<SearchIndex>>,
+    index: Arc<RwLock<ShardedSearchIndex>>,
+    /// Set once the index has been built from the database
+    loaded: AtomicBool,
+    /// Whether path lookups ignore ASCII case
+    case_insensitive_paths: bool,
+    /// Folding applied to indexed text and queries
+    normalization: Normalization,
+    /// Most bytes the in-memory index may take; 0 is unlimited
+    memory_limit: usize,
+    /// Set while the index would exceed `memory_limit`, so searches go to
+    /// the database instead
+    fallback: AtomicBool,
+}
+
+/// Where searches are answered from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    Memory,
+    /// The index is over `FM_SEARCH_INDEX_MAX_MB`; searches use SQL `LIKE`
+    Database,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchIndexStatus {
+    pub mode: SearchMode,
+    pub entries: usize,
+    pub shards: usize,
+    pub memory_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_limit_bytes: Option<usize>,
 }
 
 impl SearchService {
     /// Create a new search service with an empty index.
     pub fn new() -> Self {
+        Self::with_case_insensitive_paths(false)
+    }
+
+    /// Create a search service whose path lookups (renames, removals)
+    /// ignore ASCII case, for roots on case-insensitive filesystems
+    pub fn with_case_insensitive_paths(case_insensitive: bool) -> Self {
+        let mut index = ShardedSearchIndex::new();
+        index.set_case_insensitive_paths(case_insensitive);
         Self {
-            index: Arc::new(RwLock::new(SearchIndex::new())),
+            index: Arc::new(RwLock::new(index)),
+            loaded: AtomicBool::new(false),
+            case_insensitive_paths: case_insensitive,
+            normalization: Normalization::default(),
+            memory_limit: 0,
+            fallback: AtomicBool::new(false),
         }
     }
 
+    /// Fold indexed text and queries with `normalization`, e.g. to
+    /// transliterate
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self.index = Arc::new(RwLock::new(self.empty_index()));
+        self
+    }
+
+    fn empty_index(&self) -> ShardedSearchIndex {
+        let mut index = ShardedSearchIndex::with_normalization(self.normalization);
+        index.set_case_insensitive_paths(self.case_insensitive_paths);
+        index
+    }
+
+    /// Answer searches from the database instead of loading an index that
+    /// would take more than `bytes`; 0 is unlimited
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
     /// Rebuild the search index from the database.
     ///
     /// This fetches all indexed paths and rebuilds the index atomically.
-    pub async fn rebuild_from_db(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    /// Returns the number of entries in the new index.
+    pub async fn rebuild_from_db(&self, pool: &SqlitePool) -> Result<usize, sqlx::Error> {
         info!("Rebuilding search index from database");
 
-        // Fetch all indexed paths with IDs
-        let rows: Vec<(i64, String)> = sqlx::query_as("SELECT id, path FROM indexed_files")
-            .fetch_all(pool)
-            .await?;
+        // Fetch all indexed paths with IDs, plus tags/document fields/notes as extra searchable text
+        let rows: Vec<SearchRow> = sqlx::query_as(SEARCH_ROW_QUERY).fetch_all(pool).await?;
 
         let count = rows.len();
 
+        let documents: Vec<_> = rows
+            .into_iter()
+            .map(|row| {
+                let extra = row.extra_text();
+                (row.id, row.path, extra)
+            })
+            .collect();
+
+        let estimate = SearchIndex::estimate_memory_bytes(&documents);
+        if self.memory_limit > 0 && estimate > self.memory_limit {
+            warn!(
+                "Search index would take about {} MiB, over FM_SEARCH_INDEX_MAX_MB ({} MiB); searching the database instead",
+                estimate / (1024 * 1024),
+                self.memory_limit / (1024 * 1024)
+            );
+            *self.index.write().await = self.empty_index();
+            self.fallback.store(true, Ordering::Relaxed);
+            self.loaded.store(true, Ordering::Relaxed);
+            return Ok(count);
+        }
+
         // Build new index (this is CPU-intensive but doesn't hold the lock)
-        let new_index = SearchIndex::build_from_entries(rows);
+        let new_index = ShardedSearchIndex::build_from_documents(
+            documents,
+            self.case_insensitive_paths,
+            self.normalization,
+        );
 
         // Swap in the new index atomically
         let mut index = self.index.write().await;
         *index = new_index;
+        self.fallback.store(false, Ordering::Relaxed);
+        self.loaded.store(true, Ordering::Relaxed);
 
         info!("Search index rebuilt with {} entries", count);
-        Ok(())
+        Ok(count)
+    }
+
+    /// Reload the shards holding everything below `dirs` from the database,
+    /// after directories were renamed, moved, or deleted there. Cheaper than
+    /// `rebuild_from_db` since the other shards are left alone. Returns the
+    /// number of entries in the reloaded shards.
+    pub async fn reload_subtrees(
+        &self,
+        pool: &SqlitePool,
+        dirs: &[&str],
+    ) -> Result<usize, sqlx::Error> {
+        if self.is_fallback() {
+            return Ok(0);
+        }
+        let keys: BTreeSet<String> = {
+            let index = self.index.read().await;
+            dirs.iter().map(|dir| index.key_below(dir)).collect()
+        };
+        if keys.contains("") {
+            // Below the root is everything
+            return self.rebuild_from_db(pool).await;
+        }
+
+        let mut count = 0;
+        for key in keys {
+            count += self.reload_shard(pool, key).await?;
+        }
+        Ok(count)
+    }
+
+    async fn reload_shard(&self, pool: &SqlitePool, key: String) -> Result<usize, sqlx::Error> {
+        let prefix = format!("/{key}/");
+        let condition = if self.case_insensitive_paths {
+            "lower(substr(path, 1, ?)) = lower(?)"
+        } else {
+            "substr(path, 1, ?) = ?"
+        };
+        let rows: Vec<SearchRow> = sqlx::query_as(&format!("{SEARCH_ROW_QUERY} WHERE {condition}"))
+            .bind(prefix.chars().count() as i64)
+            .bind(&prefix)
+            .fetch_all(pool)
+            .await?;
+        let count = rows.len();
+        let shard = SearchIndex::build_from_documents_with(
+            rows.into_iter()
+                .map(|row| {
+                    let extra = row.extra_text();
+                    (row.id, row.path, extra)
+                })
+                .collect(),
+            self.normalization,
+        );
+
+        self.index.write().await.replace_shard(key, shard);
+        Ok(count)
     }
 
     /// Search for matching file IDs.
@@ -50,6 +242,101 @@ impl SearchService {
         index.search(query)
     }
 
+    /// Search for matching file IDs, leaving out hidden paths unless `include_hidden`.
+    pub async fn search_filtered(&self, query: &str, include_hidden: bool) -> Vec<i64> {
+        let index = self.index.read().await;
+        index.search_filtered(query, include_hidden)
+    }
+
+    /// Search the in-memory index, or the database while the index is over
+    /// its memory limit
+    pub async fn find(
+        &self,
+        pool: &SqlitePool,
+        query: &str,
+        include_hidden: bool,
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        self.find_terms(pool, &query_terms(query), include_hidden)
+            .await
+    }
+
+    /// Like `find`, for a query already split into terms; a term may hold
+    /// spaces to match a phrase
+    pub async fn find_terms(
+        &self,
+        pool: &SqlitePool,
+        terms: &[String],
+        include_hidden: bool,
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        if self.is_fallback() {
+            search_database(pool, terms, include_hidden).await
+        } else {
+            let index = self.index.read().await;
+            Ok(index.search_terms(terms, include_hidden))
+        }
+    }
+
+    /// Search on a blocking thread, sending each slice's matches with the
+    /// number of entries scanned so far. The scan stops once the receiver
+    /// is dropped. While the index is over its memory limit, the database
+    /// is searched instead and all matches arrive at once.
+    pub fn search_streaming(
+        &self,
+        pool: &SqlitePool,
+        query: String,
+        include_hidden: bool,
+    ) -> mpsc::Receiver<(Vec<i64>, usize)> {
+        let (sender, receiver) = mpsc::channel(4);
+        if self.is_fallback() {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                match search_database(&pool, &query_terms(&query), include_hidden).await {
+                    Ok(ids) => {
+                        let _ = sender.send((ids, 0)).await;
+                    }
+                    Err(e) => warn!("Database search failed: {}", e),
+                }
+            });
+            return receiver;
+        }
+        let index = self.index.clone();
+        tokio::task::spawn_blocking(move || {
+            let index = index.blocking_read();
+            index.search_in_batches(&query, include_hidden, |ids, scanned| {
+                sender.blocking_send((ids, scanned)).is_ok()
+            });
+        });
+        receiver
+    }
+
+    /// Whether the index has been built from the database at least once;
+    /// until then searches miss everything that is indexed.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Relaxed)
+    }
+
+    /// Whether searches go to the database because the index is over its
+    /// memory limit
+    pub fn is_fallback(&self) -> bool {
+        self.fallback.load(Ordering::Relaxed)
+    }
+
+    /// Size and memory use of the index, for `/api/index/status`
+    pub async fn status(&self) -> SearchIndexStatus {
+        let index = self.index.read().await;
+        SearchIndexStatus {
+            mode: if self.is_fallback() {
+                SearchMode::Database
+            } else {
+                SearchMode::Memory
+            },
+            entries: index.len(),
+            shards: index.shard_count(),
+            memory_bytes: index.memory_bytes(),
+            memory_limit_bytes: (self.memory_limit > 0).then_some(self.memory_limit),
+        }
+    }
+
     /// Get the current index size.
     pub async fn index_size(&self) -> usize {
         let index = self.index.read().await;
@@ -58,12 +345,39 @@ impl SearchService {
 
     /// Add a new entry to the index.
     pub async fn add_entry(&self, id: i64, path: &str) {
+        if self.is_fallback() {
+            return;
+        }
         let mut index = self.index.write().await;
         index.add_entry(id, path);
     }
 
+    /// Reload an indexed path's searchable text from the database, e.g.
+    /// after its notes changed. Paths not indexed yet are left alone.
+    pub async fn refresh_entry(&self, pool: &SqlitePool, path: &str) -> Result<(), sqlx::Error> {
+        let row: Option<SearchRow> = sqlx::query_as(&format!("{SEARCH_ROW_QUERY} WHERE path = ?"))
+            .bind(path)
+            .fetch_optional(pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(());
+        };
+        if self.is_fallback() {
+            return Ok(());
+        }
+
+        let extra = row.extra_text();
+        let mut index = self.index.write().await;
+        index.remove_entry(&row.path);
+        index.add_entry_with_extra(row.id, &row.path, extra.as_deref());
+        Ok(())
+    }
+
     /// Remove an entry from the index by path.
     pub async fn remove_entry(&self, path: &str) {
+        if self.is_fallback() {
+            return;
+        }
         let mut index = self.index.write().await;
         if !index.remove_entry(path) {
             warn!("Search index: tried to remove non-existent path: {}", path);
@@ -84,6 +398,9 @@ impl SearchService {
 
     /// Rename an entry in the index.
     pub async fn rename_entry(&self, old_path: &str, new_path: &str) {
+        if self.is_fallback() {
+            return;
+        }
         let mut index = self.index.write().await;
         if !index.rename_entry(old_path, new_path) {
             warn!(
@@ -100,6 +417,86 @@ impl SearchService {
     }
 }
 
+/// `LIKE` pattern matching `term` anywhere, with wildcards escaped
+pub(crate) fn like_pattern(term: &str) -> String {
+    let mut pattern = String::with_capacity(term.len() + 2);
+    pattern.push('%');
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Condition that one term appears in an entry's path, tags, or notes;
+/// missing tags count as empty so that negating it stays true or false
+const TERM_CONDITION: &str = "(path LIKE ? ESCAPE '\\' \
+     OR ifnull(artist, '') LIKE ? ESCAPE '\\' OR ifnull(album, '') LIKE ? ESCAPE '\\' \
+     OR ifnull(title, '') LIKE ? ESCAPE '\\' OR ifnull(author, '') LIKE ? ESCAPE '\\' \
+     OR EXISTS (SELECT 1 FROM file_notes WHERE file_notes.path = indexed_files.path \
+                AND body LIKE ? ESCAPE '\\'))";
+
+/// SQL for `expr`, pushing the term bound to each placeholder group
+fn expr_sql(expr: &TermExpr, bound: &mut Vec<usize>) -> String {
+    let join = |exprs: &[TermExpr], bound: &mut Vec<usize>, op: &str| {
+        let parts: Vec<String> = exprs.iter().map(|e| expr_sql(e, bound)).collect();
+        format!("({})", parts.join(op))
+    };
+    match expr {
+        TermExpr::Term(n) => {
+            bound.push(*n);
+            TERM_CONDITION.to_string()
+        }
+        TermExpr::Not(expr) => format!("NOT {}", expr_sql(expr, bound)),
+        TermExpr::Any(exprs) => join(exprs, bound, " OR "),
+        TermExpr::All(exprs) => join(exprs, bound, " AND "),
+    }
+}
+
+/// IDs of entries whose path, tags, or notes satisfy the terms as the
+/// in-memory index would (`OR` and `-term` included), ignoring ASCII case.
+/// Slower than the in-memory index and without its diacritic folding; used
+/// while the index is over its memory limit.
+async fn search_database(
+    pool: &SqlitePool,
+    terms: &[String],
+    include_hidden: bool,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let Some(query) = TermQuery::parse(terms) else {
+        return Ok(vec![]);
+    };
+
+    let mut bound = Vec::new();
+    let sql = format!(
+        "SELECT id, path FROM indexed_files WHERE {}",
+        expr_sql(&query.expr, &mut bound)
+    );
+    let mut db_query = sqlx::query_as::<_, (i64, String)>(&sql);
+    for n in bound {
+        let pattern = like_pattern(&query.terms[n]);
+        for _ in 0..6 {
+            db_query = db_query.bind(pattern.clone());
+        }
+    }
+
+    let start = Instant::now();
+    let rows = db_query.fetch_all(pool).await?;
+    info!(
+        terms = ?terms,
+        results = rows.len(),
+        elapsed = %format!("{:.3}s", start.elapsed().as_secs_f64()),
+        "Database search completed"
+    );
+    Ok(rows
+        .into_iter()
+        .filter(|(_, path)| include_hidden || !is_hidden_path(path))
+        .map(|(id, _)| id)
+        .collect())
+}
+
 impl Default for SearchService {
     fn default() -> Self {
         Self::new()
@@ -155,4 +552,111 @@ mod tests {
         let results = service.search("new").await;
         assert_eq!(results, vec![1]);
     }
+
+    #[tokio::test]
+    async fn test_reload_subtrees_after_directory_rename() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        for path in ["/photos", "/photos/2024/beach.jpg", "/docs/beach.txt"] {
+            sqlx::query("INSERT INTO indexed_files (path, name) VALUES (?, 'x')")
+                .bind(path)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let service = SearchService::new();
+        service.rebuild_from_db(&pool).await.unwrap();
+
+        crate::db::rename_path(&pool, "/photos", "/pictures", "pictures")
+            .await
+            .unwrap();
+        service.rename_entry("/photos", "/pictures").await;
+        let reloaded = service
+            .reload_subtrees(&pool, &["/photos", "/pictures"])
+            .await
+            .unwrap();
+        assert_eq!(reloaded, 1);
+
+        assert!(service.search("photos").await.is_empty());
+        assert_eq!(service.search("pictures").await.len(), 2);
+        assert_eq!(service.search("beach").await.len(), 2);
+        assert_eq!(service.index_size().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_index_falls_back_to_the_database() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        for path in ["/docs/Beach.txt", "/docs/100%.txt", "/.cache/beach.tmp"] {
+            sqlx::query("INSERT INTO indexed_files (path, name) VALUES (?, 'x')")
+                .bind(path)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("INSERT INTO file_notes (path, body) VALUES ('/docs/100%.txt', 'final cut')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let service = SearchService::new().with_memory_limit(64);
+        assert_eq!(service.rebuild_from_db(&pool).await.unwrap(), 3);
+        assert!(service.is_fallback());
+        let status = service.status().await;
+        assert_eq!(status.mode, SearchMode::Database);
+        assert_eq!(status.entries, 0);
+        assert_eq!(status.memory_limit_bytes, Some(64));
+
+        assert_eq!(service.find(&pool, "beach", false).await.unwrap().len(), 1);
+        assert_eq!(service.find(&pool, "beach", true).await.unwrap().len(), 2);
+        assert_eq!(
+            service.find(&pool, "docs 0%", false).await.unwrap().len(),
+            1
+        );
+        assert!(
+            service
+                .find(&pool, "docs _", false)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(service.find(&pool, "FINAL", false).await.unwrap().len(), 1);
+        // OR and negation work as in memory, also for entries without tags
+        assert_eq!(
+            service
+                .find(&pool, "docs -final", false)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            service
+                .find(&pool, "beach OR cut", true)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+
+        let mut batches = service.search_streaming(&pool, "docs".to_string(), false);
+        assert_eq!(batches.recv().await.unwrap().0.len(), 2);
+        assert!(batches.recv().await.is_none());
+
+        let unlimited = SearchService::new();
+        unlimited.rebuild_from_db(&pool).await.unwrap();
+        let status = unlimited.status().await;
+        assert_eq!(status.mode, SearchMode::Memory);
+        assert_eq!(status.entries, 3);
+        assert!(status.memory_bytes > 0);
+        assert_eq!(status.memory_limit_bytes, None);
+    }
 }
@@ -1,25 +1,87 @@
 //! In-memory search index for fast substring matching on file paths.
 //!
 //! Stores normalized (casefolded + diacritic-stripped) paths in contiguous memory
-//! for cache-efficient searching with memchr and Aho-Corasick.
+//! for cache-efficient searching with memchr and Aho-Corasick. An entry may
+//! carry extra searchable text (e.g. audio tags) after its path.
 
 use aho_corasick::AhoCorasick;
 use memchr::memmem;
 use rayon::prelude::*;
+use std::ops::Range;
 use std::time::Instant;
 use tracing::info;
 use unicode_normalization::UnicodeNormalization;
 
-/// Normalize a path for search: NFD decomposition, strip combining marks, lowercase.
+use crate::services::filesystem::is_hidden_path;
+
+/// Normalize a path for search: NFKD decomposition, strip combining marks, lowercase.
 ///
 /// This enables matching "café" with "cafe", "naïve" with "naive", etc.
+/// Compatibility decomposition also folds full-width and half-width forms,
+/// so "ＡＢＣ" matches "abc" and half-width "ｶﾀｶﾅ" matches "カタカナ".
 pub fn normalize_path(path: &str) -> String {
-    path.nfd()
-        .filter(|c| !is_combining_mark(*c))
-        .flat_map(|c| c.to_lowercase())
-        .collect()
+    Normalization::default().apply(path)
+}
+
+/// Folding applied to indexed text and queries alike, on top of
+/// `normalize_path`'s. Changing it means rebuilding the index.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Approximate letters without a decomposition in ASCII, so "Łódź"
+    /// matches "lodz" and "ß" or "æ" match "ss" or "ae"
+    pub transliterate: bool,
+    /// Treat katakana as the matching hiragana, so "カタカナ" matches
+    /// "かたかな"
+    pub fold_kana: bool,
 }
 
+impl Normalization {
+    pub fn apply(self, text: &str) -> String {
+        let fold_kana = self.fold_kana;
+        let stripped = text
+            .nfkd()
+            .filter(|c| !is_combining_mark(*c))
+            .map(move |c| {
+                if fold_kana {
+                    katakana_to_hiragana(c)
+                } else {
+                    c
+                }
+            });
+        if !self.transliterate {
+            return stripped.flat_map(|c| c.to_lowercase()).collect();
+        }
+        let mut normalized = String::with_capacity(text.len());
+        for c in stripped {
+            match deunicode::deunicode_char(c) {
+                Some(ascii) if !c.is_ascii() => {
+                    // deunicode pads some expansions with spaces (e.g. CJK
+                    // syllables)
+                    normalized.extend(ascii.trim().chars().map(|c| c.to_ascii_lowercase()))
+                }
+                _ => normalized.extend(c.to_lowercase()),
+            }
+        }
+        normalized
+    }
+}
+
+/// The hiragana for a katakana letter or iteration mark; anything else is
+/// returned as is. Voiced forms are already decomposed by NFKD, so only the
+/// base letters need mapping.
+fn katakana_to_hiragana(c: char) -> char {
+    match c {
+        '\u{30A1}'..='\u{30F6}' | '\u{30FD}'..='\u{30FE}' => {
+            char::from_u32(c as u32 - 0x60).unwrap_or(c)
+        }
+        _ => c,
+    }
+}
+
+/// Separates a normalized path from its extra searchable text. NUL never
+/// appears in paths, so renames can find where the path portion ends.
+const EXTRA_SEPARATOR: u8 = 0;
+
 /// Check if a character is a combining mark (diacritic).
 fn is_combining_mark(c: char) -> bool {
     // Unicode combining marks are in the range U+0300 to U+036F (Combining Diacritical Marks)
@@ -27,6 +89,102 @@ fn is_combining_mark(c: char) -> bool {
     matches!(c, '\u{0300}'..='\u{036F}' | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}' | '\u{20D0}'..='\u{20FF}' | '\u{FE20}'..='\u{FE2F}')
 }
 
+/// Whitespace-separated terms of a query
+pub(crate) fn query_terms(query: &str) -> Vec<String> {
+    query.split_whitespace().map(str::to_string).collect()
+}
+
+/// Word joining alternatives in a query, as in `mp4 OR mkv`
+const OR_OPERATOR: &str = "OR";
+
+/// A boolean expression over the terms of a `TermQuery`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TermExpr {
+    /// The term at this position appears
+    Term(usize),
+    Not(Box<TermExpr>),
+    Any(Vec<TermExpr>),
+    All(Vec<TermExpr>),
+}
+
+impl TermExpr {
+    /// Whether the expression holds, given which terms appear
+    pub(crate) fn eval(&self, matched: &[bool]) -> bool {
+        match self {
+            TermExpr::Term(n) => matched[*n],
+            TermExpr::Not(expr) => !expr.eval(matched),
+            TermExpr::Any(exprs) => exprs.iter().any(|e| e.eval(matched)),
+            TermExpr::All(exprs) => exprs.iter().all(|e| e.eval(matched)),
+        }
+    }
+}
+
+/// The terms of a query and how they combine: every word must appear,
+/// `a OR b` needs either, and `-word` rules a word out, so
+/// `docs pdf OR txt -archive` is `docs AND (pdf OR txt) AND NOT archive`
+#[derive(Debug, PartialEq)]
+pub(crate) struct TermQuery {
+    /// Terms as written, without operators or `-`
+    pub terms: Vec<String>,
+    pub expr: TermExpr,
+}
+
+impl TermQuery {
+    /// `None` unless some part of the query must appear; a query of only
+    /// negated terms would match nearly everything
+    pub(crate) fn parse(words: &[String]) -> Option<Self> {
+        let mut terms = Vec::new();
+        let mut clauses: Vec<Vec<TermExpr>> = Vec::new();
+        let mut join = false;
+        for (i, word) in words.iter().enumerate() {
+            // `OR` between two terms is an operator, anywhere else a word
+            if word == OR_OPERATOR && !join && !clauses.is_empty() && i + 1 < words.len() {
+                join = true;
+                continue;
+            }
+            let (negated, text) = match word.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, word.as_str()),
+            };
+            let mut literal = TermExpr::Term(terms.len());
+            terms.push(text.to_string());
+            if negated {
+                literal = TermExpr::Not(Box::new(literal));
+            }
+            match clauses.last_mut() {
+                Some(clause) if join => clause.push(literal),
+                _ => clauses.push(vec![literal]),
+            }
+            join = false;
+        }
+
+        let positive = |clause: &Vec<TermExpr>| {
+            clause
+                .iter()
+                .all(|literal| matches!(literal, TermExpr::Term(_)))
+        };
+        if !clauses.iter().any(positive) {
+            return None;
+        }
+        let mut exprs: Vec<TermExpr> = clauses
+            .into_iter()
+            .map(|mut clause| {
+                if clause.len() == 1 {
+                    clause.remove(0)
+                } else {
+                    TermExpr::Any(clause)
+                }
+            })
+            .collect();
+        let expr = if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            TermExpr::All(exprs)
+        };
+        Some(Self { terms, expr })
+    }
+}
+
 /// A compact in-memory index for fast substring search on file paths.
 ///
 /// Paths are stored in a contiguous `Vec<u8>` with their normalized forms
@@ -45,6 +203,12 @@ pub struct SearchIndex {
 
     /// Original paths (not normalized) for lookup by index
     original_paths: Vec<String>,
+
+    /// Whether path lookups ignore ASCII case, like the database's NOCASE
+    case_insensitive_paths: bool,
+
+    /// Folding applied to paths, extra text, and query terms
+    normalization: Normalization,
 }
 
 impl SearchIndex {
@@ -53,32 +217,88 @@ impl SearchIndex {
         Self::default()
     }
 
+    /// Create an empty index folding text with `normalization`
+    pub fn with_normalization(normalization: Normalization) -> Self {
+        Self {
+            normalization,
+            ..Self::default()
+        }
+    }
+
     /// Build a search index from a list of (id, path) pairs.
     pub fn build_from_entries(entries: Vec<(i64, String)>) -> Self {
-        let mut index = Self::new();
+        Self::build_from_documents(
+            entries
+                .into_iter()
+                .map(|(id, path)| (id, path, None))
+                .collect(),
+        )
+    }
+
+    /// Build a search index from (id, path, extra text) triples. The extra
+    /// text is matched like the path but never returned or renamed.
+    pub fn build_from_documents(entries: Vec<(i64, String, Option<String>)>) -> Self {
+        Self::build_from_documents_with(entries, Normalization::default())
+    }
+
+    /// Build like `build_from_documents`, folding text with `normalization`
+    pub fn build_from_documents_with(
+        entries: Vec<(i64, String, Option<String>)>,
+        normalization: Normalization,
+    ) -> Self {
+        let mut index = Self::with_normalization(normalization);
 
         // Pre-allocate with estimates
-        let estimated_path_bytes: usize = entries.iter().map(|(_, p)| p.len()).sum();
+        let estimated_path_bytes: usize = entries
+            .iter()
+            .map(|(_, p, extra)| p.len() + extra.as_ref().map_or(0, |e| e.len() + 1))
+            .sum();
         index.ids.reserve(entries.len());
         index.offsets.reserve(entries.len());
         index.original_paths.reserve(entries.len());
         index.normalized_paths.reserve(estimated_path_bytes);
 
-        let mut offset: u32 = 0;
-
-        for (id, path) in entries {
-            let normalized = normalize_path(&path);
-            let norm_bytes = normalized.as_bytes();
+        for (id, path, extra) in entries {
+            index.push_entry(id, path, extra.as_deref());
+        }
 
-            index.ids.push(id);
-            index.offsets.push(offset);
-            index.original_paths.push(path);
-            index.normalized_paths.extend_from_slice(norm_bytes);
+        index
+    }
 
-            offset += norm_bytes.len() as u32;
+    /// Normalize a path plus optional extra text into the stored byte form.
+    fn normalized_document(&self, path: &str, extra: Option<&str>) -> Vec<u8> {
+        let mut bytes = self.normalization.apply(path).into_bytes();
+        if let Some(extra) = extra.filter(|e| !e.is_empty()) {
+            bytes.push(EXTRA_SEPARATOR);
+            bytes.extend_from_slice(self.normalization.apply(extra).as_bytes());
         }
+        bytes
+    }
 
-        index
+    fn push_entry(&mut self, id: i64, path: String, extra: Option<&str>) {
+        let norm_bytes = self.normalized_document(&path, extra);
+        let offset = self.normalized_paths.len() as u32;
+
+        self.ids.push(id);
+        self.offsets.push(offset);
+        self.original_paths.push(path);
+        self.normalized_paths.extend_from_slice(&norm_bytes);
+    }
+
+    /// Make lookups by path ignore ASCII case
+    pub fn set_case_insensitive_paths(&mut self, case_insensitive: bool) {
+        self.case_insensitive_paths = case_insensitive;
+    }
+
+    /// Position of the entry for `path`
+    fn position(&self, path: &str) -> Option<usize> {
+        if self.case_insensitive_paths {
+            self.original_paths
+                .iter()
+                .position(|p| p.eq_ignore_ascii_case(path))
+        } else {
+            self.original_paths.iter().position(|p| p == path)
+        }
     }
 
     /// Get the number of indexed entries.
@@ -91,6 +311,31 @@ impl SearchIndex {
         self.ids.is_empty()
     }
 
+    /// Heap bytes held for IDs, offsets, and normalized and original paths
+    pub fn memory_bytes(&self) -> usize {
+        self.ids.capacity() * size_of::<i64>()
+            + self.offsets.capacity() * size_of::<u32>()
+            + self.normalized_paths.capacity()
+            + self.original_paths.capacity() * size_of::<String>()
+            + self
+                .original_paths
+                .iter()
+                .map(String::capacity)
+                .sum::<usize>()
+    }
+
+    /// Heap bytes an index built from `entries` would hold, roughly
+    pub fn estimate_memory_bytes(entries: &[(i64, String, Option<String>)]) -> usize {
+        let per_entry = size_of::<i64>() + size_of::<u32>() + size_of::<String>();
+        entries
+            .iter()
+            .map(|(_, path, extra)| {
+                // Original and normalized path, plus separator and extra text
+                per_entry + path.len() * 2 + extra.as_ref().map_or(0, |e| e.len() + 1)
+            })
+            .sum()
+    }
+
     /// Get the byte slice for path at index `i`.
     fn get_path_bytes(&self, i: usize) -> &[u8] {
         let start = self.offsets[i] as usize;
@@ -102,9 +347,14 @@ impl SearchIndex {
         &self.normalized_paths[start..end]
     }
 
-    /// Search for paths matching a single term using memchr.
-    fn search_single_term(&self, term: &str) -> Vec<i64> {
-        let normalized_term = normalize_path(term);
+    /// Search entries in `range` for paths matching a single term using memchr.
+    fn search_single_term(
+        &self,
+        term: &str,
+        range: Range<usize>,
+        include_hidden: bool,
+    ) -> Vec<i64> {
+        let normalized_term = self.normalization.apply(term);
         let needle = normalized_term.as_bytes();
 
         if needle.is_empty() {
@@ -113,11 +363,11 @@ impl SearchIndex {
 
         let finder = memmem::Finder::new(needle);
 
-        (0..self.len())
+        range
             .into_par_iter()
             .filter_map(|i| {
                 let path_bytes = self.get_path_bytes(i);
-                if finder.find(path_bytes).is_some() {
+                if finder.find(path_bytes).is_some() && self.is_visible(i, include_hidden) {
                     Some(self.ids[i])
                 } else {
                     None
@@ -126,43 +376,53 @@ impl SearchIndex {
             .collect()
     }
 
-    /// Search for paths matching multiple terms using Aho-Corasick.
-    /// All terms must appear in the path for a match.
-    fn search_multi_term(&self, terms: &[String]) -> Vec<i64> {
-        let normalized_terms: Vec<String> = terms.iter().map(|t| normalize_path(t)).collect();
-
-        // Filter out empty terms
-        let non_empty_terms: Vec<&[u8]> = normalized_terms
+    /// Search entries in `range` for paths matching a query of several
+    /// terms, or with `OR` or negated terms, using Aho-Corasick. Each path's
+    /// matches are checked against the query's boolean expression.
+    fn search_multi_term(
+        &self,
+        terms: &[String],
+        range: Range<usize>,
+        include_hidden: bool,
+    ) -> Vec<i64> {
+        let Some(query) = TermQuery::parse(terms) else {
+            return vec![];
+        };
+        let normalized_terms: Vec<String> = query
+            .terms
             .iter()
-            .filter(|t| !t.is_empty())
-            .map(|t| t.as_bytes())
+            .map(|t| self.normalization.apply(t))
             .collect();
 
-        if non_empty_terms.is_empty() {
-            return vec![];
-        }
-
-        let term_count = non_empty_terms.len();
+        // Empty terms (e.g. only combining marks) appear everywhere and
+        // stay out of the automaton; `term_of` maps patterns back to terms
+        let (term_of, patterns): (Vec<usize>, Vec<&[u8]>) = normalized_terms
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !t.is_empty())
+            .map(|(i, t)| (i, t.as_bytes()))
+            .unzip();
+        let always: Vec<bool> = normalized_terms.iter().map(|t| t.is_empty()).collect();
 
         // Build Aho-Corasick automaton
-        let ac = match AhoCorasick::new(&non_empty_terms) {
+        let ac = match AhoCorasick::new(&patterns) {
             Ok(ac) => ac,
             Err(_) => return vec![],
         };
 
-        (0..self.len())
+        range
             .into_par_iter()
             .filter_map(|i| {
                 let path_bytes = self.get_path_bytes(i);
 
-                // Track which patterns matched
-                let mut matched = vec![false; term_count];
-                for mat in ac.find_iter(path_bytes) {
-                    matched[mat.pattern().as_usize()] = true;
+                // Track which terms matched; overlapping matches count, so
+                // `doc` and `docs` both match "docs"
+                let mut matched = always.clone();
+                for mat in ac.find_overlapping_iter(path_bytes) {
+                    matched[term_of[mat.pattern().as_usize()]] = true;
                 }
 
-                // All terms must match
-                if matched.iter().all(|&m| m) {
+                if query.expr.eval(&matched) && self.is_visible(i, include_hidden) {
                     Some(self.ids[i])
                 } else {
                     None
@@ -171,21 +431,21 @@ impl SearchIndex {
             .collect()
     }
 
+    fn is_visible(&self, i: usize, include_hidden: bool) -> bool {
+        include_hidden || !is_hidden_path(&self.original_paths[i])
+    }
+
     /// Main search entry point - delegates to single or multi-term search.
     pub fn search(&self, query: &str) -> Vec<i64> {
-        let start = Instant::now();
+        self.search_filtered(query, true)
+    }
 
-        let terms: Vec<String> = query
-            .split_whitespace()
-            .filter(|t| !t.is_empty())
-            .map(|t| t.to_string())
-            .collect();
+    /// Search, skipping paths with a hidden component unless `include_hidden`.
+    pub fn search_filtered(&self, query: &str, include_hidden: bool) -> Vec<i64> {
+        let start = Instant::now();
 
-        let results = match terms.len() {
-            0 => vec![],
-            1 => self.search_single_term(&terms[0]),
-            _ => self.search_multi_term(&terms),
-        };
+        let terms = query_terms(query);
+        let results = self.search_terms(&terms, include_hidden);
 
         let elapsed = start.elapsed();
         let elapsed_str = format!("{:.3}s", elapsed.as_secs_f64());
@@ -201,24 +461,41 @@ impl SearchIndex {
         results
     }
 
+    pub(crate) fn search_range(
+        &self,
+        terms: &[String],
+        range: Range<usize>,
+        include_hidden: bool,
+    ) -> Vec<i64> {
+        match terms {
+            [] => vec![],
+            [term] if !term.starts_with('-') => {
+                self.search_single_term(term, range, include_hidden)
+            }
+            _ => self.search_multi_term(terms, range, include_hidden),
+        }
+    }
+
+    /// Entries matching every term, without logging
+    pub(crate) fn search_terms(&self, terms: &[String], include_hidden: bool) -> Vec<i64> {
+        self.search_range(terms, 0..self.len(), include_hidden)
+    }
+
     /// Add a new entry to the index.
     pub fn add_entry(&mut self, id: i64, path: &str) {
-        let normalized = normalize_path(path);
-        let norm_bytes = normalized.as_bytes();
-
-        let offset = self.normalized_paths.len() as u32;
+        self.push_entry(id, path.to_string(), None);
+    }
 
-        self.ids.push(id);
-        self.offsets.push(offset);
-        self.original_paths.push(path.to_string());
-        self.normalized_paths.extend_from_slice(norm_bytes);
+    /// Add a new entry with extra searchable text (e.g. audio tags).
+    pub fn add_entry_with_extra(&mut self, id: i64, path: &str, extra: Option<&str>) {
+        self.push_entry(id, path.to_string(), extra);
     }
 
     /// Remove an entry from the index by path.
     /// Returns true if an entry was removed.
     pub fn remove_entry(&mut self, path: &str) -> bool {
         // Find the index of the entry with this path
-        let idx = match self.original_paths.iter().position(|p| p == path) {
+        let idx = match self.position(path) {
             Some(idx) => idx,
             None => return false,
         };
@@ -252,7 +529,7 @@ impl SearchIndex {
     /// Returns true if the entry was found and renamed.
     pub fn rename_entry(&mut self, old_path: &str, new_path: &str) -> bool {
         // Find the index of the entry with the old path
-        let idx = match self.original_paths.iter().position(|p| p == old_path) {
+        let idx = match self.position(old_path) {
             Some(idx) => idx,
             None => return false,
         };
@@ -266,17 +543,19 @@ impl SearchIndex {
         };
         let old_len = old_end - old_start;
 
-        // Compute new normalized path
-        let new_normalized = normalize_path(new_path);
-        let new_bytes = new_normalized.as_bytes();
+        // Compute new normalized path, keeping any extra text that follows it
+        let mut new_bytes = self.normalization.apply(new_path).into_bytes();
+        let old_bytes = &self.normalized_paths[old_start..old_end];
+        if let Some(sep) = old_bytes.iter().position(|&b| b == EXTRA_SEPARATOR) {
+            new_bytes.extend_from_slice(&old_bytes[sep..]);
+        }
         let new_len = new_bytes.len();
 
         // Update original path
         self.original_paths[idx] = new_path.to_string();
 
         // Replace bytes in normalized_paths
-        self.normalized_paths
-            .splice(old_start..old_end, new_bytes.iter().copied());
+        self.normalized_paths.splice(old_start..old_end, new_bytes);
 
         // Update offsets for all entries after this one
         let len_diff = new_len as i64 - old_len as i64;
@@ -289,12 +568,36 @@ impl SearchIndex {
         true
     }
 
+    /// Remove the entry for `path`, returning its ID and its normalized extra
+    /// text (with the leading separator), to re-add it under another path
+    pub(crate) fn take_entry(&mut self, path: &str) -> Option<(i64, Vec<u8>)> {
+        let idx = self.position(path)?;
+        let id = self.ids[idx];
+        let bytes = self.get_path_bytes(idx);
+        let extra = bytes
+            .iter()
+            .position(|&b| b == EXTRA_SEPARATOR)
+            .map(|sep| bytes[sep..].to_vec())
+            .unwrap_or_default();
+        self.remove_entry(path);
+        Some((id, extra))
+    }
+
+    /// Add an entry whose extra text was taken from another with `take_entry`
+    pub(crate) fn add_entry_with_normalized_extra(&mut self, id: i64, path: &str, extra: &[u8]) {
+        let offset = self.normalized_paths.len() as u32;
+        self.ids.push(id);
+        self.offsets.push(offset);
+        self.original_paths.push(path.to_string());
+        let normalized = self.normalization.apply(path);
+        self.normalized_paths
+            .extend_from_slice(normalized.as_bytes());
+        self.normalized_paths.extend_from_slice(extra);
+    }
+
     /// Find the ID for a path, if it exists in the index.
     pub fn find_id_by_path(&self, path: &str) -> Option<i64> {
-        self.original_paths
-            .iter()
-            .position(|p| p == path)
-            .map(|idx| self.ids[idx])
+        self.position(path).map(|idx| self.ids[idx])
     }
 }
 
@@ -310,6 +613,61 @@ mod tests {
         assert_eq!(normalize_path("résumé"), "resume");
     }
 
+    #[test]
+    fn test_width_and_kana_folding() {
+        assert_eq!(normalize_path("ＡＢＣ１２３"), "abc123");
+        assert_eq!(normalize_path("ｶﾞﾝﾀﾞﾑ"), normalize_path("ガンダム"));
+        assert_ne!(normalize_path("ガンダム"), normalize_path("がんだむ"));
+
+        let folded = Normalization {
+            fold_kana: true,
+            ..Normalization::default()
+        };
+        assert_eq!(folded.apply("ガンダム"), folded.apply("がんだむ"));
+        assert_eq!(folded.apply("ｶﾞﾝﾀﾞﾑ"), folded.apply("がんだむ"));
+        // Kanji and the prolonged sound mark have no hiragana form
+        assert_eq!(folded.apply("東京ラーメン"), "東京らーめん");
+
+        let index = SearchIndex::build_from_documents_with(
+            vec![(1, "/アニメ/ガンダム 第01話.mkv".to_string(), None)],
+            folded,
+        );
+        assert_eq!(index.search("がんだむ"), vec![1]);
+        assert_eq!(index.search("ｶﾞﾝﾀﾞﾑ ０１"), vec![1]);
+    }
+
+    #[test]
+    fn test_transliteration() {
+        let folded = Normalization {
+            transliterate: true,
+            ..Normalization::default()
+        };
+        assert_eq!(folded.apply("Łódź"), "lodz");
+        assert_eq!(folded.apply("Straße/Æsir.txt"), "strasse/aesir.txt");
+        assert_eq!(folded.apply("Café"), "cafe");
+        assert_eq!(normalize_path("Łódź"), "łodz");
+
+        let index = SearchIndex::build_from_documents_with(
+            vec![
+                (1, "/music/Łódź.mp3".to_string(), None),
+                (
+                    2,
+                    "/music/song.mp3".to_string(),
+                    Some("Mötley Crüe Ærø".to_string()),
+                ),
+            ],
+            folded,
+        );
+        assert_eq!(index.search("lodz"), vec![1]);
+        assert_eq!(index.search("ŁÓDŹ"), vec![1]);
+        assert_eq!(index.search("aero"), vec![2]);
+        assert!(
+            SearchIndex::build_from_entries(vec![(1, "/Łódź".to_string())])
+                .search("lodz")
+                .is_empty()
+        );
+    }
+
     #[test]
     fn test_build_and_search_single_term() {
         let entries = vec![
@@ -333,6 +691,21 @@ mod tests {
         assert!(results.is_empty());
     }
 
+    #[test]
+    fn test_search_filtered_hidden() {
+        let entries = vec![
+            (1, "/notes/todo.txt".to_string()),
+            (2, "/.config/todo.txt".to_string()),
+            (3, "/notes/.todo.txt".to_string()),
+        ];
+
+        let index = SearchIndex::build_from_entries(entries);
+
+        assert_eq!(index.search_filtered("todo", false), vec![1]);
+        assert_eq!(index.search_filtered("notes todo", false), vec![1]);
+        assert_eq!(index.search_filtered("todo", true).len(), 3);
+    }
+
     #[test]
     fn test_search_multi_term() {
         let entries = vec![
@@ -357,6 +730,53 @@ mod tests {
         assert!(results.is_empty());
     }
 
+    #[test]
+    fn test_search_or_and_negation() {
+        let entries = vec![
+            (1, "/docs/report.pdf".to_string()),
+            (2, "/docs/notes.txt".to_string()),
+            (3, "/docs/archive/old.pdf".to_string()),
+            (4, "/images/photo.jpg".to_string()),
+        ];
+
+        let index = SearchIndex::build_from_entries(entries);
+        let sorted = |query: &str| {
+            let mut results = index.search(query);
+            results.sort();
+            results
+        };
+
+        assert_eq!(sorted("docs -archive"), vec![1, 2]);
+        assert_eq!(sorted("pdf OR txt"), vec![1, 2, 3]);
+        assert_eq!(sorted("docs pdf OR jpg -archive"), vec![1]);
+        assert_eq!(sorted("photo OR report OR notes"), vec![1, 2, 4]);
+        // Overlapping terms both count
+        assert_eq!(sorted("doc docs report"), vec![1]);
+        // A dangling OR is a plain word; negations alone match nothing
+        assert!(sorted("notes OR").is_empty());
+        assert!(sorted("-archive").is_empty());
+        assert!(sorted("-archive -docs").is_empty());
+    }
+
+    #[test]
+    fn test_term_query_expression() {
+        let words = |q: &str| query_terms(q);
+        let query = TermQuery::parse(&words("a OR b -c")).unwrap();
+        assert_eq!(query.terms, ["a", "b", "c"]);
+        assert_eq!(
+            query.expr,
+            TermExpr::All(vec![
+                TermExpr::Any(vec![TermExpr::Term(0), TermExpr::Term(1)]),
+                TermExpr::Not(Box::new(TermExpr::Term(2))),
+            ])
+        );
+        assert_eq!(
+            TermQuery::parse(&words("OR -")).unwrap().expr,
+            TermExpr::All(vec![TermExpr::Term(0), TermExpr::Term(1)])
+        );
+        assert!(TermQuery::parse(&words("a OR -b")).is_none());
+    }
+
     #[test]
     fn test_search_case_insensitive() {
         let entries = vec![
@@ -455,6 +875,41 @@ mod tests {
         assert_eq!(results, vec![2]);
     }
 
+    #[test]
+    fn test_case_insensitive_path_lookups() {
+        let mut index = SearchIndex::build_from_entries(vec![(1, "/Docs/Old.txt".to_string())]);
+        assert_eq!(index.find_id_by_path("/docs/old.txt"), None);
+
+        index.set_case_insensitive_paths(true);
+        assert_eq!(index.find_id_by_path("/docs/old.txt"), Some(1));
+        assert!(index.rename_entry("/DOCS/OLD.TXT", "/Docs/New.txt"));
+        assert_eq!(index.search("new"), vec![1]);
+        assert!(index.remove_entry("/docs/new.txt"));
+        assert!(index.is_empty());
+    }
+
+    #[test]
+    fn test_extra_text_is_searchable_and_survives_rename() {
+        let entries = vec![
+            (
+                1,
+                "/music/01.flac".to_string(),
+                Some("Björk Homogenic Jóga".to_string()),
+            ),
+            (2, "/music/02.flac".to_string(), None),
+        ];
+
+        let mut index = SearchIndex::build_from_documents(entries);
+
+        assert_eq!(index.search("bjork joga"), vec![1]);
+        assert_eq!(index.search("music homogenic"), vec![1]);
+
+        assert!(index.rename_entry("/music/01.flac", "/albums/01.flac"));
+        assert_eq!(index.search("albums homogenic"), vec![1]);
+        assert!(index.search("music homogenic").is_empty());
+        assert_eq!(index.search("02"), vec![2]);
+    }
+
     #[test]
     fn test_find_id_by_path() {
         let entries = vec![
@@ -469,6 +924,21 @@ mod tests {
         assert_eq!(index.find_id_by_path("/nonexistent"), None);
     }
 
+    #[test]
+    fn test_memory_accounting() {
+        let entries = vec![
+            (1, "/docs/report.txt".to_string(), Some("Ana".to_string())),
+            (2, "/docs/notes.txt".to_string(), None),
+        ];
+        let estimate = SearchIndex::estimate_memory_bytes(&entries);
+        let index = SearchIndex::build_from_documents(entries);
+
+        let paths = "/docs/report.txt".len() + "/docs/notes.txt".len();
+        assert!(index.memory_bytes() >= paths * 2 + "ana".len());
+        assert!(estimate >= paths * 2);
+        assert_eq!(SearchIndex::new().memory_bytes(), 0);
+    }
+
     #[test]
     fn test_empty_query() {
         let entries = vec![(1, "/docs/file.txt".to_string())];
@@ -0,0 +1,390 @@
+//! The search index split into shards by top-level directory.
+//!
+//! Everything below `/photos` lives in the `photos` shard; entries directly
+//! in the root share one shard of their own. Renaming or deleting a subtree
+//! only reloads the shards it touches, and queries scan all shards in
+//! parallel with rayon.
+
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tracing::info;
+
+use super::search_index::{Normalization, SearchIndex, query_terms};
+
+/// Shard holding the entries directly in the root
+const ROOT_SHARD: &str = "";
+
+/// Entries scanned before the first batch of streamed results; each later
+/// batch scans twice as many, up to `MAX_STREAM_BATCH`
+const FIRST_STREAM_BATCH: usize = 16 * 1024;
+const MAX_STREAM_BATCH: usize = 1024 * 1024;
+
+#[derive(Default)]
+pub struct ShardedSearchIndex {
+    shards: BTreeMap<String, SearchIndex>,
+    /// Whether path lookups ignore ASCII case, like the database's NOCASE
+    case_insensitive_paths: bool,
+    /// Folding every shard applies
+    normalization: Normalization,
+}
+
+impl ShardedSearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build from (id, path, extra text) triples, as
+    /// `SearchIndex::build_from_documents`
+    pub fn build_from_documents(
+        entries: Vec<(i64, String, Option<String>)>,
+        case_insensitive_paths: bool,
+        normalization: Normalization,
+    ) -> Self {
+        let mut grouped: BTreeMap<String, Vec<(i64, String, Option<String>)>> = BTreeMap::new();
+        for entry in entries {
+            let key = shard_key(&entry.1, case_insensitive_paths);
+            grouped.entry(key).or_default().push(entry);
+        }
+
+        let shards = grouped
+            .into_par_iter()
+            .map(|(key, entries)| {
+                let mut shard = SearchIndex::build_from_documents_with(entries, normalization);
+                shard.set_case_insensitive_paths(case_insensitive_paths);
+                (key, shard)
+            })
+            .collect();
+        Self {
+            shards,
+            case_insensitive_paths,
+            normalization,
+        }
+    }
+
+    /// Create an empty index whose shards fold text with `normalization`
+    pub fn with_normalization(normalization: Normalization) -> Self {
+        Self {
+            normalization,
+            ..Self::default()
+        }
+    }
+
+    /// Make lookups by path ignore ASCII case
+    pub fn set_case_insensitive_paths(&mut self, case_insensitive: bool) {
+        self.case_insensitive_paths = case_insensitive;
+        for shard in self.shards.values_mut() {
+            shard.set_case_insensitive_paths(case_insensitive);
+        }
+    }
+
+    /// Shard a path belongs in
+    pub fn key_for(&self, path: &str) -> String {
+        shard_key(path, self.case_insensitive_paths)
+    }
+
+    /// Shard holding everything below `dir`
+    pub fn key_below(&self, dir: &str) -> String {
+        self.key_for(&format!("{}/_", dir.trim_end_matches('/')))
+    }
+
+    /// Put a freshly built shard in place of `key`'s
+    pub fn replace_shard(&mut self, key: String, mut shard: SearchIndex) {
+        if shard.is_empty() {
+            self.shards.remove(&key);
+            return;
+        }
+        shard.set_case_insensitive_paths(self.case_insensitive_paths);
+        self.shards.insert(key, shard);
+    }
+
+    /// Heap bytes held by all shards and their keys
+    pub fn memory_bytes(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|(key, shard)| key.capacity() + shard.memory_bytes())
+            .sum()
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.values().map(SearchIndex::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.values().all(SearchIndex::is_empty)
+    }
+
+    pub fn search(&self, query: &str) -> Vec<i64> {
+        self.search_filtered(query, true)
+    }
+
+    /// Search every shard in parallel, skipping paths with a hidden
+    /// component unless `include_hidden`
+    pub fn search_filtered(&self, query: &str, include_hidden: bool) -> Vec<i64> {
+        self.search_terms(&query_terms(query), include_hidden)
+    }
+
+    /// Entries matching every term, like `search_filtered` with the query
+    /// already split up
+    pub fn search_terms(&self, terms: &[String], include_hidden: bool) -> Vec<i64> {
+        let start = Instant::now();
+
+        let results: Vec<i64> = if terms.is_empty() {
+            vec![]
+        } else {
+            self.shards
+                .par_iter()
+                .flat_map_iter(|(_, shard)| shard.search_terms(terms, include_hidden))
+                .collect()
+        };
+
+        let elapsed_str = format!("{:.3}s", start.elapsed().as_secs_f64());
+        info!(
+            terms = ?terms,
+            results = results.len(),
+            index_size = self.len(),
+            shards = self.shards.len(),
+            elapsed = %elapsed_str,
+            "Search completed"
+        );
+
+        results
+    }
+
+    /// Search like `search_filtered`, handing each slice's matches and the
+    /// number of entries scanned so far to `on_batch` as the scan goes. The
+    /// first slice is small so early hits arrive quickly on very large
+    /// indexes. Stops early when `on_batch` returns false. Returns the
+    /// number of entries scanned.
+    pub fn search_in_batches(
+        &self,
+        query: &str,
+        include_hidden: bool,
+        mut on_batch: impl FnMut(Vec<i64>, usize) -> bool,
+    ) -> usize {
+        let terms = query_terms(query);
+        if terms.is_empty() {
+            return 0;
+        }
+
+        let mut scanned = 0;
+        let mut batch = FIRST_STREAM_BATCH;
+        let mut pending = Vec::new();
+        let mut pending_scanned = 0;
+        for shard in self.shards.values() {
+            let mut start = 0;
+            while start < shard.len() {
+                let end = (start + batch - pending_scanned).min(shard.len());
+                pending.extend(shard.search_range(&terms, start..end, include_hidden));
+                pending_scanned += end - start;
+                scanned += end - start;
+                start = end;
+                if pending_scanned == batch {
+                    if !on_batch(std::mem::take(&mut pending), scanned) {
+                        return scanned;
+                    }
+                    pending_scanned = 0;
+                    batch = (batch * 2).min(MAX_STREAM_BATCH);
+                }
+            }
+        }
+        if pending_scanned > 0 {
+            on_batch(pending, scanned);
+        }
+        scanned
+    }
+
+    /// The shard `path` belongs in, created if need be
+    fn shard_for(&mut self, path: &str) -> &mut SearchIndex {
+        let case_insensitive = self.case_insensitive_paths;
+        let normalization = self.normalization;
+        self.shards.entry(self.key_for(path)).or_insert_with(|| {
+            let mut shard = SearchIndex::with_normalization(normalization);
+            shard.set_case_insensitive_paths(case_insensitive);
+            shard
+        })
+    }
+
+    /// Add a new entry with extra searchable text (e.g. audio tags).
+    pub fn add_entry_with_extra(&mut self, id: i64, path: &str, extra: Option<&str>) {
+        self.shard_for(path).add_entry_with_extra(id, path, extra);
+    }
+
+    pub fn add_entry(&mut self, id: i64, path: &str) {
+        self.add_entry_with_extra(id, path, None);
+    }
+
+    /// Remove an entry by path. Returns true if an entry was removed.
+    pub fn remove_entry(&mut self, path: &str) -> bool {
+        let key = self.key_for(path);
+        let Some(shard) = self.shards.get_mut(&key) else {
+            return false;
+        };
+        let removed = shard.remove_entry(path);
+        if shard.is_empty() {
+            self.shards.remove(&key);
+        }
+        removed
+    }
+
+    /// Rename one entry, moving it to another shard if need be. Returns
+    /// true if the entry was found and renamed.
+    pub fn rename_entry(&mut self, old_path: &str, new_path: &str) -> bool {
+        let old_key = self.key_for(old_path);
+        if old_key == self.key_for(new_path) {
+            return self
+                .shards
+                .get_mut(&old_key)
+                .is_some_and(|shard| shard.rename_entry(old_path, new_path));
+        }
+
+        let Some(shard) = self.shards.get_mut(&old_key) else {
+            return false;
+        };
+        let Some((id, extra)) = shard.take_entry(old_path) else {
+            return false;
+        };
+        if shard.is_empty() {
+            self.shards.remove(&old_key);
+        }
+        self.shard_for(new_path)
+            .add_entry_with_normalized_extra(id, new_path, &extra);
+        true
+    }
+
+    pub fn find_id_by_path(&self, path: &str) -> Option<i64> {
+        self.shards
+            .get(&self.key_for(path))
+            .and_then(|shard| shard.find_id_by_path(path))
+    }
+}
+
+/// Top-level directory `path` lies below, or `ROOT_SHARD` for entries
+/// directly in the root
+fn shard_key(path: &str, case_insensitive: bool) -> String {
+    let key = match path.trim_start_matches('/').split_once('/') {
+        Some((top, _)) => top,
+        None => ROOT_SHARD,
+    };
+    if case_insensitive {
+        key.to_ascii_lowercase()
+    } else {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn documents(paths: &[&str]) -> Vec<(i64, String, Option<String>)> {
+        paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| (i as i64 + 1, path.to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn entries_are_sharded_by_top_level_directory() {
+        let index = ShardedSearchIndex::build_from_documents(
+            documents(&[
+                "/photos",
+                "/photos/beach.jpg",
+                "/photos/2024/beach.jpg",
+                "/docs/beach-trip.txt",
+                "/readme.txt",
+            ]),
+            false,
+            Normalization::default(),
+        );
+        assert_eq!(index.shard_count(), 3);
+        assert_eq!(index.len(), 5);
+        assert_eq!(index.key_for("/photos"), "");
+        assert_eq!(index.key_below("/photos"), "photos");
+        assert_eq!(index.key_below("/photos/2024/"), "photos");
+
+        let mut results = index.search("beach");
+        results.sort();
+        assert_eq!(results, vec![2, 3, 4]);
+        assert_eq!(index.find_id_by_path("/photos/2024/beach.jpg"), Some(3));
+    }
+
+    #[test]
+    fn renames_move_entries_between_shards_with_their_extra_text() {
+        let mut index = ShardedSearchIndex::build_from_documents(
+            vec![(1, "/music/song.mp3".to_string(), Some("Nina".to_string()))],
+            false,
+            Normalization::default(),
+        );
+
+        assert!(index.rename_entry("/music/song.mp3", "/archive/song.mp3"));
+        assert_eq!(index.shard_count(), 1);
+        assert_eq!(index.search("nina"), vec![1]);
+        assert_eq!(index.search("archive"), vec![1]);
+        assert!(index.search("music").is_empty());
+        assert!(!index.rename_entry("/music/song.mp3", "/x.mp3"));
+
+        assert!(index.remove_entry("/archive/song.mp3"));
+        assert!(index.is_empty());
+        assert_eq!(index.shard_count(), 0);
+    }
+
+    #[test]
+    fn replacing_a_shard_leaves_the_others_alone() {
+        let mut index = ShardedSearchIndex::build_from_documents(
+            documents(&["/photos/a.jpg", "/docs/a.txt"]),
+            true,
+            Normalization::default(),
+        );
+        assert_eq!(index.key_below("/Photos"), "photos");
+
+        let shard = SearchIndex::build_from_entries(vec![(9, "/photos/b.jpg".to_string())]);
+        index.replace_shard(index.key_below("/Photos"), shard);
+        assert_eq!(index.search("a."), vec![2]);
+        assert_eq!(index.find_id_by_path("/PHOTOS/B.JPG"), Some(9));
+
+        index.replace_shard("docs".to_string(), SearchIndex::new());
+        assert_eq!(index.shard_count(), 1);
+    }
+
+    #[test]
+    fn search_in_batches_spans_shards() {
+        let half = FIRST_STREAM_BATCH as i64 * 2;
+        let entries = (0..half * 2)
+            .map(|i| {
+                let dir = if i < half { "a" } else { "b" };
+                (i, format!("/{dir}/img_{i}.jpg"), None)
+            })
+            .collect();
+        let index =
+            ShardedSearchIndex::build_from_documents(entries, false, Normalization::default());
+
+        let mut batches = Vec::new();
+        let scanned = index.search_in_batches("img_1", true, |ids, scanned| {
+            batches.push((ids, scanned));
+            true
+        });
+        assert_eq!(scanned, index.len());
+        // Scanned in slices of 16k, 32k, then the remaining 16k entries
+        let progress: Vec<usize> = batches.iter().map(|(_, scanned)| *scanned).collect();
+        assert_eq!(
+            progress,
+            [FIRST_STREAM_BATCH, FIRST_STREAM_BATCH * 3, index.len()]
+        );
+        let mut streamed: Vec<i64> = batches.into_iter().flat_map(|(ids, _)| ids).collect();
+        streamed.sort();
+        let mut all = index.search("img_1");
+        all.sort();
+        assert_eq!(streamed, all);
+
+        // Stopping after the first batch leaves the rest unscanned
+        let scanned = index.search_in_batches("img_1", true, |_, _| false);
+        assert_eq!(scanned, FIRST_STREAM_BATCH);
+        assert_eq!(index.search_in_batches("  ", true, |_, _| true), 0);
+    }
+}
@@ -0,0 +1,161 @@
+//! Finding other copies and versions of a file in the index: files with a
+//! similar name, the same running time or dimensions, or the same content.
+//! Meant for alternate cuts and duplicate downloads of the same movie.
+
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::models::IndexedFileRow;
+
+/// Running times this close, in seconds, count as the same
+pub const DURATION_TOLERANCE_SECS: f64 = 1.0;
+
+/// Share of name tokens two files must have in common to count as similar
+const NAME_THRESHOLD: f64 = 0.5;
+
+/// Lowest score a candidate needs. Dimensions alone, shared by every 1080p
+/// video, are not enough.
+const MIN_SCORE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimilarReason {
+    Name,
+    Duration,
+    Dimensions,
+    Checksum,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Similarity {
+    pub score: f64,
+    pub reasons: Vec<SimilarReason>,
+}
+
+/// Lowercased words of a file name without its extension, e.g. `movie`,
+/// `2010` and `1080p` for `Movie.2010.1080p.mkv`. Single characters are
+/// dropped.
+pub fn name_tokens(name: &str) -> Vec<String> {
+    let stem = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    };
+    let mut seen = HashSet::new();
+    stem.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| token.chars().count() > 1)
+        .map(str::to_lowercase)
+        .filter(|token| seen.insert(token.clone()))
+        .collect()
+}
+
+/// Dice coefficient of two token lists: 1.0 for the same words, 0.0 for
+/// none in common
+pub fn name_similarity(a: &[String], b: &[String]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let shared = a.iter().filter(|token| b.contains(token)).count();
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
+
+/// How much `candidate` looks like another copy or version of `source`, or
+/// `None` if not enough. `same_content` says their checksums matched.
+pub fn compare(
+    source: &IndexedFileRow,
+    source_tokens: &[String],
+    candidate: &IndexedFileRow,
+    same_content: bool,
+) -> Option<Similarity> {
+    let mut score = 0.0;
+    let mut reasons = Vec::new();
+
+    let name = name_similarity(source_tokens, &name_tokens(&candidate.name));
+    if name >= NAME_THRESHOLD {
+        score += name;
+        reasons.push(SimilarReason::Name);
+    }
+    if let (Some(a), Some(b)) = (source.duration, candidate.duration)
+        && a > 0.0
+        && (a - b).abs() <= DURATION_TOLERANCE_SECS
+    {
+        score += 0.5;
+        reasons.push(SimilarReason::Duration);
+    }
+    if source.width.is_some()
+        && source.height.is_some()
+        && (source.width, source.height) == (candidate.width, candidate.height)
+    {
+        score += 0.25;
+        reasons.push(SimilarReason::Dimensions);
+    }
+    if same_content {
+        score += 1.0;
+        reasons.push(SimilarReason::Checksum);
+    }
+
+    (score >= MIN_SCORE).then_some(Similarity { score, reasons })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str, duration: Option<f64>, size: (i32, i32)) -> IndexedFileRow {
+        IndexedFileRow {
+            id: 0,
+            path: format!("/{name}"),
+            name: name.to_string(),
+            is_dir: false,
+            size: Some(1),
+            created_at: None,
+            modified_at: None,
+            mime_type: Some("video/mp4".to_string()),
+            width: Some(size.0),
+            height: Some(size.1),
+            duration,
+            metadata_status: "complete".to_string(),
+            indexed_at: String::new(),
+            artist: None,
+            album: None,
+            title: None,
+            track_number: None,
+            page_count: None,
+            author: None,
+            original_name: None,
+        }
+    }
+
+    #[test]
+    fn tokens_ignore_case_punctuation_and_extension() {
+        assert_eq!(
+            name_tokens("The.Movie.2010.1080p-X.mkv"),
+            ["the", "movie", "2010", "1080p"]
+        );
+        assert_eq!(name_tokens(".bashrc"), ["bashrc"]);
+    }
+
+    #[test]
+    fn alternate_cuts_and_copies_are_similar() {
+        let source = row("Movie.2010.1080p.BluRay.mkv", Some(7200.0), (1920, 1080));
+        let tokens = name_tokens(&source.name);
+
+        let cut = row("Movie (2010) Directors Cut.mp4", Some(7900.0), (1280, 720));
+        let found = compare(&source, &tokens, &cut, false).unwrap();
+        assert_eq!(found.reasons, [SimilarReason::Name]);
+
+        let copy = row("download_17.mp4", Some(7200.4), (1920, 1080));
+        let found = compare(&source, &tokens, &copy, true).unwrap();
+        assert_eq!(
+            found.reasons,
+            [
+                SimilarReason::Duration,
+                SimilarReason::Dimensions,
+                SimilarReason::Checksum
+            ]
+        );
+
+        // Sharing only a resolution is not enough
+        let other = row("holiday.mp4", Some(95.0), (1920, 1080));
+        assert_eq!(compare(&source, &tokens, &other, false), None);
+    }
+}
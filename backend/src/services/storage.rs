@@ -0,0 +1,99 @@
+//! Free-space guards. NAS installs often keep the database on a small system
+//! partition; running it full breaks more than filex, so writes stop below a
+//! configured reserve. The root volume gets its own reserve, checked before
+//! uploads and copies rather than after they fill the disk.
+
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone)]
+pub struct StorageGuard {
+    path: PathBuf,
+    min_free_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageStatus {
+    pub path: PathBuf,
+    /// `None` if free space could not be determined
+    pub available_bytes: Option<u64>,
+    pub min_free_bytes: u64,
+    pub low: bool,
+}
+
+impl StorageGuard {
+    /// Guard the volume containing `path`. A `min_free_bytes` of zero
+    /// disables the guard.
+    pub fn new(path: PathBuf, min_free_bytes: u64) -> Self {
+        Self {
+            path,
+            min_free_bytes,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        let dir = config
+            .database_path
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."));
+        Self::new(dir, config.db_min_free_mb * 1024 * 1024)
+    }
+
+    /// Guard the volume holding the root
+    pub fn for_root(config: &Config) -> Self {
+        Self::new(
+            config.root_path.clone(),
+            config.root_min_free_mb * 1024 * 1024,
+        )
+    }
+
+    /// Whether `bytes` more fit while leaving the reserve free. Unknown free
+    /// space is assumed to fit, like in `status`.
+    pub fn has_room_for(&self, bytes: u64) -> bool {
+        fs2::available_space(&self.path).ok().is_none_or(|free| {
+            free.checked_sub(bytes)
+                .is_some_and(|left| left >= self.min_free_bytes)
+        })
+    }
+
+    pub fn status(&self) -> StorageStatus {
+        let available_bytes = fs2::available_space(&self.path).ok();
+        StorageStatus {
+            path: self.path.clone(),
+            available_bytes,
+            min_free_bytes: self.min_free_bytes,
+            // Unknown free space is not treated as low, so a failed statvfs
+            // never blocks indexing by itself
+            low: available_bytes.is_some_and(|free| free < self.min_free_bytes),
+        }
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.status().low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn threshold_controls_low_flag() {
+        let tmp = tempdir().unwrap();
+
+        let disabled = StorageGuard::new(tmp.path().to_path_buf(), 0);
+        assert!(disabled.status().available_bytes.is_some());
+        assert!(!disabled.is_low());
+
+        let impossible = StorageGuard::new(tmp.path().to_path_buf(), u64::MAX);
+        assert!(impossible.is_low());
+        assert!(!impossible.has_room_for(0));
+
+        assert!(disabled.has_room_for(1));
+        assert!(!disabled.has_room_for(u64::MAX));
+    }
+}
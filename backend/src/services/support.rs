@@ -0,0 +1,215 @@
+//! Support bundles: a gzipped tarball of diagnostics attached to bug reports.
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use serde_json::json;
+use std::io;
+
+use crate::config::Config;
+use crate::services::webhooks;
+
+const REDACTED: &str = "[redacted]";
+
+/// Files collected for a support bundle, written in insertion order
+#[derive(Default)]
+pub struct SupportBundle {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl SupportBundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_json<T: Serialize>(&mut self, name: &str, value: &T) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(value).map_err(io::Error::other)?;
+        self.files.push((name.to_string(), data));
+        Ok(())
+    }
+
+    pub fn add_text(&mut self, name: &str, text: String) {
+        self.files.push((name.to_string(), text.into_bytes()));
+    }
+
+    /// Encode the bundle as `.tar.gz` with every file under `prefix/`
+    pub fn into_tar_gz(self, prefix: &str) -> io::Result<Vec<u8>> {
+        let encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let mut archive = tar::Builder::new(encoder);
+        let mtime = chrono::Utc::now().timestamp().max(0) as u64;
+
+        for (name, data) in self.files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            header.set_cksum();
+            archive.append_data(&mut header, format!("{prefix}/{name}"), data.as_slice())?;
+        }
+
+        archive.into_inner()?.finish()
+    }
+}
+
+/// Configuration as JSON with secrets replaced, safe to attach to a public issue
+pub fn redacted_config(config: &Config) -> serde_json::Value {
+    // Built apart to keep `json!` within the macro recursion limit
+    let auth = json!({
+        "enabled": config.auth.enabled,
+        "password": config.auth.password.as_ref().map(|_| REDACTED),
+        "session_timeout_secs": config.auth.session_timeout_secs,
+        "cookie_name": config.auth.cookie_name,
+        "oidc": config.auth.oidc.as_ref().map(|oidc| json!({
+            "issuer": oidc.issuer,
+            "client_id": oidc.client_id,
+            "client_secret": oidc.client_secret.as_ref().map(|_| REDACTED),
+            "redirect_url": oidc.redirect_url,
+            "groups_claim": oidc.groups_claim,
+            "allowed_groups": oidc.allowed_groups,
+        })),
+        "proxy_header": config.auth.proxy_header,
+        "trusted_proxies": config
+            .auth
+            .trusted_proxies
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        "admin_users": config.auth.admin_users,
+    });
+    json!({
+        "root_path": config.root_path,
+        "host": config.host,
+        "port": config.port,
+        "database_path": config.database_path,
+        "db_min_free_mb": config.db_min_free_mb,
+        "root_min_free_mb": config.root_min_free_mb,
+        "db_check_interval_secs": config.db_check_interval_secs,
+        "enable_indexer": config.enable_indexer,
+        "index_interval_secs": config.index_interval_secs,
+        "purge_max_percent": config.purge_max_percent,
+        "show_hidden": config.show_hidden,
+        "case_insensitive_paths": config.case_insensitive_paths,
+        "search_index_max_mb": config.search_index_max_mb,
+        "search_transliterate": config.search_transliterate,
+        "search_fold_kana": config.search_fold_kana,
+        "ffprobe_concurrency": config.ffprobe_concurrency,
+        "ffprobe_timeout_secs": config.ffprobe_timeout_secs,
+        "webhook_urls": config
+            .webhook_urls
+            .iter()
+            .map(|url| webhooks::url_origin(url))
+            .collect::<Vec<_>>(),
+        "webhook_secret": config.webhook_secret.as_ref().map(|_| REDACTED),
+        "hooks_file": config.hooks_file,
+        "notify_file": config.notify_file,
+        "templates_dir": config.templates_dir,
+        "rclone_path": config.rclone_path,
+        "clamd_socket": config.clamd_socket,
+        "quarantine_dir": config.quarantine_dir,
+        "clamd_max_mb": config.clamd_max_mb,
+        "dedup_store": config.dedup_store,
+        "allow_links": config.allow_links,
+        "allow_permission_changes": config.allow_permission_changes,
+        "download_rate_limit_kib": config.download_rate_limit_kib,
+        "download_rate_limit_total_kib": config.download_rate_limit_total_kib,
+        "readahead_mb": config.readahead_mb,
+        "max_downloads": config.max_downloads,
+        "max_downloads_per_session": config.max_downloads_per_session,
+        "max_uploads": config.max_uploads,
+        "max_uploads_per_session": config.max_uploads_per_session,
+        "request_timeout_secs": config.request_timeout_secs,
+        "transfer_timeout_secs": config.transfer_timeout_secs,
+        "body_timeout_secs": config.body_timeout_secs,
+        "static_path": config.static_path,
+        "auth": auth,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthConfig;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    #[test]
+    fn bundle_round_trips_and_config_hides_password() {
+        let config = Config {
+            root_path: PathBuf::from("/data"),
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            database_path: PathBuf::from("/app/data/filex.db"),
+            db_min_free_mb: 0,
+            root_min_free_mb: 0,
+            db_check_interval_secs: 0,
+            enable_indexer: true,
+            index_interval_secs: 300,
+            purge_max_percent: 25,
+            show_hidden: false,
+            case_insensitive_paths: false,
+            search_index_max_mb: 0,
+            search_transliterate: false,
+            search_fold_kana: false,
+            ffprobe_concurrency: 4,
+            ffprobe_timeout_secs: 15,
+            webhook_urls: vec!["https://hooks.slack.com/services/T000/B000/s3cr3t".to_string()],
+            webhook_secret: None,
+            hooks_file: None,
+            notify_file: None,
+            templates_dir: None,
+            rclone_path: None,
+            clamd_socket: None,
+            quarantine_dir: None,
+            clamd_max_mb: 100,
+            dedup_store: None,
+            allow_links: false,
+            allow_permission_changes: false,
+            download_rate_limit_kib: 0,
+            download_rate_limit_total_kib: 0,
+            readahead_mb: 0,
+            max_downloads: 0,
+            max_downloads_per_session: 0,
+            max_uploads: 0,
+            max_uploads_per_session: 0,
+            request_timeout_secs: 0,
+            transfer_timeout_secs: 0,
+            body_timeout_secs: 0,
+            static_path: PathBuf::from("./static"),
+            auth: AuthConfig {
+                enabled: true,
+                password: Some("hunter2".to_string()),
+                session_timeout_secs: 86400,
+                cookie_name: "fm_session".to_string(),
+                oidc: None,
+                proxy_header: None,
+                trusted_proxies: Vec::new(),
+                admin_users: Vec::new(),
+            },
+        };
+
+        let mut bundle = SupportBundle::new();
+        bundle
+            .add_json("config.json", &redacted_config(&config))
+            .unwrap();
+        bundle.add_text("logs.txt", "hello\n".to_string());
+        let bytes = bundle.into_tar_gz("support").unwrap();
+
+        let mut archive = tar::Archive::new(GzDecoder::new(bytes.as_slice()));
+        let mut contents = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().to_string();
+            let mut text = String::new();
+            entry.read_to_string(&mut text).unwrap();
+            contents.push((path, text));
+        }
+
+        assert_eq!(contents[0].0, "support/config.json");
+        assert!(!contents[0].1.contains("hunter2"));
+        assert!(!contents[0].1.contains("s3cr3t"));
+        assert!(contents[0].1.contains("https://hooks.slack.com"));
+        assert!(contents[0].1.contains(REDACTED));
+        assert_eq!(contents[1], ("support/logs.txt".into(), "hello\n".into()));
+    }
+}
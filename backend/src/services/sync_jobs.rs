@@ -0,0 +1,250 @@
+//! Scheduled one-way mirrors of one directory into another on the same
+//! server, the kind of thing otherwise done with cron and rsync. Jobs live
+//! in the `sync_jobs` table; a background loop runs those that are due and
+//! records the outcome of each run.
+
+use chrono::{DateTime, Duration as ChronoDuration, SecondsFormat, Utc};
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::db::{self, SyncJobRow, SyncRunRow};
+use crate::services::filesystem::{FilesystemService, SyncCompare, SyncStats};
+
+/// How often the loop looks for due jobs
+const TICK: Duration = Duration::from_secs(30);
+
+/// Longest interval between runs of a job, one year
+pub const MAX_INTERVAL_SECS: i64 = 365 * 24 * 60 * 60;
+
+pub const RUN_SUCCESS: &str = "success";
+pub const RUN_FAILED: &str = "failed";
+
+/// Times as stored in `sync_jobs`, which compare correctly as text
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub struct SyncJobService {
+    pool: SqlitePool,
+    fs: FilesystemService,
+    /// Jobs with a run in progress, so a slow run is not started twice
+    running: Mutex<HashSet<i64>>,
+}
+
+impl SyncJobService {
+    pub fn new(pool: SqlitePool, fs: FilesystemService) -> Self {
+        Self {
+            pool,
+            fs,
+            running: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    pub fn fs(&self) -> &FilesystemService {
+        &self.fs
+    }
+
+    pub fn is_running(&self, id: i64) -> bool {
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&id)
+    }
+
+    /// Run due jobs until the server stops
+    pub async fn start_background_loop(self: Arc<Self>) {
+        info!("Starting sync job scheduler");
+        loop {
+            self.run_due().await;
+            tokio::time::sleep(TICK).await;
+        }
+    }
+
+    /// Run every enabled job whose next run time has passed, one after the
+    /// other
+    pub async fn run_due(self: &Arc<Self>) {
+        let due = match db::due_sync_jobs(&self.pool, &format_time(Utc::now())).await {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to list due sync jobs: {}", e);
+                return;
+            }
+        };
+        for job in due {
+            self.run_job(&job).await;
+        }
+    }
+
+    /// Mirror the job's source into its destination now, record the run and
+    /// schedule the next one. Returns `None` if the job is already running.
+    pub async fn run_job(self: &Arc<Self>, job: &SyncJobRow) -> Option<SyncRunRow> {
+        if !self
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(job.id)
+        {
+            return None;
+        }
+
+        let started_at = Utc::now();
+        let service = self.clone();
+        let (source, destination) = (job.source.clone(), job.destination.clone());
+        let compare = if job.checksum {
+            SyncCompare::Contents
+        } else {
+            SyncCompare::SizeAndMtime
+        };
+        let delete_extraneous = job.delete_extraneous;
+        let result = tokio::task::spawn_blocking(move || {
+            service
+                .fs
+                .mirror_directory(&source, &destination, compare, delete_extraneous)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&job.id);
+
+        let (status, stats, error) = match result {
+            Ok(stats) => {
+                info!(
+                    "Sync job {} ({} -> {}): {} created, {} updated, {} skipped, {} deleted",
+                    job.id,
+                    job.source,
+                    job.destination,
+                    stats.created,
+                    stats.updated,
+                    stats.skipped,
+                    stats.deleted
+                );
+                (RUN_SUCCESS, stats, None)
+            }
+            Err(e) => {
+                warn!("Sync job {} failed: {}", job.id, e);
+                (RUN_FAILED, SyncStats::default(), Some(e))
+            }
+        };
+        let finished_at = Utc::now();
+        let mut run = SyncRunRow {
+            id: 0, // Set by DB
+            job_id: job.id,
+            started_at: started_at.to_rfc3339(),
+            finished_at: finished_at.to_rfc3339(),
+            status: status.to_string(),
+            created: stats.created as i64,
+            updated: stats.updated as i64,
+            skipped: stats.skipped as i64,
+            deleted: stats.deleted as i64,
+            error,
+        };
+        match db::record_sync_run(&self.pool, &run).await {
+            Ok(id) => run.id = id,
+            Err(e) => warn!("Failed to record sync run: {}", e),
+        }
+
+        // Count the interval from the end of the run, so a run longer than
+        // the interval does not queue up another straight away. Intervals
+        // are capped when a job is created; chrono panics on durations out
+        // of range, so treat anything longer stored before then as the cap.
+        let next = ChronoDuration::try_seconds(job.interval_secs)
+            .filter(|interval| interval.num_seconds() <= MAX_INTERVAL_SECS)
+            .and_then(|interval| finished_at.checked_add_signed(interval))
+            .unwrap_or_else(|| finished_at + ChronoDuration::seconds(MAX_INTERVAL_SECS));
+        if let Err(e) = db::set_sync_job_next_run(&self.pool, job.id, &format_time(next)).await {
+            warn!("Failed to schedule sync job {}: {}", job.id, e);
+        }
+        Some(run)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn due_jobs_run_and_are_rescheduled() {
+        let tmp = tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("src/sub")).unwrap();
+        fs::write(tmp.path().join("src/sub/a.txt"), "a").unwrap();
+        fs::create_dir(tmp.path().join("dst")).unwrap();
+        fs::write(tmp.path().join("dst/stale.txt"), "old").unwrap();
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+        let service = Arc::new(SyncJobService::new(
+            pool.clone(),
+            FilesystemService::new(tmp.path().to_path_buf()),
+        ));
+
+        let now = Utc::now();
+        let mut job = SyncJobRow {
+            id: 0,
+            source: "/src".to_string(),
+            destination: "/dst".to_string(),
+            interval_secs: 3600,
+            delete_extraneous: true,
+            checksum: false,
+            enabled: true,
+            created_at: format_time(now),
+            next_run_at: format_time(now),
+        };
+        job.id = db::create_sync_job(&pool, &job).await.unwrap();
+
+        service.run_due().await;
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("dst/sub/a.txt")).unwrap(),
+            "a"
+        );
+        assert!(!tmp.path().join("dst/stale.txt").exists());
+
+        let runs = db::list_sync_runs(&pool, job.id, 10).await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].status, RUN_SUCCESS);
+        assert_eq!((runs[0].created, runs[0].deleted), (1, 1));
+
+        // Not due again until the interval has passed
+        let job = db::get_sync_job(&pool, job.id).await.unwrap().unwrap();
+        assert!(job.next_run_at > format_time(now + ChronoDuration::seconds(3500)));
+        service.run_due().await;
+        assert_eq!(
+            db::list_sync_runs(&pool, job.id, 10).await.unwrap().len(),
+            1
+        );
+
+        // A failure is recorded with its message
+        fs::remove_dir_all(tmp.path().join("src")).unwrap();
+        let run = service.run_job(&job).await.unwrap();
+        assert_eq!(run.status, RUN_FAILED);
+        assert!(run.error.is_some());
+
+        // An interval out of chrono's range is scheduled at the cap
+        // instead of panicking the loop
+        let job = SyncJobRow {
+            interval_secs: i64::MAX,
+            ..job
+        };
+        service.run_job(&job).await.unwrap();
+        let job = db::get_sync_job(&pool, job.id).await.unwrap().unwrap();
+        assert!(job.next_run_at > format_time(now + ChronoDuration::days(364)));
+        assert!(job.next_run_at < format_time(now + ChronoDuration::days(366)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Templates for new files, such as a `README.md` or `notes.txt` skeleton.
+//! Each file directly inside `FM_TEMPLATES_DIR` is a template named after
+//! it; a new file created from one starts as a copy.
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::services::filesystem::is_hidden_name;
+
+/// Largest template that will be copied into a new file
+pub const MAX_TEMPLATE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("No template named '{0}'")]
+    NotFound(String),
+
+    #[error("Invalid template name '{0}'")]
+    InvalidName(String),
+
+    #[error("Template '{0}' is larger than 1 MiB")]
+    TooLarge(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Clone)]
+pub struct FileTemplates {
+    dir: Option<PathBuf>,
+}
+
+impl FileTemplates {
+    /// No templates; only empty files can be created
+    pub fn disabled() -> Self {
+        Self { dir: None }
+    }
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir: Some(dir) }
+    }
+
+    /// Templates from `FM_TEMPLATES_DIR`, if set
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            dir: config.templates_dir.clone(),
+        }
+    }
+
+    /// Names of the available templates, sorted
+    pub fn list(&self) -> Result<Vec<String>, TemplateError> {
+        let Some(dir) = &self.dir else {
+            return Ok(Vec::new());
+        };
+        let mut names: Vec<String> = std::fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| std::fs::metadata(entry.path()).is_ok_and(|m| m.is_file()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| !is_hidden_name(name))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Contents of the template called `name`
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, TemplateError> {
+        if name.is_empty() || name.contains(['/', '\\']) || is_hidden_name(name) {
+            return Err(TemplateError::InvalidName(name.to_string()));
+        }
+        let Some(dir) = &self.dir else {
+            return Err(TemplateError::NotFound(name.to_string()));
+        };
+        let path = dir.join(name);
+        match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => {
+                if metadata.len() > MAX_TEMPLATE_BYTES {
+                    return Err(TemplateError::TooLarge(name.to_string()));
+                }
+                Ok(std::fs::read(path)?)
+            }
+            Ok(_) => Err(TemplateError::NotFound(name.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(TemplateError::NotFound(name.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn templates_are_the_visible_files_in_the_directory() {
+        let tmp = tempdir().unwrap();
+        fs::write(tmp.path().join("README.md"), b"# Title\n").unwrap();
+        fs::write(tmp.path().join("notes.txt"), b"").unwrap();
+        fs::write(tmp.path().join(".DS_Store"), b"").unwrap();
+        fs::create_dir(tmp.path().join("drafts")).unwrap();
+        let templates = FileTemplates::new(tmp.path().to_path_buf());
+
+        assert_eq!(templates.list().unwrap(), ["README.md", "notes.txt"]);
+        assert_eq!(templates.read("README.md").unwrap(), b"# Title\n");
+        assert!(matches!(
+            templates.read("drafts"),
+            Err(TemplateError::NotFound(_))
+        ));
+        for name in ["../secret", ".DS_Store", ""] {
+            assert!(
+                matches!(templates.read(name), Err(TemplateError::InvalidName(_))),
+                "{name:?}"
+            );
+        }
+
+        let disabled = FileTemplates::disabled();
+        assert!(disabled.list().unwrap().is_empty());
+        assert!(matches!(
+            disabled.read("README.md"),
+            Err(TemplateError::NotFound(_))
+        ));
+    }
+}
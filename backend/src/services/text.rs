@@ -0,0 +1,197 @@
+//! Telling text from binary files and guessing the encoding of text, from a
+//! sample at the start of the file, so an editor can refuse binaries and
+//! decode legacy text correctly.
+
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from the start of a file to classify it
+pub const SAMPLE_BYTES: u64 = 8 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TextEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    /// Any single-byte encoding; ISO-8859-1 is the usual guess
+    #[serde(rename = "latin-1")]
+    Latin1,
+}
+
+impl TextEncoding {
+    /// Name for a `charset` parameter
+    pub fn charset(self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "utf-8",
+            TextEncoding::Utf16Le => "utf-16le",
+            TextEncoding::Utf16Be => "utf-16be",
+            TextEncoding::Latin1 => "iso-8859-1",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ContentInfo {
+    pub is_text: bool,
+    /// Likely encoding of a text file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<TextEncoding>,
+    /// Whether the text starts with a byte order mark
+    pub bom: bool,
+}
+
+impl ContentInfo {
+    const BINARY: Self = Self {
+        is_text: false,
+        encoding: None,
+        bom: false,
+    };
+
+    fn text(encoding: TextEncoding, bom: bool) -> Self {
+        Self {
+            is_text: true,
+            encoding: Some(encoding),
+            bom,
+        }
+    }
+}
+
+/// Classify the start of a file. A byte order mark decides the encoding;
+/// otherwise NUL bytes mean binary unless they fall in every other byte as
+/// in UTF-16 text, valid UTF-8 is UTF-8, and anything else without control
+/// characters is taken for a single-byte encoding. `truncated` says the
+/// file goes on past the sample, which may cut a UTF-8 sequence short.
+pub fn detect(sample: &[u8], truncated: bool) -> ContentInfo {
+    if sample.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return ContentInfo::text(TextEncoding::Utf8, true);
+    }
+    if sample.starts_with(&[0xFF, 0xFE]) {
+        return ContentInfo::text(TextEncoding::Utf16Le, true);
+    }
+    if sample.starts_with(&[0xFE, 0xFF]) {
+        return ContentInfo::text(TextEncoding::Utf16Be, true);
+    }
+
+    if sample.contains(&0) {
+        return utf16_without_bom(sample)
+            .map(|encoding| ContentInfo::text(encoding, false))
+            .unwrap_or(ContentInfo::BINARY);
+    }
+
+    if sample.iter().any(|&b| is_binary_control(b)) {
+        return ContentInfo::BINARY;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => ContentInfo::text(TextEncoding::Utf8, false),
+        Err(e) if truncated && e.error_len().is_none() => {
+            ContentInfo::text(TextEncoding::Utf8, false)
+        }
+        Err(_) => ContentInfo::text(TextEncoding::Latin1, false),
+    }
+}
+
+/// Classify a file from its first `SAMPLE_BYTES`
+pub fn detect_file(path: &Path) -> std::io::Result<ContentInfo> {
+    let mut sample = Vec::with_capacity(SAMPLE_BYTES as usize);
+    std::fs::File::open(path)?
+        .take(SAMPLE_BYTES)
+        .read_to_end(&mut sample)?;
+    Ok(detect(&sample, sample.len() as u64 == SAMPLE_BYTES))
+}
+
+/// Control characters that do not appear in text files; tab, line breaks,
+/// form feed and escape (for terminal colours) do
+fn is_binary_control(byte: u8) -> bool {
+    byte < 0x20 && !matches!(byte, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)
+}
+
+/// Mostly-ASCII UTF-16 has a NUL in every other byte and nowhere else,
+/// next to printable characters
+fn utf16_without_bom(sample: &[u8]) -> Option<TextEncoding> {
+    let pairs = sample.chunks_exact(2);
+    let count = pairs.len();
+    let (mut le, mut be) = (0, 0);
+    for pair in pairs {
+        match (pair[0], pair[1]) {
+            (0, 0) => return None,
+            (byte, 0) if !is_binary_control(byte) => le += 1,
+            (0, byte) if !is_binary_control(byte) => be += 1,
+            (_, 0) | (0, _) => return None,
+            _ => {}
+        }
+    }
+    if count == 0 || (le > 0 && be > 0) {
+        None
+    } else if le * 2 >= count {
+        Some(TextEncoding::Utf16Le)
+    } else if be * 2 >= count {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_encodings_are_recognised() {
+        let utf16 = |text: &str, le: bool| -> Vec<u8> {
+            text.encode_utf16()
+                .flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() })
+                .collect()
+        };
+        let cases: [(Vec<u8>, Option<TextEncoding>, bool); 8] = [
+            (b"plain\r\n\ttext".to_vec(), Some(TextEncoding::Utf8), false),
+            (
+                "café ✓".as_bytes().to_vec(),
+                Some(TextEncoding::Utf8),
+                false,
+            ),
+            // Ends like a UTF-8 sequence, but the file ends there too
+            (b"caf\xE9".to_vec(), Some(TextEncoding::Latin1), false),
+            (b"\xEF\xBB\xBFbom".to_vec(), Some(TextEncoding::Utf8), true),
+            (
+                b"caf\xE9 cr\xE8me".to_vec(),
+                Some(TextEncoding::Latin1),
+                false,
+            ),
+            (
+                [&[0xFF, 0xFE][..], &utf16("hi", true)].concat(),
+                Some(TextEncoding::Utf16Le),
+                true,
+            ),
+            (utf16("Notes", true), Some(TextEncoding::Utf16Le), false),
+            (utf16("Notes", false), Some(TextEncoding::Utf16Be), false),
+        ];
+        for (sample, encoding, bom) in cases {
+            let info = detect(&sample, false);
+            assert!(info.is_text, "{sample:?}");
+            assert_eq!((info.encoding, info.bom), (encoding, bom), "{sample:?}");
+        }
+        // Cut in the middle of "✓" by the end of the sample
+        let info = detect(b"check \xE2\x9C", true);
+        assert_eq!(info.encoding, Some(TextEncoding::Utf8));
+    }
+
+    #[test]
+    fn binaries_are_not_text() {
+        let png = [
+            0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D,
+        ];
+        for sample in [
+            &png[..],
+            b"\x7fELF\x02\x01\x01\x00\x00",
+            b"ab\x01\x02cd",
+            b"\x00\x01\x02\x03\xFF",
+        ] {
+            assert_eq!(detect(sample, false), ContentInfo::BINARY, "{sample:?}");
+        }
+        assert!(detect(b"", false).is_text);
+    }
+}
@@ -0,0 +1,176 @@
+//! Download bandwidth limits: a token bucket per download, plus one shared by
+//! all downloads, so bulk transfers leave room on the uplink for everything
+//! else.
+
+use axum::body::{Body, Bytes};
+use futures_util::{Stream, StreamExt};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::Config;
+
+/// Short waits are carried as debt instead of slept, since timer granularity
+/// would otherwise throttle fast limits far below their rate
+const MIN_SLEEP: Duration = Duration::from_millis(5);
+
+/// Bytes a bucket may bank while idle, as seconds of its rate
+const BURST_SECS: f64 = 0.25;
+
+/// Limits throughput to a fixed rate; consumers may overdraw and then wait
+/// until the debt is repaid.
+pub struct TokenBucket {
+    bytes_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec as f64,
+            state: Mutex::new(BucketState {
+                tokens: 0.0,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `bytes` and return how long the caller should wait before
+    /// sending them
+    fn reserve(&self, bytes: usize) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+        state.tokens =
+            (state.tokens + elapsed * self.bytes_per_sec).min(self.bytes_per_sec * BURST_SECS);
+        state.refilled_at = now;
+        state.tokens -= bytes as f64;
+
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.bytes_per_sec)
+        }
+    }
+
+    async fn take(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if wait >= MIN_SLEEP {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Bandwidth limits applied to download bodies
+#[derive(Clone, Default)]
+pub struct DownloadThrottle {
+    /// Bytes per second for each download; 0 is unlimited
+    per_download: u64,
+    /// Shared by every download
+    global: Option<Arc<TokenBucket>>,
+}
+
+impl DownloadThrottle {
+    /// No limits
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// `per_download` and `total` in bytes per second; 0 is unlimited
+    pub fn new(per_download: u64, total: u64) -> Self {
+        Self {
+            per_download,
+            global: (total > 0).then(|| Arc::new(TokenBucket::new(total))),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            config.download_rate_limit_kib * 1024,
+            config.download_rate_limit_total_kib * 1024,
+        )
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.per_download > 0 || self.global.is_some()
+    }
+
+    /// Pace `stream` to the configured limits
+    pub fn limit<S, E>(&self, stream: S) -> impl Stream<Item = Result<Bytes, E>> + use<S, E>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+    {
+        let own = (self.per_download > 0).then(|| Arc::new(TokenBucket::new(self.per_download)));
+        let global = self.global.clone();
+        stream.then(move |chunk| {
+            let own = own.clone();
+            let global = global.clone();
+            async move {
+                if let Ok(bytes) = &chunk {
+                    if let Some(bucket) = &own {
+                        bucket.take(bytes.len()).await;
+                    }
+                    if let Some(bucket) = &global {
+                        bucket.take(bytes.len()).await;
+                    }
+                }
+                chunk
+            }
+        })
+    }
+
+    /// `body` paced to the configured limits; unchanged when there are none
+    pub fn limit_body(&self, body: Body) -> Body {
+        if self.is_limited() {
+            Body::from_stream(self.limit(body.into_data_stream()))
+        } else {
+            body
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    #[tokio::test]
+    async fn paces_chunks_to_the_rate() {
+        let throttle = DownloadThrottle::new(100_000, 0);
+        let chunks = (0..10).map(|_| Ok::<_, std::io::Error>(Bytes::from(vec![0u8; 5000])));
+
+        let started = Instant::now();
+        let total: usize = throttle
+            .limit(stream::iter(chunks))
+            .map(|chunk| chunk.unwrap().len())
+            .fold(0, |sum, len| async move { sum + len })
+            .await;
+
+        // 50 KB at 100 KB/s; the last chunk's debt is left unpaid
+        assert_eq!(total, 50_000);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(400), "{elapsed:?}");
+        assert!(elapsed <= Duration::from_millis(1500), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn global_limit_is_shared() {
+        let throttle = DownloadThrottle::new(0, 100_000);
+        let download = |throttle: DownloadThrottle| async move {
+            let chunks = (0..5).map(|_| Ok::<_, std::io::Error>(Bytes::from(vec![0u8; 5000])));
+            throttle.limit(stream::iter(chunks)).count().await
+        };
+
+        let started = Instant::now();
+        let (a, b) = tokio::join!(download(throttle.clone()), download(throttle));
+        assert_eq!((a, b), (5, 5));
+        // Two downloads of 25 KB share 100 KB/s
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert!(!DownloadThrottle::unlimited().is_limited());
+    }
+}
@@ -0,0 +1,147 @@
+//! Caps on concurrent downloads and uploads, per session and overall, so a
+//! burst of parallel transfers cannot swamp a small server.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransferKind {
+    Download,
+    Upload,
+}
+
+/// Most transfers of one kind allowed at once; 0 is unlimited
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferLimits {
+    pub per_session: usize,
+    pub total: usize,
+}
+
+#[derive(Default)]
+struct Counts {
+    total: HashMap<TransferKind, usize>,
+    by_client: HashMap<(String, TransferKind), usize>,
+}
+
+/// Transfers in progress, by kind and client. A std mutex is used since
+/// permits are released from `Drop`; every critical section is a map update.
+#[derive(Clone)]
+pub struct TransferManager {
+    downloads: TransferLimits,
+    uploads: TransferLimits,
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl TransferManager {
+    pub fn new(downloads: TransferLimits, uploads: TransferLimits) -> Self {
+        Self {
+            downloads,
+            uploads,
+            counts: Arc::new(Mutex::new(Counts::default())),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            TransferLimits {
+                per_session: config.max_downloads_per_session,
+                total: config.max_downloads,
+            },
+            TransferLimits {
+                per_session: config.max_uploads_per_session,
+                total: config.max_uploads,
+            },
+        )
+    }
+
+    /// No limits
+    pub fn unlimited() -> Self {
+        Self::new(TransferLimits::default(), TransferLimits::default())
+    }
+
+    /// Start a transfer for `client`, or `None` when that would exceed a
+    /// limit. The transfer counts until the permit is dropped.
+    pub fn try_start(&self, client: &str, kind: TransferKind) -> Option<TransferPermit> {
+        let limits = match kind {
+            TransferKind::Download => self.downloads,
+            TransferKind::Upload => self.uploads,
+        };
+        let key = (client.to_string(), kind);
+
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let total = counts.total.get(&kind).copied().unwrap_or(0);
+        let own = counts.by_client.get(&key).copied().unwrap_or(0);
+        let over = |count: usize, limit: usize| limit > 0 && count >= limit;
+        if over(total, limits.total) || over(own, limits.per_session) {
+            return None;
+        }
+
+        *counts.total.entry(kind).or_default() += 1;
+        *counts.by_client.entry(key.clone()).or_default() += 1;
+        Some(TransferPermit {
+            counts: self.counts.clone(),
+            key,
+        })
+    }
+
+    /// Transfers of `kind` in progress
+    pub fn active(&self, kind: TransferKind) -> usize {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.total.get(&kind).copied().unwrap_or(0)
+    }
+}
+
+/// One transfer in progress; dropping it frees the slot
+pub struct TransferPermit {
+    counts: Arc<Mutex<Counts>>,
+    key: (String, TransferKind),
+}
+
+impl Drop for TransferPermit {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(total) = counts.total.get_mut(&self.key.1) {
+            *total = total.saturating_sub(1);
+        }
+        if let Some(own) = counts.by_client.get_mut(&self.key) {
+            *own = own.saturating_sub(1);
+            if *own == 0 {
+                counts.by_client.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_per_session_and_overall() {
+        let manager = TransferManager::new(
+            TransferLimits {
+                per_session: 1,
+                total: 2,
+            },
+            TransferLimits::default(),
+        );
+
+        let a = manager.try_start("a", TransferKind::Download).unwrap();
+        assert!(manager.try_start("a", TransferKind::Download).is_none());
+        let b = manager.try_start("b", TransferKind::Download).unwrap();
+        assert!(manager.try_start("c", TransferKind::Download).is_none());
+        assert_eq!(manager.active(TransferKind::Download), 2);
+
+        // Uploads are counted separately and unlimited here
+        let _uploads: Vec<_> = (0..5)
+            .map(|_| manager.try_start("a", TransferKind::Upload).unwrap())
+            .collect();
+
+        drop(a);
+        assert!(manager.try_start("c", TransferKind::Download).is_some());
+        drop(b);
+        assert_eq!(manager.active(TransferKind::Download), 0);
+    }
+}
@@ -0,0 +1,212 @@
+//! In-memory progress of uploads in flight, keyed by the client-chosen
+//! `X-Upload-Id`. Lets clients behind buffering reverse proxies see how much
+//! of an upload the server has actually received.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Finished uploads stay queryable this long so clients see the outcome
+const FINISHED_RETENTION_SECS: i64 = 600;
+
+/// Longest upload ID accepted
+pub const MAX_UPLOAD_ID_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadState {
+    Receiving,
+    Complete,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadProgress {
+    pub state: UploadState,
+    /// File data written to disk so far, across all files of the upload
+    pub bytes_received: u64,
+    /// Size of the whole request as announced by the client, including
+    /// multipart framing, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<u64>,
+    pub files_completed: u64,
+    /// Name of the file being received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Upload IDs are chosen by clients, so keep them short and plain
+pub fn valid_upload_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_UPLOAD_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Progress keyed by upload ID. A std mutex is used so byte counts can be
+/// added from inside the upload loop; every critical section is a map update.
+#[derive(Clone)]
+pub struct UploadTracker {
+    uploads: Arc<Mutex<HashMap<String, UploadProgress>>>,
+}
+
+impl UploadTracker {
+    pub fn new() -> Self {
+        Self {
+            uploads: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Start tracking an upload. Without an ID the returned handle records
+    /// nothing. An upload that is dropped before `finish` counts as failed.
+    pub fn start(&self, id: Option<String>, content_length: Option<u64>) -> UploadHandle {
+        if let Some(id) = &id {
+            let now = Utc::now();
+            let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+            uploads.retain(|_, progress| {
+                progress.state == UploadState::Receiving
+                    || (now - progress.updated_at).num_seconds() < FINISHED_RETENTION_SECS
+            });
+            uploads.insert(
+                id.clone(),
+                UploadProgress {
+                    state: UploadState::Receiving,
+                    bytes_received: 0,
+                    content_length,
+                    files_completed: 0,
+                    current_file: None,
+                    error: None,
+                    started_at: now,
+                    updated_at: now,
+                },
+            );
+        }
+        UploadHandle {
+            tracker: self.clone(),
+            id,
+            finished: false,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<UploadProgress> {
+        let uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.get(id).cloned()
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut UploadProgress)) {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(progress) = uploads.get_mut(id) {
+            f(progress);
+            progress.updated_at = Utc::now();
+        }
+    }
+}
+
+impl Default for UploadTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records the progress of one upload request
+pub struct UploadHandle {
+    tracker: UploadTracker,
+    id: Option<String>,
+    finished: bool,
+}
+
+impl UploadHandle {
+    fn update(&self, f: impl FnOnce(&mut UploadProgress)) {
+        if let Some(id) = &self.id {
+            self.tracker.update(id, f);
+        }
+    }
+
+    pub fn file_started(&self, name: &str) {
+        self.update(|p| p.current_file = Some(name.to_string()));
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.update(|p| p.bytes_received += bytes);
+    }
+
+    pub fn file_finished(&self) {
+        self.update(|p| {
+            p.files_completed += 1;
+            p.current_file = None;
+        });
+    }
+
+    /// Mark the upload complete, or failed with `error`
+    pub fn finish(mut self, error: Option<String>) {
+        self.finished = true;
+        self.update(|p| {
+            p.state = if error.is_some() {
+                UploadState::Failed
+            } else {
+                UploadState::Complete
+            };
+            p.error = error;
+        });
+    }
+}
+
+impl Drop for UploadHandle {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.update(|p| {
+                p.state = UploadState::Failed;
+                p.error
+                    .get_or_insert_with(|| "Upload was interrupted".to_string());
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_bytes_and_outcome() {
+        let tracker = UploadTracker::new();
+        let upload = tracker.start(Some("abc".to_string()), Some(100));
+        upload.file_started("a.bin");
+        upload.add_bytes(40);
+        assert_eq!(
+            tracker.get("abc").unwrap().current_file.as_deref(),
+            Some("a.bin")
+        );
+        upload.add_bytes(20);
+        upload.file_finished();
+        upload.finish(None);
+
+        let progress = tracker.get("abc").unwrap();
+        assert_eq!(progress.state, UploadState::Complete);
+        assert_eq!(progress.bytes_received, 60);
+        assert_eq!(progress.files_completed, 1);
+        assert_eq!(progress.content_length, Some(100));
+
+        // Dropped without finishing: the request ended early
+        drop(tracker.start(Some("def".to_string()), None));
+        assert_eq!(tracker.get("def").unwrap().state, UploadState::Failed);
+
+        // Untracked uploads leave nothing behind
+        tracker.start(None, None).add_bytes(5);
+        assert!(tracker.get("").is_none());
+    }
+
+    #[test]
+    fn upload_ids_are_plain() {
+        assert!(valid_upload_id("f47ac10b-58cc_4372"));
+        assert!(!valid_upload_id(""));
+        assert!(!valid_upload_id("../x"));
+        assert!(!valid_upload_id(&"a".repeat(MAX_UPLOAD_ID_LEN + 1)));
+    }
+}
@@ -0,0 +1,72 @@
+//! In-memory bandwidth and operation counters per client, reset on restart.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientUsage {
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+    /// Request counts keyed by operation (e.g. `files/download`)
+    pub operations: BTreeMap<String, u64>,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// Counters keyed by client id. A std mutex is used so byte counts can be
+/// added from inside body streams; every critical section is a map update.
+#[derive(Clone)]
+pub struct UsageTracker {
+    clients: Arc<Mutex<HashMap<String, ClientUsage>>>,
+    since: DateTime<Utc>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self {
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            since: Utc::now(),
+        }
+    }
+
+    /// When counting started
+    pub fn since(&self) -> DateTime<Utc> {
+        self.since
+    }
+
+    pub fn record_operation(&self, client: &str, operation: &str) {
+        self.update(client, |usage| {
+            *usage.operations.entry(operation.to_string()).or_default() += 1;
+            usage.last_seen = Some(Utc::now());
+        });
+    }
+
+    pub fn add_uploaded(&self, client: &str, bytes: u64) {
+        self.update(client, |usage| usage.bytes_uploaded += bytes);
+    }
+
+    pub fn add_downloaded(&self, client: &str, bytes: u64) {
+        self.update(client, |usage| usage.bytes_downloaded += bytes);
+    }
+
+    /// Usage for every client seen since startup
+    pub fn snapshot(&self) -> Vec<(String, ClientUsage)> {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients
+            .iter()
+            .map(|(id, usage)| (id.clone(), usage.clone()))
+            .collect()
+    }
+
+    fn update(&self, client: &str, f: impl FnOnce(&mut ClientUsage)) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        f(clients.entry(client.to_string()).or_default());
+    }
+}
+
+impl Default for UsageTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -4,11 +4,17 @@ pub const VERSION: &str = env!("APP_VERSION");
 pub const GIT_COMMIT: &str = env!("APP_COMMIT_SHA");
 pub const BUILT_AT: &str = env!("APP_BUILT_AT");
 
+/// Oldest client release, in the same `YYYY.MM.DD` scheme as `VERSION`, that
+/// works with this server's API. Raise it with changes that break older
+/// desktop clients.
+pub const MIN_CLIENT_VERSION: &str = "2026.10.16";
+
 #[derive(Debug, Serialize)]
 pub struct VersionInfo {
     pub version: &'static str,
     pub git_commit: &'static str,
     pub built_at: &'static str,
+    pub min_client_version: &'static str,
 }
 
 pub fn current() -> VersionInfo {
@@ -16,5 +22,6 @@ pub fn current() -> VersionInfo {
         version: VERSION,
         git_commit: GIT_COMMIT,
         built_at: BUILT_AT,
+        min_client_version: MIN_CLIENT_VERSION,
     }
 }
@@ -15,6 +15,10 @@ type AuthState = "loading" | "authenticated" | "unauthenticated";
 
 function App() {
   const [authState, setAuthState] = useState<AuthState>("loading");
+  const [loginMethods, setLoginMethods] = useState({
+    passwordLogin: true,
+    sso: false,
+  });
   const [queryClient] = useState(
     () =>
       new QueryClient({
@@ -32,6 +36,10 @@ function App() {
     try {
       const status = await api.getAuthStatus();
       setAuthRequired(status.auth_required);
+      setLoginMethods({
+        passwordLogin: status.password_login ?? true,
+        sso: status.sso ?? false,
+      });
       if (!status.auth_required || status.authenticated) {
         setAuthState("authenticated");
       } else {
@@ -73,7 +81,13 @@ function App() {
   }
 
   if (authState === "unauthenticated") {
-    return <LoginPage onLoginSuccess={handleLoginSuccess} />;
+    return (
+      <LoginPage
+        onLoginSuccess={handleLoginSuccess}
+        passwordLogin={loginMethods.passwordLogin}
+        sso={loginMethods.sso}
+      />
+    );
   }
 
   return (
@@ -1,5 +1,5 @@
 import { afterEach, beforeEach, describe, expect, it, vi } from "vitest";
-import { api, ApiError } from "./client";
+import { api, ApiError, errorCode, errorHint } from "./client";
 
 const makeJsonResponse = (body: unknown, status = 200) =>
   new Response(JSON.stringify(body), {
@@ -53,6 +53,22 @@ describe("api client", () => {
     expect(options).toEqual({ signal: undefined });
   });
 
+  it("fetches directory stats for a path", async () => {
+    const fetchMock = vi.mocked(fetch);
+    const stats = {
+      path: "/photos",
+      total_files: 1,
+      total_size: 10,
+      categories: [{ category: "images", count: 1, size: 10 }],
+    };
+    fetchMock.mockResolvedValueOnce(makeJsonResponse(stats));
+
+    await expect(api.getDirectoryStats("/photos")).resolves.toEqual(stats);
+
+    const [url] = fetchMock.mock.calls[0];
+    expect(url).toBe("/api/browse/stats?path=%2Fphotos");
+  });
+
   it("throws ApiError with fallback message on error", async () => {
     const fetchMock = vi.mocked(fetch);
     fetchMock.mockResolvedValueOnce(new Response("not json", { status: 500 }));
@@ -73,6 +89,38 @@ describe("api client", () => {
     });
   });
 
+  it("carries the server hint on ApiError", async () => {
+    const fetchMock = vi.mocked(fetch);
+    fetchMock.mockResolvedValueOnce(
+      makeJsonResponse({ error: "Permission denied: /a", hint: "Set PUID" }, 403),
+    );
+
+    const error = await api.getTree("/").catch((e: unknown) => e);
+    expect(error).toBeInstanceOf(ApiError);
+    expect(errorHint(error)).toBe("Set PUID");
+    expect(errorHint(new Error("plain"))).toBeUndefined();
+  });
+
+  it("carries the server error code and path on ApiError", async () => {
+    const fetchMock = vi.mocked(fetch);
+    fetchMock.mockResolvedValueOnce(
+      makeJsonResponse(
+        {
+          code: "not_found",
+          message: "Path not found: /a",
+          error: "Path not found: /a",
+          path: "/a",
+        },
+        404,
+      ),
+    );
+
+    const error = await api.getTree("/a").catch((e: unknown) => e);
+    expect(errorCode(error)).toBe("not_found");
+    expect(error).toMatchObject({ message: "Path not found: /a", path: "/a" });
+    expect(errorCode(new Error("plain"))).toBeUndefined();
+  });
+
   it("sends createDirectory payload", async () => {
     const fetchMock = vi.mocked(fetch);
     fetchMock.mockResolvedValueOnce(makeJsonResponse({ success: true }));
@@ -86,6 +134,26 @@ describe("api client", () => {
       headers: { "Content-Type": "application/json" },
       body: JSON.stringify({ path: "/new-folder" }),
     });
+
+    fetchMock.mockResolvedValueOnce(makeJsonResponse({ success: true }));
+    await api.createDirectory("/a/b/c", { recursive: true });
+    expect(fetchMock.mock.calls[1][1]).toMatchObject({
+      body: JSON.stringify({ path: "/a/b/c", recursive: true }),
+    });
+  });
+
+  it("sends createFile payload", async () => {
+    const fetchMock = vi.mocked(fetch);
+    fetchMock.mockResolvedValueOnce(makeJsonResponse({ success: true }));
+
+    await api.createFile("/docs/README.md", { template: "README.md" });
+
+    const [url, options] = fetchMock.mock.calls[0];
+    expect(url).toBe("/api/files/create");
+    expect(options).toMatchObject({
+      method: "POST",
+      body: JSON.stringify({ path: "/docs/README.md", template: "README.md" }),
+    });
   });
 
   it("sends rename payload", async () => {
@@ -156,6 +224,42 @@ describe("api client", () => {
     expect(api.getDownloadUrl("/path/to/file.txt")).toBe(
       "/api/files/download?path=%2Fpath%2Fto%2Ffile.txt",
     );
+    expect(api.getPlaylistUrl("/Music/Album")).toBe(
+      "/api/files/playlist?path=%2FMusic%2FAlbum",
+    );
+  });
+
+  it("requests signed urls with an optional lifetime", async () => {
+    const fetchMock = vi.mocked(fetch);
+    const signed = {
+      url: "/api/files/download?path=%2Fa.mp4&expires=1&sig=ab",
+      thumbnail_url: "/api/files/thumbnail?path=%2Fa.mp4&expires=1&sig=ab",
+      expires_at: 1,
+    };
+    fetchMock.mockResolvedValueOnce(makeJsonResponse(signed));
+
+    await expect(api.signUrl("/a.mp4", 60)).resolves.toEqual(signed);
+    expect(fetchMock.mock.calls[0][0]).toBe(
+      "/api/files/sign?path=%2Fa.mp4&expires_in=60",
+    );
+  });
+
+  it("posts selected paths for a batch download", async () => {
+    const fetchMock = vi.mocked(fetch);
+    const blob = new Blob(["zip"]);
+    fetchMock.mockResolvedValueOnce({
+      ok: true,
+      blob: async () => blob,
+    } as Response);
+
+    await expect(api.downloadBatch(["/a.txt", "/b"])).resolves.toBe(blob);
+
+    const [url, options] = fetchMock.mock.calls[0];
+    expect(url).toBe("/api/files/download-batch");
+    expect(options).toMatchObject({
+      method: "POST",
+      body: JSON.stringify({ paths: ["/a.txt", "/b"] }),
+    });
   });
 
   it("reads text content with a byte limit", async () => {
@@ -423,6 +527,31 @@ describe("api client", () => {
     expect(statusOptions).toBeUndefined();
   });
 
+  it("echoes the CSRF token from login on writes until logout", async () => {
+    const fetchMock = vi.mocked(fetch);
+    fetchMock
+      .mockResolvedValueOnce(
+        makeJsonResponse({ success: true, csrf_token: "tok123" }),
+      )
+      .mockResolvedValueOnce(makeJsonResponse({ success: true }))
+      .mockResolvedValueOnce(makeJsonResponse({ success: true }))
+      .mockResolvedValueOnce(makeJsonResponse({ success: true }));
+
+    await api.login("secret");
+    await api.createDirectory("/new-folder");
+    await api.logout();
+    await api.createDirectory("/other");
+
+    expect(fetchMock.mock.calls[1][1]).toMatchObject({
+      headers: { "X-CSRF-Token": "tok123" },
+    });
+    const afterLogout = fetchMock.mock.calls[3][1]?.headers as Record<
+      string,
+      string
+    >;
+    expect(afterLogout["X-CSRF-Token"]).toBeUndefined();
+  });
+
   it("sends system requests", async () => {
     const fetchMock = vi.mocked(fetch);
     fetchMock
@@ -1,11 +1,38 @@
 import type {
+  DirectoryStats,
+  ErrorCode,
+  ExpectedState,
+  FileCategory,
+  FileMeta,
+  FileNote,
+  FileStat,
+  SignedUrl,
+  SimilarResponse,
+  HeldLock,
+  LibraryView,
+  MapResponse,
+  RecentAdditionsResponse,
+  RemoteSync,
   ListResponse,
+  LockStatus,
+  ConflictResolution,
+  CreateSyncJob,
+  MoveBatchResponse,
   SuccessResponse,
   TreeNode,
   ErrorResponse,
   SearchResponse,
+  SelectionSize,
   SortField,
   SortOrder,
+  SyncJob,
+  SyncRun,
+  TimelineGroup,
+  TimelineResponse,
+  UploadConflict,
+  UploadProgress,
+  UploadScan,
+  ViewResponse,
 } from "@/types/file";
 import { getApiBase } from "@/lib/config";
 
@@ -13,10 +40,55 @@ class ApiError extends Error {
   constructor(
     public status: number,
     message: string,
+    public hint?: string,
+    public code?: ErrorCode,
+    public path?: string,
   ) {
     super(message);
     this.name = "ApiError";
   }
+
+  static fromResponse(status: number, body: ErrorResponse): ApiError {
+    return new ApiError(
+      status,
+      body.message ?? body.error,
+      body.hint,
+      body.code,
+      body.path,
+    );
+  }
+}
+
+// Echoed in X-CSRF-Token on state-changing requests; issued by login and
+// the auth status check while authentication is enabled.
+let csrfToken: string | null = null;
+
+function csrfHeaders(): Record<string, string> {
+  return csrfToken ? { "X-CSRF-Token": csrfToken } : {};
+}
+
+interface LoginResult {
+  success: boolean;
+  error?: string;
+  csrf_token?: string;
+}
+
+interface AuthStatus {
+  authenticated: boolean;
+  auth_required: boolean;
+  password_login?: boolean;
+  sso?: boolean;
+  csrf_token?: string;
+}
+
+/** Remediation hint from the server, if the error carried one. */
+function errorHint(error: unknown): string | undefined {
+  return error instanceof ApiError ? error.hint : undefined;
+}
+
+/** The server's error code, if `error` came from the API. */
+function errorCode(error: unknown): ErrorCode | undefined {
+  return error instanceof ApiError ? error.code : undefined;
 }
 
 async function handleResponse<T>(response: Response): Promise<T> {
@@ -24,7 +96,7 @@ async function handleResponse<T>(response: Response): Promise<T> {
     const error: ErrorResponse = await response.json().catch(() => ({
       error: "Unknown error",
     }));
-    throw new ApiError(response.status, error.error);
+    throw ApiError.fromResponse(response.status, error);
   }
   return response.json();
 }
@@ -39,6 +111,13 @@ export const api = {
       limit?: number;
       sort_by?: SortField;
       sort_order?: SortOrder;
+      show_hidden?: boolean;
+      include_ignored?: boolean;
+      /** Seconds */
+      min_duration?: number;
+      /** `720p`, `1080p`, `4k`, `8k`, or `WIDTHxHEIGHT` */
+      min_resolution?: string;
+      mime_class?: FileCategory;
     } = {},
   ): Promise<ListResponse> {
     const params = new URLSearchParams({ path });
@@ -47,18 +126,57 @@ export const api = {
     if (options.limit !== undefined) params.set("limit", String(options.limit));
     if (options.sort_by) params.set("sort_by", options.sort_by);
     if (options.sort_order) params.set("sort_order", options.sort_order);
+    if (options.show_hidden !== undefined)
+      params.set("show_hidden", String(options.show_hidden));
+    if (options.include_ignored)
+      params.set("include_ignored", String(options.include_ignored));
+    if (options.min_duration !== undefined)
+      params.set("min_duration", String(options.min_duration));
+    if (options.min_resolution)
+      params.set("min_resolution", options.min_resolution);
+    if (options.mime_class) params.set("mime_class", options.mime_class);
     const response = await fetch(`${getApiBase()}/browse?${params}`, {
       signal: options.signal,
     });
     return handleResponse(response);
   },
 
-  async getTree(path: string = "/"): Promise<TreeNode[]> {
+  async getTree(
+    path: string = "/",
+    options: {
+      show_hidden?: boolean;
+      include_ignored?: boolean;
+      depth?: number;
+    } = {},
+  ): Promise<TreeNode[]> {
     const params = new URLSearchParams({ path });
+    if (options.depth !== undefined)
+      params.set("depth", String(options.depth));
+    if (options.show_hidden !== undefined)
+      params.set("show_hidden", String(options.show_hidden));
+    if (options.include_ignored)
+      params.set("include_ignored", String(options.include_ignored));
     const response = await fetch(`${getApiBase()}/tree?${params}`);
     return handleResponse(response);
   },
 
+  async getDirectoryStats(path: string = "/"): Promise<DirectoryStats> {
+    const params = new URLSearchParams({ path });
+    const response = await fetch(`${getApiBase()}/browse/stats?${params}`);
+    return handleResponse(response);
+  },
+
+  async getRecentAdditions(
+    options: { path_prefix?: string; since?: string; limit?: number } = {},
+  ): Promise<RecentAdditionsResponse> {
+    const params = new URLSearchParams();
+    if (options.path_prefix) params.set("path_prefix", options.path_prefix);
+    if (options.since) params.set("since", options.since);
+    if (options.limit) params.set("limit", String(options.limit));
+    const response = await fetch(`${getApiBase()}/recent-additions?${params}`);
+    return handleResponse(response);
+  },
+
   // Search
   async search(
     query: string,
@@ -68,6 +186,10 @@ export const api = {
       limit?: number;
       sort_by?: SortField;
       sort_order?: SortOrder;
+      show_hidden?: boolean;
+      meta?: string;
+      sort_meta?: string;
+      syntax?: "simple" | "advanced";
     } = {},
   ): Promise<SearchResponse> {
     const params = new URLSearchParams({ q: query });
@@ -76,27 +198,167 @@ export const api = {
     if (options.limit !== undefined) params.set("limit", String(options.limit));
     if (options.sort_by) params.set("sort_by", options.sort_by);
     if (options.sort_order) params.set("sort_order", options.sort_order);
+    if (options.show_hidden !== undefined)
+      params.set("show_hidden", String(options.show_hidden));
+    if (options.meta) params.set("meta", options.meta);
+    if (options.sort_meta) params.set("sort_meta", options.sort_meta);
+    if (options.syntax) params.set("syntax", options.syntax);
     const response = await fetch(`${getApiBase()}/search?${params}`, {
       signal: options.signal,
     });
     return handleResponse(response);
   },
 
+  getSearchStreamUrl(query: string, limit?: number): string {
+    const params = new URLSearchParams({ q: query });
+    if (limit !== undefined) params.set("limit", String(limit));
+    return `${getApiBase()}/search/stream?${params}`;
+  },
+
+  async getView(
+    view: LibraryView,
+    options: {
+      path?: string;
+      offset?: number;
+      limit?: number;
+      sort_by?: SortField;
+      sort_order?: SortOrder;
+      show_hidden?: boolean;
+    } = {},
+  ): Promise<ViewResponse> {
+    const params = new URLSearchParams();
+    if (options.path) params.set("path", options.path);
+    if (options.offset !== undefined)
+      params.set("offset", String(options.offset));
+    if (options.limit !== undefined) params.set("limit", String(options.limit));
+    if (options.sort_by) params.set("sort_by", options.sort_by);
+    if (options.sort_order) params.set("sort_order", options.sort_order);
+    if (options.show_hidden !== undefined)
+      params.set("show_hidden", String(options.show_hidden));
+    const response = await fetch(`${getApiBase()}/views/${view}?${params}`);
+    return handleResponse(response);
+  },
+
+  async getTimeline(
+    options: {
+      path?: string;
+      group?: TimelineGroup;
+      bucket?: string;
+      offset?: number;
+      limit?: number;
+      show_hidden?: boolean;
+    } = {},
+  ): Promise<TimelineResponse> {
+    const params = new URLSearchParams();
+    if (options.path) params.set("path", options.path);
+    if (options.group) params.set("group", options.group);
+    if (options.bucket) params.set("bucket", options.bucket);
+    if (options.offset !== undefined)
+      params.set("offset", String(options.offset));
+    if (options.limit !== undefined) params.set("limit", String(options.limit));
+    if (options.show_hidden !== undefined)
+      params.set("show_hidden", String(options.show_hidden));
+    const response = await fetch(`${getApiBase()}/views/timeline?${params}`);
+    return handleResponse(response);
+  },
+
+  async getMap(
+    options: {
+      bbox?: [number, number, number, number];
+      path?: string;
+      cluster?: boolean;
+      grid?: number;
+      limit?: number;
+      show_hidden?: boolean;
+    } = {},
+  ): Promise<MapResponse> {
+    const params = new URLSearchParams();
+    if (options.bbox) params.set("bbox", options.bbox.join(","));
+    if (options.path) params.set("path", options.path);
+    if (options.cluster !== undefined)
+      params.set("cluster", String(options.cluster));
+    if (options.grid !== undefined) params.set("grid", String(options.grid));
+    if (options.limit !== undefined) params.set("limit", String(options.limit));
+    if (options.show_hidden !== undefined)
+      params.set("show_hidden", String(options.show_hidden));
+    const response = await fetch(`${getApiBase()}/views/map?${params}`);
+    return handleResponse(response);
+  },
+
   // File Operations
-  async createDirectory(path: string): Promise<SuccessResponse> {
+  async createDirectory(
+    path: string,
+    options: { recursive?: boolean } = {},
+  ): Promise<SuccessResponse> {
     const response = await fetch(`${getApiBase()}/files/mkdir`, {
       method: "POST",
-      headers: { "Content-Type": "application/json" },
-      body: JSON.stringify({ path }),
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ path, ...options }),
+    });
+    return handleResponse(response);
+  },
+
+  async createFile(
+    path: string,
+    options: { template?: string } = {},
+  ): Promise<SuccessResponse> {
+    const response = await fetch(`${getApiBase()}/files/create`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ path, ...options }),
+    });
+    return handleResponse(response);
+  },
+
+  async listTemplates(): Promise<{ templates: string[] }> {
+    const response = await fetch(`${getApiBase()}/files/templates`);
+    return handleResponse(response);
+  },
+
+  async createLink(
+    target: string,
+    path: string,
+    kind: "hard" | "symbolic",
+  ): Promise<SuccessResponse> {
+    const response = await fetch(`${getApiBase()}/files/link`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ target, path, kind }),
     });
     return handleResponse(response);
   },
 
-  async rename(path: string, newName: string): Promise<SuccessResponse> {
+  async rename(
+    path: string,
+    newName: string,
+    expected: ExpectedState = {},
+  ): Promise<SuccessResponse> {
     const response = await fetch(`${getApiBase()}/files/rename`, {
       method: "POST",
-      headers: { "Content-Type": "application/json" },
-      body: JSON.stringify({ path, new_name: newName }),
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ path, new_name: newName, ...expected }),
+    });
+    return handleResponse(response);
+  },
+
+  async moveBatch(
+    paths: string[],
+    to: string,
+    resolutions: Record<string, ConflictResolution> = {},
+  ): Promise<MoveBatchResponse> {
+    const response = await fetch(`${getApiBase()}/files/move-batch`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ paths, to, resolutions }),
+    });
+    return handleResponse(response);
+  },
+
+  async selectionSize(paths: string[]): Promise<SelectionSize> {
+    const response = await fetch(`${getApiBase()}/files/size`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ paths }),
     });
     return handleResponse(response);
   },
@@ -105,11 +367,12 @@ export const api = {
     from: string,
     to: string,
     overwrite = false,
+    expected: ExpectedState = {},
   ): Promise<SuccessResponse> {
     const response = await fetch(`${getApiBase()}/files/move`, {
       method: "POST",
-      headers: { "Content-Type": "application/json" },
-      body: JSON.stringify({ from, to, overwrite }),
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ from, to, overwrite, ...expected }),
     });
     return handleResponse(response);
   },
@@ -121,26 +384,172 @@ export const api = {
   ): Promise<SuccessResponse> {
     const response = await fetch(`${getApiBase()}/files/copy`, {
       method: "POST",
-      headers: { "Content-Type": "application/json" },
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
       body: JSON.stringify({ from, to, overwrite }),
     });
     return handleResponse(response);
   },
 
-  async delete(path: string): Promise<SuccessResponse> {
+  /** Copy only files missing or different at the destination */
+  async sync(
+    from: string,
+    to: string,
+    checksum = false,
+  ): Promise<SuccessResponse> {
+    const response = await fetch(`${getApiBase()}/files/copy`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ from, to, mode: "sync", checksum }),
+    });
+    return handleResponse(response);
+  },
+
+  async delete(
+    path: string,
+    expected: ExpectedState = {},
+  ): Promise<SuccessResponse> {
     const response = await fetch(`${getApiBase()}/files/delete`, {
       method: "DELETE",
-      headers: { "Content-Type": "application/json" },
-      body: JSON.stringify({ path }),
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ path, ...expected }),
     });
     return handleResponse(response);
   },
 
+  // Notes
+  async getNotes(path: string): Promise<{ path: string; notes: FileNote[] }> {
+    const params = new URLSearchParams({ path });
+    const response = await fetch(`${getApiBase()}/files/notes?${params}`);
+    return handleResponse(response);
+  },
+
+  async createNote(path: string, body: string): Promise<FileNote> {
+    const response = await fetch(`${getApiBase()}/files/notes`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ path, body }),
+    });
+    return handleResponse(response);
+  },
+
+  async updateNote(id: number, body: string): Promise<FileNote> {
+    const response = await fetch(`${getApiBase()}/files/notes/${id}`, {
+      method: "PUT",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ body }),
+    });
+    return handleResponse(response);
+  },
+
+  async deleteNote(id: number): Promise<SuccessResponse> {
+    const response = await fetch(`${getApiBase()}/files/notes/${id}`, {
+      method: "DELETE",
+      headers: csrfHeaders(),
+    });
+    return handleResponse(response);
+  },
+
+  // Ratings and custom fields
+  async stat(path: string): Promise<FileStat> {
+    const params = new URLSearchParams({ path });
+    const response = await fetch(`${getApiBase()}/files/stat?${params}`);
+    return handleResponse(response);
+  },
+
+  async similar(path: string): Promise<SimilarResponse> {
+    const params = new URLSearchParams({ path });
+    const response = await fetch(`${getApiBase()}/files/similar?${params}`);
+    return handleResponse(response);
+  },
+
+  async getMeta(path: string): Promise<FileMeta> {
+    const params = new URLSearchParams({ path });
+    const response = await fetch(`${getApiBase()}/files/meta?${params}`);
+    return handleResponse(response);
+  },
+
+  async updateMeta(
+    path: string,
+    set: Record<string, string | null>,
+  ): Promise<FileMeta> {
+    const response = await fetch(`${getApiBase()}/files/meta`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ path, set }),
+    });
+    return handleResponse(response);
+  },
+
+  async getLock(path: string): Promise<LockStatus> {
+    const params = new URLSearchParams({ path });
+    const response = await fetch(`${getApiBase()}/files/lock?${params}`);
+    return handleResponse(response);
+  },
+
+  async lockFile(
+    path: string,
+    options: { owner?: string; ttlSecs?: number; token?: string } = {},
+  ): Promise<HeldLock> {
+    const response = await fetch(`${getApiBase()}/files/lock`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({
+        path,
+        owner: options.owner,
+        ttl_secs: options.ttlSecs,
+        token: options.token,
+      }),
+    });
+    return handleResponse(response);
+  },
+
+  async unlockFile(path: string, token: string): Promise<SuccessResponse> {
+    const response = await fetch(`${getApiBase()}/files/unlock`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ path, token }),
+    });
+    return handleResponse(response);
+  },
+
+  getDirectoryEventsUrl(paths: string[]): string {
+    const params = new URLSearchParams(paths.map((path) => ["path", path]));
+    return `${getApiBase()}/events?${params}`;
+  },
+
   getDownloadUrl(path: string): string {
     const params = new URLSearchParams({ path });
     return `${getApiBase()}/files/download?${params}`;
   },
 
+  /** Download and thumbnail URLs for media elements that cannot send cookies */
+  async signUrl(path: string, expiresIn?: number): Promise<SignedUrl> {
+    const params = new URLSearchParams({ path });
+    if (expiresIn !== undefined) {
+      params.set("expires_in", String(expiresIn));
+    }
+    const response = await fetch(`${getApiBase()}/files/sign?${params}`);
+    return handleResponse(response);
+  },
+
+  /** M3U8 playlist of a folder's audio and video, for external players */
+  getPlaylistUrl(path: string): string {
+    const params = new URLSearchParams({ path });
+    return `${getApiBase()}/files/playlist?${params}`;
+  },
+
+  async downloadBatch(paths: string[]): Promise<Blob> {
+    const response = await fetch(`${getApiBase()}/files/download-batch`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ paths }),
+    });
+    if (!response.ok) {
+      throw new ApiError(response.status, "Failed to download selection");
+    }
+    return response.blob();
+  },
+
   async getTextContent(
     path: string,
     maxBytes: number = 100000,
@@ -163,6 +572,7 @@ export const api = {
 
     const response = await fetch(`${getApiBase()}/files/upload${targetPath}`, {
       method: "POST",
+      headers: csrfHeaders(),
       body: formData,
     });
     return handleResponse(response);
@@ -172,12 +582,17 @@ export const api = {
     targetPath: string,
     file: File,
     onProgress: (progress: number) => void,
+    relativePath?: string,
+    conflict?: UploadConflict,
+    uploadId?: string,
+    lockToken?: string,
   ): Promise<SuccessResponse> {
     return new Promise((resolve, reject) => {
       // XHR is required here to reliably report upload progress for multipart/form-data.
       const xhr = new XMLHttpRequest();
       const formData = new FormData();
-      formData.append("files", file);
+      // Subfolders in the filename are recreated under targetPath
+      formData.append("files", file, relativePath || file.name);
 
       xhr.upload.addEventListener("progress", (event) => {
         if (event.lengthComputable) {
@@ -200,8 +615,13 @@ export const api = {
           }
         } else {
           try {
-            const error = JSON.parse(xhr.responseText);
-            reject(new ApiError(xhr.status, error.error || "Upload failed"));
+            const error: ErrorResponse = JSON.parse(xhr.responseText);
+            reject(
+              ApiError.fromResponse(xhr.status, {
+                ...error,
+                message: error.message || error.error || "Upload failed",
+              }),
+            );
           } catch {
             reject(new ApiError(xhr.status, "Upload failed"));
           }
@@ -216,34 +636,50 @@ export const api = {
         reject(new ApiError(0, "Upload cancelled"));
       });
 
-      xhr.open("POST", `${getApiBase()}/files/upload${targetPath}`);
+      const query = conflict ? `?conflict=${conflict}` : "";
+      xhr.open("POST", `${getApiBase()}/files/upload${targetPath}${query}`);
+      for (const [name, value] of Object.entries(csrfHeaders())) {
+        xhr.setRequestHeader(name, value);
+      }
+      // Lets getUploadProgress report what the server has received
+      if (uploadId) xhr.setRequestHeader("X-Upload-Id", uploadId);
+      if (lockToken) xhr.setRequestHeader("X-Lock-Token", lockToken);
       xhr.send(formData);
     });
   },
 
+  async getUploadProgress(uploadId: string): Promise<UploadProgress> {
+    const response = await fetch(
+      `${getApiBase()}/uploads/${encodeURIComponent(uploadId)}/progress`,
+    );
+    return handleResponse(response);
+  },
+
   // Authentication
-  async login(password: string): Promise<{ success: boolean; error?: string }> {
+  async login(password: string): Promise<LoginResult> {
     const response = await fetch(`${getApiBase()}/auth/login`, {
       method: "POST",
       headers: { "Content-Type": "application/json" },
       body: JSON.stringify({ password }),
     });
-    return handleResponse(response);
+    const result = await handleResponse<LoginResult>(response);
+    csrfToken = result.csrf_token ?? null;
+    return result;
   },
 
   async logout(): Promise<{ success: boolean }> {
     const response = await fetch(`${getApiBase()}/auth/logout`, {
       method: "POST",
     });
+    csrfToken = null;
     return handleResponse(response);
   },
 
-  async getAuthStatus(): Promise<{
-    authenticated: boolean;
-    auth_required: boolean;
-  }> {
+  async getAuthStatus(): Promise<AuthStatus> {
     const response = await fetch(`${getApiBase()}/auth/status`);
-    return handleResponse(response);
+    const status = await handleResponse<AuthStatus>(response);
+    csrfToken = status.csrf_token ?? null;
+    return status;
   },
 
   // System
@@ -256,17 +692,105 @@ export const api = {
     return handleResponse(response);
   },
 
-  async getIndexStatus(): Promise<{ is_running: boolean }> {
+  async getIndexStatus(): Promise<{ is_running: boolean; root_mismatch?: string }> {
     const response = await fetch(`${getApiBase()}/index/status`);
     return handleResponse(response);
   },
 
-  async triggerIndex(): Promise<{ is_running: boolean }> {
+  async triggerIndex(): Promise<{ is_running: boolean; root_mismatch?: string }> {
     const response = await fetch(`${getApiBase()}/index/trigger`, {
       method: "POST",
+      headers: csrfHeaders(),
+    });
+    return handleResponse(response);
+  },
+
+  // Scheduled sync jobs
+  async listSyncJobs(): Promise<{ jobs: SyncJob[] }> {
+    const response = await fetch(`${getApiBase()}/sync-jobs`);
+    return handleResponse(response);
+  },
+
+  async createSyncJob(job: CreateSyncJob): Promise<SyncJob> {
+    const response = await fetch(`${getApiBase()}/sync-jobs`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify(job),
+    });
+    return handleResponse(response);
+  },
+
+  async deleteSyncJob(id: number): Promise<SuccessResponse> {
+    const response = await fetch(`${getApiBase()}/sync-jobs/${id}`, {
+      method: "DELETE",
+      headers: csrfHeaders(),
+    });
+    return handleResponse(response);
+  },
+
+  async runSyncJob(id: number): Promise<SyncJob> {
+    const response = await fetch(`${getApiBase()}/sync-jobs/${id}/run`, {
+      method: "POST",
+      headers: csrfHeaders(),
+    });
+    return handleResponse(response);
+  },
+
+  async syncJobRuns(id: number, limit = 20): Promise<{ runs: SyncRun[] }> {
+    const params = new URLSearchParams({ limit: String(limit) });
+    const response = await fetch(
+      `${getApiBase()}/sync-jobs/${id}/runs?${params}`,
+    );
+    return handleResponse(response);
+  },
+
+  // rclone remotes
+  async listRemotes(): Promise<{ enabled: boolean; remotes: string[] }> {
+    const response = await fetch(`${getApiBase()}/remotes`);
+    return handleResponse(response);
+  },
+
+  async syncToRemote(
+    remote: string,
+    path: string,
+    remotePath = "",
+    deleteExtraneous = false,
+  ): Promise<RemoteSync> {
+    const response = await fetch(
+      `${getApiBase()}/remotes/${encodeURIComponent(remote)}/sync`,
+      {
+        method: "POST",
+        headers: { "Content-Type": "application/json", ...csrfHeaders() },
+        body: JSON.stringify({
+          path,
+          remote_path: remotePath,
+          delete_extraneous: deleteExtraneous,
+        }),
+      },
+    );
+    return handleResponse(response);
+  },
+
+  async remoteSyncs(): Promise<{ syncs: RemoteSync[] }> {
+    const response = await fetch(`${getApiBase()}/remotes/syncs`);
+    return handleResponse(response);
+  },
+
+  async uploadScans(
+    limit = 50,
+  ): Promise<{ enabled: boolean; scans: UploadScan[] }> {
+    const params = new URLSearchParams({ limit: String(limit) });
+    const response = await fetch(`${getApiBase()}/uploads/scans?${params}`);
+    return handleResponse(response);
+  },
+
+  async rebuildSearch(): Promise<{ entries: number; duration_ms: number }> {
+    const response = await fetch(`${getApiBase()}/index/rebuild-search`, {
+      method: "POST",
+      headers: csrfHeaders(),
     });
     return handleResponse(response);
   },
 };
 
-export { ApiError };
+export { ApiError, errorCode, errorHint };
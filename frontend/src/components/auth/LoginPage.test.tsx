@@ -102,4 +102,32 @@ describe("LoginPage", () => {
 
     await waitFor(() => expect(onLoginSuccess).toHaveBeenCalled());
   });
+
+  it("offers single sign-on without a password form", async () => {
+    const user = userEvent.setup();
+    const assign = vi.fn();
+    vi.stubGlobal("location", { ...window.location, assign, search: "" });
+
+    render(
+      <LoginPage onLoginSuccess={vi.fn()} passwordLogin={false} sso={true} />,
+    );
+
+    expect(screen.queryByPlaceholderText("Password")).not.toBeInTheDocument();
+    await user.click(screen.getByRole("button", { name: /sign in with sso/i }));
+    expect(assign).toHaveBeenCalledWith("/api/auth/oidc/login");
+
+    vi.unstubAllGlobals();
+  });
+
+  it("shows the error from a failed single sign-on", () => {
+    vi.stubGlobal("location", {
+      ...window.location,
+      search: "?login_error=alex%20is%20not%20allowed",
+    });
+
+    render(<LoginPage onLoginSuccess={vi.fn()} sso={true} />);
+
+    expect(screen.getByText("alex is not allowed")).toBeInTheDocument();
+    vi.unstubAllGlobals();
+  });
 });
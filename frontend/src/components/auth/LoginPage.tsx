@@ -2,15 +2,30 @@ import { useState } from "react";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { api } from "@/api/client";
-import { Folder, Lock, Loader2 } from "lucide-react";
+import { Folder, KeyRound, Lock, Loader2 } from "lucide-react";
 
 interface LoginPageProps {
   onLoginSuccess: () => void;
+  /** Show the password form (false when only single sign-on is configured) */
+  passwordLogin?: boolean;
+  /** Offer single sign-on through the configured identity provider */
+  sso?: boolean;
 }
 
-export function LoginPage({ onLoginSuccess }: LoginPageProps) {
+const SSO_LOGIN_URL = "/api/auth/oidc/login";
+
+// A failed single sign-on redirects back here with the reason
+function initialLoginError(): string | null {
+  return new URLSearchParams(window.location.search).get("login_error");
+}
+
+export function LoginPage({
+  onLoginSuccess,
+  passwordLogin = true,
+  sso = false,
+}: LoginPageProps) {
   const [password, setPassword] = useState("");
-  const [error, setError] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(initialLoginError);
   const [isLoading, setIsLoading] = useState(false);
 
   const handleSubmit = async (e: React.FormEvent) => {
@@ -41,45 +56,64 @@ export function LoginPage({ onLoginSuccess }: LoginPageProps) {
           </div>
           <h1 className="text-2xl font-semibold text-foreground">Filex</h1>
           <p className="text-sm text-muted-foreground mt-1">
-            Enter password to continue
+            {passwordLogin
+              ? "Enter password to continue"
+              : "Sign in to continue"}
           </p>
         </div>
 
-        <form onSubmit={handleSubmit} className="space-y-4">
-          <div className="relative">
-            <Lock className="absolute left-3 top-1/2 -translate-y-1/2 w-4 h-4 text-muted-foreground" />
-            <Input
-              type="password"
-              placeholder="Password"
-              value={password}
-              onChange={(e) => setPassword(e.target.value)}
-              className="pl-10"
-              autoFocus
+        <div className="space-y-4">
+          {passwordLogin && (
+            <form onSubmit={handleSubmit} className="space-y-4">
+              <div className="relative">
+                <Lock className="absolute left-3 top-1/2 -translate-y-1/2 w-4 h-4 text-muted-foreground" />
+                <Input
+                  type="password"
+                  placeholder="Password"
+                  value={password}
+                  onChange={(e) => setPassword(e.target.value)}
+                  className="pl-10"
+                  autoFocus
+                  disabled={isLoading}
+                />
+              </div>
+
+              <Button
+                type="submit"
+                className="w-full"
+                disabled={isLoading || !password}
+              >
+                {isLoading ? (
+                  <>
+                    <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                    Signing in...
+                  </>
+                ) : (
+                  "Sign in"
+                )}
+              </Button>
+            </form>
+          )}
+
+          {sso && (
+            <Button
+              type="button"
+              variant={passwordLogin ? "outline" : "default"}
+              className="w-full"
               disabled={isLoading}
-            />
-          </div>
+              onClick={() => window.location.assign(SSO_LOGIN_URL)}
+            >
+              <KeyRound className="w-4 h-4 mr-2" />
+              Sign in with SSO
+            </Button>
+          )}
 
           {error && (
             <div className="text-sm text-destructive bg-destructive/10 px-3 py-2 rounded-md">
               {error}
             </div>
           )}
-
-          <Button
-            type="submit"
-            className="w-full"
-            disabled={isLoading || !password}
-          >
-            {isLoading ? (
-              <>
-                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
-                Signing in...
-              </>
-            ) : (
-              "Sign in"
-            )}
-          </Button>
-        </form>
+        </div>
       </div>
     </div>
   );
@@ -23,6 +23,7 @@ const navigationStore = vi.hoisted(() => ({
 
 vi.mock("@/hooks/useDirectory", () => ({
   useDirectory: (...args: unknown[]) => mocks.useDirectory(...args),
+  useDirectoryEvents: vi.fn(),
   useUploadWithProgress: () => ({ uploadFiles: mocks.uploadFiles }),
 }));
 
@@ -10,7 +10,11 @@ import { FileTable } from "@/components/table/FileTable";
 import { SearchResults } from "@/components/search/SearchResults";
 import { useNavigationStore } from "@/stores/navigation";
 import { useUploadWithProgress } from "@/hooks/useDirectory";
-import { useDirectory } from "@/hooks/useDirectory";
+import {
+  collectDroppedFiles,
+  getDroppedEntries,
+} from "@/lib/droppedFiles";
+import { useDirectory, useDirectoryEvents } from "@/hooks/useDirectory";
 import { useSearch } from "@/hooks/useSearch";
 import {
   DEFAULT_PAGE_SIZE,
@@ -35,6 +39,7 @@ export function MainPanel() {
   const { uploadFiles } = useUploadWithProgress();
   const isSearchActive = isSearching && searchQuery.length >= 2;
   const { data: directoryData } = useDirectory(currentPath);
+  useDirectoryEvents(currentPath);
   const { data: searchData } = useSearch(searchQuery, {
     enabled: isSearchActive,
   });
@@ -81,8 +86,12 @@ export function MainPanel() {
       dragCounterRef.current = 0;
       setIsDragging(false);
 
+      // Dropped folders are walked so their structure is kept
+      const entries = getDroppedEntries(e.dataTransfer);
       const files = e.dataTransfer.files;
-      if (files.length > 0) {
+      if (entries.some((entry) => entry.isDirectory)) {
+        await uploadFiles(currentPath, await collectDroppedFiles(entries));
+      } else if (files.length > 0) {
         await uploadFiles(currentPath, files);
       }
     },
@@ -24,9 +24,11 @@ const navigationStore = vi.hoisted(() => ({
 
 const mocks = vi.hoisted(() => ({
   useCreateDirectory: vi.fn(),
+  useCreateFile: vi.fn(),
   useDirectory: vi.fn(),
   useDelete: vi.fn(),
   useRename: vi.fn(),
+  useTemplates: vi.fn(),
   useUploadWithProgress: vi.fn(),
 }));
 
@@ -36,9 +38,11 @@ vi.mock("@/stores/navigation", () => ({
 
 vi.mock("@/hooks/useDirectory", () => ({
   useCreateDirectory: () => mocks.useCreateDirectory(),
+  useCreateFile: () => mocks.useCreateFile(),
   useDirectory: () => mocks.useDirectory(),
   useDelete: () => mocks.useDelete(),
   useRename: () => mocks.useRename(),
+  useTemplates: () => mocks.useTemplates(),
   useUploadWithProgress: () => mocks.useUploadWithProgress(),
 }));
 
@@ -48,6 +52,10 @@ let createDirectoryMock: {
   mutateAsync: ReturnType<typeof vi.fn>;
   isPending: boolean;
 };
+let createFileMock: {
+  mutateAsync: ReturnType<typeof vi.fn>;
+  isPending: boolean;
+};
 let deleteMock: { mutateAsync: ReturnType<typeof vi.fn>; isPending: boolean };
 let renameMock: { mutateAsync: ReturnType<typeof vi.fn>; isPending: boolean };
 let uploadFilesMock: ReturnType<typeof vi.fn>;
@@ -67,6 +75,12 @@ describe("Toolbar", () => {
       isPending: false,
     };
     mocks.useCreateDirectory.mockReturnValue(createDirectoryMock);
+    createFileMock = {
+      mutateAsync: vi.fn().mockResolvedValue(undefined),
+      isPending: false,
+    };
+    mocks.useCreateFile.mockReturnValue(createFileMock);
+    mocks.useTemplates.mockReturnValue({ data: { templates: ["README.md"] } });
     deleteMock = {
       mutateAsync: vi.fn().mockResolvedValue(undefined),
       isPending: false,
@@ -107,6 +121,31 @@ describe("Toolbar", () => {
     });
   });
 
+  it("creates a new file from a template", async () => {
+    const user = userEvent.setup();
+    navigationStore.state.currentPath = "/Docs";
+
+    render(<Toolbar />);
+
+    await user.click(screen.getByRole("button", { name: "New file" }));
+    const dialog = await screen.findByRole("dialog");
+    await user.selectOptions(
+      within(dialog).getByRole("combobox", { name: "Template" }),
+      "README.md",
+    );
+    expect(within(dialog).getByPlaceholderText("File name")).toHaveValue(
+      "README.md",
+    );
+    await user.click(within(dialog).getByRole("button", { name: "Create" }));
+
+    await waitFor(() => {
+      expect(createFileMock.mutateAsync).toHaveBeenCalledWith({
+        path: "/Docs/README.md",
+        template: "README.md",
+      });
+    });
+  });
+
   it("renames the selected item and clears selection", async () => {
     const user = userEvent.setup();
     navigationStore.state.selectedFiles = new Set(["/Docs/report.txt"]);
@@ -173,7 +212,7 @@ describe("Toolbar", () => {
     );
   });
 
-  it("downloads all selected files", async () => {
+  it("downloads multiple selected files as one archive", async () => {
     const user = userEvent.setup();
     navigationStore.state.selectedFiles = new Set([
       "/Docs/report.txt",
@@ -189,14 +228,23 @@ describe("Toolbar", () => {
       isLoading: false,
       error: null,
     });
-    const downloadSpy = vi.spyOn(api, "getDownloadUrl");
+    const batchSpy = vi
+      .spyOn(api, "downloadBatch")
+      .mockResolvedValue(new Blob(["zip"]));
+    URL.createObjectURL = vi.fn(() => "blob:download");
+    URL.revokeObjectURL = vi.fn();
 
     render(<Toolbar />);
 
     await user.click(screen.getByRole("button", { name: "Download" }));
 
-    expect(downloadSpy).toHaveBeenCalledWith("/Docs/report.txt");
-    expect(downloadSpy).toHaveBeenCalledWith("/Docs/notes.txt");
+    expect(batchSpy).toHaveBeenCalledWith([
+      "/Docs/report.txt",
+      "/Docs/notes.txt",
+    ]);
+    await waitFor(() =>
+      expect(URL.revokeObjectURL).toHaveBeenCalledWith("blob:download"),
+    );
   });
 
   it("disables download when only folders are selected", () => {
@@ -1,5 +1,6 @@
 import React, { useMemo, useRef, useState } from "react";
 import {
+  FilePlus,
   FolderPlus,
   Trash2,
   Download,
@@ -23,9 +24,11 @@ import { DeleteConfirmDialog } from "@/components/dialogs/DeleteConfirmDialog";
 import { useNavigationStore } from "@/stores/navigation";
 import {
   useCreateDirectory,
+  useCreateFile,
   useDirectory,
   useDelete,
   useRename,
+  useTemplates,
   useUploadWithProgress,
 } from "@/hooks/useDirectory";
 import { api } from "@/api/client";
@@ -45,6 +48,9 @@ export function Toolbar() {
   // Dialog states
   const [newFolderOpen, setNewFolderOpen] = useState(false);
   const [newFolderName, setNewFolderName] = useState("");
+  const [newFileOpen, setNewFileOpen] = useState(false);
+  const [newFileName, setNewFileName] = useState("");
+  const [newFileTemplate, setNewFileTemplate] = useState("");
   const [renameOpen, setRenameOpen] = useState(false);
   const [renameValue, setRenameValue] = useState("");
 
@@ -53,6 +59,9 @@ export function Toolbar() {
 
   // Mutations
   const createDir = useCreateDirectory();
+  const createFile = useCreateFile();
+  const { data: templateData } = useTemplates(newFileOpen);
+  const templates = templateData?.templates ?? [];
   const deleteFile = useDelete();
   const rename = useRename();
   const { uploadFiles } = useUploadWithProgress();
@@ -97,6 +106,32 @@ export function Toolbar() {
     setNewFolderName("");
   };
 
+  // New File
+  const handleNewFile = () => {
+    setNewFileName("untitled.txt");
+    setNewFileTemplate("");
+    setNewFileOpen(true);
+  };
+
+  const handleTemplateChange = (template: string) => {
+    setNewFileTemplate(template);
+    if (template) setNewFileName(template);
+  };
+
+  const handleCreateFile = async () => {
+    if (!newFileName.trim()) return;
+    const path =
+      currentPath === "/"
+        ? `/${newFileName.trim()}`
+        : `${currentPath}/${newFileName.trim()}`;
+    await createFile.mutateAsync({
+      path,
+      template: newFileTemplate || undefined,
+    });
+    setNewFileOpen(false);
+    setNewFileName("");
+  };
+
   // Delete
   const handleDelete = () => {
     if (!hasSelection) return;
@@ -158,7 +193,25 @@ export function Toolbar() {
   };
 
   // Download
-  const handleDownload = () => {
+  const handleDownload = async () => {
+    // Several files go out as one archive instead of one request each
+    if (downloadablePaths.length > 1) {
+      try {
+        const blob = await api.downloadBatch(downloadablePaths);
+        const url = URL.createObjectURL(blob);
+        const a = document.createElement("a");
+        a.href = url;
+        a.download = "download.zip";
+        document.body.appendChild(a);
+        a.click();
+        document.body.removeChild(a);
+        URL.revokeObjectURL(url);
+      } catch {
+        toast.error("Failed to download selection");
+      }
+      return;
+    }
+
     for (const path of downloadablePaths) {
       const url = toSafeHttpUrl(api.getDownloadUrl(path));
       if (!url) {
@@ -188,7 +241,10 @@ export function Toolbar() {
   };
 
   const isLoading =
-    createDir.isPending || deleteFile.isPending || rename.isPending;
+    createDir.isPending ||
+    createFile.isPending ||
+    deleteFile.isPending ||
+    rename.isPending;
 
   return (
     <>
@@ -209,6 +265,22 @@ export function Toolbar() {
           <span className="sr-only">New folder</span>
         </Button>
 
+        <Button
+          variant="ghost"
+          size="icon"
+          className="h-8 w-8"
+          onClick={handleNewFile}
+          disabled={isLoading}
+          title="New file"
+        >
+          {createFile.isPending ? (
+            <Loader2 className="w-4 h-4 animate-spin" />
+          ) : (
+            <FilePlus className="w-4 h-4" />
+          )}
+          <span className="sr-only">New file</span>
+        </Button>
+
         <Button
           variant="ghost"
           size="icon"
@@ -310,6 +382,53 @@ export function Toolbar() {
         </DialogContent>
       </Dialog>
 
+      {/* New File Dialog */}
+      <Dialog open={newFileOpen} onOpenChange={setNewFileOpen}>
+        <DialogContent className="sm:max-w-[400px]">
+          <DialogHeader>
+            <DialogTitle>New File</DialogTitle>
+            <DialogDescription>
+              Enter a name for the new file
+              {templates.length > 0 ? ", or start from a template." : "."}
+            </DialogDescription>
+          </DialogHeader>
+          <div className="py-4 space-y-3">
+            {templates.length > 0 && (
+              <select
+                aria-label="Template"
+                value={newFileTemplate}
+                onChange={(e) => handleTemplateChange(e.target.value)}
+                className="w-full h-9 rounded-md border bg-background px-3 text-sm"
+              >
+                <option value="">Empty file</option>
+                {templates.map((template) => (
+                  <option key={template} value={template}>
+                    {template}
+                  </option>
+                ))}
+              </select>
+            )}
+            <Input
+              value={newFileName}
+              onChange={(e) => setNewFileName(e.target.value)}
+              placeholder="File name"
+              onKeyDown={(e) => {
+                if (e.key === "Enter") handleCreateFile();
+              }}
+              autoFocus
+            />
+          </div>
+          <DialogFooter>
+            <Button variant="outline" onClick={() => setNewFileOpen(false)}>
+              Cancel
+            </Button>
+            <Button onClick={handleCreateFile} disabled={!newFileName.trim()}>
+              Create
+            </Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
+
       <RenameDialog
         open={renameOpen}
         onOpenChange={setRenameOpen}
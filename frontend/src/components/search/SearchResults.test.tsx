@@ -2,7 +2,7 @@ import React from "react";
 import { afterEach, beforeEach, describe, expect, it, vi } from "vitest";
 import { fireEvent, render, screen } from "@testing-library/react";
 import { SearchResults } from "./SearchResults";
-import { useSearch } from "@/hooks/useSearch";
+import { useSearch, useSearchPreview } from "@/hooks/useSearch";
 import { useNavigationStore } from "@/stores/navigation";
 import { useMove, useCopy, useRename } from "@/hooks/useDirectory";
 import { useKeyboard } from "@/hooks/useKeyboard";
@@ -13,6 +13,7 @@ let latestTableProps: Record<string, unknown> | null = null;
 
 vi.mock("@/hooks/useSearch", () => ({
   useSearch: vi.fn(),
+  useSearchPreview: vi.fn(() => []),
 }));
 
 vi.mock("@/stores/navigation", () => ({
@@ -152,6 +153,22 @@ describe("SearchResults", () => {
     expect(container.querySelector(".animate-spin")).toBeTruthy();
   });
 
+  it("shows streamed matches while the search loads", () => {
+    vi.mocked(useNavigationStore).mockReturnValue({
+      ...baseNavigationState(),
+      searchQuery: "ab",
+    });
+    mockSearchResult({ data: undefined, isLoading: true, error: null });
+    vi.mocked(useSearchPreview).mockReturnValueOnce(makeEntries());
+
+    const { container } = render(<SearchResults />);
+
+    expect(useSearchPreview).toHaveBeenCalledWith("ab", true);
+    expect(screen.getByTestId("table")).toBeInTheDocument();
+    expect(container.querySelector(".animate-spin")).toBeNull();
+    expect(latestTableProps?.entries).toHaveLength(3);
+  });
+
   it("renders error state", () => {
     vi.mocked(useNavigationStore).mockReturnValue({
       ...baseNavigationState(),
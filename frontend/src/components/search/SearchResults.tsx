@@ -3,7 +3,7 @@ import { Loader2, FolderOpen, Search } from "lucide-react";
 import { FileContextMenu } from "@/components/table/FileContextMenu";
 import type { FileEntry } from "@/types/file";
 import { useNavigationStore } from "@/stores/navigation";
-import { useSearch } from "@/hooks/useSearch";
+import { useSearch, useSearchPreview } from "@/hooks/useSearch";
 import { useMove, useCopy, useRename } from "@/hooks/useDirectory";
 import { useKeyboard } from "@/hooks/useKeyboard";
 import { api } from "@/api/client";
@@ -51,10 +51,14 @@ export function SearchResults() {
     openPreview,
   } = useNavigationStore();
   const { data, isLoading, error } = useSearch(searchQuery, { enabled: true });
+  const preview = useSearchPreview(searchQuery, isLoading);
   const move = useMove();
   const copy = useCopy();
   const rename = useRename();
-  const rows = useMemo(() => (data?.entries || []).map(toRow), [data?.entries]);
+  const rows = useMemo(
+    () => (data?.entries ?? (isLoading ? preview : [])).map(toRow),
+    [data?.entries, isLoading, preview],
+  );
   const entryLookup = useMemo(() => {
     const map = new Map<string, FileEntry>();
     for (const entry of rows) {
@@ -252,7 +256,7 @@ export function SearchResults() {
     );
   }
 
-  if (isLoading) {
+  if (isLoading && rows.length === 0) {
     return (
       <div className="flex items-center justify-center h-full">
         <Loader2 className="w-8 h-8 animate-spin text-muted-foreground" />
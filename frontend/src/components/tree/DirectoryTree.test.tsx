@@ -108,6 +108,57 @@ describe("DirectoryTree", () => {
     expect(item).toHaveAttribute("title", nodes[0].name);
   });
 
+  it("expands prefetched children without fetching and shows counts", async () => {
+    const nodes: TreeNode[] = [
+      {
+        name: "Projects",
+        path: "/Projects",
+        has_children: true,
+        child_dir_count: 1,
+        file_count: 12,
+        children: [
+          { name: "Site", path: "/Projects/Site", has_children: false },
+        ],
+      },
+    ];
+    mockedUseTree.mockImplementation((path: string) => {
+      if (path === "/") {
+        return { data: nodes, isLoading: false } as never;
+      }
+      return { data: undefined, isLoading: false } as never;
+    });
+
+    render(<DirectoryTree />);
+
+    const item = await screen.findByText("Projects");
+    expect(item).toHaveAttribute("title", "Projects (1 folder, 12 files)");
+    expect(mockedUseTree).toHaveBeenCalledWith("/", true, 2);
+
+    fireEvent.click(item.parentElement?.firstElementChild as HTMLElement);
+
+    expect(await screen.findByText("Site")).toBeInTheDocument();
+    expect(mockedUseTree).toHaveBeenCalledWith("/Projects", false);
+    expect(mockedUseTree).not.toHaveBeenCalledWith("/Projects", true);
+  });
+
+  it("marks folders the server cannot open", async () => {
+    const nodes: TreeNode[] = [
+      {
+        name: "private",
+        path: "/private",
+        has_children: false,
+        readable: false,
+      },
+    ];
+    mockedUseTree.mockReturnValue({ data: nodes, isLoading: false } as never);
+
+    render(<DirectoryTree />);
+
+    const item = await screen.findByText("private");
+    expect(item).toHaveAttribute("title", "private (no permission to open)");
+    expect(item.parentElement).toHaveClass("opacity-60");
+  });
+
   it("opens drop prompt when dropping on root", async () => {
     mockedUseTree.mockReturnValue({ data: [], isLoading: false } as never);
 
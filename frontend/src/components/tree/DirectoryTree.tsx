@@ -17,6 +17,19 @@ import {
 } from "@/components/dnd/DropPrompt";
 import { performDropAction } from "@/components/dnd/dropActions";
 
+/** Name with the indexed counts, e.g. "photos (3 folders, 120 files)" */
+function nodeTitle(node: TreeNodeType): string {
+  if (node.readable === false) {
+    return `${node.name} (no permission to open)`;
+  }
+  if (node.file_count === undefined || node.child_dir_count === undefined) {
+    return node.name;
+  }
+  const plural = (n: number, word: string) =>
+    `${n} ${word}${n === 1 ? "" : "s"}`;
+  return `${node.name} (${plural(node.child_dir_count, "folder")}, ${plural(node.file_count, "file")})`;
+}
+
 interface TreeNodeProps {
   node: TreeNodeType;
   depth: number;
@@ -43,10 +56,12 @@ const TreeNode = React.memo(
       [node.name, node.path, parentPath],
     );
 
-    const { data: children, isLoading } = useTree(
+    // Nodes that came with their children need no fetch of their own
+    const { data: fetchedChildren, isLoading } = useTree(
       normalizedPath,
-      isExpanded && node.has_children,
+      isExpanded && node.has_children && !node.children,
     );
+    const children = fetchedChildren ?? node.children;
 
     const isSelected = currentPath === normalizedPath;
 
@@ -166,6 +181,7 @@ const TreeNode = React.memo(
           className={cn(
             "flex items-center gap-1 px-2 py-1 cursor-pointer hover:bg-accent rounded-sm",
             isSelected && "bg-accent",
+            node.readable === false && "opacity-60",
             isDragOver && "bg-primary/20 ring-2 ring-primary",
             isDragging && "opacity-50",
           )}
@@ -197,7 +213,7 @@ const TreeNode = React.memo(
           ) : (
             <Folder className="w-4 h-4 text-yellow-500" />
           )}
-          <span className="text-sm truncate" title={node.name}>
+          <span className="text-sm truncate" title={nodeTitle(node)}>
             {node.name}
           </span>
         </div>
@@ -224,12 +240,17 @@ const TreeNode = React.memo(
     prevProps.onDropPrompt === nextProps.onDropPrompt &&
     prevProps.node.path === nextProps.node.path &&
     prevProps.node.name === nextProps.node.name &&
-    prevProps.node.has_children === nextProps.node.has_children,
+    prevProps.node.has_children === nextProps.node.has_children &&
+    prevProps.node.readable === nextProps.node.readable &&
+    prevProps.node.children === nextProps.node.children &&
+    prevProps.node.file_count === nextProps.node.file_count &&
+    prevProps.node.child_dir_count === nextProps.node.child_dir_count,
 );
 
 export function DirectoryTree() {
   const { setCurrentPath, currentPath, clearSelection } = useNavigationStore();
-  const { data: rootNodes, isLoading } = useTree("/");
+  // Two levels up front, so expanding a top-level folder is instant
+  const { data: rootNodes, isLoading } = useTree("/", true, 2);
   const [isDragOver, setIsDragOver] = useState(false);
   const [dropPrompt, setDropPrompt] = useState<DropPromptState>(null);
   const move = useMove();
@@ -4,6 +4,7 @@ import { renderHook, waitFor, act } from "@testing-library/react";
 import { QueryClient, QueryClientProvider } from "@tanstack/react-query";
 import {
   useDirectory,
+  useDirectoryEvents,
   useTree,
   useCreateDirectory,
   useRename,
@@ -23,6 +24,7 @@ import { toast } from "sonner";
 vi.mock("@/api/client", () => ({
   api: {
     listDirectory: vi.fn(),
+    getDirectoryEventsUrl: vi.fn(() => "/api/events?path=%2Fphotos"),
     getTree: vi.fn(),
     createDirectory: vi.fn(),
     rename: vi.fn(),
@@ -34,6 +36,7 @@ vi.mock("@/api/client", () => ({
     triggerIndex: vi.fn(),
     uploadWithProgress: vi.fn(),
   },
+  errorHint: vi.fn(),
 }));
 
 vi.mock("@/stores/navigation", () => ({
@@ -98,6 +101,51 @@ describe("useDirectory", () => {
     );
   });
 
+  it("refreshes listings when the server reports a change", () => {
+    const sources: FakeEventSource[] = [];
+    class FakeEventSource {
+      listeners: Record<string, (message: { data: string }) => void> = {};
+      close = vi.fn();
+      constructor(public url: string) {
+        sources.push(this);
+      }
+      addEventListener(type: string, listener: (m: { data: string }) => void) {
+        this.listeners[type] = listener;
+      }
+    }
+    vi.stubGlobal("EventSource", FakeEventSource);
+    const { queryClient, wrapper } = createWrapper();
+    const invalidateSpy = vi.spyOn(queryClient, "invalidateQueries");
+
+    const { unmount } = renderHook(() => useDirectoryEvents("/photos"), {
+      wrapper,
+    });
+    expect(sources[0].url).toBe("/api/events?path=%2Fphotos");
+
+    sources[0].listeners.change({
+      data: JSON.stringify({
+        event: "renamed",
+        path: "/beach.jpg",
+        from: "/photos/beach.jpg",
+        entry: { name: "beach.jpg", path: "/beach.jpg", is_dir: false },
+      }),
+    });
+    expect(invalidateSpy).toHaveBeenCalledWith({
+      queryKey: ["directory", "/photos"],
+    });
+    expect(invalidateSpy).toHaveBeenCalledWith({
+      queryKey: ["directory", "/"],
+    });
+    expect(invalidateSpy).not.toHaveBeenCalledWith({ queryKey: ["tree"] });
+
+    sources[0].listeners.resync({ data: "" });
+    expect(invalidateSpy).toHaveBeenCalledWith({ queryKey: ["tree"] });
+
+    unmount();
+    expect(sources[0].close).toHaveBeenCalled();
+    vi.unstubAllGlobals();
+  });
+
   it("skips tree fetch when disabled", () => {
     const { wrapper } = createWrapper();
     renderHook(() => useTree("/", false), { wrapper });
@@ -270,6 +318,12 @@ describe("useDirectory", () => {
     });
 
     expect(addUpload).toHaveBeenCalledTimes(2);
+    expect(mockedApi.uploadWithProgress).toHaveBeenCalledWith(
+      "/uploads",
+      fileOne,
+      expect.any(Function),
+      "a.txt",
+    );
     expect(updateProgress).toHaveBeenCalled();
     expect(setStatus).toHaveBeenCalledWith(expect.any(String), "uploading");
     expect(setStatus).toHaveBeenCalledWith(expect.any(String), "completed");
@@ -1,9 +1,15 @@
+import { useEffect } from "react";
 import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
 import { toast } from "sonner";
-import { api } from "@/api/client";
+import { api, errorHint } from "@/api/client";
 import { useUploadStore } from "@/stores/upload";
 import { useNavigationStore } from "@/stores/navigation";
-import type { SortField } from "@/types/file";
+import type {
+  DirectoryChange,
+  ExpectedState,
+  SortField,
+} from "@/types/file";
+import type { DroppedFile } from "@/lib/droppedFiles";
 
 export function useDirectory(path: string) {
   const { directoryOffset, directoryLimit, sortConfig } = useNavigationStore();
@@ -41,10 +47,44 @@ export function useDirectory(path: string) {
   });
 }
 
-export function useTree(path: string, enabled = true) {
+const parentOf = (path: string) =>
+  path.split("/").slice(0, -1).join("/") || "/";
+
+/** Refresh the listing of `path` when the server reports changes in it */
+export function useDirectoryEvents(path: string) {
+  const queryClient = useQueryClient();
+
+  useEffect(() => {
+    if (typeof EventSource === "undefined") return;
+    const source = new EventSource(api.getDirectoryEventsUrl([path]));
+
+    source.addEventListener("change", (message) => {
+      const change: DirectoryChange = JSON.parse(message.data);
+      for (const changed of [change.path, change.from]) {
+        if (changed) {
+          queryClient.invalidateQueries({
+            queryKey: ["directory", parentOf(changed)],
+          });
+        }
+      }
+      if (change.entry?.is_dir !== false) {
+        queryClient.invalidateQueries({ queryKey: ["tree"] });
+      }
+    });
+    // Changes were missed; reload everything shown
+    source.addEventListener("resync", () => {
+      queryClient.invalidateQueries({ queryKey: ["directory", path] });
+      queryClient.invalidateQueries({ queryKey: ["tree"] });
+    });
+
+    return () => source.close();
+  }, [path, queryClient]);
+}
+
+export function useTree(path: string, enabled = true, depth = 1) {
   return useQuery({
-    queryKey: ["tree", path],
-    queryFn: () => api.getTree(path),
+    queryKey: ["tree", path, depth],
+    queryFn: () => api.getTree(path, { depth }),
     enabled,
     staleTime: 60_000,
   });
@@ -63,7 +103,38 @@ export function useCreateDirectory() {
       queryClient.invalidateQueries({ queryKey: ["tree"] });
     },
     onError: (error) => {
-      toast.error(`Failed to create folder: ${error.message}`);
+      toast.error(`Failed to create folder: ${error.message}`, {
+        description: errorHint(error),
+      });
+    },
+  });
+}
+
+export function useTemplates(enabled = true) {
+  return useQuery({
+    queryKey: ["templates"],
+    queryFn: () => api.listTemplates(),
+    enabled,
+    staleTime: 60_000,
+  });
+}
+
+export function useCreateFile() {
+  const queryClient = useQueryClient();
+
+  return useMutation({
+    mutationFn: ({ path, template }: { path: string; template?: string }) =>
+      api.createFile(path, { template }),
+    onSuccess: (response, { path }) => {
+      const created = response.path ?? path;
+      toast.success(`Created file "${created.split("/").pop()}"`);
+      const parent = created.split("/").slice(0, -1).join("/") || "/";
+      queryClient.invalidateQueries({ queryKey: ["directory", parent] });
+    },
+    onError: (error) => {
+      toast.error(`Failed to create file: ${error.message}`, {
+        description: errorHint(error),
+      });
     },
   });
 }
@@ -72,15 +143,24 @@ export function useRename() {
   const queryClient = useQueryClient();
 
   return useMutation({
-    mutationFn: ({ path, newName }: { path: string; newName: string }) =>
-      api.rename(path, newName),
+    mutationFn: ({
+      path,
+      newName,
+      expected,
+    }: {
+      path: string;
+      newName: string;
+      expected?: ExpectedState;
+    }) => api.rename(path, newName, expected),
     onSuccess: (_, { newName }) => {
       toast.success(`Renamed to "${newName}"`);
       queryClient.invalidateQueries({ queryKey: ["directory"] });
       queryClient.invalidateQueries({ queryKey: ["tree"] });
     },
     onError: (error) => {
-      toast.error(`Failed to rename: ${error.message}`);
+      toast.error(`Failed to rename: ${error.message}`, {
+        description: errorHint(error),
+      });
     },
   });
 }
@@ -94,13 +174,17 @@ export function useMove() {
       to,
       overwrite = false,
       suppressToast,
+      expected,
     }: {
       from: string;
       to: string;
       overwrite?: boolean;
       suppressToast?: boolean;
+      expected?: ExpectedState;
     }) =>
-      api.move(from, to, overwrite).then((res) => ({ ...res, suppressToast })),
+      api
+        .move(from, to, overwrite, expected)
+        .then((res) => ({ ...res, suppressToast })),
     onSuccess: (data, { from, suppressToast }) => {
       const name = from.split("/").pop();
       if (!suppressToast) {
@@ -114,7 +198,9 @@ export function useMove() {
       queryClient.invalidateQueries({ queryKey: ["tree"] });
     },
     onError: (error) => {
-      toast.error(`Failed to move: ${error.message}`);
+      toast.error(`Failed to move: ${error.message}`, {
+        description: errorHint(error),
+      });
     },
   });
 }
@@ -148,7 +234,9 @@ export function useCopy() {
       queryClient.invalidateQueries({ queryKey: ["tree"] });
     },
     onError: (error) => {
-      toast.error(`Failed to copy: ${error.message}`);
+      toast.error(`Failed to copy: ${error.message}`, {
+        description: errorHint(error),
+      });
     },
   });
 }
@@ -157,9 +245,15 @@ export function useDelete() {
   const queryClient = useQueryClient();
 
   return useMutation({
-    mutationFn: (input: string | { path: string; suppressToast?: boolean }) => {
-      const path = typeof input === "string" ? input : input.path;
-      return api.delete(path);
+    mutationFn: (
+      input:
+        | string
+        | { path: string; suppressToast?: boolean; expected?: ExpectedState },
+    ) => {
+      if (typeof input === "string") {
+        return api.delete(input);
+      }
+      return api.delete(input.path, input.expected);
     },
     onSuccess: (_, input) => {
       const path = typeof input === "string" ? input : input.path;
@@ -177,7 +271,9 @@ export function useDelete() {
       const suppressToast =
         typeof input === "string" ? false : input?.suppressToast;
       if (!suppressToast) {
-        toast.error(`Failed to delete: ${error.message}`);
+        toast.error(`Failed to delete: ${error.message}`, {
+          description: errorHint(error),
+        });
       }
     },
   });
@@ -201,7 +297,9 @@ export function useUpload() {
       queryClient.invalidateQueries({ queryKey: ["directory", targetPath] });
     },
     onError: (error) => {
-      toast.error(`Failed to upload: ${error.message}`);
+      toast.error(`Failed to upload: ${error.message}`, {
+        description: errorHint(error),
+      });
     },
   });
 }
@@ -226,7 +324,9 @@ export function useIndexer() {
       queryClient.invalidateQueries({ queryKey: ["indexer-status"] });
     },
     onError: (error) => {
-      toast.error(`Failed to start indexing: ${error.message}`);
+      toast.error(`Failed to start indexing: ${error.message}`, {
+        description: errorHint(error),
+      });
     },
   });
 
@@ -237,13 +337,21 @@ export function useUploadWithProgress() {
   const queryClient = useQueryClient();
   const { addUpload, updateProgress, setStatus } = useUploadStore();
 
-  const uploadFiles = async (targetPath: string, files: FileList | File[]) => {
-    const fileArray = Array.from(files);
+  const uploadFiles = async (
+    targetPath: string,
+    files: FileList | File[] | DroppedFile[],
+  ) => {
+    // Folder picks carry webkitRelativePath; folder drops come with paths
+    const fileArray = Array.from(files).map((item) =>
+      item instanceof File
+        ? { file: item, path: item.webkitRelativePath || item.name }
+        : item,
+    );
 
     // Add all files to the upload store
-    const uploadItems = fileArray.map((file) => ({
+    const uploadItems = fileArray.map(({ file, path }) => ({
       id: `${Date.now()}-${Math.random().toString(36).substr(2, 9)}-${file.name}`,
-      name: file.name,
+      name: path,
       size: file.size,
       progress: 0,
       status: "pending" as const,
@@ -256,15 +364,20 @@ export function useUploadWithProgress() {
     let errorCount = 0;
 
     for (let i = 0; i < fileArray.length; i++) {
-      const file = fileArray[i];
+      const { file, path } = fileArray[i];
       const uploadItem = uploadItems[i];
 
       setStatus(uploadItem.id, "uploading");
 
       try {
-        await api.uploadWithProgress(targetPath, file, (progress) => {
-          updateProgress(uploadItem.id, progress);
-        });
+        await api.uploadWithProgress(
+          targetPath,
+          file,
+          (progress) => {
+            updateProgress(uploadItem.id, progress);
+          },
+          path,
+        );
         setStatus(uploadItem.id, "completed");
         successCount++;
       } catch (error) {
@@ -277,6 +390,9 @@ export function useUploadWithProgress() {
 
     // Invalidate queries after all uploads
     queryClient.invalidateQueries({ queryKey: ["directory", targetPath] });
+    if (fileArray.some(({ path }) => path.includes("/"))) {
+      queryClient.invalidateQueries({ queryKey: ["tree"] });
+    }
 
     // Show summary toast
     if (successCount > 0 && errorCount === 0) {
@@ -3,7 +3,10 @@ import { useEffect, useState } from "react";
 
 import { api } from "@/api/client";
 import { useNavigationStore } from "@/stores/navigation";
-import type { SortField } from "@/types/file";
+import type { FileEntry, SearchProgress, SortField } from "@/types/file";
+
+/** Streamed matches shown while a full search page loads */
+const PREVIEW_LIMIT = 50;
 
 export function useSearch(query: string, options: { enabled?: boolean } = {}) {
   const [debouncedQuery, setDebouncedQuery] = useState(query);
@@ -48,3 +51,38 @@ export function useSearch(query: string, options: { enabled?: boolean } = {}) {
     staleTime: 60_000,
   });
 }
+
+/**
+ * First matches for `query`, streamed while the index is scanned, for
+ * showing something before the full search returns
+ */
+export function useSearchPreview(query: string, enabled = true) {
+  const [entries, setEntries] = useState<FileEntry[]>([]);
+
+  useEffect(() => {
+    setEntries([]);
+    if (!enabled || query.length < 2 || typeof EventSource === "undefined") {
+      return;
+    }
+
+    let source: EventSource | undefined;
+    // A shorter debounce than the full search; closing drops the scan
+    const handle = setTimeout(() => {
+      source = new EventSource(api.getSearchStreamUrl(query, PREVIEW_LIMIT));
+      source.addEventListener("results", (message) => {
+        const progress: SearchProgress = JSON.parse(message.data);
+        if (progress.entries.length > 0) {
+          setEntries((current) => [...current, ...progress.entries]);
+        }
+      });
+      source.addEventListener("done", () => source?.close());
+    }, 100);
+
+    return () => {
+      clearTimeout(handle);
+      source?.close();
+    };
+  }, [query, enabled]);
+
+  return entries;
+}
@@ -0,0 +1,60 @@
+/** A dropped file and its path relative to the drop target */
+export interface DroppedFile {
+  file: File;
+  path: string;
+}
+
+const readAllEntries = async (
+  directory: FileSystemDirectoryEntry,
+): Promise<FileSystemEntry[]> => {
+  const reader = directory.createReader();
+  const entries: FileSystemEntry[] = [];
+  // readEntries returns results in batches until it yields an empty one
+  for (;;) {
+    const batch = await new Promise<FileSystemEntry[]>((resolve, reject) =>
+      reader.readEntries(resolve, reject),
+    );
+    if (batch.length === 0) return entries;
+    entries.push(...batch);
+  }
+};
+
+const collectEntry = async (
+  entry: FileSystemEntry,
+  out: DroppedFile[],
+): Promise<void> => {
+  if (entry.isFile) {
+    const file = await new Promise<File>((resolve, reject) =>
+      (entry as FileSystemFileEntry).file(resolve, reject),
+    );
+    out.push({ file, path: entry.fullPath.replace(/^\//, "") });
+  } else if (entry.isDirectory) {
+    const children = await readAllEntries(entry as FileSystemDirectoryEntry);
+    for (const child of children) {
+      await collectEntry(child, out);
+    }
+  }
+};
+
+/**
+ * Entries of a drop, or an empty list when the browser does not expose them.
+ * Must be called synchronously in the drop handler; the item list is cleared
+ * once the event returns.
+ */
+export const getDroppedEntries = (
+  dataTransfer: DataTransfer,
+): FileSystemEntry[] =>
+  Array.from(dataTransfer.items ?? [])
+    .map((item) => item.webkitGetAsEntry?.())
+    .filter((entry): entry is FileSystemEntry => !!entry);
+
+/** Files below the given entries, descending into folders */
+export const collectDroppedFiles = async (
+  entries: FileSystemEntry[],
+): Promise<DroppedFile[]> => {
+  const files: DroppedFile[] = [];
+  for (const entry of entries) {
+    await collectEntry(entry, files);
+  }
+  return files;
+};
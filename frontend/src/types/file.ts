@@ -11,12 +11,105 @@ export interface FileEntry {
   height?: number;
   duration?: number;
   indexed_at?: string;
+  artist?: string;
+  album?: string;
+  title?: string;
+  track_number?: number;
+  page_count?: number;
+  author?: string;
+  original_name?: string;
+  has_notes?: boolean;
+  /** Set on links made through the API, with the path linked to */
+  link_kind?: "hard" | "symbolic";
+  link_target?: string;
+}
+
+/** Whether a file holds text, and its likely encoding */
+export interface ContentInfo {
+  is_text: boolean;
+  encoding?: "utf-8" | "utf-16le" | "utf-16be" | "latin-1";
+  bom: boolean;
+}
+
+export interface FileStat extends FileEntry {
+  /** Left out for directories */
+  content?: ContentInfo;
+}
+
+export interface SelectionSize {
+  /** Files in the selection, counting those inside folders */
+  files: number;
+  bytes: number;
+}
+
+export type SimilarReason = "name" | "duration" | "dimensions" | "checksum";
+
+export interface SimilarEntry extends FileEntry {
+  /** Higher is more alike; a matching checksum alone scores 1 */
+  score: number;
+  reasons: SimilarReason[];
+}
+
+export interface SimilarResponse {
+  path: string;
+  entries: SimilarEntry[];
+}
+
+/** Links that work without the session cookie until `expires_at` */
+export interface SignedUrl {
+  url: string;
+  thumbnail_url: string;
+  /** Unix time in seconds */
+  expires_at: number;
+}
+
+export interface FileNote {
+  id: number;
+  path: string;
+  body: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface FileMeta {
+  path: string;
+  meta: Record<string, string>;
+}
+
+export interface FileLock {
+  path: string;
+  owner?: string;
+  locked_at: string;
+  expires_at: string;
+}
+
+/** A lock taken out by this client, with the token proving it */
+export interface HeldLock extends FileLock {
+  token: string;
+}
+
+export interface LockStatus {
+  path: string;
+  lock: FileLock | null;
+}
+
+/** A change pushed for a watched directory; `entry` is absent once gone */
+export interface DirectoryChange {
+  event: "created" | "modified" | "deleted" | "renamed";
+  path: string;
+  from?: string;
+  entry: FileEntry | null;
 }
 
 export interface TreeNode {
   name: string;
   path: string;
   has_children: boolean;
+  /** False for folders the server cannot list */
+  readable?: boolean;
+  /** Subdirectories and files directly inside, when indexed */
+  child_dir_count?: number;
+  file_count?: number;
   children?: TreeNode[];
   isExpanded?: boolean;
   isLoading?: boolean;
@@ -30,10 +123,203 @@ export interface ListResponse {
   total: number;
   sort_by?: SortField;
   sort_order?: SortOrder;
+  previews_disabled?: boolean;
+  source?: "fs" | "index";
+}
+
+export type FileCategory = "images" | "video" | "audio" | "documents" | "other";
+
+export interface CategoryStats {
+  category: FileCategory;
+  count: number;
+  size: number;
+}
+
+export interface DirectoryStats {
+  path: string;
+  total_files: number;
+  total_size: number;
+  categories: CategoryStats[];
+}
+
+export interface RecentDay {
+  date: string;
+  count: number;
+  total_size: number;
+  entries: FileEntry[];
+}
+
+export interface RecentAdditionsResponse {
+  path_prefix: string;
+  since: string;
+  days: RecentDay[];
+  total: number;
+  truncated: boolean;
+}
+
+/** What an upload does when the name is taken; the server default is "fail" */
+export type UploadConflict = "overwrite" | "rename" | "fail";
+
+export interface UploadProgress {
+  state: "receiving" | "complete" | "failed";
+  bytes_received: number;
+  content_length?: number;
+  files_completed: number;
+  current_file?: string;
+  error?: string;
+  started_at: string;
+  updated_at: string;
+}
+
+/** Stable kind of failure, to branch on instead of message text. */
+export type ErrorCode =
+  | "bad_request"
+  | "invalid_name"
+  | "not_a_directory"
+  | "not_a_file"
+  | "not_found"
+  | "exists"
+  | "conflict"
+  | "modified"
+  | "forbidden"
+  | "path_escape"
+  | "unauthorized"
+  | "locked"
+  | "quota"
+  | "readonly"
+  | "infected"
+  | "too_large"
+  | "range_not_satisfiable"
+  | "unsupported_media_type"
+  | "rate_limited"
+  | "timeout"
+  | "unavailable"
+  | "not_implemented"
+  | "internal";
+
+/** What the UI last listed for an entry; the server refuses the action with
+ * code `modified` if the entry has changed since */
+export interface ExpectedState {
+  expected_mtime?: string;
+  expected_size?: number;
 }
 
 export interface ErrorResponse {
+  /** Missing from servers older than error codes */
+  code?: ErrorCode;
+  message?: string;
+  /** Same as `message`, kept for older clients */
   error: string;
+  /** The path the error is about */
+  path?: string;
+  details?: unknown;
+  hint?: string;
+}
+
+export type ConflictResolution = "overwrite" | "rename" | "skip";
+
+export interface MoveConflict {
+  path: string;
+  destination: string;
+  is_dir: boolean;
+  existing_is_dir: boolean;
+}
+
+export interface MoveBatchResponse {
+  /** False when unresolved conflicts stopped the batch; nothing moved */
+  performed: boolean;
+  conflicts: MoveConflict[];
+  results: {
+    from: string;
+    outcome: "moved" | "skipped" | "failed";
+    to?: string;
+    error?: string;
+  }[];
+}
+
+export interface SyncStats {
+  created: number;
+  updated: number;
+  /** Already current at the destination */
+  skipped: number;
+  /** Removed from the destination because the source no longer has them */
+  deleted: number;
+}
+
+/** A directory mirrored into another on a schedule */
+export interface SyncJob {
+  id: number;
+  source: string;
+  destination: string;
+  interval_secs: number;
+  delete_extraneous: boolean;
+  checksum: boolean;
+  enabled: boolean;
+  created_at: string;
+  next_run_at: string;
+  running: boolean;
+  last_run: SyncRun | null;
+}
+
+export interface SyncRun {
+  id: number;
+  job_id: number;
+  started_at: string;
+  finished_at: string;
+  status: "success" | "failed";
+  created: number;
+  updated: number;
+  skipped: number;
+  deleted: number;
+  error: string | null;
+}
+
+/** A copy of a directory to an rclone remote */
+export interface RemoteSync {
+  id: number;
+  source: string;
+  remote: string;
+  remote_path: string;
+  delete_extraneous: boolean;
+  status: "running" | "success" | "failed";
+  started_at: string;
+  finished_at: string | null;
+  progress: {
+    bytes: number;
+    total_bytes: number;
+    transfers: number;
+    total_transfers: number;
+    checks: number;
+    deletes: number;
+    errors: number;
+    /** Bytes per second */
+    speed: number;
+    /** Seconds left */
+    eta: number | null;
+  };
+  error: string | null;
+}
+
+/** The virus scan of one upload */
+export interface UploadScan {
+  id: number;
+  path: string;
+  /** `skipped` when the file was larger than the scan limit, `error` when
+   * clamd could not be reached and the upload was refused */
+  status: "clean" | "infected" | "skipped" | "error";
+  signature: string | null;
+  quarantined_as: string | null;
+  error: string | null;
+  scanned_at: string;
+}
+
+export interface CreateSyncJob {
+  source: string;
+  destination: string;
+  interval_secs: number;
+  delete_extraneous?: boolean;
+  checksum?: boolean;
+  delay_first_run?: boolean;
 }
 
 export interface SuccessResponse {
@@ -41,6 +327,8 @@ export interface SuccessResponse {
   path?: string;
   message?: string;
   performed?: boolean;
+  /** What a copy in `sync` mode did */
+  sync?: SyncStats;
 }
 
 export type SortField =
@@ -62,6 +350,65 @@ export interface SortConfig {
   order: SortOrder;
 }
 
+/** Library-wide slices of the index */
+export type LibraryView = "images" | "videos" | "recent";
+
+export interface ViewResponse {
+  path: string;
+  entries: FileEntry[];
+  offset: number;
+  limit: number;
+  total: number;
+  sort_by?: SortField;
+  sort_order?: SortOrder;
+}
+
+export type TimelineGroup = "year" | "month" | "day";
+
+export interface TimelineEntry extends FileEntry {
+  /** Capture time (camera local time), or modification time without one */
+  taken_at: string;
+}
+
+export interface TimelineResponse {
+  path: string;
+  group: TimelineGroup;
+  buckets: { key: string; count: number }[];
+  bucket?: string;
+  entries: TimelineEntry[];
+  offset: number;
+  limit: number;
+  total: number;
+}
+
+/** GeoJSON point for one geotagged file, or a cluster of nearby ones */
+export interface MapFeature {
+  type: "Feature";
+  geometry: { type: "Point"; coordinates: [number, number] };
+  properties: {
+    cluster: boolean;
+    count: number;
+    path?: string;
+    name?: string;
+    mime_type?: string;
+    taken_at?: string;
+  };
+}
+
+export interface MapResponse {
+  type: "FeatureCollection";
+  features: MapFeature[];
+  truncated: boolean;
+}
+
+/** One step of a streaming search; `entries` are new since the last */
+export interface SearchProgress {
+  entries: FileEntry[];
+  matched: number;
+  scanned: number;
+  index_size: number;
+}
+
 export interface SearchResponse {
   query: string;
   entries: FileEntry[];
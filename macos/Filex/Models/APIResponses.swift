@@ -85,10 +85,13 @@ nonisolated struct ErrorResponse: Codable, Sendable {
 nonisolated struct AuthStatus: Codable, Sendable {
     let authenticated: Bool
     let authRequired: Bool
+    /// Echoed in X-CSRF-Token on state-changing requests
+    let csrfToken: String?
 
     enum CodingKeys: String, CodingKey {
         case authenticated
         case authRequired = "auth_required"
+        case csrfToken = "csrf_token"
     }
 }
 
@@ -96,6 +99,13 @@ nonisolated struct AuthStatus: Codable, Sendable {
 nonisolated struct AuthResponse: Codable, Sendable {
     let success: Bool
     let error: String?
+    /// Echoed in X-CSRF-Token on state-changing requests
+    let csrfToken: String?
+
+    enum CodingKeys: String, CodingKey {
+        case success, error
+        case csrfToken = "csrf_token"
+    }
 }
 
 /// Response from GET /api/health
@@ -104,6 +114,7 @@ nonisolated struct HealthResponse: Codable, Sendable {
     let version: String?
     let gitCommit: String?
     let builtAt: String?
+    let minClientVersion: String?
     let ffprobeAvailable: Bool?
     let databaseStatus: DatabaseStatus?
 
@@ -111,6 +122,7 @@ nonisolated struct HealthResponse: Codable, Sendable {
         case status, version
         case gitCommit = "git_commit"
         case builtAt = "built_at"
+        case minClientVersion = "min_client_version"
         case ffprobeAvailable = "ffprobe_available"
         case databaseStatus = "database_status"
     }
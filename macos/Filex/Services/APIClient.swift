@@ -4,6 +4,12 @@ import Foundation
 actor APIClient {
     private let session: URLSession
     private var baseURL: URL?
+    /// Echoed in X-CSRF-Token on state-changing requests while the server
+    /// requires authentication; issued by login and the auth status check
+    private var csrfToken: String?
+    /// Whether `csrfToken` reflects the server's answer; without
+    /// authentication it stays nil
+    private var csrfTokenLoaded = false
 
     /// Date decoder for ISO8601 dates from the server
     private let decoder: JSONDecoder = {
@@ -46,6 +52,8 @@ actor APIClient {
     /// Configure the base URL for API requests
     func configure(baseURL: URL) {
         self.baseURL = baseURL
+        self.csrfToken = nil
+        self.csrfTokenLoaded = false
     }
 
     /// Check if the client is configured
@@ -178,6 +186,7 @@ actor APIClient {
         var request = URLRequest(url: url)
         request.httpMethod = "POST"
         request.setValue("multipart/form-data; boundary=\(boundary)", forHTTPHeaderField: "Content-Type")
+        try await setCSRFHeader(on: &request)
 
         // Build multipart form data
         var data = Data()
@@ -219,7 +228,10 @@ actor APIClient {
         guard let base = baseURL else { throw APIError.notConnected }
         let url = base.appendingPathComponent("auth/login")
         let body = ["password": password]
-        return try await request(url, method: "POST", body: try encoder.encode(body))
+        let response: AuthResponse = try await request(url, method: "POST", body: try encoder.encode(body))
+        csrfToken = response.csrfToken
+        csrfTokenLoaded = response.success
+        return response
     }
 
     /// Logout
@@ -227,13 +239,18 @@ actor APIClient {
         guard let base = baseURL else { throw APIError.notConnected }
         let url = base.appendingPathComponent("auth/logout")
         let _: SuccessResponse = try await request(url, method: "POST")
+        csrfToken = nil
+        csrfTokenLoaded = false
     }
 
     /// Get authentication status
     func getAuthStatus() async throws -> AuthStatus {
         guard let base = baseURL else { throw APIError.notConnected }
         let url = base.appendingPathComponent("auth/status")
-        return try await request(url)
+        let status: AuthStatus = try await request(url)
+        csrfToken = status.csrfToken
+        csrfTokenLoaded = true
+        return status
     }
 
     // MARK: - System
@@ -268,6 +285,17 @@ actor APIClient {
 
     // MARK: - Private
 
+    /// Add the CSRF token to a state-changing request. A session restored
+    /// from stored cookies has no token yet, so ask the server for it first.
+    private func setCSRFHeader(on request: inout URLRequest) async throws {
+        if !csrfTokenLoaded {
+            _ = try await getAuthStatus()
+        }
+        if let csrfToken {
+            request.setValue(csrfToken, forHTTPHeaderField: "X-CSRF-Token")
+        }
+    }
+
     private func request<T: Decodable>(_ url: URL, method: String = "GET", body: Data? = nil) async throws -> T {
         var request = URLRequest(url: url)
         request.httpMethod = method
@@ -276,6 +304,9 @@ actor APIClient {
         if body != nil {
             request.setValue("application/json", forHTTPHeaderField: "Content-Type")
         }
+        if method != "GET" && !url.path.hasSuffix("/auth/login") {
+            try await setCSRFHeader(on: &request)
+        }
 
         let (data, response): (Data, URLResponse)
         do {